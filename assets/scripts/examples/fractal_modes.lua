@@ -0,0 +1,17 @@
+-- The three fractal modes over the same source and seed: 1 is plain fBm, 2 ridged, 3 billow. Drag
+-- the slider to compare them; every mode samples the same octaves, only how each is shaped differs.
+--
+-- Ridged folds every octave around zero into a crest, and with a gain weights each octave by how
+-- close the previous one was to a crest, so detail gathers along the ridge lines. Billow folds the
+-- other way, giving rounded hills split by creases.
+local mode = Params.slider("mode", 1, 1, 3)
+local gain = Params.slider("ridge gain", 2, 0, 4)
+local source = Noise.simplex(Seed):scale(3)
+
+if mode < 1.5 then
+	return source:octaves(6)
+elseif mode < 2.5 then
+	return source:ridged(6, 0.5, 2, gain)
+else
+	return source:billow(6)
+end
@@ -0,0 +1,7 @@
+-- An island: fractal simplex multiplied by a radial falloff that is 1 in the middle of the map
+-- and reaches 0 well before its edges, so the coast closes in from every side.
+local falloff = (Noise.const(1) - Noise.radial(0.5, 0.5) * 2.2):clamp(0, 1)
+local land = (Noise.simplex(5):scale(4):octaves(6) * 0.5):toUnsignedUnit()
+
+-- the sea floor sits at -1 around the island
+return (land * falloff * 1.5):clamp(0, 1):toSignedUnit()
@@ -0,0 +1,19 @@
+-- Simplex, Perlin and value noise side by side, in thirds of the square from left to right. Each
+-- third is shifted so all three show the same region of their noise at the same scale.
+--
+-- Every source is pushed far below the others outside its third, so the maximum of the three is
+-- the one whose third the position lies in.
+local scale = Params.slider("scale", 8, 1, 32)
+local width = 1 / 3
+
+local function column(source, index)
+	local center = (index + 0.5) * width
+	local outside = ((Noise.gradientX() - center):abs() - width / 2):max(0) * 1000
+	return source:scale(scale):translate(-center, 0) - outside
+end
+
+local simplex = column(Noise.simplex(Seed), 0)
+local perlin = column(Noise.perlin(Seed), 1)
+local value = column(Noise.value(Seed), 2)
+
+return simplex:max(perlin):max(value)
@@ -0,0 +1,7 @@
+-- Mountains only where the mask is positive. The mask is clamped to [0, 1], so wherever it's
+-- exactly 0 the expensive mountain stack is never evaluated.
+local mask = Noise.simplex(3):scale(2):clamp(0, 1)
+local mountains = Noise.simplex(7):scale(4):octaves(10):abs()
+local plains = Noise.simplex(11):scale(3):octaves(3) * 0.1
+
+return mask * mountains + plains
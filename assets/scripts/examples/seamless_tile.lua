@@ -0,0 +1,15 @@
+-- A texture that repeats every unit, for use as a seamless tile. Check the seams with "Tile preview"
+-- in the 2D tab.
+--
+-- `tiled` works on any tree: it blends four copies of the noise, shifted by one period along x, y
+-- or both, weighting each by how close the position is to the edge that copy continues across.
+-- That keeps every edge seamless but averages the copies towards the tile's center, which lowers
+-- contrast there. For plain simplex, `Noise.simplexTileable` samples 4D noise on a torus instead
+-- and keeps full contrast, but it can't wrap warps, worley or other combinators.
+--
+-- The sampled square spans [0, 1] including both edges, so with a period of 1 the last row and
+-- column repeat the first; drop them when exporting for an exact tile.
+local cells = Noise.worley(3):scale(6)
+local detail = Noise.simplex(8):scale(4):octaves(4)
+
+return (cells + detail * 0.25):tiled(1)
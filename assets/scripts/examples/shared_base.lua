@@ -0,0 +1,8 @@
+-- Terrain and a moisture-like mask both built on the same fractal base. Without `:cached()`, the
+-- eight octaves below would be evaluated three times per sample, once for every use of `base`.
+local base = Noise.simplex(Seed):scale(3):octaves(8):cached()
+
+local ridges = base:abs() * -1 + 1
+local mask = (base * 0.5):toUnsignedUnit()
+
+return (ridges * mask + base * 0.3):clamp(-1, 1)
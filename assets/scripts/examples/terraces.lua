@@ -0,0 +1,10 @@
+-- Terraced hills: fBm pushed through a curve that flattens the lowlands and steepens the peaks,
+-- then cut into plateaus. Raise the smoothness to soften the cliffs between them.
+local steps = Params.slider("steps", 6, 2, 12)
+local smoothness = Params.slider("smoothness", 0.2, 0, 1)
+
+return Noise.simplex(Seed)
+	:scale(2)
+	:octaves(5)
+	:curve({ { -1, -1 }, { -0.2, -0.6 }, { 0.4, 0 }, { 1, 1 } })
+	:terrace(math.floor(steps), smoothness)
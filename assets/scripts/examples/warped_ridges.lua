@@ -0,0 +1,6 @@
+-- Ridged noise with its domain warped by two low-frequency fields, bending the ridge lines into
+-- swirling, eroded-looking shapes. The y displacement defaults to the x noise sampled elsewhere.
+local ridges = (Noise.simplex(5):abs() * -2 + 1):scale(3):octaves(6)
+local warp = Noise.simplex(9):scale(2):octaves(3)
+
+return ridges:warp(warp, nil, 0.25) * 0.5
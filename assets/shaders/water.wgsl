@@ -0,0 +1,74 @@
+#import bevy_pbr::{
+	pbr_fragment::pbr_input_from_standard_material,
+	forward_io::{VertexOutput, FragmentOutput},
+	pbr_functions::{apply_pbr_lighting, main_pass_post_lighting_processing},
+}
+
+struct Water {
+	color: vec4<f32>,
+	level: f32,
+	heightScale: f32,
+	extent: f32,
+	spacing: f32,
+	depthFalloff: f32,
+	foamWidth: f32,
+}
+
+@group(2) @binding(100) var<uniform> water: Water;
+@group(2) @binding(101) var heights: texture_2d<f32>;
+
+const foamColor = vec3<f32>(0.9, 0.95, 1.0);
+
+// Texel coordinates under a world XZ position, the heightmap's samples `spacing` apart around the
+// origin like the mesh's vertices are.
+fn to_texel(pos: vec2<f32>) -> vec2<f32> {
+	let size = vec2<f32>(textureDimensions(heights));
+	return pos / water.spacing + (size - 1.0) / 2.0;
+}
+
+// Terrain height at texel coordinates `pos`, bilinearly interpolated between texels the same way
+// the mesh is. Returns nothing sensible outside of the heightmap, check `on_heightmap` first.
+fn terrain_height(pos: vec2<f32>) -> f32 {
+	let maxTexel = vec2<i32>(textureDimensions(heights)) - 1;
+	let base = floor(pos);
+	let frac = pos - base;
+	let t00 = clamp(vec2<i32>(base), vec2(0), maxTexel);
+	let t11 = clamp(t00 + 1, vec2(0), maxTexel);
+	let h00 = textureLoad(heights, t00, 0).r;
+	let h10 = textureLoad(heights, vec2(t11.x, t00.y), 0).r;
+	let h01 = textureLoad(heights, vec2(t00.x, t11.y), 0).r;
+	let h11 = textureLoad(heights, t11, 0).r;
+	let h = mix(mix(h00, h10, frac.x), mix(h01, h11, frac.x), frac.y);
+	return h * water.heightScale;
+}
+
+fn on_heightmap(pos: vec2<f32>) -> bool {
+	let size = vec2<f32>(textureDimensions(heights));
+	return all(pos >= vec2(0.0)) && all(pos <= size - 1.0);
+}
+
+@fragment
+fn fragment(in: VertexOutput, @builtin(front_facing) isFront: bool) -> FragmentOutput {
+	var pbrInput = pbr_input_from_standard_material(in, isFront);
+
+	let pos = to_texel(in.world_position.xz);
+	var tint = 1.0;
+	var foam = 0.0;
+	if on_heightmap(pos) {
+		let depth = water.level - terrain_height(pos);
+		tint = 1.0 - exp(-max(depth, 0.0) * water.depthFalloff);
+		if water.foamWidth > 0.0 {
+			foam = 1.0 - smoothstep(0.0, water.foamWidth, abs(depth));
+		}
+	}
+
+	// shallow water is lighter and clearer, deep water takes on the full color
+	var color = vec4(mix(water.color.rgb * 1.6, water.color.rgb, tint), water.color.a * mix(0.2, 1.0, tint));
+	color = mix(color, vec4(foamColor, 0.9), foam);
+	pbrInput.material.base_color = color;
+
+	var out: FragmentOutput;
+	out.color = apply_pbr_lighting(pbrInput);
+	out.color = main_pass_post_lighting_processing(pbrInput, out.color);
+	return out;
+}
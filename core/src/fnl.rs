@@ -0,0 +1,187 @@
+use std::path::Path;
+
+use anyhow::{bail, ensure, Context};
+use glam::DVec2;
+use serde_json::Value;
+
+use crate::lua::{self, Noise};
+use crate::AResult;
+
+/// FastNoiseLite frequencies are per pixel, while scripts span `[0, 1]`. Presets are imported as if
+/// previewed at this size.
+pub const referenceSize: f64 = 256.0;
+
+/// Looks up `key` case-insensitively, as FNL's tools disagree on capitalization.
+fn field<'a>(preset: &'a serde_json::Map<String, Value>, key: &str) -> Option<&'a Value> {
+	preset
+		.iter()
+		.find(|(k, _)| k.eq_ignore_ascii_case(key))
+		.map(|(_, v)| v)
+}
+
+/// Enum value without FNL's `NoiseType_`-style prefix.
+fn enum_field<'a>(preset: &'a serde_json::Map<String, Value>, key: &str) -> AResult<Option<&'a str>> {
+	let Some(value) = field(preset, key) else {
+		return Ok(None);
+	};
+	let value = value.as_str().with_context(|| format!("`{key}` must be a string"))?;
+	Ok(Some(value.rsplit('_').next().unwrap_or(value)))
+}
+
+fn number_field(preset: &serde_json::Map<String, Value>, key: &str, default: f64) -> AResult<f64> {
+	match field(preset, key) {
+		None => Ok(default),
+		Some(value) => value
+			.as_f64()
+			.filter(|v| v.is_finite())
+			.with_context(|| format!("`{key}` must be a finite number")),
+	}
+}
+
+/// `1 / sum of octave amplitudes`, which FNL scales fractals by to keep them in `[-1, 1]`.
+fn fractal_bounding(octaves: usize, gain: f64) -> f64 {
+	let mut amp = gain.abs();
+	let mut total = 1.0;
+	for _ in 1 .. octaves {
+		total += amp;
+		amp *= gain.abs();
+	}
+	1.0 / total
+}
+
+/// Cellular noise as `Noise::Worley`, or `Noise::CellId` for cell values. FNL offsets distances
+/// by -1 to center them on 0, and so does this.
+fn cellular(
+	preset: &serde_json::Map<String, Value>,
+	seed: i64,
+	unsupported: &mut Vec<String>,
+) -> AResult<Noise> {
+	let jitter = number_field(preset, "cellularJitter", 1.0)?;
+	let distance = enum_field(preset, "cellularDistanceFunction")?.unwrap_or("EuclideanSq");
+	let returnType = match enum_field(preset, "cellularReturnType")?.unwrap_or("Distance") {
+		"CellValue" => {
+			// squaring doesn't move the regions
+			if !matches!(distance, "Euclidean" | "EuclideanSq") {
+				unsupported.push(format!("{distance} cell regions, using Euclidean instead"));
+			}
+			ensure!((0.0 ..= 1.0).contains(&jitter), "cellular jitter must be within [0, 1]");
+			return Ok(Noise::CellId { seed, jitter });
+		},
+		"Distance" => lua::CellReturn::F1,
+		"Distance2" => lua::CellReturn::F2,
+		"Distance2Sub" => lua::CellReturn::F2MinusF1,
+		other => {
+			unsupported.push(format!("{other} cellular return type, using Distance instead"));
+			lua::CellReturn::F1
+		},
+	};
+	let distanceFn = match distance {
+		"Euclidean" => lua::Distance::Euclidean,
+		"Manhattan" => lua::Distance::Manhattan,
+		"EuclideanSq" => {
+			unsupported.push("squared Euclidean distance, using Euclidean instead".into());
+			lua::Distance::Euclidean
+		},
+		other => {
+			unsupported.push(format!("{other} distance, using Euclidean instead"));
+			lua::Distance::Euclidean
+		},
+	};
+	if jitter != 1.0 {
+		unsupported.push("cellular jitter on distances".into());
+	}
+	let worley = Noise::Worley {
+		seed,
+		distanceFn,
+		returnType,
+	};
+	Ok(Noise::Sub(worley.into(), Noise::Const(1.0).into()))
+}
+
+/// Builds the closest equivalent tree for a FastNoiseLite preset, along with descriptions of the
+/// settings it couldn't represent.
+pub fn import(json: &str) -> AResult<(Noise, Vec<String>)> {
+	let preset: Value = serde_json::from_str(json).context("parsing preset")?;
+	let preset = preset.as_object().context("preset must be a JSON object")?;
+	let mut unsupported = vec![];
+
+	let seed = number_field(preset, "seed", 1337.0)? as i64;
+	let mut noise = match enum_field(preset, "noiseType")?.unwrap_or("OpenSimplex2") {
+		"OpenSimplex2" => Noise::SimplexFast(seed),
+		"OpenSimplex2S" => Noise::Simplex(seed),
+		"Cellular" => cellular(preset, seed, &mut unsupported)?,
+		other => {
+			unsupported.push(format!("{other} noise, using OpenSimplex2S instead"));
+			Noise::Simplex(seed)
+		},
+	};
+
+	let fractal = enum_field(preset, "fractalType")?.unwrap_or("None");
+	let ridged = match fractal {
+		"None" => false,
+		"FBm" => false,
+		"Ridged" => true,
+		"PingPong" => {
+			unsupported.push("ping-pong fractal, using fBm instead".into());
+			false
+		},
+		other => {
+			unsupported.push(format!("{other} fractal, ignored"));
+			false
+		},
+	};
+	if ridged {
+		noise = Noise::Sub(
+			Noise::Const(1.0).into(),
+			Noise::Mul(Noise::Const(2.0).into(), Noise::Abs(noise.into()).into()).into(),
+		);
+	}
+	if fractal != "None" {
+		let octaves = number_field(preset, "octaves", 3.0)?;
+		ensure!(
+			(1.0 ..= lua::maxOctaves as f64).contains(&octaves),
+			"octaves must be within [1, {}]",
+			lua::maxOctaves
+		);
+		let octaves = octaves as usize;
+		let lacunarity = number_field(preset, "lacunarity", 2.0)?;
+		let gain = number_field(preset, "gain", 0.5)?;
+		ensure!(
+			lacunarity.abs().powi(octaves as i32 - 1) <= lua::maxScale,
+			"lacunarity {lacunarity} overflows over {octaves} octaves"
+		);
+		if number_field(preset, "weightedStrength", 0.0)? != 0.0 {
+			unsupported.push("weighted strength".into());
+		}
+		noise = Noise::Mul(
+			Noise::Octaves {
+				func: noise.into(),
+				octaves,
+				ampScale: gain as f32,
+				freqScale: DVec2::splat(lacunarity),
+				mode: lua::OctaveMode::Fbm,
+				variation: lua::OctaveVariation::default(),
+				divisor: 1.0,
+			}
+			.into(),
+			Noise::Const(fractal_bounding(octaves, gain) as f32).into(),
+		);
+	}
+
+	if number_field(preset, "domainWarpAmp", 0.0)? != 0.0 || enum_field(preset, "domainWarpType")?.is_some()
+	{
+		unsupported.push("domain warp".into());
+	}
+
+	let frequency = number_field(preset, "frequency", 0.01)? * referenceSize;
+	let magnitude = frequency.abs();
+	if !(magnitude == 0.0 || (lua::minScale ..= lua::maxScale).contains(&magnitude)) {
+		bail!("frequency {frequency} is out of range");
+	}
+	Ok((Noise::CoordScale(noise.into(), DVec2::splat(frequency)), unsupported))
+}
+
+pub fn import_file(path: &Path) -> AResult<(Noise, Vec<String>)> {
+	let json = std::fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))?;
+	import(&json).with_context(|| format!("importing {}", path.display()))
+}
@@ -0,0 +1,193 @@
+use glam::{dvec2, DVec2};
+
+use crate::lua::{EvalContext, Noise, Precision};
+
+/// Maps a sample index to its evaluation coordinates over the unit square.
+pub fn sample_position(x: usize, y: usize, diameter: usize) -> DVec2 {
+	let y = y as f64 / (diameter - 1) as f64;
+	let x = x as f64 / (diameter - 1) as f64;
+	dvec2(x, y)
+}
+
+/// Offsets from `sample_position` of the `supersampling`² sub-samples averaged into a sample: a
+/// regular grid over the sample's footprint, centered on it so the average still stands for the
+/// pixel center. A single sub-sample sits at the center itself.
+pub fn subsample_offsets(supersampling: usize, diameter: usize) -> Vec<DVec2> {
+	let step = 1.0 / diameter.saturating_sub(1).max(1) as f64;
+	offsets_with_step(supersampling, step)
+}
+
+/// `subsample_offsets` for samples `step` apart.
+fn offsets_with_step(supersampling: usize, step: f64) -> Vec<DVec2> {
+	let offset = |i: usize| ((i as f64 + 0.5) / supersampling as f64 - 0.5) * step;
+	(0 .. supersampling.pow(2))
+		.map(|i| dvec2(offset(i % supersampling), offset(i / supersampling)))
+		.collect()
+}
+
+/// How sample indices map to the coordinates noise is evaluated at. Samples are square either way:
+/// the longer side of a non-square output spans the whole range, the shorter one part of it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Coords {
+	/// `[0, 1]` from the first sample to the last, like `sample_position`.
+	#[default]
+	Normalized,
+	/// `[-1, 1]`, with the origin in the middle of the output.
+	Centered,
+	/// The sample indices themselves, one unit per sample.
+	Pixels,
+}
+
+impl Coords {
+	pub const all: [Coords; 3] = [Coords::Normalized, Coords::Centered, Coords::Pixels];
+
+	pub fn label(self) -> &'static str {
+		match self {
+			Coords::Normalized => "0 … 1",
+			Coords::Centered => "-1 … 1",
+			Coords::Pixels => "pixels",
+		}
+	}
+
+	/// Value of the `Coords` global scripts see.
+	pub fn name(self) -> &'static str {
+		match self {
+			Coords::Normalized => "normalized",
+			Coords::Centered => "centered",
+			Coords::Pixels => "pixels",
+		}
+	}
+
+	/// Distance between neighbouring samples of a `width`×`height` output.
+	pub fn step(self, width: usize, height: usize) -> f64 {
+		let cells = width.max(height).saturating_sub(1).max(1) as f64;
+		match self {
+			Coords::Normalized => 1.0 / cells,
+			Coords::Centered => 2.0 / cells,
+			Coords::Pixels => 1.0,
+		}
+	}
+
+	/// Coordinates of sample `(x, y)` of a `width`×`height` output.
+	pub fn position(self, x: usize, y: usize, width: usize, height: usize) -> DVec2 {
+		self.at(dvec2(x as f64, y as f64), width, height)
+	}
+
+	/// `position` between samples, `index` counting them along each axis.
+	pub fn at(self, index: DVec2, width: usize, height: usize) -> DVec2 {
+		let step = self.step(width, height);
+		match self {
+			Coords::Normalized | Coords::Pixels => index * step,
+			Coords::Centered => {
+				let middle = dvec2(width.saturating_sub(1) as f64, height.saturating_sub(1) as f64);
+				(index - middle / 2.0) * step
+			},
+		}
+	}
+}
+
+/// Samples `sample_grid` evaluates: `width`×`height` of them laid out by `coords`, scaled by
+/// `size` and moved to `origin`, each averaging `supersampling`² sub-samples, at depth `z` of the
+/// volume. Nothing depends on the resolution, a normalized grid of any size covers the same
+/// square.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GridSpec {
+	pub width: usize,
+	pub height: usize,
+	pub coords: Coords,
+	pub origin: DVec2,
+	pub size: f64,
+	pub supersampling: usize,
+	pub z: f64,
+	pub precision: Precision,
+}
+
+impl GridSpec {
+	/// One sample per grid point over the unit square.
+	pub fn new(diameter: usize) -> Self {
+		Self::rect(diameter, diameter)
+	}
+
+	/// `new` for a `width`×`height` grid, whose longer side spans the unit square.
+	pub fn rect(width: usize, height: usize) -> Self {
+		Self {
+			width,
+			height,
+			coords: Coords::default(),
+			origin: DVec2::ZERO,
+			size: 1.0,
+			supersampling: 1,
+			z: 0.0,
+			precision: Precision::default(),
+		}
+	}
+
+	/// Evaluation coordinates of sample `(x, y)`.
+	pub fn position(&self, x: usize, y: usize) -> DVec2 {
+		self.origin + self.coords.position(x, y, self.width, self.height) * self.size
+	}
+
+	/// `subsample_offsets`, scaled to the grid.
+	pub fn offsets(&self) -> Vec<DVec2> {
+		let step = self.coords.step(self.width, self.height);
+		offsets_with_step(self.supersampling, step * self.size)
+	}
+
+	/// Fills `row` with the samples of row `y`. `offsets` are `GridSpec::offsets`, computed once
+	/// per grid by the caller.
+	pub fn sample_row(
+		&self,
+		noise: &Noise,
+		ctx: &EvalContext,
+		offsets: &[DVec2],
+		y: usize,
+		row: &mut [f32],
+	) {
+		for (x, sample) in row.iter_mut().enumerate() {
+			*sample = self.sample_at(noise, ctx, offsets, self.position(x, y));
+		}
+	}
+
+	/// `sample_row` for the red, green and blue `channels` of a color, each clamped to `[0, 1]`.
+	pub fn sample_color_row(
+		&self,
+		channels: &[Noise; 3],
+		ctx: &EvalContext,
+		offsets: &[DVec2],
+		y: usize,
+		row: &mut [[f32; 3]],
+	) {
+		for (x, color) in row.iter_mut().enumerate() {
+			let pos = self.position(x, y);
+			for (channel, value) in channels.iter().zip(color) {
+				// `max` before `min` takes NaN to 0 as well
+				*value = self.sample_at(channel, ctx, offsets, pos).max(0.0).min(1.0);
+			}
+		}
+	}
+
+	/// Average of `noise` over the sub-samples of the sample at `pos`.
+	fn sample_at(&self, noise: &Noise, ctx: &EvalContext, offsets: &[DVec2], pos: DVec2) -> f32 {
+		let eval = |offset| noise.eval3_with((pos + offset).extend(self.z), self.precision, ctx);
+		let sum: f64 = offsets.iter().map(|&offset| eval(offset) as f64).sum();
+		(sum / offsets.len() as f64) as f32
+	}
+}
+
+/// Evaluates `noise` over `spec`, returning its samples row by row. With the `rayon` feature rows
+/// are spread over rayon's pool, otherwise they're evaluated on the calling thread.
+pub fn sample_grid(noise: &Noise, spec: GridSpec) -> Vec<f32> {
+	let ctx = EvalContext::default();
+	let offsets = spec.offsets();
+	let mut samples = vec![0.0; spec.width * spec.height];
+	let rowLen = spec.width.max(1);
+	let sample_row = |(y, row): (usize, &mut [f32])| spec.sample_row(noise, &ctx, &offsets, y, row);
+	#[cfg(feature = "rayon")]
+	{
+		use rayon::prelude::*;
+		samples.par_chunks_mut(rowLen).enumerate().for_each(sample_row);
+	}
+	#[cfg(not(feature = "rayon"))]
+	samples.chunks_mut(rowLen).enumerate().for_each(sample_row);
+	samples
+}
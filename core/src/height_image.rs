@@ -0,0 +1,78 @@
+use anyhow::{ensure, Context};
+use glam::DVec2;
+
+use crate::AResult;
+
+/// Grayscale heightmap decoded from an image file, with values in `[-1, 1]`.
+pub struct HeightImage {
+	/// File the image was loaded from, as given.
+	pub path: String,
+	pub diameter: usize,
+	pub samples: Vec<f32>,
+}
+
+impl HeightImage {
+	/// Decodes a square PNG or EXR, averaging color channels. Integer images map black to -1 and
+	/// white to 1; float images have no white, so their lowest and highest values are stretched
+	/// to `[-1, 1]` instead.
+	#[cfg(feature = "image")]
+	pub fn load(path: &str) -> AResult<Self> {
+		let image = image::open(path).with_context(|| format!("loading {path}"))?;
+		let (width, height) = (image.width(), image.height());
+		ensure!(width == height, "{path} is {width}x{height}, references must be square");
+		ensure!(width >= 2, "{path} is {width}x{height}, too small to interpolate");
+		let float = matches!(image.color(), image::ColorType::Rgb32F | image::ColorType::Rgba32F);
+		let mut samples = image.to_luma32f().into_raw();
+		if float {
+			let finite = samples.iter().copied().filter(|v| v.is_finite());
+			let (min, max) = finite.fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), v| {
+				(min.min(v), max.max(v))
+			});
+			for v in &mut samples {
+				*v = if max > min { (*v - min) / (max - min) * 2.0 - 1.0 } else { 0.0 };
+			}
+		} else {
+			for v in &mut samples {
+				*v = *v * 2.0 - 1.0;
+			}
+		}
+		Ok(Self {
+			path: path.into(),
+			diameter: width as usize,
+			samples,
+		})
+	}
+
+	#[cfg(not(feature = "image"))]
+	pub fn load(path: &str) -> AResult<Self> {
+		anyhow::bail!("can't load {path}, noisebench-core was built without the image feature")
+	}
+
+	/// Bilinearly interpolated value at `pos`. The unit square spans the image with the corner
+	/// pixels' centers on its corners, like `sample_position`, so a generation at the image's
+	/// diameter reproduces it exactly. Outside the square, coordinates are clamped to the border,
+	/// or with `wrap` repeat every `diameter` pixels.
+	pub fn sample(&self, pos: DVec2, wrap: bool) -> f64 {
+		let diameter = self.diameter;
+		let last = (diameter - 1) as f64;
+		let pos = pos * last;
+		let pos = if wrap {
+			pos.rem_euclid(DVec2::splat(diameter as f64))
+		} else {
+			pos.clamp(DVec2::ZERO, DVec2::splat(last))
+		};
+		if !pos.is_finite() {
+			return f64::NAN;
+		}
+		let base = pos.floor();
+		let frac = pos - base;
+		// rounding can put a wrapped coordinate on `diameter` itself
+		let (x0, y0) = (base.x as usize % diameter, base.y as usize % diameter);
+		let next = |i: usize| if wrap { (i + 1) % diameter } else { (i + 1).min(diameter - 1) };
+		let (x1, y1) = (next(x0), next(y0));
+		let at = |x: usize, y: usize| self.samples[y * diameter + x] as f64;
+		let top = at(x0, y0) + (at(x1, y0) - at(x0, y0)) * frac.x;
+		let bottom = at(x0, y1) + (at(x1, y1) - at(x0, y1)) * frac.x;
+		top + (bottom - top) * frac.y
+	}
+}
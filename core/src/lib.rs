@@ -0,0 +1,32 @@
+#![allow(unused, non_snake_case, non_upper_case_globals)]
+
+//! Noise trees built by noisebench's Lua scripts, without the app around them.
+//!
+//! ```no_run
+//! use noisebench_core::grid::{sample_grid, GridSpec};
+//! use noisebench_core::lua::Noise;
+//!
+//! let noise = Noise::from_lua("return Noise.simplex(1):scale(4)")?;
+//! let height = noise.eval(glam::dvec2(0.25, 0.5));
+//! let heights = sample_grid(&noise, GridSpec::new(256));
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+
+#[cfg(feature = "fnl")]
+pub mod fnl;
+pub mod grid;
+pub mod height_image;
+pub mod lua;
+pub mod noise_rng;
+
+pub use anyhow::Result as AResult;
+
+pub type ContentHash = u64;
+
+/// FNV-1a hash of script or file contents. Stable across runs and platforms, unlike `std`'s
+/// hashers, so it can key on-disk caches.
+pub fn content_hash(contents: impl AsRef<[u8]>) -> ContentHash {
+	contents.as_ref().iter().fold(0xcbf29ce484222325, |hash, &byte| {
+		(hash ^ byte as u64).wrapping_mul(0x100000001b3)
+	})
+}
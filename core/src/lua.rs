@@ -0,0 +1,3372 @@
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::f64::consts::TAU;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+
+use anyhow::Context;
+use dyn_clone::DynClone;
+use glam::{dvec2, dvec3, vec3, DVec2, DVec3, Vec2, Vec3};
+use mlua::prelude::*;
+use mlua::{UserData, Value};
+
+use crate::grid::Coords;
+use crate::height_image::HeightImage;
+use crate::{content_hash, noise_rng, AResult, ContentHash};
+
+thread_local! {
+	static luaInst: Lua = {
+		let lua = Lua::new();
+		lua.globals().set("Noise", NoiseCtors);
+		sandbox_modules(&lua).expect("failed to set up the module loader");
+		lua
+	};
+	/// Overrides `moduleDir` on this thread, see `with_module_dir`.
+	static moduleDirOverride: RefCell<Option<PathBuf>> = RefCell::new(None);
+	/// Modules the current script run on this thread loaded, by name and file.
+	static runModules: RefCell<Vec<(String, PathBuf)>> = RefCell::new(Vec::new());
+	/// Files the current script run on this thread read with `Noise.image` or `Noise.fromFNL`.
+	static runFiles: RefCell<Vec<PathBuf>> = RefCell::new(Vec::new());
+	/// Functions of the `LuaFunc`s evaluated on this thread, loaded into its `luaInst`, by
+	/// `LuaFunc::id`. Scripts that failed to load keep their error.
+	static luaFuncs: RefCell<HashMap<u64, Result<LuaRegistryKey, String>>> =
+		RefCell::new(HashMap::new());
+	/// Latest value of each `Noise::Cached` node evaluated on this thread, see `cached_eval`.
+	static cachedValues: RefCell<Vec<CachedValue>> = RefCell::new(Vec::new());
+}
+
+/// Number of compiled trees kept by `construct_noisegen`, the least recently used is dropped
+/// first.
+const compiledCacheSize: usize = 32;
+/// Number of `LuaFunc` functions each thread keeps loaded.
+const luaFuncCacheSize: usize = 8;
+/// Number of `Noise::Cached` values each thread keeps, one per node. A sample only ever reuses
+/// values of the tree it's evaluating.
+const cachedValuesSize: usize = 16;
+
+/// Most octaves a single `octaves` call may stack. Past a few dozen, amplitudes underflow and
+/// frequencies overflow long before anything visible changes.
+pub const maxOctaves: usize = 64;
+/// Largest coordinate multiplier a scale, frequency or octave stack may reach.
+pub const maxScale: f64 = 1e12;
+/// Smallest nonzero coordinate multiplier. Below this positions crawl through denormals.
+pub const minScale: f64 = 1e-12;
+/// Octave amplitude below which further octaves can't change an `f32` sum and are skipped.
+pub const minOctaveAmp: f64 = 1e-8;
+/// Largest offset `decorrelate` moves a source by on each axis, far beyond simplex feature size.
+const decorrelateOffset: f64 = 256.0;
+/// Where `warp` samples its x noise to derive the y displacement when none is given. Any offset of
+/// a few feature sizes decorrelates the two.
+const warpYOffset: DVec2 = DVec2::new(5.2, 1.3);
+
+/// Directory `require` loads modules from, see `set_module_dir`.
+static moduleDir: Mutex<Option<PathBuf>> = Mutex::new(None);
+
+/// Lets scripts `require` modules from `dir`, and only from there: `require("lib.masks")` loads
+/// `lib/masks.lua` or `lib/masks/init.lua` below it. Without a directory, scripts can't require
+/// anything.
+pub fn set_module_dir(dir: impl Into<PathBuf>) {
+	*moduleDir.lock().unwrap() = Some(dir.into());
+}
+
+/// Runs `f` with modules loaded from `dir` on this thread, whatever `set_module_dir` set.
+pub fn with_module_dir<R>(dir: impl Into<PathBuf>, f: impl FnOnce() -> R) -> R {
+	let previous = moduleDirOverride.with(|over| over.replace(Some(dir.into())));
+	let res = f();
+	moduleDirOverride.with(|over| *over.borrow_mut() = previous);
+	res
+}
+
+fn module_dir() -> Option<PathBuf> {
+	moduleDirOverride
+		.with(|over| over.borrow().clone())
+		.or_else(|| moduleDir.lock().unwrap().clone())
+}
+
+/// File of module `name`, a dotted path of plain names. Symlinks leading out of the module
+/// directory are refused.
+fn module_path(name: &str) -> Result<PathBuf, String> {
+	let dir = module_dir().ok_or("modules can't be required, no module directory is set")?;
+	let plain = |part: &str| {
+		!part.is_empty() && part.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+	};
+	if !name.split('.').all(plain) {
+		return Err(format!("`{name}` isn't a module name, which are dotted like `lib.masks`"));
+	}
+	let root = dir.canonicalize().map_err(|err| format!("{}: {err}", dir.display()))?;
+	let base = name.split('.').fold(root.clone(), |path, part| path.join(part));
+	for candidate in [base.with_extension("lua"), base.join("init.lua")] {
+		let Ok(path) = candidate.canonicalize() else {
+			continue;
+		};
+		if !path.starts_with(&root) {
+			return Err(format!("{} leads out of {}", candidate.display(), dir.display()));
+		}
+		return Ok(path);
+	}
+	let relative = name.replace('.', "/");
+	Err(format!("no {relative}.lua or {relative}/init.lua in {}", dir.display()))
+}
+
+/// `package.searchers` entry loading modules from the module directory. Returns the module's
+/// chunk and file, or why there's no such module.
+fn search_module<'lua>(lua: &'lua Lua, name: String) -> mlua::Result<(Value<'lua>, Value<'lua>)> {
+	let path = match module_path(&name) {
+		Ok(path) => path,
+		Err(why) => return Ok((Value::String(lua.create_string(why)?), Value::Nil)),
+	};
+	let code = std::fs::read_to_string(&path)
+		.map_err(|err| LuaError::external(format!("reading {}: {err}", path.display())))?;
+	// globals a module sets stay its own, like a script's
+	let env = lua.create_table()?;
+	let meta = lua.create_table()?;
+	meta.set("__index", lua.globals())?;
+	env.set_metatable(Some(meta));
+	let chunk = lua.load(&code).set_name(format!("={name}")).set_environment(env);
+	let loader = chunk.into_function()?;
+	let file = lua.create_string(path.to_string_lossy().as_bytes())?;
+	runModules.with(|modules| modules.borrow_mut().push((name, path)));
+	Ok((Value::Function(loader), Value::String(file)))
+}
+
+/// Makes `require` load modules from the module directory only, leaving out Lua's own searchers
+/// of `package.path` and native libraries.
+fn sandbox_modules(lua: &Lua) -> mlua::Result<()> {
+	let package: LuaTable = lua.globals().get("package")?;
+	let searcher = lua.create_function(search_module)?;
+	package.set("searchers", lua.create_sequence_from([searcher])?)?;
+	package.set("path", "")?;
+	package.set("cpath", "")?;
+	package.set("loadlib", Value::Nil)?;
+	Ok(())
+}
+
+/// Unloads the modules the previous run on this thread loaded. Every run loads its modules again,
+/// so none is outdated and `Compiled::modules` lists all of them.
+fn forget_modules(lua: &Lua) -> mlua::Result<()> {
+	let package: LuaTable = lua.globals().get("package")?;
+	let loaded: LuaTable = package.get("loaded")?;
+	for (name, _) in runModules.with(|modules| modules.take()) {
+		loaded.set(name, Value::Nil)?;
+	}
+	Ok(())
+}
+
+/// Notes that the current script run read `path`, so its tree is rebuilt once the file changes.
+fn record_file(path: &str) {
+	let path = Path::new(path);
+	let path = path.canonicalize().unwrap_or_else(|_| path.to_owned());
+	runFiles.with(|files| files.borrow_mut().push(path));
+}
+
+/// Values a script run sees besides its source.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ScriptInputs {
+	/// The `Seed` global.
+	pub seed: i64,
+	/// Values of the script's `Params` sliders by name. Missing ones take the script's default.
+	pub params: BTreeMap<String, f64>,
+	/// Coordinates the tree is evaluated at, named by the `Coords` global.
+	pub coords: Coords,
+}
+
+/// `ScriptInputs` in hashable form, values compared bit for bit.
+type InputsKey = (i64, Vec<(String, u64)>, Coords);
+
+impl ScriptInputs {
+	fn key(&self) -> InputsKey {
+		let params = self.params.iter().map(|(name, v)| (name.clone(), v.to_bits())).collect();
+		(self.seed, params, self.coords)
+	}
+}
+
+/// Slider a script declared with `Params.slider`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ScriptParam {
+	pub name: String,
+	pub default: f64,
+	pub min: f64,
+	pub max: f64,
+	/// Value the script ran with.
+	pub value: f64,
+}
+
+/// A script's tree along with the parameters it declared.
+#[derive(Clone)]
+pub struct Compiled {
+	pub noise: Arc<Noise>,
+	pub params: Vec<ScriptParam>,
+	/// Names of the layers returned besides `height` by a `{ height = n, ... }` table, unused for
+	/// now.
+	pub layers: Vec<String>,
+	/// Red, green and blue of a `{ height = n, color = { r, g, b } }` table, each in `[0, 1]`.
+	pub color: Option<Arc<[Noise; 3]>>,
+	/// Canonical paths of the modules the script required, directly or through other modules.
+	pub modules: Vec<PathBuf>,
+	/// Paths of the files the script read with `Noise.image` or `Noise.fromFNL`, canonical where
+	/// they exist.
+	pub files: Vec<PathBuf>,
+}
+
+/// A tree in `compiled_cache`.
+struct CachedCompiled {
+	compiled: Compiled,
+	/// Modules and files the script read, with the hashes of their contents when it ran. The tree
+	/// is outdated once one of them changes.
+	dependencies: Vec<(PathBuf, ContentHash)>,
+	/// `cacheClock` as of the last time the tree was looked up.
+	lastUse: u64,
+}
+
+/// Ticks on every use of a cached tree, ordering the uses for eviction.
+static cacheClock: AtomicU64 = AtomicU64::new(0);
+
+/// Compiled trees by script content and the inputs they were built with.
+fn compiled_cache() -> &'static Mutex<HashMap<(ContentHash, InputsKey), CachedCompiled>> {
+	static cache: OnceLock<Mutex<HashMap<(ContentHash, InputsKey), CachedCompiled>>> =
+		OnceLock::new();
+	cache.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// Hash of the contents of `path`, of nothing if it can't be read.
+fn file_hash(path: &Path) -> ContentHash {
+	content_hash(std::fs::read(path).unwrap_or_default())
+}
+
+/// Heap used by each thread's Lua state as of its last script run.
+fn lua_memory() -> &'static Mutex<HashMap<std::thread::ThreadId, usize>> {
+	static memory: OnceLock<Mutex<HashMap<std::thread::ThreadId, usize>>> = OnceLock::new();
+	memory.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// Estimated bytes held by the Lua states and by the compiled tree cache.
+pub fn memory_bytes() -> (usize, usize) {
+	let lua = lua_memory().lock().unwrap().values().sum();
+	let mut trees = 0;
+	for cached in compiled_cache().lock().unwrap().values() {
+		cached.compiled.noise.visit(&mut |_, _| trees += std::mem::size_of::<Noise>());
+	}
+	(lua, trees)
+}
+
+/// Drops all cached trees, e.g. when something a script depends on besides its own source
+/// changed.
+pub fn invalidate_compiled() {
+	compiled_cache().lock().unwrap().clear();
+}
+
+/// Runs a script and returns the `Noise` it builds. Trees are cached by content hash, so
+/// reselecting a script or saving it unchanged doesn't run Lua again.
+pub fn construct_noisegen(code: &str) -> AResult<Arc<Noise>> {
+	construct_noisegen_seeded(code, 0)
+}
+
+/// Like `construct_noisegen`, with the script's `Seed` global set to `seed`. Scripts that don't
+/// read it build the same tree for every seed.
+pub fn construct_noisegen_seeded(code: &str, seed: i64) -> AResult<Arc<Noise>> {
+	let inputs = ScriptInputs {
+		seed,
+		..Default::default()
+	};
+	construct_noisegen_with(code, &inputs).map(|compiled| compiled.noise)
+}
+
+/// Runs a script with the given inputs, also returning the parameters it declared. A cached tree
+/// is only reused while the modules and files the script read are unchanged.
+pub fn construct_noisegen_with(code: &str, inputs: &ScriptInputs) -> AResult<Compiled> {
+	let key = (content_hash(code), inputs.key());
+	let cached = compiled_cache().lock().unwrap().get_mut(&key).map(|cached| {
+		cached.lastUse = cacheClock.fetch_add(1, Ordering::Relaxed);
+		(cached.compiled.clone(), cached.dependencies.clone())
+	});
+	// read outside the lock, images can be large
+	if let Some((compiled, dependencies)) = cached {
+		if dependencies.iter().all(|(path, hash)| file_hash(path) == *hash) {
+			return Ok(compiled);
+		}
+	}
+
+	let compiled = construct_noisegen_uncached(code, inputs, true)?;
+	let dependencies = compiled
+		.modules
+		.iter()
+		.chain(&compiled.files)
+		.map(|path| (path.clone(), file_hash(path)))
+		.collect();
+	let mut cache = compiled_cache().lock().unwrap();
+	if cache.len() >= compiledCacheSize && !cache.contains_key(&key) {
+		let oldest = cache.iter().min_by_key(|(_, cached)| cached.lastUse);
+		if let Some(evicted) = oldest.map(|(key, _)| key.clone()) {
+			cache.remove(&evicted);
+		}
+	}
+	let cached = CachedCompiled {
+		compiled: compiled.clone(),
+		dependencies,
+		lastUse: cacheClock.fetch_add(1, Ordering::Relaxed),
+	};
+	cache.insert(key, cached);
+	Ok(compiled)
+}
+
+/// Like `construct_noisegen`, leaving the tree as the script built it instead of `optimize`d, to
+/// compare against. Never cached; subtrees under `cached` are optimized regardless.
+pub fn construct_noisegen_unoptimized(code: &str) -> AResult<Arc<Noise>> {
+	let inputs = ScriptInputs::default();
+	construct_noisegen_uncached(code, &inputs, false).map(|compiled| compiled.noise)
+}
+
+fn construct_noisegen_uncached(
+	code: &str,
+	inputs: &ScriptInputs,
+	optimize: bool,
+) -> AResult<Compiled> {
+	luaInst.with(|lua| {
+		let res = run_script(lua, code, inputs, optimize);
+		lua_memory()
+			.lock()
+			.unwrap()
+			.insert(std::thread::current().id(), lua.used_memory());
+		res
+	})
+}
+
+/// Runs a script in its own environment, whose globals fall back to the state's, returning what
+/// it returned, the sliders it declared and the modules it loaded. Functions the script defines
+/// keep seeing its `Seed` when they're called later, whatever ran on the state since.
+fn run_chunk<'lua>(
+	lua: &'lua Lua,
+	code: &str,
+	inputs: &ScriptInputs,
+) -> AResult<(Value<'lua>, Vec<ScriptParam>, Vec<PathBuf>)> {
+	LuaErrorContext::context(forget_modules(lua), "unloading the previous run's modules failed")?;
+	runFiles.with(|files| files.borrow_mut().clear());
+	let env = LuaErrorContext::context(
+		script_env(lua, inputs),
+		"setting up the script's globals failed",
+	)?;
+	// otherwise errors point at this line of Rust instead of the script's own lines
+	let chunk = lua.load(code).set_name("=script").set_environment(env.clone());
+	let declared = RefCell::new(Vec::<ScriptParam>::new());
+	// scoped so `slider` can borrow the inputs and the declarations, and is unusable afterwards
+	let value = lua.scope(|scope| {
+		let slider = scope.create_function(
+			|_, (name, default, min, max): (String, f64, f64, f64)| {
+				if !(min < max && min.is_finite() && max.is_finite() && default.is_finite()) {
+					return Err(LuaError::external(format!(
+						"slider `{name}` needs a finite default and range with min < max"
+					)));
+				}
+				let mut declared = declared.borrow_mut();
+				// declaring twice, e.g. from a helper function, reads the same slider
+				if let Some(param) = declared.iter().find(|param| param.name == name) {
+					return Ok(param.value);
+				}
+				let default = default.clamp(min, max);
+				let value = inputs.params.get(&name).map_or(default, |v| v.clamp(min, max));
+				declared.push(ScriptParam {
+					name,
+					default,
+					min,
+					max,
+					value,
+				});
+				Ok(value)
+			},
+		)?;
+		let params = lua.create_table()?;
+		params.set("slider", slider)?;
+		env.set("Params", params)?;
+		chunk.call::<_, Value>(())
+	});
+	let value = LuaErrorContext::context(value, "eval of Lua script failed")?;
+	let mut modules: Vec<PathBuf> =
+		runModules.with(|modules| modules.borrow().iter().map(|(_, path)| path.clone()).collect());
+	modules.sort();
+	modules.dedup();
+	Ok((value, declared.into_inner(), modules))
+}
+
+/// Globals table of one script run, holding its `Seed` and `Coords` and reading everything else
+/// from the state's globals.
+fn script_env<'lua>(lua: &'lua Lua, inputs: &ScriptInputs) -> mlua::Result<LuaTable<'lua>> {
+	let env = lua.create_table()?;
+	let meta = lua.create_table()?;
+	meta.set("__index", lua.globals())?;
+	env.set_metatable(Some(meta));
+	env.set("Seed", inputs.seed)?;
+	env.set("Coords", inputs.coords.name())?;
+	Ok(env)
+}
+
+/// Field of a returned table holding what's shown: `noise`, or the `height` of named layers.
+fn shown_field(table: &LuaTable) -> AResult<&'static str> {
+	for field in ["noise", "height"] {
+		if LuaErrorContext::context(table.contains_key(field), "reading the returned table")? {
+			return Ok(field);
+		}
+	}
+	let keys = table_keys(table);
+	anyhow::ensure!(!keys.is_empty(), "Lua script returned an empty table");
+	anyhow::bail!(
+		"returned table has neither a `noise` nor a `height` field, only {}",
+		keys.join(", ")
+	)
+}
+
+/// Keys of `table`, sorted, with the ones that aren't strings shown by their Lua type.
+fn table_keys(table: &LuaTable) -> Vec<String> {
+	let mut keys: Vec<String> = table
+		.clone()
+		.pairs::<Value, Value>()
+		.filter_map(Result::ok)
+		.map(|(key, _)| match key {
+			Value::String(key) => key.to_string_lossy().into_owned(),
+			key => format!("<{}>", key.type_name()),
+		})
+		.collect();
+	keys.sort();
+	keys.dedup();
+	keys
+}
+
+/// Channels of the `color` field of a returned table, if it has one: a list of three `Noise`s or
+/// numbers.
+fn returned_color(table: &LuaTable) -> AResult<Option<[Noise; 3]>> {
+	let color: Option<LuaTable> = LuaErrorContext::context(
+		table.get("color"),
+		"`color` must be a list of red, green and blue",
+	)?;
+	let Some(color) = color else {
+		return Ok(None);
+	};
+	anyhow::ensure!(color.raw_len() == 3, "`color` must have exactly three entries");
+	let channel = |i: usize| -> AResult<Noise> {
+		let value: Value = LuaErrorContext::context(color.get(i + 1), "reading `color`")?;
+		let noise = LuaErrorContext::context(
+			rhs_to_noise(&value),
+			format!("`color` entry {} must be a Noise or number", i + 1),
+		)?;
+		Ok(noise.optimize())
+	};
+	Ok(Some([channel(0)?, channel(1)?, channel(2)?]))
+}
+
+fn run_script(
+	lua: &Lua,
+	code: &str,
+	inputs: &ScriptInputs,
+	optimize: bool,
+) -> AResult<Compiled> {
+	let (value, params, modules) = run_chunk(lua, code, inputs)?;
+	let mut files = runFiles.with(|files| files.take());
+	files.sort();
+	files.dedup();
+	let mut layers = vec![];
+	let mut color = None;
+	let noise = match value {
+		// `{ noise = n, range = {min, max} }`, equivalent to `n:withRange(min, max)`, or named
+		// layers `{ height = n, range = ..., moisture = m, ... }` of which `height` is shown
+		Value::Table(table) => {
+			let field = shown_field(&table)?;
+			// before taking the height, which the channels may share nodes with
+			color = returned_color(&table)?.map(Arc::new);
+			let noise: Value = LuaErrorContext::context(
+				table.get(field),
+				format!("reading the returned table's `{field}` field"),
+			)?;
+			let noise = returned_noise(noise, code, inputs)
+				.with_context(|| format!("in the returned table's `{field}` field"))?;
+			if field == "height" {
+				layers = table_keys(&table);
+				layers.retain(|key| !["height", "range", "color"].contains(&key.as_str()));
+			}
+			let range: Option<Vec<f32>> =
+				LuaErrorContext::context(table.get("range"), "`range` must be a list of numbers")?;
+			match range.as_deref() {
+				None => noise,
+				Some(&[min, max]) => with_range(noise, min, max)?,
+				Some(_) => anyhow::bail!("`range` must have exactly two entries"),
+			}
+		},
+		value => returned_noise(value, code, inputs)?,
+	};
+	Ok(Compiled {
+		noise: Arc::new(if optimize { noise.optimize() } else { noise }),
+		params,
+		layers,
+		color,
+		modules,
+		files,
+	})
+}
+
+/// The `Noise` a script returned, a `LuaFunc` calling the function it returned instead, or a
+/// constant for a plain number.
+fn returned_noise(value: Value, code: &str, inputs: &ScriptInputs) -> AResult<Noise> {
+	let constant = |v: f64| {
+		anyhow::ensure!(v.is_finite(), "Lua script returned {v} instead of a finite number");
+		Ok(Noise::Const(v as f32))
+	};
+	match value {
+		Value::UserData(noise) => LuaErrorContext::context(
+			noise.take(),
+			"Lua script returned a userdata that isn't a Noise",
+		),
+		Value::Function(_) => Ok(Noise::LuaFunc(LuaFunc::new(code, inputs))),
+		// handy while trying things out, `return 0.5` is a flat plane
+		Value::Integer(v) => constant(v as f64),
+		Value::Number(v) => constant(v),
+		value => anyhow::bail!(
+			"Lua script returned a {}, expected a Noise, number, function or table",
+			value.type_name()
+		),
+	}
+}
+
+/// A plain Lua function `(x, y, z, time) -> number` a script returned instead of a `Noise`,
+/// called for every sample. Lua states can't be shared between threads, so each thread
+/// evaluating it runs the script once in its own state to get the function, keeping it in
+/// `luaFuncs`.
+#[derive(Clone)]
+pub struct LuaFunc {
+	/// Identifies the function across threads' `luaFuncs`.
+	id: u64,
+	pub code: Arc<String>,
+	inputs: ScriptInputs,
+	/// First runtime error since the last `Noise::take_lua_error`. Samples that fail are NaN.
+	error: Arc<Mutex<Option<String>>>,
+}
+
+impl LuaFunc {
+	fn new(code: &str, inputs: &ScriptInputs) -> Self {
+		static nextId: AtomicU64 = AtomicU64::new(0);
+		Self {
+			id: nextId.fetch_add(1, Ordering::Relaxed),
+			code: Arc::new(code.to_owned()),
+			inputs: inputs.clone(),
+			error: Arc::new(Mutex::new(None)),
+		}
+	}
+
+	fn eval(&self, pos: DVec3, time: f64) -> f64 {
+		let res = luaInst.with(|lua| {
+			let func = self.function(lua)?;
+			func.call::<_, f64>((pos.x, pos.y, pos.z, time)).map_err(|err| err.to_string())
+		});
+		res.unwrap_or_else(|err| {
+			self.error.lock().unwrap().get_or_insert(err);
+			f64::NAN
+		})
+	}
+
+	/// The function in this thread's state, running the script on first use.
+	fn function<'lua>(&self, lua: &'lua Lua) -> Result<LuaFunction<'lua>, String> {
+		luaFuncs.with(|funcs| {
+			let mut funcs = funcs.borrow_mut();
+			if !funcs.contains_key(&self.id) {
+				if funcs.len() >= luaFuncCacheSize {
+					let evicted = *funcs.keys().next().unwrap();
+					if let Some(Ok(key)) = funcs.remove(&evicted) {
+						let _ = lua.remove_registry_value(key);
+					}
+				}
+				let loaded = self.load(lua);
+				lua_memory()
+					.lock()
+					.unwrap()
+					.insert(std::thread::current().id(), lua.used_memory());
+				funcs.insert(self.id, loaded);
+			}
+			match &funcs[&self.id] {
+				Ok(key) => lua.registry_value(key).map_err(|err| err.to_string()),
+				Err(err) => Err(err.clone()),
+			}
+		})
+	}
+
+	fn load(&self, lua: &Lua) -> Result<LuaRegistryKey, String> {
+		let (value, ..) =
+			run_chunk(lua, &self.code, &self.inputs).map_err(|err| format!("{err:#}"))?;
+		let value = match value {
+			Value::Table(table) => {
+				let field = shown_field(&table).map_err(|err| format!("{err:#}"))?;
+				table.get(field).map_err(|err| err.to_string())?
+			},
+			value => value,
+		};
+		let Value::Function(func) = value else {
+			return Err("script didn't return a function when run again".into());
+		};
+		lua.create_registry_value(func).map_err(|err| err.to_string())
+	}
+}
+
+pub trait NoiseFunc: Send + Sync + DynClone {
+	fn eval(&self, pos: DVec2) -> f32;
+}
+
+impl<Func: Clone + Send + Sync + Fn(DVec2) -> f32> NoiseFunc for Func {
+	fn eval(&self, pos: DVec2) -> f32 {
+		self(pos)
+	}
+}
+
+type NoisePtr = Box<Noise>;
+
+pub enum Noise {
+	Const(f32),
+	Func(Box<dyn NoiseFunc>),
+	/// A Lua function returned by the script, see `LuaFunc`.
+	LuaFunc(LuaFunc),
+	Simplex(i64),
+	SimplexFast(i64),
+	/// Smooth 3D simplex, oriented for slices along `z`.
+	Simplex3(i64),
+	/// Cellular noise: distances to the feature points nearest to the position, one point per
+	/// unit cell.
+	Worley {
+		seed: i64,
+		distanceFn: Distance,
+		returnType: CellReturn,
+	},
+	/// Random value in `[-1, 1)` shared by every position of a Voronoi region, for biome maps.
+	/// Regions are those of feature points offset from the unit cell centers by up to `jitter`
+	/// of a cell: 0 gives a square grid, 1 the same regions as `Worley` F1 with this seed.
+	CellId {
+		seed: i64,
+		jitter: f64,
+	},
+	/// Independent value in `[-1, 1)` at every position, hashed from the bits of its coordinates.
+	White(i64),
+	/// Lattice value noise: a random value in `[-1, 1)` at every integer point, blended with
+	/// quintic interpolation. Stays within `[-1, 1)`, at the values of the lattice points it lies
+	/// between.
+	Value(i64),
+	/// Classic Perlin gradient noise, see `perlin_noise`. Within `[-1, 1]`, reaching the bounds
+	/// only at cell centers.
+	Perlin {
+		seed: i64,
+		/// Built from `seed` by `perlin_permutation`.
+		perm: Arc<[u8; 256]>,
+	},
+	/// The `x` coordinate itself, for ramps and masks.
+	GradientX,
+	/// The `y` coordinate itself.
+	GradientY,
+	/// Animation time, supplied by the `EvalContext` at evaluation instead of baked into the tree
+	/// so every frame reuses the compiled script.
+	Time,
+	/// Distance from `center`, for radial falloffs such as island masks.
+	Radial(DVec2),
+	/// 1 and -1 in alternating squares `size` units wide, 1 in the square right of and below the
+	/// origin.
+	Checker(f64),
+	/// Heightmap image loaded by `Noise.image`, see `HeightImage::sample`.
+	Image {
+		image: Arc<HeightImage>,
+		wrap: bool,
+	},
+	/// Smooth simplex that repeats every `period` units on each axis, sampled from 4D noise on a
+	/// torus.
+	SimplexTileable {
+		seed: i64,
+		period: DVec2,
+	},
+	/// Smooth simplex where time loops every `period`, sampled from 4D noise with time on a
+	/// circle. Follows `EvalContext::time` unless `time` fixes it.
+	SimplexLooped {
+		seed: i64,
+		period: f64,
+		time: Option<f64>,
+	},
+	Octaves {
+		func: NoisePtr,
+		octaves: usize,
+		ampScale: f32,
+		/// Per-axis frequency multiplier between octaves; unequal axes stretch features along the
+		/// slower one.
+		freqScale: DVec2,
+		mode: OctaveMode,
+		variation: OctaveVariation,
+		/// What the summed octaves are divided by, 1 unless normalized, see
+		/// `OctaveNormalization::divisor`.
+		divisor: f64,
+	},
+
+	Add(NoisePtr, NoisePtr),
+	Sub(NoisePtr, NoisePtr),
+	Mul(NoisePtr, NoisePtr),
+	/// `Mul` that skips evaluating the right side when the left is exactly zero, returning the
+	/// left side. Only produced by `optimize`, which guarantees the right side is always finite
+	/// with its sign bit clear, so the skipped product would have been that same zero.
+	MulSkipZero(NoisePtr, NoisePtr),
+	Div(NoisePtr, NoisePtr),
+	Pow(NoisePtr, NoisePtr),
+	Rem(NoisePtr, NoisePtr),
+	RemEuclid(NoisePtr, NoisePtr),
+	SignedPow(NoisePtr, NoisePtr),
+	Floor(NoisePtr),
+	Ceil(NoisePtr),
+	Abs(NoisePtr),
+	Min(NoisePtr, NoisePtr),
+	Max(NoisePtr, NoisePtr),
+	Clamp {
+		func: NoisePtr,
+		min: f32,
+		max: f32,
+	},
+	/// Remaps values piecewise linearly through `points`, sorted by input, which `eval_curve`
+	/// describes.
+	Curve {
+		func: NoisePtr,
+		points: Vec<DVec2>,
+	},
+	ToUnsignedUnit(NoisePtr),
+	ToSignedUnit(NoisePtr),
+	/// `a + (b - a) * t`, with `t` clamped to `[0, 1]` unless `clamped` is off.
+	Lerp {
+		a: NoisePtr,
+		b: NoisePtr,
+		t: NoisePtr,
+		clamped: bool,
+	},
+	/// `Lerp` with the clamped mask eased by smoothstep, `3t² - 2t³`.
+	SmoothLerp {
+		a: NoisePtr,
+		b: NoisePtr,
+		t: NoisePtr,
+	},
+	/// `a` where `control` is below `threshold - falloff`, `b` from `threshold + falloff` up, and
+	/// a smoothstep blend in between. A `falloff` of 0 gives a hard edge, `b` from the threshold.
+	Select {
+		control: NoisePtr,
+		a: NoisePtr,
+		b: NoisePtr,
+		threshold: f64,
+		falloff: f64,
+	},
+
+	CoordTranslate(NoisePtr, DVec2),
+	CoordScale(NoisePtr, DVec2),
+	/// Rotates positions by the unit vector `(cos, sin)` of the angle.
+	CoordRotate(NoisePtr, DVec2),
+	/// Maps `z` to `z * scale + offset`, leaving `x` and `y` alone. Only 3D sources can tell.
+	CoordZ {
+		func: NoisePtr,
+		offset: f64,
+		scale: f64,
+	},
+	/// Domain warp: samples `func` at the position displaced by `(warpX, warpY) * strength`, both
+	/// evaluated at the undisplaced position.
+	Warp {
+		func: NoisePtr,
+		warpX: NoisePtr,
+		warpY: NoisePtr,
+		strength: f64,
+	},
+	/// Makes `func` repeat every `period` units by blending four copies of it, each shifted by a
+	/// period along one or both axes, with weights going to 1 for the copy that continues
+	/// seamlessly across the nearest tile edge. Works with any subtree, at the cost of contrast
+	/// towards tile centers.
+	Tiled {
+		func: NoisePtr,
+		period: DVec2,
+	},
+	/// Length of `func`'s gradient in x and y, by central differences `epsilon` to either side.
+	/// Both `epsilon` and the slope are in the coordinates this node is evaluated at, so a scale
+	/// inside it steepens the slope by the same factor, like the analytic derivative would.
+	GradientMagnitude {
+		func: NoisePtr,
+		epsilon: f64,
+	},
+
+	/// `func`, evaluated once per sample however often the tree uses it at the same position, see
+	/// `cached_eval`. Copies of the node share `func` and the `id` its values are kept under.
+	Cached {
+		func: Arc<Noise>,
+		id: u64,
+	},
+
+	/// Passes `func` through unchanged, declaring the range its output is meant to span. Only
+	/// meaningful at the root, see `output_range`.
+	Ranged {
+		func: NoisePtr,
+		min: f32,
+		max: f32,
+	},
+}
+
+/// Transform `Noise::Octaves` applies to each octave before summing them.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OctaveMode {
+	/// Plain sum, fractal Brownian motion.
+	Fbm,
+	/// `1 - 2|v|`, turning zero crossings into sharp ridges. With a `gain`, every octave after the
+	/// first is also weighted by the previous one's ridge strength `1 - |v|` times `gain`, clamped
+	/// to `[0, 1]`, so detail gathers on the ridges and valleys stay smooth.
+	Ridged { gain: Option<f32> },
+	/// `2|v| - 1`, rounding zero crossings into creases between puffy lobes.
+	Billow,
+}
+
+impl OctaveMode {
+	fn apply_f64(self, v: f64, weight: &mut f64) -> f64 {
+		match self {
+			OctaveMode::Fbm => v,
+			OctaveMode::Ridged { gain } => {
+				let res = (1.0 - v.abs() * 2.0) * *weight;
+				if let Some(gain) = gain {
+					*weight = ((1.0 - v.abs()) * gain as f64).clamp(0.0, 1.0);
+				}
+				res
+			},
+			OctaveMode::Billow => v.abs() * 2.0 - 1.0,
+		}
+	}
+
+	fn apply_f32(self, v: f32, weight: &mut f32) -> f32 {
+		match self {
+			OctaveMode::Fbm => v,
+			OctaveMode::Ridged { gain } => {
+				let res = (1.0 - v.abs() * 2.0) * *weight;
+				if let Some(gain) = gain {
+					*weight = ((1.0 - v.abs()) * gain).clamp(0.0, 1.0);
+				}
+				res
+			},
+			OctaveMode::Billow => v.abs() * 2.0 - 1.0,
+		}
+	}
+
+	/// Range of a transformed octave whose input lies in `[lo, hi]`, before weighting.
+	fn bounds(self, (lo, hi): (f64, f64)) -> (f64, f64) {
+		let absMax = lo.abs().max(hi.abs());
+		let absMin = if lo <= 0.0 && hi >= 0.0 { 0.0 } else { lo.abs().min(hi.abs()) };
+		match self {
+			OctaveMode::Fbm => (lo, hi),
+			OctaveMode::Ridged { .. } => (1.0 - absMax * 2.0, 1.0 - absMin * 2.0),
+			OctaveMode::Billow => (absMin * 2.0 - 1.0, absMax * 2.0 - 1.0),
+		}
+	}
+}
+
+/// How `Noise::Octaves` scales the sum of its octaves.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum OctaveNormalization {
+	/// The plain sum, whose range grows with the number of octaves.
+	#[default]
+	None,
+	/// Divides by the largest magnitude the sum can reach with every octave in `[-1, 1]`, so the
+	/// stack stays within `[-1, 1]` however many octaves it has.
+	SumAmplitudes,
+}
+
+impl OctaveNormalization {
+	fn from_name(name: &str) -> mlua::Result<Self> {
+		match name {
+			"none" => Ok(Self::None),
+			"sumAmplitudes" => Ok(Self::SumAmplitudes),
+			_ => Err(LuaError::external(format!(
+				"octave normalization must be \"none\" or \"sumAmplitudes\", not {name:?}"
+			))),
+		}
+	}
+
+	/// What a stack of `octaves` scaled by `ampScale` is divided by. Under `SumAmplitudes` that's
+	/// the sum of the active octaves' absolute amplitudes, where a ridged stack with a gain weights
+	/// octaves after the first by at most the gain clamped to `[0, 1]`.
+	pub fn divisor(self, octaves: usize, ampScale: f32, mode: OctaveMode) -> f64 {
+		if self == Self::None {
+			return 1.0;
+		}
+		let maxWeight = match mode {
+			OctaveMode::Ridged { gain: Some(gain) } => (gain as f64).clamp(0.0, 1.0),
+			_ => 1.0,
+		};
+		let ampScale = (ampScale as f64).abs();
+		let mut amp = 1.0;
+		let mut sum = 0.0;
+		for octave in 0 .. active_octaves(octaves, ampScale as f32) {
+			sum += if octave == 0 { amp } else { amp * maxWeight };
+			amp *= ampScale;
+		}
+		if sum > 0.0 { sum } else { 1.0 }
+	}
+}
+
+/// Per-octave changes `Noise::Octaves` makes so its octaves, all the same function at scaled
+/// positions, don't line up into self-similar and axis-aligned patterns. The default changes
+/// nothing.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct OctaveVariation {
+	/// Added to the seed once more for every octave. `Simplex` and `SimplexFast` functions are
+	/// reseeded; other functions, whose seeds may be anywhere below, are shifted by an offset
+	/// hashed from the octave's seed instead.
+	pub seedOffset: i64,
+	/// Rotation of each octave relative to the previous one, as the unit vector `(cos, sin)` of
+	/// the angle.
+	pub rotation: Option<DVec2>,
+	/// Largest relative change of the frequency multiplier between two octaves, each change drawn
+	/// from `jitterSeed`. Within `[0, 1)`.
+	pub lacunarityJitter: f64,
+	pub jitterSeed: i64,
+}
+
+impl OctaveVariation {
+	pub fn is_none(&self) -> bool {
+		*self == Self::default()
+	}
+
+	/// Factor the frequency multiplier after `octave` is scaled by.
+	pub fn jitter(&self, octave: usize) -> f64 {
+		if self.lacunarityJitter == 0.0 {
+			return 1.0;
+		}
+		let h = noise_rng::hash(self.jitterSeed as u64, octave as u64, 5);
+		1.0 + self.lacunarityJitter * noise_rng::signed_unit(h)
+	}
+
+	/// Seed of `octave` for a function seeded with `seed`.
+	pub fn seed(&self, seed: i64, octave: usize) -> i64 {
+		seed.wrapping_add(self.seedOffset.wrapping_mul(octave as i64))
+	}
+
+	/// Position offset standing in for the seed change of `octave` on functions that can't be
+	/// reseeded directly.
+	pub fn shift(&self, octave: usize) -> DVec2 {
+		if self.seedOffset == 0 || octave == 0 {
+			return DVec2::ZERO;
+		}
+		let seed = self.seed(0, octave) as u64;
+		let axis = |axis| noise_rng::signed_unit(noise_rng::hash(seed, octave as u64, axis));
+		dvec2(axis(6), axis(7)) * decorrelateOffset
+	}
+}
+
+/// Metric `Noise::Worley` measures distances to feature points with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Distance {
+	Euclidean,
+	Manhattan,
+	Chebyshev,
+}
+
+impl Distance {
+	pub fn eval(self, v: DVec2) -> f64 {
+		match self {
+			Distance::Euclidean => v.length(),
+			Distance::Manhattan => v.x.abs() + v.y.abs(),
+			Distance::Chebyshev => v.x.abs().max(v.y.abs()),
+		}
+	}
+
+	/// Farthest a point in the same cell can be, the bound on F1.
+	fn max_in_cell(self) -> f64 {
+		self.eval(DVec2::ONE)
+	}
+
+	/// Farthest a point in an edge-adjacent cell can be. Every position has its own cell's point
+	/// and at least that one this close, which bounds F2.
+	fn max_in_neighbor(self) -> f64 {
+		self.eval(dvec2(2.0, 1.0))
+	}
+}
+
+/// Which distances `Noise::Worley` outputs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CellReturn {
+	/// Distance to the nearest feature point.
+	F1,
+	/// Distance to the second nearest feature point.
+	F2,
+	/// Difference between the two, zero along cell borders.
+	F2MinusF1,
+}
+
+/// Cells searched on each side of the position's own cell. Points three cells away are farther than
+/// any point in the own cell under every metric, so F1 is exact; F2 can in rare configurations
+/// miss a point just outside.
+const worleyReach: i32 = 2;
+
+/// Nearest and second nearest feature point distances for `Noise::Worley`.
+pub fn worley_distances(seed: i64, distanceFn: Distance, pos: DVec2) -> (f64, f64) {
+	let cell = pos.floor();
+	let (mut f1, mut f2) = (f64::INFINITY, f64::INFINITY);
+	for dy in -worleyReach ..= worleyReach {
+		for dx in -worleyReach ..= worleyReach {
+			let cell = cell + dvec2(dx as f64, dy as f64);
+			let point = cell + noise_rng::cell_point(seed as u64, cell.x as i64, cell.y as i64);
+			let d = distanceFn.eval(point - pos);
+			if d < f1 {
+				f2 = f1;
+				f1 = d;
+			} else if d < f2 {
+				f2 = d;
+			}
+		}
+	}
+	(f1, f2)
+}
+
+/// Lattice cell whose jittered feature point is nearest to `pos`, for `Noise::CellId`.
+pub fn nearest_cell(seed: i64, jitter: f64, pos: DVec2) -> (i64, i64) {
+	let cell = pos.floor();
+	let mut nearest = (f64::INFINITY, (0, 0));
+	// a jitter of at most 1 keeps points as close as Worley's, so the same reach finds the nearest
+	for dy in -worleyReach ..= worleyReach {
+		for dx in -worleyReach ..= worleyReach {
+			let cell = cell + dvec2(dx as f64, dy as f64);
+			let (x, y) = (cell.x as i64, cell.y as i64);
+			let offset = noise_rng::cell_point(seed as u64, x, y) - 0.5;
+			let d = (cell + 0.5 + offset * jitter - pos).length_squared();
+			if d < nearest.0 {
+				nearest = (d, (x, y));
+			}
+		}
+	}
+	nearest.1
+}
+
+/// Quintic fade `6t⁵ - 15t⁴ + 10t³`, with zero first and second derivatives at 0 and 1, so
+/// interpolated lattice noise has no creases along the cell edges.
+fn quintic(t: f64) -> f64 {
+	t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+/// Bilinear blend of the corners `[(0, 0), (1, 0), (0, 1), (1, 1)]` of a unit cell, faded by
+/// `quintic`.
+fn blend_corners(corners: [f64; 4], offset: DVec2) -> f64 {
+	let (u, v) = (quintic(offset.x), quintic(offset.y));
+	let bottom = corners[0] + (corners[1] - corners[0]) * u;
+	let top = corners[2] + (corners[3] - corners[2]) * u;
+	bottom + (top - bottom) * v
+}
+
+/// `Noise::Value` at `pos`, interpolating the `noise_rng::cell_value`s of the lattice points
+/// around it.
+pub fn value_noise(seed: i64, pos: DVec2) -> f64 {
+	let cell = pos.floor();
+	let (x, y) = (cell.x as i64, cell.y as i64);
+	let corner = |dx: i64, dy: i64| noise_rng::cell_value(seed as u64, x + dx, y + dy);
+	blend_corners([corner(0, 0), corner(1, 0), corner(0, 1), corner(1, 1)], pos - cell)
+}
+
+/// Permutation of `0 .. 256` hashing lattice points for `perlin_noise`, a Fisher-Yates shuffle
+/// drawn from `seed`'s `noise_rng::Rng` stream.
+pub fn perlin_permutation(seed: i64) -> [u8; 256] {
+	let mut perm = std::array::from_fn(|i| i as u8);
+	let mut rng = noise_rng::Rng::new(seed as u64);
+	for i in (1 .. 256).rev() {
+		let j = rng.next_u64() % (i as u64 + 1);
+		perm.swap(i, j as usize);
+	}
+	perm
+}
+
+/// Classic 2D Perlin noise at `pos`: every lattice point picks one of the gradients `(±1, ±1)`,
+/// `(±1, 0)` and `(0, ±1)` by `perm[perm[x] + y]`, and the dot products with the offsets to `pos`
+/// are blended with `quintic` fading. The diagonals reach ±1 at a cell center when all four point
+/// towards it or away from it. Repeats every 256 units along each axis, like the table.
+pub fn perlin_noise(perm: &[u8; 256], pos: DVec2) -> f64 {
+	let cell = pos.floor();
+	let offset = pos - cell;
+	let (x, y) = (cell.x as i64 as usize, cell.y as i64 as usize);
+	let corner = |dx: usize, dy: usize| {
+		let hash = perm[(perm[x.wrapping_add(dx) & 255] as usize + y.wrapping_add(dy)) & 255];
+		let d = offset - dvec2(dx as f64, dy as f64);
+		match hash & 7 {
+			0 => d.x + d.y,
+			1 => -d.x + d.y,
+			2 => d.x - d.y,
+			3 => -d.x - d.y,
+			4 => d.x,
+			5 => -d.x,
+			6 => d.y,
+			_ => -d.y,
+		}
+	};
+	blend_corners([corner(0, 0), corner(1, 0), corner(0, 1), corner(1, 1)], offset)
+}
+
+/// Numeric precision `Noise` trees are evaluated with.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Precision {
+	/// Coordinates converted to `f32` once at the root and all arithmetic in `f32`. Cheaper, but
+	/// positions visibly quantize far from the origin, e.g. after large translations.
+	F32,
+	/// Coordinates and arithmetic in `f64`, cast to `f32` at the root.
+	#[default]
+	F64,
+}
+
+impl Precision {
+	pub const all: [Precision; 2] = [Precision::F32, Precision::F64];
+
+	pub fn label(self) -> &'static str {
+		match self {
+			Precision::F32 => "f32",
+			Precision::F64 => "f64",
+		}
+	}
+}
+
+/// Inputs of an evaluation besides the position, the same for every sample of a generation.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct EvalContext {
+	/// Value of `Noise::Time`, in seconds of animation.
+	pub time: f64,
+}
+
+impl Noise {
+	/// Runs a script and returns the tree it builds, see `construct_noisegen`.
+	pub fn from_lua(code: &str) -> AResult<Self> {
+		construct_noisegen(code).map(|noise| Noise::clone(&noise))
+	}
+
+	/// Evaluates at `pos` with `Precision::F64`.
+	pub fn eval(&self, pos: DVec2) -> f32 {
+		self.eval3(pos.extend(0.0))
+	}
+
+	pub fn eval_with(&self, pos: DVec2, precision: Precision) -> f32 {
+		self.eval3_with(pos.extend(0.0), precision, &EvalContext::default())
+	}
+
+	/// Evaluates at a point in the volume, at time 0. 2D sources only see `x` and `y`, and 2D
+	/// evaluation is the slice at `z = 0`.
+	pub fn eval3(&self, pos: DVec3) -> f32 {
+		self.eval_f64(pos, &EvalContext::default()) as f32
+	}
+
+	pub fn eval3_with(&self, pos: DVec3, precision: Precision, ctx: &EvalContext) -> f32 {
+		match precision {
+			Precision::F32 => self.eval_f32(pos.as_vec3(), ctx),
+			Precision::F64 => self.eval_f64(pos, ctx) as f32,
+		}
+	}
+
+	fn eval_f64(&self, pos: DVec3, ctx: &EvalContext) -> f64 {
+		use Noise::*;
+		match self {
+			&Const(v) => v as f64,
+			Func(func) => func.eval(pos.xy()) as f64,
+			LuaFunc(func) => func.eval(pos, ctx.time),
+			&Simplex(seed) => opensimplex2::smooth::noise2(seed, pos.x, pos.y) as f64,
+			&SimplexFast(seed) => opensimplex2::fast::noise2(seed, pos.x, pos.y) as f64,
+			&Simplex3(seed) => {
+				opensimplex2::smooth::noise3_ImproveXY(seed, pos.x, pos.y, pos.z) as f64
+			},
+			&Worley {
+				seed,
+				distanceFn,
+				returnType,
+			} => {
+				let (f1, f2) = worley_distances(seed, distanceFn, pos.xy());
+				match returnType {
+					CellReturn::F1 => f1,
+					CellReturn::F2 => f2,
+					CellReturn::F2MinusF1 => f2 - f1,
+				}
+			},
+			&CellId { seed, jitter } => {
+				let (x, y) = nearest_cell(seed, jitter, pos.xy());
+				noise_rng::cell_value(seed as u64, x, y)
+			},
+			&White(seed) => noise_rng::signed_unit(noise_rng::hash_pos(seed as u64, pos.xy())),
+			&Value(seed) => value_noise(seed, pos.xy()),
+			Perlin { perm, .. } => perlin_noise(perm, pos.xy()),
+			GradientX => pos.x,
+			GradientY => pos.y,
+			Time => ctx.time,
+			&Radial(center) => pos.xy().distance(center),
+			&Checker(size) => {
+				let cell = (pos.xy() / size).floor();
+				if (cell.x + cell.y).rem_euclid(2.0) == 0.0 { 1.0 } else { -1.0 }
+			},
+			Image { image, wrap } => image.sample(pos.xy(), *wrap),
+			&SimplexTileable { seed, period } => {
+				// radius chosen so one period along the circle is `period` units long, keeping
+				// feature size the same as plain simplex
+				let radius = period / TAU;
+				let angle = pos.xy() / period * TAU;
+				opensimplex2::smooth::noise4_Fallback(
+					seed,
+					radius.x * angle.x.cos(),
+					radius.x * angle.x.sin(),
+					radius.y * angle.y.cos(),
+					radius.y * angle.y.sin(),
+				) as f64
+			},
+			&SimplexLooped { seed, period, time } => {
+				let radius = period / TAU;
+				let angle = time.unwrap_or(ctx.time) / period * TAU;
+				opensimplex2::smooth::noise4_ImproveXY_ImproveZW(
+					seed,
+					pos.x,
+					pos.y,
+					radius * angle.cos(),
+					radius * angle.sin(),
+				) as f64
+			},
+			Octaves {
+				func,
+				octaves,
+				ampScale,
+				freqScale,
+				mode,
+				variation,
+				divisor,
+			} => {
+				// z follows the x multiplier
+				let freqScale = freqScale.extend(freqScale.x);
+				let mut res = 0.0;
+				let mut amp = 1.0;
+				let mut freq = DVec3::ONE;
+				let mut weight = 1.0;
+				let mut rotation = DVec2::X;
+				for octave in 0 .. *octaves {
+					if amp.abs() < minOctaveAmp {
+						break;
+					}
+					let v = if variation.is_none() {
+						func.eval_f64(pos * freq, ctx)
+					} else {
+						func.eval_octave_f64(variation, octave, rotation, pos * freq, ctx)
+					};
+					res += amp * mode.apply_f64(v, &mut weight);
+					amp *= *ampScale as f64;
+					freq *= freqScale * variation.jitter(octave);
+					if let Some(step) = variation.rotation {
+						rotation = step.rotate(rotation);
+					}
+				}
+				res / divisor
+			},
+
+			Add(l, r) => l.eval_f64(pos, ctx) + r.eval_f64(pos, ctx),
+			Sub(l, r) => l.eval_f64(pos, ctx) - r.eval_f64(pos, ctx),
+			Mul(l, r) => l.eval_f64(pos, ctx) * r.eval_f64(pos, ctx),
+			MulSkipZero(l, r) => {
+				let l = l.eval_f64(pos, ctx);
+				if l == 0.0 {
+					l
+				} else {
+					l * r.eval_f64(pos, ctx)
+				}
+			},
+			Div(l, r) => l.eval_f64(pos, ctx) / r.eval_f64(pos, ctx),
+			Pow(l, r) => l.eval_f64(pos, ctx).powf(r.eval_f64(pos, ctx)),
+			Rem(l, r) => l.eval_f64(pos, ctx) % r.eval_f64(pos, ctx),
+			RemEuclid(l, r) => l.eval_f64(pos, ctx).rem_euclid(r.eval_f64(pos, ctx)),
+			Floor(v) => v.eval_f64(pos, ctx).floor(),
+			Ceil(v) => v.eval_f64(pos, ctx).ceil(),
+			Abs(v) => v.eval_f64(pos, ctx).abs(),
+			Min(l, r) => l.eval_f64(pos, ctx).min(r.eval_f64(pos, ctx)),
+			Max(l, r) => l.eval_f64(pos, ctx).max(r.eval_f64(pos, ctx)),
+			Clamp { func, min, max } => func.eval_f64(pos, ctx).clamp(*min as f64, *max as f64),
+			Curve { func, points } => eval_curve(points, func.eval_f64(pos, ctx)),
+			ToUnsignedUnit(v) => (v.eval_f64(pos, ctx) + 1.0) / 2.0,
+			ToSignedUnit(v) => v.eval_f64(pos, ctx) * 2.0 - 1.0,
+			Lerp { a, b, t, clamped } => {
+				let a = a.eval_f64(pos, ctx);
+				let b = b.eval_f64(pos, ctx);
+				let t = t.eval_f64(pos, ctx);
+				a + (b - a) * if *clamped { t.clamp(0.0, 1.0) } else { t }
+			},
+			SmoothLerp { a, b, t } => {
+				let a = a.eval_f64(pos, ctx);
+				let b = b.eval_f64(pos, ctx);
+				let t = t.eval_f64(pos, ctx).clamp(0.0, 1.0);
+				a + (b - a) * (t * t * (3.0 - 2.0 * t))
+			},
+			Select {
+				control,
+				a,
+				b,
+				threshold,
+				falloff,
+			} => {
+				let (lo, hi) = (threshold - falloff, threshold + falloff);
+				let control = control.eval_f64(pos, ctx);
+				// only the selected sides are evaluated, and the blend never divides by zero
+				if control >= hi {
+					b.eval_f64(pos, ctx)
+				} else if control <= lo {
+					a.eval_f64(pos, ctx)
+				} else {
+					let t = (control - lo) / (hi - lo);
+					let (a, b) = (a.eval_f64(pos, ctx), b.eval_f64(pos, ctx));
+					a + (b - a) * (t * t * (3.0 - 2.0 * t))
+				}
+			},
+			SignedPow(l, r) => {
+				let l = l.eval_f64(pos, ctx);
+				let r = r.eval_f64(pos, ctx);
+				l.powf(r).copysign(l)
+			},
+
+			CoordTranslate(func, translation) => func.eval_f64(pos + translation.extend(0.0), ctx),
+			CoordScale(func, scale) => func.eval_f64(pos * scale.extend(1.0), ctx),
+			CoordRotate(func, rotation) => {
+				func.eval_f64(rotation.rotate(pos.xy()).extend(pos.z), ctx)
+			},
+			&CoordZ {
+				ref func,
+				offset,
+				scale,
+			} => func.eval_f64(dvec3(pos.x, pos.y, pos.z * scale + offset), ctx),
+			Warp {
+				func,
+				warpX,
+				warpY,
+				strength,
+			} => {
+				let offset = dvec2(warpX.eval_f64(pos, ctx), warpY.eval_f64(pos, ctx)) * *strength;
+				func.eval_f64(pos + offset.extend(0.0), ctx)
+			},
+			&Tiled { ref func, period } => {
+				let wrapped = pos.xy().rem_euclid(period);
+				let t = wrapped / period;
+				let [v00, v10, v01, v11] = [(0.0, 0.0), (1.0, 0.0), (0.0, 1.0), (1.0, 1.0)]
+					.map(|(dx, dy)| {
+						func.eval_f64((wrapped - dvec2(dx, dy) * period).extend(pos.z), ctx)
+					});
+				let top = v00 + (v10 - v00) * t.x;
+				let bottom = v01 + (v11 - v01) * t.x;
+				top + (bottom - top) * t.y
+			},
+			&GradientMagnitude { ref func, epsilon } => {
+				let [dx, dy] = [DVec3::X, DVec3::Y].map(|axis| {
+					let offset = axis * epsilon;
+					func.eval_f64(pos + offset, ctx) - func.eval_f64(pos - offset, ctx)
+				});
+				dvec2(dx, dy).length() / (2.0 * epsilon)
+			},
+
+			Cached { func, id } => {
+				cached_eval(*id, Precision::F64, pos, ctx.time, || func.eval_f64(pos, ctx))
+			},
+			Ranged { func, .. } => func.eval_f64(pos, ctx),
+		}
+	}
+
+	fn eval_f32(&self, pos: Vec3, ctx: &EvalContext) -> f32 {
+		use Noise::*;
+		match self {
+			&Const(v) => v,
+			Func(func) => func.eval(pos.xy().as_dvec2()),
+			LuaFunc(func) => func.eval(pos.as_dvec3(), ctx.time) as f32,
+			&Simplex(seed) => opensimplex2::smooth::noise2(seed, pos.x as f64, pos.y as f64),
+			&SimplexFast(seed) => opensimplex2::fast::noise2(seed, pos.x as f64, pos.y as f64),
+			&Simplex3(seed) => {
+				let pos = pos.as_dvec3();
+				opensimplex2::smooth::noise3_ImproveXY(seed, pos.x, pos.y, pos.z)
+			},
+			// cells are found from the already reduced position, only the distances use f64
+			Worley { .. } | CellId { .. } => self.eval_f64(pos.as_dvec3(), ctx) as f32,
+			// likewise for the hashed coordinates and checker cells
+			White(_) | Checker(_) => self.eval_f64(pos.as_dvec3(), ctx) as f32,
+			// and the lattice cells
+			Value(_) | Perlin { .. } => self.eval_f64(pos.as_dvec3(), ctx) as f32,
+			Image { image, wrap } => image.sample(pos.xy().as_dvec2(), *wrap) as f32,
+			GradientX => pos.x,
+			GradientY => pos.y,
+			Time => ctx.time as f32,
+			&Radial(center) => pos.xy().distance(center.as_vec2()),
+			&SimplexTileable { seed, period } => {
+				let period = period.as_vec2();
+				let radius = period / std::f32::consts::TAU;
+				let angle = pos.xy() / period * std::f32::consts::TAU;
+				opensimplex2::smooth::noise4_Fallback(
+					seed,
+					(radius.x * angle.x.cos()) as f64,
+					(radius.x * angle.x.sin()) as f64,
+					(radius.y * angle.y.cos()) as f64,
+					(radius.y * angle.y.sin()) as f64,
+				)
+			},
+			// the time coordinate is constant per node, only the position is reduced
+			SimplexLooped { .. } => self.eval_f64(pos.as_dvec3(), ctx) as f32,
+			Octaves {
+				func,
+				octaves,
+				ampScale,
+				freqScale,
+				mode,
+				variation,
+				divisor,
+			} => {
+				let freqScale = freqScale.as_vec2().extend(freqScale.x as f32);
+				let mut res = 0.0;
+				let mut amp = 1.0f32;
+				let mut freq = Vec3::ONE;
+				let mut weight = 1.0;
+				let mut rotation = DVec2::X;
+				for octave in 0 .. *octaves {
+					if (amp.abs() as f64) < minOctaveAmp {
+						break;
+					}
+					let v = if variation.is_none() {
+						func.eval_f32(pos * freq, ctx)
+					} else {
+						func.eval_octave_f32(variation, octave, rotation, pos * freq, ctx)
+					};
+					res += amp * mode.apply_f32(v, &mut weight);
+					amp *= ampScale;
+					freq *= freqScale * variation.jitter(octave) as f32;
+					if let Some(step) = variation.rotation {
+						rotation = step.rotate(rotation);
+					}
+				}
+				res / *divisor as f32
+			},
+
+			Add(l, r) => l.eval_f32(pos, ctx) + r.eval_f32(pos, ctx),
+			Sub(l, r) => l.eval_f32(pos, ctx) - r.eval_f32(pos, ctx),
+			Mul(l, r) => l.eval_f32(pos, ctx) * r.eval_f32(pos, ctx),
+			MulSkipZero(l, r) => {
+				let l = l.eval_f32(pos, ctx);
+				if l == 0.0 {
+					l
+				} else {
+					l * r.eval_f32(pos, ctx)
+				}
+			},
+			Div(l, r) => l.eval_f32(pos, ctx) / r.eval_f32(pos, ctx),
+			Pow(l, r) => l.eval_f32(pos, ctx).powf(r.eval_f32(pos, ctx)),
+			Rem(l, r) => l.eval_f32(pos, ctx) % r.eval_f32(pos, ctx),
+			RemEuclid(l, r) => l.eval_f32(pos, ctx).rem_euclid(r.eval_f32(pos, ctx)),
+			Floor(v) => v.eval_f32(pos, ctx).floor(),
+			Ceil(v) => v.eval_f32(pos, ctx).ceil(),
+			Abs(v) => v.eval_f32(pos, ctx).abs(),
+			Min(l, r) => l.eval_f32(pos, ctx).min(r.eval_f32(pos, ctx)),
+			Max(l, r) => l.eval_f32(pos, ctx).max(r.eval_f32(pos, ctx)),
+			Clamp { func, min, max } => func.eval_f32(pos, ctx).clamp(*min, *max),
+			Curve { func, points } => eval_curve(points, func.eval_f32(pos, ctx) as f64) as f32,
+			ToUnsignedUnit(v) => (v.eval_f32(pos, ctx) + 1.0) / 2.0,
+			ToSignedUnit(v) => v.eval_f32(pos, ctx) * 2.0 - 1.0,
+			Lerp { a, b, t, clamped } => {
+				let a = a.eval_f32(pos, ctx);
+				let b = b.eval_f32(pos, ctx);
+				let t = t.eval_f32(pos, ctx);
+				a + (b - a) * if *clamped { t.clamp(0.0, 1.0) } else { t }
+			},
+			SmoothLerp { a, b, t } => {
+				let a = a.eval_f32(pos, ctx);
+				let b = b.eval_f32(pos, ctx);
+				let t = t.eval_f32(pos, ctx).clamp(0.0, 1.0);
+				a + (b - a) * (t * t * (3.0 - 2.0 * t))
+			},
+			Select {
+				control,
+				a,
+				b,
+				threshold,
+				falloff,
+			} => {
+				let (lo, hi) = ((threshold - falloff) as f32, (threshold + falloff) as f32);
+				let control = control.eval_f32(pos, ctx);
+				if control >= hi {
+					b.eval_f32(pos, ctx)
+				} else if control <= lo {
+					a.eval_f32(pos, ctx)
+				} else {
+					let t = (control - lo) / (hi - lo);
+					let (a, b) = (a.eval_f32(pos, ctx), b.eval_f32(pos, ctx));
+					a + (b - a) * (t * t * (3.0 - 2.0 * t))
+				}
+			},
+			SignedPow(l, r) => {
+				let l = l.eval_f32(pos, ctx);
+				let r = r.eval_f32(pos, ctx);
+				l.powf(r).copysign(l)
+			},
+
+			CoordTranslate(func, translation) => {
+				func.eval_f32(pos + translation.as_vec2().extend(0.0), ctx)
+			},
+			CoordScale(func, scale) => func.eval_f32(pos * scale.as_vec2().extend(1.0), ctx),
+			CoordRotate(func, rotation) => {
+				func.eval_f32(rotation.as_vec2().rotate(pos.xy()).extend(pos.z), ctx)
+			},
+			&CoordZ {
+				ref func,
+				offset,
+				scale,
+			} => func.eval_f32(vec3(pos.x, pos.y, pos.z * scale as f32 + offset as f32), ctx),
+			Warp {
+				func,
+				warpX,
+				warpY,
+				strength,
+			} => {
+				let offset = Vec2::new(warpX.eval_f32(pos, ctx), warpY.eval_f32(pos, ctx));
+				let offset = offset * *strength as f32;
+				func.eval_f32(pos + offset.extend(0.0), ctx)
+			},
+			&Tiled { ref func, period } => {
+				let period = period.as_vec2();
+				let wrapped = pos.xy().rem_euclid(period);
+				let t = wrapped / period;
+				let [v00, v10, v01, v11] = [(0.0, 0.0), (1.0, 0.0), (0.0, 1.0), (1.0, 1.0)]
+					.map(|(dx, dy)| {
+						func.eval_f32((wrapped - Vec2::new(dx, dy) * period).extend(pos.z), ctx)
+					});
+				let top = v00 + (v10 - v00) * t.x;
+				let bottom = v01 + (v11 - v01) * t.x;
+				top + (bottom - top) * t.y
+			},
+			&GradientMagnitude { ref func, epsilon } => {
+				let epsilon = epsilon as f32;
+				let [dx, dy] = [Vec3::X, Vec3::Y].map(|axis| {
+					let offset = axis * epsilon;
+					func.eval_f32(pos + offset, ctx) - func.eval_f32(pos - offset, ctx)
+				});
+				Vec2::new(dx, dy).length() / (2.0 * epsilon)
+			},
+
+			Cached { func, id } => {
+				let eval = || func.eval_f32(pos, ctx) as f64;
+				cached_eval(*id, Precision::F32, pos.as_dvec3(), ctx.time, eval) as f32
+			},
+			Ranged { func, .. } => func.eval_f32(pos, ctx),
+		}
+	}
+
+	/// Value of this function as `octave` of an `Octaves` node with `variation`, at the octave's
+	/// scaled position `pos`, turned by the octave's `rotation`.
+	fn eval_octave_f64(
+		&self,
+		variation: &OctaveVariation,
+		octave: usize,
+		rotation: DVec2,
+		pos: DVec3,
+		ctx: &EvalContext,
+	) -> f64 {
+		let pos = match variation.rotation {
+			Some(_) => rotation.rotate(pos.xy()).extend(pos.z),
+			None => pos,
+		};
+		match *self {
+			Noise::Simplex(seed) if variation.seedOffset != 0 => {
+				Noise::Simplex(variation.seed(seed, octave)).eval_f64(pos, ctx)
+			},
+			Noise::SimplexFast(seed) if variation.seedOffset != 0 => {
+				Noise::SimplexFast(variation.seed(seed, octave)).eval_f64(pos, ctx)
+			},
+			_ => self.eval_f64(pos + variation.shift(octave).extend(0.0), ctx),
+		}
+	}
+
+	fn eval_octave_f32(
+		&self,
+		variation: &OctaveVariation,
+		octave: usize,
+		rotation: DVec2,
+		pos: Vec3,
+		ctx: &EvalContext,
+	) -> f32 {
+		let pos = match variation.rotation {
+			Some(_) => rotation.as_vec2().rotate(pos.xy()).extend(pos.z),
+			None => pos,
+		};
+		match *self {
+			Noise::Simplex(seed) if variation.seedOffset != 0 => {
+				Noise::Simplex(variation.seed(seed, octave)).eval_f32(pos, ctx)
+			},
+			Noise::SimplexFast(seed) if variation.seedOffset != 0 => {
+				Noise::SimplexFast(variation.seed(seed, octave)).eval_f32(pos, ctx)
+			},
+			_ => self.eval_f32(pos + variation.shift(octave).as_vec2().extend(0.0), ctx),
+		}
+	}
+
+	/// First runtime error of the tree's Lua functions since the last call, clearing them.
+	pub fn take_lua_error(&self) -> Option<String> {
+		let mut first = None;
+		self.visit(&mut |_, node| {
+			if let Noise::LuaFunc(func) = node {
+				let error = func.error.lock().unwrap().take();
+				first = first.take().or(error);
+			}
+		});
+		first
+	}
+
+	/// Total number of octaves across all `Octaves` nodes skipped because their amplitude
+	/// underflowed.
+	pub fn skipped_octaves(&self) -> usize {
+		let mut skipped = 0;
+		self.visit(&mut |_, node| {
+			if let &Noise::Octaves {
+				octaves, ampScale, ..
+			} = node
+			{
+				skipped += octaves - active_octaves(octaves, ampScale);
+			}
+		});
+		skipped
+	}
+
+	/// Size and estimated evaluation cost of the tree.
+	pub fn stats(&self) -> NoiseStats {
+		fn walk(node: &Noise, depth: usize, evaluations: f64, stats: &mut NoiseStats) {
+			let kind = node.kind_name();
+			stats.depth = stats.depth.max(depth);
+			*stats.kinds.entry(kind).or_default() += 1;
+			*stats.evaluations.entry(kind).or_default() += evaluations;
+			stats.perNode.push(evaluations);
+			let repeats = match *node {
+				Noise::Octaves {
+					octaves, ampScale, ..
+				} => active_octaves(octaves, ampScale),
+				// one evaluation per corner of the period, or per side of each axis
+				Noise::Tiled { .. } | Noise::GradientMagnitude { .. } => 4,
+				_ => 1,
+			};
+			for child in node.children() {
+				walk(child, depth + 1, evaluations * repeats as f64, stats);
+			}
+		}
+		let mut stats = NoiseStats::default();
+		walk(self, 1, 1.0, &mut stats);
+		stats
+	}
+
+	/// Wraps the tree in a `Cached` node, optimized now as `optimize` leaves cached subtrees alone.
+	/// Cached trees are returned as they are.
+	pub fn cached(self) -> Self {
+		match self {
+			cached @ Noise::Cached { .. } => cached,
+			func => Noise::Cached {
+				func: Arc::new(func.optimize()),
+				id: next_cached_id(),
+			},
+		}
+	}
+
+	/// The tree with every `Cached` node replaced by its subtree, evaluated at every use again.
+	pub fn uncached(&self) -> Self {
+		fn inline(node: &mut Noise) {
+			if let Noise::Cached { func, .. } = node {
+				*node = (**func).clone();
+			}
+			for child in node.children_mut() {
+				inline(child);
+			}
+		}
+		let mut tree = self.clone();
+		inline(&mut tree);
+		tree
+	}
+
+	/// Range the output is meant to span, which display and meshing map to `[-1, 1]`. Declared by
+	/// the script through `withRange`, `[-1, 1]` otherwise.
+	pub fn output_range(&self) -> (f32, f32) {
+		match *self {
+			Noise::Ranged { min, max, .. } => (min, max),
+			_ => (-1.0, 1.0),
+		}
+	}
+}
+
+impl Noise {
+	/// Conservative range of values this node can produce, or `None` if unknown or possibly
+	/// non-finite. Float ops are monotonic, so bounds computed with the same operations as the
+	/// `f64` evaluation hold exactly; `Precision::F32` can stray from them by rounding.
+	pub fn bounds(&self) -> Option<(f64, f64)> {
+		use Noise::*;
+		let bounds = match self {
+			&Const(v) => (v as f64, v as f64),
+			Func(_) | LuaFunc(_) => return None,
+			Simplex(_) | SimplexFast(_) | Simplex3(_) => (-1.0, 1.0),
+			&Worley {
+				distanceFn,
+				returnType,
+				..
+			} => match returnType {
+				CellReturn::F1 => (0.0, distanceFn.max_in_cell()),
+				CellReturn::F2 | CellReturn::F2MinusF1 => (0.0, distanceFn.max_in_neighbor()),
+			},
+			SimplexTileable { .. } | SimplexLooped { .. } | CellId { .. } => (-1.0, 1.0),
+			White(_) | Checker(_) | Image { .. } => (-1.0, 1.0),
+			Value(_) | Perlin { .. } => (-1.0, 1.0),
+			// as unbounded as the domain
+			GradientX | GradientY | Radial(_) | Time => return None,
+			&Octaves {
+				ref func,
+				octaves,
+				ampScale,
+				mode,
+				divisor,
+				..
+			} => {
+				let (lo, hi) = mode.bounds(func.bounds()?);
+				if !(ampScale >= 0.0) {
+					return None;
+				}
+				let weighted = matches!(mode, OctaveMode::Ridged { gain: Some(_) });
+				let (mut resLo, mut resHi) = (0.0, 0.0);
+				let mut amp = 1.0;
+				for octave in 0 .. octaves {
+					if amp < minOctaveAmp {
+						break;
+					}
+					// a weight anywhere in [0, 1] can pull the octave to zero
+					let (lo, hi) = if weighted && octave > 0 {
+						(lo.min(0.0), hi.max(0.0))
+					} else {
+						(lo, hi)
+					};
+					resLo += amp * lo;
+					resHi += amp * hi;
+					amp *= ampScale as f64;
+				}
+				(resLo / divisor, resHi / divisor)
+			},
+
+			Add(l, r) => {
+				let (l, r) = (l.bounds()?, r.bounds()?);
+				(l.0 + r.0, l.1 + r.1)
+			},
+			Sub(l, r) => {
+				let (l, r) = (l.bounds()?, r.bounds()?);
+				(l.0 - r.1, l.1 - r.0)
+			},
+			Mul(l, r) | MulSkipZero(l, r) => {
+				let (l, r) = (l.bounds()?, r.bounds()?);
+				let products = [l.0 * r.0, l.0 * r.1, l.1 * r.0, l.1 * r.1];
+				(
+					products.into_iter().fold(f64::INFINITY, f64::min),
+					products.into_iter().fold(f64::NEG_INFINITY, f64::max),
+				)
+			},
+			Floor(v) => {
+				let (lo, hi) = v.bounds()?;
+				(lo.floor(), hi.floor())
+			},
+			Ceil(v) => {
+				let (lo, hi) = v.bounds()?;
+				(lo.ceil(), hi.ceil())
+			},
+			Abs(v) => {
+				let (lo, hi) = v.bounds()?;
+				if lo >= 0.0 {
+					(lo, hi)
+				} else if hi <= 0.0 {
+					(-hi, -lo)
+				} else {
+					(0.0, hi.max(-lo))
+				}
+			},
+			Min(l, r) => {
+				let (l, r) = (l.bounds()?, r.bounds()?);
+				(l.0.min(r.0), l.1.min(r.1))
+			},
+			Max(l, r) => {
+				let (l, r) = (l.bounds()?, r.bounds()?);
+				(l.0.max(r.0), l.1.max(r.1))
+			},
+			&Clamp { ref func, min, max } => {
+				let (lo, hi) = func.bounds()?;
+				let (min, max) = (min as f64, max as f64);
+				(lo.clamp(min, max), hi.clamp(min, max))
+			},
+			// the output only ever interpolates between the points' outputs
+			Curve { points, .. } => {
+				let outputs = points.iter().map(|p| p.y);
+				let lo = outputs.clone().fold(f64::INFINITY, f64::min);
+				(lo, outputs.fold(f64::NEG_INFINITY, f64::max))
+			},
+			ToUnsignedUnit(v) => {
+				let (lo, hi) = v.bounds()?;
+				((lo + 1.0) / 2.0, (hi + 1.0) / 2.0)
+			},
+			ToSignedUnit(v) => {
+				let (lo, hi) = v.bounds()?;
+				(lo * 2.0 - 1.0, hi * 2.0 - 1.0)
+			},
+			Lerp { a, b, t, clamped } => {
+				let (lo, hi) = t.bounds()?;
+				let t = if *clamped {
+					(lo.clamp(0.0, 1.0), hi.clamp(0.0, 1.0))
+				} else {
+					(lo, hi)
+				};
+				lerp_bounds(a.bounds()?, b.bounds()?, t)
+			},
+			// smoothstep maps `[0, 1]` onto itself, and is monotonic in between
+			SmoothLerp { a, b, t } => {
+				t.bounds()?;
+				lerp_bounds(a.bounds()?, b.bounds()?, (0.0, 1.0))
+			},
+			Select { control, a, b, .. } => {
+				control.bounds()?;
+				lerp_bounds(a.bounds()?, b.bounds()?, (0.0, 1.0))
+			},
+
+			CoordTranslate(func, _) | CoordScale(func, _) | CoordRotate(func, _) => func.bounds()?,
+			CoordZ { func, .. } => func.bounds()?,
+			// unbounded displacements could be non-finite, and no position is safe from those
+			Warp {
+				func, warpX, warpY, ..
+			} => {
+				warpX.bounds()?;
+				warpY.bounds()?;
+				func.bounds()?
+			},
+			// a weighted average of the copies
+			Tiled { func, .. } => func.bounds()?,
+			// differences of bounded values over a small step can still be huge
+			GradientMagnitude { .. } => return None,
+			Cached { func, .. } | Ranged { func, .. } => func.bounds()?,
+
+			Div(..) | Pow(..) | Rem(..) | RemEuclid(..) | SignedPow(..) => return None,
+		};
+		// values past the f32 range overflow once cast at the root
+		let limit = f32::MAX as f64;
+		(bounds.0.abs() <= limit && bounds.1.abs() <= limit).then_some(bounds)
+	}
+
+	/// Rewrites the tree to skip work that can't affect the result: `Min`/`Max` whose operand
+	/// ranges don't overlap and `Select` whose control stays on one side of the blend collapse
+	/// to that side, and `Mul` with a finite side that is never negative, `-0` included, becomes
+	/// `MulSkipZero`. Output is bit-identical to the original tree's.
+	pub fn optimize(self) -> Self {
+		use Noise::*;
+		let opt = |v: NoisePtr| -> NoisePtr { Box::new(v.optimize()) };
+		// a zero times anything else would take that side's sign
+		let skippable = |v: &Noise| match v.bounds() {
+			Some((lo, _)) => lo > 0.0 || matches!(v, Abs(_)),
+			None => false,
+		};
+		match self {
+			Octaves {
+				func,
+				octaves,
+				ampScale,
+				freqScale,
+				mode,
+				variation,
+				divisor,
+			} => Octaves {
+				func: opt(func),
+				octaves,
+				ampScale,
+				freqScale,
+				mode,
+				variation,
+				divisor,
+			},
+
+			Add(l, r) => Add(opt(l), opt(r)),
+			Sub(l, r) => Sub(opt(l), opt(r)),
+			Mul(l, r) | MulSkipZero(l, r) => {
+				let (l, r) = (opt(l), opt(r));
+				if skippable(&r) {
+					MulSkipZero(l, r)
+				} else if skippable(&l) {
+					MulSkipZero(r, l)
+				} else {
+					Mul(l, r)
+				}
+			},
+			Div(l, r) => Div(opt(l), opt(r)),
+			Pow(l, r) => Pow(opt(l), opt(r)),
+			Rem(l, r) => Rem(opt(l), opt(r)),
+			RemEuclid(l, r) => RemEuclid(opt(l), opt(r)),
+			SignedPow(l, r) => SignedPow(opt(l), opt(r)),
+			Floor(v) => Floor(opt(v)),
+			Ceil(v) => Ceil(opt(v)),
+			Abs(v) => Abs(opt(v)),
+			Min(l, r) => {
+				let (l, r) = (opt(l), opt(r));
+				match (l.bounds(), r.bounds()) {
+					(Some(lb), Some(rb)) if lb.1 < rb.0 => *l,
+					(Some(lb), Some(rb)) if rb.1 < lb.0 => *r,
+					_ => Min(l, r),
+				}
+			},
+			Max(l, r) => {
+				let (l, r) = (opt(l), opt(r));
+				match (l.bounds(), r.bounds()) {
+					(Some(lb), Some(rb)) if lb.0 > rb.1 => *l,
+					(Some(lb), Some(rb)) if rb.0 > lb.1 => *r,
+					_ => Max(l, r),
+				}
+			},
+			Clamp { func, min, max } => Clamp {
+				func: opt(func),
+				min,
+				max,
+			},
+			Curve { func, points } => Curve {
+				func: opt(func),
+				points,
+			},
+			ToUnsignedUnit(v) => ToUnsignedUnit(opt(v)),
+			ToSignedUnit(v) => ToSignedUnit(opt(v)),
+			Lerp { a, b, t, clamped } => Lerp {
+				a: opt(a),
+				b: opt(b),
+				t: opt(t),
+				clamped,
+			},
+			SmoothLerp { a, b, t } => SmoothLerp {
+				a: opt(a),
+				b: opt(b),
+				t: opt(t),
+			},
+			Select {
+				control,
+				a,
+				b,
+				threshold,
+				falloff,
+			} => {
+				let control = opt(control);
+				let (lo, hi) = (threshold - falloff, threshold + falloff);
+				match control.bounds() {
+					Some(bounds) if bounds.0 >= hi => *opt(b),
+					Some(bounds) if bounds.1 <= lo && bounds.1 < hi => *opt(a),
+					_ => Select {
+						control,
+						a: opt(a),
+						b: opt(b),
+						threshold,
+						falloff,
+					},
+				}
+			},
+
+			CoordTranslate(f, v) => CoordTranslate(opt(f), v),
+			CoordScale(f, v) => CoordScale(opt(f), v),
+			CoordRotate(f, v) => CoordRotate(opt(f), v),
+			CoordZ {
+				func,
+				offset,
+				scale,
+			} => CoordZ {
+				func: opt(func),
+				offset,
+				scale,
+			},
+			Warp {
+				func,
+				warpX,
+				warpY,
+				strength,
+			} => Warp {
+				func: opt(func),
+				warpX: opt(warpX),
+				warpY: opt(warpY),
+				strength,
+			},
+			Tiled { func, period } => Tiled {
+				func: opt(func),
+				period,
+			},
+			GradientMagnitude { func, epsilon } => GradientMagnitude {
+				func: opt(func),
+				epsilon,
+			},
+
+			Ranged { func, min, max } => Ranged {
+				func: opt(func),
+				min,
+				max,
+			},
+
+			leaf @ (Const(_) | Func(_) | Simplex(_) | SimplexFast(_) | Simplex3(_)) => leaf,
+			leaf @ LuaFunc(_) => leaf,
+			leaf @ (SimplexTileable { .. } | SimplexLooped { .. } | Worley { .. }) => leaf,
+			leaf @ (CellId { .. } | Value(_) | Perlin { .. }) => leaf,
+			leaf @ (White(_) | GradientX | GradientY | Radial(_) | Checker(_) | Time) => leaf,
+			leaf @ Image { .. } => leaf,
+			// optimized when cached, see `Noise::cached`
+			leaf @ Cached { .. } => leaf,
+		}
+	}
+}
+
+/// Piecewise linear curve through `points`, sorted by input, at `v`. Inputs beyond the first or
+/// last point take its output, and where two points share an input the curve jumps to the later
+/// one's output.
+pub fn eval_curve(points: &[DVec2], v: f64) -> f64 {
+	if v.is_nan() {
+		return v;
+	}
+	let next = points.partition_point(|p| p.x <= v);
+	if next == 0 {
+		return points[0].y;
+	}
+	let Some(&b) = points.get(next) else {
+		return points[next - 1].y;
+	};
+	let a = points[next - 1];
+	a.y + (b.y - a.y) * (v - a.x) / (b.x - a.x)
+}
+
+/// Points of `Noise::terrace`: `steps` plateaus evenly spread over `[-1, 1]` in both input and
+/// output, joined by ramps taking up `smoothness` of each step's width, or jumps at 0.
+fn terrace_points(steps: usize, smoothness: f64) -> Vec<DVec2> {
+	let width = 2.0 / steps as f64;
+	let ramp = smoothness * width / 2.0;
+	(0 .. steps)
+		.flat_map(|step| {
+			let level = -1.0 + 2.0 * step as f64 / (steps - 1) as f64;
+			let start = -1.0 + width * step as f64 + if step > 0 { ramp } else { 0.0 };
+			let end = -1.0 + width * (step + 1) as f64 - if step + 1 < steps { ramp } else { 0.0 };
+			[dvec2(start, level), dvec2(end, level)]
+		})
+		.collect()
+}
+
+/// Bounds of `a + (b - a) * t` by interval arithmetic over the same operations, so they hold
+/// exactly despite rounding.
+fn lerp_bounds(a: (f64, f64), b: (f64, f64), t: (f64, f64)) -> (f64, f64) {
+	let d = (b.0 - a.1, b.1 - a.0);
+	let products = [d.0 * t.0, d.0 * t.1, d.1 * t.0, d.1 * t.1];
+	let lo = products.into_iter().fold(f64::INFINITY, f64::min);
+	let hi = products.into_iter().fold(f64::NEG_INFINITY, f64::max);
+	(a.0 + lo, a.1 + hi)
+}
+
+/// ID of a new `Noise::Cached` node, unique across trees so values of one are never taken for
+/// another's.
+fn next_cached_id() -> u64 {
+	static nextId: AtomicU64 = AtomicU64::new(0);
+	nextId.fetch_add(1, Ordering::Relaxed)
+}
+
+/// Value a `Noise::Cached` node computed, and where.
+#[derive(Clone, Copy)]
+struct CachedValue {
+	id: u64,
+	precision: Precision,
+	pos: DVec3,
+	time: f64,
+	value: f64,
+}
+
+/// Value of the `Cached` node `id` at `pos`, reusing the last one it computed on this thread if
+/// that was at the same position and time, which is how the other uses of it within a sample see
+/// it. Otherwise computes it with `eval`, replacing the node's last value. Nodes are in the
+/// memo only while evaluated, so trees without any pay nothing.
+fn cached_eval(
+	id: u64,
+	precision: Precision,
+	pos: DVec3,
+	time: f64,
+	eval: impl FnOnce() -> f64,
+) -> f64 {
+	let isNode = |cached: &CachedValue| cached.id == id && cached.precision == precision;
+	let hit = cachedValues.with(|values| {
+		let values = values.borrow();
+		let last = values.iter().find(|cached| isNode(cached))?;
+		(last.pos == pos && last.time == time).then_some(last.value)
+	});
+	if let Some(value) = hit {
+		return value;
+	}
+	// not borrowed meanwhile, the subtree may hold cached nodes too
+	let value = eval();
+	cachedValues.with(|values| {
+		let mut values = values.borrow_mut();
+		let cached = CachedValue {
+			id,
+			precision,
+			pos,
+			time,
+			value,
+		};
+		match values.iter_mut().find(|last| isNode(last)) {
+			Some(last) => *last = cached,
+			None => {
+				if values.len() >= cachedValuesSize {
+					values.remove(0);
+				}
+				values.push(cached);
+			},
+		}
+	});
+	value
+}
+
+/// Octaves of a stack that are evaluated, the rest having an amplitude too small to contribute.
+fn active_octaves(octaves: usize, ampScale: f32) -> usize {
+	let mut amp = 1f64;
+	(0 .. octaves)
+		.take_while(|_| {
+			let active = amp.abs() >= minOctaveAmp;
+			amp *= ampScale as f64;
+			active
+		})
+		.count()
+}
+
+/// Size and estimated cost of a tree, see `Noise::stats`. Evaluations are counted per sample,
+/// multiplied through octaves, tiling and gradient magnitudes. Both sides of selects and skipping
+/// multiplications are counted, so costs are upper bounds.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct NoiseStats {
+	/// Longest path from the root to a leaf, in nodes.
+	pub depth: usize,
+	/// Nodes of each kind, by `Noise::kind_name`.
+	pub kinds: BTreeMap<&'static str, usize>,
+	/// Evaluations of each kind per sample.
+	pub evaluations: BTreeMap<&'static str, f64>,
+	/// Evaluations per sample of each node, by pre-order ID.
+	pub perNode: Vec<f64>,
+}
+
+impl NoiseStats {
+	pub fn nodes(&self) -> usize {
+		self.perNode.len()
+	}
+
+	/// Evaluations per sample of the tree's noise sources, which dominate its cost.
+	pub fn source_evaluations(&self) -> f64 {
+		let sources = [
+			"Simplex",
+			"SimplexFast",
+			"Simplex3",
+			"SimplexTileable",
+			"SimplexLooped",
+			"Worley",
+			"CellId",
+			"White",
+			"Value",
+			"Perlin",
+			"Image",
+			"Func",
+			"LuaFunc",
+		];
+		sources.iter().filter_map(|kind| self.evaluations.get(kind)).sum()
+	}
+}
+
+/// One coordinate transform between the root and a node.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CoordStep {
+	Translate(DVec2),
+	Scale(DVec2),
+	Rotate(DVec2),
+	/// A node that doesn't pass a single well-defined position to its children.
+	Opaque(&'static str),
+}
+
+/// Transforms applied to the domain position on the way from the root to some node, outermost
+/// first.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CoordChain(pub Vec<CoordStep>);
+
+impl CoordChain {
+	/// The position the node sees when the root is evaluated at `pos`, or `None` if an opaque
+	/// node is in the way.
+	pub fn apply(&self, mut pos: DVec2) -> Option<DVec2> {
+		for step in &self.0 {
+			pos = match *step {
+				CoordStep::Translate(translation) => pos + translation,
+				CoordStep::Scale(scale) => pos * scale,
+				CoordStep::Rotate(rotation) => rotation.rotate(pos),
+				CoordStep::Opaque(_) => return None,
+			};
+		}
+		Some(pos)
+	}
+
+	/// First node on the chain that hides the position from the node.
+	pub fn opaque(&self) -> Option<&'static str> {
+		self.0.iter().find_map(|step| match *step {
+			CoordStep::Opaque(kind) => Some(kind),
+			_ => None,
+		})
+	}
+}
+
+/// Node a non-finite value comes from, see `Noise::trace_non_finite`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct NonFiniteSource {
+	/// Pre-order ID, see `Noise::visit`.
+	pub node: usize,
+	pub kind: &'static str,
+	/// Whether the node turns finite inputs into the value. Otherwise it's the innermost node that
+	/// could be checked, its inputs seeing positions that aren't known from outside, like the
+	/// octaves of `Octaves`.
+	pub exact: bool,
+}
+
+impl Noise {
+	/// Direct children in evaluation order.
+	pub fn children(&self) -> Vec<&Noise> {
+		use Noise::*;
+		match self {
+			Const(_) | Func(_) | LuaFunc(_) | Simplex(_) | SimplexFast(_) | Simplex3(_) => vec![],
+			SimplexTileable { .. } | SimplexLooped { .. } | Worley { .. } | CellId { .. } => vec![],
+			White(_) | GradientX | GradientY | Radial(_) | Checker(_) | Time => vec![],
+			Value(_) | Perlin { .. } | Image { .. } => vec![],
+			Octaves { func, .. } | Clamp { func, .. } | Curve { func, .. } | Ranged { func, .. } => {
+				vec![&**func]
+			},
+			CoordZ { func, .. } => vec![&**func],
+			Add(l, r) |
+			Sub(l, r) |
+			Mul(l, r) |
+			MulSkipZero(l, r) |
+			Div(l, r) |
+			Pow(l, r) |
+			Rem(l, r) |
+			RemEuclid(l, r) |
+			SignedPow(l, r) |
+			Min(l, r) |
+			Max(l, r) => vec![&**l, &**r],
+			Floor(v) | Ceil(v) | Abs(v) | ToUnsignedUnit(v) | ToSignedUnit(v) => vec![&**v],
+			Lerp { a, b, t, .. } | SmoothLerp { a, b, t } => vec![&**a, &**b, &**t],
+			Select { control, a, b, .. } => vec![&**control, &**a, &**b],
+			CoordTranslate(func, _) | CoordScale(func, _) | CoordRotate(func, _) => vec![&**func],
+			Warp {
+				func, warpX, warpY, ..
+			} => vec![&**warpX, &**warpY, &**func],
+			Tiled { func, .. } | GradientMagnitude { func, .. } => vec![&**func],
+			Cached { func, .. } => vec![&**func],
+		}
+	}
+
+	/// Mutable access to the direct children, in the same order as `children`. A `Cached` node's
+	/// subtree is unshared first, and keeps its ID: passes changing its values must go through
+	/// `rewrite_cached` instead.
+	pub fn children_mut(&mut self) -> Vec<&mut Noise> {
+		use Noise::*;
+		match self {
+			Const(_) | Func(_) | LuaFunc(_) | Simplex(_) | SimplexFast(_) | Simplex3(_) => vec![],
+			SimplexTileable { .. } | SimplexLooped { .. } | Worley { .. } | CellId { .. } => vec![],
+			White(_) | GradientX | GradientY | Radial(_) | Checker(_) | Time => vec![],
+			Value(_) | Perlin { .. } | Image { .. } => vec![],
+			Octaves { func, .. } | Clamp { func, .. } | Curve { func, .. } | Ranged { func, .. } => {
+				vec![&mut **func]
+			},
+			CoordZ { func, .. } => vec![&mut **func],
+			Add(l, r) |
+			Sub(l, r) |
+			Mul(l, r) |
+			MulSkipZero(l, r) |
+			Div(l, r) |
+			Pow(l, r) |
+			Rem(l, r) |
+			RemEuclid(l, r) |
+			SignedPow(l, r) |
+			Min(l, r) |
+			Max(l, r) => vec![&mut **l, &mut **r],
+			Floor(v) | Ceil(v) | Abs(v) | ToUnsignedUnit(v) | ToSignedUnit(v) => vec![&mut **v],
+			Lerp { a, b, t, .. } | SmoothLerp { a, b, t } => vec![&mut **a, &mut **b, &mut **t],
+			Select { control, a, b, .. } => vec![&mut **control, &mut **a, &mut **b],
+			CoordTranslate(func, _) | CoordScale(func, _) | CoordRotate(func, _) => {
+				vec![&mut **func]
+			},
+			Warp {
+				func, warpX, warpY, ..
+			} => vec![&mut **warpX, &mut **warpY, &mut **func],
+			Tiled { func, .. } | GradientMagnitude { func, .. } => vec![&mut **func],
+			Cached { func, .. } => vec![Arc::make_mut(func)],
+		}
+	}
+
+	/// Adds `offset` to the seed of every seeded node, giving a different variation of the same
+	/// tree. Opaque `Func` nodes are left alone.
+	pub fn reseed(&mut self, offset: i64) {
+		self.reseed_with(offset, &mut HashMap::new());
+	}
+
+	fn reseed_with(&mut self, offset: i64, rewritten: &mut HashMap<u64, Noise>) {
+		use Noise::*;
+		match self {
+			Simplex(seed) |
+			SimplexFast(seed) |
+			Simplex3(seed) |
+			Worley { seed, .. } |
+			CellId { seed, .. } |
+			White(seed) |
+			Value(seed) |
+			SimplexTileable { seed, .. } |
+			SimplexLooped { seed, .. } => *seed = seed.wrapping_add(offset),
+			Octaves { variation, .. } => {
+				variation.jitterSeed = variation.jitterSeed.wrapping_add(offset);
+			},
+			Perlin { seed, perm } => {
+				*seed = seed.wrapping_add(offset);
+				*perm = Arc::new(perlin_permutation(*seed));
+			},
+			Cached { .. } => {
+				self.rewrite_cached(rewritten, |func, rewritten| {
+					func.reseed_with(offset, rewritten)
+				});
+				return;
+			},
+			_ => {},
+		}
+		for child in self.children_mut() {
+			child.reseed_with(offset, rewritten);
+		}
+	}
+
+	/// Wraps every stochastic source in a rotation and offset derived from `seed` and the source's
+	/// pre-order ID, so sources sharing a seed no longer line up. Periodic sources are left alone, as
+	/// rotating them would break their period.
+	pub fn decorrelate(&mut self, seed: i64) {
+		self.decorrelate_from(seed as u64, &mut 0, &mut HashMap::new());
+	}
+
+	fn decorrelate_from(&mut self, seed: u64, id: &mut u64, rewritten: &mut HashMap<u64, Noise>) {
+		use Noise::*;
+		if let Cached { .. } = self {
+			// copies are decorrelated alike, by the IDs below the first one
+			self.rewrite_cached(rewritten, |func, rewritten| {
+				func.decorrelate_from(seed, id, rewritten)
+			});
+			return;
+		}
+		let nodeId = *id;
+		*id += 1;
+		if matches!(
+			self,
+			Simplex(_) | SimplexFast(_) | Simplex3(_) | Worley { .. } | CellId { .. } | White(_) |
+				Value(_) | Perlin { .. }
+		) {
+			let angle = noise_rng::unit(noise_rng::hash(seed, nodeId, 2)) * TAU;
+			let offset = dvec2(
+				noise_rng::signed_unit(noise_rng::hash(seed, nodeId, 3)),
+				noise_rng::signed_unit(noise_rng::hash(seed, nodeId, 4)),
+			) * decorrelateOffset;
+			let source = std::mem::replace(self, Const(0.0));
+			*self = CoordTranslate(
+				CoordRotate(source.into(), DVec2::from_angle(angle)).into(),
+				offset,
+			);
+			return;
+		}
+		for child in self.children_mut() {
+			child.decorrelate_from(seed, id, rewritten);
+		}
+	}
+
+	/// Applies `rewrite` to the subtree of this `Cached` node, under a new ID as its values change.
+	/// Copies of the node met later get the same rewritten node from `rewritten`, so they still
+	/// share it.
+	fn rewrite_cached(
+		&mut self,
+		rewritten: &mut HashMap<u64, Noise>,
+		rewrite: impl FnOnce(&mut Noise, &mut HashMap<u64, Noise>),
+	) {
+		let Noise::Cached { func, id } = self else {
+			unreachable!("only cached nodes are rewritten as shared");
+		};
+		if let Some(done) = rewritten.get(id) {
+			*self = done.clone();
+			return;
+		}
+		let original = *id;
+		let mut func = (**func).clone();
+		rewrite(&mut func, rewritten);
+		*self = Noise::Cached {
+			func: Arc::new(func),
+			id: next_cached_id(),
+		};
+		rewritten.insert(original, self.clone());
+	}
+
+	/// Variant name, for display.
+	pub fn kind_name(&self) -> &'static str {
+		use Noise::*;
+		match self {
+			Const(_) => "Const",
+			Func(_) => "Func",
+			LuaFunc(_) => "LuaFunc",
+			Simplex(_) => "Simplex",
+			SimplexFast(_) => "SimplexFast",
+			Simplex3(_) => "Simplex3",
+			Worley { .. } => "Worley",
+			CellId { .. } => "CellId",
+			White(_) => "White",
+			Value(_) => "Value",
+			Perlin { .. } => "Perlin",
+			GradientX => "GradientX",
+			Time => "Time",
+			GradientY => "GradientY",
+			Radial(_) => "Radial",
+			Checker(_) => "Checker",
+			Image { .. } => "Image",
+			SimplexTileable { .. } => "SimplexTileable",
+			SimplexLooped { .. } => "SimplexLooped",
+			Octaves { .. } => "Octaves",
+			Add(..) => "Add",
+			Sub(..) => "Sub",
+			Mul(..) => "Mul",
+			MulSkipZero(..) => "MulSkipZero",
+			Div(..) => "Div",
+			Pow(..) => "Pow",
+			Rem(..) => "Rem",
+			RemEuclid(..) => "RemEuclid",
+			SignedPow(..) => "SignedPow",
+			Floor(_) => "Floor",
+			Ceil(_) => "Ceil",
+			Abs(_) => "Abs",
+			Min(..) => "Min",
+			Max(..) => "Max",
+			Clamp { .. } => "Clamp",
+			Curve { .. } => "Curve",
+			ToUnsignedUnit(_) => "ToUnsignedUnit",
+			ToSignedUnit(_) => "ToSignedUnit",
+			Lerp { .. } => "Lerp",
+			SmoothLerp { .. } => "SmoothLerp",
+			Select { .. } => "Select",
+			CoordTranslate(..) => "CoordTranslate",
+			CoordScale(..) => "CoordScale",
+			CoordRotate(..) => "CoordRotate",
+			CoordZ { .. } => "CoordZ",
+			Warp { .. } => "Warp",
+			Tiled { .. } => "Tiled",
+			GradientMagnitude { .. } => "GradientMagnitude",
+			Cached { .. } => "Cached",
+			Ranged { .. } => "Ranged",
+		}
+	}
+
+	/// Transform applied by this node to the position passed to its children.
+	fn coord_step(&self) -> Option<CoordStep> {
+		use Noise::*;
+		match self {
+			&CoordTranslate(_, translation) => Some(CoordStep::Translate(translation)),
+			&CoordScale(_, scale) => Some(CoordStep::Scale(scale)),
+			&CoordRotate(_, rotation) => Some(CoordStep::Rotate(rotation)),
+			Octaves { .. } => Some(CoordStep::Opaque("Octaves")),
+			Warp { .. } => Some(CoordStep::Opaque("Warp")),
+			Tiled { .. } => Some(CoordStep::Opaque("Tiled")),
+			GradientMagnitude { .. } => Some(CoordStep::Opaque("GradientMagnitude")),
+			_ => None,
+		}
+	}
+
+	/// Visits nodes in pre-order, which also defines their IDs: the root is 0 and IDs are stable
+	/// for a given tree.
+	pub fn visit<'a>(&'a self, func: &mut dyn FnMut(usize, &'a Noise)) {
+		fn visit<'a>(node: &'a Noise, next: &mut usize, func: &mut dyn FnMut(usize, &'a Noise)) {
+			func(*next, node);
+			*next += 1;
+			for child in node.children() {
+				visit(child, next, func);
+			}
+		}
+		visit(self, &mut 0, func);
+	}
+
+	/// Positions the children are evaluated at when this node is at `pos`, in `children` order.
+	/// `None` for children seeing more than one position.
+	fn child_positions(
+		&self,
+		pos: DVec3,
+		precision: Precision,
+		ctx: &EvalContext,
+	) -> Vec<Option<DVec3>> {
+		use Noise::*;
+		let count = self.children().len();
+		let all = |pos: DVec3| vec![Some(pos); count];
+		match self {
+			CoordTranslate(_, translation) => all(pos + translation.extend(0.0)),
+			CoordScale(_, scale) => all(pos * scale.extend(1.0)),
+			CoordRotate(_, rotation) => all(rotation.rotate(pos.xy()).extend(pos.z)),
+			&CoordZ { offset, scale, .. } => all(dvec3(pos.x, pos.y, pos.z * scale + offset)),
+			Warp {
+				warpX,
+				warpY,
+				strength,
+				..
+			} => {
+				let eval = |warp: &Noise| warp.eval3_with(pos, precision, ctx) as f64;
+				let offset = dvec2(eval(warpX), eval(warpY)) * *strength;
+				let displaced = offset.is_finite().then(|| pos + offset.extend(0.0));
+				vec![Some(pos), Some(pos), displaced]
+			},
+			Octaves { .. } | Tiled { .. } | GradientMagnitude { .. } => vec![None; count],
+			_ => all(pos),
+		}
+	}
+
+	/// Finds where the non-finite value the tree evaluates to at `pos` comes from, following
+	/// non-finite children down to the first node producing it from finite ones. `None` if the
+	/// value at `pos` is finite.
+	pub fn trace_non_finite(
+		&self,
+		pos: DVec3,
+		precision: Precision,
+		ctx: &EvalContext,
+	) -> Option<NonFiniteSource> {
+		fn trace(
+			node: &Noise,
+			id: usize,
+			pos: DVec3,
+			precision: Precision,
+			ctx: &EvalContext,
+		) -> NonFiniteSource {
+			let mut childId = id + 1;
+			let mut opaque = false;
+			let positions = node.child_positions(pos, precision, ctx);
+			for (child, childPos) in node.children().into_iter().zip(positions) {
+				match childPos {
+					Some(childPos) if !child.eval3_with(childPos, precision, ctx).is_finite() => {
+						return trace(child, childId, childPos, precision, ctx);
+					},
+					Some(_) => {},
+					None => opaque = true,
+				}
+				child.visit(&mut |_, _| childId += 1);
+			}
+			NonFiniteSource {
+				node: id,
+				kind: node.kind_name(),
+				exact: !opaque,
+			}
+		}
+		let v = self.eval3_with(pos, precision, ctx);
+		(!v.is_finite()).then(|| trace(self, 0, pos, precision, ctx))
+	}
+
+	/// `kind_name` with the node's own parameters, leaving out its children, as `Display` shows.
+	fn describe(&self) -> String {
+		use Noise::*;
+		let kind = self.kind_name();
+		match self {
+			Const(v) => format!("{kind}({v})"),
+			Simplex(seed) | SimplexFast(seed) | Simplex3(seed) | White(seed) | Value(seed) => {
+				format!("{kind}({seed})")
+			},
+			Perlin { seed, .. } => format!("{kind}({seed})"),
+			Worley { seed, .. } | CellId { seed, .. } => format!("{kind}(seed {seed})"),
+			SimplexTileable { seed, period } => format!("{kind}(seed {seed}, period {period})"),
+			SimplexLooped { seed, period, time } => match time {
+				Some(time) => format!("{kind}(seed {seed}, period {period}, time {time})"),
+				None => format!("{kind}(seed {seed}, period {period})"),
+			},
+			Radial(center) => format!("{kind}({center})"),
+			Checker(size) => format!("{kind}({size})"),
+			Image { image, wrap } => {
+				let outside = if *wrap { "wrap" } else { "clamp" };
+				format!("{kind}({:?}, {outside})", image.path)
+			},
+			Octaves {
+				octaves,
+				ampScale,
+				freqScale,
+				mode,
+				variation,
+				divisor,
+				..
+			} => {
+				let mut res =
+					format!("{kind}({octaves}, amp {ampScale}, freq {freqScale}, {mode:?}");
+				if *divisor != 1.0 {
+					res += &format!(", divided by {divisor}");
+				}
+				if variation.is_none() {
+					res + ")"
+				} else {
+					format!("{res}, {variation:?})")
+				}
+			},
+			Clamp { min, max, .. } | Ranged { min, max, .. } => format!("{kind}({min}, {max})"),
+			Curve { points, .. } => format!("{kind}({} points)", points.len()),
+			Lerp { clamped, .. } => format!("{kind}(clamped {clamped})"),
+			Select {
+				threshold, falloff, ..
+			} => format!("{kind}(threshold {threshold}, falloff {falloff})"),
+			CoordTranslate(_, v) | CoordScale(_, v) | CoordRotate(_, v) => format!("{kind}({v})"),
+			Tiled { period, .. } => format!("{kind}({period})"),
+			CoordZ { offset, scale, .. } => format!("{kind}(offset {offset}, scale {scale})"),
+			Warp { strength, .. } => format!("{kind}(strength {strength})"),
+			GradientMagnitude { epsilon, .. } => format!("{kind}(epsilon {epsilon})"),
+			_ => kind.into(),
+		}
+	}
+
+	/// Whether the output depends on `EvalContext::time`, through `Time`, a Lua function or a
+	/// `SimplexLooped` without a fixed time.
+	pub fn uses_time(&self) -> bool {
+		let mut found = false;
+		self.visit(&mut |_, node| {
+			found |= matches!(
+				node,
+				Noise::Time | Noise::LuaFunc(_) | Noise::SimplexLooped { time: None, .. }
+			)
+		});
+		found
+	}
+
+	/// Node with the given pre-order ID.
+	pub fn node(&self, id: usize) -> Option<&Noise> {
+		let mut found = None;
+		self.visit(&mut |nodeId, node| {
+			if nodeId == id {
+				found = Some(node);
+			}
+		});
+		found
+	}
+
+	/// Transforms between the root and the node with the given ID.
+	pub fn coord_chain(&self, id: usize) -> Option<CoordChain> {
+		fn walk(node: &Noise, id: usize, next: &mut usize, chain: &mut Vec<CoordStep>) -> bool {
+			if *next == id {
+				return true;
+			}
+			*next += 1;
+			chain.extend(node.coord_step());
+			for child in node.children() {
+				if walk(child, id, next, chain) {
+					return true;
+				}
+			}
+			if node.coord_step().is_some() {
+				chain.pop();
+			}
+			false
+		}
+
+		let mut chain = vec![];
+		walk(self, id, &mut 0, &mut chain).then_some(CoordChain(chain))
+	}
+}
+
+/// The node with its parameters, or with `{:#}` the whole tree as one line per node indented by
+/// depth, for debugging scripts with `tostring`.
+impl fmt::Display for Noise {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		fn tree(node: &Noise, depth: usize, f: &mut fmt::Formatter) -> fmt::Result {
+			if depth > 0 {
+				writeln!(f)?;
+			}
+			write!(f, "{}{}", "  ".repeat(depth), node.describe())?;
+			for child in node.children() {
+				tree(child, depth + 1, f)?;
+			}
+			Ok(())
+		}
+		if f.alternate() { tree(self, 0, f) } else { f.write_str(&self.describe()) }
+	}
+}
+
+impl Clone for Noise {
+	fn clone(&self) -> Self {
+		use Noise::*;
+		match self {
+			&Const(v) => Const(v),
+			Func(f) => Func(dyn_clone::clone_box(&**f)),
+			LuaFunc(f) => LuaFunc(f.clone()),
+			&Simplex(seed) => Simplex(seed),
+			&SimplexFast(seed) => SimplexFast(seed),
+			&Simplex3(seed) => Simplex3(seed),
+			&Worley {
+				seed,
+				distanceFn,
+				returnType,
+			} => Worley {
+				seed,
+				distanceFn,
+				returnType,
+			},
+			&CellId { seed, jitter } => CellId { seed, jitter },
+			&White(seed) => White(seed),
+			&Value(seed) => Value(seed),
+			Perlin { seed, perm } => Perlin {
+				seed: *seed,
+				perm: perm.clone(),
+			},
+			GradientX => GradientX,
+			GradientY => GradientY,
+			Time => Time,
+			&Radial(center) => Radial(center),
+			&Checker(size) => Checker(size),
+			Image { image, wrap } => Image {
+				image: image.clone(),
+				wrap: *wrap,
+			},
+			&SimplexTileable { seed, period } => SimplexTileable { seed, period },
+			&SimplexLooped { seed, period, time } => SimplexLooped { seed, period, time },
+			Octaves {
+				func,
+				octaves,
+				freqScale,
+				ampScale,
+				mode,
+				variation,
+				divisor,
+			} => Octaves {
+				func: func.clone(),
+				octaves: *octaves,
+				freqScale: *freqScale,
+				ampScale: *ampScale,
+				mode: *mode,
+				variation: *variation,
+				divisor: *divisor,
+			},
+
+			Add(l, r) => Add(l.clone(), r.clone()),
+			Sub(l, r) => Sub(l.clone(), r.clone()),
+			Mul(l, r) => Mul(l.clone(), r.clone()),
+			MulSkipZero(l, r) => MulSkipZero(l.clone(), r.clone()),
+			Div(l, r) => Div(l.clone(), r.clone()),
+			Pow(l, r) => Pow(l.clone(), r.clone()),
+			Rem(l, r) => Rem(l.clone(), r.clone()),
+			RemEuclid(l, r) => RemEuclid(l.clone(), r.clone()),
+			SignedPow(l, r) => SignedPow(l.clone(), r.clone()),
+			Floor(v) => Floor(v.clone()),
+			Ceil(v) => Ceil(v.clone()),
+			Abs(v) => Abs(v.clone()),
+			Min(l, r) => Min(l.clone(), r.clone()),
+			Max(l, r) => Max(l.clone(), r.clone()),
+			Clamp { func, min, max } => Clamp {
+				func: func.clone(),
+				min: *min,
+				max: *max,
+			},
+			Curve { func, points } => Curve {
+				func: func.clone(),
+				points: points.clone(),
+			},
+			ToUnsignedUnit(v) => ToUnsignedUnit(v.clone()),
+			ToSignedUnit(v) => ToSignedUnit(v.clone()),
+			Lerp { a, b, t, clamped } => Lerp {
+				a: a.clone(),
+				b: b.clone(),
+				t: t.clone(),
+				clamped: *clamped,
+			},
+			SmoothLerp { a, b, t } => SmoothLerp {
+				a: a.clone(),
+				b: b.clone(),
+				t: t.clone(),
+			},
+			Select {
+				control,
+				a,
+				b,
+				threshold,
+				falloff,
+			} => Select {
+				control: control.clone(),
+				a: a.clone(),
+				b: b.clone(),
+				threshold: *threshold,
+				falloff: *falloff,
+			},
+
+			CoordTranslate(f, v) => CoordTranslate(f.clone(), v.clone()),
+			CoordScale(f, v) => CoordScale(f.clone(), v.clone()),
+			CoordRotate(f, v) => CoordRotate(f.clone(), v.clone()),
+			&CoordZ {
+				ref func,
+				offset,
+				scale,
+			} => CoordZ {
+				func: func.clone(),
+				offset,
+				scale,
+			},
+			Warp {
+				func,
+				warpX,
+				warpY,
+				strength,
+			} => Warp {
+				func: func.clone(),
+				warpX: warpX.clone(),
+				warpY: warpY.clone(),
+				strength: *strength,
+			},
+			&Tiled { ref func, period } => Tiled {
+				func: func.clone(),
+				period,
+			},
+			&GradientMagnitude { ref func, epsilon } => GradientMagnitude {
+				func: func.clone(),
+				epsilon,
+			},
+
+			// copies share the subtree and its values
+			Cached { func, id } => Cached {
+				func: func.clone(),
+				id: *id,
+			},
+			Ranged { func, min, max } => Ranged {
+				func: func.clone(),
+				min: *min,
+				max: *max,
+			},
+		}
+	}
+}
+
+struct NoiseCtors;
+
+impl UserData for NoiseCtors {
+	fn add_methods<'lua, M: LuaUserDataMethods<'lua, Self>>(methods: &mut M) {
+		methods.add_function("const", |lua, val: f32| Ok(Noise::Const(val)));
+		methods.add_function("simplex", |lua, seed: i64| Ok(Noise::Simplex(seed)));
+		methods.add_function("simplexFast", |lua, seed: i64| Ok(Noise::SimplexFast(seed)));
+		methods.add_function("simplex3", |lua, seed: i64| Ok(Noise::Simplex3(seed)));
+		methods.add_function("worley", |lua, (seed, opts): (i64, Option<LuaTable>)| {
+			let mut distanceFn = Distance::Euclidean;
+			let mut returnType = CellReturn::F1;
+			for pair in opts.into_iter().flat_map(|opts| opts.pairs::<String, String>()) {
+				let (key, value) = pair?;
+				match (key.as_str(), value.to_ascii_lowercase().as_str()) {
+					("distance", "euclidean") => distanceFn = Distance::Euclidean,
+					("distance", "manhattan") => distanceFn = Distance::Manhattan,
+					("distance", "chebyshev") => distanceFn = Distance::Chebyshev,
+					("returnType", "f1") => returnType = CellReturn::F1,
+					("returnType", "f2") => returnType = CellReturn::F2,
+					("returnType", "f2-f1") => returnType = CellReturn::F2MinusF1,
+					("distance" | "returnType", _) => {
+						return Err(LuaError::external(format!("unknown worley {key} `{value}`")));
+					},
+					_ => return Err(LuaError::external(format!("unknown worley option `{key}`"))),
+				}
+			}
+			Ok(Noise::Worley {
+				seed,
+				distanceFn,
+				returnType,
+			})
+		});
+		methods.add_function("cellId", |lua, (seed, jitter): (i64, Option<f64>)| {
+			let jitter = jitter.unwrap_or(1.0);
+			if !(0.0 ..= 1.0).contains(&jitter) {
+				return Err(LuaError::external("cell jitter must be within [0, 1]"));
+			}
+			Ok(Noise::CellId { seed, jitter })
+		});
+		methods.add_function("white", |lua, seed: i64| Ok(Noise::White(seed)));
+		methods.add_function("value", |lua, seed: i64| Ok(Noise::Value(seed)));
+		methods.add_function("perlin", |lua, seed: i64| {
+			Ok(Noise::Perlin {
+				seed,
+				perm: Arc::new(perlin_permutation(seed)),
+			})
+		});
+		methods.add_function("gradientX", |lua, ()| Ok(Noise::GradientX));
+		methods.add_function("gradientY", |lua, ()| Ok(Noise::GradientY));
+		methods.add_function("time", |lua, ()| Ok(Noise::Time));
+		methods.add_function("radial", |lua, (cx, cy): (Option<f64>, Option<f64>)| {
+			let center = dvec2(cx.unwrap_or(0.0), cy.unwrap_or(0.0));
+			if !center.is_finite() {
+				return Err(LuaError::external("radial center must be finite"));
+			}
+			Ok(Noise::Radial(center))
+		});
+		methods.add_function("checker", |lua, size: Option<f64>| {
+			let size = size.unwrap_or(1.0);
+			if !(size > 0.0 && size.is_finite()) {
+				return Err(LuaError::external("checker size must be positive"));
+			}
+			check_scale("checker size", size)?;
+			Ok(Noise::Checker(size))
+		});
+		methods.add_function("image", |lua, (path, outside): (String, Option<String>)| {
+			let wrap = match outside.as_deref() {
+				None | Some("clamp") => false,
+				Some("wrap") => true,
+				Some(other) => {
+					return Err(LuaError::external(format!(
+						"image outside mode must be \"clamp\" or \"wrap\", not {other:?}"
+					)));
+				},
+			};
+			let image =
+				HeightImage::load(&path).map_err(|err| LuaError::external(format!("{err:#}")))?;
+			record_file(&path);
+			Ok(Noise::Image {
+				image: Arc::new(image),
+				wrap,
+			})
+		});
+		#[cfg(feature = "fnl")]
+		methods.add_function("fromFNL", |lua, path: String| {
+			let (noise, unsupported) = crate::fnl::import_file(path.as_ref())
+				.map_err(|err| LuaError::external(format!("{err:#}")))?;
+			record_file(&path);
+			for setting in unsupported {
+				log::warn!("{path}: not representable: {setting}");
+			}
+			Ok(noise)
+		});
+		methods.add_function("hash", |lua, (seed, x, y): (i64, f64, f64)| {
+			Ok(noise_rng::unit(noise_rng::hash_pos(seed as u64, dvec2(x, y))))
+		});
+		methods.add_function(
+			"simplexTileable",
+			|lua, (seed, periodX, periodY): (i64, f64, Option<f64>)| {
+				let period = dvec2(periodX, periodY.unwrap_or(periodX));
+				if !(period.x > 0.0 && period.y > 0.0 && period.is_finite()) {
+					return Err(LuaError::external("tile period must be positive"));
+				}
+				Ok(Noise::SimplexTileable { seed, period })
+			},
+		);
+		methods.add_function(
+			"simplexLooped",
+			|lua, (seed, period, time): (i64, f64, Option<f64>)| {
+				if !(period > 0.0 && period.is_finite()) {
+					return Err(LuaError::external("loop period must be positive"));
+				}
+				if time.is_some_and(|time| !time.is_finite()) {
+					return Err(LuaError::external("loop time must be finite"));
+				}
+				Ok(Noise::SimplexLooped { seed, period, time })
+			},
+		);
+		methods.add_function(
+			"sinefield",
+			|lua, (freq, amp): (Option<f64>, Option<f64>)| {
+				let freq = freq.unwrap_or(1.0);
+				let amp = amp.unwrap_or(1.0);
+				check_scale("sinefield frequency", freq)?;
+				if !amp.is_finite() {
+					return Err(LuaError::external("sinefield amplitude must be finite"));
+				}
+				Ok(Noise::Func(Box::new(move |mut pos: DVec2| {
+					pos = pos * 2.0 * std::f64::consts::PI * freq;
+					((pos.x.sin() + pos.y.cos()) * amp) as f32
+				})))
+			},
+		)
+	}
+}
+
+/// Rejects coordinate multipliers that are non-finite or so large or small that evaluation
+/// degrades into overflow or denormal arithmetic. Zero is allowed, it just flattens the axis.
+fn check_scale(what: &str, v: f64) -> mlua::Result<()> {
+	let magnitude = v.abs();
+	if magnitude == 0.0 || (minScale ..= maxScale).contains(&magnitude) {
+		return Ok(());
+	}
+	Err(LuaError::external(format!(
+		"{what} {v} is outside of the supported magnitude range [{minScale:e}, {maxScale:e}]"
+	)))
+}
+
+fn with_range(noise: Noise, min: f32, max: f32) -> mlua::Result<Noise> {
+	if !(min.is_finite() && max.is_finite() && min < max) {
+		return Err(LuaError::external(format!("invalid output range [{min}, {max}]")));
+	}
+	Ok(Noise::Ranged {
+		func: noise.into(),
+		min,
+		max,
+	})
+}
+
+/// Validated `Noise::Octaves` over `func`, `ampScale` defaulting to 0.5.
+fn octaves_node(
+	func: &Noise,
+	octaves: usize,
+	ampScale: Option<f32>,
+	freqScale: DVec2,
+	mode: OctaveMode,
+	variation: OctaveVariation,
+	normalization: OctaveNormalization,
+) -> mlua::Result<Noise> {
+	let ampScale = ampScale.unwrap_or(0.5);
+	if octaves > maxOctaves {
+		return Err(LuaError::external(format!(
+			"{octaves} octaves requested, at most {maxOctaves} are supported"
+		)));
+	}
+	if !ampScale.is_finite() {
+		return Err(LuaError::external("octave amplitude scale must be finite"));
+	}
+	let jitter = variation.lacunarityJitter;
+	if !(0.0 .. 1.0).contains(&jitter) {
+		return Err(LuaError::external("lacunarity jitter must be within [0, 1)"));
+	}
+	// the last octave's frequency is the largest (or smallest) multiplier reached, at most every
+	// jitter going the same way
+	let exponent = octaves.saturating_sub(1) as i32;
+	for scale in [freqScale.x, freqScale.y] {
+		check_scale("octave frequency scale", scale)?;
+		for jittered in [scale * (1.0 - jitter), scale * (1.0 + jitter)] {
+			check_scale("final octave frequency", jittered.powi(exponent))?;
+		}
+	}
+	Ok(Noise::Octaves {
+		func: func.clone().into(),
+		octaves,
+		ampScale,
+		freqScale,
+		mode,
+		variation,
+		divisor: normalization.divisor(octaves, ampScale, mode),
+	})
+}
+
+/// Settings of an `octaves` options table, as returned by `octave_options`.
+struct OctaveOptions {
+	ampScale: Option<f32>,
+	freqScale: DVec2,
+	variation: OctaveVariation,
+	normalization: OctaveNormalization,
+}
+
+/// Settings of an `octaves` options table: `ampScale`, `freqScale` and `freqScaleY` like the
+/// positional arguments, the `OctaveVariation` as `seedOffset`, `rotation` in degrees,
+/// `lacunarityJitter` and `jitterSeed`, and the `OctaveNormalization` as `normalize`.
+fn octave_options(opts: LuaTable) -> mlua::Result<OctaveOptions> {
+	let mut ampScale = None;
+	let (mut freqScaleX, mut freqScaleY) = (2.0, None);
+	let mut variation = OctaveVariation::default();
+	let mut normalization = OctaveNormalization::None;
+	let integer = |key: &str, value: f64| {
+		if value.fract() != 0.0 || !value.is_finite() {
+			return Err(LuaError::external(format!("octave {key} must be an integer")));
+		}
+		Ok(value as i64)
+	};
+	for pair in opts.pairs::<String, Value>() {
+		let (key, value) = pair?;
+		if key == "normalize" {
+			let Value::String(name) = value else {
+				return Err(LuaError::external("octave normalize must be a string"));
+			};
+			normalization = OctaveNormalization::from_name(name.to_str()?)?;
+			continue;
+		}
+		let Some(value) = value.as_f64() else {
+			return Err(LuaError::external(format!("octave {key} must be a number")));
+		};
+		match key.as_str() {
+			"ampScale" => ampScale = Some(value as f32),
+			"freqScale" => freqScaleX = value,
+			"freqScaleY" => freqScaleY = Some(value),
+			"seedOffset" => variation.seedOffset = integer(&key, value)?,
+			"rotation" => {
+				if !value.is_finite() {
+					return Err(LuaError::external("octave rotation must be finite"));
+				}
+				variation.rotation = (value != 0.0).then(|| DVec2::from_angle(value.to_radians()));
+			},
+			"lacunarityJitter" => variation.lacunarityJitter = value,
+			"jitterSeed" => variation.jitterSeed = integer(&key, value)?,
+			_ => return Err(LuaError::external(format!("unknown octave option `{key}`"))),
+		}
+	}
+	Ok(OctaveOptions {
+		ampScale,
+		freqScale: dvec2(freqScaleX, freqScaleY.unwrap_or(freqScaleX)),
+		variation,
+		normalization,
+	})
+}
+
+/// The normalization named by the trailing argument of `ridged` and `billow`, none if omitted.
+fn octave_normalization(name: Option<String>) -> mlua::Result<OctaveNormalization> {
+	name.map_or(Ok(OctaveNormalization::None), |name| OctaveNormalization::from_name(&name))
+}
+
+/// Validated `Noise::Curve` over `func`.
+fn curve_node(func: &Noise, points: Vec<DVec2>) -> mlua::Result<Noise> {
+	if points.len() < 2 {
+		return Err(LuaError::external("curve needs at least 2 points"));
+	}
+	if !points.iter().all(|p| p.is_finite()) {
+		return Err(LuaError::external("curve points must be finite"));
+	}
+	if let Some(i) = (1 .. points.len()).find(|&i| points[i].x < points[i - 1].x) {
+		return Err(LuaError::external(format!(
+			"curve points must be sorted by input, point {} is left of point {}",
+			i + 1,
+			i
+		)));
+	}
+	Ok(Noise::Curve {
+		func: func.clone().into(),
+		points,
+	})
+}
+
+fn rhs_to_noise(rhs: &Value) -> mlua::Result<Noise> {
+	Ok(if let Some(v) = rhs.as_number() {
+		Noise::Const(v as _)
+	} else if let Some(v) = rhs.as_integer() {
+		Noise::Const(v as _)
+	} else if let Some(v) = rhs.as_userdata() {
+		v.borrow::<Noise>()?.clone()
+	} else {
+		return Err(LuaError::external("expected number or Noise"));
+	})
+}
+
+impl UserData for Noise {
+	fn add_methods<'lua, M: LuaUserDataMethods<'lua, Self>>(methods: &mut M) {
+		methods.add_method(
+			"octaves",
+			|lua, this, args: (usize, Value, Option<f64>, Option<f64>)| {
+				let (octaves, second, freqScaleX, freqScaleY) = args;
+				let mode = OctaveMode::Fbm;
+				if let Value::Table(opts) = second {
+					if freqScaleX.is_some() || freqScaleY.is_some() {
+						return Err(LuaError::external(
+							"octave options can't be combined with positional frequency scales",
+						));
+					}
+					let OctaveOptions {
+						ampScale,
+						freqScale,
+						variation,
+						normalization,
+					} = octave_options(opts)?;
+					return octaves_node(
+						this,
+						octaves,
+						ampScale,
+						freqScale,
+						mode,
+						variation,
+						normalization,
+					);
+				}
+				let ampScale = Option::<f32>::from_lua(second, lua)?;
+				let freqScaleX = freqScaleX.unwrap_or(2.0);
+				let freqScale = dvec2(freqScaleX, freqScaleY.unwrap_or(freqScaleX));
+				let (variation, normalization) = Default::default();
+				octaves_node(this, octaves, ampScale, freqScale, mode, variation, normalization)
+			},
+		);
+		methods.add_method(
+			"ridged",
+			|_, this, args: (usize, Option<f32>, Option<f64>, Option<f32>, Option<String>)| {
+				let (octaves, ampScale, freqScale, gain, normalize) = args;
+				if gain.is_some_and(|gain| !gain.is_finite()) {
+					return Err(LuaError::external("ridge weight gain must be finite"));
+				}
+				let freqScale = DVec2::splat(freqScale.unwrap_or(2.0));
+				let mode = OctaveMode::Ridged { gain };
+				let variation = OctaveVariation::default();
+				let normalization = octave_normalization(normalize)?;
+				octaves_node(this, octaves, ampScale, freqScale, mode, variation, normalization)
+			},
+		);
+		methods.add_method(
+			"billow",
+			|_, this, args: (usize, Option<f32>, Option<f64>, Option<String>)| {
+				let (octaves, ampScale, freqScale, normalize) = args;
+				let freqScale = DVec2::splat(freqScale.unwrap_or(2.0));
+				let (mode, variation) = (OctaveMode::Billow, OctaveVariation::default());
+				let normalization = octave_normalization(normalize)?;
+				octaves_node(this, octaves, ampScale, freqScale, mode, variation, normalization)
+			},
+		);
+
+		// Lua calls the metamethod of whichever operand is a `Noise`, passing both in source order
+		let ops: [(LuaMetaMethod, fn(NoisePtr, NoisePtr) -> Noise); 6] = [
+			(LuaMetaMethod::Add, Noise::Add),
+			(LuaMetaMethod::Sub, Noise::Sub),
+			(LuaMetaMethod::Mul, Noise::Mul),
+			(LuaMetaMethod::Div, Noise::Div),
+			(LuaMetaMethod::Pow, Noise::Pow),
+			(LuaMetaMethod::Mod, Noise::Rem),
+		];
+		for (method, node) in ops {
+			methods.add_meta_function(method, move |_, (lhs, rhs): (Value, Value)| {
+				Ok(node(rhs_to_noise(&lhs)?.into(), rhs_to_noise(&rhs)?.into()))
+			});
+		}
+		// Lua passes the operand twice
+		methods.add_meta_method(LuaMetaMethod::Unm, |_, this, _: Value| {
+			Ok(Noise::Sub(Noise::Const(0.0).into(), this.clone().into()))
+		});
+		methods.add_meta_method(LuaMetaMethod::ToString, |_, this, ()| Ok(format!("{this:#}")));
+
+		methods.add_method("remEuclid", |_, this, rhs: Value| {
+			let rhs = rhs_to_noise(&rhs)?;
+			Ok(Noise::RemEuclid(this.clone().into(), rhs.into()))
+		});
+		methods.add_method("floor", |_, this, rhs: ()| {
+			Ok(Noise::Floor(this.clone().into()))
+		});
+		methods.add_method("ceil", |_, this, rhs: ()| {
+			Ok(Noise::Ceil(this.clone().into()))
+		});
+		methods.add_method("abs", |_, this, rhs: ()| {
+			Ok(Noise::Abs(this.clone().into()))
+		});
+		methods.add_method("min", |_, this, rhs: Value| {
+			let rhs = rhs_to_noise(&rhs)?;
+			Ok(Noise::Min(this.clone().into(), rhs.into()))
+		});
+		methods.add_method("max", |_, this, rhs: Value| {
+			let rhs = rhs_to_noise(&rhs)?;
+			Ok(Noise::Max(this.clone().into(), rhs.into()))
+		});
+		methods.add_method("clamp", |_, this, (min, max): (f32, f32)| {
+			Ok(Noise::Clamp {
+				func: this.clone().into(),
+				min,
+				max,
+			})
+		});
+		methods.add_method("curve", |_, this, points: Vec<[f64; 2]>| {
+			curve_node(this, points.into_iter().map(DVec2::from).collect())
+		});
+		methods.add_method("terrace", |_, this, (steps, smoothness): (usize, Option<f64>)| {
+			let smoothness = smoothness.unwrap_or(0.0);
+			if steps < 2 {
+				return Err(LuaError::external("terrace needs at least 2 steps"));
+			}
+			if !(0.0 ..= 1.0).contains(&smoothness) {
+				return Err(LuaError::external("terrace smoothness must be within [0, 1]"));
+			}
+			curve_node(this, terrace_points(steps, smoothness))
+		});
+		methods.add_method("toSignedUnit", |_, this, rhs: ()| {
+			Ok(Noise::ToSignedUnit(this.clone().into()))
+		});
+		methods.add_method("toUnsignedUnit", |_, this, rhs: ()| {
+			Ok(Noise::ToUnsignedUnit(this.clone().into()))
+		});
+		methods.add_method("signedPow", |_, this, rhs: Value| {
+			let rhs = rhs_to_noise(&rhs)?;
+			Ok(Noise::SignedPow(this.clone().into(), rhs.into()))
+		});
+		methods.add_method("lerp", |_, this, (b, t, clamped): (Value, Value, Option<bool>)| {
+			Ok(Noise::Lerp {
+				a: this.clone().into(),
+				b: rhs_to_noise(&b)?.into(),
+				t: rhs_to_noise(&t)?.into(),
+				clamped: clamped.unwrap_or(true),
+			})
+		});
+		methods.add_method("smoothLerp", |_, this, (b, t): (Value, Value)| {
+			Ok(Noise::SmoothLerp {
+				a: this.clone().into(),
+				b: rhs_to_noise(&b)?.into(),
+				t: rhs_to_noise(&t)?.into(),
+			})
+		});
+		methods.add_method(
+			"select",
+			|_, this, (a, b, threshold, falloff): (Value, Value, Option<f64>, Option<f64>)| {
+				let (threshold, falloff) = (threshold.unwrap_or(0.0), falloff.unwrap_or(0.0));
+				if !threshold.is_finite() {
+					return Err(LuaError::external("select threshold must be finite"));
+				}
+				if !(falloff >= 0.0 && falloff.is_finite()) {
+					return Err(LuaError::external("select falloff must be finite and not negative"));
+				}
+				Ok(Noise::Select {
+					control: this.clone().into(),
+					a: rhs_to_noise(&a)?.into(),
+					b: rhs_to_noise(&b)?.into(),
+					threshold,
+					falloff,
+				})
+			},
+		);
+
+		methods.add_method(
+			"translate",
+			|_, this, (x, y, z): (f64, Option<f64>, Option<f64>)| {
+				let y = y.unwrap_or(x);
+				let translation = dvec2(x, y);
+				if !translation.is_finite() || !z.unwrap_or(0.0).is_finite() {
+					return Err(LuaError::external("translation must be finite"));
+				}
+				let noise = Noise::CoordTranslate(this.clone().into(), translation);
+				Ok(match z {
+					Some(z) => Noise::CoordZ {
+						func: noise.into(),
+						offset: z,
+						scale: 1.0,
+					},
+					None => noise,
+				})
+			},
+		);
+		methods.add_method("scale", |_, this, (x, y, z): (f64, Option<f64>, Option<f64>)| {
+			let y = y.unwrap_or(x);
+			let scale = dvec2(x, y);
+			check_scale("scale", x)?;
+			check_scale("scale", y)?;
+			let noise = Noise::CoordScale(this.clone().into(), scale);
+			Ok(match z {
+				Some(z) => {
+					check_scale("scale", z)?;
+					Noise::CoordZ {
+						func: noise.into(),
+						offset: 0.0,
+						scale: z,
+					}
+				},
+				None => noise,
+			})
+		});
+
+		methods.add_method("rotate", |_, this, degrees: f64| {
+			if !degrees.is_finite() {
+				return Err(LuaError::external("rotation angle must be finite"));
+			}
+			let rotation = DVec2::from_angle(degrees.to_radians());
+			Ok(Noise::CoordRotate(this.clone().into(), rotation))
+		});
+		methods.add_method(
+			"warp",
+			|_, this, (warpX, warpY, strength): (Value, Value, Option<f64>)| {
+				let strength = strength.unwrap_or(1.0);
+				if !strength.is_finite() {
+					return Err(LuaError::external("warp strength must be finite"));
+				}
+				let warpX = rhs_to_noise(&warpX)?;
+				let warpY = match warpY {
+					Value::Nil => Noise::CoordTranslate(warpX.clone().into(), warpYOffset),
+					warpY => rhs_to_noise(&warpY)?,
+				};
+				Ok(Noise::Warp {
+					func: this.clone().into(),
+					warpX: warpX.into(),
+					warpY: warpY.into(),
+					strength,
+				})
+			},
+		);
+		methods.add_method("tiled", |_, this, (periodX, periodY): (f64, Option<f64>)| {
+			let period = dvec2(periodX, periodY.unwrap_or(periodX));
+			if !(period.x > 0.0 && period.y > 0.0 && period.is_finite()) {
+				return Err(LuaError::external("tile period must be positive"));
+			}
+			Ok(Noise::Tiled {
+				func: this.clone().into(),
+				period,
+			})
+		});
+		methods.add_method("gradientMagnitude", |_, this, epsilon: Option<f64>| {
+			let epsilon = epsilon.unwrap_or(1e-3);
+			if !(epsilon > 0.0 && epsilon.is_finite()) {
+				return Err(LuaError::external("gradient epsilon must be positive"));
+			}
+			Ok(Noise::GradientMagnitude {
+				func: this.clone().into(),
+				epsilon,
+			})
+		});
+		methods.add_method("cached", |_, this, ()| Ok(this.clone().cached()));
+		methods.add_method("decorrelate", |_, this, seed: i64| {
+			let mut noise = this.clone();
+			noise.decorrelate(seed);
+			Ok(noise)
+		});
+
+		methods.add_method("withRange", |_, this, (min, max): (f32, f32)| {
+			with_range(this.clone(), min, max)
+		});
+	}
+}
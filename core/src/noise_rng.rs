@@ -0,0 +1,78 @@
+use glam::DVec2;
+
+const golden: u64 = 0x9E3779B97F4A7C15;
+
+/// SplitMix64's finalizer, a bijective avalanche mix.
+fn mix(mut z: u64) -> u64 {
+	z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+	z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+	z ^ (z >> 31)
+}
+
+/// Hashes a seed and two lattice coordinates, the source of randomness for all stochastic nodes.
+///
+/// This is pure wrapping integer arithmetic, so results are identical on every platform. Exported
+/// terrain depends on them: this and the conversions below must never change, new variants get new
+/// functions instead.
+pub fn hash(seed: u64, x: u64, y: u64) -> u64 {
+	let mut h = mix(seed.wrapping_add(golden));
+	h = mix((h ^ x).wrapping_add(golden));
+	mix((h ^ y).wrapping_add(golden))
+}
+
+/// Hashes a seed and a position by the bits of its coordinates, `-0.0` counting as `0.0`.
+pub fn hash_pos(seed: u64, pos: DVec2) -> u64 {
+	let bits = |v: f64| if v == 0.0 { 0 } else { v.to_bits() };
+	hash(seed, bits(pos.x), bits(pos.y))
+}
+
+/// Uniform point in `[0, 1)²` for lattice cell `(x, y)`, e.g. a Worley feature point.
+pub fn cell_point(seed: u64, x: i64, y: i64) -> DVec2 {
+	let h = hash(seed, x as u64, y as u64);
+	DVec2::new(unit(h), unit(mix(h)))
+}
+
+/// Uniform value in `[-1, 1)` for lattice cell `(x, y)`, independent of its `cell_point`.
+pub fn cell_value(seed: u64, x: i64, y: i64) -> f64 {
+	signed_unit(mix(mix(hash(seed, x as u64, y as u64))))
+}
+
+/// Maps a hash to `[0, 1)` using its top 53 bits.
+pub fn unit(h: u64) -> f64 {
+	(h >> 11) as f64 * (1.0 / (1u64 << 53) as f64)
+}
+
+/// Maps a hash to `[-1, 1)`.
+pub fn signed_unit(h: u64) -> f64 {
+	unit(h) * 2.0 - 1.0
+}
+
+/// Random number stream. Streams are addressed by counter rather than advanced by mutation of
+/// hidden state, so the n-th value doesn't depend on how the work was split across threads.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Rng {
+	seed: u64,
+	counter: u64,
+}
+
+impl Rng {
+	pub fn new(seed: u64) -> Self {
+		Self { seed, counter: 0 }
+	}
+
+	/// Independent child stream, e.g. one per cell or per worker band.
+	pub fn split(&self, stream: u64) -> Self {
+		Self::new(hash(self.seed, stream, 1))
+	}
+
+	pub fn next_u64(&mut self) -> u64 {
+		let h = hash(self.seed, self.counter, 0);
+		self.counter += 1;
+		h
+	}
+
+	/// Uniform in `[0, 1)`.
+	pub fn next_f64(&mut self) -> f64 {
+		unit(self.next_u64())
+	}
+}
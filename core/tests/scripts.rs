@@ -0,0 +1,176 @@
+#![allow(non_snake_case)]
+
+use glam::{dvec2, DVec2};
+use noisebench_core::grid::{sample_grid, sample_position, GridSpec};
+use noisebench_core::lua::{CoordChain, CoordStep, Noise, Precision};
+
+const island: &str = include_str!("../../assets/scripts/examples/island.lua");
+const seamlessTile: &str = include_str!("../../assets/scripts/examples/seamless_tile.lua");
+const terraces: &str = include_str!("../../assets/scripts/examples/terraces.lua");
+
+fn load(code: &str) -> Noise {
+	Noise::from_lua(code).unwrap_or_else(|err| panic!("{err:#}"))
+}
+
+/// Pre-order ID of the first node of the given kind.
+fn node_id(noise: &Noise, kind: &str) -> usize {
+	let mut found = None;
+	noise.visit(&mut |id, node| {
+		if found.is_none() && node.kind_name() == kind {
+			found = Some(id);
+		}
+	});
+	found.unwrap_or_else(|| panic!("no {kind} node"))
+}
+
+#[test]
+fn grid_matches_pointwise_eval() {
+	let noise = load(terraces);
+	let diameter = 33;
+	let samples = sample_grid(&noise, GridSpec::new(diameter));
+	assert_eq!(samples.len(), diameter * diameter);
+	for (i, &sample) in samples.iter().enumerate() {
+		let pos = sample_position(i % diameter, i / diameter, diameter);
+		assert_eq!(sample, noise.eval(pos), "sample {i} at {pos}");
+	}
+}
+
+#[test]
+fn island_is_surrounded_by_sea() {
+	let noise = load(island);
+	let diameter = 64;
+	let samples = sample_grid(&noise, GridSpec::new(diameter));
+	assert!(samples.iter().all(|v| (-1.0 ..= 1.0).contains(v)));
+	let last = diameter - 1;
+	for (x, y) in [(0, 0), (last, 0), (0, last), (last, last)] {
+		assert_eq!(samples[y * diameter + x], -1.0, "corner ({x}, {y})");
+	}
+	assert!(samples.iter().any(|&v| v > -1.0), "no land at all");
+}
+
+#[test]
+fn seamless_tile_repeats_its_edges() {
+	let noise = load(seamlessTile);
+	let diameter = 48;
+	let samples = sample_grid(&noise, GridSpec::new(diameter));
+	let at = |x: usize, y: usize| samples[y * diameter + x];
+	let last = diameter - 1;
+	for i in 0 .. diameter {
+		assert!((at(0, i) - at(last, i)).abs() < 1e-5, "column seam at row {i}");
+		assert!((at(i, 0) - at(i, last)).abs() < 1e-5, "row seam at column {i}");
+	}
+}
+
+#[test]
+fn grid_spans_its_square_at_any_resolution() {
+	let noise = load(island);
+	let spec = |diameter| GridSpec {
+		origin: dvec2(0.25, -0.5),
+		size: 2.0,
+		precision: Precision::F32,
+		..GridSpec::new(diameter)
+	};
+	let coarse = sample_grid(&noise, spec(5));
+	let fine = sample_grid(&noise, spec(17));
+	// every fourth fine sample lands on a coarse one
+	for y in 0 .. 5 {
+		for x in 0 .. 5 {
+			assert_eq!(coarse[y * 5 + x], fine[y * 4 * 17 + x * 4], "sample ({x}, {y})");
+		}
+	}
+	assert_eq!(coarse[0], noise.eval_with(dvec2(0.25, -0.5), Precision::F32));
+}
+
+#[test]
+fn sum_amplitudes_keeps_constant_stack_at_one() {
+	let positions = [dvec2(0.0, 0.0), dvec2(0.3, -7.5), dvec2(120.0, 4.25)];
+	let plain = load("return Noise.const(1):octaves(8)");
+	let normalized = load(r#"return Noise.const(1):octaves(8, { normalize = "sumAmplitudes" })"#);
+	for pos in positions {
+		assert_eq!(plain.eval(pos), 255.0 / 128.0, "plain stack at {pos}");
+		for precision in Precision::all {
+			assert_eq!(normalized.eval_with(pos, precision), 1.0, "{precision:?} at {pos}");
+		}
+	}
+	assert_eq!(normalized.bounds(), Some((1.0, 1.0)));
+}
+
+#[test]
+fn ridged_and_billow_normalize_to_unit_range() {
+	let cases = [
+		// every ridged octave of 0 is 1, of 1 is -1
+		("Noise.const(0):ridged(6, 0.5, 2, nil, 'sumAmplitudes')", 1.0),
+		("Noise.const(1):ridged(6, 0.5, 2, nil, 'sumAmplitudes')", -1.0),
+		// a gain of 0.5 halves every octave after the first, and so the divisor's share of them
+		("Noise.const(0):ridged(6, 0.5, 2, 0.5, 'sumAmplitudes')", 1.0),
+		("Noise.const(1):billow(6, 0.5, 2, 'sumAmplitudes')", 1.0),
+		("Noise.const(0):billow(6, 0.5, 2, 'sumAmplitudes')", -1.0),
+		// negative amplitude scales alternate signs, only their magnitude counts
+		("Noise.const(1):billow(4, -0.5, 2, 'sumAmplitudes')", 0.625 / 1.875),
+	];
+	for (code, expected) in cases {
+		let noise = load(&format!("return {code}"));
+		assert_eq!(noise.eval(dvec2(0.5, 0.5)), expected, "{code}");
+	}
+	let res = Noise::from_lua("return Noise.const(0):billow(6, 0.5, 2, 'observed')");
+	let err = res.err().expect("unknown normalization accepted");
+	assert!(format!("{err:#}").contains("sumAmplitudes"), "{err:#}");
+}
+
+#[test]
+fn coord_chain_composes_nested_transforms() {
+	let code = "return Noise.value(1):translate(0.5):rotate(90):scale(2, 3):translate(1, -1)";
+	let noise = load(code);
+	let leaf = node_id(&noise, "Value");
+	let chain = noise.coord_chain(leaf).expect("leaf is in the tree");
+	let quarterTurn = DVec2::from_angle(90f64.to_radians());
+	assert_eq!(chain.0, [
+		CoordStep::Translate(dvec2(1.0, -1.0)),
+		CoordStep::Scale(dvec2(2.0, 3.0)),
+		CoordStep::Rotate(quarterTurn),
+		CoordStep::Translate(dvec2(0.5, 0.5)),
+	]);
+	// (1.5, -0.75), (3, -2.25), (2.25, 3), (2.75, 3.5)
+	let seen = chain.apply(dvec2(0.5, 0.25)).expect("no opaque node above the leaf");
+	assert!(seen.abs_diff_eq(dvec2(2.75, 3.5), 1e-12), "leaf sees {seen}");
+
+	let leafNode = noise.node(leaf).expect("leaf is in the tree");
+	for pos in [dvec2(0.0, 0.0), dvec2(0.3, -0.7), dvec2(-12.5, 4.25)] {
+		let seen = chain.apply(pos).expect("no opaque node above the leaf");
+		let (root, leaf) = (noise.eval(pos), leafNode.eval(seen));
+		assert!((root - leaf).abs() < 1e-6, "root {root} and leaf {leaf} differ at {pos}");
+	}
+
+	// nodes only see the transforms above them
+	assert_eq!(noise.coord_chain(0), Some(CoordChain(vec![])));
+	let inner = noise.coord_chain(node_id(&noise, "CoordRotate")).expect("rotation is in the tree");
+	assert_eq!(inner.apply(dvec2(0.5, 0.25)), Some(dvec2(3.0, -2.25)));
+	assert_eq!(noise.coord_chain(leaf + 1), None);
+}
+
+#[test]
+fn coord_chain_is_opaque_below_warps_and_tiling() {
+	let code = "return Noise.value(1):translate(2, 0):warp(Noise.white(2), nil, 0.5):scale(2)";
+	let noise = load(code);
+	let pos = dvec2(0.5, 0.25);
+	let warp = noise.coord_chain(node_id(&noise, "Warp")).expect("warp is in the tree");
+	assert_eq!(warp.apply(pos), Some(dvec2(1.0, 0.5)));
+	assert_eq!(warp.opaque(), None);
+	// the warped source and the warp's own inputs alike
+	for kind in ["Value", "White"] {
+		let chain = noise.coord_chain(node_id(&noise, kind)).expect("node is in the tree");
+		assert_eq!(chain.apply(pos), None, "{kind} sees a position");
+		assert_eq!(chain.opaque(), Some("Warp"), "{kind}");
+	}
+
+	for (method, kind) in [
+		("tiled(1)", "Tiled"),
+		("octaves(3)", "Octaves"),
+		("gradientMagnitude()", "GradientMagnitude"),
+	] {
+		let noise = load(&format!("return Noise.value(1):translate(2, 0):{method}:rotate(45)"));
+		let chain = noise.coord_chain(node_id(&noise, "Value")).expect("source is in the tree");
+		assert_eq!(chain.apply(pos), None, "source sees a position under {kind}");
+		assert_eq!(chain.opaque(), Some(kind));
+	}
+}
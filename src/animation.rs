@@ -0,0 +1,147 @@
+use std::time::Duration;
+
+use bevy::prelude::*;
+use bevy_egui::egui;
+
+use crate::{
+	generate_layer,
+	lod,
+	log_panel,
+	mesh_allowed,
+	sequence,
+	session,
+	GenTarget,
+	NoiseGenCounter,
+	NoiseGenTask,
+	NoiseOutput,
+	UiState,
+};
+
+/// Playback of `Noise.time()`. While playing, `animate` advances the time and regenerates the
+/// active layer at it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Animation {
+	pub playing: bool,
+	/// Seconds of animation time per second of playback.
+	pub speed: f64,
+	/// Time the next generation evaluates `Noise::Time` at.
+	pub time: f64,
+	/// Shortest wait between two generated frames.
+	pub interval: Duration,
+	/// Rebuilds the terrain mesh for every frame too. Off by default, as meshing takes longer
+	/// than generating at most diameters.
+	pub meshUpdates: bool,
+}
+
+impl Default for Animation {
+	fn default() -> Self {
+		Self {
+			playing: false,
+			speed: 1.0,
+			time: 0.0,
+			interval: Duration::from_millis(50),
+			meshUpdates: false,
+		}
+	}
+}
+
+impl Animation {
+	/// Whether outputs shown now leave the terrain mesh alone, see `meshUpdates`.
+	pub fn skips_mesh(&self) -> bool {
+		self.playing && !self.meshUpdates
+	}
+}
+
+/// Returns whether the time was changed while paused, which needs a regeneration to show.
+pub fn animation_ui(ui: &mut egui::Ui, animation: &mut Animation) -> bool {
+	let before = animation.time;
+	let label = if animation.playing { "⏸" } else { "▶" };
+	ui.toggle_value(&mut animation.playing, label)
+		.on_hover_text("Play scripts using Noise.time(), regenerating the active layer as it advances");
+	ui.add(
+		egui::DragValue::new(&mut animation.speed)
+			.range(-100.0 ..= 100.0)
+			.speed(0.01)
+			.suffix("×"),
+	)
+	.on_hover_text("Animation seconds per second");
+	ui.add(egui::DragValue::new(&mut animation.time).speed(0.01).prefix("t "));
+	if ui.small_button("⏮").on_hover_text("Back to time 0").clicked() {
+		animation.time = 0.0;
+	}
+	ui.menu_button("…", |ui| {
+		let mut millis = animation.interval.as_millis() as u64;
+		ui.horizontal(|ui| {
+			ui.label("Frame interval");
+			ui.add(egui::DragValue::new(&mut millis).range(0 ..= 1000).suffix(" ms"));
+		});
+		animation.interval = Duration::from_millis(millis);
+		ui.checkbox(&mut animation.meshUpdates, "Update mesh")
+			.on_hover_text("Rebuild the terrain mesh for every frame, not just once paused");
+	});
+	!animation.playing && animation.time != before
+}
+
+/// Advances the animation time while playing, and regenerates the active layer at it once the
+/// frame interval passed. Frames are dropped while the previous one is still generating instead
+/// of superseding it, so slow scripts still show every frame they finish. Once paused, the mesh
+/// the frames skipped is built from the last one.
+pub fn animate(
+	mut cmd: Commands,
+	existingRequests: Query<(Entity, &NoiseGenTask)>,
+	mut uiState: ResMut<UiState>,
+	lastNoiseOutput: Option<Res<NoiseOutput>>,
+	mut terrain: Option<ResMut<lod::TerrainChunks>>,
+	mut meshes: ResMut<Assets<Mesh>>,
+	mut session: ResMut<session::SessionRecorder>,
+	mut counter: ResMut<NoiseGenCounter>,
+	recorder: Res<sequence::SweepRecorder>,
+	log: Res<log_panel::SessionLog>,
+	time: Res<Time>,
+	mut lastFrame: Local<Option<Duration>>,
+	mut wasPlaying: Local<bool>,
+) {
+	if !uiState.animation.playing {
+		*lastFrame = None;
+		if std::mem::take(&mut *wasPlaying) && !uiState.animation.meshUpdates {
+			if let (Some(output), Some(terrain)) = (lastNoiseOutput.as_deref(), &mut terrain) {
+				if mesh_allowed(output.size(), &uiState) {
+					terrain.update(output, uiState.terrain_scale(), &mut meshes);
+				}
+			}
+		}
+		return;
+	}
+	*wasPlaying = true;
+	let animation = &mut uiState.animation;
+	animation.time += time.delta_seconds_f64() * animation.speed;
+	let now = time.elapsed();
+	if lastFrame.is_some_and(|last| now - last < animation.interval) {
+		return;
+	}
+
+	// nothing to animate in outputs of trees not using the time
+	if lastNoiseOutput.as_ref().is_some_and(|output| output.time.is_none()) {
+		return;
+	}
+	let layer = uiState.activeLayer;
+	let pending = existingRequests
+		.iter()
+		.any(|(_, task)| task.target == GenTarget::Layer(layer));
+	// frames wait for a recorded sweep like other generations
+	if pending || recorder.running() || uiState.layers[layer].script.is_none() {
+		return;
+	}
+	*lastFrame = Some(now);
+	generate_layer(
+		&mut cmd,
+		&existingRequests,
+		&uiState,
+		layer,
+		lastNoiseOutput.as_deref(),
+		false,
+		&mut session,
+		&mut counter,
+		&log,
+	);
+}
@@ -0,0 +1,243 @@
+use std::path::{Path, PathBuf};
+use std::sync::atomic::AtomicBool;
+
+use anyhow::{bail, ensure, Context};
+use bevy::app::AppExit;
+use bevy::prelude::default;
+use bevy::tasks::{AsyncComputeTaskPool, TaskPool};
+use bevy::utils::HashMap;
+
+use crate::{
+	compile_script,
+	diff,
+	export,
+	generate_samples_cancellable,
+	lua,
+	scan_scripts,
+	AResult,
+	SampleGrid,
+};
+
+const usage: &str = "usage: noisebench bake <script> --out <file> [options]\n       \
+	noisebench bake --all <dir> --out-dir <dir> [options]\n\
+	options: [--size N] [--seed N] [--format png16|exr|raw32] [--precision f64|f32]\n         \
+	[--diff-against <raw32 file>]";
+
+/// File format of a baked heightmap.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+	/// 16-bit grayscale PNG, the declared range mapped to black and white and clamped.
+	Png16,
+	/// 32-bit float EXR, normalized like `Png16`.
+	Exr,
+	/// The samples themselves as little-endian `f32`s, row by row, unnormalized.
+	Raw32,
+}
+
+impl Format {
+	fn parse(name: &str) -> AResult<Self> {
+		Ok(match name {
+			"png16" => Self::Png16,
+			"exr" => Self::Exr,
+			"raw32" => Self::Raw32,
+			other => bail!("unknown --format {other:?}"),
+		})
+	}
+
+	fn extension(self) -> &'static str {
+		match self {
+			Self::Png16 => "png",
+			Self::Exr => "exr",
+			// as the diff export's baseline files
+			Self::Raw32 => "f32",
+		}
+	}
+
+	/// Format the extension of `path` asks for, if it names one.
+	fn of_path(path: &Path) -> Option<Self> {
+		match path.extension()?.to_str()?.to_ascii_lowercase().as_str() {
+			"png" => Some(Self::Png16),
+			"exr" => Some(Self::Exr),
+			"f32" | "raw" => Some(Self::Raw32),
+			_ => None,
+		}
+	}
+}
+
+enum Target {
+	Script { script: PathBuf, out: PathBuf },
+	/// Every script below `dir`, each written to the same relative path below `outDir`.
+	All { dir: PathBuf, outDir: PathBuf },
+}
+
+pub struct BakeOptions {
+	pub size: usize,
+	/// Seed offset, as the toolbar's seed field.
+	pub seed: i64,
+	/// `None` picks the format from the output's extension, or PNG.
+	pub format: Option<Format>,
+	pub precision: lua::Precision,
+	/// Raw `f32` samples, as `raw32` or the diff export's baseline, to summarize the changes from.
+	pub diffAgainst: Option<PathBuf>,
+}
+
+impl Default for BakeOptions {
+	fn default() -> Self {
+		Self {
+			size: 512,
+			seed: 0,
+			format: None,
+			precision: default(),
+			diffAgainst: None,
+		}
+	}
+}
+
+fn parse(mut args: impl Iterator<Item = String>) -> AResult<(Target, BakeOptions)> {
+	let mut options = BakeOptions::default();
+	let (mut out, mut all, mut outDir) = (None, None, None);
+	let mut positional = vec![];
+	while let Some(arg) = args.next() {
+		let mut value = || args.next().with_context(|| format!("missing value of {arg}"));
+		match arg.as_str() {
+			"--size" => options.size = value()?.parse().context("bad --size")?,
+			"--seed" => options.seed = value()?.parse().context("bad --seed")?,
+			"--format" => options.format = Some(Format::parse(&value()?)?),
+			"--precision" => {
+				options.precision = match value()?.as_str() {
+					"f64" => lua::Precision::F64,
+					"f32" => lua::Precision::F32,
+					other => bail!("unknown --precision {other:?}"),
+				}
+			},
+			"--out" => out = Some(PathBuf::from(value()?)),
+			"--all" => all = Some(PathBuf::from(value()?)),
+			"--out-dir" => outDir = Some(PathBuf::from(value()?)),
+			"--diff-against" => options.diffAgainst = Some(PathBuf::from(value()?)),
+			flag if flag.starts_with("--") => bail!("unknown flag {flag}"),
+			_ => positional.push(arg),
+		}
+	}
+	ensure!(options.size >= 2, "size must be at least 2");
+	let mut positional = positional.into_iter();
+	let target = match (positional.next(), all) {
+		(Some(script), None) => Target::Script {
+			script: script.into(),
+			out: out.context("missing --out")?,
+		},
+		(None, Some(_)) if options.diffAgainst.is_some() => {
+			bail!("--diff-against needs a single script, not --all")
+		},
+		(None, Some(dir)) => Target::All {
+			dir,
+			outDir: outDir.context("missing --out-dir")?,
+		},
+		(Some(_), Some(_)) => bail!("give either a script or --all, not both"),
+		(None, None) => bail!("missing script"),
+	};
+	if let Some(extra) = positional.next() {
+		bail!("unexpected argument {extra}");
+	}
+	Ok((target, options))
+}
+
+/// Generates `script` as the app would at the options' size and seed offset, and writes it to
+/// `out`. Errors of Lua functions the script returned fail the bake like errors running it.
+/// Summarizes how the output differs from `options.diffAgainst`, if given.
+pub fn bake(
+	script: &Path,
+	out: &Path,
+	options: &BakeOptions,
+) -> AResult<Option<diff::DiffSummary>> {
+	// read first, so a bad baseline fails before generating
+	let baseline = options.diffAgainst.as_deref().map(diff::read_raw).transpose()?;
+	let code = std::fs::read_to_string(script)
+		.with_context(|| format!("failed to read {}", script.display()))?;
+	let inputs = lua::ScriptInputs {
+		seed: options.seed,
+		..default()
+	};
+	let ast = compile_script(&code, &inputs, false)?.noise;
+	// left over from generations sharing the cached tree
+	ast.take_lua_error();
+	let (ctx, cancel) = (default(), AtomicBool::new(false));
+	let grid = SampleGrid::new(options.size);
+	let output = generate_samples_cancellable(&ast, &grid, options.precision, 0.0, &ctx, &cancel)
+		.expect("generation can't be cancelled without a flag to set");
+	if let Some(err) = ast.take_lua_error() {
+		bail!("Lua function failed: {err}");
+	}
+
+	if let Some(dir) = out.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+		std::fs::create_dir_all(dir).with_context(|| format!("creating {}", dir.display()))?;
+	}
+	let format = options.format.or(Format::of_path(out)).unwrap_or(Format::Png16);
+	let clamp = export::Normalization::Clamp;
+	let normalized = || export::normalize(&output.samples, output.range, clamp);
+	match format {
+		Format::Png16 => export::write_png(out, output.size(), &normalized())?,
+		Format::Exr => export::write_exr(out, output.size(), &normalized())?,
+		Format::Raw32 => diff::write_raw(out, &output.samples)?,
+	}
+	let Some(base) = baseline else {
+		return Ok(None);
+	};
+	Ok(Some(diff::HeightDiff::new(&base, &output.samples, output.range)?.summary()))
+}
+
+/// Bakes every script below `dir`, carrying on past failures. Returns how many failed.
+fn bake_all(dir: &Path, outDir: &Path, options: &BakeOptions) -> AResult<usize> {
+	ensure!(dir.is_dir(), "{} isn't a directory", dir.display());
+	let root = dir.canonicalize().with_context(|| format!("resolving {}", dir.display()))?;
+	let mut scripts = HashMap::new();
+	scan_scripts(dir, &mut scripts);
+	let mut paths: Vec<_> = scripts.keys().map(|path| path.path.clone()).collect();
+	paths.sort();
+	let extension = options.format.unwrap_or(Format::Png16).extension();
+	let mut failed = 0;
+	for script in paths {
+		// scanned paths are canonical, and below the canonical root
+		let relative = script.strip_prefix(&root).unwrap_or(&script);
+		let out = outDir.join(relative).with_extension(extension);
+		match bake(&script, &out, options) {
+			Ok(_) => println!("{} -> {}", relative.display(), out.display()),
+			Err(err) => {
+				eprintln!("{}: {err:#}", relative.display());
+				failed += 1;
+			},
+		}
+	}
+	Ok(failed)
+}
+
+/// Entry point for `noisebench bake`, run without opening a window.
+pub fn main() -> AppExit {
+	let (target, options) = match parse(std::env::args().skip(2)) {
+		Ok(parsed) => parsed,
+		Err(err) => {
+			eprintln!("{err:#}\n{usage}");
+			return AppExit::error();
+		},
+	};
+	AsyncComputeTaskPool::get_or_init(TaskPool::new);
+	let res = match &target {
+		Target::Script { script, out } => bake(script, out, &options).map(|summary| {
+			if let (Some(summary), Some(base)) = (summary, &options.diffAgainst) {
+				println!("against {}: {summary}", base.display());
+			}
+			0
+		}),
+		Target::All { dir, outDir } => bake_all(dir, outDir, &options),
+	};
+	match res {
+		Ok(0) => AppExit::Success,
+		Ok(failed) => {
+			eprintln!("{failed} scripts failed");
+			AppExit::error()
+		},
+		Err(err) => {
+			eprintln!("{err:#}");
+			AppExit::error()
+		},
+	}
+}
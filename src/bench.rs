@@ -0,0 +1,402 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Context};
+use bevy::app::AppExit;
+use bevy::tasks::{AsyncComputeTaskPool, TaskPool};
+use serde::{Deserialize, Serialize};
+
+use crate::{diff, generate_samples, lua, sample_position, AResult};
+
+const usage: &str = "usage: noisebench bench <script> [size] [iterations] \
+	[--threads pool|single|both] [--precision f64|f32] [--uncached] [--unoptimized] [--json] \
+	[--compare <bench.json>] [--diff-against <raw32 file>]";
+
+/// How a benchmark spreads the grid over threads.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Threads {
+	/// Bands of rows on the async compute pool, as the app generates.
+	Pool,
+	/// Every sample in order on the calling thread.
+	Single,
+}
+
+struct Options {
+	script: PathBuf,
+	size: usize,
+	iterations: usize,
+	threads: Vec<Threads>,
+	precision: lua::Precision,
+	/// Evaluates `cached` subtrees at every use, to compare against the cached tree.
+	uncached: bool,
+	/// Evaluates the tree as the script built it, to compare against `Noise::optimize`'s.
+	unoptimized: bool,
+	json: bool,
+	/// `--json` output of an earlier run to compare the timings with.
+	compare: Option<PathBuf>,
+	/// Raw `f32` samples, as `bake --format raw32` writes, to summarize the grid's changes from.
+	diffAgainst: Option<PathBuf>,
+}
+
+impl Options {
+	fn parse(mut args: impl Iterator<Item = String>) -> AResult<Self> {
+		let mut positional = vec![];
+		let mut threads = vec![Threads::Pool];
+		let mut precision = lua::Precision::default();
+		let (mut uncached, mut unoptimized, mut json) = (false, false, false);
+		let (mut compare, mut diffAgainst) = (None, None);
+		while let Some(arg) = args.next() {
+			match arg.as_str() {
+				"--threads" => {
+					threads = match args.next().as_deref() {
+						Some("pool") => vec![Threads::Pool],
+						Some("single") => vec![Threads::Single],
+						Some("both") => vec![Threads::Single, Threads::Pool],
+						other => bail!("unknown --threads {other:?}"),
+					}
+				},
+				"--precision" => {
+					precision = match args.next().as_deref() {
+						Some("f64") => lua::Precision::F64,
+						Some("f32") => lua::Precision::F32,
+						other => bail!("unknown --precision {other:?}"),
+					}
+				},
+				"--uncached" => uncached = true,
+				"--unoptimized" => unoptimized = true,
+				"--json" => json = true,
+				"--compare" => {
+					compare = Some(args.next().context("--compare needs a file")?.into());
+				},
+				"--diff-against" => {
+					let path = args.next().context("--diff-against needs a file")?;
+					diffAgainst = Some(path.into());
+				},
+				flag if flag.starts_with("--") => bail!("unknown flag {flag}"),
+				_ => positional.push(arg),
+			}
+		}
+		let mut positional = positional.into_iter();
+		let script = positional.next().context("missing script")?.into();
+		let size = positional.next().map_or(Ok(512), |v| v.parse()).context("bad size")?;
+		let iterations = positional
+			.next()
+			.map_or(Ok(10), |v| v.parse())
+			.context("bad iterations")?;
+		if let Some(extra) = positional.next() {
+			bail!("unexpected argument {extra}");
+		}
+		if size < 2 || iterations == 0 {
+			bail!("size must be at least 2 and iterations at least 1");
+		}
+		Ok(Self {
+			script,
+			size,
+			iterations,
+			threads,
+			precision,
+			uncached,
+			unoptimized,
+			json,
+			compare,
+			diffAgainst,
+		})
+	}
+}
+
+/// Timings of one threading mode. Per-sample times are an iteration's time divided by the sample
+/// count, in nanoseconds; totals are whole iterations, in milliseconds.
+#[derive(Debug, Serialize)]
+struct Report {
+	threads: Threads,
+	samplesPerSecond: f64,
+	meanSampleNs: f64,
+	p50SampleNs: f64,
+	p99SampleNs: f64,
+	meanTotalMs: f64,
+	p50TotalMs: f64,
+	p99TotalMs: f64,
+}
+
+impl Report {
+	fn new(threads: Threads, samples: usize, mut times: Vec<Duration>) -> Self {
+		times.sort();
+		let percentile = |p: f64| times[((times.len() - 1) as f64 * p).round() as usize];
+		let mean = times.iter().sum::<Duration>() / times.len() as u32;
+		let ms = |d: Duration| d.as_secs_f64() * 1e3;
+		let perSample = |d: Duration| d.as_secs_f64() * 1e9 / samples as f64;
+		Self {
+			threads,
+			samplesPerSecond: samples as f64 / mean.as_secs_f64(),
+			meanSampleNs: perSample(mean),
+			p50SampleNs: perSample(percentile(0.5)),
+			p99SampleNs: perSample(percentile(0.99)),
+			meanTotalMs: ms(mean),
+			p50TotalMs: ms(percentile(0.5)),
+			p99TotalMs: ms(percentile(0.99)),
+		}
+	}
+}
+
+/// Mean time per sample of one threading mode against the run given to `--compare`.
+#[derive(Debug, Serialize)]
+struct Change {
+	threads: Threads,
+	baselineMeanSampleNs: f64,
+	meanSampleNs: f64,
+	/// Relative to the baseline, negative when faster.
+	change: f64,
+}
+
+#[derive(Debug, Serialize)]
+struct Results {
+	script: String,
+	size: usize,
+	iterations: usize,
+	precision: String,
+	uncached: bool,
+	unoptimized: bool,
+	/// Time running the script took, which the reports leave out.
+	buildMs: f64,
+	/// Time getting the same tree from the compiled cache took, what a hit saves being the
+	/// difference. Unoptimized trees aren't cached.
+	#[serde(skip_serializing_if = "Option::is_none")]
+	cachedBuildMs: Option<f64>,
+	reports: Vec<Report>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	comparedWith: Option<String>,
+	/// Threading modes both runs timed.
+	#[serde(skip_serializing_if = "Vec::is_empty")]
+	changes: Vec<Change>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	diffedAgainst: Option<String>,
+	/// How the grid differs from the `--diff-against` samples.
+	#[serde(skip_serializing_if = "Option::is_none")]
+	diff: Option<diff::DiffSummary>,
+}
+
+/// The parts of an earlier run's `--json` output that `--compare` compares with.
+#[derive(Deserialize)]
+struct Baseline {
+	size: usize,
+	precision: String,
+	uncached: bool,
+	unoptimized: bool,
+	reports: Vec<BaselineReport>,
+}
+
+#[derive(Deserialize)]
+struct BaselineReport {
+	threads: Threads,
+	meanSampleNs: f64,
+}
+
+impl Baseline {
+	fn load(path: &Path) -> AResult<Self> {
+		let json = std::fs::read_to_string(path)
+			.with_context(|| format!("failed to read {}", path.display()))?;
+		serde_json::from_str(&json).with_context(|| format!("failed to parse {}", path.display()))
+	}
+
+	/// Settings of the baseline that differ from `options`, which makes comparing it unfair.
+	fn mismatches(&self, options: &Options) -> Vec<String> {
+		let mut mismatches = vec![];
+		if self.size != options.size {
+			mismatches.push(format!("size {}", self.size));
+		}
+		if self.precision != format!("{:?}", options.precision) {
+			mismatches.push(format!("{} precision", self.precision));
+		}
+		if self.uncached != options.uncached {
+			mismatches.push(format!("uncached {}", self.uncached));
+		}
+		if self.unoptimized != options.unoptimized {
+			mismatches.push(format!("unoptimized {}", self.unoptimized));
+		}
+		mismatches
+	}
+
+	fn changes(&self, reports: &[Report]) -> Vec<Change> {
+		reports
+			.iter()
+			.filter_map(|report| {
+				let baseline = self.reports.iter().find(|base| base.threads == report.threads)?;
+				Some(Change {
+					threads: report.threads,
+					baselineMeanSampleNs: baseline.meanSampleNs,
+					meanSampleNs: report.meanSampleNs,
+					change: report.meanSampleNs / baseline.meanSampleNs - 1.0,
+				})
+			})
+			.collect()
+	}
+}
+
+/// Evaluates the grid once with `threads`, returning how long it took.
+fn time_grid(ast: &lua::Noise, options: &Options, threads: Threads) -> Duration {
+	let size = options.size;
+	let start = Instant::now();
+	match threads {
+		Threads::Pool => {
+			std::hint::black_box(generate_samples(ast, size, options.precision));
+		},
+		Threads::Single => {
+			for y in 0 .. size {
+				for x in 0 .. size {
+					let pos = sample_position(x, y, size).extend(0.0);
+					let ctx = lua::EvalContext::default();
+					std::hint::black_box(ast.eval3_with(pos, options.precision, &ctx));
+				}
+			}
+		},
+	}
+	start.elapsed()
+}
+
+fn run(options: &Options) -> AResult<Results> {
+	// loaded first, so a bad file fails before the timing
+	let baseline = options.compare.as_deref().map(Baseline::load).transpose()?;
+	let diffBase = options.diffAgainst.as_deref().map(diff::read_raw).transpose()?;
+	if let Some(baseline) = &baseline {
+		let mismatches = baseline.mismatches(options);
+		if !mismatches.is_empty() {
+			eprintln!("warning: the baseline ran with {}", mismatches.join(", "));
+		}
+	}
+	let code = std::fs::read_to_string(&options.script)
+		.with_context(|| format!("failed to read {}", options.script.display()))?;
+	// built once up front, only evaluation is timed; then again from the compiled cache, to show
+	// what a cache hit saves
+	lua::invalidate_compiled();
+	let start = Instant::now();
+	let mut ast = if options.unoptimized {
+		lua::construct_noisegen_unoptimized(&code)?
+	} else {
+		lua::construct_noisegen(&code)?
+	};
+	let buildMs = start.elapsed().as_secs_f64() * 1e3;
+	let cachedBuildMs = if options.unoptimized {
+		// unoptimized trees aren't cached
+		None
+	} else {
+		let start = Instant::now();
+		lua::construct_noisegen(&code)?;
+		Some(start.elapsed().as_secs_f64() * 1e3)
+	};
+	if options.uncached {
+		ast = Arc::new(ast.uncached());
+	}
+	let samples = options.size * options.size;
+	let reports: Vec<Report> = options
+		.threads
+		.iter()
+		.map(|&threads| {
+			// one untimed pass so the pool's threads and the caches are warm
+			time_grid(&ast, options, threads);
+			let times = (0 .. options.iterations)
+				.map(|_| time_grid(&ast, options, threads))
+				.collect();
+			Report::new(threads, samples, times)
+		})
+		.collect();
+	let diff = match diffBase {
+		Some(base) => {
+			let output = generate_samples(&ast, options.size, options.precision);
+			Some(diff::HeightDiff::new(&base, &output.samples, output.range)?.summary())
+		},
+		None => None,
+	};
+	Ok(Results {
+		script: options.script.display().to_string(),
+		size: options.size,
+		iterations: options.iterations,
+		precision: format!("{:?}", options.precision),
+		uncached: options.uncached,
+		unoptimized: options.unoptimized,
+		buildMs,
+		cachedBuildMs,
+		changes: baseline.map_or(vec![], |baseline| baseline.changes(&reports)),
+		reports,
+		comparedWith: options.compare.as_ref().map(|path| path.display().to_string()),
+		diffedAgainst: options.diffAgainst.as_ref().map(|path| path.display().to_string()),
+		diff,
+	})
+}
+
+fn print_results(results: &Results) {
+	println!(
+		"{}: {size}x{size}, {} iterations, {} precision{}{}",
+		results.script,
+		results.iterations,
+		results.precision,
+		if results.uncached { ", uncached" } else { "" },
+		if results.unoptimized { ", unoptimized" } else { "" },
+		size = results.size,
+	);
+	match results.cachedBuildMs {
+		Some(cached) => println!(
+			"script built in {:.3}ms, {cached:.3}ms from the compiled cache",
+			results.buildMs
+		),
+		None => println!("script built in {:.3}ms", results.buildMs),
+	}
+	for report in &results.reports {
+		println!(
+			"{threads:>6}  per sample {:>8.2}ns mean {:>8.2}ns p50 {:>8.2}ns p99  \
+			 total {:>9.3}ms mean {:>9.3}ms p50 {:>9.3}ms p99  {:.3e} samples/s",
+			report.meanSampleNs,
+			report.p50SampleNs,
+			report.p99SampleNs,
+			report.meanTotalMs,
+			report.p50TotalMs,
+			report.p99TotalMs,
+			report.samplesPerSecond,
+			threads = format!("{:?}", report.threads),
+		);
+	}
+	if let Some(path) = &results.comparedWith {
+		println!("against {path}:");
+		if results.changes.is_empty() {
+			println!("  no threading mode in common");
+		}
+	}
+	for change in &results.changes {
+		println!(
+			"{threads:>6}  per sample {:>8.2}ns mean, was {:>8.2}ns  {:+.1}%",
+			change.meanSampleNs,
+			change.baselineMeanSampleNs,
+			change.change * 100.0,
+			threads = format!("{:?}", change.threads),
+		);
+	}
+	if let (Some(path), Some(diff)) = (&results.diffedAgainst, &results.diff) {
+		println!("against {path}: {diff}");
+	}
+}
+
+/// Entry point for `noisebench bench`, run without opening a window.
+pub fn main() -> AppExit {
+	let options = match Options::parse(std::env::args().skip(2)) {
+		Ok(options) => options,
+		Err(err) => {
+			eprintln!("{err:#}\n{usage}");
+			return AppExit::error();
+		},
+	};
+	AsyncComputeTaskPool::get_or_init(TaskPool::new);
+	let results = match run(&options) {
+		Ok(results) => results,
+		Err(err) => {
+			eprintln!("{err:#}");
+			return AppExit::error();
+		},
+	};
+	if options.json {
+		println!("{}", serde_json::to_string_pretty(&results).unwrap());
+	} else {
+		print_results(&results);
+	}
+	AppExit::Success
+}
@@ -0,0 +1,249 @@
+use bevy::prelude::*;
+use bevy::render::camera::ScalingMode;
+use bevy_egui::egui;
+
+use crate::{input, walk, SelectedTab, Tab, TerrainScale, UiState};
+
+/// Length of the transition to a bookmark, in seconds.
+const transitionTime: f32 = 0.3;
+/// Pitch of the top-down view, just short of straight down so the yaw `camera_controller_3d`
+/// takes over stays defined.
+const topDownPitch: f32 = -89.9;
+/// Margin around the terrain left by the framing presets, relative to its size.
+const framingMargin: f32 = 1.05;
+
+/// Where the 3D camera is and how it projects.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CameraPose {
+	pub translation: Vec3,
+	pub rotation: Quat,
+	/// Top-down orthographic projection instead of the usual perspective.
+	pub orthographic: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Bookmark {
+	/// Straight down at the whole terrain, orthographic, rows going down the view like in 2D.
+	TopDown,
+	/// 45° down at the whole terrain from the default viewing direction.
+	Overview,
+	/// One of the poses stored by the user.
+	Slot(usize),
+}
+
+impl Bookmark {
+	pub const all: [Self; 4] = [Self::TopDown, Self::Overview, Self::Slot(0), Self::Slot(1)];
+
+	pub fn label(self) -> &'static str {
+		match self {
+			Self::TopDown => "Top",
+			Self::Overview => "Overview",
+			Self::Slot(0) => "3",
+			Self::Slot(_) => "4",
+		}
+	}
+
+	pub fn key(self) -> KeyCode {
+		match self {
+			Self::TopDown => KeyCode::Digit1,
+			Self::Overview => KeyCode::Digit2,
+			Self::Slot(0) => KeyCode::Digit3,
+			Self::Slot(_) => KeyCode::Digit4,
+		}
+	}
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum BookmarkAction {
+	Jump(Bookmark),
+	Store(usize),
+}
+
+/// Transition of the camera towards a pose, eased over `transitionTime`.
+#[derive(Clone, Copy, Debug)]
+struct Tween {
+	from: Transform,
+	to: CameraPose,
+	elapsed: f32,
+}
+
+/// Camera bookmarks of the 3D view. 1 and 2 jump to the top-down and overview presets, 3 and 4 to
+/// the user's slots, and Shift with 3 or 4 stores the current pose in that slot. While moving to
+/// a bookmark, `camera_bookmarks` owns the camera and `camera_controller_3d` leaves it alone.
+#[derive(Resource, Default)]
+pub struct CameraBookmarks {
+	slots: [Option<CameraPose>; 2],
+	tween: Option<Tween>,
+	/// Set when a transition ends, until the controller picked up the pose it reached.
+	handedBack: bool,
+	/// Requested from the toolbar, carried out by `camera_bookmarks`.
+	action: Option<BookmarkAction>,
+	orthographic: bool,
+}
+
+impl CameraBookmarks {
+	/// Whether a transition currently moves the camera.
+	pub fn controls_camera(&self) -> bool {
+		self.tween.is_some()
+	}
+
+	/// Whether a transition ended since the last call, so the controller should take its angles
+	/// from the camera's rotation.
+	pub fn take_handback(&mut self) -> bool {
+		std::mem::take(&mut self.handedBack)
+	}
+
+	fn start(&mut self, from: Transform, to: CameraPose) {
+		self.tween = Some(Tween {
+			from,
+			to,
+			elapsed: 0.0,
+		});
+	}
+}
+
+/// Rotation of the controller's yaw and pitch, in degrees.
+fn rotation(yaw: f32, pitch: f32) -> Quat {
+	Quat::from_rotation_y(yaw.to_radians()) * Quat::from_rotation_x(pitch.to_radians())
+}
+
+/// Radius of the sphere around the origin holding the whole terrain, which spans `extent` across
+/// and `height` above and below the ground plane at most.
+fn terrain_radius(scale: TerrainScale) -> f32 {
+	let half = scale.extent / 2.0;
+	(2.0 * half * half + scale.height * scale.height).sqrt() * framingMargin
+}
+
+/// Pose of a preset framing the whole terrain at its current extent and height scale. `None` for
+/// empty slots.
+pub fn preset_pose(
+	bookmark: Bookmark,
+	bookmarks: &CameraBookmarks,
+	scale: TerrainScale,
+) -> Option<CameraPose> {
+	match bookmark {
+		Bookmark::TopDown => Some(CameraPose {
+			// above the highest point, with `top_down_projection` reaching past the lowest
+			translation: Vec3::Y * (scale.height.abs() + scale.extent),
+			rotation: rotation(0.0, topDownPitch),
+			orthographic: true,
+		}),
+		Bookmark::Overview => {
+			let rotation = rotation(225.0, -45.0);
+			let fov = PerspectiveProjection::default().fov;
+			let distance = terrain_radius(scale) / (fov / 2.0).sin();
+			Some(CameraPose {
+				translation: rotation * Vec3::Z * distance,
+				rotation,
+				orthographic: false,
+			})
+		},
+		Bookmark::Slot(slot) => bookmarks.slots[slot],
+	}
+}
+
+fn top_down_projection(scale: TerrainScale, cameraHeight: f32) -> Projection {
+	let size = scale.extent * framingMargin;
+	Projection::Orthographic(OrthographicProjection {
+		far: cameraHeight + scale.height.abs() + scale.extent,
+		scaling_mode: ScalingMode::AutoMin {
+			min_width: size,
+			min_height: size,
+		},
+		..default()
+	})
+}
+
+/// Toolbar buttons of the 3D view. Shift+click stores the current pose into a slot.
+pub fn bookmarks_ui(ui: &mut egui::Ui, bookmarks: &mut CameraBookmarks) {
+	ui.label("View");
+	for bookmark in Bookmark::all {
+		let hover = match bookmark {
+			Bookmark::TopDown => "Orthographic view straight down at the whole terrain (1)".into(),
+			Bookmark::Overview => "Look down at the whole terrain at 45° (2)".into(),
+			Bookmark::Slot(slot) => {
+				let key = slot + 3;
+				let stored = if bookmarks.slots[slot].is_some() { "Stored view" } else { "Empty" };
+				format!("{stored} ({key}), Shift+click or Shift+{key} stores the current view")
+			},
+		};
+		let selected = bookmark == Bookmark::TopDown && bookmarks.orthographic;
+		if ui.selectable_label(selected, bookmark.label()).on_hover_text(hover).clicked() {
+			bookmarks.action = Some(match bookmark {
+				Bookmark::Slot(slot) if ui.input(|input| input.modifiers.shift) => {
+					BookmarkAction::Store(slot)
+				},
+				bookmark => BookmarkAction::Jump(bookmark),
+			});
+		}
+	}
+}
+
+/// Carries out bookmark keys and buttons, and moves the camera during transitions.
+pub fn camera_bookmarks(
+	mut bookmarks: ResMut<CameraBookmarks>,
+	mut camera: Query<(&mut Transform, &mut Projection), With<Camera3d>>,
+	keyboard: Res<ButtonInput<KeyCode>>,
+	input: Res<input::ViewportInput>,
+	selectedTab: Res<SelectedTab>,
+	uiState: Res<UiState>,
+	mut walk: ResMut<walk::WalkMode>,
+	time: Res<Time>,
+) {
+	let bookmarks = &mut *bookmarks;
+	let Ok((mut transform, mut projection)) = camera.get_single_mut() else {
+		return;
+	};
+	if selectedTab.shows(Tab::D3) && input.keyboard(Tab::D3) {
+		let shift = keyboard.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+		for bookmark in Bookmark::all.into_iter().filter(|b| keyboard.just_pressed(b.key())) {
+			bookmarks.action = Some(match bookmark {
+				Bookmark::Slot(slot) if shift => BookmarkAction::Store(slot),
+				bookmark => BookmarkAction::Jump(bookmark),
+			});
+		}
+	}
+
+	match bookmarks.action.take() {
+		Some(BookmarkAction::Store(slot)) => {
+			bookmarks.slots[slot] = Some(CameraPose {
+				translation: transform.translation,
+				rotation: transform.rotation,
+				orthographic: bookmarks.orthographic,
+			});
+			info!("stored camera bookmark {}", slot + 3);
+		},
+		Some(BookmarkAction::Jump(bookmark)) => {
+			if let Some(pose) = preset_pose(bookmark, bookmarks, uiState.terrain_scale()) {
+				// the terrain would pull the camera down to eye height on the way
+				walk.enabled = false;
+				// the transition happens in perspective, the top-down view switches at its end
+				if bookmarks.orthographic {
+					bookmarks.orthographic = false;
+					*projection = Projection::Perspective(default());
+				}
+				bookmarks.start(*transform, pose);
+			}
+		},
+		None => {},
+	}
+
+	let Some(tween) = &mut bookmarks.tween else {
+		return;
+	};
+	tween.elapsed += time.delta_seconds();
+	let t = (tween.elapsed / transitionTime).min(1.0);
+	let eased = t * t * (3.0 - 2.0 * t);
+	transform.translation = tween.from.translation.lerp(tween.to.translation, eased);
+	transform.rotation = tween.from.rotation.slerp(tween.to.rotation, eased);
+	if t < 1.0 {
+		return;
+	}
+	let pose = tween.to;
+	bookmarks.tween = None;
+	bookmarks.handedBack = true;
+	if pose.orthographic {
+		bookmarks.orthographic = true;
+		*projection = top_down_projection(uiState.terrain_scale(), pose.translation.y);
+	}
+}
@@ -0,0 +1,127 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use bevy::math::{dvec2, DVec2};
+use bevy_egui::egui;
+
+use crate::{lua, workers, NoiseOutput};
+
+/// Largest stitched output, in samples per side, matching the diameter setting's limit.
+const maxStitched: usize = 4096;
+
+/// Generation as a grid of independently evaluated chunks, the way games generate terrain, so
+/// seams between chunks show up in the preview.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ChunkSettings {
+	pub enabled: bool,
+	/// Samples per chunk side.
+	pub chunkSize: usize,
+	/// Chunks per side of the grid.
+	pub extent: usize,
+	/// Samples the chunks' last row and column at the next chunk's origin, so neighbours share
+	/// their border samples, instead of one step before it.
+	pub sharedEdges: bool,
+}
+
+impl Default for ChunkSettings {
+	fn default() -> Self {
+		Self {
+			enabled: false,
+			chunkSize: 128,
+			extent: 2,
+			sharedEdges: false,
+		}
+	}
+}
+
+impl ChunkSettings {
+	/// Samples per side of the stitched output.
+	pub fn diameter(&self) -> usize {
+		self.chunkSize * self.extent
+	}
+
+	/// World-space position of sample `(x, y)` of chunk `chunk`: the chunk's origin plus the
+	/// local offset. The grid covers the unit square, like a plain generation.
+	pub fn position(&self, chunk: (usize, usize), x: usize, y: usize) -> DVec2 {
+		let steps = if self.sharedEdges { self.chunkSize - 1 } else { self.chunkSize };
+		let origin = dvec2(chunk.0 as f64, chunk.1 as f64);
+		let local = dvec2(x as f64, y as f64) / steps as f64;
+		(origin + local) / self.extent as f64
+	}
+}
+
+/// Returns whether a setting changed.
+pub fn chunk_ui(ui: &mut egui::Ui, settings: &mut ChunkSettings) -> bool {
+	let before = *settings;
+	ui.checkbox(&mut settings.enabled, "Chunked")
+		.on_hover_text("Generate a grid of chunks evaluated independently, to check for seams");
+	ui.add_enabled_ui(settings.enabled, |ui| {
+		ui.horizontal(|ui| {
+			ui.label("Chunk size");
+			ui.add(egui::DragValue::new(&mut settings.chunkSize).range(16 ..= 1024));
+		});
+		let maxExtent = (maxStitched / settings.chunkSize).max(1);
+		ui.horizontal(|ui| {
+			ui.label("Grid");
+			ui.add(egui::DragValue::new(&mut settings.extent).range(1 ..= maxExtent));
+			ui.label(format!("chunks per side, {0}×{0} samples", settings.diameter()));
+		});
+		ui.checkbox(&mut settings.sharedEdges, "Shared edges").on_hover_text(
+			"Sample each chunk's last row and column at the next chunk's origin, duplicating the \
+			 border samples",
+		);
+	});
+	*settings != before
+}
+
+/// Samples of each chunk of a chunked generation, row by row, with the chunks also in rows.
+pub struct ChunkedNoiseOutput {
+	pub settings: ChunkSettings,
+	pub chunks: Vec<Vec<f32>>,
+}
+
+impl ChunkedNoiseOutput {
+	/// Evaluates the chunks one at a time per worker of the async compute pool, giving up once
+	/// `cancel` is set, which is checked before every row. Counts the chunks done in `progress`.
+	pub fn generate_cancellable(
+		ast: &lua::Noise,
+		settings: ChunkSettings,
+		precision: lua::Precision,
+		z: f64,
+		ctx: &lua::EvalContext,
+		cancel: &AtomicBool,
+		progress: &workers::Progress,
+	) -> Option<Self> {
+		let size = settings.chunkSize;
+		let mut chunks = vec![vec![0.0; size * size]; settings.extent.pow(2)];
+		progress.start(chunks.len());
+		workers::for_each_limited(chunks.iter_mut().enumerate(), |(index, samples)| {
+			let chunk = (index % settings.extent, index / settings.extent);
+			for (i, sample) in samples.iter_mut().enumerate() {
+				let (x, y) = (i % size, i / size);
+				if x == 0 && cancel.load(Ordering::Relaxed) {
+					return;
+				}
+				let pos = settings.position(chunk, x, y).extend(z);
+				*sample = ast.eval3_with(pos, precision, ctx);
+			}
+			progress.advance();
+		});
+		(!cancel.load(Ordering::Relaxed)).then_some(Self { settings, chunks })
+	}
+
+	/// Lays the chunks out side by side in one output, which the 2D view, mesh and tools use
+	/// like any other.
+	pub fn stitch(&self) -> NoiseOutput {
+		let size = self.settings.chunkSize;
+		let mut output = NoiseOutput::new(self.settings.diameter());
+		for (y, row) in output.rows() {
+			let (chunkY, localY) = (y / size, y % size);
+			for (chunkX, dest) in row.chunks_exact_mut(size).enumerate() {
+				let chunk = &self.chunks[chunkY * self.settings.extent + chunkX];
+				dest.copy_from_slice(&chunk[localY * size .. (localY + 1) * size]);
+			}
+		}
+		output.chunks = Some(self.settings);
+		output
+	}
+}
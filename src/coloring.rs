@@ -0,0 +1,183 @@
+use bevy::color::{ColorToPacked, Mix};
+use bevy::prelude::*;
+use bevy_egui::egui;
+
+use crate::water::WaterSettings;
+use crate::{gradients, Heightmaps, NoiseOutput, UiState};
+
+/// Names of the height bands, lowest first.
+const bandNames: [&str; 5] = ["Water", "Sand", "Grass", "Rock", "Snow"];
+/// Band steep slopes blend towards.
+const rockBand: usize = 3;
+
+/// Gradient the terrain is colored with.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Palette {
+	/// Color of each band in `bandNames`.
+	pub colors: [Color; 5],
+	/// Height each band but the last ends at, in normalized output units like the water level.
+	/// Ascending.
+	pub thresholds: [f32; 4],
+	/// Blends steep terrain towards the rock color.
+	pub slopeShading: bool,
+	/// Slope, as rise over run of the mesh, around which terrain turns to rock.
+	pub rockSlope: f32,
+}
+
+impl Default for Palette {
+	fn default() -> Self {
+		Self {
+			colors: [
+				Color::srgb_u8(0x2A, 0x4B, 0x7C),
+				Color::srgb_u8(0xC2, 0xB2, 0x80),
+				Color::srgb_u8(0x4C, 0x7A, 0x2E),
+				Color::srgb_u8(0x6E, 0x66, 0x5E),
+				Color::srgb_u8(0xF0, 0xF0, 0xF5),
+			],
+			thresholds: [-0.2, -0.1, 0.4, 0.7],
+			slopeShading: true,
+			rockSlope: 1.0,
+		}
+	}
+}
+
+impl Palette {
+	/// sRGB color of terrain at normalized `height` with the given `slope`.
+	pub fn color_at(&self, height: f32, slope: f32) -> [u8; 4] {
+		let band = self
+			.thresholds
+			.iter()
+			.position(|&top| height < top)
+			.unwrap_or(bandNames.len() - 1);
+		let mut color = self.colors[band].to_linear();
+		// underwater slopes stay water
+		if self.slopeShading && band != 0 {
+			// fades in over a quarter of `rockSlope` either side of it
+			let t = ((slope / self.rockSlope - 0.75) * 2.0).clamp(0.0, 1.0);
+			color = color.mix(&self.colors[rockBand].to_linear(), t);
+		}
+		color.to_srgba().to_u8_array()
+	}
+
+	/// RGBA8 texture data for a `width`×`height` grid of normalized `heights`, with slopes
+	/// measured on a mesh `heightScale` tall with samples one unit apart.
+	pub fn colorize(&self, heights: &[f32], size: (usize, usize), heightScale: f32) -> Vec<u8> {
+		let (width, height) = size;
+		let mut colors = Vec::with_capacity(heights.len() * 4);
+		for y in 0 .. height {
+			for x in 0 .. width {
+				let slope = if self.slopeShading {
+					gradients::gradient_at(heights, size, x, y).length() * heightScale
+				} else {
+					0.0
+				};
+				colors.extend(self.color_at(heights[y * width + x], slope));
+			}
+		}
+		colors
+	}
+}
+
+#[derive(Resource, Default)]
+pub struct TerrainColoring {
+	pub palette: Palette,
+	pub windowOpen: bool,
+}
+
+pub fn coloring_ui(ui: &mut egui::Ui, coloring: &mut TerrainColoring) {
+	let palette = &mut coloring.palette;
+	egui::Grid::new("terrainColoring").show(ui, |ui| {
+		for (band, name) in bandNames.into_iter().enumerate() {
+			ui.label(name);
+			let mut color = palette.colors[band].to_srgba().to_u8_array_no_alpha();
+			if ui.color_edit_button_srgb(&mut color).changed() {
+				palette.colors[band] = Color::srgb_u8(color[0], color[1], color[2]);
+			}
+			if band < palette.thresholds.len() {
+				// keep the bands in order
+				let min = if band == 0 { -f32::INFINITY } else { palette.thresholds[band - 1] };
+				let max = palette.thresholds.get(band + 1).copied().unwrap_or(f32::INFINITY);
+				ui.add(
+					egui::DragValue::new(&mut palette.thresholds[band])
+						.range(min ..= max)
+						.speed(0.01)
+						.prefix("up to "),
+				);
+			}
+			ui.end_row();
+		}
+
+		ui.checkbox(&mut palette.slopeShading, "Rocky slopes");
+		ui.add_enabled(
+			palette.slopeShading,
+			egui::DragValue::new(&mut palette.rockSlope)
+				.range(0.05 ..= 10.0)
+				.speed(0.01)
+				.prefix("from slope "),
+		);
+		ui.end_row();
+	});
+}
+
+/// Recolors `Heightmaps::colors` whenever the heights or the palette change. Works from the
+/// heights texture alone, so the palette can be tweaked without generating again. With
+/// `WaterSettings::markWater`, the water band ends at the water level instead of its threshold.
+/// Colors the script returned replace the palette.
+pub fn update_terrain_colors(
+	coloring: Res<TerrainColoring>,
+	water: Res<WaterSettings>,
+	uiState: Res<UiState>,
+	heightmaps: Res<Heightmaps>,
+	noiseOutput: Option<Res<NoiseOutput>>,
+	mut images: ResMut<Assets<Image>>,
+	mut imageEvents: EventReader<AssetEvent<Image>>,
+	mut applied: Local<Option<(Palette, f32)>>,
+) {
+	let heightsChanged = imageEvents
+		.read()
+		.any(|ev| ev.is_modified(&heightmaps.heights));
+	let Some(heights) = images.get(&heightmaps.heights) else {
+		error!("heights image is missing");
+		return;
+	};
+	let size = heights.texture_descriptor.size;
+	let grid = (size.width as usize, size.height as usize);
+	// the mesh height and sample spacing only matter to slopes
+	let heightScale = if coloring.palette.slopeShading {
+		uiState.height / uiState.terrain_scale().spacing(grid)
+	} else {
+		0.0
+	};
+	let mut palette = coloring.palette.clone();
+	if water.markWater {
+		// capped at the top of the sand, which keeps the thresholds ascending
+		palette.thresholds[0] = water.level.min(palette.thresholds[1]);
+	}
+	let current = (palette, heightScale);
+	if !heightsChanged && applied.as_ref() == Some(&current) {
+		return;
+	}
+	*applied = Some(current.clone());
+
+	let heights: &[f32] = bytemuck::cast_slice(&heights.data);
+	// downsampled heights leave them out
+	let scripted = noiseOutput
+		.as_ref()
+		.and_then(|output| output.colors.as_ref())
+		.filter(|colors| colors.len() == heights.len());
+	let colors = match scripted {
+		Some(colors) => colors
+			.iter()
+			.flat_map(|&[r, g, b]| Color::linear_rgb(r, g, b).to_srgba().to_u8_array())
+			.collect(),
+		None => current.0.colorize(heights, grid, heightScale),
+	};
+	let Some(image) = images.get_mut(&heightmaps.colors) else {
+		error!("terrain color image is missing");
+		return;
+	};
+	if image.texture_descriptor.size != size {
+		image.resize(size);
+	}
+	image.data = colors;
+}
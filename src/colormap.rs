@@ -0,0 +1,349 @@
+use bevy::color::ColorToPacked;
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContexts};
+
+use crate::pin::DisplayMode;
+use crate::theme::Theme;
+use crate::{
+	stats, textures, upload_output, Heightmaps, NoiseOutput, SelectedTab, Tab, UiState,
+	ViewportRects,
+};
+
+/// Entries of the lookup table the gradient maps are sampled through.
+const lutSize: usize = 256;
+/// Linear color of samples outside the mapped range with `Colormap::highlightOutOfRange`.
+const outOfRangeColor: [f32; 4] = [1.0, 0.0, 1.0, 1.0];
+/// Size of the legend's color bar, in points.
+const legendBar: egui::Vec2 = egui::vec2(14.0, 160.0);
+/// Width left of the bar for its labels, in points.
+const legendLabels: f32 = 56.0;
+
+/// Stops of the gradient maps: position in `[0, 1]` and sRGB color.
+const viridis: &[(f32, u32)] = &[
+	(0.0, 0x440154),
+	(0.125, 0x472D7B),
+	(0.25, 0x3B528B),
+	(0.375, 0x2C728E),
+	(0.5, 0x21918C),
+	(0.625, 0x28AE80),
+	(0.75, 0x5EC962),
+	(0.875, 0xADDC30),
+	(1.0, 0xFDE725),
+];
+const magma: &[(f32, u32)] = &[
+	(0.0, 0x000004),
+	(0.125, 0x1C1044),
+	(0.25, 0x4F127B),
+	(0.375, 0x812581),
+	(0.5, 0xB5367A),
+	(0.625, 0xE55064),
+	(0.75, 0xFB8761),
+	(0.875, 0xFEC287),
+	(1.0, 0xFCFDBF),
+];
+/// Deep water through shallows, lowland, sand and rock to snow.
+const terrain: &[(f32, u32)] = &[
+	(0.0, 0x333399),
+	(0.15, 0x0099FF),
+	(0.25, 0x00CC66),
+	(0.5, 0xFFFF99),
+	(0.75, 0x805C54),
+	(1.0, 0xFFFFFF),
+];
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ColormapKind {
+	#[default]
+	Grayscale,
+	Viridis,
+	Magma,
+	Terrain,
+	/// Ramp between `Colormap::custom`'s two colors.
+	Custom,
+}
+
+impl ColormapKind {
+	pub const all: [Self; 5] =
+		[Self::Grayscale, Self::Viridis, Self::Magma, Self::Terrain, Self::Custom];
+
+	pub fn label(self) -> &'static str {
+		match self {
+			Self::Grayscale => "Grayscale",
+			Self::Viridis => "Viridis",
+			Self::Magma => "Magma",
+			Self::Terrain => "Terrain",
+			Self::Custom => "Custom",
+		}
+	}
+}
+
+/// How the 2D view turns values into colors. Values arrive mapped from the displayed range to
+/// `[0, 1]`, as `NoiseOutput::fill_image_in` used to write them as gray.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Colormap {
+	pub kind: ColormapKind,
+	/// Colors of the lowest and highest values with `ColormapKind::Custom`.
+	pub custom: [Color; 2],
+	/// Stretches values away from the middle of the range, 1 leaving them as they are.
+	pub contrast: f32,
+	/// Shifts values after `contrast`, in fractions of the range.
+	pub brightness: f32,
+	/// Paints samples outside the displayed range, and NaNs, magenta.
+	pub highlightOutOfRange: bool,
+}
+
+impl Default for Colormap {
+	fn default() -> Self {
+		Self {
+			kind: default(),
+			custom: [Color::srgb_u8(0x10, 0x18, 0x40), Color::srgb_u8(0xFF, 0xD8, 0x66)],
+			contrast: 1.0,
+			brightness: 0.0,
+			highlightOutOfRange: false,
+		}
+	}
+}
+
+impl Colormap {
+	/// Function from a value in `[0, 1]` to a linear RGBA color. Grayscale passes values through
+	/// unclamped like before there were colormaps; the others clamp them to their ends.
+	pub fn mapper(&self) -> impl '_ + Fn(f32) -> [f32; 4] {
+		let lut = self.lut();
+		move |v| {
+			if self.highlightOutOfRange && !(0.0 ..= 1.0).contains(&v) {
+				return outOfRangeColor;
+			}
+			let v = (v - 0.5) * self.contrast + 0.5 + self.brightness;
+			let Some(lut) = &lut else {
+				return [v, v, v, 1.0];
+			};
+			let pos = v.clamp(0.0, 1.0) * (lutSize - 1) as f32;
+			let i = (pos as usize).min(lutSize - 2);
+			let frac = pos - i as f32;
+			let [a, b] = [lut[i], lut[i + 1]];
+			let [r, g, b] = [0, 1, 2].map(|c| a[c] + (b[c] - a[c]) * frac);
+			[r, g, b, 1.0]
+		}
+	}
+
+	/// The gradient sampled at `lutSize` evenly spaced values, in linear RGB. `None` for grayscale.
+	fn lut(&self) -> Option<Vec<[f32; 3]>> {
+		let stops: Vec<(f32, Srgba)> = match self.kind {
+			ColormapKind::Grayscale => return None,
+			ColormapKind::Viridis => hex_stops(viridis),
+			ColormapKind::Magma => hex_stops(magma),
+			ColormapKind::Terrain => hex_stops(terrain),
+			ColormapKind::Custom => {
+				vec![(0.0, self.custom[0].into()), (1.0, self.custom[1].into())]
+			},
+		};
+		let lut = (0 .. lutSize).map(|i| {
+			let t = i as f32 / (lutSize - 1) as f32;
+			let end = stops.iter().position(|&(pos, _)| pos >= t).unwrap_or(stops.len() - 1).max(1);
+			let ((from, a), (to, b)) = (stops[end - 1], stops[end]);
+			let color = a.mix(&b, ((t - from) / (to - from)).clamp(0.0, 1.0));
+			let color = LinearRgba::from(color);
+			[color.red, color.green, color.blue]
+		});
+		Some(lut.collect())
+	}
+}
+
+fn hex_stops(stops: &[(f32, u32)]) -> Vec<(f32, Srgba)> {
+	let channel = |hex: u32, shift: u32| ((hex >> shift) & 0xFF) as u8;
+	stops
+		.iter()
+		.map(|&(pos, hex)| (pos, Srgba::rgb_u8(channel(hex, 16), channel(hex, 8), channel(hex, 0))))
+		.collect()
+}
+
+/// Colormap of the 2D view and its window.
+#[derive(Resource)]
+pub struct ColormapSettings {
+	pub open: bool,
+	pub colormap: Colormap,
+	/// Shows the value to color scale next to the 2D view.
+	pub legend: bool,
+}
+
+impl Default for ColormapSettings {
+	fn default() -> Self {
+		Self {
+			open: false,
+			colormap: default(),
+			legend: true,
+		}
+	}
+}
+
+fn colormap_ui(ui: &mut egui::Ui, colormap: &mut Colormap) {
+	egui::Grid::new("colormap").show(ui, |ui| {
+		ui.label("Map");
+		egui::ComboBox::from_id_source("colormapKind")
+			.selected_text(colormap.kind.label())
+			.show_ui(ui, |ui| {
+				for option in ColormapKind::all {
+					ui.selectable_value(&mut colormap.kind, option, option.label());
+				}
+			});
+		ui.end_row();
+
+		if colormap.kind == ColormapKind::Custom {
+			ui.label("Ramp");
+			ui.horizontal(|ui| {
+				for end in &mut colormap.custom {
+					let mut color = end.to_srgba().to_u8_array_no_alpha();
+					if ui.color_edit_button_srgb(&mut color).changed() {
+						*end = Color::srgb_u8(color[0], color[1], color[2]);
+					}
+				}
+			});
+			ui.end_row();
+		}
+
+		ui.label("Contrast");
+		ui.add(egui::Slider::new(&mut colormap.contrast, 0.1 ..= 10.0).logarithmic(true));
+		ui.end_row();
+		ui.label("Brightness");
+		ui.add(egui::Slider::new(&mut colormap.brightness, -1.0 ..= 1.0));
+		ui.end_row();
+	});
+	ui.checkbox(&mut colormap.highlightOutOfRange, "Highlight out of range")
+		.on_hover_text("Paint samples outside the displayed range, and NaNs, magenta");
+	if ui.button("Reset").clicked() {
+		*colormap = default();
+	}
+}
+
+/// The colormap window, and the legend next to the 2D view.
+pub fn colormap_panel(
+	mut eguiCtx: EguiContexts,
+	mut settings: ResMut<ColormapSettings>,
+	selectedTab: Res<SelectedTab>,
+	viewportRects: Res<ViewportRects>,
+	noiseOutput: Option<Res<NoiseOutput>>,
+	outputStats: Res<stats::OutputStats>,
+	displayMode: Res<DisplayMode>,
+	theme: Res<Theme>,
+) {
+	let ctx = eguiCtx.ctx_mut();
+	if settings.open {
+		let (mut open, mut legend) = (true, settings.legend);
+		// edited on a copy, as every change to the settings recolors the outputs
+		let mut colormap = settings.colormap.clone();
+		egui::Window::new("Colormap").open(&mut open).show(ctx, |ui| {
+			colormap_ui(ui, &mut colormap);
+			ui.checkbox(&mut legend, "Legend");
+		});
+		if colormap != settings.colormap {
+			settings.colormap = colormap;
+		}
+		let settings = settings.bypass_change_detection();
+		settings.open = open;
+		settings.legend = legend;
+	}
+
+	// diffs keep their own grayscale
+	let shown =
+		selectedTab.shows(Tab::D2) && settings.legend && *displayMode != DisplayMode::PinnedDiff;
+	let Some(output) = noiseOutput.filter(|_| shown) else {
+		return;
+	};
+	let (min, max) = outputStats.display_range(&output);
+	let color = settings.colormap.mapper();
+	egui::Area::new(egui::Id::new("colormapLegend"))
+		.fixed_pos(viewportRects.d2.rect.right_top() + egui::vec2(-8.0, 8.0))
+		.pivot(egui::Align2::RIGHT_TOP)
+		.interactable(false)
+		.show(ctx, |ui| {
+			theme.overlay_frame(ui.style()).show(ui, |ui| {
+				let size = legendBar + egui::vec2(legendLabels, 0.0);
+				let (rect, _) = ui.allocate_exact_size(size, egui::Sense::hover());
+				let barMin = rect.right_top() - egui::vec2(legendBar.x, 0.0);
+				let bar = egui::Rect::from_min_size(barMin, legendBar);
+				let painter = ui.painter_at(rect);
+				let steps = 64;
+				for step in 0 .. steps {
+					// highest values on top
+					let v = 1.0 - (step as f32 + 0.5) / steps as f32;
+					let top = bar.top() + bar.height() * step as f32 / steps as f32;
+					let bottom = bar.top() + bar.height() * (step + 1) as f32 / steps as f32;
+					let segment = egui::Rect::from_x_y_ranges(bar.x_range(), top ..= bottom);
+					painter.rect_filled(segment, 0.0, to_color32(color(v)));
+				}
+				let font = egui::TextStyle::Small.resolve(ui.style());
+				let labels = [
+					(max, bar.top(), egui::Align2::RIGHT_TOP),
+					((min + max) / 2.0, bar.center().y, egui::Align2::RIGHT_CENTER),
+					(min, bar.bottom(), egui::Align2::RIGHT_BOTTOM),
+				];
+				for (value, y, align) in labels {
+					let pos = egui::pos2(bar.left() - 4.0, y);
+					let text = format!("{value:.3}");
+					painter.text(pos, align, text, font.clone(), ui.visuals().text_color());
+				}
+				if settings.colormap.highlightOutOfRange {
+					ui.horizontal(|ui| {
+						let swatch = egui::vec2(10.0, 10.0);
+						let (rect, _) = ui.allocate_exact_size(swatch, egui::Sense::hover());
+						ui.painter().rect_filled(rect, 0.0, to_color32(outOfRangeColor));
+						ui.small("out of range");
+					});
+				}
+			});
+		});
+}
+
+/// What `NoiseOutput::fill_image` shows, as an 8-bit sRGB image for saving.
+pub fn rgba8_image(output: &NoiseOutput, colormap: &Colormap) -> image::RgbaImage {
+	let color = colormap.mapper();
+	let (min, max) = output.range;
+	let pixel = |i: usize| match &output.colors {
+		Some(colors) => {
+			let [r, g, b] = colors[i];
+			[r, g, b, 1.0]
+		},
+		None => {
+			let v = output.samples[i];
+			let v = if v.is_finite() || colormap.highlightOutOfRange { v } else { 0.0 };
+			color((v - min) / (max - min))
+		},
+	};
+	let pixels = (0 .. output.samples.len())
+		.flat_map(|i| {
+			let [r, g, b, a] = pixel(i);
+			Color::linear_rgba(r, g, b, a).to_srgba().to_u8_array()
+		})
+		.collect();
+	let (width, height) = (output.width as u32, output.height as u32);
+	image::RgbaImage::from_raw(width, height, pixels).expect("a pixel per sample")
+}
+
+fn to_color32([r, g, b, _]: [f32; 4]) -> egui::Color32 {
+	let [r, g, b] = Color::linear_rgb(r, g, b).to_srgba().to_u8_array_no_alpha();
+	egui::Color32::from_rgb(r, g, b)
+}
+
+/// Redraws the 2D images of every layer's output when the colormap changes, from the outputs
+/// themselves, so switching maps doesn't generate again.
+pub fn recolor_outputs(
+	settings: Res<ColormapSettings>,
+	noiseOutput: Option<Res<NoiseOutput>>,
+	uiState: Res<UiState>,
+	heightmaps: Res<Heightmaps>,
+	mut images: ResMut<Assets<Image>>,
+	limits: Res<textures::TextureLimits>,
+) {
+	if !settings.is_changed() || settings.is_added() {
+		return;
+	}
+	let colormap = &settings.colormap;
+	if let Some(output) = &noiseOutput {
+		upload_output(output, &heightmaps.image, None, &mut images, &limits, colormap);
+	}
+	for layer in &uiState.layers {
+		if let Some(output) = &layer.output {
+			upload_output(output, &layer.image, None, &mut images, &limits, colormap);
+		}
+	}
+}
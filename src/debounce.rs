@@ -0,0 +1,145 @@
+use std::time::{Duration, Instant};
+
+use bevy::prelude::*;
+use bevy_egui::egui;
+
+use crate::{NoiseGenRequest, NoiseGenTask};
+
+/// Quiet time before a held back regeneration starts, unless changed in the settings.
+const defaultDelay: Duration = Duration::from_millis(300);
+/// Longest quiet time the settings offer.
+const maxDelay: Duration = Duration::from_secs(3);
+
+/// A regeneration waiting for changes to stop coming.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PendingRegen {
+	/// Time of the latest change, which the wait counts from.
+	pub requestedAt: Instant,
+	/// The changes merged into one request, see `merge_requests`.
+	pub kind: NoiseGenRequest,
+}
+
+/// Holds back regenerations asked for by script saves and dragged sliders until no change came
+/// for `delay`, so a fast autosave or a drag doesn't queue up a generation per frame. Requests sent
+/// as `NoiseGenRequest` events directly, like F5's, aren't held back.
+#[derive(Resource)]
+pub struct RegenDebounce {
+	pub delay: Duration,
+	pub pending: Option<PendingRegen>,
+	/// Since when a regeneration has been pending or running, for the toolbar's indicator.
+	busySince: Option<Instant>,
+	/// Scripts of the running generations with the share of each done, oldest first.
+	running: Vec<(String, f32)>,
+}
+
+impl Default for RegenDebounce {
+	fn default() -> Self {
+		Self {
+			delay: defaultDelay,
+			pending: None,
+			busySince: None,
+			running: vec![],
+		}
+	}
+}
+
+impl RegenDebounce {
+	/// Holds back `kind` until `delay` passed without another request, merged with the one
+	/// pending.
+	pub fn request(&mut self, kind: NoiseGenRequest) {
+		self.request_at(kind, Instant::now());
+	}
+
+	pub fn request_at(&mut self, kind: NoiseGenRequest, now: Instant) {
+		let kind = match self.pending {
+			Some(pending) => merge_requests(pending.kind, kind),
+			None => kind,
+		};
+		self.pending = Some(PendingRegen {
+			requestedAt: now,
+			kind,
+		});
+		self.busySince.get_or_insert(now);
+	}
+
+	/// Takes the pending request if it waited long enough by `now`.
+	pub fn take_due(&mut self, now: Instant) -> Option<NoiseGenRequest> {
+		let pending = self.pending.filter(|pending| now - pending.requestedAt >= self.delay)?;
+		self.pending = None;
+		Some(pending.kind)
+	}
+}
+
+/// One request doing what both `a` and `b` ask for. Regenerating every layer supersedes
+/// regenerating one of them or just rebuilding the mesh, and forcing scripts to run supersedes
+/// everything; two different single layers take regenerating them all.
+pub fn merge_requests(a: NoiseGenRequest, b: NoiseGenRequest) -> NoiseGenRequest {
+	use NoiseGenRequest::*;
+	match (a, b) {
+		(Regenerate, _) | (_, Regenerate) => Regenerate,
+		(AlgorithmChanged, _) | (_, AlgorithmChanged) => AlgorithmChanged,
+		(LayerChanged(a), LayerChanged(b)) if a == b => LayerChanged(a),
+		(LayerChanged(_), LayerChanged(_)) => AlgorithmChanged,
+		// a layer's new output rebuilds the mesh too only if it's the active one
+		(LayerChanged(_), ModelParamsChanged) | (ModelParamsChanged, LayerChanged(_)) => {
+			AlgorithmChanged
+		},
+		(ModelParamsChanged, ModelParamsChanged) => ModelParamsChanged,
+	}
+}
+
+/// Sends the pending request once it's due, and keeps track of how long regeneration has been
+/// busy and how far each running generation got.
+pub fn fire_pending_regen(
+	mut debounce: ResMut<RegenDebounce>,
+	tasks: Query<&NoiseGenTask>,
+	mut noiseGenRequests: EventWriter<NoiseGenRequest>,
+) {
+	let now = Instant::now();
+	let debounce = debounce.bypass_change_detection();
+	let mut running: Vec<_> = tasks.iter().collect();
+	running.sort_by_key(|task| task.id);
+	debounce.running = running
+		.into_iter()
+		.map(|task| (task.script.display.clone(), task.progress.fraction()))
+		.collect();
+	if let Some(kind) = debounce.take_due(now) {
+		noiseGenRequests.send(kind);
+		// the tasks are spawned later this frame
+		return;
+	}
+	if !tasks.is_empty() {
+		debounce.busySince.get_or_insert(now);
+	} else if debounce.pending.is_none() {
+		debounce.busySince = None;
+	}
+}
+
+/// Spinner and elapsed time in the toolbar while a regeneration is pending or running.
+pub fn status_ui(ui: &mut egui::Ui, debounce: &RegenDebounce) {
+	let Some(since) = debounce.busySince else {
+		return;
+	};
+	ui.spinner();
+	let (label, hover) = if debounce.pending.is_some() {
+		("pending…", "Waiting for changes to stop before generating, see Settings")
+	} else {
+		("generating…", "Time since the change that started this generation")
+	};
+	ui.label(format!("{label} {:.1}s", since.elapsed().as_secs_f32())).on_hover_text(hover);
+	for (script, done) in &debounce.running {
+		ui.add(egui::ProgressBar::new(*done).desired_width(40.0))
+			.on_hover_text(format!("{script}: {:.0}%", done * 100.0));
+	}
+}
+
+/// The quiet time setting, for the Settings window.
+pub fn debounce_ui(ui: &mut egui::Ui, debounce: &mut RegenDebounce) {
+	let mut millis = debounce.delay.as_millis() as u64;
+	ui.add(egui::Slider::new(&mut millis, 0 ..= maxDelay.as_millis() as u64).suffix(" ms"))
+		.on_hover_text(
+			"Quiet time after a script save or a slider change before generating, so \
+			 quick successive changes start a single generation",
+		);
+	debounce.delay = Duration::from_millis(millis);
+}
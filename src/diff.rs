@@ -0,0 +1,411 @@
+use std::fmt;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, ensure, Context};
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContexts};
+use serde::Serialize;
+
+use crate::log_panel::{LogKind, SessionLog, Severity};
+use crate::snapshot::Snapshots;
+use crate::{AResult, NoiseOutput};
+
+const magic: &[u8; 8] = b"NBDIFF01";
+
+/// FNV-1a over the sample bits, so equal hashes mean bit-identical outputs.
+pub fn samples_hash(samples: &[f32]) -> u64 {
+	samples
+		.iter()
+		.flat_map(|v| v.to_le_bytes())
+		.fold(0xcbf29ce484222325, |hash, byte| {
+			(hash ^ byte as u64).wrapping_mul(0x100000001b3)
+		})
+}
+
+/// Difference between two outputs of the same size: per-texel deltas quantized to 16 bits, plus
+/// the exact values of the texels quantization can't reproduce bit for bit, so applying a diff to
+/// its baseline always reconstructs the target exactly.
+pub struct HeightDiff {
+	pub diameter: usize,
+	/// Range of the target output, see `NoiseOutput::range`.
+	pub range: (f32, f32),
+	pub baseHash: u64,
+	pub targetHash: u64,
+	/// Value of one quantization step.
+	step: f32,
+	deltas: Vec<i16>,
+	/// Texel index and value of every texel the quantized delta misses.
+	exact: Vec<(u32, f32)>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DiffSummary {
+	pub texels: usize,
+	pub changed: usize,
+	pub maxDelta: f32,
+	pub rmsDelta: f32,
+	/// Texels stored verbatim because their quantized delta wasn't exact.
+	pub exact: usize,
+}
+
+impl fmt::Display for DiffSummary {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(
+			f,
+			"{} / {} texels changed, max delta {:.6}, RMS delta {:.6}, {} stored exactly",
+			self.changed, self.texels, self.maxDelta, self.rmsDelta, self.exact
+		)
+	}
+}
+
+impl HeightDiff {
+	pub fn new(base: &[f32], target: &[f32], range: (f32, f32)) -> AResult<Self> {
+		ensure!(
+			base.len() == target.len(),
+			"baseline has {} texels but the output has {}",
+			base.len(),
+			target.len()
+		);
+		let diameter = (target.len() as f64).sqrt() as usize;
+		ensure!(diameter * diameter == target.len(), "outputs must be square");
+
+		let maxDelta = base
+			.iter()
+			.zip(target)
+			.map(|(a, b)| (b - a).abs())
+			.filter(|d| d.is_finite())
+			.fold(0f32, f32::max);
+		let step = maxDelta / i16::MAX as f32;
+		let mut deltas = Vec::with_capacity(target.len());
+		let mut exact = vec![];
+		for (i, (&a, &b)) in base.iter().zip(target).enumerate() {
+			let delta = b - a;
+			let q = if step > 0.0 && delta.is_finite() {
+				(delta / step).round().clamp(i16::MIN as f32, i16::MAX as f32) as i16
+			} else {
+				0
+			};
+			if (a + q as f32 * step).to_bits() != b.to_bits() {
+				exact.push((i as u32, b));
+			}
+			deltas.push(q);
+		}
+
+		Ok(Self {
+			diameter,
+			range,
+			baseHash: samples_hash(base),
+			targetHash: samples_hash(target),
+			step,
+			deltas,
+			exact,
+		})
+	}
+
+	/// Reconstructs the target from the baseline the diff was made against.
+	pub fn apply(&self, base: &[f32]) -> AResult<Vec<f32>> {
+		ensure!(
+			base.len() == self.deltas.len(),
+			"baseline has {} texels but the diff expects {}",
+			base.len(),
+			self.deltas.len()
+		);
+		ensure!(
+			samples_hash(base) == self.baseHash,
+			"baseline doesn't match the one the diff was made against"
+		);
+		let mut samples: Vec<f32> = base
+			.iter()
+			.zip(&self.deltas)
+			.map(|(&a, &q)| a + q as f32 * self.step)
+			.collect();
+		for &(i, v) in &self.exact {
+			*samples.get_mut(i as usize).context("exact texel out of bounds")? = v;
+		}
+		ensure!(
+			samples_hash(&samples) == self.targetHash,
+			"reconstruction doesn't match the target hash"
+		);
+		Ok(samples)
+	}
+
+	pub fn summary(&self) -> DiffSummary {
+		let texels = self.deltas.len();
+		let mut changed = 0;
+		let mut sumSquares = 0f64;
+		for (i, &q) in self.deltas.iter().enumerate() {
+			if q != 0 {
+				changed += 1;
+				sumSquares += (q as f64 * self.step as f64).powi(2);
+			}
+		}
+		// texels whose delta rounds to zero can still differ, but then they're stored exactly
+		changed += self
+			.exact
+			.iter()
+			.filter(|&&(i, _)| self.deltas[i as usize] == 0)
+			.count();
+		DiffSummary {
+			texels,
+			changed,
+			maxDelta: self.step * i16::MAX as f32,
+			rmsDelta: (sumSquares / texels.max(1) as f64).sqrt() as f32,
+			exact: self.exact.len(),
+		}
+	}
+
+	pub fn write(&self, mut w: impl Write) -> std::io::Result<()> {
+		w.write_all(magic)?;
+		w.write_all(&(self.diameter as u32).to_le_bytes())?;
+		w.write_all(&self.range.0.to_le_bytes())?;
+		w.write_all(&self.range.1.to_le_bytes())?;
+		w.write_all(&self.baseHash.to_le_bytes())?;
+		w.write_all(&self.targetHash.to_le_bytes())?;
+		w.write_all(&self.step.to_le_bytes())?;
+		w.write_all(&(self.exact.len() as u32).to_le_bytes())?;
+		for q in &self.deltas {
+			w.write_all(&q.to_le_bytes())?;
+		}
+		for (i, v) in &self.exact {
+			w.write_all(&i.to_le_bytes())?;
+			w.write_all(&v.to_le_bytes())?;
+		}
+		Ok(())
+	}
+
+	pub fn read(mut r: impl Read) -> AResult<Self> {
+		let mut header = [0u8; 8];
+		r.read_exact(&mut header)?;
+		ensure!(&header == magic, "not a noisebench diff");
+		let mut read4 = || -> AResult<[u8; 4]> {
+			let mut buf = [0; 4];
+			r.read_exact(&mut buf)?;
+			Ok(buf)
+		};
+		let diameter = u32::from_le_bytes(read4()?) as usize;
+		let range = (f32::from_le_bytes(read4()?), f32::from_le_bytes(read4()?));
+		let baseHash = u64::from_le_bytes(bytemuck::cast([read4()?, read4()?]));
+		let targetHash = u64::from_le_bytes(bytemuck::cast([read4()?, read4()?]));
+		let step = f32::from_le_bytes(read4()?);
+		let exactCount = u32::from_le_bytes(read4()?) as usize;
+		ensure!(diameter <= 1 << 16, "implausible diameter {diameter}");
+
+		let mut data = vec![0u8; diameter * diameter * 2];
+		r.read_exact(&mut data)?;
+		let deltas = data
+			.chunks_exact(2)
+			.map(|b| i16::from_le_bytes([b[0], b[1]]))
+			.collect();
+		if exactCount > diameter * diameter {
+			bail!("more exact texels than texels");
+		}
+		let mut data = vec![0u8; exactCount * 8];
+		r.read_exact(&mut data)?;
+		let exact = data
+			.chunks_exact(8)
+			.map(|b| {
+				(
+					u32::from_le_bytes(b[.. 4].try_into().unwrap()),
+					f32::from_le_bytes(b[4 ..].try_into().unwrap()),
+				)
+			})
+			.collect();
+
+		Ok(Self {
+			diameter,
+			range,
+			baseHash,
+			targetHash,
+			step,
+			deltas,
+			exact,
+		})
+	}
+}
+
+/// Raw little-endian f32 samples, the baseline format diffs are applied to.
+pub fn read_raw(path: &Path) -> AResult<Vec<f32>> {
+	let data = std::fs::read(path).with_context(|| format!("reading {}", path.display()))?;
+	ensure!(data.len() % 4 == 0, "{} isn't a raw f32 file", path.display());
+	Ok(data
+		.chunks_exact(4)
+		.map(|b| f32::from_le_bytes(b.try_into().unwrap()))
+		.collect())
+}
+
+pub fn write_raw(path: &Path, samples: &[f32]) -> AResult<()> {
+	let data: Vec<u8> = samples.iter().flat_map(|v| v.to_le_bytes()).collect();
+	std::fs::write(path, data).with_context(|| format!("writing {}", path.display()))
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Default)]
+enum Baseline {
+	/// The output before the latest regeneration.
+	#[default]
+	Previous,
+	Snapshot(usize),
+	File,
+}
+
+/// Exports diffs of the live output against a baseline, for keeping terrain changes in git.
+#[derive(Resource)]
+pub struct DiffExport {
+	pub open: bool,
+	baseline: Baseline,
+	baselineFile: String,
+	diffFile: String,
+	/// Samples of the output before the current one, and of the current one.
+	previous: Option<Vec<f32>>,
+	current: Option<Vec<f32>>,
+	message: Option<String>,
+}
+
+impl Default for DiffExport {
+	fn default() -> Self {
+		Self {
+			open: false,
+			baseline: default(),
+			baselineFile: "baseline.f32".into(),
+			diffFile: "output.nbdiff".into(),
+			previous: None,
+			current: None,
+			message: None,
+		}
+	}
+}
+
+impl DiffExport {
+	pub fn memory_bytes(&self) -> usize {
+		[&self.previous, &self.current]
+			.into_iter()
+			.flatten()
+			.map(|samples| samples.capacity() * 4)
+			.sum()
+	}
+
+	/// Forgets the previous generation, leaving the "Previous" baseline empty until the next one.
+	pub fn drop_history(&mut self) {
+		self.previous = None;
+	}
+
+	fn baseline_samples(&self, snapshots: &Snapshots) -> AResult<Vec<f32>> {
+		match self.baseline {
+			Baseline::Previous => self.previous.clone().context("no previous generation yet"),
+			Baseline::Snapshot(slot) => snapshots
+				.samples(slot)
+				.map(<[f32]>::to_vec)
+				.context("snapshot slot is empty"),
+			Baseline::File => read_raw(Path::new(&self.baselineFile)),
+		}
+	}
+
+	fn export(&self, output: &NoiseOutput, snapshots: &Snapshots) -> AResult<DiffSummary> {
+		let base = self.baseline_samples(snapshots)?;
+		let diff = HeightDiff::new(&base, &output.samples, output.range)?;
+		let file =
+			std::fs::File::create(&self.diffFile).with_context(|| format!("creating {}", self.diffFile))?;
+		diff.write(std::io::BufWriter::new(file))?;
+		Ok(diff.summary())
+	}
+
+	/// Applies the diff file to the baseline file and writes the result next to the diff.
+	fn apply(&self) -> AResult<PathBuf> {
+		let base = read_raw(Path::new(&self.baselineFile))?;
+		let file =
+			std::fs::File::open(&self.diffFile).with_context(|| format!("opening {}", self.diffFile))?;
+		let diff = HeightDiff::read(std::io::BufReader::new(file))?;
+		let samples = diff.apply(&base)?;
+		let path = PathBuf::from(&self.diffFile).with_extension("f32");
+		write_raw(&path, &samples)?;
+		Ok(path)
+	}
+}
+
+pub fn diff_export(
+	mut eguiCtx: EguiContexts,
+	mut export: ResMut<DiffExport>,
+	noiseOutput: Option<Res<NoiseOutput>>,
+	snapshots: Res<Snapshots>,
+	log: Res<SessionLog>,
+) {
+	if let Some(output) = noiseOutput.as_ref().filter(|output| output.is_changed()) {
+		let current = export.current.replace(output.samples.clone());
+		export.previous = current;
+	}
+	if !export.open {
+		return;
+	}
+
+	let export = &mut *export;
+	let mut open = true;
+	egui::Window::new("Diff export")
+		.open(&mut open)
+		.show(eguiCtx.ctx_mut(), |ui| {
+			egui::Grid::new("diffExport").show(ui, |ui| {
+				ui.label("Baseline");
+				ui.horizontal(|ui| {
+					ui.selectable_value(&mut export.baseline, Baseline::Previous, "Previous");
+					ui.selectable_value(&mut export.baseline, Baseline::Snapshot(0), "Snapshot A");
+					ui.selectable_value(&mut export.baseline, Baseline::Snapshot(1), "Snapshot B");
+					ui.selectable_value(&mut export.baseline, Baseline::File, "File");
+				});
+				ui.end_row();
+
+				ui.label("Baseline file");
+				ui.text_edit_singleline(&mut export.baselineFile)
+					.on_hover_text("Raw little-endian f32 samples");
+				ui.end_row();
+
+				ui.label("Diff file");
+				ui.text_edit_singleline(&mut export.diffFile);
+				ui.end_row();
+			});
+
+			ui.horizontal(|ui| {
+				let output = noiseOutput.as_deref();
+				if ui
+					.add_enabled(output.is_some(), egui::Button::new("Export diff"))
+					.clicked()
+				{
+					let res = export.export(output.unwrap(), &snapshots);
+					export.message = Some(match res {
+						Ok(summary) => {
+							let message = format!("wrote {}: {summary}", export.diffFile);
+							log.record(Severity::Info, LogKind::Export, None, message);
+							summary.to_string()
+						},
+						Err(err) => {
+							let message = format!("diff export failed: {err:#}");
+							log.record(Severity::Error, LogKind::Export, None, message);
+							format!("export failed: {err:#}")
+						},
+					});
+				}
+				if ui
+					.add_enabled(output.is_some(), egui::Button::new("Save as baseline file"))
+					.clicked()
+				{
+					let res = write_raw(Path::new(&export.baselineFile), &output.unwrap().samples);
+					export.message = Some(match res {
+						Ok(()) => format!("wrote {}", export.baselineFile),
+						Err(err) => format!("{err:#}"),
+					});
+				}
+				if ui
+					.button("Apply diff")
+					.on_hover_text("Reconstructs the output from the baseline file and the diff file")
+					.clicked()
+				{
+					export.message = Some(match export.apply() {
+						Ok(path) => format!("reconstructed {}", path.display()),
+						Err(err) => format!("apply failed: {err:#}"),
+					});
+				}
+			});
+			if let Some(message) = &export.message {
+				ui.label(message);
+			}
+		});
+	export.open = open;
+}
@@ -0,0 +1,156 @@
+use std::time::Duration;
+
+use bevy::math::{dvec2, DVec2};
+use bevy::prelude::*;
+use bevy_egui::egui;
+
+use crate::{NoiseGenRequest, SelectedTab, Tab, UiState, ViewportRects};
+
+/// How long the 2D camera has to rest before the domain follows it.
+const settleTime: Duration = Duration::from_millis(300);
+
+/// Smallest 2D camera zoom while following it, showing a ten-thousandth of what zoom 1 shows.
+const minFollowZoom: f32 = 1e-4;
+
+/// Square region of the evaluation plane a generation samples, with the first sample on `origin`
+/// and the last one `size` further along both axes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Domain {
+	pub origin: DVec2,
+	pub size: f64,
+}
+
+impl Default for Domain {
+	/// The unit square.
+	fn default() -> Self {
+		Self {
+			origin: DVec2::ZERO,
+			size: 1.0,
+		}
+	}
+}
+
+impl Domain {
+	pub fn is_unit(&self) -> bool {
+		*self == Self::default()
+	}
+
+	/// Maps a position over the unit square, like `sample_position`'s, into the domain.
+	pub fn apply(&self, pos: DVec2) -> DVec2 {
+		self.origin + pos * self.size
+	}
+
+	/// Transform placing a sprite of a `diameter`² output of this domain over the texels of the
+	/// unit square's `viewDiameter`² output it zooms into. `None` for the unit square, whose
+	/// outputs are shown as they are.
+	pub fn placement(&self, diameter: usize, viewDiameter: usize) -> Option<(Vec2, f32)> {
+		if self.is_unit() {
+			return None;
+		}
+		let center = to_world(self.apply(DVec2::splat(0.5)), viewDiameter);
+		let scale = self.size * viewDiameter.saturating_sub(1).max(1) as f64 /
+			diameter.saturating_sub(1).max(1) as f64;
+		Some((center, scale as f32))
+	}
+}
+
+/// Whether and where the 2D view's generations follow the camera.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ViewDomain {
+	/// Samples the region the 2D camera shows instead of the unit square, once it came to rest.
+	pub followCamera: bool,
+	/// Domain the generations use, `None` for the unit square. Set by `follow_camera`, and only
+	/// while the 2D view is selected, so the 3D view keeps showing the whole unit square.
+	pub current: Option<Domain>,
+}
+
+impl ViewDomain {
+	pub fn domain(&self) -> Domain {
+		self.current.unwrap_or_default()
+	}
+
+	/// Smallest zoom the 2D camera allows.
+	pub fn min_zoom(&self) -> f32 {
+		if self.followCamera { minFollowZoom } else { 0.1 }
+	}
+}
+
+/// Maps a point of the 2D world to evaluation coordinates, for a `diameter`² output of the unit
+/// square centered on the origin with one world unit per texel.
+pub fn to_domain(world: Vec2, diameter: usize) -> DVec2 {
+	let half = diameter as f64 / 2.0;
+	let texel = dvec2(world.x as f64 + half, half - world.y as f64);
+	// sample i sits at the center of texel i and is evaluated at i / (diameter - 1)
+	(texel - 0.5) / diameter.saturating_sub(1).max(1) as f64
+}
+
+/// Inverse of `to_domain`.
+pub fn to_world(domain: DVec2, diameter: usize) -> Vec2 {
+	let half = diameter as f64 / 2.0;
+	let texel = domain * diameter.saturating_sub(1).max(1) as f64 + 0.5;
+	vec2((texel.x - half) as f32, (half - texel.y) as f32)
+}
+
+/// Toolbar controls of the 2D view. Switching following on or off is picked up by `follow_camera`.
+pub fn domain_ui(ui: &mut egui::Ui, view: &mut ViewDomain) {
+	ui.toggle_value(&mut view.followCamera, "Follow camera").on_hover_text(
+		"Generate the region the 2D camera shows at full resolution once it stops moving, instead \
+		 of the unit square. Only square outputs in 0 … 1 coordinates follow it",
+	);
+	let Domain { origin, size } = view.domain();
+	let end = origin + size;
+	ui.label(format!("x {:.4} … {:.4}, y {:.4} … {:.4}", origin.x, end.x, origin.y, end.y))
+		.on_hover_text("Evaluation coordinates of the first and last samples");
+}
+
+/// Points the generations at the square the 2D camera shows, a little after it stopped panning
+/// and zooming, and back at the unit square when following is switched off or the 2D view left.
+pub fn follow_camera(
+	mut uiState: ResMut<UiState>,
+	selectedTab: Res<SelectedTab>,
+	viewportRects: Res<ViewportRects>,
+	camera: Query<&Transform, With<Camera2d>>,
+	time: Res<Time>,
+	mut noiseGenRequests: EventWriter<NoiseGenRequest>,
+	mut moving: Local<Option<(Domain, Duration)>>,
+) {
+	let following = uiState.domain.followCamera &&
+		uiState.can_follow_camera() &&
+		selectedTab.shows(Tab::D2) &&
+		!uiState.splitView;
+	let viewportRect = &viewportRects.d2;
+	// before the first layout, there's no view to follow yet
+	if following && viewportRect.rect.area() <= 0.0 {
+		return;
+	}
+	let wanted = following.then(|| {
+		let camera = camera.single();
+		let size = viewportRect.pixel_size();
+		// the square covering the whole viewport, so no part of it is left unsampled
+		let half = size.x.max(size.y) / 2.0 * camera.scale.x;
+		let center = camera.translation.truncate();
+		let (diameter, _) = uiState.output_size();
+		let topLeft = to_domain(center + vec2(-half, half), diameter);
+		let bottomRight = to_domain(center + vec2(half, -half), diameter);
+		Domain {
+			origin: topLeft,
+			size: bottomRight.x - topLeft.x,
+		}
+	});
+	let now = time.elapsed();
+	let settled = match (wanted, *moving) {
+		// leaving takes effect at once
+		(None, _) => true,
+		(Some(domain), Some((last, since))) if domain == last => now - since >= settleTime,
+		(Some(domain), _) => {
+			*moving = Some((domain, now));
+			false
+		},
+	};
+	if !settled || wanted == uiState.domain.current {
+		return;
+	}
+	*moving = None;
+	uiState.domain.current = wanted;
+	noiseGenRequests.send(NoiseGenRequest::AlgorithmChanged);
+}
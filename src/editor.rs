@@ -0,0 +1,167 @@
+use std::path::{Path, PathBuf};
+
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+use bevy_egui::{egui, EguiContexts};
+
+use crate::thumbnails::Thumbnails;
+use crate::{content_hash, ContentHash, InternedPath, NoiseGenRequest, UiState};
+
+/// Side panel editing the selected script in place.
+///
+/// Edits only reach generation when applied or saved. Saving writes the file and applies the
+/// buffer right away, recording the write in `ownWrites` so `scripts_changed` can ignore the
+/// watcher's echo of it instead of reloading and regenerating a second time.
+#[derive(Resource, Default)]
+pub struct ScriptEditor {
+	pub open: bool,
+	/// Script the buffer was loaded from.
+	path: Option<InternedPath>,
+	buffer: String,
+	/// Contents last known to be on disk, to tell unsaved edits.
+	saved: String,
+	/// `UiState::scripts` entry as of the last frame, to notice changes made from outside.
+	lastSeen: String,
+	/// Whether the file changed on disk while the buffer had unsaved edits.
+	conflict: bool,
+	/// Whether the text field has keyboard focus, giving Ctrl+S to saving the script.
+	focused: bool,
+	message: Option<String>,
+	/// Hash of the contents last written to each path by the editor.
+	ownWrites: HashMap<PathBuf, ContentHash>,
+}
+
+impl ScriptEditor {
+	/// Whether `contents` of `path` is what the editor wrote there last.
+	pub fn wrote(&self, path: &Path, contents: &str) -> bool {
+		self.ownWrites.get(path) == Some(&content_hash(contents))
+	}
+
+	/// Whether Ctrl+S should save the script rather than trigger other bindings.
+	pub fn has_focus(&self) -> bool {
+		self.open && self.focused
+	}
+
+	fn dirty(&self) -> bool {
+		self.buffer != self.saved
+	}
+
+	/// Replaces the script's in-memory contents with the buffer and regenerates from them.
+	fn apply(
+		&mut self,
+		path: &InternedPath,
+		uiState: &mut UiState,
+		thumbnails: &mut Thumbnails,
+		noiseGenRequests: &mut EventWriter<NoiseGenRequest>,
+	) {
+		uiState.scripts.insert(path.clone(), self.buffer.clone());
+		self.lastSeen = self.buffer.clone();
+		thumbnails.forget(path);
+		noiseGenRequests.send(NoiseGenRequest::AlgorithmChanged);
+	}
+
+	fn save(&mut self, path: &InternedPath) -> std::io::Result<()> {
+		std::fs::write(&path.path, &self.buffer)?;
+		self.ownWrites.insert(path.path.clone(), content_hash(&self.buffer));
+		self.saved = self.buffer.clone();
+		self.conflict = false;
+		Ok(())
+	}
+}
+
+pub fn script_editor(
+	mut eguiCtx: EguiContexts,
+	mut editor: ResMut<ScriptEditor>,
+	mut uiState: ResMut<UiState>,
+	mut thumbnails: ResMut<Thumbnails>,
+	keyboard: Res<ButtonInput<KeyCode>>,
+	mut noiseGenRequests: EventWriter<NoiseGenRequest>,
+) {
+	let editor = &mut *editor;
+	if !editor.open {
+		editor.focused = false;
+		return;
+	}
+	let Some(path) = uiState.selected().cloned() else {
+		editor.path = None;
+		editor.focused = false;
+		return;
+	};
+	let Some(contents) = uiState.scripts.get(&path) else {
+		return;
+	};
+
+	if editor.path.as_ref() != Some(&path) {
+		if editor.dirty() {
+			info!("discarding unsaved edits of {}", editor.path.as_ref().unwrap().display);
+		}
+		editor.path = Some(path.clone());
+		editor.buffer = contents.clone();
+		editor.saved = contents.clone();
+		editor.lastSeen = contents.clone();
+		editor.conflict = false;
+		editor.message = None;
+	} else if *contents != editor.lastSeen {
+		// changed on disk by someone else
+		if editor.dirty() {
+			editor.conflict = true;
+		} else {
+			editor.buffer = contents.clone();
+		}
+		editor.saved = contents.clone();
+		editor.lastSeen = contents.clone();
+	}
+
+	let mut apply = false;
+	let mut save = editor.focused &&
+		keyboard.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]) &&
+		keyboard.just_pressed(KeyCode::KeyS);
+	egui::SidePanel::right("scriptEditor")
+		.resizable(true)
+		.default_width(420.0)
+		.show(eguiCtx.ctx_mut(), |ui| {
+			ui.horizontal(|ui| {
+				let marker = if editor.dirty() { " •" } else { "" };
+				ui.strong(format!("{}{marker}", path.display));
+				save |= ui.button("Save").on_hover_text("Ctrl+S").clicked();
+				apply = ui
+					.button("Apply")
+					.on_hover_text("Regenerate from the editor's contents without saving")
+					.clicked();
+			});
+			if editor.conflict {
+				ui.colored_label(
+					egui::Color32::YELLOW,
+					"The file changed on disk, saving overwrites those changes",
+				);
+			}
+			if let Some(message) = &editor.message {
+				ui.label(message);
+			}
+			egui::ScrollArea::vertical().show(ui, |ui| {
+				let resp = ui.add(
+					egui::TextEdit::multiline(&mut editor.buffer)
+						.code_editor()
+						.desired_width(f32::INFINITY)
+						.desired_rows(32),
+				);
+				editor.focused = resp.has_focus();
+			});
+		});
+
+	if save {
+		editor.message = Some(match editor.save(&path) {
+			Ok(()) => {
+				apply = true;
+				format!("saved {}", path.path.display())
+			},
+			Err(err) => {
+				error!("saving {} failed: {err}", path.path.display());
+				format!("save failed: {err}")
+			},
+		});
+	}
+	if apply {
+		editor.apply(&path, &mut uiState, &mut thumbnails, &mut noiseGenRequests);
+	}
+}
@@ -0,0 +1,340 @@
+use std::time::Instant;
+
+use bevy::math::vec2;
+use bevy::prelude::*;
+use bevy::tasks::futures_lite::future;
+use bevy::tasks::{block_on, AsyncComputeTaskPool, Task};
+use bevy_egui::{egui, EguiContexts};
+
+use crate::{colormap, lod, noise_rng, show_output, textures, Heightmaps, NoiseOutput, UiState};
+
+/// Parameters of the droplet simulation. Heights are measured with the output's declared range
+/// spanning 1 and distances in samples, so the same parameters erode alike across scripts.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ErosionParams {
+	/// Seed of the droplets' starting positions; the same seed and parameters always erode the
+	/// same output the same way.
+	pub seed: u64,
+	pub droplets: usize,
+	/// Most steps a droplet takes before it's dropped.
+	pub lifetime: usize,
+	/// How much droplets keep their direction instead of turning downhill, from 0 to 1.
+	pub inertia: f32,
+	/// Sediment a droplet can carry per unit of speed, water and height it descended.
+	pub capacity: f32,
+	/// Capacity left on flat ground, so droplets keep eroding there.
+	pub minCapacity: f32,
+	/// Part of the sediment over capacity that's deposited each step.
+	pub deposition: f32,
+	/// Part of the free capacity that's taken from the ground each step.
+	pub erosion: f32,
+	/// Part of the water that evaporates each step.
+	pub evaporation: f32,
+	pub gravity: f32,
+	/// Samples around the droplet it erodes, weighted by distance. Larger radii carve wider and
+	/// smoother channels.
+	pub radius: usize,
+}
+
+impl Default for ErosionParams {
+	fn default() -> Self {
+		Self {
+			seed: 0,
+			droplets: 50_000,
+			lifetime: 30,
+			inertia: 0.05,
+			capacity: 4.0,
+			minCapacity: 0.01,
+			deposition: 0.3,
+			erosion: 0.3,
+			evaporation: 0.01,
+			gravity: 4.0,
+			radius: 3,
+		}
+	}
+}
+
+/// Erosion pass an output went through, with the output as generated so the pass can be run
+/// again with other parameters or reverted.
+#[derive(Clone)]
+pub struct Eroded {
+	pub params: ErosionParams,
+	pub original: NoiseOutput,
+}
+
+/// Height at `pos` and its gradient, interpolated bilinearly in the cell containing `pos`, which
+/// must be inside the grid.
+fn height_and_gradient(map: &[f32], width: usize, pos: Vec2) -> (f32, Vec2) {
+	let (x, y) = (pos.x as usize, pos.y as usize);
+	let (u, v) = (pos.x - x as f32, pos.y - y as f32);
+	let i = y * width + x;
+	let (nw, ne, sw, se) = (map[i], map[i + 1], map[i + width], map[i + width + 1]);
+	let gradient = vec2(
+		(ne - nw) * (1.0 - v) + (se - sw) * v,
+		(sw - nw) * (1.0 - u) + (se - ne) * u,
+	);
+	let height = nw * (1.0 - u) * (1.0 - v) + ne * u * (1.0 - v) + sw * (1.0 - u) * v + se * u * v;
+	(height, gradient)
+}
+
+/// Lets one droplet run downhill from `pos`, taking sediment where it speeds up and dropping it
+/// where it slows down or climbs.
+fn simulate(
+	map: &mut [f32],
+	(width, height): (usize, usize),
+	mut pos: Vec2,
+	params: &ErosionParams,
+) {
+	let last = vec2((width - 1) as f32, (height - 1) as f32);
+	let radius = params.radius as isize;
+	let (mut dir, mut speed, mut water, mut sediment) = (Vec2::ZERO, 1.0f32, 1.0f32, 0.0f32);
+	for _ in 0 .. params.lifetime {
+		let cell = pos.floor();
+		let (u, v) = (pos.x - cell.x, pos.y - cell.y);
+		let i = cell.y as usize * width + cell.x as usize;
+		let (here, gradient) = height_and_gradient(map, width, pos);
+		// NaN samples, from scripts failing on them, stop droplets instead of spreading
+		if !here.is_finite() || !gradient.is_finite() {
+			return;
+		}
+
+		dir = (dir * params.inertia - gradient * (1.0 - params.inertia)).normalize_or_zero();
+		if dir == Vec2::ZERO {
+			return;
+		}
+		pos += dir;
+		if !(pos.x >= 0.0 && pos.x < last.x && pos.y >= 0.0 && pos.y < last.y) {
+			return;
+		}
+		let (newHeight, _) = height_and_gradient(map, width, pos);
+		let deltaHeight = newHeight - here;
+		let capacity = (-deltaHeight * speed * water * params.capacity).max(params.minCapacity);
+
+		if sediment > capacity || deltaHeight > 0.0 {
+			// fills the pit it climbed out of, or drops what it can't carry
+			let amount = if deltaHeight > 0.0 {
+				deltaHeight.min(sediment)
+			} else {
+				(sediment - capacity) * params.deposition
+			};
+			sediment -= amount;
+			map[i] += amount * (1.0 - u) * (1.0 - v);
+			map[i + 1] += amount * u * (1.0 - v);
+			map[i + width] += amount * (1.0 - u) * v;
+			map[i + width + 1] += amount * u * v;
+		} else {
+			// never digs deeper than the height it descended, which would leave pits behind
+			let amount = ((capacity - sediment) * params.erosion).min(-deltaHeight);
+			let (x, y) = (cell.x as isize, cell.y as isize);
+			let brush = (-radius ..= radius)
+				.flat_map(|dy| (-radius ..= radius).map(move |dx| (dx, dy)))
+				.filter_map(|(dx, dy)| {
+					let distance = ((dx * dx + dy * dy) as f32).sqrt();
+					let (x, y) = (x + dx, y + dy);
+					let inside = x >= 0 && y >= 0 && x < width as isize && y < height as isize;
+					if !inside || distance > radius as f32 {
+						return None;
+					}
+					let weight = 1.0 - distance / (radius + 1) as f32;
+					Some((y as usize * width + x as usize, weight))
+				});
+			let total: f32 = brush.clone().map(|(_, weight)| weight).sum();
+			for (i, weight) in brush {
+				let taken = amount * weight / total;
+				map[i] -= taken;
+				sediment += taken;
+			}
+		}
+
+		speed = (speed * speed - deltaHeight * params.gravity).max(0.0).sqrt();
+		water *= 1.0 - params.evaporation;
+	}
+}
+
+/// Particle-based hydraulic erosion: `params.droplets` droplets, started at random positions, each
+/// run downhill over `samples`, carving channels where they speed up and depositing sediment in
+/// valleys. Deterministic for the same samples and parameters.
+pub fn erode(
+	samples: &mut [f32],
+	size: (usize, usize),
+	(min, max): (f32, f32),
+	params: &ErosionParams,
+) {
+	let (width, height) = size;
+	if width < 2 || height < 2 || !(max > min) {
+		return;
+	}
+	let scale = max - min;
+	let mut map: Vec<f32> = samples.iter().map(|&v| (v - min) / scale).collect();
+	let mut rng = noise_rng::Rng::new(params.seed);
+	let last = ((width - 1) as f64, (height - 1) as f64);
+	for _ in 0 .. params.droplets {
+		let start = vec2((rng.next_f64() * last.0) as f32, (rng.next_f64() * last.1) as f32);
+		// rounding can land on the last row or column, which has no cell to interpolate in
+		let start = start.min(vec2(last.0 as f32, last.1 as f32) - 1e-3);
+		simulate(&mut map, size, start, params);
+	}
+	for (v, h) in samples.iter_mut().zip(map) {
+		*v = h * scale + min;
+	}
+}
+
+/// Erosion window, opened from the toolbar. Erosion runs in the background on a copy of the
+/// output as generated, and replaces the live output when done.
+#[derive(Resource, Default)]
+pub struct ErosionPanel {
+	pub open: bool,
+	params: ErosionParams,
+	task: Option<(Task<NoiseOutput>, Instant)>,
+	message: Option<String>,
+}
+
+impl ErosionPanel {
+	/// Starts eroding the samples `output` was generated with, dropping any running pass.
+	fn start(&mut self, output: &NoiseOutput) {
+		let original = match &output.eroded {
+			Some(eroded) => eroded.original.clone(),
+			None => output.clone(),
+		};
+		let params = self.params;
+		let task = AsyncComputeTaskPool::get().spawn(async move {
+			let mut output = original.clone();
+			output.erode(params);
+			output.eroded = Some(Box::new(Eroded { params, original }));
+			output
+		});
+		self.task = Some((task, Instant::now()));
+		self.message = None;
+	}
+}
+
+fn params_ui(ui: &mut egui::Ui, params: &mut ErosionParams) {
+	egui::Grid::new("erosionParams").show(ui, |ui| {
+		ui.label("Seed");
+		ui.add(egui::DragValue::new(&mut params.seed));
+		ui.end_row();
+
+		ui.label("Droplets");
+		ui.add(egui::DragValue::new(&mut params.droplets).range(1 ..= 10_000_000).speed(100));
+		ui.end_row();
+
+		ui.label("Lifetime");
+		ui.add(egui::DragValue::new(&mut params.lifetime).range(1 ..= 1000))
+			.on_hover_text("Most steps each droplet takes");
+		ui.end_row();
+
+		ui.label("Radius");
+		ui.add(egui::DragValue::new(&mut params.radius).range(0 ..= 16))
+			.on_hover_text("Distance around a droplet it erodes, in samples");
+		ui.end_row();
+
+		let factors = [
+			("Inertia", &mut params.inertia, 1.0, "How much droplets resist turning downhill"),
+			("Capacity", &mut params.capacity, 100.0, "Sediment a fast, full droplet carries"),
+			("Min capacity", &mut params.minCapacity, 1.0, "Capacity left on flat ground"),
+			("Deposition", &mut params.deposition, 1.0, "Part of excess sediment dropped per step"),
+			("Erosion", &mut params.erosion, 1.0, "Part of the free capacity filled per step"),
+			("Evaporation", &mut params.evaporation, 1.0, "Part of the water lost per step"),
+			("Gravity", &mut params.gravity, 100.0, "How quickly droplets speed up downhill"),
+		];
+		for (label, value, max, hover) in factors {
+			ui.label(label);
+			ui.add(egui::DragValue::new(value).range(0.0 ..= max).speed(0.005))
+				.on_hover_text(hover);
+			ui.end_row();
+		}
+	});
+	if ui.button("Defaults").clicked() {
+		*params = ErosionParams {
+			seed: params.seed,
+			..default()
+		};
+	}
+}
+
+pub fn erosion_panel(
+	mut cmd: Commands,
+	mut eguiCtx: EguiContexts,
+	mut panel: ResMut<ErosionPanel>,
+	noiseOutput: Option<Res<NoiseOutput>>,
+	uiState: Res<UiState>,
+	heightmaps: Res<Heightmaps>,
+	mut terrain: Option<ResMut<lod::TerrainChunks>>,
+	mut images: ResMut<Assets<Image>>,
+	mut meshes: ResMut<Assets<Mesh>>,
+	textureLimits: Res<textures::TextureLimits>,
+	colormap: Res<colormap::ColormapSettings>,
+) {
+	let panel = &mut *panel;
+	let mut show = |output: NoiseOutput| {
+		show_output(
+			&output,
+			&uiState,
+			&heightmaps,
+			terrain.as_deref_mut(),
+			&mut images,
+			&mut meshes,
+			&textureLimits,
+			&colormap.colormap,
+		);
+		cmd.insert_resource(output);
+	};
+
+	// a new generation or another layer took over, the pass would overwrite it
+	if panel.task.is_some() && noiseOutput.as_ref().map_or(true, |output| output.is_changed()) {
+		panel.task = None;
+		panel.message = Some("the output changed, erosion cancelled".into());
+	}
+	let finished = panel
+		.task
+		.as_mut()
+		.and_then(|(task, started)| Some((block_on(future::poll_once(task))?, started.elapsed())));
+	if let Some((output, elapsed)) = finished {
+		panel.task = None;
+		info!("eroded the {}x{} output in {elapsed:?}", output.width, output.height);
+		panel.message = Some(format!("done in {:.2}s", elapsed.as_secs_f32()));
+		show(output);
+	}
+	if !panel.open {
+		return;
+	}
+
+	let mut open = true;
+	egui::Window::new("Erosion")
+		.open(&mut open)
+		.show(eguiCtx.ctx_mut(), |ui| {
+			egui::CollapsingHeader::new("Parameters")
+				.default_open(true)
+				.show(ui, |ui| params_ui(ui, &mut panel.params));
+
+			let eroded = noiseOutput.as_ref().and_then(|output| output.eroded.as_ref());
+			ui.horizontal(|ui| {
+				let running = panel.task.is_some();
+				let erode = ui
+					.add_enabled(noiseOutput.is_some() && !running, egui::Button::new("Erode"))
+					.on_hover_text("Erode the output as generated, replacing any earlier pass");
+				if let Some(output) = noiseOutput.as_ref().filter(|_| erode.clicked()) {
+					panel.start(output);
+				}
+				if ui.add_enabled(eroded.is_some(), egui::Button::new("Revert")).clicked() {
+					let original = eroded.unwrap().original.clone();
+					panel.task = None;
+					panel.message = None;
+					show(original);
+				}
+				if running {
+					ui.spinner();
+					if ui.button("Cancel").clicked() {
+						panel.task = None;
+					}
+				}
+			});
+			if let Some(Eroded { params, .. }) = eroded.map(|eroded| &**eroded) {
+				ui.label(format!("showing {} droplets of seed {}", params.droplets, params.seed));
+			}
+			if let Some(message) = &panel.message {
+				ui.label(message);
+			}
+		});
+	panel.open = open;
+}
@@ -0,0 +1,483 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{ensure, Context};
+use bevy::prelude::*;
+use bevy::tasks::futures_lite::future;
+use bevy::tasks::{block_on, AsyncComputeTaskPool, Task};
+use bevy_egui::{egui, EguiContexts};
+use serde::Serialize;
+
+use crate::editor::ScriptEditor;
+use crate::log_panel::{LogKind, SessionLog, Severity};
+use crate::{AResult, NoiseOutput};
+
+/// How samples are mapped to `[0, 1]` for export.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Normalization {
+	/// Maps the output's declared range to `[0, 1]` and clamps anything outside it, so exports of
+	/// different seeds share a scale.
+	#[default]
+	Clamp,
+	/// Stretches the lowest and highest sample to `[0, 1]`, using the full precision of the file.
+	Rescale,
+	/// Maps `[-1, 1]` to `[0, 1]` whatever the output's range, clamping anything outside it.
+	Unit,
+}
+
+impl Normalization {
+	pub const all: [Self; 3] = [Self::Clamp, Self::Rescale, Self::Unit];
+
+	pub fn label(self) -> &'static str {
+		match self {
+			Self::Clamp => "Clamp to range",
+			Self::Rescale => "Min/max rescale",
+			Self::Unit => "Fixed [-1, 1]",
+		}
+	}
+
+	/// Sample values mapped to 0 and 1, for an output declaring `range`.
+	pub fn mapped_range(self, samples: &[f32], range: (f32, f32)) -> (f32, f32) {
+		match self {
+			Self::Clamp => range,
+			Self::Rescale => sample_range(samples),
+			Self::Unit => (-1.0, 1.0),
+		}
+	}
+}
+
+/// Lowest and highest finite sample, infinite bounds the wrong way round if there's none.
+pub fn sample_range(samples: &[f32]) -> (f32, f32) {
+	samples
+		.iter()
+		.filter(|v| v.is_finite())
+		.fold((f32::INFINITY, f32::NEG_INFINITY), |(lo, hi), &v| (lo.min(v), hi.max(v)))
+}
+
+/// Maps `samples` to `[0, 1]`. Non-finite samples become 0.
+pub fn normalize(samples: &[f32], range: (f32, f32), mode: Normalization) -> Vec<f32> {
+	let (min, max) = mode.mapped_range(samples, range);
+	let scale = if max > min { 1.0 / (max - min) } else { 0.0 };
+	samples
+		.iter()
+		.map(|&v| if v.is_finite() { ((v - min) * scale).clamp(0.0, 1.0) } else { 0.0 })
+		.collect()
+}
+
+/// Writes normalized values as a 16-bit grayscale PNG.
+pub fn write_png(path: &Path, (width, height): (usize, usize), values: &[f32]) -> AResult<()> {
+	let pixels = values.iter().map(|&v| (v * u16::MAX as f32).round() as u16).collect();
+	let image = image::ImageBuffer::<image::Luma<u16>, Vec<u16>>::from_raw(
+		width as u32,
+		height as u32,
+		pixels,
+	)
+	.context("sample count doesn't match the size")?;
+	image
+		.save_with_format(path, image::ImageFormat::Png)
+		.with_context(|| format!("writing {}", path.display()))
+}
+
+/// Writes normalized values as a 32-bit float EXR, the value repeated in every channel as the
+/// encoder has no single channel layout.
+pub fn write_exr(path: &Path, (width, height): (usize, usize), values: &[f32]) -> AResult<()> {
+	let pixels = values.iter().flat_map(|&v| [v; 3]).collect();
+	let image = image::Rgb32FImage::from_raw(width as u32, height as u32, pixels)
+		.context("sample count doesn't match the size")?;
+	image
+		.save_with_format(path, image::ImageFormat::OpenExr)
+		.with_context(|| format!("writing {}", path.display()))
+}
+
+/// Bits per value of a RAW heightmap.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub enum RawBits {
+	/// Unsigned integers, 0 to 65535.
+	R16,
+	/// Floats in `[0, 1]`.
+	R32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub enum Endianness {
+	Little,
+	Big,
+}
+
+/// Order the rows of a RAW heightmap are written in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub enum RowOrder {
+	/// The first row of the 2D view first, as the PNG and EXR are.
+	TopDown,
+	BottomUp,
+}
+
+/// How a RAW heightmap is laid out, which the file itself doesn't tell.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub struct RawLayout {
+	pub bits: RawBits,
+	pub endianness: Endianness,
+	pub rowOrder: RowOrder,
+}
+
+impl RawLayout {
+	/// Extension engines expect for the bits per value.
+	fn extension(self) -> &'static str {
+		match self.bits {
+			RawBits::R16 => "r16",
+			RawBits::R32 => "r32",
+		}
+	}
+}
+
+/// Engines whose heightmap import the export can be set up for, see `HeightmapExport::apply`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EnginePreset {
+	Unity,
+	Unreal,
+}
+
+impl EnginePreset {
+	pub const all: [Self; 2] = [Self::Unity, Self::Unreal];
+
+	pub fn label(self) -> &'static str {
+		match self {
+			Self::Unity => "Unity",
+			Self::Unreal => "Unreal",
+		}
+	}
+
+	fn layout(self) -> RawLayout {
+		RawLayout {
+			bits: RawBits::R16,
+			endianness: Endianness::Little,
+			// Unity's terrain starts at its bottom row, Unreal's landscape at its top one
+			rowOrder: match self {
+				Self::Unity => RowOrder::BottomUp,
+				Self::Unreal => RowOrder::TopDown,
+			},
+		}
+	}
+}
+
+/// Sizes engines take heightmaps in, `2ⁿ + 1` so a power of two cells span the terrain.
+pub const engineDiameters: [usize; 6] = [129, 257, 513, 1025, 2049, 4097];
+
+/// Normalized values laid out as a RAW heightmap, rows of `width` values.
+pub fn encode_raw(width: usize, values: &[f32], layout: RawLayout) -> Vec<u8> {
+	let rows = values.chunks_exact(width.max(1));
+	let rows: Vec<_> = match layout.rowOrder {
+		RowOrder::TopDown => rows.collect(),
+		RowOrder::BottomUp => rows.rev().collect(),
+	};
+	let little = layout.endianness == Endianness::Little;
+	let mut bytes = Vec::with_capacity(values.len() * 4);
+	for &v in rows.into_iter().flatten() {
+		match layout.bits {
+			RawBits::R16 => {
+				let v = (v * u16::MAX as f32).round() as u16;
+				bytes.extend(if little { v.to_le_bytes() } else { v.to_be_bytes() });
+			},
+			RawBits::R32 => bytes.extend(if little { v.to_le_bytes() } else { v.to_be_bytes() }),
+		}
+	}
+	bytes
+}
+
+pub fn write_raw(
+	path: &Path,
+	(width, height): (usize, usize),
+	values: &[f32],
+	layout: RawLayout,
+) -> AResult<()> {
+	ensure!(values.len() == width * height, "sample count doesn't match the size");
+	std::fs::write(path, encode_raw(width, values, layout))
+		.with_context(|| format!("writing {}", path.display()))
+}
+
+/// Sidecar describing an export, so an engine's height scale can be set to match.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct HeightmapInfo {
+	pub width: usize,
+	pub height: usize,
+	/// Layout of the RAW file, if one was written.
+	pub raw: Option<RawLayout>,
+	pub normalization: &'static str,
+	/// Sample values mapped to black and white, 0 and 65535 in a RAW16.
+	pub mappedRange: (f32, f32),
+	/// Lowest and highest sample actually exported.
+	pub sampleRange: (f32, f32),
+}
+
+/// Heightmap export window, also triggered by Ctrl+S outside the script editor. Files are written in
+/// the background.
+#[derive(Resource)]
+pub struct HeightmapExport {
+	pub open: bool,
+	/// Output path without extension, each format appends its own.
+	path: String,
+	png: bool,
+	exr: bool,
+	raw: bool,
+	rawLayout: RawLayout,
+	/// Writes a `HeightmapInfo` JSON next to the heightmaps.
+	sidecar: bool,
+	/// Diameter the output is resampled to, `None` to export it as it is. Non-square outputs are
+	/// stretched to the square.
+	resample: Option<usize>,
+	normalization: Normalization,
+	task: Option<Task<AResult<Vec<PathBuf>>>>,
+	message: Option<String>,
+}
+
+impl Default for HeightmapExport {
+	fn default() -> Self {
+		Self {
+			open: false,
+			path: "heightmap".into(),
+			png: true,
+			exr: false,
+			raw: false,
+			rawLayout: EnginePreset::Unreal.layout(),
+			sidecar: false,
+			resample: None,
+			normalization: default(),
+			task: None,
+			message: None,
+		}
+	}
+}
+
+impl HeightmapExport {
+	/// Sets up a RAW16 export at the size `preset` takes, keeping the chosen normalization.
+	fn apply(&mut self, preset: EnginePreset) {
+		self.raw = true;
+		self.rawLayout = preset.layout();
+		self.sidecar = true;
+		self.resample.get_or_insert(1025);
+	}
+
+	/// Starts writing `output` in every selected format, unless an export is still running.
+	fn start(&mut self, output: Option<&NoiseOutput>) -> AResult<()> {
+		ensure!(self.task.is_none(), "an export is already running");
+		let output = output.context("nothing has been generated yet")?;
+		ensure!(self.png || self.exr || self.raw, "no format selected");
+		ensure!(!self.path.trim().is_empty(), "no output path");
+
+		let (samples, size) = match self.resample {
+			Some(diameter) if (diameter, diameter) != output.size() => {
+				(output.resampled(diameter, diameter), (diameter, diameter))
+			},
+			_ => (output.samples.clone(), output.size()),
+		};
+		let range = output.range;
+		let (png, exr, normalization) = (self.png, self.exr, self.normalization);
+		let raw = self.raw.then_some(self.rawLayout);
+		let sidecar = self.sidecar;
+		let path = self.path.clone();
+		self.task = Some(AsyncComputeTaskPool::get().spawn(async move {
+			let values = normalize(&samples, range, normalization);
+			let mut written = vec![];
+			if png {
+				let path = PathBuf::from(format!("{path}.png"));
+				write_png(&path, size, &values)?;
+				written.push(path);
+			}
+			if exr {
+				let path = PathBuf::from(format!("{path}.exr"));
+				write_exr(&path, size, &values)?;
+				written.push(path);
+			}
+			if let Some(layout) = raw {
+				let path = PathBuf::from(format!("{path}.{}", layout.extension()));
+				write_raw(&path, size, &values, layout)?;
+				written.push(path);
+			}
+			if sidecar {
+				let info = HeightmapInfo {
+					width: size.0,
+					height: size.1,
+					raw,
+					normalization: normalization.label(),
+					mappedRange: normalization.mapped_range(&samples, range),
+					sampleRange: sample_range(&samples),
+				};
+				let path = PathBuf::from(format!("{path}.json"));
+				let json = serde_json::to_string_pretty(&info)?;
+				std::fs::write(&path, json).with_context(|| format!("writing {}", path.display()))?;
+				written.push(path);
+			}
+			Ok(written)
+		}));
+		Ok(())
+	}
+}
+
+pub fn heightmap_export(
+	mut eguiCtx: EguiContexts,
+	mut export: ResMut<HeightmapExport>,
+	noiseOutput: Option<Res<NoiseOutput>>,
+	keyboard: Res<ButtonInput<KeyCode>>,
+	editor: Res<ScriptEditor>,
+	log: Res<SessionLog>,
+) {
+	let export = &mut *export;
+	// while editing a script the shortcut saves it instead
+	if keyboard.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]) &&
+		keyboard.just_pressed(KeyCode::KeyS) &&
+		!editor.has_focus()
+	{
+		export.open = true;
+		if let Err(err) = export.start(noiseOutput.as_deref()) {
+			export.message = Some(format!("export failed: {err:#}"));
+		}
+	}
+
+	let finished = export
+		.task
+		.as_mut()
+		.and_then(|task| block_on(future::poll_once(task)));
+	if let Some(res) = finished {
+		export.task = None;
+		export.message = Some(match res {
+			Ok(paths) => {
+				let paths: Vec<_> = paths.iter().map(|path| path.display().to_string()).collect();
+				let message = format!("exported heightmap to {}", paths.join(", "));
+				log.record(Severity::Info, LogKind::Export, None, message);
+				format!("wrote {}", paths.join(", "))
+			},
+			Err(err) => {
+				let message = format!("heightmap export failed: {err:#}");
+				log.record(Severity::Error, LogKind::Export, None, message);
+				format!("export failed: {err:#}")
+			},
+		});
+	}
+	if !export.open {
+		return;
+	}
+
+	let mut open = true;
+	egui::Window::new("Export heightmap")
+		.open(&mut open)
+		.show(eguiCtx.ctx_mut(), |ui| {
+			egui::Grid::new("heightmapExport").show(ui, |ui| {
+				ui.label("Path");
+				ui.text_edit_singleline(&mut export.path)
+					.on_hover_text("Without extension, each format appends its own");
+				ui.end_row();
+
+				ui.label("Preset");
+				ui.horizontal(|ui| {
+					for preset in EnginePreset::all {
+						if ui.button(preset.label()).clicked() {
+							export.apply(preset);
+						}
+					}
+				})
+				.response
+				.on_hover_text(
+					"RAW16 in the row order the engine's import expects, with a sidecar, \
+					 resampled to 1025² unless a size is already set",
+				);
+				ui.end_row();
+
+				ui.label("Formats");
+				ui.horizontal(|ui| {
+					ui.checkbox(&mut export.png, "16-bit PNG");
+					ui.checkbox(&mut export.exr, "32-bit float EXR");
+					ui.checkbox(&mut export.raw, "RAW");
+					ui.checkbox(&mut export.sidecar, "JSON sidecar").on_hover_text(
+						"Size, layout and the sample values mapped to black and white, to set the \
+						 engine's height scale from",
+					);
+				});
+				ui.end_row();
+
+				if export.raw {
+					ui.label("RAW");
+					ui.horizontal(|ui| raw_layout_ui(ui, &mut export.rawLayout));
+					ui.end_row();
+				}
+
+				ui.label("Size");
+				ui.horizontal(|ui| resample_ui(ui, &mut export.resample, noiseOutput.as_deref()));
+				ui.end_row();
+
+				ui.label("Normalization");
+				egui::ComboBox::from_id_source("exportNormalization")
+					.selected_text(export.normalization.label())
+					.show_ui(ui, |ui| {
+						for mode in Normalization::all {
+							ui.selectable_value(&mut export.normalization, mode, mode.label());
+						}
+					})
+					.response
+					.on_hover_text(
+						"Clamp maps the script's declared range to black and white, rescale \
+						 stretches the lowest and highest sample, fixed maps -1 and 1 whatever \
+						 the range",
+					);
+				ui.end_row();
+			});
+
+			ui.horizontal(|ui| {
+				let ready = noiseOutput.is_some() && export.task.is_none();
+				if ui
+					.add_enabled(ready, egui::Button::new("Export"))
+					.on_hover_text("Ctrl+S")
+					.clicked()
+				{
+					if let Err(err) = export.start(noiseOutput.as_deref()) {
+						export.message = Some(format!("export failed: {err:#}"));
+					}
+				}
+				if export.task.is_some() {
+					ui.spinner();
+				}
+			});
+			if let Some(message) = &export.message {
+				ui.label(message);
+			}
+		});
+	export.open = open;
+}
+
+fn raw_layout_ui(ui: &mut egui::Ui, layout: &mut RawLayout) {
+	ui.selectable_value(&mut layout.bits, RawBits::R16, "16-bit")
+		.on_hover_text("Unsigned integers");
+	ui.selectable_value(&mut layout.bits, RawBits::R32, "32-bit float");
+	ui.separator();
+	ui.selectable_value(&mut layout.endianness, Endianness::Little, "Little endian");
+	ui.selectable_value(&mut layout.endianness, Endianness::Big, "Big endian");
+	ui.separator();
+	ui.selectable_value(&mut layout.rowOrder, RowOrder::TopDown, "Top row first")
+		.on_hover_text("The 2D view's top row first, like the PNG");
+	ui.selectable_value(&mut layout.rowOrder, RowOrder::BottomUp, "Bottom row first");
+}
+
+fn resample_ui(ui: &mut egui::Ui, resample: &mut Option<usize>, output: Option<&NoiseOutput>) {
+	let (width, height) = output.map_or((0, 0), NoiseOutput::size);
+	let mut enabled = resample.is_some();
+	ui.checkbox(&mut enabled, "Resample to").on_hover_text(
+		"Bilinearly interpolated, the corner samples staying in the corners. Non-square outputs \
+		 are stretched to the square",
+	);
+	let mut diameter = resample.unwrap_or(width.max(height).max(2));
+	ui.add_enabled(enabled, egui::DragValue::new(&mut diameter).range(2 ..= 8193));
+	ui.add_enabled_ui(enabled, |ui| {
+		ui.menu_button("▾", |ui| {
+			for preset in engineDiameters {
+				if ui.selectable_label(diameter == preset, preset.to_string()).clicked() {
+					diameter = preset;
+					ui.close_menu();
+				}
+			}
+		})
+		.response
+		.on_hover_text("2ⁿ + 1 sizes engines take");
+	});
+	*resample = enabled.then_some(diameter);
+	if !enabled {
+		ui.weak(format!("{width}×{height}, as generated"));
+	}
+}
@@ -0,0 +1,57 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{ensure, Context};
+use bevy::prelude::*;
+use bevy_egui::egui;
+
+pub use noisebench_core::fnl::{import, import_file};
+
+use crate::AResult;
+
+/// "Import preset…" dialog. Importing writes a script that loads the preset, so it shows up in
+/// the script list and can be edited from there.
+#[derive(Resource, Default)]
+pub struct PresetImport {
+	pub open: bool,
+	path: String,
+	message: Option<String>,
+}
+
+fn write_import_script(path: &Path) -> AResult<(PathBuf, Vec<String>)> {
+	let (_, unsupported) = import_file(path)?;
+	let stem = path
+		.file_stem()
+		.context("preset path has no file name")?
+		.to_string_lossy();
+	let script = Path::new("assets/scripts").join(format!("{stem}.lua"));
+	ensure!(!script.exists(), "{} already exists", script.display());
+	let mut code = format!("-- imported from {}\n", path.display());
+	for setting in &unsupported {
+		code += &format!("-- not representable: {setting}\n");
+	}
+	code += &format!("return Noise.fromFNL({:?})\n", path.display().to_string());
+	std::fs::write(&script, code).with_context(|| format!("writing {}", script.display()))?;
+	Ok((script, unsupported))
+}
+
+pub fn import_ui(ui: &mut egui::Ui, import: &mut PresetImport) {
+	ui.horizontal(|ui| {
+		ui.label("Preset");
+		ui.text_edit_singleline(&mut import.path)
+			.on_hover_text("FastNoiseLite preset JSON");
+	});
+	if ui.button("Import").clicked() {
+		import.message = Some(match write_import_script(Path::new(&import.path)) {
+			Ok((script, unsupported)) if unsupported.is_empty() => format!("wrote {}", script.display()),
+			Ok((script, unsupported)) => format!(
+				"wrote {}, not representable: {}",
+				script.display(),
+				unsupported.join(", ")
+			),
+			Err(err) => format!("import failed: {err:#}"),
+		});
+	}
+	if let Some(message) = &import.message {
+		ui.label(message);
+	}
+}
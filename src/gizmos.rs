@@ -0,0 +1,239 @@
+use bevy::color::palettes::css;
+use bevy::prelude::*;
+use bevy::render::view::RenderLayers;
+use bevy_egui::egui;
+
+use crate::theme::Theme;
+use crate::{
+	lighting, lod, mesh_allowed, scene, NoiseOutput, SelectedTab, Tab, TerrainScale, UiState,
+};
+
+/// Most grid lines drawn per axis, regardless of the configured spacing.
+const maxGridLines: usize = 64;
+/// Most heights drawn by the height grid, and most cells per axis of each.
+const maxHeightGrids: usize = 16;
+/// Most points per draped line, so large heightmaps don't flood the gizmo buffers.
+const maxLinePoints: usize = 256;
+
+/// Debug drawing toggles, edited from the View menu.
+#[derive(Resource)]
+pub struct GizmoSettings {
+	pub axes: bool,
+	/// Box around the terrain, from its lowest to its highest point at the current height scale.
+	pub bounds: bool,
+	/// Edge of the evaluation domain, draped on the terrain.
+	pub domain: bool,
+	/// Every `gridStep`-th sample row and column, draped on the terrain.
+	pub grid: bool,
+	pub gridStep: usize,
+	pub light: bool,
+}
+
+impl Default for GizmoSettings {
+	fn default() -> Self {
+		Self {
+			axes: true,
+			bounds: false,
+			domain: false,
+			grid: false,
+			gridStep: 16,
+			light: false,
+		}
+	}
+}
+
+/// Gizmos only the 2D view's camera draws, so they stay out of the 3D pane next to it.
+#[derive(Default, Reflect, GizmoConfigGroup)]
+pub struct View2dGizmos;
+
+/// Gizmos only the 3D view's camera draws.
+#[derive(Default, Reflect, GizmoConfigGroup)]
+pub struct View3dGizmos;
+
+/// Render layers of the view groups' gizmos. Everything else is on layer 0, which both cameras
+/// draw.
+const layer2d: usize = 1;
+const layer3d: usize = 2;
+
+pub fn add_view_groups(app: &mut App) {
+	let onLayer = |layer| GizmoConfig {
+		render_layers: RenderLayers::layer(layer),
+		..default()
+	};
+	app.insert_gizmo_config(View2dGizmos, onLayer(layer2d));
+	app.insert_gizmo_config(View3dGizmos, onLayer(layer3d));
+}
+
+/// Render layers of the camera of `view`, `Tab::D2` or `Tab::D3`: layer 0 and its own gizmos.
+pub fn view_layers(view: Tab) -> RenderLayers {
+	let layer = if view == Tab::D2 { layer2d } else { layer3d };
+	RenderLayers::layer(0).with(layer)
+}
+
+pub fn gizmo_ui(ui: &mut egui::Ui, settings: &mut GizmoSettings) {
+	ui.checkbox(&mut settings.axes, "World axes");
+	ui.checkbox(&mut settings.bounds, "Bounding box");
+	ui.checkbox(&mut settings.domain, "Domain outline");
+	ui.horizontal(|ui| {
+		ui.checkbox(&mut settings.grid, "Sample grid");
+		ui.add_enabled(
+			settings.grid,
+			egui::DragValue::new(&mut settings.gridStep)
+				.range(1 ..= 1024)
+				.prefix("every "),
+		);
+	});
+	ui.checkbox(&mut settings.light, "Light direction");
+}
+
+pub fn to_color(color: egui::Color32) -> Color {
+	let [r, g, b, a] = color.to_srgba_unmultiplied();
+	Color::srgba_u8(r, g, b, a)
+}
+
+/// Terrain surface position of sample `(x, y)`, matching `NoiseOutput::update_mesh`.
+fn surface(output: &NoiseOutput, scale: TerrainScale, x: usize, y: usize) -> Vec3 {
+	let v = output.normalized(output.samples[y * output.width + x]);
+	let ground = scale.to_world(output.size(), vec2(x as f32, y as f32));
+	vec3(ground.x, v * scale.height, ground.y)
+}
+
+/// Line along samples `from + i * step` for `i` in `0 .. count`, lifted slightly so it doesn't
+/// z-fight with the surface.
+fn draped_line(
+	gizmos: &mut Gizmos<View3dGizmos>,
+	output: &NoiseOutput,
+	scale: TerrainScale,
+	from: UVec2,
+	step: UVec2,
+	count: usize,
+	color: Color,
+) {
+	let stride = count.div_ceil(maxLinePoints).max(1);
+	let lift = Vec3::Y * 0.01 * scale.height.abs().max(1.0);
+	let points = (0 .. count)
+		.step_by(stride)
+		.chain(std::iter::once(count - 1))
+		.map(|i| {
+			let pos = from + step * i as u32;
+			surface(output, scale, pos.x as usize, pos.y as usize) + lift
+		});
+	gizmos.linestrip(points, color);
+}
+
+pub fn debug_gizmos(
+	mut sharedGizmos: Gizmos,
+	mut gizmos: Gizmos<View3dGizmos>,
+	settings: Res<GizmoSettings>,
+	theme: Res<Theme>,
+	selectedTab: Res<SelectedTab>,
+	uiState: Res<UiState>,
+	terrain: Option<Res<lod::TerrainChunks>>,
+	noiseOutput: Option<Res<NoiseOutput>>,
+	light: Query<&GlobalTransform, (With<DirectionalLight>, Without<lighting::FillLight>)>,
+) {
+	// in both views
+	if settings.axes {
+		sharedGizmos.line(Vec3::ZERO, Vec3::X * 5.0, css::RED);
+		sharedGizmos.line(Vec3::ZERO, Vec3::Y * 5.0, css::GREEN);
+		sharedGizmos.line(Vec3::ZERO, Vec3::Z * 5.0, css::BLUE);
+	}
+	if !selectedTab.shows(Tab::D3) {
+		return;
+	}
+
+	let accent = to_color(theme.accent());
+	let muted = accent.with_alpha(0.5);
+	let scale = uiState.terrain_scale();
+	let height = scale.height;
+	if settings.light {
+		let length = scale.extent.max(8.0) / 4.0;
+		for transform in &light {
+			let start = vec3(0.0, height.abs() + length, 0.0);
+			gizmos.arrow(start, start + *transform.forward() * length, css::YELLOW);
+		}
+	}
+
+	// draped gizmos need the mesh to exist, and to match the current output
+	let Some(output) = noiseOutput.filter(|output| {
+		let (width, height) = output.size();
+		terrain.is_some() && mesh_allowed(output.size(), &uiState) && width > 1 && height > 1
+	}) else {
+		return;
+	};
+	let (width, depth) = output.size();
+	let half = scale.to_world(output.size(), vec2((width - 1) as f32, (depth - 1) as f32));
+
+	if settings.bounds {
+		let (lo, hi) = output
+			.samples
+			.iter()
+			.map(|&v| output.normalized(v) * height)
+			.fold((f32::INFINITY, f32::NEG_INFINITY), |(lo, hi), v| (lo.min(v), hi.max(v)));
+		if lo <= hi {
+			let min = vec3(-half.x, lo, -half.y);
+			let max = vec3(half.x, hi, half.y);
+			let transform = Transform::from_translation((min + max) / 2.0).with_scale(max - min);
+			gizmos.cuboid(transform, accent);
+		}
+	}
+
+	if settings.domain {
+		let last = UVec2::new(width as u32 - 1, depth as u32 - 1);
+		for (from, step, count) in [
+			(UVec2::ZERO, UVec2::X, width),
+			(UVec2::new(last.x, 0), UVec2::Y, depth),
+			(UVec2::new(0, last.y), UVec2::X, width),
+			(UVec2::ZERO, UVec2::Y, depth),
+		] {
+			draped_line(&mut gizmos, &output, scale, from, step, count, accent);
+		}
+	}
+
+	if settings.grid {
+		let step = settings.gridStep.max(width.max(depth).div_ceil(maxGridLines)).max(1);
+		for x in (0 .. width).step_by(step) {
+			let from = UVec2::new(x as u32, 0);
+			draped_line(&mut gizmos, &output, scale, from, UVec2::Y, depth, muted);
+		}
+		for y in (0 .. depth).step_by(step) {
+			let from = UVec2::new(0, y as u32);
+			draped_line(&mut gizmos, &output, scale, from, UVec2::X, width, muted);
+		}
+	}
+}
+
+/// Draws `ViewSettings::heightGrid`: a horizontal grid over the terrain at every integer height
+/// it can reach at the current height scale, thinned out to at most `maxHeightGrids` heights.
+pub fn height_grid(
+	mut gizmos: Gizmos<View3dGizmos>,
+	settings: Res<scene::ViewSettings>,
+	theme: Res<Theme>,
+	selectedTab: Res<SelectedTab>,
+	uiState: Res<UiState>,
+	terrain: Option<Res<lod::TerrainChunks>>,
+) {
+	if !settings.heightGrid || !selectedTab.shows(Tab::D3) || terrain.is_none() {
+		return;
+	}
+	let top = uiState.height.abs().floor() as usize;
+	let step = (2 * top + 1).div_ceil(maxHeightGrids).max(1);
+	let (width, depth) = uiState.output_size();
+	let longer = width.max(depth).saturating_sub(1).max(1);
+	let cells = longer.clamp(1, maxHeightGrids);
+	let spacing = Vec2::splat(uiState.extent / cells as f32);
+	// the shorter side gets as many cells as its share of the longer one
+	let count = |side: usize| {
+		((side.saturating_sub(1) * cells) as f32 / longer as f32).round().max(1.0) as u32
+	};
+	let counts = UVec2::new(count(width), count(depth));
+	// the grid gizmo lies in the XY plane
+	let rotation = Quat::from_rotation_x(std::f32::consts::FRAC_PI_2);
+	let accent = to_color(theme.accent());
+	let levels = (step ..= top).step_by(step).flat_map(|level| [level as f32, -(level as f32)]);
+	for level in std::iter::once(0.0).chain(levels) {
+		let color = accent.with_alpha(if level == 0.0 { 0.7 } else { 0.3 });
+		let position = vec3(0.0, level, 0.0);
+		gizmos.grid(position, rotation, counts, spacing, color);
+	}
+}
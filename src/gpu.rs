@@ -0,0 +1,857 @@
+use std::fmt::Write as _;
+use std::sync::{Mutex, OnceLock};
+
+use bevy::math::DVec2;
+use bevy::prelude::*;
+use bevy::tasks::block_on;
+use bevy::utils::HashMap;
+
+use crate::lua::{self, Noise, OctaveMode};
+use crate::{Coords, NoiseOutput, SampleGrid};
+
+/// Side of the square workgroups the compute shader runs in.
+const workgroupSize: u32 = 8;
+
+/// Where generations evaluate their tree.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Backend {
+	/// Evaluation on the async compute pool, at the selected `lua::Precision`.
+	#[default]
+	Cpu,
+	/// The tree translated to a WGSL compute shader by `translate`, always in `f32`. Trees with
+	/// nodes it can't translate fall back to the CPU.
+	Gpu,
+}
+
+impl Backend {
+	pub const all: [Backend; 2] = [Backend::Cpu, Backend::Gpu];
+
+	pub fn label(self) -> &'static str {
+		match self {
+			Backend::Cpu => "CPU",
+			Backend::Gpu => "GPU",
+		}
+	}
+}
+
+/// Headless device generations run their shaders on, separate from the renderer's so a slow
+/// generation never stalls a frame.
+pub struct GpuContext {
+	device: wgpu::Device,
+	queue: wgpu::Queue,
+	/// Error scopes are a stack shared by the whole device, so generations take turns.
+	lock: Mutex<()>,
+}
+
+/// The device generations share, requested on first use. Errors tell why there's none.
+pub fn context() -> Result<&'static GpuContext, String> {
+	static context: OnceLock<Result<GpuContext, String>> = OnceLock::new();
+	let res = context.get_or_init(|| {
+		let instance = wgpu::Instance::new(default());
+		let adapter = block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+			power_preference: wgpu::PowerPreference::HighPerformance,
+			..default()
+		}))
+		.ok_or("no GPU adapter")?;
+		let info = adapter.get_info();
+		let descriptor = wgpu::DeviceDescriptor {
+			label: Some("noise generation"),
+			required_limits: adapter.limits(),
+			..default()
+		};
+		let (device, queue) = block_on(adapter.request_device(&descriptor, None))
+			.map_err(|err| format!("GPU device request failed: {err}"))?;
+		// errors outside a scope would panic by default
+		device.on_uncaptured_error(Box::new(|err| error!("GPU error: {err}")));
+		info!("GPU generation on {} ({:?})", info.name, info.backend);
+		Ok(GpuContext {
+			device,
+			queue,
+			lock: Mutex::new(()),
+		})
+	});
+	res.as_ref().map_err(Clone::clone)
+}
+
+/// Evaluates `noise` over `grid` at depth `z` on the GPU, like the CPU does. Errors tell why the
+/// generation has to fall back to the CPU.
+pub fn generate_samples(
+	noise: &Noise,
+	grid: &SampleGrid,
+	z: f64,
+	ctx: &lua::EvalContext,
+) -> Result<NoiseOutput, String> {
+	let (width, height) = (grid.width, grid.height);
+	let functions = translate(noise)?;
+	let gpu = context()?;
+	let maxDimension = gpu.device.limits().max_texture_dimension_2d as usize;
+	if width.max(height) > maxDimension {
+		return Err(format!("size is above the GPU's limit of {maxDimension}"));
+	}
+	let source = compute_shader(&functions, grid, z as f32, ctx.time as f32);
+	let samples = run(gpu, &source, width as u32, height as u32)?;
+
+	let mut output = NoiseOutput::sized(width, height);
+	output.samples = samples;
+	output.range = noise.output_range();
+	output.supersampling = grid.supersampling;
+	output.domain = grid.domain;
+	output.coords = grid.coords;
+	Ok(output)
+}
+
+/// WGSL functions evaluating `noise`, `node0` being the root. Errors name the first node kind
+/// that has no translation: Lua functions, images, and sources whose reference implementations use
+/// 64 bit floats or too many hash rounds to port exactly.
+pub fn translate(noise: &Noise) -> Result<String, String> {
+	let mut translator = Translator {
+		functions: vec![],
+		cached: HashMap::new(),
+	};
+	translator.node(noise)?;
+	Ok(translator.functions.concat())
+}
+
+struct Translator {
+	functions: Vec<String>,
+	/// Function of each `Cached` subtree translated so far, by node ID. Copies call the same
+	/// function, though the GPU evaluates it for each of them.
+	cached: HashMap<u64, usize>,
+}
+
+impl Translator {
+	/// Adds the function for `noise` and its children, returning the call of it at `pos`.
+	fn call(&mut self, noise: &Noise, pos: &str) -> Result<String, String> {
+		Ok(format!("node{}({pos})", self.node(noise)?))
+	}
+
+	fn node(&mut self, noise: &Noise) -> Result<usize, String> {
+		let id = self.functions.len();
+		self.functions.push(String::new());
+		let body = self.body(noise)?;
+		self.functions[id] = format!("fn node{id}(p: vec3<f32>) -> f32 {{\n{body}}}\n\n");
+		Ok(id)
+	}
+
+	fn body(&mut self, noise: &Noise) -> Result<String, String> {
+		use Noise::*;
+		let ret = |expr: String| Ok(format!("\treturn {expr};\n"));
+		match noise {
+			&Const(v) => ret(lit(v)),
+			GradientX => ret("p.x".into()),
+			GradientY => ret("p.y".into()),
+			Time => ret("animationTime".into()),
+			&Radial(center) => ret(format!("distance(p.xy, {})", vec2_lit(center))),
+			&Checker(size) => Ok(format!(
+				"\tlet cell = floor(p.xy / {});\n\
+				 \treturn select(-1.0, 1.0, rem_euclid(cell.x + cell.y, 2.0) == 0.0);\n",
+				lit(size)
+			)),
+			&Simplex(seed) => ret(format!("simplex_smooth({}, p.xy)", u64_lit(seed as u64))),
+			&SimplexFast(seed) => ret(format!("simplex_fast({}, p.xy)", u64_lit(seed as u64))),
+			&Octaves {
+				ref func,
+				octaves,
+				ampScale,
+				freqScale,
+				mode,
+				variation,
+				divisor,
+			} => {
+				let (value, weightUpdate) = match mode {
+					OctaveMode::Fbm => ("v".to_string(), None),
+					OctaveMode::Ridged { gain } => (
+						"(1.0 - abs(v) * 2.0) * weight".into(),
+						gain.map(|gain| {
+							let gain = lit(gain);
+							format!("\t\tweight = clamp((1.0 - abs(v)) * {gain}, 0.0, 1.0);\n")
+						}),
+					),
+					OctaveMode::Billow => ("abs(v) * 2.0 - 1.0".into(), None),
+				};
+				let weightUpdate = weightUpdate.unwrap_or_default();
+				if !variation.is_none() {
+					let octave = Octave {
+						func,
+						ampScale,
+						freqScale,
+						variation,
+						divisor,
+					};
+					return self.varied_octaves(octave, octaves, &value, &weightUpdate);
+				}
+				let octave = self.call(func, "p * freq")?;
+				// z follows the x multiplier, like on the CPU
+				let freqScale = freqScale.extend(freqScale.x);
+				Ok(format!(
+					"\tvar res = 0.0;\n\
+					 \tvar amp = 1.0;\n\
+					 \tvar freq = vec3<f32>(1.0);\n\
+					 \tvar weight = 1.0;\n\
+					 \tfor (var i = 0u; i < {octaves}u; i++) {{\n\
+					 \t\tif abs(amp) < {} {{ break; }}\n\
+					 \t\tlet v = {octave};\n\
+					 \t\tres += amp * {value};\n\
+					 {}\
+					 \t\tamp *= {};\n\
+					 \t\tfreq *= vec3<f32>({}, {}, {});\n\
+					 \t}}\n\
+					 \treturn res / {};\n",
+					lit(lua::minOctaveAmp),
+					weightUpdate,
+					lit(ampScale),
+					lit(freqScale.x),
+					lit(freqScale.y),
+					lit(freqScale.z),
+					lit(divisor),
+				))
+			},
+
+			Add(l, r) => self.binary(l, r, |l, r| format!("{l} + {r}")),
+			Sub(l, r) => self.binary(l, r, |l, r| format!("{l} - {r}")),
+			Mul(l, r) => self.binary(l, r, |l, r| format!("{l} * {r}")),
+			MulSkipZero(l, r) => {
+				let (l, r) = (self.call(l, "p")?, self.call(r, "p")?);
+				Ok(format!("\tlet l = {l};\n\tif l == 0.0 {{ return l; }}\n\treturn l * {r};\n"))
+			},
+			Div(l, r) => self.binary(l, r, |l, r| format!("{l} / {r}")),
+			Pow(l, r) => self.binary(l, r, |l, r| format!("powf({l}, {r})")),
+			// WGSL's float remainder truncates like Rust's
+			Rem(l, r) => self.binary(l, r, |l, r| format!("{l} % {r}")),
+			RemEuclid(l, r) => self.binary(l, r, |l, r| format!("rem_euclid({l}, {r})")),
+			SignedPow(l, r) => {
+				let (l, r) = (self.call(l, "p")?, self.call(r, "p")?);
+				Ok(format!("\tlet l = {l};\n\treturn copysign(powf(l, {r}), l);\n"))
+			},
+			Floor(v) => ret(format!("floor({})", self.call(v, "p")?)),
+			Ceil(v) => ret(format!("ceil({})", self.call(v, "p")?)),
+			Abs(v) => ret(format!("abs({})", self.call(v, "p")?)),
+			Min(l, r) => self.binary(l, r, |l, r| format!("fmin({l}, {r})")),
+			Max(l, r) => self.binary(l, r, |l, r| format!("fmax({l}, {r})")),
+			Clamp { func, min, max } => {
+				ret(format!("clamp({}, {}, {})", self.call(func, "p")?, lit(*min), lit(*max)))
+			},
+			Curve { func, points } => {
+				// the segment `eval_curve` finds by bisection, as a chain of comparisons
+				let v = self.call(func, "p")?;
+				let mut body = format!("\tlet v = {v};\n\tif is_nan(v) {{ return v; }}\n");
+				for (i, b) in points.iter().enumerate() {
+					let value = match i.checked_sub(1).map(|prev| points[prev]) {
+						None => lit(b.y),
+						Some(a) => format!(
+							"{} + {} * (v - {}) / {}",
+							lit(a.y),
+							lit(b.y - a.y),
+							lit(a.x),
+							lit(b.x - a.x)
+						),
+					};
+					let _ = writeln!(body, "\tif v < {} {{ return {value}; }}", lit(b.x));
+				}
+				let last = points.last().map_or(f64::NAN, |p| p.y);
+				let _ = writeln!(body, "\treturn {};", lit(last));
+				Ok(body)
+			},
+			ToUnsignedUnit(v) => ret(format!("({} + 1.0) / 2.0", self.call(v, "p")?)),
+			ToSignedUnit(v) => ret(format!("{} * 2.0 - 1.0", self.call(v, "p")?)),
+			Lerp { a, b, t, clamped } => {
+				let (a, b, t) = (self.call(a, "p")?, self.call(b, "p")?, self.call(t, "p")?);
+				let t = if *clamped { format!("clamp({t}, 0.0, 1.0)") } else { t };
+				Ok(format!("\tlet a = {a};\n\tlet b = {b};\n\treturn a + (b - a) * {t};\n"))
+			},
+			SmoothLerp { a, b, t } => {
+				let (a, b, t) = (self.call(a, "p")?, self.call(b, "p")?, self.call(t, "p")?);
+				Ok(format!(
+					"\tlet a = {a};\n\
+					 \tlet b = {b};\n\
+					 \tlet t = clamp({t}, 0.0, 1.0);\n\
+					 \treturn a + (b - a) * (t * t * (3.0 - 2.0 * t));\n"
+				))
+			},
+			&Select {
+				ref control,
+				ref a,
+				ref b,
+				threshold,
+				falloff,
+			} => {
+				let control = self.call(control, "p")?;
+				let (a, b) = (self.call(a, "p")?, self.call(b, "p")?);
+				let (lo, hi) = (lit(threshold - falloff), lit(threshold + falloff));
+				// only the selected sides are evaluated, like on the CPU
+				Ok(format!(
+					"\tlet control = {control};\n\
+					 \tif control >= {hi} {{ return {b}; }}\n\
+					 \tif control <= {lo} {{ return {a}; }}\n\
+					 \tlet t = (control - {lo}) / ({hi} - {lo});\n\
+					 \tlet a = {a};\n\
+					 \treturn a + ({b} - a) * (t * t * (3.0 - 2.0 * t));\n"
+				))
+			},
+
+			&CoordTranslate(ref func, translation) => {
+				ret(self.call(func, &format!("p + vec3<f32>({}, 0.0)", vec2_lit(translation)))?)
+			},
+			&CoordScale(ref func, scale) => {
+				ret(self.call(func, &format!("p * vec3<f32>({}, 1.0)", vec2_lit(scale)))?)
+			},
+			&CoordRotate(ref func, r) => {
+				let (c, s) = (lit(r.x), lit(r.y));
+				let pos = format!("vec3<f32>({c} * p.x - {s} * p.y, {s} * p.x + {c} * p.y, p.z)");
+				ret(self.call(func, &pos)?)
+			},
+			&CoordZ {
+				ref func,
+				offset,
+				scale,
+			} => {
+				let pos = format!("vec3<f32>(p.xy, p.z * {} + {})", lit(scale), lit(offset));
+				ret(self.call(func, &pos)?)
+			},
+			&Warp {
+				ref func,
+				ref warpX,
+				ref warpY,
+				strength,
+			} => {
+				let (x, y) = (self.call(warpX, "p")?, self.call(warpY, "p")?);
+				let func = self.call(func, "p + vec3<f32>(offset, 0.0)")?;
+				Ok(format!(
+					"\tlet offset = vec2<f32>({x}, {y}) * {};\n\treturn {func};\n",
+					lit(strength)
+				))
+			},
+			&Tiled { ref func, period } => {
+				let id = self.node(func)?;
+				let period = vec2_lit(period);
+				Ok(format!(
+					"\tlet period = {period};\n\
+					 \tlet x = rem_euclid(p.x, period.x);\n\
+					 \tlet wrapped = vec2<f32>(x, rem_euclid(p.y, period.y));\n\
+					 \tlet t = wrapped / period;\n\
+					 \tlet v00 = node{id}(vec3<f32>(wrapped, p.z));\n\
+					 \tlet v10 = node{id}(vec3<f32>(wrapped - vec2<f32>(period.x, 0.0), p.z));\n\
+					 \tlet v01 = node{id}(vec3<f32>(wrapped - vec2<f32>(0.0, period.y), p.z));\n\
+					 \tlet v11 = node{id}(vec3<f32>(wrapped - period, p.z));\n\
+					 \tlet top = v00 + (v10 - v00) * t.x;\n\
+					 \tlet bottom = v01 + (v11 - v01) * t.x;\n\
+					 \treturn top + (bottom - top) * t.y;\n"
+				))
+			},
+			&GradientMagnitude { ref func, epsilon } => {
+				let id = self.node(func)?;
+				let e = lit(epsilon);
+				Ok(format!(
+					"\tlet ex = vec3<f32>({e}, 0.0, 0.0);\n\
+					 \tlet ey = vec3<f32>(0.0, {e}, 0.0);\n\
+					 \tlet dx = node{id}(p + ex) - node{id}(p - ex);\n\
+					 \tlet dy = node{id}(p + ey) - node{id}(p - ey);\n\
+					 \treturn length(vec2<f32>(dx, dy)) / (2.0 * {e});\n"
+				))
+			},
+
+			Cached { func, id } => {
+				let node = match self.cached.get(id) {
+					Some(&node) => node,
+					None => {
+						let node = self.node(func)?;
+						self.cached.insert(*id, node);
+						node
+					},
+				};
+				ret(format!("node{node}(p)"))
+			},
+			Ranged { func, .. } => ret(self.call(func, "p")?),
+
+			Func(_) | LuaFunc(_) | Simplex3(_) | SimplexTileable { .. } | SimplexLooped { .. } |
+			Worley { .. } | CellId { .. } | White(_) | Value(_) | Perlin { .. } | Image { .. } => {
+				Err(format!("{} nodes can't run on the GPU", noise.kind_name()))
+			},
+		}
+	}
+
+	/// `Octaves` with an `OctaveVariation`, unrolled so every octave gets its own frequency,
+	/// rotation and seed. Those are computed here in `f32` like the CPU does, `value` and
+	/// `weightUpdate` being the mode's transform of the octave `v` as in the plain loop.
+	fn varied_octaves(
+		&mut self,
+		octave: Octave,
+		octaves: usize,
+		value: &str,
+		weightUpdate: &str,
+	) -> Result<String, String> {
+		let Octave {
+			func,
+			ampScale,
+			freqScale,
+			variation,
+			divisor,
+		} = octave;
+		let reseeded = match *func {
+			Noise::Simplex(seed) if variation.seedOffset != 0 => Some(("simplex_smooth", seed)),
+			Noise::SimplexFast(seed) if variation.seedOffset != 0 => Some(("simplex_fast", seed)),
+			_ => None,
+		};
+		// reseeded simplex is called directly, other functions are shared by the octaves
+		let shared = match reseeded {
+			Some(_) => None,
+			None => Some(self.node(func)?),
+		};
+		let freqScale = freqScale.as_vec2().extend(freqScale.x as f32);
+		let mut body = "	var res = 0.0;
+	var weight = 1.0;
+".to_string();
+		let mut amp = 1.0f32;
+		let mut freq = Vec3::ONE;
+		let mut rotation = DVec2::X;
+		for i in 0 .. octaves {
+			if (amp.abs() as f64) < lua::minOctaveAmp {
+				break;
+			}
+			let (fx, fy, fz) = (lit(freq.x), lit(freq.y), lit(freq.z));
+			let rotated = match variation.rotation {
+				Some(_) => {
+					let (c, s) = (lit(rotation.x), lit(rotation.y));
+					format!("vec3<f32>({c} * q.x - {s} * q.y, {s} * q.x + {c} * q.y, q.z)")
+				},
+				None => "q".into(),
+			};
+			let v = match (reseeded, shared) {
+				(Some((simplex, seed)), _) => {
+					let seed = u64_lit(variation.seed(seed, i) as u64);
+					format!("{simplex}({seed}, r.xy)")
+				},
+				(None, Some(node)) => {
+					let shift = vec2_lit(variation.shift(i));
+					format!("node{node}(r + vec3<f32>({shift}, 0.0))")
+				},
+				(None, None) => unreachable!("functions that aren't reseeded are shared"),
+			};
+			let _ = write!(
+				body,
+				"	{{
+				 		let q = p * vec3<f32>({fx}, {fy}, {fz});
+				 		let r = {rotated};
+				 		let v = {v};
+				 		res += {} * {value};
+				 {weightUpdate}				 	}}
+",
+				lit(amp)
+			);
+			amp *= ampScale;
+			freq *= freqScale * variation.jitter(i) as f32;
+			if let Some(step) = variation.rotation {
+				rotation = step.rotate(rotation);
+			}
+		}
+		let _ = writeln!(body, "	return res / {};", lit(divisor));
+		Ok(body)
+	}
+
+	fn binary(
+		&mut self,
+		l: &Noise,
+		r: &Noise,
+		op: impl FnOnce(String, String) -> String,
+	) -> Result<String, String> {
+		let (l, r) = (self.call(l, "p")?, self.call(r, "p")?);
+		Ok(format!("\treturn {};\n", op(l, r)))
+	}
+}
+
+/// Octave settings of a `Noise::Octaves` node with an `OctaveVariation`.
+struct Octave<'a> {
+	func: &'a Noise,
+	ampScale: f32,
+	freqScale: DVec2,
+	variation: lua::OctaveVariation,
+	divisor: f64,
+}
+
+/// WGSL literal of `v` as an `f32`. WGSL has no literals for infinities and NaN, those are
+/// rebuilt from their bits at runtime.
+fn lit(v: impl Into<f64>) -> String {
+	let v = v.into() as f32;
+	if v.is_finite() {
+		format!("({v:?})")
+	} else {
+		format!("from_bits({:#010x}u)", v.to_bits())
+	}
+}
+
+fn vec2_lit(v: DVec2) -> String {
+	format!("vec2<f32>({}, {})", lit(v.x), lit(v.y))
+}
+
+/// A 64 bit integer as the `(low, high)` word pair the prelude does its arithmetic on.
+fn u64_lit(v: u64) -> String {
+	format!("vec2<u32>({:#010x}u, {:#010x}u)", v as u32, (v >> 32) as u32)
+}
+
+fn compute_shader(functions: &str, grid: &SampleGrid, z: f32, time: f32) -> String {
+	let SampleGrid {
+		width,
+		height,
+		coords,
+		supersampling,
+		domain,
+	} = *grid;
+	// sample positions match `Coords::position`, sub-samples `SampleGrid::offsets`
+	let cells = width.max(height).saturating_sub(1).max(1) as f64;
+	let (first, divisor, scale) = match coords {
+		Coords::Normalized => (DVec2::ZERO, cells, domain.size),
+		Coords::Centered => {
+			let middle =
+				DVec2::new(width.saturating_sub(1) as f64, height.saturating_sub(1) as f64);
+			(-middle / 2.0, cells, domain.size * 2.0)
+		},
+		Coords::Pixels => (DVec2::ZERO, 1.0, domain.size),
+	};
+	format!(
+		"{prelude}\n\
+		 const animationTime = {};\n\n\
+		 {functions}\
+		 @group(0) @binding(0) var heights: texture_storage_2d<r32float, write>;\n\n\
+		 @compute @workgroup_size({workgroupSize}, {workgroupSize})\n\
+		 fn main(@builtin(global_invocation_id) id: vec3<u32>) {{\n\
+		 \tif id.x >= {width}u || id.y >= {height}u {{ return; }}\n\
+		 \tvar sum = 0.0;\n\
+		 \tfor (var i = 0u; i < {subsamples}u; i++) {{\n\
+		 \t\tlet cell = vec2<u32>(i % {supersampling}u, i / {supersampling}u);\n\
+		 \t\tlet offset = (vec2<f32>(cell) + 0.5) / {n} - 0.5;\n\
+		 \t\tlet pos = {} + (vec2<f32>(id.xy) + {} + offset) / {} * {};\n\
+		 \t\tsum += node0(vec3<f32>(pos, {}));\n\
+		 \t}}\n\
+		 \tlet v = sum / f32({subsamples}u);\n\
+		 \ttextureStore(heights, id.xy, vec4<f32>(v, 0.0, 0.0, 0.0));\n\
+		 }}\n",
+		lit(time),
+		vec2_lit(domain.origin),
+		vec2_lit(first),
+		lit(divisor),
+		lit(scale),
+		lit(z),
+		subsamples = supersampling.pow(2),
+		n = lit(supersampling as f64),
+	)
+}
+
+/// Runs `source` over a `width`×`height` texture and reads the texture back, row by row.
+fn run(ctx: &GpuContext, source: &str, width: u32, height: u32) -> Result<Vec<f32>, String> {
+	// a generation panicking while holding the lock leaves nothing half done to guard
+	let _guard = ctx.lock.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+	let device = &ctx.device;
+	device.push_error_scope(wgpu::ErrorFilter::Validation);
+	let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+		label: Some("noise"),
+		source: wgpu::ShaderSource::Wgsl(source.into()),
+	});
+	let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+		label: Some("noise"),
+		layout: None,
+		module: &module,
+		entry_point: "main",
+		compilation_options: default(),
+	});
+	let size = wgpu::Extent3d {
+		width,
+		height,
+		depth_or_array_layers: 1,
+	};
+	let texture = device.create_texture(&wgpu::TextureDescriptor {
+		label: Some("noise"),
+		size,
+		mip_level_count: 1,
+		sample_count: 1,
+		dimension: wgpu::TextureDimension::D2,
+		format: wgpu::TextureFormat::R32Float,
+		usage: wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::COPY_SRC,
+		view_formats: &[],
+	});
+	let view = texture.create_view(&default());
+	let bindGroup = device.create_bind_group(&wgpu::BindGroupDescriptor {
+		label: Some("noise"),
+		layout: &pipeline.get_bind_group_layout(0),
+		entries: &[wgpu::BindGroupEntry {
+			binding: 0,
+			resource: wgpu::BindingResource::TextureView(&view),
+		}],
+	});
+	// copied rows have to start at multiples of the alignment
+	let rowBytes = (width * 4).next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT);
+	let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+		label: Some("noise readback"),
+		size: rowBytes as u64 * height as u64,
+		usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+		mapped_at_creation: false,
+	});
+
+	let mut encoder = device.create_command_encoder(&default());
+	{
+		let mut pass = encoder.begin_compute_pass(&default());
+		pass.set_pipeline(&pipeline);
+		pass.set_bind_group(0, &bindGroup, &[]);
+		let groups = (width.div_ceil(workgroupSize), height.div_ceil(workgroupSize));
+		pass.dispatch_workgroups(groups.0, groups.1, 1);
+	}
+	encoder.copy_texture_to_buffer(
+		texture.as_image_copy(),
+		wgpu::ImageCopyBuffer {
+			buffer: &buffer,
+			layout: wgpu::ImageDataLayout {
+				offset: 0,
+				bytes_per_row: Some(rowBytes),
+				rows_per_image: None,
+			},
+		},
+		size,
+	);
+	if let Some(err) = block_on(device.pop_error_scope()) {
+		return Err(format!("GPU error: {err}"));
+	}
+	ctx.queue.submit([encoder.finish()]);
+
+	let slice = buffer.slice(..);
+	let (sender, receiver) = crossbeam_channel::bounded(1);
+	slice.map_async(wgpu::MapMode::Read, move |res| {
+		let _ = sender.send(res);
+	});
+	device.poll(wgpu::Maintain::Wait);
+	match receiver.recv() {
+		Ok(Ok(())) => {},
+		Ok(Err(err)) => return Err(format!("GPU readback failed: {err}")),
+		Err(_) => return Err("GPU readback never completed".into()),
+	}
+	let samples = {
+		let bytes = slice.get_mapped_range();
+		bytes
+			.chunks_exact(rowBytes as usize)
+			.flat_map(|row| row[.. width as usize * 4].chunks_exact(4))
+			.map(|sample| f32::from_le_bytes(sample.try_into().unwrap()))
+			.collect()
+	};
+	buffer.unmap();
+	Ok(samples)
+}
+
+/// Helpers the translated nodes call, with the 2D sources ported from the `opensimplex2` crate.
+/// WGSL has no 64 bit integers, so the hashes work on `(low, high)` pairs of 32 bit words.
+const prelude: &str = r#"
+fn from_bits(bits: u32) -> f32 {
+	return bitcast<f32>(bits);
+}
+
+fn is_nan(v: f32) -> bool {
+	return (bitcast<u32>(v) & 0x7fffffffu) > 0x7f800000u;
+}
+
+// NaN-ignoring like Rust's `min` and `max`
+fn fmin(a: f32, b: f32) -> f32 {
+	if is_nan(a) { return b; }
+	if is_nan(b) { return a; }
+	return min(a, b);
+}
+
+fn fmax(a: f32, b: f32) -> f32 {
+	if is_nan(a) { return b; }
+	if is_nan(b) { return a; }
+	return max(a, b);
+}
+
+fn rem_euclid(a: f32, b: f32) -> f32 {
+	let r = a % b;
+	return select(r, r + abs(b), r < 0.0);
+}
+
+fn copysign(v: f32, sign: f32) -> f32 {
+	return bitcast<f32>((bitcast<u32>(v) & 0x7fffffffu) | (bitcast<u32>(sign) & 0x80000000u));
+}
+
+// `pow` is undefined for negative bases in WGSL, Rust's `powf` only for fractional exponents
+fn powf(x: f32, y: f32) -> f32 {
+	if y == 0.0 || x == 1.0 { return 1.0; }
+	if x == 0.0 { return select(0.0, from_bits(0x7f800000u), y < 0.0); }
+	let m = exp2(y * log2(abs(x)));
+	if x > 0.0 { return m; }
+	if fract(y) != 0.0 { return from_bits(0x7fc00000u); }
+	return select(m, -m, fract(y * 0.5) != 0.0);
+}
+
+// full product of two 32 bit words, from their 16 bit halves
+fn mul32(a: u32, b: u32) -> vec2<u32> {
+	let a0 = a & 0xffffu;
+	let a1 = a >> 16u;
+	let b0 = b & 0xffffu;
+	let b1 = b >> 16u;
+	let p00 = a0 * b0;
+	let p01 = a0 * b1;
+	let p10 = a1 * b0;
+	let mid = (p00 >> 16u) + (p01 & 0xffffu) + (p10 & 0xffffu);
+	let lo = (p00 & 0xffffu) | (mid << 16u);
+	let hi = a1 * b1 + (p01 >> 16u) + (p10 >> 16u) + (mid >> 16u);
+	return vec2<u32>(lo, hi);
+}
+
+fn mul64(a: vec2<u32>, b: vec2<u32>) -> vec2<u32> {
+	let lo = mul32(a.x, b.x);
+	return vec2<u32>(lo.x, lo.y + a.x * b.y + a.y * b.x);
+}
+
+fn add64(a: vec2<u32>, b: vec2<u32>) -> vec2<u32> {
+	let lo = a.x + b.x;
+	return vec2<u32>(lo, a.y + b.y + select(0u, 1u, lo < a.x));
+}
+
+fn sub64(a: vec2<u32>, b: vec2<u32>) -> vec2<u32> {
+	return vec2<u32>(a.x - b.x, a.y - b.y - select(0u, 1u, a.x < b.x));
+}
+
+fn from_i32(v: i32) -> vec2<u32> {
+	return vec2<u32>(u32(v), select(0u, 0xffffffffu, v < 0));
+}
+
+const PRIME_X = vec2<u32>(0x9270c86fu, 0x5205402bu);
+const PRIME_Y = vec2<u32>(0x003817b5u, 0x598cd327u);
+const HASH_MULTIPLIER = vec2<u32>(0xeec546f5u, 0x53a3f72du);
+const SKEW_2D = 0.366025403784439;
+const UNSKEW_2D = -0.21132486540518713;
+
+var<private> GRADIENTS_2D: array<f32, 48> = array<f32, 48>(
+	0.38268343236509, 0.923879532511287,
+	0.923879532511287, 0.38268343236509,
+	0.923879532511287, -0.38268343236509,
+	0.38268343236509, -0.923879532511287,
+	-0.38268343236509, -0.923879532511287,
+	-0.923879532511287, -0.38268343236509,
+	-0.923879532511287, 0.38268343236509,
+	-0.38268343236509, 0.923879532511287,
+	0.130526192220052, 0.99144486137381,
+	0.608761429008721, 0.793353340291235,
+	0.793353340291235, 0.608761429008721,
+	0.99144486137381, 0.130526192220052,
+	0.99144486137381, -0.130526192220052,
+	0.793353340291235, -0.608761429008721,
+	0.608761429008721, -0.793353340291235,
+	0.130526192220052, -0.99144486137381,
+	-0.130526192220052, -0.99144486137381,
+	-0.608761429008721, -0.793353340291235,
+	-0.793353340291235, -0.608761429008721,
+	-0.99144486137381, -0.130526192220052,
+	-0.99144486137381, 0.130526192220052,
+	-0.793353340291235, 0.608761429008721,
+	-0.608761429008721, 0.793353340291235,
+	-0.130526192220052, 0.99144486137381,
+);
+
+// unnormalized, the sources divide their sums by their normalizer
+fn grad2(seed: vec2<u32>, xsvp: vec2<u32>, ysvp: vec2<u32>, d: vec2<f32>) -> f32 {
+	let hash = mul64(seed ^ xsvp ^ ysvp, HASH_MULTIPLIER);
+	// low word of `hash ^ (hash >> 58)`, shifting arithmetically
+	let gi = (hash.x ^ u32(bitcast<i32>(hash.y) >> 26u)) & 254u;
+	let i = gi % 48u;
+	return GRADIENTS_2D[i] * d.x + GRADIENTS_2D[i + 1u] * d.y;
+}
+
+fn falloff(a: f32) -> f32 {
+	return (a * a) * (a * a);
+}
+
+fn simplex_fast(seed: vec2<u32>, pos: vec2<f32>) -> f32 {
+	let s = SKEW_2D * (pos.x + pos.y);
+	let xs = pos + s;
+	let sb = floor(xs);
+	let i = xs - sb;
+	let xsbp = mul64(from_i32(i32(sb.x)), PRIME_X);
+	let ysbp = mul64(from_i32(i32(sb.y)), PRIME_Y);
+	let t = (i.x + i.y) * UNSKEW_2D;
+	let d0 = i + t;
+
+	var value = 0.0;
+	let a0 = 0.5 - dot(d0, d0);
+	if a0 > 0.0 {
+		value = falloff(a0) * grad2(seed, xsbp, ysbp, d0);
+	}
+	let a1 = f32(2.0 * (1.0 + 2.0 * UNSKEW_2D) * (1.0 / UNSKEW_2D + 2.0)) * t +
+		(f32(-2.0 * (1.0 + 2.0 * UNSKEW_2D) * (1.0 + 2.0 * UNSKEW_2D)) + a0);
+	if a1 > 0.0 {
+		let d1 = d0 - f32(1.0 + 2.0 * UNSKEW_2D);
+		value += falloff(a1) * grad2(seed, add64(xsbp, PRIME_X), add64(ysbp, PRIME_Y), d1);
+	}
+	if d0.y > d0.x {
+		let d2 = d0 - vec2<f32>(UNSKEW_2D, UNSKEW_2D + 1.0);
+		let a2 = 0.5 - dot(d2, d2);
+		if a2 > 0.0 {
+			value += falloff(a2) * grad2(seed, xsbp, add64(ysbp, PRIME_Y), d2);
+		}
+	} else {
+		let d2 = d0 - vec2<f32>(UNSKEW_2D + 1.0, UNSKEW_2D);
+		let a2 = 0.5 - dot(d2, d2);
+		if a2 > 0.0 {
+			value += falloff(a2) * grad2(seed, add64(xsbp, PRIME_X), ysbp, d2);
+		}
+	}
+	return value / 0.01001634121365712;
+}
+
+fn simplex_smooth_vertex(seed: vec2<u32>, xsvp: vec2<u32>, ysvp: vec2<u32>, d: vec2<f32>) -> f32 {
+	let a = 2.0 / 3.0 - dot(d, d);
+	if a > 0.0 {
+		return falloff(a) * grad2(seed, xsvp, ysvp, d);
+	}
+	return 0.0;
+}
+
+fn simplex_smooth(seed: vec2<u32>, pos: vec2<f32>) -> f32 {
+	let s = SKEW_2D * (pos.x + pos.y);
+	let xs = pos + s;
+	let sb = floor(xs);
+	let i = xs - sb;
+	let xsbp = mul64(from_i32(i32(sb.x)), PRIME_X);
+	let ysbp = mul64(from_i32(i32(sb.y)), PRIME_Y);
+	let t = (i.x + i.y) * UNSKEW_2D;
+	let d0 = i + t;
+
+	let a0 = 2.0 / 3.0 - dot(d0, d0);
+	var value = falloff(a0) * grad2(seed, xsbp, ysbp, d0);
+	let a1 = f32(2.0 * (1.0 + 2.0 * UNSKEW_2D) * (1.0 / UNSKEW_2D + 2.0)) * t +
+		(f32(-2.0 * (1.0 + 2.0 * UNSKEW_2D) * (1.0 + 2.0 * UNSKEW_2D)) + a0);
+	let d1 = d0 - f32(1.0 + 2.0 * UNSKEW_2D);
+	value += falloff(a1) * grad2(seed, add64(xsbp, PRIME_X), add64(ysbp, PRIME_Y), d1);
+
+	let xmyi = i.x - i.y;
+	if t < UNSKEW_2D {
+		if i.x + xmyi > 1.0 {
+			let d2 = d0 - vec2<f32>(3.0 * UNSKEW_2D + 2.0, 3.0 * UNSKEW_2D + 1.0);
+			let xsvp = add64(add64(xsbp, PRIME_X), PRIME_X);
+			value += simplex_smooth_vertex(seed, xsvp, add64(ysbp, PRIME_Y), d2);
+		} else {
+			let d2 = d0 - vec2<f32>(UNSKEW_2D, UNSKEW_2D + 1.0);
+			value += simplex_smooth_vertex(seed, xsbp, add64(ysbp, PRIME_Y), d2);
+		}
+		if i.y - xmyi > 1.0 {
+			let d3 = d0 - vec2<f32>(3.0 * UNSKEW_2D + 1.0, 3.0 * UNSKEW_2D + 2.0);
+			let ysvp = add64(add64(ysbp, PRIME_Y), PRIME_Y);
+			value += simplex_smooth_vertex(seed, add64(xsbp, PRIME_X), ysvp, d3);
+		} else {
+			let d3 = d0 - vec2<f32>(UNSKEW_2D + 1.0, UNSKEW_2D);
+			value += simplex_smooth_vertex(seed, add64(xsbp, PRIME_X), ysbp, d3);
+		}
+	} else {
+		if i.x + xmyi < 0.0 {
+			let d2 = d0 + vec2<f32>(1.0 + UNSKEW_2D, UNSKEW_2D);
+			value += simplex_smooth_vertex(seed, sub64(xsbp, PRIME_X), ysbp, d2);
+		} else {
+			let d2 = d0 - vec2<f32>(UNSKEW_2D + 1.0, UNSKEW_2D);
+			value += simplex_smooth_vertex(seed, add64(xsbp, PRIME_X), ysbp, d2);
+		}
+		if i.y < xmyi {
+			let d3 = d0 + vec2<f32>(UNSKEW_2D, UNSKEW_2D + 1.0);
+			value += simplex_smooth_vertex(seed, xsbp, sub64(ysbp, PRIME_Y), d3);
+		} else {
+			let d3 = d0 - vec2<f32>(UNSKEW_2D, UNSKEW_2D + 1.0);
+			value += simplex_smooth_vertex(seed, xsbp, add64(ysbp, PRIME_Y), d3);
+		}
+	}
+	return value / 0.05481866495625118;
+}
+"#;
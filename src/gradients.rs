@@ -0,0 +1,187 @@
+use bevy::math::{vec2, vec3};
+use bevy::prelude::*;
+
+use crate::gizmos::{to_color, View2dGizmos};
+use crate::theme::Theme;
+use crate::{colormap, lighting, HeightmapSprite, Heightmaps, SelectedTab, Tab, UiState};
+
+/// Most arrows drawn per axis, regardless of the configured stride.
+const maxArrows: usize = 96;
+
+/// What the 2D view shows of the output's gradient.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum GradientView {
+	#[default]
+	Off,
+	/// Replaces the values with the terrain's normals, RGB encoded.
+	NormalMap,
+	/// Arrows pointing uphill over the values, their length relative to the steepest one shown.
+	Arrows,
+	/// Replaces the values with the terrain lit by the 3D view's sun, see `hillshade`.
+	Hillshade,
+}
+
+impl GradientView {
+	pub const all: [Self; 4] = [Self::Off, Self::NormalMap, Self::Arrows, Self::Hillshade];
+
+	pub fn label(self) -> &'static str {
+		match self {
+			Self::Off => "Values",
+			Self::NormalMap => "Normal map",
+			Self::Arrows => "Gradient arrows",
+			Self::Hillshade => "Hillshade",
+		}
+	}
+}
+
+/// Gradient at sample `(x, y)` of a `width`×`height` grid, in value per sample step with y
+/// growing with the row. Central differences, one-sided at the edges.
+pub fn gradient_at(values: &[f32], (width, height): (usize, usize), x: usize, y: usize) -> Vec2 {
+	let at = |x: usize, y: usize| values[y * width + x];
+	let (x0, x1) = (x.saturating_sub(1), (x + 1).min(width - 1));
+	let (y0, y1) = (y.saturating_sub(1), (y + 1).min(height - 1));
+	let dx = (at(x1, y) - at(x0, y)) / (x1 - x0).max(1) as f32;
+	let dy = (at(x, y1) - at(x, y0)) / (y1 - y0).max(1) as f32;
+	vec2(dx, dy)
+}
+
+/// `gradient_at` for every sample, row by row.
+pub fn sample_gradients(values: &[f32], size: (usize, usize)) -> Vec<Vec2> {
+	let (width, height) = size;
+	(0 .. height)
+		.flat_map(|y| (0 .. width).map(move |x| (x, y)))
+		.map(|(x, y)| gradient_at(values, size, x, y))
+		.collect()
+}
+
+/// Normal of a surface `heightScale` tall with a per-sample `gradient`, encoded to `[0, 1]` RGB.
+/// Green points up the image, towards row 0, as in OpenGL style normal maps.
+pub fn encode_normal(gradient: Vec2, heightScale: f32) -> [f32; 3] {
+	let normal = vec3(-gradient.x * heightScale, gradient.y * heightScale, 1.0).normalize();
+	(normal * 0.5 + 0.5).to_array()
+}
+
+/// Lambert shading, from 0 to 1, of a surface `heightScale` tall with a per-sample `gradient`, lit
+/// from `sun`. The surface lies like the terrain mesh, rows going along +Z.
+pub fn hillshade(gradient: Vec2, heightScale: f32, sun: Vec3) -> f32 {
+	let normal = vec3(-gradient.x * heightScale, 1.0, -gradient.y * heightScale).normalize();
+	normal.dot(sun).max(0.0)
+}
+
+/// Draws `UiState::gradientView` over the 2D view. Works from the heights texture, like the
+/// terrain colors, so previews and downsampled outputs are covered too.
+pub fn gradient_view(
+	uiState: Res<UiState>,
+	selectedTab: Res<SelectedTab>,
+	heightmaps: Res<Heightmaps>,
+	mut images: ResMut<Assets<Image>>,
+	mut imageEvents: EventReader<AssetEvent<Image>>,
+	sprite: Query<&Transform, With<HeightmapSprite>>,
+	mut gizmos: Gizmos<View2dGizmos>,
+	theme: Res<Theme>,
+	colormap: Res<colormap::ColormapSettings>,
+	lighting: Res<lighting::LightingSettings>,
+	mut applied: Local<Option<(GradientView, f32)>>,
+) {
+	let heightsChanged = imageEvents
+		.read()
+		.any(|ev| ev.is_modified(&heightmaps.heights));
+	let Some(heights) = images.get(&heightmaps.heights) else {
+		error!("heights image is missing");
+		return;
+	};
+	let size = heights.texture_descriptor.size;
+	let (width, height) = (size.width as usize, size.height as usize);
+	let values: &[f32] = bytemuck::cast_slice(&heights.data);
+
+	let view = uiState.gradientView;
+	// slopes of the mesh, whose samples are spread apart by the terrain extent
+	let heightScale = uiState.height / uiState.terrain_scale().spacing((width, height));
+	let current = (view, heightScale);
+	let previous = applied.replace(current);
+	// uploads and recolors overwrite the image with values, so only the views replacing them need
+	// redoing after them
+	let replaces = |view| matches!(view, GradientView::NormalMap | GradientView::Hillshade);
+	let wasReplaced = previous.is_some_and(|(view, _)| replaces(view));
+	let refill = match view {
+		GradientView::NormalMap => {
+			heightsChanged || colormap.is_changed() || previous != Some(current)
+		},
+		GradientView::Hillshade => {
+			heightsChanged ||
+				colormap.is_changed() ||
+				lighting.is_changed() ||
+				previous != Some(current)
+		},
+		_ => wasReplaced && !heightsChanged,
+	};
+	if width < 2 || height < 2 {
+		return;
+	}
+
+	if refill {
+		let pixels: Vec<[f32; 4]> = if view == GradientView::NormalMap {
+			sample_gradients(values, (width, height))
+				.into_iter()
+				.map(|gradient| {
+					let [r, g, b] = encode_normal(gradient, heightScale);
+					[r, g, b, 1.0]
+				})
+				.collect()
+		} else if view == GradientView::Hillshade {
+			let sun = lighting.sun_direction();
+			sample_gradients(values, (width, height))
+				.into_iter()
+				.map(|gradient| {
+					let shade = hillshade(gradient, heightScale, sun);
+					[shade, shade, shade, 1.0]
+				})
+				.collect()
+		} else {
+			// heights are normalized to [-1, 1], which `NoiseOutput::fill_image` maps to [0, 1]
+			let color = colormap.colormap.mapper();
+			values.iter().map(|&v| color((v + 1.0) / 2.0)).collect()
+		};
+		let Some(image) = images.get_mut(&heightmaps.image) else {
+			error!("heightmap image is missing");
+			return;
+		};
+		if image.texture_descriptor.size != size {
+			image.resize(size);
+		}
+		image.data = bytemuck::cast_slice(&pixels).to_vec();
+		return;
+	}
+
+	if view != GradientView::Arrows || !selectedTab.shows(Tab::D2) {
+		return;
+	}
+	let stride = uiState.arrowStride.max(width.max(height).div_ceil(maxArrows)).max(1);
+	let arrows: Vec<(Vec2, Vec2)> = (stride / 2 .. height)
+		.step_by(stride)
+		.flat_map(|y| (stride / 2 .. width).step_by(stride).map(move |x| (x, y)))
+		.map(|(x, y)| {
+			// the sprite is centered on its transform, with rows going down
+			let half = vec2(width as f32, height as f32) / 2.0;
+			let pos = vec2(x as f32 + 0.5 - half.x, half.y - y as f32 - 0.5);
+			let gradient = gradient_at(values, (width, height), x, y);
+			(pos, vec2(gradient.x, -gradient.y))
+		})
+		.collect();
+	let steepest = arrows.iter().map(|(_, dir)| dir.length()).fold(0.0, f32::max);
+	if !(steepest > 0.0) {
+		return;
+	}
+	let transform = sprite.single();
+	// zoomed in domains scale the sprite, see `Domain::placement`
+	let (origin, scale) = (transform.translation.truncate(), transform.scale.x);
+	let color = to_color(theme.accent());
+	for (pos, dir) in arrows {
+		let length = dir.length() / steepest * stride as f32 * 0.9 * scale;
+		if length < scale {
+			continue;
+		}
+		let start = origin + pos * scale;
+		gizmos.arrow_2d(start, start + dir.normalize() * length, color);
+	}
+}
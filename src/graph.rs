@@ -0,0 +1,546 @@
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use bevy::math::DVec2;
+use bevy::prelude::*;
+use bevy::tasks::{block_on, AsyncComputeTaskPool, Task};
+use bevy::utils::HashMap;
+use bevy_egui::egui::{self, TextureId};
+use bevy_egui::EguiContexts;
+
+use crate::thumbnails::{self, thumbnailSize};
+use crate::{
+	colormap, domain, generate_samples_cancellable, lua, snapshot, workers, Coords, NoiseOutput,
+	SampleGrid,
+};
+
+/// Node thumbnails kept rendered, the ones drawn least recently being dropped past it. Only nodes
+/// scrolled into view are rendered, so huge trees stay bounded by this rather than their size.
+const maxThumbnails: usize = 256;
+
+/// Thumbnail jobs allowed to run at once, leaving most of the pool to generations.
+const maxRunningJobs: usize = 2;
+
+const nodeSize: egui::Vec2 = egui::vec2(128.0, 92.0);
+const nodeGap: egui::Vec2 = egui::vec2(12.0, 32.0);
+
+/// Longest label drawn in a node, longer ones are cut short and shown whole on hover.
+const labelChars: usize = 20;
+
+/// A node of the drawn tree, indexed by its pre-order ID like in the Inspector.
+pub struct GraphNode {
+	pub parent: Option<usize>,
+	pub depth: usize,
+	/// Column in node widths. Leaves take the next free one and parents sit centered over their
+	/// children.
+	pub column: f32,
+	/// Kind and parameters.
+	pub label: String,
+}
+
+enum Thumbnail {
+	Queued,
+	Running(Task<Result<Vec<f32>, String>>),
+	Ready {
+		image: Handle<Image>,
+		texture: TextureId,
+		lastUse: u64,
+	},
+	Failed(String),
+}
+
+/// A subtree shown in the 2D view in place of the output.
+enum Promotion {
+	Running {
+		id: usize,
+		cancel: Arc<AtomicBool>,
+		task: Task<Result<NoiseOutput, String>>,
+	},
+	Shown {
+		id: usize,
+		output: Box<NoiseOutput>,
+		image: Handle<Image>,
+	},
+	Failed {
+		id: usize,
+		error: String,
+	},
+}
+
+impl Promotion {
+	fn id(&self) -> usize {
+		match *self {
+			Promotion::Running { id, .. } |
+			Promotion::Shown { id, .. } |
+			Promotion::Failed { id, .. } => id,
+		}
+	}
+}
+
+/// The output's grid, which subtrees are evaluated over: the same rectangle of the plane, depth,
+/// animation time and precision.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Sampling {
+	width: usize,
+	height: usize,
+	coords: Coords,
+	supersampling: usize,
+	domain: domain::Domain,
+	sliceZ: f64,
+	precision: lua::Precision,
+	ctx: lua::EvalContext,
+}
+
+impl Sampling {
+	pub fn of(output: &NoiseOutput) -> Self {
+		Self {
+			width: output.width,
+			height: output.height,
+			coords: output.coords,
+			supersampling: output.supersampling,
+			domain: output.domain,
+			sliceZ: output.sliceZ,
+			precision: output.precision,
+			ctx: lua::EvalContext {
+				time: output.time.unwrap_or_default(),
+			},
+		}
+	}
+
+	fn grid(&self) -> SampleGrid {
+		SampleGrid {
+			width: self.width,
+			height: self.height,
+			coords: self.coords,
+			supersampling: self.supersampling,
+			domain: self.domain,
+		}
+	}
+
+	/// `node` at `thumbnailSize`² points spread over the output's rectangle, from corner sample to
+	/// corner sample, mapped to `[-1, 1]` by the lowest and highest finite one.
+	pub fn thumbnail(&self, node: &lua::Noise) -> Vec<f32> {
+		let step = |samples: usize| samples.saturating_sub(1) as f64 / (thumbnailSize - 1) as f64;
+		let step = DVec2::new(step(self.width), step(self.height));
+		let mut samples = Vec::with_capacity(thumbnailSize.pow(2));
+		for y in 0 .. thumbnailSize {
+			for x in 0 .. thumbnailSize {
+				let index = DVec2::new(x as f64, y as f64) * step;
+				let pos = self.domain.apply(self.coords.at(index, self.width, self.height));
+				samples.push(node.eval3_with(pos.extend(self.sliceZ), self.precision, &self.ctx));
+			}
+		}
+		let (min, max) = finite_range(&samples).unwrap_or((-1.0, 1.0));
+		for v in &mut samples {
+			*v = (*v - min) / (max - min) * 2.0 - 1.0;
+		}
+		samples
+	}
+}
+
+/// Lowest and highest finite sample, if they differ.
+pub fn finite_range(samples: &[f32]) -> Option<(f32, f32)> {
+	let (min, max) = samples
+		.iter()
+		.filter(|v| v.is_finite())
+		.fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), &v| (min.min(v), max.max(v)));
+	(min < max).then_some((min, max))
+}
+
+/// Lays the tree out top-down, a row per depth, indexed by pre-order ID.
+pub fn layout(tree: &lua::Noise) -> Vec<GraphNode> {
+	fn place(
+		node: &lua::Noise,
+		parent: Option<usize>,
+		depth: usize,
+		nextColumn: &mut f32,
+		nodes: &mut Vec<GraphNode>,
+	) -> f32 {
+		let id = nodes.len();
+		nodes.push(GraphNode {
+			parent,
+			depth,
+			column: 0.0,
+			label: node.to_string(),
+		});
+		let children = node.children();
+		let column = if children.is_empty() {
+			*nextColumn += 1.0;
+			*nextColumn - 1.0
+		} else {
+			let columns: Vec<_> = children
+				.into_iter()
+				.map(|child| place(child, Some(id), depth + 1, nextColumn, nodes))
+				.collect();
+			(columns[0] + columns[columns.len() - 1]) / 2.0
+		};
+		nodes[id].column = column;
+		column
+	}
+	let mut nodes = vec![];
+	place(tree, None, 0, &mut 0.0, &mut nodes);
+	nodes
+}
+
+fn short_label(label: &str) -> String {
+	if label.chars().count() <= labelChars {
+		return label.to_owned();
+	}
+	label.chars().take(labelChars - 1).chain(['…']).collect()
+}
+
+/// The output's tree drawn as a graph, each node with a thumbnail of its subtree evaluated over
+/// the output's grid. Thumbnails are rendered in the background as nodes are scrolled into view,
+/// and kept until the output is replaced. Clicking a node shows its subtree in the 2D view instead
+/// of the output, until it's clicked again, the window is closed or the output replaced.
+#[derive(Resource, Default)]
+pub struct NoiseGraph {
+	pub open: bool,
+	/// Tree drawn, which the output was generated from.
+	tree: Option<Arc<lua::Noise>>,
+	sampling: Option<Sampling>,
+	nodes: Vec<GraphNode>,
+	/// By node ID.
+	thumbnails: HashMap<usize, Thumbnail>,
+	queue: VecDeque<usize>,
+	/// Images of dropped thumbnails and promotions, removed on the next update.
+	released: Vec<Handle<Image>>,
+	/// Frame counter for LRU eviction.
+	clock: u64,
+	promotion: Option<Promotion>,
+}
+
+impl NoiseGraph {
+	/// Image of the promoted subtree, shown in the 2D view in place of the output.
+	pub fn promoted_image(&self) -> Option<Handle<Image>> {
+		match &self.promotion {
+			Some(Promotion::Shown { image, .. }) => Some(image.clone()),
+			_ => None,
+		}
+	}
+
+	/// Estimated bytes of ready thumbnails, whose images only live on the GPU.
+	pub fn memory_bytes(&self) -> usize {
+		let ready = self.thumbnails.values().filter(|t| matches!(t, Thumbnail::Ready { .. }));
+		ready.count() * thumbnailSize.pow(2) * 4
+	}
+
+	/// Drops every thumbnail. Nodes still in view are rendered again.
+	pub fn clear_thumbnails(&mut self) {
+		for (_, thumbnail) in self.thumbnails.drain() {
+			if let Thumbnail::Ready { image, .. } = thumbnail {
+				self.released.push(image);
+			}
+		}
+		self.queue.clear();
+	}
+
+	/// Follows the output to a new tree or grid, dropping what was rendered for the last one.
+	fn sync(&mut self, output: Option<&NoiseOutput>) {
+		let tree = output.and_then(|output| output.noise.clone());
+		let sampling = output.map(Sampling::of);
+		let sameTree = match (&self.tree, &tree) {
+			(Some(old), Some(new)) => Arc::ptr_eq(old, new),
+			(old, new) => old.is_none() && new.is_none(),
+		};
+		if sameTree && self.sampling == sampling {
+			return;
+		}
+		self.clear_thumbnails();
+		self.demote();
+		if !sameTree {
+			self.nodes = tree.as_deref().map_or_else(Vec::new, layout);
+		}
+		self.tree = tree;
+		self.sampling = sampling;
+	}
+
+	/// Thumbnail of node `id`, queued if it isn't rendered or rendering yet.
+	fn thumbnail(&mut self, id: usize) -> &Thumbnail {
+		let (queue, clock) = (&mut self.queue, self.clock);
+		let thumbnail = self.thumbnails.entry(id).or_insert_with(|| {
+			queue.push_back(id);
+			Thumbnail::Queued
+		});
+		if let Thumbnail::Ready { lastUse, .. } = thumbnail {
+			*lastUse = clock;
+		}
+		thumbnail
+	}
+
+	fn promoted(&self) -> Option<usize> {
+		self.promotion.as_ref().map(Promotion::id)
+	}
+
+	/// Generates node `id`'s subtree over the output's grid in the background, to show it in the
+	/// 2D view once done. Its samples span a range of their own, which the colormap is stretched
+	/// over.
+	fn promote(&mut self, id: usize) {
+		self.demote();
+		let Some(sampling) = self.sampling else {
+			return;
+		};
+		let Some(node) = self.tree.as_ref().and_then(|tree| tree.node(id)).cloned() else {
+			return;
+		};
+		let cancel = Arc::new(AtomicBool::new(false));
+		let taskCancel = cancel.clone();
+		let task = AsyncComputeTaskPool::get().spawn(async move {
+			workers::catch_panic(move || {
+				let (grid, z, ctx) = (sampling.grid(), sampling.sliceZ, sampling.ctx);
+				let precision = sampling.precision;
+				let output =
+					generate_samples_cancellable(&node, &grid, precision, z, &ctx, &taskCancel);
+				let mut output = output.ok_or_else(|| "cancelled".to_owned())?;
+				if let Some(range) = finite_range(&output.samples) {
+					output.range = range;
+				}
+				Ok(output)
+			})
+		});
+		self.promotion = Some(Promotion::Running { id, cancel, task });
+	}
+
+	/// Goes back to showing the output.
+	fn demote(&mut self) {
+		match self.promotion.take() {
+			Some(Promotion::Running { cancel, .. }) => cancel.store(true, Ordering::Relaxed),
+			Some(Promotion::Shown { image, .. }) => self.released.push(image),
+			_ => {},
+		}
+	}
+}
+
+pub fn graph_window(
+	mut eguiCtx: EguiContexts,
+	mut graph: ResMut<NoiseGraph>,
+	noiseOutput: Option<Res<NoiseOutput>>,
+) {
+	let graph = &mut *graph;
+	graph.sync(noiseOutput.as_deref());
+	if !graph.open {
+		graph.demote();
+		return;
+	}
+	graph.clock += 1;
+
+	let mut open = true;
+	egui::Window::new("Noise graph")
+		.open(&mut open)
+		.default_size([520.0, 400.0])
+		.show(eguiCtx.ctx_mut(), |ui| {
+			if graph.nodes.is_empty() {
+				ui.label("no generated output");
+				return;
+			}
+			promotion_ui(ui, graph);
+			egui::ScrollArea::both()
+				.auto_shrink(false)
+				.show(ui, |ui| graph_ui(ui, graph));
+		});
+	graph.open = open;
+}
+
+fn promotion_ui(ui: &mut egui::Ui, graph: &mut NoiseGraph) {
+	ui.horizontal(|ui| {
+		match &graph.promotion {
+			None => {
+				ui.label("Click a node to show its subtree in the 2D view");
+			},
+			Some(Promotion::Running { id, .. }) => {
+				ui.spinner();
+				ui.label(format!("generating #{id}"));
+			},
+			Some(Promotion::Shown { id, .. }) => {
+				ui.label(format!("the 2D view shows #{id}"));
+			},
+			Some(Promotion::Failed { id, error }) => {
+				ui.colored_label(ui.visuals().warn_fg_color, format!("#{id} failed: {error}"));
+			},
+		}
+		if graph.promotion.is_some() && ui.button("Back to output").clicked() {
+			graph.demote();
+		}
+	});
+}
+
+fn graph_ui(ui: &mut egui::Ui, graph: &mut NoiseGraph) {
+	let columns = graph.nodes.iter().map(|node| node.column).fold(0.0, f32::max) + 1.0;
+	let rows = graph.nodes.iter().map(|node| node.depth).max().unwrap_or(0) + 1;
+	let pitch = nodeSize + nodeGap;
+	let size = egui::vec2(columns * pitch.x, rows as f32 * pitch.y);
+	let (canvas, _) = ui.allocate_exact_size(size, egui::Sense::hover());
+	let node_rect = |node: &GraphNode| {
+		let offset = egui::vec2(node.column * pitch.x, node.depth as f32 * pitch.y);
+		egui::Rect::from_min_size(canvas.min + nodeGap / 2.0 + offset, nodeSize)
+	};
+
+	let edge = egui::Stroke::new(1.0, ui.visuals().weak_text_color());
+	for node in &graph.nodes {
+		if let Some(parent) = node.parent {
+			let from = node_rect(&graph.nodes[parent]).center_bottom();
+			ui.painter().line_segment([from, node_rect(node).center_top()], edge);
+		}
+	}
+
+	let aspect = graph.sampling.map_or(1.0, |s| s.width as f32 / s.height.max(1) as f32);
+	let font = egui::TextStyle::Small.resolve(ui.style());
+	let promoted = graph.promoted();
+	let mut clicked = None;
+	for id in 0 .. graph.nodes.len() {
+		let rect = node_rect(&graph.nodes[id]);
+		// rendering thumbnails only for nodes in view keeps huge trees cheap
+		if !ui.clip_rect().intersects(rect) {
+			continue;
+		}
+		let resp = ui.interact(rect, ui.id().with(("graphNode", id)), egui::Sense::click());
+		let visuals = ui.style().interact_selectable(&resp, promoted == Some(id));
+		let painter = ui.painter();
+		painter.rect(rect, 4.0, visuals.weak_bg_fill, visuals.bg_stroke);
+
+		let inner = rect.shrink(4.0);
+		let area = egui::Rect::from_min_size(inner.min, egui::vec2(inner.width(), 64.0));
+		let fitted = if aspect >= area.aspect_ratio() {
+			egui::vec2(area.width(), area.width() / aspect)
+		} else {
+			egui::vec2(area.height() * aspect, area.height())
+		};
+		let thumbRect = egui::Rect::from_center_size(area.center(), fitted);
+		let mut hover = format!("#{id} {}", graph.nodes[id].label);
+		let (center, align) = (area.center(), egui::Align2::CENTER_CENTER);
+		match graph.thumbnail(id) {
+			Thumbnail::Ready { texture, .. } => {
+				let uv = egui::Rect::from_min_max(egui::pos2(0.0, 0.0), egui::pos2(1.0, 1.0));
+				painter.image(*texture, thumbRect, uv, egui::Color32::WHITE);
+			},
+			Thumbnail::Failed(err) => {
+				let color = ui.visuals().warn_fg_color;
+				painter.text(center, align, "⚠", font.clone(), color);
+				hover += &format!("\n{err}");
+			},
+			Thumbnail::Queued | Thumbnail::Running(_) => {
+				let color = ui.visuals().weak_text_color();
+				painter.text(center, align, "…", font.clone(), color);
+			},
+		}
+		let label = short_label(&format!("#{id} {}", graph.nodes[id].label));
+		let bottom = egui::pos2(inner.center().x, inner.bottom());
+		let color = visuals.text_color();
+		painter.text(bottom, egui::Align2::CENTER_BOTTOM, label, font.clone(), color);
+
+		if resp.clicked() {
+			clicked = Some(id);
+		}
+		resp.on_hover_text(hover);
+	}
+	match clicked {
+		Some(id) if promoted == Some(id) => graph.demote(),
+		Some(id) => graph.promote(id),
+		None => {},
+	}
+}
+
+/// Renders queued thumbnails and the promoted subtree in the background, and uploads them once
+/// they're done.
+pub fn update_graph(
+	mut graph: ResMut<NoiseGraph>,
+	mut images: ResMut<Assets<Image>>,
+	mut eguiCtx: EguiContexts,
+	colormap: Res<colormap::ColormapSettings>,
+) {
+	let graph = &mut *graph;
+
+	let mut running = graph
+		.thumbnails
+		.values()
+		.filter(|thumbnail| matches!(thumbnail, Thumbnail::Running(_)))
+		.count();
+	while running < maxRunningJobs {
+		let Some(id) = graph.queue.pop_front() else {
+			break;
+		};
+		let (Some(tree), Some(sampling)) = (&graph.tree, graph.sampling) else {
+			break;
+		};
+		// dropped while still queued
+		if !matches!(graph.thumbnails.get(&id), Some(Thumbnail::Queued)) {
+			continue;
+		}
+		let Some(node) = tree.node(id).cloned() else {
+			continue;
+		};
+		let task = AsyncComputeTaskPool::get()
+			.spawn(async move { workers::catch_panic(move || Ok(sampling.thumbnail(&node))) });
+		graph.thumbnails.insert(id, Thumbnail::Running(task));
+		running += 1;
+	}
+
+	for thumbnail in graph.thumbnails.values_mut() {
+		let Thumbnail::Running(task) = thumbnail else {
+			continue;
+		};
+		if !task.is_finished() {
+			continue;
+		}
+		let res = block_on(task);
+		*thumbnail = match res {
+			Ok(samples) => {
+				let image = images.add(thumbnails::thumbnail_image(&samples));
+				let texture = eguiCtx.add_image(image.clone_weak());
+				Thumbnail::Ready {
+					image,
+					texture,
+					lastUse: graph.clock,
+				}
+			},
+			Err(err) => Thumbnail::Failed(err),
+		};
+	}
+
+	let mut ready: Vec<_> = graph
+		.thumbnails
+		.iter()
+		.filter_map(|(&id, thumbnail)| match thumbnail {
+			Thumbnail::Ready { lastUse, .. } => Some((*lastUse, id)),
+			_ => None,
+		})
+		.collect();
+	if ready.len() > maxThumbnails {
+		ready.sort_unstable();
+		for &(_, id) in &ready[.. ready.len() - maxThumbnails] {
+			if let Some(Thumbnail::Ready { image, .. }) = graph.thumbnails.remove(&id) {
+				graph.released.push(image);
+			}
+		}
+	}
+
+	if let Some(Promotion::Running { id, task, .. }) = &mut graph.promotion {
+		if task.is_finished() {
+			let (id, res) = (*id, block_on(task));
+			graph.promotion = Some(match res {
+				Ok(output) => {
+					let image = images.add(snapshot::display_image(&output, &colormap.colormap));
+					Promotion::Shown {
+						id,
+						output: Box::new(output),
+						image,
+					}
+				},
+				Err(error) => Promotion::Failed { id, error },
+			});
+		}
+	}
+	if let Some(Promotion::Shown { output, image, .. }) = &graph.promotion {
+		if colormap.is_changed() {
+			if let Some(image) = images.get_mut(image) {
+				output.fill_image(image, &colormap.colormap);
+			}
+		}
+	}
+
+	for image in graph.released.drain(..) {
+		eguiCtx.remove_image(&image);
+		images.remove(&image);
+	}
+}
@@ -0,0 +1,116 @@
+use bevy::color::palettes::css;
+use bevy::prelude::*;
+use bevy_egui::EguiContexts;
+
+use crate::gizmos::View3dGizmos;
+use crate::water::WaterSettings;
+use crate::{scene, SelectedTab, Tab, UiState, ViewportRects};
+
+/// Handle radius relative to its distance from the camera, keeping it the same size on screen.
+const handleScale: f32 = 0.02;
+/// Step water levels snap to while Ctrl is held.
+const levelSnap: f32 = 0.05;
+
+/// Draggable handles in the 3D view. While one is hovered or dragged, it owns the left mouse
+/// button and the camera controller leaves it alone.
+#[derive(Resource, Default)]
+pub struct Handles {
+	pub hovered: bool,
+	/// Offset between the handle and the grab point along the drag axis, while dragging.
+	dragging: Option<f32>,
+}
+
+impl Handles {
+	pub fn captures_pointer(&self) -> bool {
+		self.hovered || self.dragging.is_some()
+	}
+}
+
+/// Distance along `ray` to its first intersection with a sphere, if any.
+fn ray_sphere(ray: Ray3d, center: Vec3, radius: f32) -> Option<f32> {
+	let offset = ray.origin - center;
+	let b = offset.dot(*ray.direction);
+	let c = offset.length_squared() - radius * radius;
+	let discriminant = b * b - c;
+	if discriminant < 0.0 {
+		return None;
+	}
+	let t = -b - discriminant.sqrt();
+	(t >= 0.0).then_some(t)
+}
+
+/// Height on the vertical line through `anchor` closest to `ray`.
+fn closest_height(ray: Ray3d, anchor: Vec3) -> Option<f32> {
+	// plane containing the vertical axis, facing the camera as much as possible
+	let normal = ray.direction.reject_from_normalized(Vec3::Y);
+	if normal.length_squared() < 1e-8 {
+		return None;
+	}
+	let t = ray.intersect_plane(anchor, InfinitePlane3d::new(normal))?;
+	Some(ray.get_point(t).y)
+}
+
+pub fn water_handle(
+	mut eguiCtx: EguiContexts,
+	mut gizmos: Gizmos<View3dGizmos>,
+	mut handles: ResMut<Handles>,
+	mut water: ResMut<WaterSettings>,
+	uiState: Res<UiState>,
+	selectedTab: Res<SelectedTab>,
+	sceneMode: Res<scene::SceneMode>,
+	viewportRects: Res<ViewportRects>,
+	keyboard: Res<ButtonInput<KeyCode>>,
+	mouseButtons: Res<ButtonInput<MouseButton>>,
+	camera: Query<(&Camera, &GlobalTransform), With<Camera3d>>,
+) {
+	let active = selectedTab.shows(Tab::D3) && *sceneMode == scene::SceneMode::Single;
+	if !active || uiState.height == 0.0 {
+		*handles = default();
+		return;
+	}
+
+	// over the middle of the terrain
+	let anchor = vec3(0.0, water.level * uiState.height, 0.0);
+	let (camera, cameraTransform) = camera.single();
+	let viewportRect = &viewportRects.d3;
+	let radius = handleScale * cameraTransform.translation().distance(anchor);
+	let ray = eguiCtx
+		.ctx_mut()
+		.pointer_hover_pos()
+		.filter(|&pos| viewportRect.normalized(pos).is_some())
+		.and_then(|pos| camera.viewport_to_world(cameraTransform, viewportRect.to_pixels(pos)));
+
+	handles.hovered = ray.is_some_and(|ray| ray_sphere(ray, anchor, radius).is_some());
+	if handles.hovered && mouseButtons.just_pressed(MouseButton::Left) {
+		handles.dragging = ray
+			.and_then(|ray| closest_height(ray, anchor))
+			.map(|y| anchor.y - y);
+	}
+	if !mouseButtons.pressed(MouseButton::Left) {
+		handles.dragging = None;
+	}
+
+	if let Some((offset, ray)) = handles.dragging.zip(ray) {
+		if let Some(y) = closest_height(ray, anchor) {
+			let mut level = (y + offset) / uiState.height;
+			if keyboard.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]) {
+				level = (level / levelSnap).round() * levelSnap;
+			}
+			// same field the water settings edit, so everything downstream reacts the same way
+			if water.level != level {
+				water.level = level;
+			}
+		}
+	}
+
+	let color = if handles.dragging.is_some() {
+		css::ORANGE
+	} else if handles.hovered {
+		css::YELLOW
+	} else {
+		css::WHITE
+	};
+	let anchor = vec3(0.0, water.level * uiState.height, 0.0);
+	gizmos.sphere(anchor, Quat::IDENTITY, radius, color);
+	gizmos.line(anchor - Vec3::Y * radius * 4.0, anchor + Vec3::Y * radius * 4.0, color);
+}
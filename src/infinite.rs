@@ -0,0 +1,219 @@
+use std::sync::Arc;
+
+use bevy::math::dvec2;
+use bevy::prelude::*;
+use bevy::render::mesh::PrimitiveTopology;
+use bevy::render::render_asset::RenderAssetUsages;
+use bevy::tasks::{block_on, AsyncComputeTaskPool, Task};
+use bevy::utils::HashMap;
+use noisebench_core::grid::{sample_grid, GridSpec};
+
+use crate::scene::{SceneMode, SceneOwned, TerrainSurface};
+use crate::{domain, lua, workers, NoiseOutput, TerrainScale, UiState};
+
+/// Samples along each side of a tile. Neighbouring tiles share their edge samples.
+pub const tileSamples: usize = 65;
+/// Tiles kept around the one under the camera along each axis, in either direction.
+pub const tileRadius: i32 = 2;
+/// Most tiles generated at once, leaving the rest of the pool to generations.
+const maxPendingTiles: usize = 4;
+
+/// What the tiles are generated from: the live output's tree, sampled over the same square of the
+/// evaluation plane the output covers and its neighbours.
+#[derive(Clone)]
+struct TileSource {
+	noise: Arc<lua::Noise>,
+	range: (f32, f32),
+	domain: domain::Domain,
+	precision: lua::Precision,
+	sliceZ: f64,
+}
+
+impl TileSource {
+	fn of(output: &NoiseOutput) -> Option<Self> {
+		Some(Self {
+			noise: output.noise.clone()?,
+			range: output.range,
+			domain: output.domain,
+			precision: output.precision,
+			sliceZ: output.sliceZ,
+		})
+	}
+
+	fn same(&self, other: &Self) -> bool {
+		Arc::ptr_eq(&self.noise, &other.noise) &&
+			self.range == other.range &&
+			self.domain == other.domain &&
+			self.precision == other.precision &&
+			self.sliceZ == other.sliceZ
+	}
+
+	/// Samples of tile `tile`, whose square lies `tile` domain sizes away from the output's.
+	fn sample(&self, tile: IVec2) -> Vec<f32> {
+		let size = self.domain.size;
+		let spec = GridSpec {
+			origin: self.domain.origin + dvec2(tile.x as f64, tile.y as f64) * size,
+			size,
+			z: self.sliceZ,
+			precision: self.precision,
+			..GridSpec::new(tileSamples)
+		};
+		sample_grid(&self.noise, spec)
+	}
+}
+
+enum Tile {
+	Pending(Task<Result<Vec<f32>, String>>),
+	Shown {
+		entity: Entity,
+		mesh: Handle<Mesh>,
+	},
+	Failed,
+}
+
+/// The terrain of `SceneMode::Infinite`, present only while that mode is active: the live
+/// output's tree generated tile by tile around the camera, one output's extent per tile, with
+/// tiles out of reach dropped again. Tile `(0, 0)` is where the single terrain would be.
+#[derive(Resource)]
+pub struct InfiniteTerrain {
+	material: Handle<StandardMaterial>,
+	source: Option<TileSource>,
+	scale: Option<TerrainScale>,
+	tiles: HashMap<IVec2, Tile>,
+}
+
+impl InfiniteTerrain {
+	pub fn new(material: Handle<StandardMaterial>) -> Self {
+		Self {
+			material,
+			source: None,
+			scale: None,
+			tiles: default(),
+		}
+	}
+
+	/// Tiles being generated.
+	pub fn pending(&self) -> usize {
+		self.tiles.values().filter(|tile| matches!(tile, Tile::Pending(_))).count()
+	}
+
+	/// Tiles with a mesh in the scene.
+	pub fn shown(&self) -> usize {
+		self.tiles.values().filter(|tile| matches!(tile, Tile::Shown { .. })).count()
+	}
+
+	/// Drops `tile`, cancelling its generation or despawning it and releasing its mesh.
+	fn remove(&mut self, tile: IVec2, cmd: &mut Commands, meshes: &mut Assets<Mesh>) {
+		if let Some(Tile::Shown { entity, mesh }) = self.tiles.remove(&tile) {
+			if let Some(ent) = cmd.get_entity(entity) {
+				ent.despawn_recursive();
+			}
+			meshes.remove(&mesh);
+		}
+	}
+
+	fn clear(&mut self, cmd: &mut Commands, meshes: &mut Assets<Mesh>) {
+		let tiles: Vec<IVec2> = self.tiles.keys().copied().collect();
+		for tile in tiles {
+			self.remove(tile, cmd, meshes);
+		}
+	}
+}
+
+/// Tile under the ground position `pos`, for terrain `extent` wide.
+fn tile_at(pos: Vec2, extent: f32) -> IVec2 {
+	(pos / extent).round().as_ivec2()
+}
+
+/// Keeps the tiles within `tileRadius` of the camera generated and shown, starting with the
+/// nearest, and drops those further out. A new live tree or terrain scale starts over.
+pub fn update_infinite_terrain(
+	mut cmd: Commands,
+	terrain: Option<ResMut<InfiniteTerrain>>,
+	noiseOutput: Option<Res<NoiseOutput>>,
+	uiState: Res<UiState>,
+	camera: Query<&GlobalTransform, With<Camera3d>>,
+	mut meshes: ResMut<Assets<Mesh>>,
+) {
+	let Some(mut terrain) = terrain else {
+		return;
+	};
+	let terrain = &mut *terrain;
+	let source = noiseOutput.as_deref().and_then(TileSource::of);
+	let scale = uiState.terrain_scale();
+	let sameSource = match (&source, &terrain.source) {
+		(Some(source), Some(current)) => source.same(current),
+		(None, None) => true,
+		_ => false,
+	};
+	if !sameSource || terrain.scale != Some(scale) {
+		terrain.clear(&mut cmd, &mut meshes);
+		terrain.source = source;
+		terrain.scale = Some(scale);
+	}
+	let (Some(source), Ok(camera)) = (terrain.source.clone(), camera.get_single()) else {
+		return;
+	};
+
+	let eye = camera.translation();
+	let center = tile_at(vec2(eye.x, eye.z), scale.extent);
+	let far: Vec<IVec2> = terrain
+		.tiles
+		.keys()
+		.filter(|&&tile| (tile - center).abs().max_element() > tileRadius)
+		.copied()
+		.collect();
+	for tile in far {
+		terrain.remove(tile, &mut cmd, &mut meshes);
+	}
+
+	for (&tile, state) in &mut terrain.tiles {
+		let Tile::Pending(task) = state else {
+			continue;
+		};
+		if !task.is_finished() {
+			continue;
+		}
+		*state = match block_on(task) {
+			Ok(samples) => {
+				let mut output = NoiseOutput::sized(tileSamples, tileSamples);
+				output.samples = samples;
+				output.range = source.range;
+				let mut mesh = Mesh::new(PrimitiveTopology::TriangleList, RenderAssetUsages::all());
+				output.update_mesh(&mut mesh, scale);
+				let mesh = meshes.add(mesh);
+				let offset = tile.as_vec2() * scale.extent;
+				let entity = cmd
+					.spawn((
+						SceneOwned(SceneMode::Infinite),
+						TerrainSurface,
+						PbrBundle {
+							mesh: mesh.clone(),
+							material: terrain.material.clone(),
+							transform: Transform::from_xyz(offset.x, 0.0, offset.y),
+							..default()
+						},
+					))
+					.id();
+				Tile::Shown { entity, mesh }
+			},
+			Err(err) => {
+				warn!("failed to generate infinite terrain tile {tile}: {err}");
+				Tile::Failed
+			},
+		};
+	}
+
+	let mut wanted: Vec<IVec2> = (-tileRadius ..= tileRadius)
+		.flat_map(|y| (-tileRadius ..= tileRadius).map(move |x| center + ivec2(x, y)))
+		.filter(|tile| !terrain.tiles.contains_key(tile))
+		.collect();
+	wanted.sort_by_key(|&tile| (tile - center).length_squared());
+	let free = maxPendingTiles.saturating_sub(terrain.pending());
+	for tile in wanted.into_iter().take(free) {
+		let source = source.clone();
+		let task = AsyncComputeTaskPool::get()
+			.spawn(async move { workers::catch_panic(move || Ok(source.sample(tile))) });
+		terrain.tiles.insert(tile, Tile::Pending(task));
+	}
+}
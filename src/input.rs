@@ -0,0 +1,145 @@
+use bevy::input::mouse::{MouseMotion, MouseWheel};
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContexts};
+
+use crate::{SelectedTab, Tab, ViewportRect, ViewportRects};
+
+/// Keys that turn a shortcut key into another shortcut, like S into Ctrl+S.
+const modifierKeys: [KeyCode; 8] = [
+	KeyCode::ControlLeft,
+	KeyCode::ControlRight,
+	KeyCode::ShiftLeft,
+	KeyCode::ShiftRight,
+	KeyCode::AltLeft,
+	KeyCode::AltRight,
+	KeyCode::SuperLeft,
+	KeyCode::SuperRight,
+];
+
+/// Viewport mouse input for the frame. `dispatch_input` drains the raw events once and hands them
+/// to a single pane: a drag to the pane it started on, the wheel to the pane under the pointer.
+/// Nothing carries over a tab switch.
+#[derive(Resource, Default)]
+pub struct ViewportInput {
+	/// Pointer motion while a left button drag that started on `dragPane` is held.
+	dragMotion: Vec2,
+	/// Wheel lines scrolled while hovering `hoveredPane`.
+	scroll: f32,
+	/// Whether egui leaves the keyboard to the viewport, i.e. no text field has focus.
+	keyboard: bool,
+	dragPane: Option<Tab>,
+	hoveredPane: Option<Tab>,
+	/// Pane the keyboard goes to: the shown one, or the last one clicked while both are.
+	focused: Tab,
+	tab: Option<Tab>,
+}
+
+impl ViewportInput {
+	pub fn drag_motion(&self, pane: Tab) -> Vec2 {
+		if self.dragPane == Some(pane) { self.dragMotion } else { Vec2::ZERO }
+	}
+
+	pub fn scroll(&self, pane: Tab) -> f32 {
+		if self.hoveredPane == Some(pane) { self.scroll } else { 0.0 }
+	}
+
+	/// Whether `pane` takes keyboard input this frame.
+	pub fn keyboard(&self, pane: Tab) -> bool {
+		self.keyboard && self.focused == pane
+	}
+
+	pub fn focused(&self) -> Tab {
+		self.focused
+	}
+
+	/// Input for the frame after `self`, with `tab` selected, the pointer over `hoveredPane`,
+	/// `buttons` as they are now and the frame's `motion` and `scroll` events. `keyboard` is
+	/// whether egui leaves the keyboard to the viewport.
+	pub fn route<'a>(
+		&self,
+		tab: Tab,
+		hoveredPane: Option<Tab>,
+		buttons: &ButtonInput<MouseButton>,
+		motion: impl IntoIterator<Item = &'a MouseMotion>,
+		scroll: impl IntoIterator<Item = &'a MouseWheel>,
+		keyboard: bool,
+	) -> Self {
+		let motion: Vec2 = motion.into_iter().map(|ev| ev.delta).sum();
+		let scroll: f32 = scroll.into_iter().map(|ev| ev.y).sum();
+		let switched = self.switched(tab);
+		let hoveredPane = hoveredPane.filter(|&pane| SelectedTab(tab).shows(pane));
+		let mut dragPane = self.dragPane.filter(|_| !switched);
+		if buttons.just_pressed(MouseButton::Left) {
+			dragPane = hoveredPane;
+		}
+		if !buttons.pressed(MouseButton::Left) {
+			dragPane = None;
+		}
+		let focused = match tab {
+			Tab::Both => {
+				let clicked = buttons.get_just_pressed().next().is_some();
+				hoveredPane.filter(|_| clicked).unwrap_or(self.focused)
+			},
+			pane => pane,
+		};
+
+		Self {
+			dragMotion: if dragPane.is_some() { motion } else { Vec2::ZERO },
+			scroll: if switched { 0.0 } else { scroll },
+			keyboard,
+			dragPane,
+			hoveredPane: hoveredPane.filter(|_| !switched),
+			focused,
+			tab: Some(tab),
+		}
+	}
+
+	/// Whether `tab` replaces the tab of the last frame.
+	fn switched(&self, tab: Tab) -> bool {
+		self.tab.is_some_and(|old| old != tab)
+	}
+}
+
+/// Whether `pos` is on the viewport image itself rather than a window or popup over it.
+pub fn over_viewport(ctx: &egui::Context, viewportRect: &ViewportRect, pos: egui::Pos2) -> bool {
+	viewportRect.normalized(pos).is_some() &&
+		ctx.layer_id_at(pos)
+			.map_or(true, |layer| layer.order == egui::Order::Background)
+}
+
+/// Pane whose image is at `pos`, if one is.
+pub fn pane_at(ctx: &egui::Context, rects: &ViewportRects, pos: egui::Pos2) -> Option<Tab> {
+	let mut panes = rects.panes().into_iter();
+	panes.find(|(_, rect)| over_viewport(ctx, rect, pos)).map(|(pane, _)| pane)
+}
+
+/// Whether `key` was just pressed with no modifier held.
+pub fn just_pressed_alone(keyboard: &ButtonInput<KeyCode>, key: KeyCode) -> bool {
+	keyboard.just_pressed(key) && !keyboard.any_pressed(modifierKeys)
+}
+
+pub fn dispatch_input(
+	mut eguiCtx: EguiContexts,
+	mut input: ResMut<ViewportInput>,
+	selectedTab: Res<SelectedTab>,
+	viewportRects: Res<ViewportRects>,
+	mouseButtons: Res<ButtonInput<MouseButton>>,
+	mut mouseMotion: EventReader<MouseMotion>,
+	mut mouseScroll: EventReader<MouseWheel>,
+) {
+	let ctx = eguiCtx.ctx_mut();
+	let tab = selectedTab.0;
+	if input.switched(tab) && input.dragPane.is_some() {
+		warn!("switched tabs mid-drag, dropping the drag instead of handing it to the {tab:?} camera");
+	}
+	let hoveredPane = ctx.pointer_hover_pos().and_then(|pos| pane_at(ctx, &viewportRects, pos));
+	let keyboard = !ctx.wants_keyboard_input();
+	*input = input.route(
+		tab,
+		hoveredPane,
+		&mouseButtons,
+		mouseMotion.read(),
+		mouseScroll.read(),
+		keyboard,
+	);
+}
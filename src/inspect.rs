@@ -0,0 +1,223 @@
+use std::sync::Arc;
+
+use bevy::math::DVec2;
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContexts};
+
+use crate::domain::{self, Domain};
+use crate::lua::{Noise, NoiseStats};
+use crate::theme::Theme;
+use crate::{NoiseOutput, SelectedTab, Tab, UiState, ViewportRects};
+
+/// Node picked in the inspector, whose input coordinates the hover readout also shows.
+#[derive(Resource, Default)]
+pub struct Inspector {
+	pub open: bool,
+	pub node: Option<usize>,
+	/// Statistics of the last tree shown, kept until the output's tree is replaced.
+	stats: Option<(Arc<Noise>, NoiseStats)>,
+}
+
+impl Inspector {
+	fn stats(&mut self, noise: &Arc<Noise>) -> &NoiseStats {
+		if !self.stats.as_ref().is_some_and(|(tree, _)| Arc::ptr_eq(tree, noise)) {
+			self.stats = Some((noise.clone(), noise.stats()));
+		}
+		&self.stats.as_ref().unwrap().1
+	}
+}
+
+/// A point of the 2D view under the pointer.
+#[derive(Clone, Copy, Debug)]
+pub struct ViewportPoint {
+	/// Continuous position in heightmap texels, `(0, 0)` being the top left corner of the image.
+	pub texel: Vec2,
+	/// Evaluation coordinates, as passed to `Noise::eval` at the root.
+	pub domain: DVec2,
+}
+
+/// Maps a physical pixel of the 2D viewport to coordinates of `output`, undoing the 2D camera's
+/// pan and zoom. The heightmap sprite is centered on the origin with one world unit per texel,
+/// unless it covers another domain than the unit square, see `Domain::placement`.
+pub fn viewport_to_heightmap(
+	pixel: Vec2,
+	viewportSize: Vec2,
+	camera: &Transform,
+	output: &NoiseOutput,
+	viewDiameter: usize,
+) -> ViewportPoint {
+	let centered = (pixel - viewportSize / 2.0) * camera.scale.truncate();
+	let world = camera.translation.truncate() + vec2(centered.x, -centered.y);
+	if output.domain.is_unit() {
+		// sample i sits at the center of texel i
+		let half = vec2(output.width as f32, output.height as f32) / 2.0;
+		let texel = vec2(world.x + half.x, half.y - world.y);
+		let domain = output.domain_position(texel - 0.5);
+		return ViewportPoint { texel, domain };
+	}
+	// zoomed in domains are only followed for square outputs, see `UiState::can_follow_camera`
+	let Domain { origin, size } = output.domain;
+	let domain = domain::to_domain(world, viewDiameter);
+	let steps = output.width.saturating_sub(1).max(1) as f64;
+	let texel = ((domain - origin) / size * steps + 0.5).as_vec2();
+	ViewportPoint { texel, domain }
+}
+
+pub fn hover_readout(
+	mut eguiCtx: EguiContexts,
+	selectedTab: Res<SelectedTab>,
+	viewportRects: Res<ViewportRects>,
+	camera: Query<&Transform, With<Camera2d>>,
+	noiseOutput: Option<Res<NoiseOutput>>,
+	uiState: Res<UiState>,
+	mut inspector: ResMut<Inspector>,
+	theme: Res<Theme>,
+) {
+	let ctx = eguiCtx.ctx_mut();
+	let noise = noiseOutput.as_ref().and_then(|output| output.noise.clone());
+
+	if inspector.open {
+		let mut open = true;
+		egui::Window::new("Inspector")
+			.open(&mut open)
+			.show(ctx, |ui| match &noise {
+				None => {
+					ui.label("no generated output");
+				},
+				Some(noise) => {
+					let stats = inspector.stats(noise).clone();
+					egui::CollapsingHeader::new("AST")
+						.default_open(true)
+						.show(ui, |ui| ast_summary(ui, &stats));
+					egui::ScrollArea::vertical().show(ui, |ui| {
+						let evaluations = &stats.perNode;
+						node_tree(ui, noise, &mut 0, &mut inspector.node, evaluations);
+					});
+				},
+			});
+		inspector.open = open;
+	}
+
+	if !selectedTab.shows(Tab::D2) {
+		return;
+	}
+	let viewportRect = &viewportRects.d2;
+	let Some(output) = noiseOutput else {
+		return;
+	};
+	let Some(pos) = ctx.pointer_hover_pos() else {
+		return;
+	};
+	if viewportRect.normalized(pos).is_none() {
+		return;
+	}
+
+	let point = viewport_to_heightmap(
+		viewportRect.to_pixels(pos),
+		viewportRect.pixel_size(),
+		camera.single(),
+		&output,
+		uiState.output_size().0,
+	);
+	let inside = point.texel.cmpge(Vec2::ZERO).all() &&
+		point.texel.cmplt(vec2(output.width as f32, output.height as f32)).all();
+
+	egui::Area::new(egui::Id::new("hoverReadout"))
+		.fixed_pos(viewportRect.rect.left_bottom() + egui::vec2(8.0, -8.0))
+		.pivot(egui::Align2::LEFT_BOTTOM)
+		.interactable(false)
+		.show(ctx, |ui| {
+			theme.overlay_frame(ui.style()).show(ui, |ui| {
+				ui.label(format!("domain ({:.4}, {:.4})", point.domain.x, point.domain.y));
+				if inside {
+					let (x, y) = (point.texel.x as usize, point.texel.y as usize);
+					let value = output.samples[y * output.width + x];
+					ui.label(format!("sample [{x}, {y}] = {value:.4}"));
+				}
+
+				let Some((noise, id)) = noise.as_ref().zip(inspector.node) else {
+					return;
+				};
+				let Some(node) = noise.node(id) else {
+					return;
+				};
+				let chain = noise.coord_chain(id).unwrap_or_default();
+				match chain.apply(point.domain) {
+					Some(pos) => {
+						ui.label(format!(
+							"#{id} {} sees ({:.4}, {:.4})",
+							node.kind_name(),
+							pos.x,
+							pos.y
+						));
+					},
+					None => {
+						let opaque = chain.opaque().unwrap_or("?");
+						ui.label(format!("#{id} {} sees n/a (under {opaque})", node.kind_name()));
+					},
+				}
+			});
+		});
+}
+
+/// Size and cost of the tree, with a row per node kind.
+fn ast_summary(ui: &mut egui::Ui, stats: &NoiseStats) {
+	ui.label(format!("{} nodes, {} deep", stats.nodes(), stats.depth));
+	ui.label(format!("~{} source evaluations per sample", stats.source_evaluations()))
+		.on_hover_text(
+			"Noise sources, images and functions evaluated per sample, through octaves, tiling and \
+			 gradient magnitudes. Both sides of selects are counted",
+		);
+	egui::Grid::new("astKinds").striped(true).show(ui, |ui| {
+		ui.strong("Kind");
+		ui.strong("Nodes");
+		ui.strong("Evaluations");
+		ui.end_row();
+		for (kind, count) in &stats.kinds {
+			ui.label(*kind);
+			ui.label(count.to_string());
+			ui.label(stats.evaluations[kind].to_string());
+			ui.end_row();
+		}
+	});
+}
+
+/// Label of a node in the tree, with how many times a sample evaluates it if that isn't once.
+fn node_label(id: usize, node: &Noise, evaluations: f64) -> String {
+	if evaluations == 1.0 {
+		format!("#{id} {node}")
+	} else {
+		format!("#{id} {node} ×{evaluations}")
+	}
+}
+
+fn node_tree(
+	ui: &mut egui::Ui,
+	node: &Noise,
+	next: &mut usize,
+	selected: &mut Option<usize>,
+	evaluations: &[f64],
+) {
+	let id = *next;
+	let mut size = 0;
+	node.visit(&mut |_, _| size += 1);
+	// collapsed subtrees aren't walked, so skip over their IDs up front
+	*next += size;
+	let mut childId = id + 1;
+	let children = node.children();
+	let label = node_label(id, node, evaluations[id]);
+	if children.is_empty() {
+		ui.selectable_value(selected, Some(id), label);
+		return;
+	}
+
+	egui::CollapsingHeader::new(egui::RichText::new(label).strong())
+		.id_source(id)
+		.default_open(true)
+		.show(ui, |ui| {
+			ui.selectable_value(selected, Some(id), "select");
+			for child in children {
+				node_tree(ui, child, &mut childId, selected, evaluations);
+			}
+		});
+}
@@ -0,0 +1,207 @@
+use bevy::ecs::system::SystemState;
+use bevy::prelude::*;
+use bevy_egui::egui;
+
+use crate::{
+	colormap,
+	lod,
+	show_output,
+	textures,
+	upload_output,
+	HeightmapSprite,
+	Heightmaps,
+	InternedPath,
+	NoiseGenRequest,
+	NoiseOutput,
+	ScriptError,
+	UiState,
+};
+
+/// Layers offered in the toolbar. Nothing else assumes a count, it's only kept low for the UI.
+pub const layerCount: usize = 2;
+
+/// Gap between the layers of the split view, in pixels of the 2D view.
+const splitGap: f32 = 16.0;
+
+/// A script generated and displayed independently of the others, so outputs can be compared side
+/// by side. The active one, `UiState::activeLayer`, is the one the toolbar edits and the 3D view,
+/// tools and exports work on.
+pub struct Layer {
+	pub name: String,
+	pub script: Option<InternedPath>,
+	/// Hidden layers are left out of the split view and not regenerated until shown again. The
+	/// active layer is always visible.
+	pub visible: bool,
+	/// 2D image of the layer while it's inactive. The active layer is drawn from
+	/// `Heightmaps::image` like before there were layers.
+	pub image: Handle<Image>,
+	/// Output of an inactive layer, the active layer's being the `NoiseOutput` resource.
+	pub output: Option<NoiseOutput>,
+	/// Why the last generation failed while inactive, the active layer's being `ScriptError`.
+	pub error: Option<String>,
+}
+
+impl Layer {
+	pub fn new(index: usize) -> Self {
+		Self {
+			name: format!("Layer {}", index + 1),
+			script: None,
+			visible: true,
+			image: default(),
+			output: None,
+			error: None,
+		}
+	}
+}
+
+/// Sprite of an inactive layer in the split view, by index into `UiState::layers`.
+#[derive(Component)]
+pub struct LayerSprite(pub usize);
+
+pub fn layer_ui(
+	ui: &mut egui::Ui,
+	uiState: &mut UiState,
+	noiseGenRequests: &mut EventWriter<NoiseGenRequest>,
+) {
+	ui.label("Layer");
+	let mut activated = None;
+	for (index, layer) in uiState.layers.iter_mut().enumerate() {
+		let active = index == uiState.activeLayer;
+		let script = layer.script.as_ref().map_or("no script", |path| &path.display);
+		let resp = ui.selectable_label(active, &layer.name).on_hover_ui(|ui| {
+			ui.label(script);
+			if let Some(err) = &layer.error {
+				ui.colored_label(ui.visuals().error_fg_color, err);
+			}
+		});
+		if resp.clicked() && !active {
+			activated = Some(index);
+		}
+		let resp = ui
+			.add_enabled(!active, egui::Checkbox::without_text(&mut layer.visible))
+			.on_hover_text("Show in the split view");
+		// hidden layers aren't kept up to date
+		if resp.changed() && layer.visible {
+			noiseGenRequests.send(NoiseGenRequest::LayerChanged(index));
+		}
+	}
+	if let Some(index) = activated {
+		uiState.activeLayer = index;
+		uiState.layers[index].visible = true;
+	}
+	ui.toggle_value(&mut uiState.splitView, "Split")
+		.on_hover_text("Show the visible layers side by side in the 2D view");
+}
+
+/// Moves outputs between the `NoiseOutput` resource and the layer slots when another layer is
+/// activated, so tools and the 3D view switch to it without generating again. Exclusive, so the
+/// resource is never briefly missing or stale in between.
+pub fn switch_active_layer(world: &mut World, mut shown: Local<usize>) {
+	let active = world.resource::<UiState>().activeLayer;
+	if active == *shown {
+		return;
+	}
+	let previous = std::mem::replace(&mut *shown, active);
+	info!("switching from layer {previous} to layer {active}");
+
+	let outgoing = world.remove_resource::<NoiseOutput>();
+	let outgoingError = world.resource_mut::<ScriptError>().0.take();
+	let mut uiState = world.resource_mut::<UiState>();
+	let incoming = uiState.layers[active].output.take();
+	let incomingError = uiState.layers[active].error.take();
+	uiState.layers[previous].output = outgoing;
+	uiState.layers[previous].error = outgoingError;
+	drop(uiState);
+	world.resource_mut::<ScriptError>().0 = incomingError;
+
+	let mut state = SystemState::<(
+		Res<UiState>,
+		Res<Heightmaps>,
+		Option<ResMut<lod::TerrainChunks>>,
+		ResMut<Assets<Image>>,
+		ResMut<Assets<Mesh>>,
+		Res<textures::TextureLimits>,
+		Res<colormap::ColormapSettings>,
+	)>::new(world);
+	let (uiState, heightmaps, mut terrain, mut images, mut meshes, limits, colormap) =
+		state.get_mut(world);
+	let colormap = &colormap.colormap;
+	let slot = &uiState.layers[previous];
+	if let Some(output) = &slot.output {
+		upload_output(output, &slot.image, None, &mut images, &limits, colormap);
+	}
+	if let Some(output) = &incoming {
+		show_output(
+			output,
+			&uiState,
+			&heightmaps,
+			terrain.as_deref_mut(),
+			&mut images,
+			&mut meshes,
+			&limits,
+			colormap,
+		);
+	}
+
+	if let Some(output) = incoming {
+		world.insert_resource(output);
+	}
+	// settings may have changed while the layer was inactive, up to date layers are skipped
+	world.send_event(NoiseGenRequest::AlgorithmChanged);
+}
+
+/// Places the active and, in the split view, the other visible layers' sprites next to each other,
+/// in layer order and centered on the origin. An active output of a domain other than the unit
+/// square is laid over the part of the unit square's image it covers instead.
+pub fn layout_layer_sprites(
+	uiState: Res<UiState>,
+	heightmaps: Res<Heightmaps>,
+	noiseOutput: Option<Res<NoiseOutput>>,
+	images: Res<Assets<Image>>,
+	mut activeSprite: Query<&mut Transform, With<HeightmapSprite>>,
+	mut layerSprites: Query<
+		(&LayerSprite, &mut Transform, &mut Handle<Image>, &mut Visibility),
+		Without<HeightmapSprite>,
+	>,
+) {
+	let shown: Vec<usize> = (0 .. uiState.layers.len())
+		.filter(|&i| {
+			i == uiState.activeLayer || (uiState.splitView && uiState.layers[i].visible)
+		})
+		.collect();
+	// layers share the size, so the active image's width fits them all
+	let width = images
+		.get(&heightmaps.image)
+		.map_or(0.0, |image| image.size_f32().x);
+	let offset = |layer: usize| {
+		let slot = shown.iter().position(|&i| i == layer)?;
+		Some((slot as f32 - (shown.len() - 1) as f32 / 2.0) * (width + splitGap))
+	};
+
+	let mut transform = activeSprite.single_mut();
+	let x = offset(uiState.activeLayer).unwrap_or(0.0);
+	// the image's own width, as uploads may be downsampled
+	let placement = noiseOutput
+		.as_ref()
+		.and_then(|output| output.domain.placement(width as usize, uiState.output_size().0));
+	let (translation, scale) = placement.unwrap_or((vec2(x, 0.0), 1.0));
+	if transform.translation.truncate() != translation {
+		transform.translation = translation.extend(transform.translation.z);
+	}
+	if transform.scale.x != scale {
+		transform.scale = Vec3::new(scale, scale, 1.0);
+	}
+	for (&LayerSprite(layer), mut transform, mut image, mut visibility) in &mut layerSprites {
+		let x = offset(layer).filter(|_| layer != uiState.activeLayer);
+		let wanted = if x.is_some() { Visibility::Inherited } else { Visibility::Hidden };
+		if *visibility != wanted {
+			*visibility = wanted;
+		}
+		if let Some(x) = x.filter(|&x| transform.translation.x != x) {
+			transform.translation.x = x;
+		}
+		if *image != uiState.layers[layer].image {
+			*image = uiState.layers[layer].image.clone();
+		}
+	}
+}
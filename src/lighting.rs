@@ -0,0 +1,148 @@
+use bevy::pbr::DirectionalLightShadowMap;
+use bevy::prelude::*;
+use bevy_egui::egui;
+
+/// Height of the fill light above the horizon, in degrees. It faces the sun from the opposite
+/// side, to lift the shadowed slopes.
+const fillElevation: f32 = 30.0;
+/// Illuminance of the fill light relative to the sun's.
+const fillStrength: f32 = 0.25;
+
+/// Resolution of the sun's shadow maps.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ShadowQuality {
+	Off,
+	Low,
+	Medium,
+	#[default]
+	High,
+}
+
+impl ShadowQuality {
+	pub const all: [Self; 4] = [Self::Off, Self::Low, Self::Medium, Self::High];
+
+	pub fn label(self) -> &'static str {
+		match self {
+			Self::Off => "Off",
+			Self::Low => "2048",
+			Self::Medium => "4096",
+			Self::High => "8192",
+		}
+	}
+
+	/// Side of the shadow map, `None` without shadows.
+	pub fn size(self) -> Option<usize> {
+		match self {
+			Self::Off => None,
+			Self::Low => Some(2048),
+			Self::Medium => Some(4096),
+			Self::High => Some(8192),
+		}
+	}
+}
+
+/// The light that doesn't cast shadows, opposite the sun.
+#[derive(Component)]
+pub struct FillLight;
+
+/// The 3D view's sun, which `GradientView::Hillshade` lights the 2D view with too.
+#[derive(Resource, Clone, Debug, PartialEq)]
+pub struct LightingSettings {
+	pub open: bool,
+	/// Direction of the sun around the vertical axis in degrees, turning from +Z towards +X.
+	pub azimuth: f32,
+	/// Height of the sun above the horizon, in degrees. Just short of 90 at most, so the
+	/// direction keeps its azimuth.
+	pub elevation: f32,
+	/// Illuminance of the sun, in lux.
+	pub intensity: f32,
+	pub shadows: ShadowQuality,
+	pub fill: bool,
+}
+
+impl Default for LightingSettings {
+	fn default() -> Self {
+		Self {
+			open: false,
+			azimuth: 241.0,
+			elevation: 23.25,
+			intensity: DirectionalLight::default().illuminance,
+			shadows: default(),
+			fill: false,
+		}
+	}
+}
+
+impl LightingSettings {
+	/// Unit vector from the terrain towards the sun.
+	pub fn sun_direction(&self) -> Vec3 {
+		direction(self.azimuth, self.elevation)
+	}
+}
+
+fn direction(azimuth: f32, elevation: f32) -> Vec3 {
+	let (azimuth, elevation) = (azimuth.to_radians(), elevation.to_radians());
+	let horizontal = elevation.cos();
+	vec3(horizontal * azimuth.sin(), elevation.sin(), horizontal * azimuth.cos())
+}
+
+pub fn lighting_ui(ui: &mut egui::Ui, settings: &mut LightingSettings) {
+	egui::Grid::new("lighting").show(ui, |ui| {
+		ui.label("Azimuth");
+		ui.add(egui::Slider::new(&mut settings.azimuth, 0.0 ..= 360.0).suffix("°"));
+		ui.end_row();
+		ui.label("Elevation");
+		ui.add(egui::Slider::new(&mut settings.elevation, 0.0 ..= 89.9).suffix("°"));
+		ui.end_row();
+		ui.label("Intensity");
+		ui.add(
+			egui::Slider::new(&mut settings.intensity, 100.0 ..= 100_000.0)
+				.logarithmic(true)
+				.suffix(" lx"),
+		);
+		ui.end_row();
+		ui.label("Shadows");
+		egui::ComboBox::from_id_source("shadowQuality")
+			.selected_text(settings.shadows.label())
+			.show_ui(ui, |ui| {
+				for option in ShadowQuality::all {
+					ui.selectable_value(&mut settings.shadows, option, option.label());
+				}
+			})
+			.response
+			.on_hover_text("Shadow map resolution; lower ones are much cheaper on weak GPUs");
+		ui.end_row();
+	});
+	ui.checkbox(&mut settings.fill, "Fill light")
+		.on_hover_text("A weaker light from the opposite side, lifting the shadowed slopes");
+	if ui.button("Reset").clicked() {
+		*settings = LightingSettings {
+			open: settings.open,
+			..default()
+		};
+	}
+}
+
+/// Points the sun and the fill light and sets up shadows, whenever the settings changed.
+pub fn apply_lighting(
+	settings: Res<LightingSettings>,
+	mut lights: Query<(&mut Transform, &mut DirectionalLight, Has<FillLight>)>,
+	mut shadowMap: ResMut<DirectionalLightShadowMap>,
+) {
+	let sun = settings.sun_direction();
+	let fill = direction(settings.azimuth + 180.0, fillElevation);
+	for (mut transform, mut light, isFill) in &mut lights {
+		if isFill {
+			*transform = Transform::IDENTITY.looking_to(-fill, Vec3::Y);
+			light.illuminance = if settings.fill { settings.intensity * fillStrength } else { 0.0 };
+			light.shadows_enabled = false;
+		} else {
+			*transform = Transform::IDENTITY.looking_to(-sun, Vec3::Y);
+			light.illuminance = settings.intensity;
+			light.shadows_enabled = settings.shadows.size().is_some();
+		}
+	}
+	if let Some(size) = settings.shadows.size().filter(|&size| size != shadowMap.size) {
+		shadowMap.size = size;
+	}
+}
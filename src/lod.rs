@@ -0,0 +1,295 @@
+use bevy::prelude::*;
+use bevy::render::mesh::{Indices, PrimitiveTopology};
+use bevy::render::render_asset::RenderAssetUsages;
+use bevy::render::view::NoFrustumCulling;
+
+use crate::scene::{SceneMode, SceneOwned, TerrainSurface, ViewSettings};
+use crate::{NoiseOutput, TerrainScale, UiState};
+
+/// Cells along each side of a terrain chunk at full resolution.
+pub const chunkCells: usize = 64;
+/// Sample steps of the detail levels, full resolution first. Each divides `chunkCells`, so the
+/// levels of neighbouring chunks share every other edge vertex.
+const lodSteps: [usize; 3] = [1, 2, 4];
+/// Camera distance, in chunk widths, up to which chunks are shown at full resolution. Every
+/// further level starts at twice the distance of the one before.
+const lodDistance: f32 = 3.0;
+/// Frames between picking the chunks' levels again.
+const lodInterval: u32 = 8;
+
+/// One square of the terrain, with a mesh per detail level.
+pub struct TerrainChunk {
+	/// First sample along each axis.
+	origin: (usize, usize),
+	/// Cells along each axis, `chunkCells` but at the far edges of the terrain.
+	cells: (usize, usize),
+	/// Mesh of each of `lodSteps`.
+	lods: [Handle<Mesh>; lodSteps.len()],
+	/// Center of the full resolution mesh's bounds, which the shown level is picked by.
+	center: Vec3,
+	/// Level shown, by index into `lodSteps`.
+	lod: usize,
+	/// Spawned by `sync_terrain_chunks`.
+	entity: Option<Entity>,
+}
+
+/// The live terrain of `SceneMode::Single` and `Compare`, present only while one of those is
+/// active: the current output split into chunks `chunkCells` wide, each shown at a level of
+/// detail picked by its distance to the camera. Every chunk has a skirt hanging down from its
+/// edges, which hides the cracks between neighbours at different levels.
+#[derive(Resource)]
+pub struct TerrainChunks {
+	pub material: Handle<StandardMaterial>,
+	/// Mode the chunk entities are spawned for.
+	owner: SceneMode,
+	/// Width and height of the output the chunks are laid out for, 0 before the first.
+	size: (usize, usize),
+	chunks: Vec<TerrainChunk>,
+	/// Entities of chunks a new layout replaced, despawned by `sync_terrain_chunks`.
+	stale: Vec<Entity>,
+}
+
+impl TerrainChunks {
+	pub fn new(material: Handle<StandardMaterial>, owner: SceneMode) -> Self {
+		Self {
+			material,
+			owner,
+			size: (0, 0),
+			chunks: vec![],
+			stale: vec![],
+		}
+	}
+
+	/// Meshes of every level of every chunk.
+	pub fn meshes(&self) -> impl '_ + Iterator<Item = &Handle<Mesh>> {
+		self.chunks.iter().flat_map(|chunk| &chunk.lods)
+	}
+
+	/// Builds the chunks from `output`. They're only laid out again if its size changed;
+	/// otherwise every mesh keeps its topology and just gets new vertices, unless flat shading
+	/// split it up.
+	pub fn update(&mut self, output: &NoiseOutput, scale: TerrainScale, meshes: &mut Assets<Mesh>) {
+		if output.size() != self.size {
+			self.layout(output.size(), meshes);
+		}
+		for chunk in &mut self.chunks {
+			for (&step, handle) in lodSteps.iter().zip(&chunk.lods) {
+				let mesh = meshes.get_mut(handle).expect("chunk meshes are kept by their handles");
+				let (origin, cells) = (chunk.origin, chunk.cells);
+				let (min, max) = build_chunk_mesh(mesh, output, scale, origin, cells, step);
+				if step == 1 {
+					chunk.center = (min + max) / 2.0;
+				}
+			}
+		}
+	}
+
+	/// Splits a `(width, height)` output into chunks, with empty meshes for `update` to fill.
+	fn layout(&mut self, (width, height): (usize, usize), meshes: &mut Assets<Mesh>) {
+		// dropping the handles frees the meshes
+		self.stale.extend(self.chunks.drain(..).filter_map(|chunk| chunk.entity));
+		let cells = (width.saturating_sub(1), height.saturating_sub(1));
+		for y in (0 .. cells.1).step_by(chunkCells) {
+			for x in (0 .. cells.0).step_by(chunkCells) {
+				let mesh = || Mesh::new(PrimitiveTopology::TriangleList, RenderAssetUsages::all());
+				self.chunks.push(TerrainChunk {
+					origin: (x, y),
+					cells: (chunkCells.min(cells.0 - x), chunkCells.min(cells.1 - y)),
+					lods: std::array::from_fn(|_| meshes.add(mesh())),
+					center: Vec3::ZERO,
+					lod: 0,
+					entity: None,
+				});
+			}
+		}
+		self.size = (width, height);
+	}
+}
+
+/// Vertices and indices of all meshes `TerrainChunks` builds for a `(width, height)` output:
+/// every level of every chunk, with its skirt.
+pub fn mesh_size((width, height): (usize, usize)) -> (usize, usize) {
+	// cells of each chunk along an axis `cells` long
+	let chunks = |cells: usize| {
+		(0 .. cells).step_by(chunkCells).map(move |x| chunkCells.min(cells - x))
+	};
+	let (mut vertices, mut indices) = (0, 0);
+	for cellsY in chunks(height.saturating_sub(1)) {
+		for cellsX in chunks(width.saturating_sub(1)) {
+			for step in lodSteps {
+				let samples = |cells: usize| cells.div_ceil(step) + 1;
+				let (width, height) = (samples(cellsX), samples(cellsY));
+				let ring = 2 * (width + height) - 4;
+				vertices += width * height + ring;
+				indices += ((width - 1) * (height - 1) + ring) * 6;
+			}
+		}
+	}
+	(vertices, indices)
+}
+
+/// Samples along one axis of a chunk starting at `origin` and `cells` long, every `step`th and
+/// the last, so the chunk still reaches its neighbour.
+fn lod_samples(origin: usize, cells: usize, step: usize) -> Vec<usize> {
+	let mut samples: Vec<usize> = (0 ..= cells).step_by(step).map(|i| origin + i).collect();
+	if cells % step != 0 {
+		samples.push(origin + cells);
+	}
+	samples
+}
+
+/// Fills `mesh` with the part of `output`'s surface a chunk covers, every `step`th sample along
+/// each axis, and its skirt. Indices and UVs are only written if the vertex count changed or the
+/// mesh lost them. Returns the bounds of the surface without the skirt.
+fn build_chunk_mesh(
+	mesh: &mut Mesh,
+	output: &NoiseOutput,
+	scale: TerrainScale,
+	origin: (usize, usize),
+	cells: (usize, usize),
+	step: usize,
+) -> (Vec3, Vec3) {
+	let xs = lod_samples(origin.0, cells.0, step);
+	let ys = lod_samples(origin.1, cells.1, step);
+	let (width, height) = (xs.len(), ys.len());
+	// edge vertices clockwise seen from above, starting at the first sample; rows go along +Z
+	let ring: Vec<usize> = (0 .. width)
+		.chain((1 .. height).map(|y| y * width + width - 1))
+		.chain((0 .. width - 1).rev().map(|x| (height - 1) * width + x))
+		.chain((1 .. height - 1).rev().map(|y| y * width))
+		.collect();
+
+	let vertexCount = width * height + ring.len();
+	let mut positions = Vec::with_capacity(vertexCount);
+	let mut normals = Vec::with_capacity(vertexCount);
+	for &y in &ys {
+		for &x in &xs {
+			let (position, normal) = output.surface_vertex(x, y, scale);
+			positions.push(position);
+			normals.push(normal);
+		}
+	}
+	let (min, max) = positions
+		.iter()
+		.fold((Vec3::INFINITY, Vec3::NEG_INFINITY), |(min, max), &p| (min.min(p), max.max(p)));
+	// deep enough to cover the largest gap a coarser neighbour could leave
+	let depth = (max.y - min.y).max(scale.spacing(output.size()));
+	for &i in &ring {
+		positions.push(positions[i] - Vec3::Y * depth);
+		normals.push(normals[i]);
+	}
+
+	let topologyKept = mesh.indices().is_some() && mesh.count_vertices() == vertexCount;
+	mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+	mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
+	if !topologyKept {
+		let uvScale = vec2(
+			1.0 / output.width.saturating_sub(1).max(1) as f32,
+			1.0 / output.height.saturating_sub(1).max(1) as f32,
+		);
+		let grid = ys.iter().flat_map(|&y| xs.iter().map(move |&x| vec2(x as f32, y as f32)));
+		let mut uvs: Vec<Vec2> = grid.map(|sample| sample * uvScale).collect();
+		let skirtUvs: Vec<Vec2> = ring.iter().map(|&i| uvs[i]).collect();
+		uvs.extend(skirtUvs);
+		mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
+
+		let mut indices = Vec::with_capacity(((width - 1) * (height - 1) + ring.len()) * 6);
+		for y in 0 .. height - 1 {
+			for x in 0 .. width - 1 {
+				let index = |dx: usize, dy: usize| ((y + dy) * width + x + dx) as u32;
+				// counter-clockwise seen from above, like `NoiseOutput::update_mesh`
+				indices.extend([
+					index(0, 0),
+					index(0, 1),
+					index(1, 0),
+					index(1, 0),
+					index(0, 1),
+					index(1, 1),
+				]);
+			}
+		}
+		// walking the ring clockwise, these face outwards
+		let skirt = width * height;
+		for k in 0 .. ring.len() {
+			let next = (k + 1) % ring.len();
+			let (a, b) = (ring[k] as u32, ring[next] as u32);
+			let (below, nextBelow) = ((skirt + k) as u32, (skirt + next) as u32);
+			indices.extend([a, b, below, b, nextBelow, below]);
+		}
+		mesh.insert_indices(Indices::U32(indices));
+	}
+	if let Err(err) = mesh.generate_tangents() {
+		warn!("failed to generate tangents for a terrain chunk: {err}");
+	}
+	(min, max)
+}
+
+/// Spawns an entity for every chunk without one, and despawns those of replaced layouts.
+pub fn sync_terrain_chunks(mut cmd: Commands, chunks: Option<ResMut<TerrainChunks>>) {
+	let Some(mut chunks) = chunks.filter(|chunks| chunks.is_changed()) else {
+		return;
+	};
+	let chunks = &mut *chunks;
+	for ent in chunks.stale.drain(..) {
+		if let Some(ent) = cmd.get_entity(ent) {
+			ent.despawn_recursive();
+		}
+	}
+	for chunk in chunks.chunks.iter_mut().filter(|chunk| chunk.entity.is_none()) {
+		let ent = cmd.spawn((
+			SceneOwned(chunks.owner),
+			TerrainSurface,
+			// bounds are computed on spawn only, while the meshes are rebuilt in place
+			NoFrustumCulling,
+			PbrBundle {
+				mesh: chunk.lods[chunk.lod].clone(),
+				material: chunks.material.clone(),
+				..default()
+			},
+		));
+		chunk.entity = Some(ent.id());
+	}
+}
+
+/// Level of detail for a chunk `distance` chunk widths away from the camera.
+fn lod_at(distance: f32) -> usize {
+	let mut lod = 0;
+	let mut reach = lodDistance;
+	while lod + 1 < lodSteps.len() && distance > reach {
+		lod += 1;
+		reach *= 2.0;
+	}
+	lod
+}
+
+/// Shows every chunk at the level its distance to the camera calls for, every `lodInterval`
+/// frames and right after the chunks changed. With `ViewSettings::lod` off, all of them are at
+/// full resolution.
+pub fn select_terrain_lods(
+	chunks: Option<ResMut<TerrainChunks>>,
+	settings: Res<ViewSettings>,
+	uiState: Res<UiState>,
+	camera: Query<&GlobalTransform, With<Camera3d>>,
+	mut surfaces: Query<&mut Handle<Mesh>, With<TerrainSurface>>,
+	mut frame: Local<u32>,
+) {
+	*frame = frame.wrapping_add(1);
+	let (Some(mut chunks), Ok(camera)) = (chunks, camera.get_single()) else {
+		return;
+	};
+	if *frame % lodInterval != 0 && !chunks.is_changed() && !settings.is_changed() {
+		return;
+	}
+	let chunkWidth = uiState.terrain_scale().spacing(chunks.size) * chunkCells as f32;
+	let eye = camera.translation();
+	for chunk in &mut chunks.bypass_change_detection().chunks {
+		let lod = if settings.lod { lod_at(eye.distance(chunk.center) / chunkWidth) } else { 0 };
+		let surface = chunk.entity.and_then(|ent| surfaces.get_mut(ent).ok());
+		// spawned this frame, picked up at the next interval
+		let Some(mut mesh) = surface.filter(|_| lod != chunk.lod) else {
+			continue;
+		};
+		chunk.lod = lod;
+		*mesh = chunk.lods[lod].clone();
+	}
+}
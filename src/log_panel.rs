@@ -0,0 +1,336 @@
+use std::collections::VecDeque;
+use std::fmt::{self, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use bevy::log::tracing_subscriber::layer::{Context, Layer};
+use bevy::log::BoxedLayer;
+use bevy::prelude::*;
+use bevy::utils::tracing::field::{Field, Visit};
+use bevy::utils::tracing::{Event, Level, Subscriber};
+use bevy_egui::{egui, EguiContexts};
+
+use crate::{scripts_root, InternedPath, NoiseGenRequest, NoiseOutput, UiState};
+
+/// Entries kept before the oldest are dropped.
+const capacity: usize = 500;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+	Error,
+	Warn,
+	Info,
+	Debug,
+}
+
+impl Severity {
+	pub const all: [Self; 4] = [Self::Error, Self::Warn, Self::Info, Self::Debug];
+
+	fn from_level(level: &Level) -> Self {
+		match *level {
+			Level::ERROR => Self::Error,
+			Level::WARN => Self::Warn,
+			Level::INFO => Self::Info,
+			_ => Self::Debug,
+		}
+	}
+
+	pub fn label(self) -> &'static str {
+		match self {
+			Self::Error => "error",
+			Self::Warn => "warn",
+			Self::Info => "info",
+			Self::Debug => "debug",
+		}
+	}
+}
+
+/// What an entry is about. Everything but `Message` is recorded by the system it's about.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LogKind {
+	/// A line logged with `info!` and the like.
+	Message,
+	ScriptSelected,
+	/// A generation started or finished.
+	Generation,
+	LuaError,
+	/// A script or directory changed on disk.
+	FileWatcher,
+	Export,
+	/// A preset loaded, saved or deleted.
+	Preset,
+}
+
+impl LogKind {
+	/// Tag in front of the message, none for plain log lines.
+	fn tag(self) -> &'static str {
+		match self {
+			Self::Message => "",
+			Self::ScriptSelected => "[script] ",
+			Self::Generation => "[generation] ",
+			Self::LuaError => "[lua] ",
+			Self::FileWatcher => "[file] ",
+			Self::Export => "[export] ",
+			Self::Preset => "[preset] ",
+		}
+	}
+}
+
+#[derive(Clone, Debug)]
+pub struct LogEntry {
+	/// Time since the log was created, at startup.
+	pub time: Duration,
+	pub severity: Severity,
+	pub kind: LogKind,
+	pub message: String,
+	/// Script the entry is about, selected again by clicking the entry.
+	pub script: Option<InternedPath>,
+}
+
+impl fmt::Display for LogEntry {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		let (secs, millis) = (self.time.as_secs(), self.time.subsec_millis());
+		let (hours, minutes, secs) = (secs / 3600, secs / 60 % 60, secs % 60);
+		let (severity, tag, message) = (self.severity.label(), self.kind.tag(), &self.message);
+		write!(f, "{hours:02}:{minutes:02}:{secs:02}.{millis:03} {severity:<5} {tag}{message}")
+	}
+}
+
+/// The last `capacity` log entries, shown in the panel at the bottom of the window. Shared with
+/// the tracing layer `log_layer` installs, which adds every line the app logs.
+#[derive(Resource, Clone)]
+pub struct SessionLog {
+	entries: Arc<Mutex<VecDeque<LogEntry>>>,
+	start: Instant,
+	pub open: bool,
+	/// Severities shown in the panel.
+	pub shown: [bool; 4],
+}
+
+impl Default for SessionLog {
+	fn default() -> Self {
+		Self {
+			entries: default(),
+			start: Instant::now(),
+			open: false,
+			shown: [true, true, true, false],
+		}
+	}
+}
+
+impl SessionLog {
+	fn push(
+		&self,
+		severity: Severity,
+		kind: LogKind,
+		script: Option<&InternedPath>,
+		message: String,
+	) {
+		let entry = LogEntry {
+			time: self.start.elapsed(),
+			severity,
+			kind,
+			message,
+			script: script.cloned(),
+		};
+		let mut entries = self.entries.lock().unwrap();
+		if entries.len() == capacity {
+			entries.pop_front();
+		}
+		entries.push_back(entry);
+	}
+
+	/// Adds an entry about `script`, if any, and logs it like `info!` and the like would.
+	pub fn record(
+		&self,
+		severity: Severity,
+		kind: LogKind,
+		script: Option<&InternedPath>,
+		message: impl Into<String>,
+	) {
+		let message = message.into();
+		// the layer leaves out this module's lines, so the entry isn't added twice
+		match severity {
+			Severity::Error => error!("{message}"),
+			Severity::Warn => warn!("{message}"),
+			Severity::Info => info!("{message}"),
+			Severity::Debug => debug!("{message}"),
+		}
+		self.push(severity, kind, script, message);
+	}
+
+	/// The entries shown in the panel, oldest first.
+	pub fn shown_entries(&self) -> Vec<LogEntry> {
+		let entries = self.entries.lock().unwrap();
+		entries.iter().filter(|entry| self.is_shown(entry)).cloned().collect()
+	}
+
+	fn count(&self, severity: Severity) -> usize {
+		let entries = self.entries.lock().unwrap();
+		entries.iter().filter(|entry| entry.severity == severity).count()
+	}
+
+	fn is_shown(&self, entry: &LogEntry) -> bool {
+		self.shown[entry.severity as usize]
+	}
+}
+
+/// Name of a path the file watcher reported, relative to the scripts directory.
+pub fn watched_name(path: &Path) -> String {
+	path.strip_prefix(scripts_root())
+		.unwrap_or(path)
+		.display()
+		.to_string()
+}
+
+/// "`n` samples, `min` … `max`" of the finite samples of `output`.
+pub fn output_summary(output: &NoiseOutput) -> String {
+	let (min, max) = output
+		.samples
+		.iter()
+		.filter(|v| v.is_finite())
+		.fold((f32::INFINITY, f32::NEG_INFINITY), |(lo, hi), &v| (lo.min(v), hi.max(v)));
+	let count = output.samples.len();
+	if min > max {
+		format!("{count} samples, none finite")
+	} else {
+		format!("{count} samples, {min:.4} … {max:.4}")
+	}
+}
+
+/// Collects a logged event's fields into one line, the message first.
+#[derive(Default)]
+struct LineVisitor(String);
+
+impl Visit for LineVisitor {
+	fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+		if field.name() == "message" {
+			let _ = write!(self.0, "{value:?}");
+		} else {
+			let _ = write!(self.0, " {}={value:?}", field.name());
+		}
+	}
+
+	fn record_str(&mut self, field: &Field, value: &str) {
+		if field.name() == "message" {
+			self.0.push_str(value);
+		} else {
+			let _ = write!(self.0, " {}={value}", field.name());
+		}
+	}
+}
+
+/// Adds the app's own log lines and everyone's warnings and errors to a `SessionLog`.
+struct SessionLogLayer(SessionLog);
+
+impl<S: Subscriber> Layer<S> for SessionLogLayer {
+	fn on_event(&self, event: &Event, _ctx: Context<S>) {
+		let metadata = event.metadata();
+		let severity = Severity::from_level(metadata.level());
+		let target = metadata.target();
+		let ours = target.starts_with("noisebench") && target != module_path!();
+		if !ours && severity > Severity::Warn {
+			return;
+		}
+		let mut line = LineVisitor::default();
+		event.record(&mut line);
+		self.0.push(severity, LogKind::Message, None, line.0);
+	}
+}
+
+/// `LogPlugin::custom_layer`, which inserts the `SessionLog` the layer fills.
+pub fn log_layer(app: &mut App) -> Option<BoxedLayer> {
+	let log = SessionLog::default();
+	app.insert_resource(log.clone());
+	Some(Box::new(SessionLogLayer(log)))
+}
+
+/// Records script selections, wherever they come from.
+pub fn record_selection(
+	uiState: Res<UiState>,
+	log: Res<SessionLog>,
+	mut last: Local<Option<(usize, InternedPath)>>,
+) {
+	let Some(selected) = uiState.selected() else {
+		return;
+	};
+	let current = (uiState.activeLayer, selected.clone());
+	if last.as_ref() == Some(&current) {
+		return;
+	}
+	let message = format!("layer {}: selected {}", current.0, selected.display);
+	log.record(Severity::Info, LogKind::ScriptSelected, Some(selected), message);
+	*last = Some(current);
+}
+
+/// The collapsible log panel along the bottom of the window. Clicking an entry about a script
+/// selects it again on the active layer.
+pub fn log_panel(
+	mut eguiCtx: EguiContexts,
+	mut log: ResMut<SessionLog>,
+	mut uiState: ResMut<UiState>,
+	mut noiseGenRequests: EventWriter<NoiseGenRequest>,
+) {
+	let log = &mut *log;
+	// cloned only while they're shown, the panel is mostly closed
+	let entries = if log.open { log.shown_entries() } else { vec![] };
+	egui::TopBottomPanel::bottom("sessionLog")
+		.resizable(log.open)
+		.default_height(180.0)
+		.show(eguiCtx.ctx_mut(), |ui| {
+			ui.horizontal(|ui| {
+				let arrow = if log.open { "⏷" } else { "⏵" };
+				ui.toggle_value(&mut log.open, format!("{arrow} Log"));
+				let errors = log.count(Severity::Error);
+				if errors > 0 {
+					ui.colored_label(egui::Color32::RED, format!("{errors} errors"));
+				}
+				if !log.open {
+					return;
+				}
+				ui.separator();
+				for severity in Severity::all {
+					ui.checkbox(&mut log.shown[severity as usize], severity.label());
+				}
+				ui.separator();
+				if ui
+					.button("Copy all")
+					.on_hover_text("Copy the shown entries to the clipboard")
+					.clicked()
+				{
+					let text = entries.iter().map(|entry| format!("{entry}\n")).collect();
+					ui.output_mut(|output| output.copied_text = text);
+				}
+			});
+			if !log.open {
+				return;
+			}
+			egui::ScrollArea::vertical()
+				.auto_shrink([false, false])
+				.stick_to_bottom(true)
+				.show(ui, |ui| {
+					for entry in &entries {
+						let text = egui::RichText::new(entry.to_string()).monospace();
+						let text = match entry.severity {
+							Severity::Error => text.color(egui::Color32::RED),
+							Severity::Warn => text.color(egui::Color32::YELLOW),
+							Severity::Info => text,
+							Severity::Debug => text.weak(),
+						};
+						let Some(script) = &entry.script else {
+							ui.label(text);
+							continue;
+						};
+						let resp = ui
+							.add(egui::Label::new(text).sense(egui::Sense::click()))
+							.on_hover_text(format!("Select {}", script.display));
+						if resp.clicked() && uiState.selected() != Some(script) {
+							let layer = uiState.activeLayer;
+							*uiState.selected_mut() = Some(script.clone());
+							noiseGenRequests.send(NoiseGenRequest::LayerChanged(layer));
+						}
+					}
+				});
+		});
+}
@@ -1,25 +1,76 @@
 #![allow(unused, non_snake_case, non_upper_case_globals)]
 
-mod lua;
+mod animation;
+mod bake;
+mod bench;
+mod bookmarks;
+mod chunks;
+mod coloring;
+mod colormap;
+mod debounce;
+mod diff;
+mod domain;
+mod editor;
+mod erosion;
+mod export;
+mod fnl;
+mod gizmos;
+mod gpu;
+mod gradients;
+mod graph;
+mod handles;
+mod infinite;
+mod input;
+mod inspect;
+mod layers;
+mod lighting;
+mod lod;
+mod log_panel;
+mod memory;
+mod panes;
+mod persist;
+mod pin;
+mod presets;
+mod probe;
+mod reference;
+mod scatter;
+mod scene;
+mod screenshot;
+mod script_tree;
+mod selftest;
+mod sequence;
+mod session;
+mod snapshot;
+mod stats;
+mod sweep;
+mod textures;
+mod theme;
+mod thumbnails;
+mod walk;
+mod water;
+mod workers;
 
 use std::borrow::Borrow;
+use std::collections::{BTreeMap, BTreeSet};
 use std::ffi::OsStr;
 use std::ops::Deref;
 use std::path::{Path, PathBuf};
-use std::sync::{Arc, OnceLock, RwLock};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, OnceLock, RwLock, Weak};
 use std::time::Duration;
 
 pub use anyhow::Result as AResult;
+use anyhow::Context;
 use bevy::asset::io::AssetSourceEvent;
 use bevy::asset::{AssetLoader, AsyncReadExt, LoadedFolder};
-use bevy::color::palettes::css;
 use bevy::core_pipeline::Skybox;
-use bevy::input::mouse::{MouseMotion, MouseWheel};
+use bevy::ecs::system::SystemParam;
+use bevy::log::LogPlugin;
 use bevy::math::{dvec2, vec2, vec3, DVec2};
-use bevy::pbr::DirectionalLightShadowMap;
+use bevy::pbr::wireframe::WireframePlugin;
 use bevy::prelude::*;
 use bevy::render::camera::RenderTarget;
-use bevy::render::mesh::PrimitiveTopology;
+use bevy::render::mesh::{Indices, PrimitiveTopology};
 use bevy::render::render_asset::RenderAssetUsages;
 use bevy::render::render_resource::{
 	Extent3d,
@@ -30,58 +81,229 @@ use bevy::render::render_resource::{
 	TextureViewDescriptor,
 	TextureViewDimension,
 };
+use bevy::render::settings::{RenderCreation, WgpuFeatures, WgpuSettings};
 use bevy::render::texture::BevyDefault;
 use bevy::render::view::NoFrustumCulling;
-use bevy::tasks::futures_lite::future;
+use bevy::render::RenderPlugin;
 use bevy::tasks::{block_on, AsyncComputeTaskPool, Task};
-use bevy::utils::{HashMap, HashSet};
+use bevy::utils::HashMap;
 use bevy::window::{PrimaryWindow, WindowResolution};
 use bevy::winit::WinitSettings;
+use noisebench_core::grid::{sample_position, subsample_offsets, Coords, GridSpec};
+use noisebench_core::{content_hash, lua, noise_rng, ContentHash};
 use bevy_egui::egui::load::SizedTexture;
 use bevy_egui::egui::{self, ImageSource, TextureId};
-use bevy_egui::{EguiContexts, EguiPlugin};
+use bevy_egui::{EguiContexts, EguiPlugin, EguiSettings};
 use crossbeam_channel::Receiver;
 use notify::event::{CreateKind, ModifyKind, RemoveKind, RenameMode};
 use notify::{EventKind, RecursiveMode, Watcher};
+use serde::{Deserialize, Serialize};
 
 const skyboxTexture: &'static str = "skybox/clouds.jpg";
+const uiScaleRange: std::ops::RangeInclusive<f32> = 0.75 ..= 2.0;
+const uiScaleStep: f32 = 0.125;
+/// Diameters offered in the toolbar's size menu; any other value can still be dragged in.
+const diameterPresets: [usize; 5] = [64, 128, 256, 512, 1024];
+/// Fixed aspect ratios the viewport can be letterboxed to, `None` filling the panel.
+const previewAspects: [(&str, Option<(u32, u32)>); 5] = [
+	("Fill", None),
+	("1:1", Some((1, 1))),
+	("2:1", Some((2, 1))),
+	("16:9", Some((16, 9))),
+	("4:3", Some((4, 3))),
+];
 
 fn main() -> AppExit {
+	lua::set_module_dir(scriptsDir);
+	match std::env::args().nth(1).as_deref() {
+		Some("selftest") => return selftest::main(),
+		Some("bench") => return bench::main(),
+		Some("bake") => return bake::main(),
+		_ => {},
+	}
+
 	let mut app = App::new();
 
-	app.add_plugins(DefaultPlugins.set(WindowPlugin {
-		primary_window: Some(Window {
-			title: "noisebench".into(),
-			resolution: WindowResolution::new(1280.0, 720.0),
-			resizable: true,
-			position: WindowPosition::Centered(MonitorSelection::Primary),
-			..default()
-		}),
-		..default()
-	}));
+	app.add_plugins(
+		DefaultPlugins
+			.set(WindowPlugin {
+				primary_window: Some(Window {
+					title: "noisebench".into(),
+					resolution: WindowResolution::new(1280.0, 720.0),
+					resizable: true,
+					position: WindowPosition::Centered(MonitorSelection::Primary),
+					..default()
+				}),
+				..default()
+			})
+			.set(RenderPlugin {
+				// for the wireframe view
+				render_creation: RenderCreation::Automatic(WgpuSettings {
+					features: WgpuFeatures::POLYGON_MODE_LINE,
+					..default()
+				}),
+				..default()
+			})
+			.set(LogPlugin {
+				custom_layer: log_panel::log_layer,
+				..default()
+			}),
+	);
+	app.add_plugins(WireframePlugin);
+	gizmos::add_view_groups(&mut app);
 	app.add_plugins(EguiPlugin);
+	app.add_plugins(MaterialPlugin::<water::WaterMaterial>::default());
+	screenshot::add_readback(&mut app);
 
 	app.add_event::<NoiseGenRequest>();
 
 	app.add_systems(Startup, setup);
-	app.add_systems(PreUpdate, update_viewport_size);
+	app.add_systems(Last, persist::save_state);
+	app.add_systems(
+		PreUpdate,
+		(textures::update_texture_limits, update_viewport_size).chain(),
+	);
 	app.add_systems(
 		Update,
 		(
 			close_on_esc,
-			axes_gizmo,
+			ui_scale,
+			theme::apply_theme,
+			gizmos::debug_gizmos,
 			setup_cubemap,
+			editor::script_editor.before(main_ui),
 			main_ui,
-			camera_controller_2d,
-			camera_controller_3d,
+			inspect::hover_readout.after(main_ui),
+			snapshot::snapshots.after(main_ui),
+			tile_preview.after(snapshot::snapshots),
+			sweep::seed_sweep.after(main_ui),
+			diff::diff_export.after(main_ui),
+			export::heightmap_export.after(main_ui),
+			erosion::erosion_panel.after(main_ui),
+			scatter::scatter_panel.after(main_ui),
+			scatter::draw_scatter_2d.after(scatter::scatter_panel),
+			memory::memory_panel.after(main_ui),
+			stats::output_stats.after(main_ui),
+			input::dispatch_input.after(main_ui),
+			domain::follow_camera.after(camera_controller_2d).before(generate_noise),
+		),
+	);
+	// a separate tuple, a single one takes at most 20 systems
+	app.add_systems(
+		Update,
+		(
+			camera_controller_2d.after(input::dispatch_input),
+			handles::water_handle.after(main_ui),
+			camera_controller_3d.after(input::dispatch_input).after(handles::water_handle),
+			layers::switch_active_layer.after(main_ui),
+			layers::layout_layer_sprites.after(layers::switch_active_layer),
 			scripts_changed,
 			generate_noise,
 			update_noise_outputs,
+			thumbnails::update_thumbnails,
+			scene::apply_scene_mode,
+			water::update_water_material,
+			water::update_water_overlay.after(update_noise_outputs).after(handles::water_handle),
+			coloring::update_terrain_colors,
+			gradients::gradient_view.after(stats::output_stats),
+			scene::apply_view_settings.after(update_noise_outputs).after(scene::apply_scene_mode),
+			gizmos::height_grid,
+			pin::pinned_diff
+				.after(main_ui)
+				.after(update_noise_outputs)
+				.before(snapshot::snapshots),
+			animation::animate.after(main_ui),
+			reference::reference_image
+				.after(main_ui)
+				.after(update_noise_outputs)
+				.before(snapshot::snapshots),
+			scatter::place_scatter_markers.after(scatter::scatter_panel),
+		),
+	);
+	app.add_systems(
+		Update,
+		(
+			colormap::colormap_panel.after(main_ui).before(stats::output_stats),
+			colormap::recolor_outputs
+				.after(colormap::colormap_panel)
+				.after(update_noise_outputs)
+				.before(stats::output_stats)
+				.before(gradients::gradient_view),
+			sequence::sweep_recorder,
+			presets::presets_changed,
+			lighting::apply_lighting.run_if(resource_changed::<lighting::LightingSettings>),
+			lod::sync_terrain_chunks.after(update_noise_outputs).after(scene::apply_view_settings),
+			lod::select_terrain_lods.after(lod::sync_terrain_chunks).after(camera_controller_3d),
+			probe::probe_3d.after(main_ui).after(input::dispatch_input).after(camera_controller_3d),
+			probe::draw_probe_2d.after(camera_controller_2d),
+			debounce::fire_pending_regen
+				.after(main_ui)
+				.after(scripts_changed)
+				.before(generate_noise),
+			log_panel::log_panel.before(editor::script_editor),
+			log_panel::record_selection.after(main_ui).after(log_panel::log_panel),
+			screenshot::screenshots.after(main_ui),
+			bookmarks::camera_bookmarks.after(input::dispatch_input).before(camera_controller_3d),
+			graph::graph_window.after(main_ui),
+			graph::update_graph.after(graph::graph_window).before(snapshot::snapshots),
+			scene::sync_pinned_terrain.after(scene::apply_scene_mode).after(pin::pinned_diff),
+			infinite::update_infinite_terrain
+				.after(update_noise_outputs)
+				.after(scene::apply_scene_mode)
+				.after(camera_controller_3d),
 		),
 	);
 
-	app.insert_resource(SelectedTab(Tab::D2));
-	app.insert_resource(ViewportSize(UVec2::ONE));
+	app.init_resource::<scene::SceneMode>();
+	app.insert_resource(ViewportSizes {
+		d2: UVec2::ONE,
+		d3: UVec2::ONE,
+	});
+	let hidden = ViewportRect {
+		rect: egui::Rect::NOTHING,
+		pixelsPerPoint: 1.0,
+	};
+	app.insert_resource(ViewportRects {
+		d2: hidden,
+		d3: hidden,
+	});
+	app.init_resource::<selftest::SelfTestReport>();
+	app.init_resource::<thumbnails::Thumbnails>();
+	app.init_resource::<session::SessionRecorder>();
+	app.init_resource::<inspect::Inspector>();
+	app.init_resource::<graph::NoiseGraph>();
+	app.init_resource::<water::WaterSettings>();
+	app.init_resource::<coloring::TerrainColoring>();
+	app.init_resource::<snapshot::Snapshots>();
+	app.init_resource::<handles::Handles>();
+	app.init_resource::<probe::Probe>();
+	app.init_resource::<debounce::RegenDebounce>();
+	app.init_resource::<sweep::SeedSweep>();
+	app.init_resource::<input::ViewportInput>();
+	app.init_resource::<ScriptError>();
+	app.init_resource::<editor::ScriptEditor>();
+	app.init_resource::<NoiseGenCounter>();
+	app.init_resource::<diff::DiffExport>();
+	app.init_resource::<export::HeightmapExport>();
+	app.init_resource::<textures::TextureLimits>();
+	app.init_resource::<gizmos::GizmoSettings>();
+	app.init_resource::<scene::ViewSettings>();
+	app.init_resource::<walk::WalkMode>();
+	app.init_resource::<pin::PinnedNoiseOutput>();
+	app.init_resource::<pin::DisplayMode>();
+	app.init_resource::<reference::ReferenceHeightmap>();
+	app.init_resource::<erosion::ErosionPanel>();
+	app.init_resource::<scatter::ScatterPanel>();
+	app.init_resource::<bookmarks::CameraBookmarks>();
+	app.init_resource::<colormap::ColormapSettings>();
+	app.init_resource::<sequence::SweepRecorder>();
+	app.init_resource::<lighting::LightingSettings>();
+	app.init_resource::<fnl::PresetImport>();
+	app.init_resource::<memory::MemoryPanel>();
+	app.init_resource::<stats::OutputStats>();
+	// inserted by `log_panel::log_layer` already, unless logging was set up before the app
+	app.init_resource::<log_panel::SessionLog>();
+	app.insert_resource(theme::Theme::load());
 
 	let mut images: Mut<Assets<Image>> = app.world_mut().resource_mut();
 	let defaultImage = Image {
@@ -96,9 +318,11 @@ fn main() -> AppExit {
 			format: TextureFormat::bevy_default(),
 			mip_level_count: 1,
 			sample_count: 1,
+			// copied from for screenshots
 			usage: TextureUsages::TEXTURE_BINDING |
 				TextureUsages::RENDER_ATTACHMENT |
-				TextureUsages::COPY_DST,
+				TextureUsages::COPY_DST |
+				TextureUsages::COPY_SRC,
 			view_formats: &[],
 		},
 		..default()
@@ -125,33 +349,26 @@ fn main() -> AppExit {
 	})
 	.unwrap();
 	watcher
-		.watch(Path::new("assets/scripts"), RecursiveMode::Recursive)
+		.watch(Path::new(scriptsDir), RecursiveMode::Recursive)
 		.unwrap();
+	// presets have a watcher of their own, their directory is created on the first start
+	let (presetSender, presetReceiver) = crossbeam_channel::unbounded();
+	let _presetWatcher = presets::watch(presetSender);
+	app.insert_resource(presets::Presets::new(presetReceiver));
 	let mut scripts = HashMap::new();
-	let luaExtension = OsStr::new("lua");
-	for file in std::fs::read_dir("assets/scripts").unwrap() {
-		let file = file.unwrap();
-		let ty = file.file_type().unwrap();
-		if !ty.is_file() {
-			continue;
-		}
-
-		let path = file.path();
-		if !matches!(path.extension(), Some(luaExtension)) {
-			continue;
-		}
-
-		let path = InternedPath::new(path);
-		let contents = std::fs::read_to_string(&path.path).unwrap();
-		scripts.insert(path, contents);
+	scan_scripts(Path::new(scriptsDir), &mut scripts);
+	let mut uiState = UiState::new(receiver, scripts);
+	let mut selectedTab = SelectedTab(Tab::D2);
+	let savedState = persist::AppState::load();
+	savedState.apply(&mut uiState, &mut selectedTab);
+	// with a single script there's nothing to choose from, `setup` generates it right away
+	if uiState.selected().is_none() && uiState.scripts.len() == 1 {
+		*uiState.selected_mut() = uiState.scripts.keys().next().cloned();
 	}
-	app.insert_resource(UiState {
-		channel: receiver,
-		scripts,
-		selected: None,
-		diameter: 256,
-		height: 1.0,
-	});
+	app.insert_resource(uiState);
+	app.insert_resource(selectedTab);
+	// cameras are placed from it in `setup`
+	app.insert_resource(savedState);
 
 	app.run()
 }
@@ -162,24 +379,111 @@ fn close_on_esc(keyboard: Res<ButtonInput<KeyCode>>, mut exit: EventWriter<AppEx
 	}
 }
 
-fn axes_gizmo(mut gizmos: Gizmos) {
-	gizmos.line(Vec3::ZERO, Vec3::X * 5.0, css::RED);
-	gizmos.line(Vec3::ZERO, Vec3::Y * 5.0, css::GREEN);
-	gizmos.line(Vec3::ZERO, Vec3::Z * 5.0, css::BLUE);
+/// Handles the Ctrl+=/Ctrl+- shortcuts and applies `UiState::uiScale` on top of the window's own
+/// scale factor.
+fn ui_scale(
+	keyboard: Res<ButtonInput<KeyCode>>,
+	mut uiState: ResMut<UiState>,
+	mut eguiSettings: ResMut<EguiSettings>,
+) {
+	if keyboard.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]) {
+		if keyboard.just_pressed(KeyCode::Equal) {
+			uiState.uiScale += uiScaleStep;
+		}
+		if keyboard.just_pressed(KeyCode::Minus) {
+			uiState.uiScale -= uiScaleStep;
+		}
+	}
+	let scale = uiState.uiScale.clamp(*uiScaleRange.start(), *uiScaleRange.end());
+	if uiState.uiScale != scale {
+		uiState.uiScale = scale;
+	}
+	if eguiSettings.scale_factor != scale {
+		eguiSettings.scale_factor = scale;
+	}
 }
 
-#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
 enum Tab {
 	#[default]
 	D2,
 	D3,
+	/// Both views side by side, laid out by `UiState::paneLayout`.
+	Both,
 }
 
 #[derive(Resource)]
 struct SelectedTab(pub Tab);
 
+impl SelectedTab {
+	/// Whether `pane`, `Tab::D2` or `Tab::D3`, is on screen.
+	fn shows(&self, pane: Tab) -> bool {
+		self.0 == pane || self.0 == Tab::Both
+	}
+}
+
+/// Why the last generation of the selected script failed, shown below the viewport until one
+/// succeeds. The previous output stays displayed meanwhile.
+#[derive(Resource, Default)]
+struct ScriptError(Option<String>);
+
+/// Size of each view's render target in physical pixels. A view that isn't shown keeps the
+/// size it was last shown at.
 #[derive(Resource)]
-struct ViewportSize(UVec2);
+struct ViewportSizes {
+	d2: UVec2,
+	d3: UVec2,
+}
+
+/// Where a view's image was last drawn, in egui logical points. All conversions between pointer
+/// positions and viewport coordinates go through this so UI scale changes can't skew them.
+#[derive(Clone, Copy, Debug)]
+struct ViewportRect {
+	rect: egui::Rect,
+	pixelsPerPoint: f32,
+}
+
+impl ViewportRect {
+	/// Maps a position in logical points to normalized image coordinates, `(0, 0)` being the top
+	/// left corner. Returns `None` outside of the image.
+	pub fn normalized(&self, pos: egui::Pos2) -> Option<Vec2> {
+		if !self.rect.contains(pos) {
+			return None;
+		}
+		let rel = (pos - self.rect.min) / self.rect.size();
+		Some(vec2(rel.x, rel.y))
+	}
+
+	/// Maps a position in logical points to physical pixels of the render target.
+	pub fn to_pixels(&self, pos: egui::Pos2) -> Vec2 {
+		let rel = pos - self.rect.min;
+		vec2(rel.x, rel.y) * self.pixelsPerPoint
+	}
+
+	/// Inverse of `normalized`.
+	pub fn from_normalized(&self, uv: Vec2) -> egui::Pos2 {
+		self.rect.min + egui::vec2(uv.x, uv.y) * self.rect.size()
+	}
+
+	/// Size of the render target in physical pixels, which `to_pixels` positions are in.
+	pub fn pixel_size(&self) -> Vec2 {
+		let size = self.rect.size() * self.pixelsPerPoint;
+		vec2(size.x, size.y)
+	}
+}
+
+/// Where each view's image was last drawn, `egui::Rect::NOTHING` for one that isn't shown.
+#[derive(Resource, Clone, Copy, Debug)]
+struct ViewportRects {
+	d2: ViewportRect,
+	d3: ViewportRect,
+}
+
+impl ViewportRects {
+	fn panes(&self) -> [(Tab, &ViewportRect); 2] {
+		[(Tab::D2, &self.d2), (Tab::D3, &self.d3)]
+	}
+}
 
 #[derive(Resource)]
 struct Viewport2D {
@@ -196,16 +500,188 @@ struct Viewport3D {
 #[derive(Resource)]
 struct Heightmaps {
 	image: Handle<Image>,
-	mesh: Handle<Mesh>,
+	/// Raw samples as a single channel texture, for shaders that need the terrain height.
+	heights: Handle<Image>,
+	/// Terrain colors derived from `heights`, see `coloring::TerrainColoring`.
+	colors: Handle<Image>,
 }
 
+/// The 2D view's sprite, normally showing `Heightmaps::image`.
+#[derive(Component)]
+struct HeightmapSprite;
+
 #[derive(Resource)]
 struct UiState {
 	channel: Receiver<notify::Event>,
 	scripts: HashMap<InternedPath, String>,
-	selected: Option<InternedPath>,
-	diameter: usize,
+	layers: Vec<layers::Layer>,
+	/// Layer the toolbar edits and the 3D view, tools and exports work on.
+	activeLayer: usize,
+	/// Shows the visible layers side by side in the 2D view instead of just the active one.
+	splitView: bool,
+	/// How `Tab::Both` divides the central panel between the views.
+	paneLayout: panes::PaneLayout,
+	/// Samples per row and rows of generations outside chunked mode.
+	outputWidth: usize,
+	outputHeight: usize,
+	/// Coordinates samples are evaluated at, see `Coords`. Scripts see it as their `Coords` global.
+	coords: Coords,
 	height: f32,
+	/// Width of the terrain mesh in world units, whatever its number of samples.
+	extent: f32,
+	uiScale: f32,
+	/// Offset added to the seeds of all seeded nodes, 0 keeping the script's own seeds. Scripts
+	/// also see it as their `Seed` global.
+	seed: i64,
+	/// Values of `Params` sliders set in the UI, by script and parameter name. Kept across reloads
+	/// so saving a script doesn't reset its tuning.
+	params: HashMap<(InternedPath, String), f64>,
+	precision: lua::Precision,
+	backend: gpu::Backend,
+	/// Sub-samples per axis averaged into every sample, see `subsample_offsets`.
+	supersampling: usize,
+	/// Applies `Noise::decorrelate` to the whole tree, using the seed offset as its seed.
+	decorrelate: bool,
+	/// Looks for non-finite samples after every generation and traces the first one back to the
+	/// node producing it, see `NonFiniteSample`.
+	checkFinite: bool,
+	/// Depth of the slice through the volume that 3D sources are sampled at.
+	sliceZ: f64,
+	animation: animation::Animation,
+	/// Region of the evaluation plane the 2D view samples, see `domain::follow_camera`.
+	domain: domain::ViewDomain,
+	/// User confirmation to build meshes above `MeshEstimate::hardLimit`.
+	allowHugeMesh: bool,
+	/// Aspect ratio the viewport is letterboxed to, `None` filling the central panel.
+	previewAspect: Option<(u32, u32)>,
+	/// Repeats the 2D view's image in a 3×3 grid, see `tile_preview`.
+	tilePreview: bool,
+	gradientView: gradients::GradientView,
+	/// Samples between gradient arrows along each axis.
+	arrowStride: usize,
+	chunks: chunks::ChunkSettings,
+}
+
+impl UiState {
+	/// Default settings for `scripts`, file changes arriving through `channel`.
+	fn new(channel: Receiver<notify::Event>, scripts: HashMap<InternedPath, String>) -> Self {
+		Self {
+			channel,
+			scripts,
+			layers: (0 .. layers::layerCount).map(layers::Layer::new).collect(),
+			activeLayer: 0,
+			splitView: false,
+			paneLayout: default(),
+			outputWidth: 256,
+			outputHeight: 256,
+			coords: default(),
+			height: 1.0,
+			extent: 256.0,
+			uiScale: 1.0,
+			seed: 0,
+			params: HashMap::new(),
+			precision: default(),
+			backend: default(),
+			supersampling: 1,
+			decorrelate: false,
+			checkFinite: false,
+			sliceZ: 0.0,
+			animation: default(),
+			domain: default(),
+			allowHugeMesh: false,
+			previewAspect: None,
+			tilePreview: false,
+			gradientView: default(),
+			arrowStride: 16,
+			chunks: default(),
+		}
+	}
+
+	/// Script of the active layer.
+	fn selected(&self) -> Option<&InternedPath> {
+		self.layers[self.activeLayer].script.as_ref()
+	}
+
+	fn selected_mut(&mut self) -> &mut Option<InternedPath> {
+		&mut self.layers[self.activeLayer].script
+	}
+
+	/// Width and height of full generations, the chunk grid's square in chunked mode.
+	fn output_size(&self) -> (usize, usize) {
+		if self.chunks.enabled {
+			(self.chunks.diameter(), self.chunks.diameter())
+		} else {
+			(self.outputWidth, self.outputHeight)
+		}
+	}
+
+	/// Whether the 2D view's domain can follow the camera, which `Domain` only places over square
+	/// outputs of the unit square.
+	fn can_follow_camera(&self) -> bool {
+		let (width, height) = self.output_size();
+		width == height && self.coords == Coords::Normalized
+	}
+
+	fn terrain_scale(&self) -> TerrainScale {
+		TerrainScale {
+			height: self.height,
+			extent: self.extent,
+		}
+	}
+
+	/// Slider values set for `path`'s script, as passed to it.
+	fn script_params(&self, path: &InternedPath) -> BTreeMap<String, f64> {
+		script_params(&self.params, path)
+	}
+
+	/// Replaces the slider values set for `path`'s script with `params`.
+	fn set_script_params(&mut self, path: &InternedPath, params: &BTreeMap<String, f64>) {
+		self.params.retain(|(script, _), _| script != path);
+		let params = params.iter().map(|(name, &v)| ((path.clone(), name.clone()), v));
+		self.params.extend(params);
+	}
+}
+
+/// Values in `params`, slider values by script and name like `UiState::params`, set for `path`.
+fn script_params(
+	params: &HashMap<(InternedPath, String), f64>,
+	path: &InternedPath,
+) -> BTreeMap<String, f64> {
+	params
+		.iter()
+		.filter(|((script, _), _)| script == path)
+		.map(|((_, name), &v)| (name.clone(), v))
+		.collect()
+}
+
+/// How outputs map onto the terrain mesh: normalized samples times `height` tall, centered on the
+/// origin with their longer side `extent` wide.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct TerrainScale {
+	height: f32,
+	extent: f32,
+}
+
+impl TerrainScale {
+	/// World distance between neighbouring samples of a `(width, height)` output.
+	fn spacing(self, (width, height): (usize, usize)) -> f32 {
+		self.extent / width.max(height).saturating_sub(1).max(1) as f32
+	}
+
+	/// Ground plane position of the (fractional) sample coordinates `sample` of a `size` output.
+	fn to_world(self, size: (usize, usize), sample: Vec2) -> Vec2 {
+		(sample - Self::middle(size)) * self.spacing(size)
+	}
+
+	/// Inverse of `to_world`.
+	fn to_sample(self, size: (usize, usize), pos: Vec2) -> Vec2 {
+		pos / self.spacing(size) + Self::middle(size)
+	}
+
+	/// Sample coordinates of the middle of a `size` output, placed on the origin.
+	fn middle((width, height): (usize, usize)) -> Vec2 {
+		vec2(width.saturating_sub(1) as f32, height.saturating_sub(1) as f32) / 2.0
+	}
 }
 
 fn setup(
@@ -217,6 +693,8 @@ fn setup(
 	mut meshes: ResMut<Assets<Mesh>>,
 	mut viewport2d: ResMut<Viewport2D>,
 	mut viewport3d: ResMut<Viewport3D>,
+	mut uiState: ResMut<UiState>,
+	savedState: Res<persist::AppState>,
 	mut noiseGenRequests: EventWriter<NoiseGenRequest>,
 ) {
 	let noiseImage = Image::new_fill(
@@ -230,101 +708,134 @@ fn setup(
 		TextureFormat::Rgba32Float,
 		default(),
 	);
+	for layer in &mut uiState.layers {
+		layer.image = images.add(noiseImage.clone());
+	}
 	let image = images.add(noiseImage);
-	let mesh = meshes.add({
-		let mut mesh = Mesh::new(PrimitiveTopology::TriangleList, RenderAssetUsages::all());
-		mesh.insert_attribute(
-			Mesh::ATTRIBUTE_POSITION,
-			vec![
-				Vec3::ZERO,
-				Vec3::Z,
-				Vec3::X,
-			],
-		);
-		mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, vec![Vec3::Y; 3]);
-		mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, vec![Vec2::ZERO; 3]);
-		mesh.generate_tangents().unwrap();
-		mesh
-	});
+	let heightsImage = Image::new_fill(
+		Extent3d {
+			width: 256,
+			height: 256,
+			depth_or_array_layers: 1,
+		},
+		TextureDimension::D2,
+		bytemuck::cast_slice(&[0f32]),
+		TextureFormat::R32Float,
+		default(),
+	);
+	let colorsImage = Image::new_fill(
+		Extent3d {
+			width: 256,
+			height: 256,
+			depth_or_array_layers: 1,
+		},
+		TextureDimension::D2,
+		&[0, 0, 0, 255],
+		TextureFormat::Rgba8UnormSrgb,
+		default(),
+	);
+	let waterOverlay = images.add(Image::new_fill(
+		Extent3d {
+			width: 256,
+			height: 256,
+			depth_or_array_layers: 1,
+		},
+		TextureDimension::D2,
+		&[0, 0, 0, 0],
+		TextureFormat::Rgba8UnormSrgb,
+		default(),
+	));
 	cmd.insert_resource(Heightmaps {
 		image: image.clone(),
-		mesh: mesh.clone(),
+		heights: images.add(heightsImage),
+		colors: images.add(colorsImage),
 	});
 
 	let camera2d = cmd
-		.spawn(Camera2dBundle {
-			camera: Camera {
-				target: RenderTarget::Image(viewport2d.bevyImage.clone()),
+		.spawn((
+			Camera2dBundle {
+				camera: Camera {
+					target: RenderTarget::Image(viewport2d.bevyImage.clone()),
+					..default()
+				},
+				transform: savedState.camera2d_transform().unwrap_or_default(),
 				..default()
 			},
-			..default()
-		})
+			gizmos::view_layers(Tab::D2),
+		))
 		.id();
 	cmd.spawn((
+		HeightmapSprite,
 		TargetCamera(camera2d),
 		SpriteBundle {
 			texture: image,
 			..default()
 		},
-	));
+	))
+	.with_children(|sprite| {
+		sprite.spawn((
+			water::WaterOverlay,
+			TargetCamera(camera2d),
+			SpriteBundle {
+				texture: waterOverlay,
+				transform: Transform::from_xyz(0.0, 0.0, 0.5),
+				visibility: Visibility::Hidden,
+				..default()
+			},
+		));
+	});
+	// only shown by the split view, the active layer is always drawn by the sprite above
+	for (index, layer) in uiState.layers.iter().enumerate() {
+		cmd.spawn((
+			layers::LayerSprite(index),
+			TargetCamera(camera2d),
+			SpriteBundle {
+				texture: layer.image.clone(),
+				visibility: Visibility::Hidden,
+				..default()
+			},
+		));
+	}
 
+	// the controller turns the camera to its initial angles once the 3D tab is first shown
+	let (transform, initialAngles) = match savedState.camera3d {
+		Some(saved) => (
+			Transform::from_translation(Vec3::from(saved.position)),
+			Vec2::from(saved.angles),
+		),
+		None => (
+			// off a corner of the terrain, looking over it
+			Transform::from_translation(vec3(-0.6, 0.6, -0.6) * uiState.extent)
+				.looking_at(Vec3::ZERO, Vec3::Y),
+			vec2(225.0, -35.0),
+		),
+	};
 	cmd.spawn((
 		Camera3dBundle {
 			camera: Camera {
 				target: RenderTarget::Image(viewport3d.bevyImage.clone()),
 				..default()
 			},
-			transform: Transform::from_xyz(-2.5, 2.5, -2.5).looking_at(Vec3::ZERO, Vec3::Y),
+			transform,
 			..default()
 		},
 		Skybox {
 			image: assets.load(skyboxTexture),
 			brightness: 1000.0,
 		},
+		gizmos::view_layers(Tab::D3),
 	));
 	cmd.insert_resource(CameraControllerSettings {
-		initialAngles: vec2(225.0, -35.0),
-		baseSpeed: 10.0,
-		..default()
-	});
-	cmd.spawn(DirectionalLightBundle {
-		transform: Transform::IDENTITY
-			.looking_at(-vec3(-0.8035929, 0.39474383, -0.44543877), Vec3::Y),
-		directional_light: DirectionalLight {
-			shadows_enabled: true,
-			..default()
-		},
-		..default()
-	});
-	cmd.insert_resource(DirectionalLightShadowMap { size: 8192 });
-
-	let material = materials.add(StandardMaterial {
-		base_color_texture: Some(assets.load("ground_texture/albedo.jpg")),
-		normal_map_texture: Some(assets.load("ground_texture/normal.jpg")),
+		initialAngles,
 		..default()
 	});
-	cmd.spawn((
-		NoFrustumCulling,
-		PbrBundle {
-			mesh,
-			material,
-			..default()
-		},
-	));
+	// pointed and set up by `lighting::apply_lighting`
+	cmd.spawn(DirectionalLightBundle::default());
+	cmd.spawn((DirectionalLightBundle::default(), lighting::FillLight));
 
-	// water
-	let mesh = meshes.add(Rectangle::new(2f32.powi(14), 2f32.powi(14)));
-	let material = materials.add(StandardMaterial {
-		base_color: Color::srgba_u8(0x11, 0x7F, 0xD5, 0x7F),
-		alpha_mode: AlphaMode::Blend,
-		..default()
-	});
-	cmd.spawn(PbrBundle {
-		mesh,
-		material,
-		transform: Transform::IDENTITY.looking_to(Vec3::NEG_Y, Vec3::Z),
-		..default()
-	});
+	if uiState.selected().is_some() {
+		noiseGenRequests.send(NoiseGenRequest::AlgorithmChanged);
+	}
 
 	viewport2d.eguiImage = eguiCtx.add_image(viewport2d.bevyImage.clone_weak());
 	viewport3d.eguiImage = eguiCtx.add_image(viewport3d.bevyImage.clone_weak());
@@ -355,31 +866,237 @@ fn setup_cubemap(
 	*done = true;
 }
 
+/// Resources of the optional tools driven from the toolbar, bundled to keep `main_ui` under the
+/// system parameter limit.
+#[derive(SystemParam)]
+struct ToolPanels<'w> {
+	selfTestReport: ResMut<'w, selftest::SelfTestReport>,
+	thumbnails: ResMut<'w, thumbnails::Thumbnails>,
+	session: ResMut<'w, session::SessionRecorder>,
+	sceneMode: ResMut<'w, scene::SceneMode>,
+	inspector: ResMut<'w, inspect::Inspector>,
+	sweep: ResMut<'w, sweep::SeedSweep>,
+	water: ResMut<'w, water::WaterSettings>,
+	coloring: ResMut<'w, coloring::TerrainColoring>,
+	theme: ResMut<'w, theme::Theme>,
+	diffExport: ResMut<'w, diff::DiffExport>,
+	heightmapExport: ResMut<'w, export::HeightmapExport>,
+	editor: ResMut<'w, editor::ScriptEditor>,
+	gizmos: ResMut<'w, gizmos::GizmoSettings>,
+	presetImport: ResMut<'w, fnl::PresetImport>,
+	memory: ResMut<'w, memory::MemoryPanel>,
+	stats: ResMut<'w, stats::OutputStats>,
+}
+
+/// Continues `ToolPanels`, which is at the limit of a bundle's fields.
+#[derive(SystemParam)]
+struct MoreToolPanels<'w> {
+	viewSettings: ResMut<'w, scene::ViewSettings>,
+	walk: ResMut<'w, walk::WalkMode>,
+	pin: pin::PinControls<'w>,
+	erosion: ResMut<'w, erosion::ErosionPanel>,
+	scatter: ResMut<'w, scatter::ScatterPanel>,
+	bookmarks: ResMut<'w, bookmarks::CameraBookmarks>,
+	colormap: ResMut<'w, colormap::ColormapSettings>,
+	recorder: ResMut<'w, sequence::SweepRecorder>,
+	lighting: ResMut<'w, lighting::LightingSettings>,
+	regen: ResMut<'w, debounce::RegenDebounce>,
+	screenshots: ResMut<'w, screenshot::Screenshots>,
+	presets: ResMut<'w, presets::Presets>,
+	log: Res<'w, log_panel::SessionLog>,
+	graph: ResMut<'w, graph::NoiseGraph>,
+}
+
+/// Picks a nonzero seed offset different from `previous`, small enough to read off and retype.
+fn random_seed(previous: i64) -> i64 {
+	let nanos = std::time::SystemTime::now()
+		.duration_since(std::time::UNIX_EPOCH)
+		.unwrap_or_default()
+		.as_nanos() as u64;
+	let seed = 1 + (noise_rng::hash(nanos, previous as u64, 0) % 99_999) as i64;
+	if seed == previous { seed % 99_999 + 1 } else { seed }
+}
+
 fn main_ui(
 	mut eguiCtx: EguiContexts,
 	mut selectedTab: ResMut<SelectedTab>,
-	mut viewportSize: ResMut<ViewportSize>,
+	mut viewportSizes: ResMut<ViewportSizes>,
+	mut viewportRects: ResMut<ViewportRects>,
 	viewport2d: Res<Viewport2D>,
 	viewport3d: Res<Viewport3D>,
 	images: Res<Assets<Image>>,
 	mut uiState: ResMut<UiState>,
+	panels: ToolPanels,
+	scriptError: Res<ScriptError>,
+	noiseOutput: Option<Res<NoiseOutput>>,
 	mut noiseGenRequests: EventWriter<NoiseGenRequest>,
+	morePanels: MoreToolPanels,
+	mut settingsOpen: Local<bool>,
+	keyboard: Res<ButtonInput<KeyCode>>,
+	input: Res<input::ViewportInput>,
 ) {
+	let ToolPanels {
+		mut selfTestReport,
+		mut thumbnails,
+		mut session,
+		mut sceneMode,
+		mut inspector,
+		mut sweep,
+		mut water,
+		mut coloring,
+		mut theme,
+		mut diffExport,
+		mut heightmapExport,
+		mut editor,
+		mut gizmos,
+		mut presetImport,
+		mut memory,
+		mut stats,
+	} = panels;
+	let MoreToolPanels {
+		mut viewSettings,
+		mut walk,
+		mut pin,
+		mut erosion,
+		mut scatter,
+		mut bookmarks,
+		mut colormap,
+		mut recorder,
+		mut lighting,
+		mut regen,
+		mut screenshots,
+		mut presets,
+		log,
+		mut graph,
+	} = morePanels;
+
 	let eguiCtx = eguiCtx.ctx_mut();
+	// a focused text field, like the script editor, owns the keyboard
+	if keyboard.just_pressed(KeyCode::F5) && !eguiCtx.wants_keyboard_input() {
+		noiseGenRequests.send(NoiseGenRequest::Regenerate);
+	}
 	egui::TopBottomPanel::top("toolbar").show(eguiCtx, |ui| {
 		ui.horizontal(|ui| {
 			ui.selectable_value(&mut selectedTab.0, Tab::D2, "2D");
 			ui.selectable_value(&mut selectedTab.0, Tab::D3, "3D");
+			ui.selectable_value(&mut selectedTab.0, Tab::Both, "2D | 3D")
+				.on_hover_text("Both views side by side, the divider between them can be dragged");
+			if selectedTab.0 == Tab::Both {
+				panes::layout_ui(ui, &mut uiState.paneLayout);
+			}
+			ui.menu_button("View", |ui| {
+				gizmos::gizmo_ui(ui, &mut gizmos);
+				if selectedTab.shows(Tab::D3) {
+					ui.separator();
+					scene::view_ui(ui, &mut viewSettings);
+				}
+			});
+
+			// with both views shown, the toolbar has the last clicked one's controls
+			let pane = match selectedTab.0 {
+				Tab::Both => input.focused(),
+				pane => pane,
+			};
+			if pane == Tab::D3 {
+				ui.add_space(10.0);
+				ui.label("Scene");
+				let mut mode = *sceneMode;
+				egui::ComboBox::from_id_source("sceneMode")
+					.selected_text(mode.label())
+					.show_ui(ui, |ui| {
+						for option in scene::SceneMode::all {
+							ui.selectable_value(&mut mode, option, option.label());
+						}
+					});
+				// only touch the resource on an actual switch to keep change detection quiet
+				if mode != *sceneMode {
+					*sceneMode = mode;
+				}
+				ui.toggle_value(&mut water.windowOpen, "Water");
+				ui.toggle_value(&mut coloring.windowOpen, "Colors");
+				// opening the window doesn't move the lights
+				ui.toggle_value(&mut lighting.bypass_change_detection().open, "Lighting");
+				walk::walk_ui(ui, &mut walk);
+				bookmarks::bookmarks_ui(ui, &mut bookmarks);
+
+				ui.add_space(10.0);
+				ui.label("Slice z");
+				let resp = ui
+					.add(egui::DragValue::new(&mut uiState.sliceZ).speed(0.01))
+					.on_hover_text("Depth the volume of 3D sources like simplex3 is sliced at");
+				if resp.changed() {
+					noiseGenRequests.send(NoiseGenRequest::AlgorithmChanged);
+				}
+			} else {
+				ui.add_space(10.0);
+				ui.toggle_value(&mut water.windowOpen, "Water")
+					.on_hover_text("Water level and color, and marking the water in this view");
+				ui.toggle_value(&mut uiState.tilePreview, "Tile preview").on_hover_text(
+					"Repeat the image 3×3 to check that a tiling output's edges meet",
+				);
+				// opening the window isn't a change of colors to redraw for
+				ui.toggle_value(&mut colormap.bypass_change_detection().open, "Colormap")
+					.on_hover_text("Colors of the values in this view, and their legend");
+				let mut view = uiState.gradientView;
+				egui::ComboBox::from_id_source("gradientView")
+					.selected_text(view.label())
+					.show_ui(ui, |ui| {
+						for option in gradients::GradientView::all {
+							ui.selectable_value(&mut view, option, option.label());
+						}
+					})
+					.response
+					.on_hover_text(
+						"Normals, slopes and hillshading are measured at the 3D view's mesh height, \
+						 hillshading lit by its sun",
+					);
+				if view != uiState.gradientView {
+					uiState.gradientView = view;
+				}
+				if view == gradients::GradientView::Arrows {
+					ui.add(
+						egui::DragValue::new(&mut uiState.arrowStride)
+							.range(2 ..= 256)
+							.prefix("every "),
+					);
+				}
+				ui.add_space(10.0);
+				pin.ui(ui, noiseOutput.as_deref());
+				ui.add_space(10.0);
+				domain::domain_ui(ui, &mut uiState.domain);
+			}
+
+			ui.add_space(25.0);
+			layers::layer_ui(ui, &mut uiState, &mut noiseGenRequests);
+			ui.add_space(10.0);
+			presets::presets_ui(ui, &mut presets, &mut uiState, &mut noiseGenRequests, &log);
 
 			let UiState {
 				scripts,
-				selected,
-				diameter,
+				layers,
+				activeLayer,
+				outputWidth,
+				outputHeight,
+				coords,
 				height,
+				extent,
+				uiScale,
+				seed,
+				params,
+				precision,
+				backend,
+				supersampling,
+				decorrelate,
+				checkFinite,
+				animation,
+				allowHugeMesh,
+				previewAspect,
+				chunks: chunkSettings,
 				..
 			} = &mut *uiState;
+			let selected = &mut layers[*activeLayer].script;
 
-			ui.add_space(25.0);
+			ui.add_space(10.0);
 			ui.label("Script");
 			egui::ComboBox::from_id_source("script")
 				.selected_text(match selected {
@@ -388,113 +1105,546 @@ fn main_ui(
 				})
 				.show_ui(ui, |ui| {
 					let current = selected.clone();
-					for (i, path) in scripts.keys().enumerate() {
-						ui.selectable_value(selected, Some(path.clone()), &path.display);
-					}
+					// previews show what selecting the script would generate
+					let inputs = |path: &InternedPath| lua::ScriptInputs {
+						seed: *seed,
+						params: script_params(params, path),
+						coords: *coords,
+					};
+					let tree = script_tree::ScriptTree::new(scripts.iter());
+					tree.ui(ui, selected, &mut thumbnails, &inputs);
 					if *selected != current {
-						noiseGenRequests.send(NoiseGenRequest::AlgorithmChanged);
+						noiseGenRequests.send(NoiseGenRequest::LayerChanged(*activeLayer));
 					}
 				});
+			if selected.as_ref().is_some_and(|path| !scripts.contains_key(path)) {
+				ui.colored_label(egui::Color32::YELLOW, "missing").on_hover_text(
+					"The script was deleted or moved. Generation is skipped until it's back, or \
+					 another one is picked",
+				);
+			}
+
+			ui.toggle_value(&mut presetImport.open, "Import preset…");
+			ui.toggle_value(&mut editor.open, "Edit");
+			if ui
+				.button("🔄")
+				.on_hover_text("Run the scripts again even if nothing changed (F5)")
+				.clicked()
+			{
+				noiseGenRequests.send(NoiseGenRequest::Regenerate);
+			}
+			debounce::status_ui(ui, &regen);
+
+			ui.add_space(10.0);
+			ui.label("Size");
+			let sizes = 16 ..= 4096;
+			let mut changed =
+				ui.add(egui::DragValue::new(outputWidth).range(sizes.clone())).changed();
+			ui.label("×");
+			changed |= ui.add(egui::DragValue::new(outputHeight).range(sizes)).changed();
+			ui.menu_button("▾", |ui| {
+				for preset in diameterPresets {
+					let current = *outputWidth == preset && *outputHeight == preset;
+					if ui.selectable_label(current, format!("{preset}²")).clicked() {
+						changed |= !current;
+						(*outputWidth, *outputHeight) = (preset, preset);
+						ui.close_menu();
+					}
+				}
+			})
+			.response
+			.on_hover_text("Common square sizes");
+			ui.menu_button("Chunks", |ui| changed |= chunks::chunk_ui(ui, chunkSettings))
+				.response
+				.on_hover_text("Chunked generation, always square");
+			let current = *coords;
+			egui::ComboBox::from_id_source("coords")
+				.selected_text(coords.label())
+				.show_ui(ui, |ui| {
+					for option in Coords::all {
+						ui.selectable_value(coords, option, option.label());
+					}
+				})
+				.response
+				.on_hover_text(
+					"Coordinates samples are evaluated at: 0 to 1 or -1 to 1 along the longer \
+					 side, or one unit per pixel. Scripts read it from `Coords`",
+				);
+			changed |= *coords != current;
+			if changed {
+				noiseGenRequests.send(NoiseGenRequest::AlgorithmChanged);
+			}
+			let mut estimate = MeshEstimate::new(if chunkSettings.enabled {
+				(chunkSettings.diameter(), chunkSettings.diameter())
+			} else {
+				(*outputWidth, *outputHeight)
+			});
+			if viewSettings.flatShading {
+				estimate = estimate.flat();
+			}
+			if estimate.bytes > MeshEstimate::softLimit {
+				let color = if estimate.bytes > MeshEstimate::hardLimit {
+					egui::Color32::RED
+				} else {
+					egui::Color32::YELLOW
+				};
+				ui.colored_label(color, format!("mesh ~{}", estimate))
+					.on_hover_text("Large meshes use a lot of memory and take long to build");
+			}
+			if estimate.bytes > MeshEstimate::hardLimit {
+				let resp = ui.checkbox(allowHugeMesh, "Build anyway");
+				if resp.changed() && *allowHugeMesh {
+					noiseGenRequests.send(NoiseGenRequest::ModelParamsChanged);
+				}
+			}
+
+			ui.add_space(10.0);
+			ui.label("Precision");
+			let current = *precision;
+			egui::ComboBox::from_id_source("precision")
+				.selected_text(precision.label())
+				.show_ui(ui, |ui| {
+					for option in lua::Precision::all {
+						ui.selectable_value(precision, option, option.label());
+					}
+				})
+				.response
+				.on_hover_text(
+					"f32 is faster but loses coordinate precision far from the origin; \
+					 f64 stays exact at the cost of some speed",
+				);
+			if *precision != current {
+				noiseGenRequests.send(NoiseGenRequest::AlgorithmChanged);
+			}
+			let current = *backend;
+			egui::ComboBox::from_id_source("backend")
+				.selected_text(backend.label())
+				.show_ui(ui, |ui| {
+					for option in gpu::Backend::all {
+						ui.selectable_value(backend, option, option.label());
+					}
+				})
+				.response
+				.on_hover_text("Evaluate on the GPU, in f32, when the script's nodes allow it");
+			if *backend != current {
+				noiseGenRequests.send(NoiseGenRequest::AlgorithmChanged);
+			}
+			let fallback = noiseOutput.as_ref().and_then(|output| output.gpuFallback.clone());
+			if let Some(reason) = fallback {
+				ui.colored_label(ui.visuals().warn_fg_color, "CPU fallback").on_hover_text(reason);
+			}
+			let current = *supersampling;
+			egui::ComboBox::from_id_source("supersampling")
+				.selected_text(format!("{} spp", supersampling.pow(2)))
+				.show_ui(ui, |ui| {
+					for option in supersamplingOptions {
+						ui.selectable_value(supersampling, option, format!("{} spp", option.pow(2)));
+					}
+				})
+				.response
+				.on_hover_text(
+					"Samples averaged per pixel, on a regular grid over its footprint. Smooths \
+					 detail finer than a pixel at that many times the generation time",
+				);
+			if *supersampling != current {
+				noiseGenRequests.send(NoiseGenRequest::AlgorithmChanged);
+			}
+			if let Some(elapsed) = noiseOutput.as_ref().and_then(|output| output.elapsed) {
+				ui.label(format!("{elapsed:.1?}"))
+					.on_hover_text("Duration of the last generation, compiling the script included");
+			}
 
 			ui.add_space(10.0);
-			ui.label("Diameter");
-			let resp = ui.add(egui::DragValue::new(diameter).range(16 ..= 4096));
+			if animation::animation_ui(ui, animation) {
+				noiseGenRequests.send(NoiseGenRequest::AlgorithmChanged);
+			}
+			let resp = ui
+				.checkbox(decorrelate, "Decorrelate")
+				.on_hover_text("Rotate and offset every noise source so sources sharing a seed don't line up");
 			if resp.changed() {
 				noiseGenRequests.send(NoiseGenRequest::AlgorithmChanged);
 			}
+			let resp = ui.checkbox(checkFinite, "Check NaN").on_hover_text(
+				"Look for NaN and infinite samples after generating, and trace the first one \
+				 back to the node producing it",
+			);
+			if resp.changed() {
+				noiseGenRequests.send(NoiseGenRequest::AlgorithmChanged);
+			}
+			let nonFinite = noiseOutput.as_ref().and_then(|output| output.nonFinite.as_ref());
+			if let Some(nonFinite) = nonFinite.filter(|_| *checkFinite) {
+				ui.colored_label(ui.visuals().warn_fg_color, nonFinite.to_string())
+					.on_hover_text("Node numbers are those of the Inspector");
+			}
 
 			ui.add_space(10.0);
 			ui.label("Mesh height");
 			let resp = ui.add(egui::DragValue::new(height).speed(0.1));
 			if resp.changed() {
-				noiseGenRequests.send(NoiseGenRequest::ModelParamsChanged);
+				regen.request(NoiseGenRequest::ModelParamsChanged);
+			}
+			let resp = ui
+				.add(
+					egui::DragValue::new(extent)
+						.range(1.0 ..= 1e5)
+						// proportional, like a zoom
+						.speed(*extent * 0.01)
+						.suffix(" m"),
+				)
+				.on_hover_text("Width of the terrain's longer side, whatever the size");
+			if resp.changed() {
+				regen.request(NoiseGenRequest::ModelParamsChanged);
 			}
-		});
-	});
-	egui::CentralPanel::default().show(eguiCtx, |ui| {
-		let size = ui.available_size();
-		viewportSize.0 = UVec2::from((size.x as _, size.y as _));
-
-		match selectedTab.0 {
-			Tab::D2 => {
-				let img = ImageSource::Texture(SizedTexture::new(viewport2d.eguiImage, size));
-				ui.image(img);
-			},
-			Tab::D3 => {
-				let img = ImageSource::Texture(SizedTexture::new(viewport3d.eguiImage, size));
-				ui.image(img);
-			},
-		}
-	});
-}
 
-fn update_viewport_size(
-	viewportSize: Res<ViewportSize>,
-	viewport2d: Res<Viewport2D>,
-	viewport3d: Res<Viewport3D>,
-	mut images: ResMut<Assets<Image>>,
-	mut lastSize: Local<UVec2>,
-) {
-	if viewportSize.0 == *lastSize {
-		return;
-	}
-	*lastSize = viewportSize.0;
+			ui.add_space(10.0);
+			ui.label("Aspect");
+			let label = previewAspects
+				.iter()
+				.find(|(_, aspect)| aspect == previewAspect)
+				.map_or("Fill", |(label, _)| *label);
+			egui::ComboBox::from_id_source("previewAspect")
+				.selected_text(label)
+				.show_ui(ui, |ui| {
+					for (label, aspect) in previewAspects {
+						ui.selectable_value(previewAspect, aspect, label);
+					}
+				})
+				.response
+				.on_hover_text("Letterbox the viewport to preview fixed-aspect exports");
 
-	let size = Extent3d {
-		width: viewportSize.0.x,
-		height: viewportSize.0.y,
-		depth_or_array_layers: 1,
-	};
-	let viewport2d = images.get_mut(&viewport2d.bevyImage).unwrap();
-	viewport2d.resize(size);
-	let viewport3d = images.get_mut(&viewport3d.bevyImage).unwrap();
-	viewport3d.resize(size);
-}
+			ui.add_space(10.0);
+			ui.label("UI scale");
+			ui.add(
+				egui::DragValue::new(uiScale)
+					.range(uiScaleRange)
+					.speed(0.01)
+					.fixed_decimals(2),
+			)
+			.on_hover_text("Ctrl+= / Ctrl+-");
 
-fn camera_controller_2d(
-	mut camera: Query<&mut Transform, With<Camera2d>>,
-	time: Res<Time>,
+			ui.add_space(10.0);
+			let code = selected.as_ref().and_then(|path| scripts.get(path));
+			let sweepClicked = ui
+				.add_enabled(code.is_some(), egui::Button::new("Seed sweep"))
+				.clicked();
+			if let Some(code) = code.filter(|_| sweepClicked) {
+				sweep.start(code, *seed, *precision);
+			}
+			ui.toggle_value(&mut recorder.open, "Record sweep")
+				.on_hover_text("Save a numbered image per value of a parameter swept over a range");
+			if let Some((done, total)) = recorder.progress() {
+				ui.spinner();
+				ui.label(format!("{done}/{total}"))
+					.on_hover_text("Frames of the recorded sweep written, generation waits for it");
+				if ui.small_button("×").on_hover_text("Cancel the sweep").clicked() {
+					recorder.cancel();
+				}
+			}
+			ui.label("Seed");
+			let mut seedChanged = ui
+				.add(egui::DragValue::new(seed).speed(0.2))
+				.on_hover_text("Offset added to every seed in the script, also its `Seed` global")
+				.changed();
+			if ui.button("🎲").on_hover_text("Random seed").clicked() {
+				*seed = random_seed(*seed);
+				seedChanged = true;
+			}
+			if *seed != 0 &&
+				ui.small_button("×").on_hover_text("Use the script's own seeds").clicked()
+			{
+				*seed = 0;
+				seedChanged = true;
+			}
+			if seedChanged {
+				noiseGenRequests.send(NoiseGenRequest::AlgorithmChanged);
+			}
+
+			ui.add_space(10.0);
+			let label = if session.replaying() {
+				"Session (replaying)"
+			} else {
+				"Session"
+			};
+			ui.toggle_value(&mut session.windowOpen, label);
+			ui.toggle_value(&mut inspector.open, "Inspector");
+			ui.toggle_value(&mut graph.open, "Graph");
+			ui.toggle_value(&mut heightmapExport.open, "Export");
+			ui.toggle_value(&mut diffExport.open, "Diff");
+			ui.toggle_value(&mut memory.open, "Memory");
+			ui.toggle_value(&mut stats.open, "Stats");
+			ui.toggle_value(&mut erosion.open, "Erosion");
+			ui.toggle_value(&mut scatter.open, "Scatter");
+			ui.toggle_value(&mut settingsOpen, "Settings");
+			screenshot::screenshot_ui(ui, &mut screenshots);
+
+			ui.add_space(10.0);
+			if ui.button("Self-test").clicked() {
+				selfTestReport.0 = Some(selftest::run());
+			}
+		});
+	});
+
+	let mut sessionOpen = session.windowOpen;
+	egui::Window::new("Session")
+		.open(&mut sessionOpen)
+		.show(eguiCtx, |ui| {
+			session::session_ui(ui, &mut session, &mut uiState, &mut noiseGenRequests);
+		});
+	session.windowOpen = sessionOpen;
+
+	let mut waterOpen = water.windowOpen;
+	egui::Window::new("Water")
+		.open(&mut waterOpen)
+		.show(eguiCtx, |ui| {
+			water::water_ui(ui, &mut water);
+		});
+	water.windowOpen = waterOpen;
+
+	let mut coloringOpen = coloring.windowOpen;
+	egui::Window::new("Terrain colors")
+		.open(&mut coloringOpen)
+		.show(eguiCtx, |ui| {
+			coloring::coloring_ui(ui, &mut coloring);
+		});
+	coloring.windowOpen = coloringOpen;
+
+	let mut lightingOpen = lighting.open;
+	egui::Window::new("Lighting")
+		.open(&mut lightingOpen)
+		.show(eguiCtx, |ui| {
+			// edit a copy so the lights are only updated when something was edited
+			let mut edited = lighting.clone();
+			lighting::lighting_ui(ui, &mut edited);
+			if edited != *lighting {
+				*lighting = edited;
+			}
+		});
+	lighting.bypass_change_detection().open = lightingOpen;
+
+	let mut importOpen = presetImport.open;
+	egui::Window::new("Import preset")
+		.open(&mut importOpen)
+		.show(eguiCtx, |ui| {
+			fnl::import_ui(ui, &mut presetImport);
+		});
+	presetImport.open = importOpen;
+
+	egui::Window::new("Settings")
+		.open(&mut settingsOpen)
+		.show(eguiCtx, |ui| {
+			ui.heading("Style");
+			// edit a copy so the resource only registers a change when something was edited
+			let mut edited = theme.clone();
+			theme::theme_ui(ui, &mut edited);
+			if edited != *theme {
+				*theme = edited;
+			}
+			ui.heading("Generation");
+			debounce::debounce_ui(ui, &mut regen);
+			workers::workers_ui(ui);
+		});
+
+	if let Some(results) = &selfTestReport.0 {
+		let mut open = true;
+		egui::Window::new("Self-test")
+			.open(&mut open)
+			.collapsible(false)
+			.show(eguiCtx, |ui| {
+				egui::Grid::new("selftest").striped(true).show(ui, |ui| {
+					for res in results {
+						if res.passed {
+							ui.colored_label(egui::Color32::GREEN, "PASS");
+						} else {
+							ui.colored_label(egui::Color32::RED, "FAIL");
+						}
+						ui.label(&res.name);
+						ui.label(format!("{:.3}ms", res.duration.as_secs_f64() * 1000.0));
+						ui.label(&res.detail);
+						ui.end_row();
+					}
+				});
+				let failed = results.iter().filter(|res| !res.passed).count();
+				ui.label(format!("{} passed, {failed} failed", results.len() - failed));
+			});
+		if !open {
+			selfTestReport.0 = None;
+		}
+	}
+	let declared = noiseOutput.as_ref().map_or(&[][..], |output| &output.params[..]);
+	if let Some(path) = uiState.selected().cloned().filter(|_| !declared.is_empty()) {
+		egui::TopBottomPanel::top("scriptParams").show(eguiCtx, |ui| {
+			ui.horizontal_wrapped(|ui| {
+				for param in declared {
+					let key = (path.clone(), param.name.clone());
+					let mut value = uiState.params.get(&key).copied().unwrap_or(param.value);
+					ui.label(&param.name);
+					let resp = ui.add(egui::Slider::new(&mut value, param.min ..= param.max));
+					let reset = ui
+						.add_enabled(value != param.default, egui::Button::new("↺").small())
+						.on_hover_text(format!("Reset to {}", param.default))
+						.clicked();
+					if reset {
+						uiState.params.remove(&key);
+					} else if resp.changed() {
+						uiState.params.insert(key, value);
+					}
+					if reset {
+						noiseGenRequests.send(NoiseGenRequest::AlgorithmChanged);
+					} else if resp.changed() {
+						regen.request(NoiseGenRequest::AlgorithmChanged);
+					}
+					ui.add_space(10.0);
+				}
+			});
+		});
+	}
+	if let Some(err) = &scriptError.0 {
+		egui::TopBottomPanel::bottom("scriptError").show(eguiCtx, |ui| {
+			egui::ScrollArea::vertical().max_height(160.0).show(ui, |ui| {
+				ui.colored_label(egui::Color32::RED, err);
+			});
+		});
+	}
+	egui::CentralPanel::default().show(eguiCtx, |ui| {
+		let available = ui.available_rect_before_wrap();
+		let pixelsPerPoint = ui.ctx().pixels_per_point();
+		let hidden = ViewportRect {
+			rect: egui::Rect::NOTHING,
+			pixelsPerPoint,
+		};
+		let (rect2d, rect3d) = match selectedTab.0 {
+			Tab::D2 => (Some(available), None),
+			Tab::D3 => (None, Some(available)),
+			Tab::Both => {
+				let [rect2d, _, rect3d] = uiState.paneLayout.split(available);
+				panes::divider(ui, available, &mut uiState.paneLayout);
+				(Some(rect2d), Some(rect3d))
+			},
+		};
+		let aspect = uiState.previewAspect;
+		viewportRects.d2 = rect2d.map_or(hidden, |rect| {
+			let (rect, size) = viewport_pane(ui, rect, aspect, viewport2d.eguiImage);
+			viewportSizes.d2 = size;
+			rect
+		});
+		viewportRects.d3 = rect3d.map_or(hidden, |rect| {
+			let (rect, size) = viewport_pane(ui, rect, aspect, viewport3d.eguiImage);
+			viewportSizes.d3 = size;
+			rect
+		});
+	});
+}
+
+/// Draws a view's image in `available`, letterboxed to `aspect` if set. Returns where it was drawn
+/// and the size of its render target in physical pixels.
+fn viewport_pane(
+	ui: &mut egui::Ui,
+	available: egui::Rect,
+	aspect: Option<(u32, u32)>,
+	texture: TextureId,
+) -> (ViewportRect, UVec2) {
+	// the render target only covers the letterboxed area, so it matches what gets exported
+	let size = match aspect {
+		None => available.size(),
+		Some((w, h)) => {
+			let aspect = w as f32 / h as f32;
+			let width = available.width().min(available.height() * aspect);
+			egui::vec2(width, width / aspect)
+		},
+	};
+	let pixelsPerPoint = ui.ctx().pixels_per_point();
+	let pixels = UVec2::from(((size.x * pixelsPerPoint) as _, (size.y * pixelsPerPoint) as _));
+	if aspect.is_some() {
+		ui.painter()
+			.rect_filled(available, 0.0, egui::Color32::from_black_alpha(160));
+	}
+
+	let img = ImageSource::Texture(SizedTexture::new(texture, size));
+	let resp = ui.put(
+		egui::Rect::from_center_size(available.center(), size),
+		egui::Image::new(img),
+	);
+	let rect = ViewportRect {
+		rect: resp.rect,
+		pixelsPerPoint,
+	};
+	(rect, pixels)
+}
+
+fn update_viewport_size(
+	viewportSizes: Res<ViewportSizes>,
+	viewport2d: Res<Viewport2D>,
+	viewport3d: Res<Viewport3D>,
+	mut images: ResMut<Assets<Image>>,
+	limits: Res<textures::TextureLimits>,
+	mut lastSizes: Local<[UVec2; 2]>,
+) {
+	let views = [
+		(viewportSizes.d2, &viewport2d.bevyImage),
+		(viewportSizes.d3, &viewport3d.bevyImage),
+	];
+	for ((size, handle), lastSize) in views.into_iter().zip(&mut *lastSizes) {
+		if size == *lastSize {
+			continue;
+		}
+		*lastSize = size;
+
+		// a rejected size keeps the previous render target, stretched to the pane
+		if let Err(err) = limits.check(size, TextureFormat::bevy_default()) {
+			error!("viewport size {size} rejected, keeping the previous render target: {err}");
+			continue;
+		}
+		let size = Extent3d {
+			width: size.x,
+			height: size.y,
+			depth_or_array_layers: 1,
+		};
+		match images.get_mut(handle) {
+			Some(image) => image.resize(size),
+			None => error!("viewport render target {handle:?} is missing"),
+		}
+	}
+}
+
+fn camera_controller_2d(
+	mut camera: Query<&mut Transform, With<Camera2d>>,
+	time: Res<Time>,
 	keyboard: Res<ButtonInput<KeyCode>>,
-	mouseButtons: Res<ButtonInput<MouseButton>>,
 	selectedTab: Res<SelectedTab>,
-	mut mouseMotion: EventReader<MouseMotion>,
-	mut mouseScroll: EventReader<MouseWheel>,
+	input: Res<input::ViewportInput>,
+	uiState: Res<UiState>,
 	mut zoom: Local<f32>,
 	mut init: Local<bool>,
 ) {
-	if selectedTab.0 != Tab::D2 {
+	if !selectedTab.shows(Tab::D2) {
 		return;
 	}
 
+	let mut cameraTransform = camera.single_mut();
+
+	// starts from the zoom the camera was restored with
 	if !*init {
 		*init = true;
-		*zoom = 1.0;
+		*zoom = cameraTransform.scale.x;
 	}
 
-	let mut cameraTransform = camera.single_mut();
-
-	if keyboard.just_pressed(KeyCode::Space) {
+	if input.keyboard(Tab::D2) && keyboard.just_pressed(KeyCode::Space) {
 		cameraTransform.translation = Vec3::ZERO;
 	}
 
-	if mouseButtons.pressed(MouseButton::Left) {
-		let mut motion = Vec2::ZERO;
-		for event in mouseMotion.read() {
-			motion += event.delta;
-		}
-		motion.x *= -1.0;
-		motion *= *zoom;
-		cameraTransform.translation += Vec3::from((motion, 0.0));
-	} else {
-		mouseMotion.clear();
-	}
+	let mut motion = input.drag_motion(Tab::D2);
+	motion.x *= -1.0;
+	motion *= *zoom;
+	cameraTransform.translation += Vec3::from((motion, 0.0));
 
-	let mut zoomDelta = 0.0;
-	for event in mouseScroll.read() {
-		zoomDelta -= event.y;
+	let zoomDelta = -input.scroll(Tab::D2);
+	if uiState.domain.followCamera {
+		// zooming deep into the domain takes steps in proportion to the zoom
+		*zoom *= 1.1f32.powf(zoomDelta);
+	} else {
+		*zoom += zoomDelta * 0.1;
 	}
-	*zoom += zoomDelta * 0.1;
-	*zoom = zoom.clamp(0.1, 4.0);
+	*zoom = zoom.clamp(uiState.domain.min_zoom(), 4.0);
 	if zoomDelta != 0.0 {
 		cameraTransform.scale = Vec3::splat(*zoom);
 	}
@@ -504,6 +1654,8 @@ fn camera_controller_2d(
 struct CameraControllerSettings {
 	pub initialAngles: Vec2,
 	pub mouseSensitivity: f32,
+	/// Flying speed in terrain extents per second, so crossing the terrain takes as long at any
+	/// `UiState::extent`.
 	pub baseSpeed: f32,
 }
 
@@ -512,23 +1664,63 @@ impl Default for CameraControllerSettings {
 		Self {
 			initialAngles: default(),
 			mouseSensitivity: 0.25,
-			baseSpeed: 1.0,
+			baseSpeed: 0.04,
 		}
 	}
 }
 
+/// Surrounds the 2D view's image with copies of itself while `UiState::tilePreview` is on, to
+/// check that tiling outputs continue seamlessly across their edges.
+fn tile_preview(
+	mut cmd: Commands,
+	uiState: Res<UiState>,
+	images: Res<Assets<Image>>,
+	mut sprite: Query<
+		(Entity, &Handle<Image>, &mut Sprite, Has<ImageScaleMode>),
+		With<HeightmapSprite>,
+	>,
+) {
+	let (ent, image, mut sprite, tiled) = sprite.single_mut();
+	let size = images
+		.get(image)
+		.map(|image| image.size_f32() * 3.0)
+		.filter(|_| uiState.tilePreview && !uiState.splitView);
+	// the sprite's tiles are recomputed whenever it's written to
+	if sprite.custom_size != size {
+		sprite.custom_size = size;
+	}
+	match (size.is_some(), tiled) {
+		(true, false) => {
+			cmd.entity(ent).insert(ImageScaleMode::Tiled {
+				tile_x: true,
+				tile_y: true,
+				stretch_value: 1.0,
+			});
+		},
+		(false, true) => {
+			cmd.entity(ent).remove::<ImageScaleMode>();
+		},
+		_ => {},
+	}
+}
+
 fn camera_controller_3d(
 	mut camera: Query<&mut Transform, With<Camera3d>>,
 	time: Res<Time>,
 	keyboard: Res<ButtonInput<KeyCode>>,
-	mouseButtons: Res<ButtonInput<MouseButton>>,
 	settings: Option<Res<CameraControllerSettings>>,
 	selectedTab: Res<SelectedTab>,
-	mut mouseMotion: EventReader<MouseMotion>,
+	handles: Res<handles::Handles>,
+	input: Res<input::ViewportInput>,
+	mut walk: ResMut<walk::WalkMode>,
+	mut bookmarks: ResMut<bookmarks::CameraBookmarks>,
+	uiState: Res<UiState>,
+	noiseOutput: Option<Res<NoiseOutput>>,
 	mut angles: Local<Vec2>,
 	mut initialized: Local<bool>,
+	mut walkState: Local<walk::WalkState>,
 ) {
-	if selectedTab.0 != Tab::D3 {
+	if !selectedTab.shows(Tab::D3) {
 		return;
 	}
 
@@ -545,19 +1737,30 @@ fn camera_controller_3d(
 		*initialized = true;
 		*angles = settings.initialAngles;
 	}
+	// a bookmark transition owns the camera, and leaves it at angles to continue from
+	if bookmarks.controls_camera() {
+		return;
+	}
+	if bookmarks.take_handback() {
+		let (yaw, pitch, _) = camera.single().rotation.to_euler(EulerRot::YXZ);
+		*angles = Vec2::new(yaw.to_degrees(), pitch.to_degrees());
+	}
 
-	if mouseButtons.pressed(MouseButton::Left) {
-		let mut motion = Vec2::ZERO;
-		for ev in mouseMotion.read() {
-			motion += -ev.delta * settings.mouseSensitivity;
-		}
-		*angles += motion;
+	if !handles.captures_pointer() {
+		*angles += -input.drag_motion(Tab::D3) * settings.mouseSensitivity;
 		angles.y = angles.y.clamp(-89.9, 89.9);
-	} else {
-		mouseMotion.clear();
 	}
 
 	let mut velocity = Vec3::ZERO;
+	// a focused text field owns the keyboard
+	let noKeys = ButtonInput::default();
+	let keyboard = if input.keyboard(Tab::D3) { &*keyboard } else { &noKeys };
+	if keyboard.just_pressed(KeyCode::KeyF) {
+		walk.enabled = !walk.enabled;
+	}
+	if !walk.enabled {
+		walkState.reset();
+	}
 	if keyboard.pressed(KeyCode::KeyW) {
 		velocity.z += 1.0;
 	}
@@ -570,10 +1773,11 @@ fn camera_controller_3d(
 	if keyboard.pressed(KeyCode::KeyA) {
 		velocity.x -= 1.0;
 	}
-	if keyboard.pressed(KeyCode::KeyQ) {
+	// walking leaves the height to the terrain
+	if keyboard.pressed(KeyCode::KeyQ) && !walk.enabled {
 		velocity.y += 1.0;
 	}
-	if keyboard.pressed(KeyCode::KeyZ) {
+	if keyboard.pressed(KeyCode::KeyZ) && !walk.enabled {
 		velocity.y -= 1.0;
 	}
 
@@ -589,7 +1793,7 @@ fn camera_controller_3d(
 		.reject_from_normalized(Vec3::Y)
 		.normalize();
 	let up = Vec3::Y;
-	let speed = settings.baseSpeed *
+	let speed = settings.baseSpeed * uiState.extent *
 		if keyboard.pressed(KeyCode::ShiftLeft) {
 			2.0
 		} else if keyboard.pressed(KeyCode::AltLeft) {
@@ -602,44 +1806,171 @@ fn camera_controller_3d(
 	transform.translation += (forward * velocity.z + right * velocity.x + up * velocity.y)
 		.normalize_or_zero() *
 		speed * time.delta_seconds();
+
+	if let Some(output) = noiseOutput.as_ref().filter(|_| walk.enabled) {
+		walkState.update(
+			&mut transform.translation,
+			output,
+			uiState.terrain_scale(),
+			walk.eyeHeight,
+			output.is_changed(),
+			time.delta_seconds(),
+		);
+	}
 }
 
-#[derive(Clone, Copy, Event)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Event)]
 enum NoiseGenRequest {
+	/// Regenerates the active and every visible layer.
 	AlgorithmChanged,
+	/// Like `AlgorithmChanged`, but also runs scripts whose outputs are up to date again, for
+	/// scripts using `math.random` or reading files. Sent by F5 and the toolbar.
+	Regenerate,
+	/// Regenerates one layer, by index into `UiState::layers`.
+	LayerChanged(usize),
 	ModelParamsChanged,
 }
 
+/// A frame's noise generation requests merged into what they ask for, so requests sent together,
+/// like a script save and a UI change landing on the same frame, are handled once.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+struct CoalescedRequests {
+	/// Some request regenerates the active and every visible layer.
+	allLayers: bool,
+	/// Layers to regenerate besides those.
+	layers: BTreeSet<usize>,
+	/// Scripts run again even if their outputs are up to date, see `NoiseGenRequest::Regenerate`.
+	force: bool,
+	/// Only the mesh has to be rebuilt. Regenerating every layer supersedes it, the new output
+	/// rebuilds the mesh anyway.
+	rebuildMesh: bool,
+}
+
+fn coalesce_requests(requests: impl IntoIterator<Item = NoiseGenRequest>) -> CoalescedRequests {
+	let mut coalesced = CoalescedRequests::default();
+	for request in requests {
+		match request {
+			NoiseGenRequest::AlgorithmChanged => coalesced.allLayers = true,
+			NoiseGenRequest::Regenerate => {
+				coalesced.allLayers = true;
+				coalesced.force = true;
+			},
+			NoiseGenRequest::LayerChanged(layer) => {
+				coalesced.layers.insert(layer);
+			},
+			NoiseGenRequest::ModelParamsChanged => coalesced.rebuildMesh = true,
+		}
+	}
+	coalesced.rebuildMesh &= !coalesced.allLayers;
+	coalesced
+}
+
+/// Directory scripts are loaded from, subdirectories included.
+const scriptsDir: &str = "assets/scripts";
+
+fn is_lua_script(path: &Path) -> bool {
+	let extension = OsStr::new("lua");
+	matches!(path.extension(), Some(extension))
+}
+
+/// Reads every script in `path` and its subdirectories, or `path` itself if it's a script.
+fn scan_scripts(path: &Path, scripts: &mut HashMap<InternedPath, String>) {
+	if path.is_file() {
+		if is_lua_script(path) {
+			// gone again already, as with editors saving through a temporary file
+			let res = std::fs::read_to_string(path).map_err(anyhow::Error::from);
+			match res.and_then(|contents| Ok((InternedPath::new(path.to_owned())?, contents))) {
+				Ok((ipath, contents)) => {
+					scripts.insert(ipath, contents);
+				},
+				Err(err) => warn!("failed to read {}: {err:#}", path.display()),
+			}
+		}
+		return;
+	}
+	let entries = match std::fs::read_dir(path) {
+		Ok(entries) => entries,
+		Err(err) => {
+			warn!("failed to read {}: {err}", path.display());
+			return;
+		},
+	};
+	for entry in entries.flatten() {
+		scan_scripts(&entry.path(), scripts);
+	}
+}
+
+/// Canonical `scriptsDir`, which `InternedPath::display` is relative to.
+fn scripts_root() -> &'static Path {
+	static root: OnceLock<PathBuf> = OnceLock::new();
+	root.get_or_init(|| Path::new(scriptsDir).canonicalize().unwrap_or(scriptsDir.into()))
+}
+
+/// A canonical script path, shared by every `InternedPath` of the same file so comparing and
+/// hashing them stays cheap. The entry is dropped along with the last of them.
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
 struct InternedPath(Arc<InternedPathInner>);
 
 #[derive(Debug, PartialEq, Eq, Hash)]
 struct InternedPathInner {
 	pub path: PathBuf,
+	/// Path relative to `scriptsDir` with `/` separators, or the file name of scripts outside it.
+	/// Names that aren't UTF-8 are shown with replacement characters.
 	pub display: String,
 }
 
-impl InternedPath {
-	pub fn new(mut path: PathBuf) -> Self {
-		static internedPaths: OnceLock<RwLock<HashSet<InternedPath>>> = OnceLock::new();
-		let interned = internedPaths.get_or_init(|| RwLock::new(HashSet::new()));
-
-		path = path.canonicalize().unwrap();
+/// Every live `InternedPath` by canonical path.
+fn interned_paths() -> &'static RwLock<HashMap<PathBuf, Weak<InternedPathInner>>> {
+	static internedPaths: OnceLock<RwLock<HashMap<PathBuf, Weak<InternedPathInner>>>> =
+		OnceLock::new();
+	internedPaths.get_or_init(default)
+}
 
-		let read = interned.read().unwrap();
-		for ipath in read.iter() {
-			if ipath.path == path {
-				return ipath.clone();
-			}
+impl InternedPath {
+	/// Interns the canonical form of `path`, which fails if it doesn't exist (anymore).
+	pub fn new(path: PathBuf) -> AResult<Self> {
+		let path = path.canonicalize().with_context(|| format!("resolving {}", path.display()))?;
+		let interned = interned_paths();
+		if let Some(inner) = interned.read().unwrap().get(&path).and_then(Weak::upgrade) {
+			return Ok(Self(inner));
 		}
 
-		drop(read);
 		let mut write = interned.write().unwrap();
-		let display = path.file_name().unwrap().to_str().unwrap();
-		let display = format!("{display}");
-		let ipath = Self(Arc::new(InternedPathInner { path, display }));
-		write.insert(ipath.clone());
-		ipath
+		// interned by another thread meanwhile
+		if let Some(inner) = write.get(&path).and_then(Weak::upgrade) {
+			return Ok(Self(inner));
+		}
+		let display = match path.strip_prefix(scripts_root()) {
+			Ok(relative) => relative
+				.components()
+				.map(|component| component.as_os_str().to_string_lossy())
+				.collect::<Vec<_>>()
+				.join("/"),
+			Err(_) => path.file_name().unwrap_or(path.as_os_str()).to_string_lossy().into_owned(),
+		};
+		let inner = Arc::new(InternedPathInner {
+			path: path.clone(),
+			display,
+		});
+		write.insert(path, Arc::downgrade(&inner));
+		Ok(Self(inner))
+	}
+
+	/// Whether `path`, canonical, is interned, i.e. an `InternedPath` of it is still alive.
+	pub fn is_interned(path: &Path) -> bool {
+		interned_paths().read().unwrap().contains_key(path)
+	}
+}
+
+impl Drop for InternedPathInner {
+	fn drop(&mut self) {
+		let Ok(mut interned) = interned_paths().write() else {
+			return;
+		};
+		// the path may have been interned again since the last reference went away
+		if interned.get(&self.path).is_some_and(|weak| weak.strong_count() == 0) {
+			interned.remove(&self.path);
+		}
 	}
 }
 
@@ -651,12 +1982,6 @@ impl Deref for InternedPath {
 	}
 }
 
-impl From<PathBuf> for InternedPath {
-	fn from(path: PathBuf) -> Self {
-		Self::new(path)
-	}
-}
-
 impl Borrow<Path> for InternedPath {
 	fn borrow(&self) -> &Path {
 		&self.path
@@ -671,293 +1996,1296 @@ impl Borrow<PathBuf> for InternedPath {
 
 fn scripts_changed(
 	mut uiState: ResMut<UiState>,
-	mut noiseGenRequests: EventWriter<NoiseGenRequest>,
+	mut thumbnails: ResMut<thumbnails::Thumbnails>,
+	editor: Res<editor::ScriptEditor>,
+	mut regen: ResMut<debounce::RegenDebounce>,
+	noiseOutput: Option<Res<NoiseOutput>>,
+	scriptError: Res<ScriptError>,
+	log: Res<log_panel::SessionLog>,
 ) {
-	fn read_script(path: &Path) -> String {
-		std::fs::read_to_string(path).unwrap()
+	/// Where `path` was, canonicalized through its parent since it's gone itself.
+	fn gone_path(path: &Path) -> PathBuf {
+		path.parent()
+			.and_then(|parent| parent.canonicalize().ok())
+			.zip(path.file_name())
+			.map_or(path.to_owned(), |(parent, name)| parent.join(name))
 	}
 
-	fn is_lua_script(path: &Path) -> bool {
-		let extension = OsStr::new("lua");
-		matches!(path.extension(), Some(extension))
+	/// Forgets the script at `path`, or every script under it if it was a directory.
+	fn remove_under(
+		path: &Path,
+		scripts: &mut HashMap<InternedPath, String>,
+		thumbnails: &mut thumbnails::Thumbnails,
+	) {
+		let path = gone_path(path);
+		scripts.retain(|ipath, _| {
+			let removed = ipath.path.starts_with(&path);
+			if removed {
+				thumbnails.forget(ipath);
+			}
+			!removed
+		});
+	}
+
+	/// Finds the scripts at `path`, returning its canonical path. Layers keep their script while
+	/// it's gone, and since paths are interned, one deleted and created again, as some editors save,
+	/// is found under the same key and shown again.
+	fn appear(path: &Path, scripts: &mut HashMap<InternedPath, String>) -> Option<PathBuf> {
+		scan_scripts(path, scripts);
+		path.canonicalize().ok()
+	}
+
+	/// Picks up whatever happened to `path`: rereads it if it's a known script, finds it if it's
+	/// new and forgets it if it's gone. Returns its canonical path if a script's contents changed
+	/// or it appeared.
+	fn refresh(
+		path: &Path,
+		scripts: &mut HashMap<InternedPath, String>,
+		thumbnails: &mut thumbnails::Thumbnails,
+		editor: &editor::ScriptEditor,
+	) -> Option<PathBuf> {
+		let Ok(path) = path.canonicalize() else {
+			remove_under(path, scripts, thumbnails);
+			return None;
+		};
+		if !scripts.keys().any(|ipath| ipath.path == path) {
+			return appear(&path, scripts);
+		}
+		let newContents = match std::fs::read_to_string(&path) {
+			Ok(contents) => contents,
+			Err(err) => {
+				warn!("failed to reload {}: {err}", path.display());
+				return None;
+			},
+		};
+		// saved from the editor, which already applied it
+		if editor.wrote(&path, &newContents) {
+			return None;
+		}
+		let mut changed = false;
+		for (ipath, contents) in scripts.iter_mut().filter(|(ipath, _)| ipath.path == path) {
+			// editors often report a single save two or three times
+			if *contents != newContents {
+				*contents = newContents.clone();
+				thumbnails.forget(ipath);
+				changed = true;
+			}
+		}
+		changed.then_some(path)
 	}
 
 	let UiState {
 		channel,
 		scripts,
-		selected,
+		layers,
+		activeLayer,
 		..
 	} = &mut *uiState;
+	// other files, like editors' backups, come and go with every save
+	let watched = |path: &Path, what: &str| {
+		if is_lua_script(path) {
+			let message = format!("{} {what}", log_panel::watched_name(path));
+			log.record(log_panel::Severity::Info, log_panel::LogKind::FileWatcher, None, message);
+		}
+	};
+	let mut changed = vec![];
 	while let Ok(ev) = channel.recv_timeout(Duration::ZERO) {
+		let Some(path) = ev.paths.first() else {
+			continue;
+		};
 		match ev.kind {
-			EventKind::Create(CreateKind::File) => {
-				let path = &ev.paths[0];
-				if is_lua_script(path) {
-					scripts.insert(InternedPath::new(path.clone()), read_script(path));
-				}
+			// scripts moved in along with a directory don't get events of their own
+			EventKind::Create(CreateKind::File | CreateKind::Folder) => {
+				watched(path, "created");
+				changed.extend(appear(path, scripts));
 			},
-			EventKind::Remove(RemoveKind::File) => {
-				let path = &ev.paths[0];
-				if is_lua_script(path) {
-					scripts.remove(path);
-				}
+			EventKind::Remove(RemoveKind::File | RemoveKind::Folder) => {
+				watched(path, "removed");
+				remove_under(path, scripts, &mut thumbnails);
 			},
-			EventKind::Modify(ModifyKind::Data(_)) => {
-				let path = ev.paths[0].canonicalize().unwrap();
-				for (ipath, contents) in scripts.iter_mut() {
-					if ipath.path == path {
-						*contents = read_script(&path);
-					}
-				}
-				if selected.as_ref().map(Borrow::borrow) == Some(&path) {
-					noiseGenRequests.send(NoiseGenRequest::AlgorithmChanged);
-				}
+			EventKind::Modify(ModifyKind::Name(RenameMode::To)) => {
+				watched(path, "moved in");
+				changed.extend(appear(path, scripts));
 			},
-			EventKind::Modify(ModifyKind::Name(kind)) => match kind {
-				RenameMode::To => {
-					let path = &ev.paths[0];
-					if is_lua_script(path) {
-						scripts.insert(InternedPath::new(path.clone()), read_script(path));
-					}
-				},
-				RenameMode::From => {
-					let path = &ev.paths[0];
-					if is_lua_script(path) {
-						scripts.remove(path);
-					}
-				},
-				RenameMode::Both => {
-					let from = &ev.paths[0];
-					let to = &ev.paths[0];
-				},
-				RenameMode::Other | RenameMode::Any => {
-					panic!("detected script modification of unknown kind")
-				},
+			EventKind::Modify(ModifyKind::Name(RenameMode::From)) => {
+				watched(path, "moved away");
+				remove_under(path, scripts, &mut thumbnails);
 			},
-			EventKind::Modify(ModifyKind::Any) => {
-				let path = ev.paths[0].canonicalize().unwrap();
-				for (ipath, contents) in scripts.iter_mut() {
-					if ipath.path == path {
-						*contents = read_script(&path);
-					}
+			EventKind::Modify(ModifyKind::Name(RenameMode::Both)) => {
+				let [from, to] = &ev.paths[..] else {
+					warn!("rename event with {} paths: {:?}", ev.paths.len(), ev.paths);
+					continue;
+				};
+				watched(from, &format!("renamed to {}", log_panel::watched_name(to)));
+				remove_under(from, scripts, &mut thumbnails);
+				changed.extend(appear(to, scripts));
+				// layers keep showing the renamed scripts under their new names
+				let (from, Ok(to)) = (gone_path(from), to.canonicalize()) else {
+					continue;
+				};
+				for layer in layers.iter_mut() {
+					let Some(script) = &layer.script else {
+						continue;
+					};
+					let Ok(rest) = script.path.strip_prefix(&from) else {
+						continue;
+					};
+					let renamed = to.join(rest);
+					layer.script = scripts.keys().find(|ipath| ipath.path == renamed).cloned();
 				}
-				if selected.as_ref().map(Borrow::borrow) == Some(&path) {
-					noiseGenRequests.send(NoiseGenRequest::AlgorithmChanged);
+			},
+			// other renames, like editors atomically replacing the file, and changes of unknown kind
+			EventKind::Modify(
+				ModifyKind::Name(_) | ModifyKind::Data(_) | ModifyKind::Any | ModifyKind::Other,
+			) => {
+				for path in &ev.paths {
+					let refreshed = refresh(path, scripts, &mut thumbnails, &editor);
+					if refreshed.is_some() {
+						watched(path, "changed");
+					}
+					changed.extend(refreshed);
 				}
 			},
 			_ => {},
 		}
 	}
+
+	// one regeneration for however many events the saves produced
+	let shown = |path: &PathBuf| {
+		layers.iter().any(|layer| {
+			layer.script.as_ref().is_some_and(|script| script.path.starts_with(path))
+		})
+	};
+	// the compiled trees of scripts requiring a changed module are outdated although their own
+	// source isn't, and failed scripts may have been waiting for a fix to one
+	let failed = scriptError.0.is_some() || layers.iter().any(|layer| layer.error.is_some());
+	let modules = layers.iter().enumerate().flat_map(|(i, layer)| {
+		let output = if i == *activeLayer {
+			noiseOutput.as_deref()
+		} else {
+			layer.output.as_ref()
+		};
+		output.map_or(&[][..], |output| &output.modules[..])
+	});
+	let required = |path: &PathBuf| modules.clone().any(|module| module.starts_with(path));
+	if changed.iter().any(required) || (failed && !changed.is_empty()) {
+		regen.request(NoiseGenRequest::Regenerate);
+	} else if changed.iter().any(shown) {
+		regen.request(NoiseGenRequest::AlgorithmChanged);
+	}
 }
 
-#[derive(Resource)]
+#[derive(Resource, Clone)]
 struct NoiseOutput {
-	diameter: usize,
+	/// Samples per row.
+	width: usize,
+	/// Number of rows.
+	height: usize,
 	samples: Vec<f32>,
+	/// Tree the samples were generated from, kept for inspection.
+	noise: Option<Arc<lua::Noise>>,
+	/// Hash of the script the samples were generated from.
+	contentHash: Option<ContentHash>,
+	/// Range the samples are meant to span, see `lua::Noise::output_range`.
+	range: (f32, f32),
+	/// Seed offset the tree was reseeded with, see `UiState::seed`.
+	seed: i64,
+	/// Sliders the script declared, with the values it ran with.
+	params: Vec<lua::ScriptParam>,
+	/// Slider values passed to the script, see `UiState::params`.
+	paramInputs: BTreeMap<String, f64>,
+	precision: lua::Precision,
+	backend: gpu::Backend,
+	/// Sub-samples per axis averaged into every sample, see `subsample_offsets`.
+	supersampling: usize,
+	/// Region of the evaluation plane the samples cover.
+	domain: domain::Domain,
+	/// Coordinates the samples were evaluated at, see `UiState::coords`.
+	coords: Coords,
+	/// Why a generation meant for the GPU ran on the CPU instead.
+	gpuFallback: Option<String>,
+	/// Whether the tree was decorrelated, see `UiState::decorrelate`.
+	decorrelate: bool,
+	sliceZ: f64,
+	/// Animation time the samples were generated at, `None` if the tree doesn't depend on it.
+	time: Option<f64>,
+	/// Chunk grid the samples were stitched from, `None` for a plain generation.
+	chunks: Option<chunks::ChunkSettings>,
+	/// Color of every sample, channels in `[0, 1]`, if the script returned a `color`. Shown
+	/// instead of the colormap and the terrain palette.
+	colors: Option<Vec<[f32; 3]>>,
+	/// First runtime error of a Lua function the script returned. Its failed samples are NaN.
+	evalError: Option<String>,
+	/// Whether the samples were checked for non-finite ones, see `UiState::checkFinite`.
+	finiteChecked: bool,
+	/// First non-finite sample, if they were checked.
+	nonFinite: Option<NonFiniteSample>,
+	/// Time the whole generation took, `None` for previews and outputs not generated from a script.
+	elapsed: Option<Duration>,
+	/// Erosion pass the samples went through, see `erosion::ErosionPanel`.
+	eroded: Option<Box<erosion::Eroded>>,
+	/// Modules the script required, which regenerate it when they change.
+	modules: Vec<PathBuf>,
 }
 
 impl NoiseOutput {
+	/// A `diameter`² output of zeros.
 	pub fn new(diameter: usize) -> Self {
+		Self::sized(diameter, diameter)
+	}
+
+	/// A `width`×`height` output of zeros.
+	pub fn sized(width: usize, height: usize) -> Self {
 		Self {
-			diameter,
-			samples: vec![0.0; diameter.pow(2)],
+			width,
+			height,
+			samples: vec![0.0; width * height],
+			noise: None,
+			contentHash: None,
+			range: (-1.0, 1.0),
+			seed: 0,
+			params: vec![],
+			paramInputs: BTreeMap::new(),
+			precision: default(),
+			backend: default(),
+			supersampling: 1,
+			domain: default(),
+			coords: default(),
+			gpuFallback: None,
+			decorrelate: false,
+			sliceZ: 0.0,
+			time: None,
+			chunks: None,
+			colors: None,
+			evalError: None,
+			finiteChecked: false,
+			nonFinite: None,
+			elapsed: None,
+			eroded: None,
+			modules: vec![],
 		}
 	}
 
+	/// Maps a sample from the output range to `[-1, 1]`.
+	pub fn normalized(&self, v: f32) -> f32 {
+		let (min, max) = self.range;
+		(v - min) / (max - min) * 2.0 - 1.0
+	}
+
+	/// Width and height.
+	pub fn size(&self) -> (usize, usize) {
+		(self.width, self.height)
+	}
+
+	pub fn is_square(&self) -> bool {
+		self.width == self.height
+	}
+
+	/// Evaluation coordinates at `sample`, counted in samples along each axis and possibly
+	/// between them.
+	pub fn domain_position(&self, sample: Vec2) -> DVec2 {
+		self.domain.apply(self.coords.at(sample.as_dvec2(), self.width, self.height))
+	}
+
 	pub fn rows(&mut self) -> impl '_ + Iterator<Item = (usize, &mut [f32])> {
-		self.samples.chunks_exact_mut(self.diameter).enumerate()
+		self.samples.chunks_exact_mut(self.width.max(1)).enumerate()
 	}
 
-	pub fn fill_image(&self, image: &mut Image) {
-		let diameter = self.diameter as _;
-		if diameter != image.size().x {
-			image.resize(Extent3d {
-				width: diameter,
-				height: diameter,
-				depth_or_array_layers: 1,
-			});
+	/// The samples bilinearly interpolated onto a `width`×`height` grid over the same rectangle,
+	/// the corner samples staying where they are. Non-finite samples spread to the cells around
+	/// them.
+	pub fn resampled(&self, width: usize, height: usize) -> Vec<f32> {
+		let (lastX, lastY) = (self.width.saturating_sub(1), self.height.saturating_sub(1));
+		// sample to interpolate from and the weight of the next one, the last cell taking the end
+		let split = |i: usize, last: usize, to: usize| {
+			let cells = to.saturating_sub(1).max(1);
+			// exact at the ends, where the product is a multiple of `cells`
+			let pos = (i * last) as f64 / cells as f64;
+			let first = (pos.floor() as usize).min(last.saturating_sub(1));
+			(first, (pos - first as f64) as f32)
+		};
+		let at = |x: usize, y: usize| self.samples[y * self.width + x];
+		let mut samples = Vec::with_capacity(width * height);
+		for y in 0 .. height {
+			let (y0, ty) = split(y, lastY, height);
+			let y1 = (y0 + 1).min(lastY);
+			for x in 0 .. width {
+				let (x0, tx) = split(x, lastX, width);
+				let x1 = (x0 + 1).min(lastX);
+				let top = at(x0, y0) + (at(x1, y0) - at(x0, y0)) * tx;
+				let bottom = at(x0, y1) + (at(x1, y1) - at(x0, y1)) * tx;
+				samples.push(top + (bottom - top) * ty);
+			}
 		}
+		samples
+	}
+
+	pub fn fill_image(&self, image: &mut Image, colormap: &colormap::Colormap) {
+		self.fill_image_in(image, self.range, colormap);
+	}
+
+	/// `fill_image`, mapping `range` instead of the declared one to the ends of the colormap.
+	pub fn fill_image_in(
+		&self,
+		image: &mut Image,
+		(min, max): (f32, f32),
+		colormap: &colormap::Colormap,
+	) {
+		fit_image(image, self.size());
+		// `resize` keeps the data tightly packed, a row being exactly `width` pixels
 		let data: &mut [[f32; 4]] = bytemuck::cast_slice_mut(&mut image.data);
+		if let Some(colors) = &self.colors {
+			for (pixel, &[r, g, b]) in data.iter_mut().zip(colors) {
+				*pixel = [r, g, b, 1.0];
+			}
+			return;
+		}
+		let color = colormap.mapper();
 		data.iter_mut().enumerate().for_each(|(i, pixel)| {
 			let v = self.samples[i];
-			let v = (v + 1.0) / 2.0;
-			(&mut pixel[.. 3]).fill(v);
-			pixel[3] = 1.0;
+			// garbage pixels otherwise, unless the colormap is to point them out
+			let v = if v.is_finite() || colormap.highlightOutOfRange { v } else { 0.0 };
+			*pixel = color((v - min) / (max - min));
 		});
 	}
 
-	pub fn update_mesh(&self, mesh: &mut Mesh, height: f32) {
-		let mut positions = vec![];
-		let mut normals = vec![];
-		let mut uvs = vec![];
-
-		let get_height = |x: usize, y: usize| self.samples[y * self.diameter + x] * height;
-
-		for y in 0 .. self.diameter - 1 {
-			for x in 0 .. self.diameter - 1 {
-				for (dx, dy) in [
-					(0, 0),
-					(0, 1),
-					(1, 0),
-					(1, 0),
-					(0, 1),
-					(1, 1),
-				] {
-					let x = x + dx;
-					let y = y + dy;
-					let height = get_height(x, y);
-					let position = vec3(x as f32, height, y as f32);
-
-					let north = {
-						let y = if y == 0 { y } else { y - 1 };
-						let height = get_height(x, y);
-						vec3(x as f32, height, y as f32)
-					};
-					let north = position - north;
-					let east = {
-						let x = if x == self.diameter - 1 { x } else { x + 1 };
-						let height = get_height(x, y);
-						vec3(x as f32, height, y as f32)
-					};
-					let east = position - east;
-					let south = {
-						let y = if y == self.diameter - 1 { y } else { y + 1 };
-						let height = get_height(x, y);
-						vec3(x as f32, height, y as f32)
-					};
-					let south = position - south;
-					let west = {
-						let x = if x == 0 { x } else { x - 1 };
-						let height = get_height(x, y);
-						vec3(x as f32, height, y as f32)
-					};
-					let west = position - west;
-
-					let northwest = north.cross(west);
-					let northeast = east.cross(north);
-					let southeast = south.cross(east);
-					let southwest = west.cross(south);
-					let normal =
-						((northwest + northeast + southeast + southwest) / 4.0).normalize();
-
-					positions.push(position);
-					normals.push(normal);
-					uvs.push(vec2(dx as _, dy as _));
-				}
+	/// `fill_image` showing `abs(self - other) * amplification` instead, `other` being samples of
+	/// the same size. A difference as large as the declared range is white at an
+	/// amplification of 1; samples only one of the two has as NaN are red.
+	pub fn fill_diff_image(&self, image: &mut Image, other: &[f32], amplification: f32) {
+		fit_image(image, self.size());
+		let (min, max) = self.range;
+		let data: &mut [[f32; 4]] = bytemuck::cast_slice_mut(&mut image.data);
+		for ((pixel, &a), &b) in data.iter_mut().zip(&self.samples).zip(other) {
+			*pixel = if a.is_nan() != b.is_nan() {
+				[1.0, 0.0, 0.0, 1.0]
+			} else {
+				let v = (a - b).abs() / (max - min) * amplification;
+				// both being NaN counts as no difference
+				let v = if v.is_nan() { 0.0 } else { v.min(1.0) };
+				[v, v, v, 1.0]
+			};
+		}
+	}
+
+	/// Runs `erosion::erode` over the samples.
+	pub fn erode(&mut self, params: erosion::ErosionParams) {
+		erosion::erode(&mut self.samples, self.size(), self.range, &params);
+	}
+
+	pub fn fill_heights(&self, image: &mut Image) {
+		fit_image(image, self.size());
+		let data: &mut [f32] = bytemuck::cast_slice_mut(&mut image.data);
+		for (height, &v) in data.iter_mut().zip(&self.samples) {
+			*height = self.normalized(v);
+		}
+	}
+
+	/// Gradient at every sample, in output units per unit of the domain. Central differences,
+	/// one-sided at the edges, with y growing with the row.
+	pub fn compute_gradients(&self) -> Vec<Vec2> {
+		let steps = (1.0 / self.coords.step(self.width, self.height)) as f32;
+		gradients::sample_gradients(&self.samples, self.size())
+			.into_iter()
+			.map(|gradient| gradient * steps)
+			.collect()
+	}
+
+	/// Position of sample `(x, y)` on the terrain surface laid out by `scale`, and the normal
+	/// smoothed over the four cells around it.
+	pub fn surface_vertex(&self, x: usize, y: usize, scale: TerrainScale) -> (Vec3, Vec3) {
+		let (width, height) = self.size();
+		let get_position = |x: usize, y: usize| {
+			let v = self.normalized(self.samples[y * width + x]) * scale.height;
+			// non-finite positions would fail tangent generation
+			let v = if v.is_finite() { v } else { 0.0 };
+			let ground = scale.to_world((width, height), vec2(x as f32, y as f32));
+			vec3(ground.x, v, ground.y)
+		};
+		let position = get_position(x, y);
+
+		let north = position - get_position(x, if y == 0 { y } else { y - 1 });
+		let east = position - get_position(if x == width - 1 { x } else { x + 1 }, y);
+		let south = position - get_position(x, if y == height - 1 { y } else { y + 1 });
+		let west = position - get_position(if x == 0 { x } else { x - 1 }, y);
+
+		let northwest = north.cross(west);
+		let northeast = east.cross(north);
+		let southeast = south.cross(east);
+		let southwest = west.cross(south);
+		let normal = ((northwest + northeast + southeast + southwest) / 4.0).normalize();
+		(position, normal)
+	}
+
+	/// Rebuilds `mesh` as the terrain surface: one vertex per sample, laid out by `scale`, two
+	/// triangles per cell between them, and UVs spanning the whole grid. The 3D view splits it
+	/// into `lod::TerrainChunks` instead.
+	pub fn update_mesh(&self, mesh: &mut Mesh, scale: TerrainScale) {
+		let (width, height) = self.size();
+		let mut positions = Vec::with_capacity(width * height);
+		let mut normals = Vec::with_capacity(width * height);
+		let mut uvs = Vec::with_capacity(width * height);
+		let uvScale = vec2(
+			1.0 / width.saturating_sub(1).max(1) as f32,
+			1.0 / height.saturating_sub(1).max(1) as f32,
+		);
+
+		for y in 0 .. height {
+			for x in 0 .. width {
+				let (position, normal) = self.surface_vertex(x, y, scale);
+				positions.push(position);
+				normals.push(normal);
+				uvs.push(vec2(x as f32, y as f32) * uvScale);
+			}
+		}
+
+		let cells = (width.saturating_sub(1), height.saturating_sub(1));
+		let mut indices = Vec::with_capacity(cells.0 * cells.1 * 6);
+		for y in 0 .. cells.1 {
+			for x in 0 .. cells.0 {
+				let index = |dx: usize, dy: usize| ((y + dy) * width + x + dx) as u32;
+				// counter-clockwise seen from above, so faces point up
+				indices.extend([
+					index(0, 0),
+					index(0, 1),
+					index(1, 0),
+					index(1, 0),
+					index(0, 1),
+					index(1, 1),
+				]);
 			}
 		}
+
 		mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
 		mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
 		mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
+		mesh.insert_indices(Indices::U32(indices));
 		mesh.generate_tangents().unwrap();
 	}
 }
 
+/// Resizes `image` to `width`×`height` pixels unless it already is.
+fn fit_image(image: &mut Image, (width, height): (usize, usize)) {
+	let size = UVec2::new(width as u32, height as u32);
+	if image.size() != size {
+		image.resize(Extent3d {
+			width: size.x,
+			height: size.y,
+			depth_or_array_layers: 1,
+		});
+	}
+}
+
+/// Memory cost of the terrain meshes for a given output size.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct MeshEstimate {
+	vertices: usize,
+	indices: usize,
+	bytes: usize,
+}
+
+impl MeshEstimate {
+	/// Above this the user gets a warning.
+	pub const softLimit: usize = 256 << 20;
+	/// Above this the mesh isn't built without explicit confirmation.
+	pub const hardLimit: usize = 1 << 30;
+
+	/// Position, normal, UV and tangent.
+	const bytesPerVertex: usize = 12 + 12 + 8 + 16;
+
+	/// The indexed meshes `lod::TerrainChunks` builds for a `(width, height)` output.
+	pub fn new(size: (usize, usize)) -> Self {
+		let (vertices, indices) = lod::mesh_size(size);
+		Self::with(vertices, indices)
+	}
+
+	/// The same meshes flat shaded, split into separate triangles without indices.
+	pub fn flat(self) -> Self {
+		Self::with(self.indices, 0)
+	}
+
+	fn with(vertices: usize, indices: usize) -> Self {
+		Self {
+			vertices,
+			indices,
+			bytes: vertices * Self::bytesPerVertex + indices * 4,
+		}
+	}
+}
+
+impl std::fmt::Display for MeshEstimate {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(
+			f,
+			"{:.1}M vertices, {:.0} MiB",
+			self.vertices as f64 / 1e6,
+			self.bytes as f64 / (1 << 20) as f64
+		)
+	}
+}
+
+/// First non-finite sample of an output, and where in the tree it comes from.
+#[derive(Clone, Debug, PartialEq)]
+struct NonFiniteSample {
+	value: f32,
+	/// Evaluation coordinates of the sample, or of its first non-finite sub-sample.
+	pos: DVec2,
+	/// `None` if evaluating there again came out finite, as the GPU evaluates differently.
+	source: Option<lua::NonFiniteSource>,
+}
+
+impl NonFiniteSample {
+	/// Finds the first non-finite sample of `output`, generated from `ast` over `spec`, and traces
+	/// it back through the tree.
+	fn find(
+		output: &NoiseOutput,
+		ast: &lua::Noise,
+		spec: &GridSpec,
+		ctx: &lua::EvalContext,
+	) -> Option<Self> {
+		let i = output.samples.iter().position(|v| !v.is_finite())?;
+		let center = spec.position(i % output.width, i / output.width);
+		// averages are non-finite if any of their sub-samples is
+		let traced = spec.offsets().into_iter().find_map(|offset| {
+			let pos = center + offset;
+			let source = ast.trace_non_finite(pos.extend(spec.z), spec.precision, ctx)?;
+			Some((pos, source))
+		});
+		Some(Self {
+			value: output.samples[i],
+			pos: traced.map_or(center, |(pos, _)| pos),
+			source: traced.map(|(_, source)| source),
+		})
+	}
+}
+
+impl std::fmt::Display for NonFiniteSample {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		let (value, pos) = (self.value, self.pos);
+		match self.source {
+			Some(source) if source.exact => {
+				write!(f, "{value} produced by {} at node #{}", source.kind, source.node)?;
+			},
+			Some(source) => {
+				write!(f, "{value} produced inside {} at node #{}", source.kind, source.node)?;
+			},
+			None => write!(f, "{value} sample")?,
+		}
+		write!(f, " at ({:.2}, {:.2})", pos.x, pos.y)
+	}
+}
+
+/// Consumer a generation's output is routed to once it completes. Every target has at most one
+/// task in flight; requesting a new generation for it cancels the old one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum GenTarget {
+	/// A layer's output, by index into `UiState::layers`. The active layer's is shown in both
+	/// viewports, the others only by the split view.
+	Layer(usize),
+}
+
 #[derive(Component)]
-struct NoiseGenTask(Task<NoiseOutput>);
+struct NoiseGenTask {
+	/// Increases with every request, see `NoiseGenCounter`.
+	id: u64,
+	target: GenTarget,
+	/// Script the task runs, for the session log.
+	script: InternedPath,
+	/// Set when the task is superseded. Dropping the task only cancels it between polls, while
+	/// generation runs in a single one, so the sample loop checks this instead.
+	cancel: Arc<AtomicBool>,
+	/// Rows or chunks of the full resolution done, shown in the toolbar.
+	progress: Arc<workers::Progress>,
+	/// Coarse passes published while the full resolution is still generating, see
+	/// `preview_diameters`.
+	previews: Receiver<NoiseOutput>,
+	/// Fails with the script's error, traceback included, or with what it panicked with.
+	task: Task<Result<NoiseOutput, String>>,
+}
+
+/// IDs of generation requests. A result is only applied if it's from the latest request for its
+/// target, so a superseded task finishing late can't overwrite a newer output.
+#[derive(Resource, Default)]
+struct NoiseGenCounter {
+	next: u64,
+	latest: HashMap<GenTarget, u64>,
+}
 
 fn generate_noise(
 	mut cmd: Commands,
 	existingRequests: Query<(Entity, &NoiseGenTask)>,
 	uiState: Res<UiState>,
+	lastNoiseOutput: Option<Res<NoiseOutput>>,
+	mut session: ResMut<session::SessionRecorder>,
 	mut noiseGenRequests: EventReader<NoiseGenRequest>,
+	mut counter: ResMut<NoiseGenCounter>,
+	recorder: Res<sequence::SweepRecorder>,
+	log: Res<log_panel::SessionLog>,
+	mut held: Local<Vec<NoiseGenRequest>>,
 ) {
-	let mut requested = false;
-	for &ev in noiseGenRequests.read() {
-		if requested {
-			panic!("multiple noise generation requests in one frame");
-		}
-		if matches!(ev, NoiseGenRequest::AlgorithmChanged) {
-			requested = true;
-		}
-	}
-	if !requested {
+	// a recorded sweep has the pool to itself, requests made meanwhile are carried out after it
+	if recorder.running() {
+		held.extend(noiseGenRequests.read().copied());
 		return;
 	}
-	info!("noise gen requested");
+	let CoalescedRequests {
+		allLayers,
+		layers: mut requested,
+		force,
+		..
+	} = coalesce_requests(held.drain(..).chain(noiseGenRequests.read().copied()));
+	if allLayers {
+		// hidden layers are brought up to date once shown again
+		requested.extend(
+			(0 .. uiState.layers.len())
+				.filter(|&i| i == uiState.activeLayer || uiState.layers[i].visible)
+				.filter(|&i| uiState.layers[i].script.is_some()),
+		);
+	}
+	// compiled trees are reused by script contents, which haven't changed
+	if force {
+		lua::invalidate_compiled();
+	}
 
-	for (ent, task) in existingRequests.iter() {
-		// FIXME: despawn should also cancel but would be nice to be explicit
-		// task.0.cancel();
-		cmd.entity(ent).despawn();
+	for layer in requested {
+		let lastOutput = if layer == uiState.activeLayer {
+			lastNoiseOutput.as_deref()
+		} else {
+			uiState.layers[layer].output.as_ref()
+		};
+		generate_layer(
+			&mut cmd,
+			&existingRequests,
+			&uiState,
+			layer,
+			lastOutput,
+			force,
+			&mut session,
+			&mut counter,
+			&log,
+		);
 	}
+}
 
-	let diameter = uiState.diameter;
-	let code = {
-		let selected = uiState.selected.as_ref().unwrap();
-		uiState.scripts.get(selected).unwrap().clone()
-	};
+/// Severity of the session log's entries about generations, animation frames being too many to
+/// show by default.
+fn generation_severity(uiState: &UiState) -> log_panel::Severity {
+	if uiState.animation.playing { log_panel::Severity::Debug } else { log_panel::Severity::Info }
+}
 
+/// Starts generating `layer`'s script with the current settings, superseding the layer's pending
+/// generation, unless `lastOutput` is already up to date and the generation isn't `forced`.
+fn generate_layer(
+	cmd: &mut Commands,
+	existingRequests: &Query<(Entity, &NoiseGenTask)>,
+	uiState: &UiState,
+	layer: usize,
+	lastOutput: Option<&NoiseOutput>,
+	forced: bool,
+	session: &mut session::SessionRecorder,
+	counter: &mut NoiseGenCounter,
+	log: &log_panel::SessionLog,
+) {
+	let target = GenTarget::Layer(layer);
+	let (width, height) = (uiState.outputWidth, uiState.outputHeight);
+	let chunks = uiState.chunks.enabled.then_some(uiState.chunks);
+	let Some(selected) = uiState.layers[layer].script.as_ref() else {
+		return;
+	};
+	// deleted or moved away, the script selector shows it's missing
+	let Some(code) = uiState.scripts.get(selected).cloned() else {
+		info!("noise gen of layer {layer} skipped, {} is missing", selected.display);
+		return;
+	};
+	let params = uiState.script_params(selected);
+	let contentHash = content_hash(&code);
+	let (seed, precision, decorrelate) = (uiState.seed, uiState.precision, uiState.decorrelate);
+	let (sliceZ, backend) = (uiState.sliceZ, uiState.backend);
+	let checkFinite = uiState.checkFinite;
+	// chunks sample their own grids, once per sample and over the unit square
+	let grid = match chunks {
+		Some(_) => SampleGrid::new(width),
+		None => SampleGrid {
+			width,
+			height,
+			coords: uiState.coords,
+			supersampling: uiState.supersampling,
+			domain: uiState.domain.domain(),
+		},
+	};
+	let ctx = lua::EvalContext {
+		time: uiState.animation.time,
+	};
+	let lastDomain = lastOutput.map_or(default(), |output| output.domain);
+	// previews would flicker between the frames of an animation, and be laid out by the domain of
+	// the output they replace. Pixel coordinates would make them show a part of it
+	let withPreviews = !uiState.animation.playing &&
+		grid.domain == lastDomain &&
+		grid.coords != Coords::Pixels;
+	let upToDate = !forced && lastOutput.is_some_and(|output| {
+		output.contentHash == Some(contentHash) &&
+			output.seed == seed &&
+			output.paramInputs == params &&
+			output.precision == precision &&
+			output.backend == backend &&
+			output.supersampling == grid.supersampling &&
+			output.domain == grid.domain &&
+			output.coords == grid.coords &&
+			output.decorrelate == decorrelate &&
+			(output.finiteChecked || !checkFinite) &&
+			output.sliceZ == sliceZ &&
+			output.time.map_or(true, |time| time == ctx.time) &&
+			output.chunks == chunks &&
+			(chunks.is_some() || output.size() == (width, height))
+	});
+	let pending = existingRequests
+		.iter()
+		.filter(|(_, task)| task.target == target);
+	if upToDate && pending.clone().next().is_none() {
+		info!("noise gen of layer {layer} skipped, output is up to date");
+		return;
+	}
+	let id = counter.next;
+	counter.next += 1;
+	counter.latest.insert(target, id);
+	let script = &selected.display;
+	let message = format!("#{id} started on layer {layer}: {script} at {width}×{height}");
+	let severity = generation_severity(uiState);
+	log.record(severity, log_panel::LogKind::Generation, Some(selected), message);
+
+	for (ent, task) in pending {
+		info!("noise gen #{} superseded", task.id);
+		task.cancel.store(true, Ordering::Relaxed);
+		cmd.entity(ent).despawn();
+	}
+	// a session of animation frames would be a line per frame
+	if layer == uiState.activeLayer && !uiState.animation.playing {
+		session.record(uiState, &code);
+	}
+	let cancel = Arc::new(AtomicBool::new(false));
+	let taskCancel = cancel.clone();
+	let progress = Arc::new(workers::Progress::default());
+	let taskProgress = progress.clone();
+	let (previewSender, previews) = crossbeam_channel::unbounded();
 	let threadPool = AsyncComputeTaskPool::get();
+	let inputs = lua::ScriptInputs {
+		seed,
+		params,
+		coords: grid.coords,
+	};
+	// a panic becomes the task's error, the task would never finish otherwise
 	let task = threadPool.spawn(async move {
-		let mut img = NoiseOutput::new(diameter);
+		workers::catch_panic(move || {
+			let start = std::time::Instant::now();
+			let compiled = compile_script(&code, &inputs, decorrelate);
+			info!("script compiled in {:?}", start.elapsed());
+			let (ast, declared, color, modules) = match compiled {
+				Ok(lua::Compiled {
+					noise: ast,
+					params,
+					color,
+					modules,
+					..
+				}) => (ast, params, color, modules),
+				// logged once the result is picked up, see `update_noise_outputs`
+				Err(err) => {
+					return Err(format!("{err:#}"));
+				},
+			};
+
+			// left over from earlier generations sharing the cached tree
+			ast.take_lua_error();
+			let gpuRes = (backend == gpu::Backend::Gpu).then(|| match chunks {
+				Some(_) => Err("chunked generation runs on the CPU".to_string()),
+				None if color.is_some() => Err("colors are generated on the CPU".to_string()),
+				None => gpu::generate_samples(&ast, &grid, sliceZ, &ctx),
+			});
+			if chunks.is_some() && color.is_some() {
+				info!("chunked generation leaves out the script's colors");
+			}
+			let color = color.as_deref();
+			let (gpuOutput, gpuFallback) = match gpuRes {
+				Some(Ok(output)) => (Some(output), None),
+				Some(Err(reason)) => (None, Some(reason)),
+				None => (None, None),
+			};
+			let res = if let Some(mut output) = gpuOutput {
+				// fast enough to skip the preview passes
+				output.precision = precision;
+				Some(output)
+			} else if let Some(chunks) = chunks {
+				// chunks are small, and previews wouldn't be chunked
+				chunks::ChunkedNoiseOutput::generate_cancellable(
+					&ast,
+					chunks,
+					precision,
+					sliceZ,
+					&ctx,
+					&taskCancel,
+					&taskProgress,
+				)
+				.map(|chunked| chunked.stitch())
+			} else {
+				for passDiameter in preview_diameters(width.max(height)).filter(|_| withPreviews) {
+					let passGrid = grid.scaled(passDiameter);
+					let res = generate_colored_cancellable(
+						&ast,
+						color,
+						&passGrid,
+						precision,
+						sliceZ,
+						&ctx,
+						&taskCancel,
+						&default(),
+					);
+					let Some(preview) = res else {
+						return Err("cancelled".into());
+					};
+					// fails once the task is superseded and despawned, the next pass notices the
+					// flag
+					let _ = previewSender.send(preview);
+				}
+				generate_colored_cancellable(
+					&ast,
+					color,
+					&grid,
+					precision,
+					sliceZ,
+					&ctx,
+					&taskCancel,
+					&taskProgress,
+				)
+			};
+			let Some(mut output) = res else {
+				return Err("cancelled".into());
+			};
+			if checkFinite {
+				// chunked outputs are stitched to a grid of their own diameter
+				let spec = GridSpec {
+					width: output.width,
+					height: output.height,
+					..grid.spec(sliceZ, precision)
+				};
+				output.nonFinite = NonFiniteSample::find(&output, &ast, &spec, &ctx);
+				output.finiteChecked = true;
+				if let Some(nonFinite) = &output.nonFinite {
+					warn!("{nonFinite}");
+				}
+			}
+			output.evalError = ast.take_lua_error();
+			output.backend = backend;
+			if let Some(reason) = &gpuFallback {
+				warn!("generated on the CPU: {reason}");
+			}
+			output.gpuFallback = gpuFallback;
+			output.time = ast.uses_time().then_some(ctx.time);
+			output.noise = Some(ast);
+			output.contentHash = Some(contentHash);
+			output.seed = seed;
+			output.params = declared;
+			output.modules = modules;
+			output.paramInputs = inputs.params;
+			output.decorrelate = decorrelate;
+			output.sliceZ = sliceZ;
+			output.elapsed = Some(start.elapsed());
+			Ok(output)
+		})
+	});
+	cmd.spawn(NoiseGenTask {
+		id,
+		target,
+		script: selected.clone(),
+		cancel,
+		progress,
+		previews,
+		task,
+	});
+}
 
-		let ast = match lua::construct_noisegen(&code) {
-			Ok(ast) => ast,
-			Err(err) => {
-				let err: mlua::Error = err.downcast().unwrap();
-				error!("Lua error: {err}");
-				return img;
-			},
-		};
+/// Runs `code` with `inputs`, then offsets the tree's seeds by the `Seed` input and decorrelates it
+/// like every generation of a script does, in the app or baked from the command line.
+fn compile_script(
+	code: &str,
+	inputs: &lua::ScriptInputs,
+	decorrelate: bool,
+) -> AResult<lua::Compiled> {
+	let compiled = lua::construct_noisegen_with(code, inputs)?;
+	let skipped = compiled.noise.skipped_octaves();
+	if skipped > 0 {
+		info!("{skipped} octaves skipped, their amplitude is too small to contribute");
+	}
+	if !compiled.layers.is_empty() {
+		let layers = compiled.layers.join(", ");
+		info!("script also returned the layers {layers}, only `height` is shown");
+	}
+	let seed = inputs.seed;
+	if seed == 0 && !decorrelate {
+		return Ok(compiled);
+	}
+	// colors are reseeded alike, so they keep following the sources they share with the height
+	let prepare = |noise: &lua::Noise| {
+		let mut noise = noise.clone();
+		noise.reseed(seed);
+		if decorrelate {
+			noise.decorrelate(seed);
+		}
+		noise
+	};
+	Ok(lua::Compiled {
+		noise: Arc::new(prepare(&compiled.noise)),
+		params: compiled.params,
+		layers: compiled.layers,
+		color: compiled.color.map(|color| Arc::new(color.each_ref().map(prepare))),
+		modules: compiled.modules,
+		files: compiled.files,
+	})
+}
 
-		let diameter = img.diameter;
-		threadPool.scope(|scope| {
-			img.rows().for_each(|(y, heights)| {
-				let ast = ast.clone();
-				scope.spawn(async move {
-					for (x, height) in heights.into_iter().enumerate() {
-						let y = y as f64 / (diameter - 1) as f64;
-						let x = x as f64 / (diameter - 1) as f64;
-						let pos = dvec2(x, y);
-						*height = ast.eval(pos);
-					}
-				});
-			});
-		});
-		img
+/// Smallest pass published ahead of a generation's full resolution.
+const previewDiameter: usize = 64;
+
+/// Diameters of the coarse passes generated before the full `diameter`, each with a sixteenth of
+/// the samples of the next, so together they add only a few percent to a generation. Small
+/// generations finish quickly enough to skip them.
+fn preview_diameters(diameter: usize) -> impl Iterator<Item = usize> {
+	std::iter::successors(Some(previewDiameter), |d| Some(d * 4))
+		.take_while(move |d| d * 4 <= diameter)
+}
+
+/// Sub-samples per axis the toolbar offers, shown as their squares.
+const supersamplingOptions: [usize; 4] = [1, 2, 3, 4];
+
+/// Samples a generation evaluates: `width`×`height` of them laid out by `coords` and spread over
+/// `domain`, each averaging `supersampling`² sub-samples.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct SampleGrid {
+	width: usize,
+	height: usize,
+	coords: Coords,
+	supersampling: usize,
+	domain: domain::Domain,
+}
+
+impl SampleGrid {
+	/// One sample per grid point over the unit square.
+	fn new(diameter: usize) -> Self {
+		Self {
+			width: diameter,
+			height: diameter,
+			coords: default(),
+			supersampling: 1,
+			domain: default(),
+		}
+	}
+
+	/// The same grid with `diameter` samples along its longer side, keeping the aspect.
+	fn scaled(&self, diameter: usize) -> Self {
+		let longer = self.width.max(self.height).max(1);
+		Self {
+			width: (self.width * diameter / longer).max(2),
+			height: (self.height * diameter / longer).max(2),
+			..*self
+		}
+	}
+
+	/// Evaluation coordinates of sample `(x, y)`.
+	fn position(&self, x: usize, y: usize) -> DVec2 {
+		self.spec(0.0, default()).position(x, y)
+	}
+
+	/// `subsample_offsets`, scaled to the domain.
+	fn offsets(&self) -> Vec<DVec2> {
+		self.spec(0.0, default()).offsets()
+	}
+
+	/// The same grid for `noisebench_core`'s sampler, at depth `z` of the volume.
+	fn spec(&self, z: f64, precision: lua::Precision) -> GridSpec {
+		GridSpec {
+			width: self.width,
+			height: self.height,
+			coords: self.coords,
+			origin: self.domain.origin,
+			size: self.domain.size,
+			supersampling: self.supersampling,
+			z,
+			precision,
+		}
+	}
+}
+
+/// Bands each generation is split into per worker. Several generations running at once then
+/// interleave on the pool instead of one queueing all its rows ahead of the others.
+const bandsPerWorker: usize = 4;
+
+/// Evaluates `ast` over the unit square at `diameter`² samples, bands of rows at a time on
+/// `workers::limit()` workers of the async compute pool.
+fn generate_samples(ast: &lua::Noise, diameter: usize, precision: lua::Precision) -> NoiseOutput {
+	let ctx = default();
+	let cancel = AtomicBool::new(false);
+	let grid = SampleGrid::new(diameter);
+	generate_samples_cancellable(ast, &grid, precision, 0.0, &ctx, &cancel)
+		.expect("generation can't be cancelled without a flag to set")
+}
+
+/// `generate_samples` over `grid` at depth `z` of the volume, giving up once `cancel` is set,
+/// which is checked before every row.
+fn generate_samples_cancellable(
+	ast: &lua::Noise,
+	grid: &SampleGrid,
+	precision: lua::Precision,
+	z: f64,
+	ctx: &lua::EvalContext,
+	cancel: &AtomicBool,
+) -> Option<NoiseOutput> {
+	generate_colored_cancellable(ast, None, grid, precision, z, ctx, cancel, &default())
+}
+
+/// `generate_samples_cancellable`, also filling `NoiseOutput::colors` from `color`'s channels
+/// row by row along with the heights, and counting the rows done in `progress`.
+fn generate_colored_cancellable(
+	ast: &lua::Noise,
+	color: Option<&[lua::Noise; 3]>,
+	grid: &SampleGrid,
+	precision: lua::Precision,
+	z: f64,
+	ctx: &lua::EvalContext,
+	cancel: &AtomicBool,
+	progress: &workers::Progress,
+) -> Option<NoiseOutput> {
+	let (width, height) = (grid.width, grid.height);
+	let mut img = NoiseOutput::sized(width, height);
+	img.range = ast.output_range();
+	img.precision = precision;
+	img.supersampling = grid.supersampling;
+	img.domain = grid.domain;
+	img.coords = grid.coords;
+	let spec = &grid.spec(z, precision);
+	let offsets = &spec.offsets();
+	let bandRows = height.div_ceil(workers::limit() * bandsPerWorker).max(1);
+	progress.start(height);
+	let mut colors = color.map(|_| vec![[0.0; 3]; width * height]);
+	let colorBands = colors
+		.iter_mut()
+		.flat_map(|colors| colors.chunks_mut(bandRows * width).map(Some))
+		.chain(std::iter::repeat_with(|| None));
+	let bands = img.samples.chunks_mut(bandRows * width).zip(colorBands).enumerate();
+	workers::for_each_limited(bands, |(band, (heights, mut colors))| {
+		for (i, row) in heights.chunks_mut(width).enumerate() {
+			if cancel.load(Ordering::Relaxed) {
+				return;
+			}
+			let y = band * bandRows + i;
+			spec.sample_row(ast, ctx, offsets, y, row);
+			if let (Some(color), Some(colors)) = (color, colors.as_deref_mut()) {
+				let row = &mut colors[i * width .. (i + 1) * width];
+				spec.sample_color_row(color, ctx, offsets, y, row);
+			}
+			progress.advance();
+		}
 	});
-	cmd.spawn(NoiseGenTask(task));
+	img.colors = colors;
+	(!cancel.load(Ordering::Relaxed)).then_some(img)
 }
 
 fn update_noise_outputs(
 	mut cmd: Commands,
-	mut task: Query<(Entity, &mut NoiseGenTask)>,
+	mut tasks: Query<(Entity, &mut NoiseGenTask)>,
 	mut images: ResMut<Assets<Image>>,
 	mut meshes: ResMut<Assets<Mesh>>,
-	uiState: Res<UiState>,
+	mut uiState: ResMut<UiState>,
 	heightmaps: Res<Heightmaps>,
+	mut terrain: Option<ResMut<lod::TerrainChunks>>,
 	lastNoiseOutput: Option<Res<NoiseOutput>>,
+	textureLimits: Res<textures::TextureLimits>,
+	counter: Res<NoiseGenCounter>,
+	mut scriptError: ResMut<ScriptError>,
 	mut noiseGenRequests: EventReader<NoiseGenRequest>,
+	colormap: Res<colormap::ColormapSettings>,
+	log: Res<log_panel::SessionLog>,
 ) {
-	let Ok((taskEnt, mut task)) = task.get_single_mut() else {
-		let mut requested = false;
-		for &ev in noiseGenRequests.read() {
-			if requested {
-				panic!("multiple noise generation requests in one frame");
+	use log_panel::{LogKind, Severity};
+	let colormap = &colormap.colormap;
+	let activeTarget = GenTarget::Layer(uiState.activeLayer);
+	let activePending = tasks.iter().any(|(_, task)| task.target == activeTarget);
+	if !activePending {
+		let requested = coalesce_requests(noiseGenRequests.read().copied()).rebuildMesh;
+		// nothing to rebuild if no generation succeeded yet
+		if let Some(lastNoiseOutput) = lastNoiseOutput.filter(|_| requested) {
+			if let Some(terrain) = &mut terrain {
+				if mesh_allowed(lastNoiseOutput.size(), &uiState) {
+					terrain.update(&lastNoiseOutput, uiState.terrain_scale(), &mut meshes);
+				}
 			}
-			if matches!(ev, NoiseGenRequest::ModelParamsChanged) {
-				requested = true;
+		}
+	}
+
+	for (taskEnt, mut task) in tasks.iter_mut() {
+		let latest = counter.latest.get(&task.target) == Some(&task.id);
+		let GenTarget::Layer(layer) = task.target;
+		// routed by the layer's current role, it may have been switched to since the request
+		let active = layer == uiState.activeLayer;
+		// only the newest pass is worth uploading, older ones queued since last frame are dropped
+		if let Some(preview) = task.previews.try_iter().last().filter(|_| latest) {
+			if active {
+				scriptError.0 = None;
+				show_output(
+					&preview,
+					&uiState,
+					&heightmaps,
+					terrain.as_deref_mut(),
+					&mut images,
+					&mut meshes,
+					&textureLimits,
+					colormap,
+				);
+			} else {
+				let image = &uiState.layers[layer].image;
+				upload_output(&preview, image, None, &mut images, &textureLimits, colormap);
 			}
 		}
-		if !requested {
-			return;
+
+		// never waited on, a finished task is ready when polled
+		if !task.task.is_finished() {
+			continue;
+		}
+		let res = block_on(&mut task.task);
+		cmd.entity(taskEnt).despawn();
+		if !latest {
+			info!("noise gen #{} finished after being superseded, discarding it", task.id);
+			continue;
+		}
+		let script = Some(&task.script);
+		let noiseOutput = match res {
+			Ok(noiseOutput) => noiseOutput,
+			Err(err) => {
+				let message = format!("#{} {} failed: {err}", task.id, task.script.display);
+				log.record(Severity::Error, LogKind::LuaError, script, message);
+				if active {
+					scriptError.0 = Some(err);
+				} else {
+					uiState.layers[layer].error = Some(err);
+				}
+				continue;
+			},
+		};
+		let elapsed = noiseOutput.elapsed.unwrap_or_default();
+		let message = format!(
+			"#{} {} done in {elapsed:.2?}: {}",
+			task.id,
+			task.script.display,
+			log_panel::output_summary(&noiseOutput)
+		);
+		log.record(generation_severity(&uiState), LogKind::Generation, script, message);
+		if let Some(err) = &noiseOutput.evalError {
+			let message =
+				format!("#{} {} failed while evaluating: {err}", task.id, task.script.display);
+			log.record(Severity::Error, LogKind::LuaError, script, message);
 		}
-		let mesh = meshes.get_mut(&heightmaps.mesh).unwrap();
-		lastNoiseOutput.unwrap().update_mesh(mesh, uiState.height);
-		return;
-	};
-	let Some(noiseOutput) = block_on(future::poll_once(&mut task.0)) else {
-		return;
-	};
-	cmd.entity(taskEnt).despawn();
-	info!("noise gen done");
 
+		if active {
+			scriptError.0 = noiseOutput.evalError.clone();
+			show_output(
+				&noiseOutput,
+				&uiState,
+				&heightmaps,
+				terrain.as_deref_mut(),
+				&mut images,
+				&mut meshes,
+				&textureLimits,
+				colormap,
+			);
+			cmd.insert_resource(noiseOutput);
+		} else {
+			let slot = &mut uiState.layers[layer];
+			slot.error = noiseOutput.evalError.clone();
+			upload_output(&noiseOutput, &slot.image, None, &mut images, &textureLimits, colormap);
+			slot.output = Some(noiseOutput);
+		}
+	}
+}
+
+/// Puts `output` into the heightmap textures and, if there is one, the terrain.
+fn show_output(
+	output: &NoiseOutput,
+	uiState: &UiState,
+	heightmaps: &Heightmaps,
+	terrain: Option<&mut lod::TerrainChunks>,
+	images: &mut Assets<Image>,
+	meshes: &mut Assets<Mesh>,
+	textureLimits: &textures::TextureLimits,
+	colormap: &colormap::Colormap,
+) {
 	// TODO: this should probably happen in a background thread
-	let image = images.get_mut(&heightmaps.image).unwrap();
-	noiseOutput.fill_image(image);
-	let mesh = meshes.get_mut(&heightmaps.mesh).unwrap();
-	noiseOutput.update_mesh(mesh, uiState.height);
+	upload_output(
+		output,
+		&heightmaps.image,
+		Some(&heightmaps.heights),
+		images,
+		textureLimits,
+		colormap,
+	);
+	// `animation::animate` builds the mesh once paused
+	if let Some(terrain) = terrain.filter(|_| !uiState.animation.skips_mesh()) {
+		if mesh_allowed(output.size(), uiState) {
+			terrain.update(output, uiState.terrain_scale(), meshes);
+		}
+	}
+}
 
-	cmd.insert_resource(noiseOutput);
+/// Writes `output` into a 2D view `image` and, if given, a `heights` texture, downsampled if the
+/// device rejects the full size. If not even that works, the previous images are kept so the
+/// session survives.
+fn upload_output(
+	output: &NoiseOutput,
+	image: &Handle<Image>,
+	heights: Option<&Handle<Image>>,
+	images: &mut Assets<Image>,
+	limits: &textures::TextureLimits,
+	colormap: &colormap::Colormap,
+) {
+	let longer = output.width.max(output.height);
+	let diameter = match limits.fit(longer, TextureFormat::Rgba32Float) {
+		Ok(diameter) => diameter,
+		Err(err) => {
+			error!("keeping the previous heightmap textures: {err}");
+			return;
+		},
+	};
+	let resampled;
+	let display = if diameter == longer {
+		output
+	} else {
+		let size = (
+			(output.width * diameter / longer).max(1),
+			(output.height * diameter / longer).max(1),
+		);
+		warn!(
+			"displaying the {}x{} output at {}x{}",
+			output.width, output.height, size.0, size.1
+		);
+		resampled = snapshot::resample(&output.samples, output.range, output.size(), size);
+		&resampled
+	};
+	match images.get_mut(image) {
+		Some(image) => display.fill_image(image, colormap),
+		None => error!("heightmap image is missing"),
+	}
+	match heights.map(|heights| images.get_mut(heights)) {
+		Some(Some(heights)) => display.fill_heights(heights),
+		Some(None) => error!("heights image is missing"),
+		None => {},
+	}
+}
+
+/// Pre-flight check before building a mesh for `(width, height)` samples.
+fn mesh_allowed((width, height): (usize, usize), uiState: &UiState) -> bool {
+	let estimate = MeshEstimate::new((width, height));
+	if estimate.bytes > MeshEstimate::hardLimit && !uiState.allowHugeMesh {
+		warn!("refusing to build {width}x{height} mesh ({estimate}), confirm in the toolbar");
+		return false;
+	}
+	if estimate.bytes > MeshEstimate::softLimit {
+		warn!("building large {width}x{height} mesh ({estimate})");
+	}
+	true
 }
@@ -0,0 +1,200 @@
+use bevy::prelude::*;
+use bevy::render::mesh::Indices;
+use bevy_egui::{egui, EguiContexts};
+
+use crate::diff::DiffExport;
+use crate::graph::NoiseGraph;
+use crate::pin::PinnedNoiseOutput;
+use crate::session::{SessionEntry, SessionRecorder};
+use crate::snapshot::Snapshots;
+use crate::sweep::SeedSweep;
+use crate::thumbnails::Thumbnails;
+use crate::{lua, NoiseOutput};
+
+/// Everything the estimate is computed from, borrowed from the owning resources.
+pub struct MemorySources<'a> {
+	pub output: Option<&'a NoiseOutput>,
+	pub snapshots: &'a Snapshots,
+	pub pinned: &'a PinnedNoiseOutput,
+	pub diff: &'a DiffExport,
+	pub sweep: &'a SeedSweep,
+	pub thumbnails: &'a Thumbnails,
+	pub graph: &'a NoiseGraph,
+	pub session: &'a [SessionEntry],
+	pub meshes: &'a Assets<Mesh>,
+	pub images: &'a Assets<Image>,
+}
+
+/// Estimated bytes by category. Not exact, but tracks the big consumers.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MemoryUsage {
+	/// Sample buffers: the live output and its uneroded original, snapshots, the pin, diff history
+	/// and seed sweeps.
+	pub noise: usize,
+	pub meshes: usize,
+	/// CPU-side image data in `Assets<Image>`.
+	pub images: usize,
+	/// Script previews and the noise graph's node previews.
+	pub thumbnails: usize,
+	/// Lua states and compiled trees.
+	pub lua: usize,
+	/// Session log entries, mostly embedded sources.
+	pub history: usize,
+}
+
+pub fn mesh_bytes(mesh: &Mesh) -> usize {
+	let vertices: usize = mesh
+		.attributes()
+		.map(|(_, values)| values.get_bytes().len())
+		.sum();
+	let indices = match mesh.indices() {
+		Some(Indices::U16(indices)) => indices.len() * 2,
+		Some(Indices::U32(indices)) => indices.len() * 4,
+		None => 0,
+	};
+	vertices + indices
+}
+
+impl MemoryUsage {
+	/// Estimates everything but Lua, which lives in global state and is added by `current`.
+	pub fn estimate(sources: &MemorySources) -> Self {
+		let output = sources.output.map_or(0, |output| {
+			let eroded = output.eroded.as_ref();
+			let original = eroded.map_or(0, |eroded| eroded.original.samples.capacity());
+			(output.samples.capacity() + original) * 4
+		});
+		Self {
+			noise: output +
+				sources.snapshots.memory_bytes() +
+				sources.pinned.memory_bytes() +
+				sources.diff.memory_bytes() +
+				sources.sweep.memory_bytes(),
+			meshes: sources.meshes.iter().map(|(_, mesh)| mesh_bytes(mesh)).sum(),
+			images: sources.images.iter().map(|(_, image)| image.data.len()).sum(),
+			thumbnails: sources.thumbnails.memory_bytes() + sources.graph.memory_bytes(),
+			lua: 0,
+			history: sources
+				.session
+				.iter()
+				.map(|entry| {
+					std::mem::size_of::<SessionEntry>() + entry.source.as_ref().map_or(0, String::len)
+				})
+				.sum(),
+		}
+	}
+
+	pub fn current(sources: &MemorySources) -> Self {
+		let (states, trees) = lua::memory_bytes();
+		Self {
+			lua: states + trees,
+			..Self::estimate(sources)
+		}
+	}
+
+	pub fn total(&self) -> usize {
+		self.noise + self.meshes + self.images + self.thumbnails + self.lua + self.history
+	}
+
+	fn categories(&self) -> [(&'static str, usize); 6] {
+		[
+			("Noise buffers", self.noise),
+			("Meshes", self.meshes),
+			("Images", self.images),
+			("Thumbnails", self.thumbnails),
+			("Lua", self.lua),
+			("Session history", self.history),
+		]
+	}
+}
+
+fn format_bytes(bytes: usize) -> String {
+	match bytes {
+		0 ..= 1023 => format!("{bytes} B"),
+		1024 ..= 1048575 => format!("{:.1} KiB", bytes as f64 / 1024.0),
+		1048576 ..= 1073741823 => format!("{:.1} MiB", bytes as f64 / 1048576.0),
+		_ => format!("{:.2} GiB", bytes as f64 / 1073741824.0),
+	}
+}
+
+#[derive(Resource, Default)]
+pub struct MemoryPanel {
+	pub open: bool,
+	/// Total before the last clean up, reported against the next estimate once the released
+	/// assets are actually gone.
+	beforeCleanUp: Option<usize>,
+	message: Option<String>,
+}
+
+pub fn memory_panel(
+	mut eguiCtx: EguiContexts,
+	mut panel: ResMut<MemoryPanel>,
+	noiseOutput: Option<Res<NoiseOutput>>,
+	mut snapshots: ResMut<Snapshots>,
+	pinned: Res<PinnedNoiseOutput>,
+	mut diff: ResMut<DiffExport>,
+	sweep: Res<SeedSweep>,
+	mut thumbnails: ResMut<Thumbnails>,
+	mut graph: ResMut<NoiseGraph>,
+	session: Res<SessionRecorder>,
+	meshes: Res<Assets<Mesh>>,
+	mut images: ResMut<Assets<Image>>,
+) {
+	if !panel.open {
+		return;
+	}
+	let current = MemoryUsage::current(&MemorySources {
+		output: noiseOutput.as_deref(),
+		snapshots: &snapshots,
+		pinned: &pinned,
+		diff: &diff,
+		sweep: &sweep,
+		thumbnails: &thumbnails,
+		graph: &graph,
+		session: &session.entries,
+		meshes: &meshes,
+		images: &images,
+	});
+
+	let panel = &mut *panel;
+	if let Some(before) = panel.beforeCleanUp.take() {
+		let reclaimed = format_bytes(before.saturating_sub(current.total()));
+		info!("memory clean up reclaimed ~{reclaimed}");
+		panel.message = Some(format!("reclaimed ~{reclaimed}"));
+	}
+	let mut open = true;
+	let mut cleanUp = false;
+	egui::Window::new("Memory")
+		.open(&mut open)
+		.show(eguiCtx.ctx_mut(), |ui| {
+			egui::Grid::new("memory").striped(true).show(ui, |ui| {
+				for (name, bytes) in current.categories() {
+					ui.label(name);
+					ui.label(format_bytes(bytes));
+					ui.end_row();
+				}
+				ui.strong("Total");
+				ui.strong(format_bytes(current.total()));
+				ui.end_row();
+			});
+			cleanUp = ui
+				.button("Clean up")
+				.on_hover_text(
+					"Drop the previous generation kept for diffs, thumbnails, resampled snapshots and \
+					 compiled scripts",
+				)
+				.clicked();
+			if let Some(message) = &panel.message {
+				ui.label(message);
+			}
+		});
+	panel.open = open;
+
+	if cleanUp {
+		diff.drop_history();
+		thumbnails.clear();
+		graph.clear_thumbnails();
+		snapshots.shrink(&mut images);
+		lua::invalidate_compiled();
+		panel.beforeCleanUp = Some(current.total());
+	}
+}
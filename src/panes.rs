@@ -0,0 +1,92 @@
+use bevy_egui::egui;
+use serde::{Deserialize, Serialize};
+
+/// Thickness of the divider between the panes, in logical points.
+const dividerWidth: f32 = 6.0;
+/// Least share of the central panel either pane keeps when the divider is dragged.
+const minFraction: f32 = 0.1;
+
+/// How the `Tab::Both` layout divides the central panel between the 2D and the 3D pane.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PaneLayout {
+	/// Stacks the 2D pane above the 3D one instead of placing it to the left.
+	pub stacked: bool,
+	/// Share of the panel's width, or height when stacked, given to the 2D pane.
+	pub fraction: f32,
+}
+
+impl Default for PaneLayout {
+	fn default() -> Self {
+		Self {
+			stacked: false,
+			fraction: 0.5,
+		}
+	}
+}
+
+impl PaneLayout {
+	/// Splits `rect` into the 2D pane, the divider and the 3D pane.
+	pub fn split(self, rect: egui::Rect) -> [egui::Rect; 3] {
+		let fraction = self.fraction.clamp(minFraction, 1.0 - minFraction);
+		let half = dividerWidth / 2.0;
+		if self.stacked {
+			let y = rect.top() + rect.height() * fraction;
+			[
+				egui::Rect::from_x_y_ranges(rect.x_range(), rect.top() ..= y - half),
+				egui::Rect::from_x_y_ranges(rect.x_range(), y - half ..= y + half),
+				egui::Rect::from_x_y_ranges(rect.x_range(), y + half ..= rect.bottom()),
+			]
+		} else {
+			let x = rect.left() + rect.width() * fraction;
+			[
+				egui::Rect::from_x_y_ranges(rect.left() ..= x - half, rect.y_range()),
+				egui::Rect::from_x_y_ranges(x - half ..= x + half, rect.y_range()),
+				egui::Rect::from_x_y_ranges(x + half ..= rect.right(), rect.y_range()),
+			]
+		}
+	}
+
+	/// Moves the divider by `delta` points within `rect`, keeping both panes at least
+	/// `minFraction` of it.
+	pub fn drag(&mut self, rect: egui::Rect, delta: egui::Vec2) {
+		let (delta, length) = if self.stacked {
+			(delta.y, rect.height())
+		} else {
+			(delta.x, rect.width())
+		};
+		if length > 0.0 {
+			self.fraction = (self.fraction + delta / length).clamp(minFraction, 1.0 - minFraction);
+		}
+	}
+}
+
+/// Draws the draggable divider between the panes of `rect`, split by `layout`.
+pub fn divider(ui: &mut egui::Ui, rect: egui::Rect, layout: &mut PaneLayout) {
+	let [_, divider, _] = layout.split(rect);
+	let resp = ui.interact(divider, ui.id().with("paneDivider"), egui::Sense::drag());
+	let cursor = if layout.stacked {
+		egui::CursorIcon::ResizeVertical
+	} else {
+		egui::CursorIcon::ResizeHorizontal
+	};
+	if resp.hovered() || resp.dragged() {
+		ui.ctx().set_cursor_icon(cursor);
+	}
+	let visuals = ui.style().interact(&resp);
+	ui.painter().rect_filled(divider, 0.0, visuals.bg_fill);
+	if resp.dragged() {
+		layout.drag(rect, resp.drag_delta());
+	}
+}
+
+/// Orientation toggle for the toolbar, shown with the `Tab::Both` layout.
+pub fn layout_ui(ui: &mut egui::Ui, layout: &mut PaneLayout) {
+	let (icon, hover) = if layout.stacked {
+		("⬍", "2D above 3D, click to place them side by side")
+	} else {
+		("⬌", "2D left of 3D, click to stack them")
+	};
+	if ui.button(icon).on_hover_text(hover).clicked() {
+		layout.stacked = !layout.stacked;
+	}
+}
@@ -0,0 +1,186 @@
+use std::path::PathBuf;
+use std::time::Duration;
+
+use bevy::app::AppExit;
+use bevy::math::EulerRot;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{panes, uiScaleRange, workers, SelectedTab, Tab, UiState};
+
+const stateFile: &str = "noisebench.state.json";
+
+/// Quiet time after the last change before the state is written, so flying the camera doesn't
+/// write every frame.
+const saveDelay: Duration = Duration::from_secs(2);
+
+/// Where the 2D camera looks and how far it's zoomed out.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Camera2dState {
+	pub translation: [f32; 2],
+	pub zoom: f32,
+}
+
+/// Position of the 3D camera and its yaw and pitch in degrees, as `camera_controller_3d` keeps
+/// them.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Camera3dState {
+	pub position: [f32; 3],
+	pub angles: [f32; 2],
+}
+
+/// Session state restored on the next launch. Every field is optional so a partial or older file
+/// still restores what it has, the rest keeping the defaults.
+#[derive(Resource, Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AppState {
+	/// Script of each layer, as the path it was loaded from.
+	pub layerScripts: Vec<Option<PathBuf>>,
+	pub activeLayer: Option<usize>,
+	pub height: Option<f32>,
+	pub extent: Option<f32>,
+	pub tab: Option<Tab>,
+	pub paneLayout: Option<panes::PaneLayout>,
+	pub camera2d: Option<Camera2dState>,
+	pub camera3d: Option<Camera3dState>,
+	/// UI scale on top of the window's own scale factor, see `UiState::uiScale`.
+	pub uiScale: Option<f32>,
+	/// Set only if changed from `workers::default_limit`, which depends on the machine.
+	pub generationWorkers: Option<usize>,
+}
+
+impl AppState {
+	/// Reads the state file, falling back to defaults if it's missing or malformed.
+	pub fn load() -> Self {
+		let Ok(text) = std::fs::read_to_string(stateFile) else {
+			return default();
+		};
+		match serde_json::from_str(&text) {
+			Ok(state) => state,
+			Err(err) => {
+				warn!("ignoring malformed {stateFile}: {err}");
+				default()
+			},
+		}
+	}
+
+	fn save(&self) {
+		let res = serde_json::to_string_pretty(self)
+			.map_err(std::io::Error::from)
+			.and_then(|text| std::fs::write(stateFile, text));
+		if let Err(err) = res {
+			warn!("failed to write {stateFile}: {err}");
+		}
+	}
+
+	/// Restores the scripts, mesh size, tab with its pane layout, UI scale and worker limit.
+	/// Scripts that no longer exist are dropped.
+	pub fn apply(&self, uiState: &mut UiState, selectedTab: &mut SelectedTab) {
+		for (layer, path) in uiState.layers.iter_mut().zip(&self.layerScripts) {
+			let Some(path) = path else {
+				continue;
+			};
+			match uiState.scripts.get_key_value(path) {
+				Some((ipath, _)) => layer.script = Some(ipath.clone()),
+				None => info!("{} no longer exists, not restoring it", path.display()),
+			}
+		}
+		if let Some(active) = self.activeLayer.filter(|&i| i < uiState.layers.len()) {
+			uiState.activeLayer = active;
+			uiState.layers[active].visible = true;
+		}
+		if let Some(height) = self.height.filter(|height| height.is_finite()) {
+			uiState.height = height;
+		}
+		if let Some(extent) = self.extent.filter(|extent| extent.is_finite() && *extent > 0.0) {
+			uiState.extent = extent;
+		}
+		if let Some(tab) = self.tab {
+			selectedTab.0 = tab;
+		}
+		if let Some(layout) = self.paneLayout.filter(|layout| layout.fraction.is_finite()) {
+			uiState.paneLayout = layout;
+		}
+		if let Some(scale) = self.uiScale.filter(|scale| scale.is_finite()) {
+			uiState.uiScale = scale.clamp(*uiScaleRange.start(), *uiScaleRange.end());
+		}
+		if let Some(limit) = self.generationWorkers.filter(|&limit| limit > 0) {
+			workers::set_limit(Some(limit));
+		}
+	}
+
+	/// Transform of the 2D camera, if one was saved.
+	pub fn camera2d_transform(&self) -> Option<Transform> {
+		let camera = self.camera2d.filter(|camera| camera.zoom > 0.0)?;
+		Some(
+			Transform::from_translation(Vec2::from(camera.translation).extend(0.0))
+				.with_scale(Vec3::splat(camera.zoom)),
+		)
+	}
+
+	fn capture(
+		uiState: &UiState,
+		selectedTab: &SelectedTab,
+		camera2d: &Transform,
+		camera3d: &Transform,
+	) -> Self {
+		let (yaw, pitch, _) = camera3d.rotation.to_euler(EulerRot::YXZ);
+		Self {
+			layerScripts: uiState
+				.layers
+				.iter()
+				.map(|layer| layer.script.as_ref().map(|path| path.path.clone()))
+				.collect(),
+			activeLayer: Some(uiState.activeLayer),
+			height: Some(uiState.height),
+			extent: Some(uiState.extent),
+			tab: Some(selectedTab.0),
+			paneLayout: Some(uiState.paneLayout),
+			camera2d: Some(Camera2dState {
+				translation: camera2d.translation.truncate().into(),
+				zoom: camera2d.scale.x,
+			}),
+			camera3d: Some(Camera3dState {
+				position: camera3d.translation.into(),
+				angles: [yaw.to_degrees(), pitch.to_degrees()],
+			}),
+			uiScale: Some(uiState.uiScale),
+			generationWorkers: workers::configured(),
+		}
+	}
+}
+
+/// Writes the state once it's been unchanged for `saveDelay`, and on exit. The `AppState`
+/// resource holds what was last loaded or written.
+pub fn save_state(
+	mut saved: ResMut<AppState>,
+	uiState: Res<UiState>,
+	selectedTab: Res<SelectedTab>,
+	camera2d: Query<&Transform, With<Camera2d>>,
+	camera3d: Query<&Transform, With<Camera3d>>,
+	time: Res<Time>,
+	mut exit: EventReader<AppExit>,
+	mut pending: Local<Option<(AppState, Duration)>>,
+) {
+	let exiting = exit.read().count() > 0;
+	let (Ok(camera2d), Ok(camera3d)) = (camera2d.get_single(), camera3d.get_single()) else {
+		return;
+	};
+	let current = AppState::capture(&uiState, &selectedTab, camera2d, camera3d);
+	if current == *saved {
+		*pending = None;
+		return;
+	}
+	// restart the delay on every change
+	let now = time.elapsed();
+	if pending.as_ref().map_or(true, |(state, _)| *state != current) {
+		*pending = Some((current, now));
+	}
+	let Some((state, changed)) = &*pending else {
+		return;
+	};
+	if exiting || now - *changed >= saveDelay {
+		state.save();
+		*saved = pending.take().unwrap().0;
+	}
+}
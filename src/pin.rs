@@ -0,0 +1,260 @@
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
+use bevy_egui::egui;
+
+use crate::reference::{self, ReferenceHeightmap};
+use crate::{snapshot, NoiseOutput};
+
+/// What the 2D view shows in place of the live output's values.
+#[derive(Resource, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DisplayMode {
+	#[default]
+	Output,
+	/// Absolute difference between the live output and `PinnedNoiseOutput`.
+	PinnedDiff,
+	/// The `ReferenceHeightmap` alone.
+	Reference,
+	/// The live output and the `ReferenceHeightmap` averaged.
+	ReferenceBlend,
+}
+
+impl DisplayMode {
+	pub const all: [Self; 4] = [
+		Self::Output,
+		Self::PinnedDiff,
+		Self::Reference,
+		Self::ReferenceBlend,
+	];
+
+	pub fn label(self) -> &'static str {
+		match self {
+			Self::Output => "Output",
+			Self::PinnedDiff => "Diff to pin",
+			Self::Reference => "Reference",
+			Self::ReferenceBlend => "Blend with reference",
+		}
+	}
+}
+
+/// Differences between the live output and the pinned one.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PinDiff {
+	pub max: f32,
+	pub mean: f32,
+	/// Samples where exactly one of the two is NaN, which the maximum and mean leave out.
+	pub nans: usize,
+	/// Width and height of the pin, if it was resampled to the live output's.
+	pub resampledFrom: Option<(usize, usize)>,
+}
+
+impl PinDiff {
+	/// Compares `live` against `pinned`, which must have as many samples.
+	pub fn new(live: &[f32], pinned: &[f32]) -> Self {
+		let mut diff = Self {
+			max: 0.0,
+			mean: 0.0,
+			nans: 0,
+			resampledFrom: None,
+		};
+		let (mut sum, mut compared) = (0.0, 0);
+		for (&a, &b) in live.iter().zip(pinned) {
+			match (a.is_nan(), b.is_nan()) {
+				(false, false) => {
+					let d = (a - b).abs();
+					diff.max = diff.max.max(d);
+					sum += d as f64;
+					compared += 1;
+				},
+				(true, true) => {},
+				_ => diff.nans += 1,
+			}
+		}
+		diff.mean = (sum / compared.max(1) as f64) as f32;
+		diff
+	}
+}
+
+/// Copy of an output taken with "Pin", kept across regenerations to check a refactored script
+/// against.
+#[derive(Resource)]
+pub struct PinnedNoiseOutput {
+	pub output: Option<NoiseOutput>,
+	/// Factor differences are multiplied by for display, so tiny ones stand out. At 1, a
+	/// difference spanning the output's whole range is white.
+	pub amplification: f32,
+	/// Result of the last comparison with the live output, in `DisplayMode::PinnedDiff`.
+	pub diff: Option<PinDiff>,
+	image: Option<Handle<Image>>,
+}
+
+impl Default for PinnedNoiseOutput {
+	fn default() -> Self {
+		Self {
+			output: None,
+			amplification: 1.0,
+			diff: None,
+			image: None,
+		}
+	}
+}
+
+impl PinnedNoiseOutput {
+	/// Image the 2D view shows instead of the heightmap, if there's a comparison to show.
+	pub fn diff_image(&self, mode: DisplayMode) -> Option<Handle<Image>> {
+		self.image.clone().filter(|_| mode == DisplayMode::PinnedDiff && self.diff.is_some())
+	}
+
+	/// Bytes held in the pinned sample buffer.
+	pub fn memory_bytes(&self) -> usize {
+		self.output.as_ref().map_or(0, |output| output.samples.capacity() * 4)
+	}
+}
+
+/// Pin and reference resources edited from the 2D toolbar, bundled to keep `main_ui` under the
+/// system parameter limit.
+#[derive(SystemParam)]
+pub struct PinControls<'w> {
+	pinned: ResMut<'w, PinnedNoiseOutput>,
+	mode: ResMut<'w, DisplayMode>,
+	reference: ResMut<'w, ReferenceHeightmap>,
+}
+
+impl PinControls<'_> {
+	pub fn ui(&mut self, ui: &mut egui::Ui, live: Option<&NoiseOutput>) {
+		let resp = ui
+			.add_enabled(live.is_some(), egui::Button::new("Pin"))
+			.on_hover_text("Keep a copy of the current output to compare later generations with");
+		if let Some(live) = live.filter(|_| resp.clicked()) {
+			self.pinned.output = Some(live.clone());
+			info!("pinned the {}x{} output", live.width, live.height);
+		}
+		if self.pinned.output.is_some() && ui.button("Unpin").clicked() {
+			self.pinned.output = None;
+			self.pinned.diff = None;
+			if *self.mode == DisplayMode::PinnedDiff {
+				*self.mode = DisplayMode::Output;
+			}
+		}
+		ui.menu_button("Reference", |ui| reference::reference_ui(ui, &mut self.reference))
+			.response
+			.on_hover_text("Load a heightmap image to compare the output with");
+
+		let available = |mode: DisplayMode| match mode {
+			DisplayMode::Output => true,
+			DisplayMode::PinnedDiff => self.pinned.output.is_some(),
+			DisplayMode::Reference | DisplayMode::ReferenceBlend => self.reference.image.is_some(),
+		};
+		if !DisplayMode::all.into_iter().skip(1).any(available) {
+			return;
+		}
+		let mut mode = *self.mode;
+		egui::ComboBox::from_id_source("displayMode")
+			.selected_text(mode.label())
+			.show_ui(ui, |ui| {
+				for option in DisplayMode::all.into_iter().filter(|&option| available(option)) {
+					ui.selectable_value(&mut mode, option, option.label());
+				}
+			});
+		// only touch the resource on an actual switch to keep change detection quiet
+		if mode != *self.mode {
+			*self.mode = mode;
+		}
+		if mode != DisplayMode::PinnedDiff {
+			return;
+		}
+		let mut amplification = self.pinned.amplification;
+		ui.add(
+			egui::DragValue::new(&mut amplification)
+				.range(1.0 ..= 1e6)
+				// proportional, to get from 1 to thousands in one drag
+				.speed(amplification * 0.02)
+				.prefix("×"),
+		)
+		.on_hover_text("Amplification of the displayed differences");
+		if amplification != self.pinned.amplification {
+			self.pinned.amplification = amplification;
+		}
+		match self.pinned.diff {
+			Some(diff) => {
+				if diff.max == 0.0 && diff.nans == 0 {
+					ui.label("identical");
+				} else {
+					ui.label(format!("max {:.3e}  mean {:.3e}", diff.max, diff.mean));
+				}
+				if diff.nans > 0 {
+					let warning = format!("{} NaN mismatches", diff.nans);
+					ui.colored_label(ui.visuals().warn_fg_color, warning);
+				}
+				if let Some((width, height)) = diff.resampledFrom {
+					let warning = format!("pin is {width}x{height}, resampled");
+					ui.colored_label(ui.visuals().warn_fg_color, warning).on_hover_text(
+						"Differences include resampling error, pin at this size to compare exactly",
+					);
+				}
+			},
+			None => {
+				ui.label("no output to compare");
+			},
+		}
+	}
+}
+
+/// Compares the live output against the pin whenever either or the display mode changes, and
+/// redraws the difference image `snapshot::snapshots` puts on the 2D view.
+pub fn pinned_diff(
+	noiseOutput: Option<Res<NoiseOutput>>,
+	mode: Res<DisplayMode>,
+	mut pinned: ResMut<PinnedNoiseOutput>,
+	mut images: ResMut<Assets<Image>>,
+) {
+	let changed = mode.is_changed() ||
+		pinned.is_changed() ||
+		noiseOutput.as_ref().is_some_and(|output| output.is_changed());
+	if !changed {
+		return;
+	}
+	// the comparison is a result, not an edit to react to next frame
+	let pinned = pinned.bypass_change_detection();
+	let (Some(live), Some(pin)) = (noiseOutput.as_deref(), &pinned.output) else {
+		pinned.diff = None;
+		return;
+	};
+	if *mode != DisplayMode::PinnedDiff {
+		pinned.diff = None;
+		return;
+	}
+
+	let resampled;
+	let pinSamples = if pin.size() == live.size() {
+		&pin.samples
+	} else {
+		warn!(
+			"pinned output is {}x{} but the live one is {}x{}, resampling the pin",
+			pin.width, pin.height, live.width, live.height
+		);
+		resampled = snapshot::resample(&pin.samples, pin.range, pin.size(), live.size());
+		&resampled.samples
+	};
+	let mut diff = PinDiff::new(&live.samples, pinSamples);
+	diff.resampledFrom = (pin.size() != live.size()).then_some(pin.size());
+	pinned.diff = Some(diff);
+
+	let handle = pinned.image.get_or_insert_with(|| {
+		images.add(Image::new_fill(
+			Extent3d {
+				width: 1,
+				height: 1,
+				depth_or_array_layers: 1,
+			},
+			TextureDimension::D2,
+			bytemuck::cast_slice(&[0f32; 4]),
+			TextureFormat::Rgba32Float,
+			default(),
+		))
+	});
+	match images.get_mut(&*handle) {
+		Some(image) => live.fill_diff_image(image, pinSamples, pinned.amplification),
+		None => error!("pin diff image is missing"),
+	}
+}
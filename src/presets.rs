@@ -0,0 +1,332 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+
+use anyhow::{ensure, Context};
+use bevy::prelude::*;
+use bevy_egui::egui;
+use crossbeam_channel::{Receiver, Sender};
+use notify::{RecursiveMode, Watcher};
+use serde::{Deserialize, Serialize};
+
+use crate::log_panel::{LogKind, SessionLog, Severity};
+use crate::{AResult, NoiseGenRequest, UiState};
+
+/// Directory presets are saved to and listed from, a `.toml` file each named after the preset.
+pub const presetsDir: &str = "assets/presets";
+
+/// What a preset restores: the active layer's script with its parameters, and the settings a
+/// script is usually tuned at. Every field is optional so files written by hand, or by older and
+/// newer versions, load what they have; unknown fields are ignored.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Preset {
+	/// Relative to the scripts directory with `/` separators, like `InternedPath::display`.
+	pub script: Option<String>,
+	/// Mesh height.
+	pub heightScale: f32,
+	pub seed: i64,
+	pub width: usize,
+	pub height: usize,
+	/// Values of the script's `Params` sliders, by name.
+	pub params: BTreeMap<String, f64>,
+}
+
+impl Default for Preset {
+	fn default() -> Self {
+		Self {
+			script: None,
+			heightScale: 1.0,
+			seed: 0,
+			width: 256,
+			height: 256,
+			params: default(),
+		}
+	}
+}
+
+impl Preset {
+	/// The active layer's script and the current settings.
+	pub fn capture(uiState: &UiState) -> Self {
+		let selected = uiState.selected();
+		Self {
+			script: selected.map(|script| script.display.clone()),
+			heightScale: uiState.height,
+			seed: uiState.seed,
+			width: uiState.outputWidth,
+			height: uiState.outputHeight,
+			params: selected.map_or(default(), |script| uiState.script_params(script)),
+		}
+	}
+
+	/// Selects the script on the active layer and applies the rest. A missing script is left
+	/// out, along with its parameters, and reported as the error.
+	pub fn apply(&self, uiState: &mut UiState) -> Result<(), String> {
+		if self.heightScale.is_finite() {
+			uiState.height = self.heightScale;
+		}
+		uiState.seed = self.seed;
+		uiState.outputWidth = self.width.clamp(16, 4096);
+		uiState.outputHeight = self.height.clamp(16, 4096);
+		let Some(name) = &self.script else {
+			return Ok(());
+		};
+		let Some(script) = uiState.scripts.keys().find(|path| path.display == *name).cloned() else {
+			return Err(format!("{name} is missing, loaded the rest without its parameters"));
+		};
+		// sliders the preset doesn't set go back to the script's defaults
+		uiState.params.retain(|(path, _), _| *path != script);
+		for (param, &v) in &self.params {
+			uiState.params.insert((script.clone(), param.clone()), v);
+		}
+		*uiState.selected_mut() = Some(script);
+		Ok(())
+	}
+
+	pub fn from_toml(text: &str) -> AResult<Self> {
+		Ok(toml::from_str(text)?)
+	}
+
+	pub fn to_toml(&self) -> AResult<String> {
+		Ok(toml::to_string_pretty(self)?)
+	}
+}
+
+fn preset_path(name: &str) -> PathBuf {
+	Path::new(presetsDir).join(format!("{name}.toml"))
+}
+
+fn is_preset_file(path: &Path) -> bool {
+	path.extension().is_some_and(|extension| extension == "toml")
+}
+
+fn write_preset(name: &str, preset: &Preset) -> AResult<()> {
+	ensure!(valid_name(name), "{name:?} can't be a file name");
+	let path = preset_path(name);
+	std::fs::write(&path, preset.to_toml()?).with_context(|| format!("writing {}", path.display()))
+}
+
+/// Names that stay a single file in `presetsDir`.
+fn valid_name(name: &str) -> bool {
+	!name.is_empty() && !name.starts_with('.') && !name.contains(['/', '\\'])
+}
+
+/// Watches `presetsDir`, creating it if needed, so presets written by hand or checked out show
+/// up. The watcher stops once dropped.
+pub fn watch(sender: Sender<notify::Event>) -> Option<notify::RecommendedWatcher> {
+	let res = std::fs::create_dir_all(presetsDir).map_err(anyhow::Error::from).and_then(|()| {
+		let mut watcher = notify::recommended_watcher(move |res| match res {
+			Ok(event) => {
+				let _ = sender.send(event);
+			},
+			Err(err) => error!("preset watcher error: {err:?}"),
+		})?;
+		watcher.watch(Path::new(presetsDir), RecursiveMode::NonRecursive)?;
+		Ok(watcher)
+	});
+	res.map_err(|err| warn!("not watching {presetsDir}: {err:#}")).ok()
+}
+
+/// The presets in `presetsDir` and the toolbar's preset controls.
+#[derive(Resource)]
+pub struct Presets {
+	/// By name, which is the file's stem.
+	presets: BTreeMap<String, Preset>,
+	/// Preset last loaded or saved, which Save overwrites.
+	selected: Option<String>,
+	/// Name being typed for "Save as", while its field is shown.
+	newName: Option<String>,
+	/// Outcome of the last action, and whether it's a warning.
+	message: Option<(String, bool)>,
+	channel: Receiver<notify::Event>,
+}
+
+impl Presets {
+	pub fn new(channel: Receiver<notify::Event>) -> Self {
+		let mut presets = Self {
+			presets: default(),
+			selected: None,
+			newName: None,
+			message: None,
+			channel,
+		};
+		presets.rescan();
+		presets
+	}
+
+	/// Reads every preset again. Files that don't parse are left out with a warning.
+	fn rescan(&mut self) {
+		self.presets.clear();
+		let entries = match std::fs::read_dir(presetsDir) {
+			Ok(entries) => entries,
+			Err(err) => {
+				warn!("failed to read {presetsDir}: {err}");
+				return;
+			},
+		};
+		let paths = entries.flatten().map(|entry| entry.path());
+		for path in paths.filter(|path| is_preset_file(path)) {
+			let Some(name) = path.file_stem().map(|stem| stem.to_string_lossy().into_owned()) else {
+				continue;
+			};
+			let read = std::fs::read_to_string(&path).map_err(anyhow::Error::from);
+			match read.and_then(|text| Preset::from_toml(&text)) {
+				Ok(preset) => {
+					self.presets.insert(name, preset);
+				},
+				Err(err) => warn!("ignoring preset {}: {err:#}", path.display()),
+			}
+		}
+		if self.selected.as_ref().is_some_and(|name| !self.presets.contains_key(name)) {
+			self.selected = None;
+		}
+	}
+
+	fn load(&mut self, name: &str, uiState: &mut UiState, log: &SessionLog) {
+		let Some(preset) = self.presets.get(name) else {
+			return;
+		};
+		let res = preset.apply(uiState);
+		self.selected = Some(name.to_owned());
+		let script = uiState.selected();
+		self.message = match res {
+			Ok(()) => {
+				log.record(Severity::Info, LogKind::Preset, script, format!("loaded {name}"));
+				None
+			},
+			Err(err) => {
+				let message = format!("loaded {name}: {err}");
+				log.record(Severity::Warn, LogKind::Preset, None, message);
+				Some((err, true))
+			},
+		};
+	}
+
+	fn save(&mut self, name: &str, uiState: &UiState, log: &SessionLog) {
+		let preset = Preset::capture(uiState);
+		match write_preset(name, &preset) {
+			Ok(()) => {
+				let script = uiState.selected();
+				log.record(Severity::Info, LogKind::Preset, script, format!("saved {name}"));
+				// listed right away, the watcher's echo reads it again
+				self.presets.insert(name.to_owned(), preset);
+				self.selected = Some(name.to_owned());
+				self.message = Some((format!("saved {name}"), false));
+			},
+			Err(err) => {
+				let message = format!("saving {name}: {err:#}");
+				log.record(Severity::Error, LogKind::Preset, None, message);
+				self.message = Some((format!("{err:#}"), true));
+			},
+		}
+	}
+
+	fn delete(&mut self, name: &str, log: &SessionLog) {
+		let path = preset_path(name);
+		match std::fs::remove_file(&path) {
+			Ok(()) => {
+				log.record(Severity::Info, LogKind::Preset, None, format!("deleted {name}"));
+				self.presets.remove(name);
+				self.selected = None;
+				self.message = None;
+			},
+			Err(err) => {
+				let message = format!("deleting {}: {err}", path.display());
+				log.record(Severity::Error, LogKind::Preset, None, message.clone());
+				self.message = Some((message, true));
+			},
+		}
+	}
+}
+
+/// Lists the presets again once files in `presetsDir` changed.
+pub fn presets_changed(mut presets: ResMut<Presets>, log: Res<SessionLog>) {
+	// editors often report a single save two or three times, once per file is enough
+	let mut changed = BTreeSet::new();
+	while let Ok(ev) = presets.channel.try_recv() {
+		let paths = ev.paths.iter().filter(|path| is_preset_file(path));
+		changed.extend(paths.filter_map(|path| path.file_name()).map(|name| {
+			name.to_string_lossy().into_owned()
+		}));
+	}
+	if changed.is_empty() {
+		return;
+	}
+	for name in &changed {
+		let message = format!("preset {name} changed");
+		log.record(Severity::Info, LogKind::FileWatcher, None, message);
+	}
+	presets.rescan();
+}
+
+/// Preset combo box with Save, Save as and Delete, for the toolbar. Picking a preset applies it
+/// and regenerates.
+pub fn presets_ui(
+	ui: &mut egui::Ui,
+	presets: &mut Presets,
+	uiState: &mut UiState,
+	noiseGenRequests: &mut EventWriter<NoiseGenRequest>,
+	log: &SessionLog,
+) {
+	ui.label("Preset");
+	let mut picked = None;
+	egui::ComboBox::from_id_source("preset")
+		.selected_text(presets.selected.as_deref().unwrap_or(""))
+		.show_ui(ui, |ui| {
+			for name in presets.presets.keys() {
+				let current = presets.selected.as_ref() == Some(name);
+				if ui.selectable_label(current, name).clicked() {
+					picked = Some(name.clone());
+				}
+			}
+		})
+		.response
+		.on_hover_text(format!(
+			"Script, parameters, seed, size and mesh height saved in {presetsDir}. Picking one \
+			 applies it, again too to undo changes since"
+		));
+	if let Some(name) = picked {
+		presets.load(&name, uiState, log);
+		noiseGenRequests.send(NoiseGenRequest::AlgorithmChanged);
+	}
+
+	let selected = presets.selected.clone();
+	let resp = ui
+		.add_enabled(selected.is_some(), egui::Button::new("Save"))
+		.on_hover_text("Overwrite the selected preset with the current settings");
+	if let Some(name) = selected.as_deref().filter(|_| resp.clicked()) {
+		presets.save(name, uiState, log);
+	}
+	if ui.button("Save as…").clicked() {
+		presets.newName = match presets.newName {
+			Some(_) => None,
+			None => Some(selected.clone().unwrap_or_default()),
+		};
+	}
+	if let Some(newName) = &mut presets.newName {
+		let field = egui::TextEdit::singleline(newName).desired_width(100.0).hint_text("name");
+		let resp = ui.add(field);
+		let entered = resp.lost_focus() && ui.input(|input| input.key_pressed(egui::Key::Enter));
+		let name = newName.trim().to_owned();
+		let exists = presets.presets.contains_key(&name);
+		let label = if exists { "Overwrite" } else { "OK" };
+		if ui.add_enabled(valid_name(&name), egui::Button::new(label)).clicked() || entered {
+			presets.newName = None;
+			presets.save(&name, uiState, log);
+		}
+	}
+	let resp = ui
+		.add_enabled(selected.is_some(), egui::Button::new("Delete"))
+		.on_hover_text("Delete the selected preset's file");
+	if let Some(name) = selected.as_deref().filter(|_| resp.clicked()) {
+		presets.delete(name, log);
+	}
+	match &presets.message {
+		Some((message, true)) => {
+			ui.colored_label(ui.visuals().warn_fg_color, message);
+		},
+		Some((message, false)) => {
+			ui.label(message);
+		},
+		None => {},
+	}
+}
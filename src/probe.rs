@@ -0,0 +1,234 @@
+use bevy::color::palettes::css;
+use bevy::math::DVec2;
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContexts};
+
+use crate::gizmos::{View2dGizmos, View3dGizmos};
+use crate::input::{self, ViewportInput};
+use crate::theme::Theme;
+use crate::walk::terrain_height;
+use crate::{
+	scene, HeightmapSprite, Heightmaps, NoiseOutput, SelectedTab, Tab, TerrainScale, UiState,
+	ViewportRects,
+};
+
+/// Marker radius relative to its distance from the camera, like the water handle's.
+const markerScale: f32 = 0.01;
+/// Marker radius in the 2D view, in physical pixels.
+const markerPixels: f32 = 8.0;
+/// Ray marching steps per sample spacing, before bisecting the step that crossed the surface.
+const stepsPerSample: f32 = 2.0;
+/// Bisections refining a crossing, each halving the error.
+const bisections: usize = 16;
+
+/// The last point probed in the 3D view, by middle click or P over the terrain.
+#[derive(Resource, Default)]
+pub struct Probe {
+	/// Position over the grid, `(0, 0)` being the first sample and `(1, 1)` the last. Kept
+	/// instead of a world position so the marker stays on the surface as outputs and the terrain
+	/// scale change.
+	pub hit: Option<Vec2>,
+	/// The last probe's ray passed the terrain by.
+	pub missed: bool,
+}
+
+/// A probe hit laid out on `output` as it is now.
+#[derive(Clone, Copy, Debug)]
+pub struct ProbePoint {
+	pub world: Vec3,
+	/// Evaluation coordinates, as passed to `Noise::eval` at the root.
+	pub domain: DVec2,
+	/// Nearest sample and its value.
+	pub sample: UVec2,
+	pub value: f32,
+}
+
+impl ProbePoint {
+	pub fn new(output: &NoiseOutput, scale: TerrainScale, unit: Vec2) -> Self {
+		let last = last_sample(output);
+		let samplePos = unit * last.max(UVec2::ONE).as_vec2();
+		let ground = scale.to_world(output.size(), samplePos);
+		let sample = samplePos.round().as_uvec2().min(last);
+		Self {
+			world: vec3(ground.x, terrain_height(output, scale, ground), ground.y),
+			domain: output.domain_position(samplePos),
+			sample,
+			value: output.samples[sample.y as usize * output.width + sample.x as usize],
+		}
+	}
+}
+
+fn last_sample(output: &NoiseOutput) -> UVec2 {
+	UVec2::new(output.width.saturating_sub(1) as u32, output.height.saturating_sub(1) as u32)
+}
+
+/// Distance along `ray` to where it first crosses the surface `NoiseOutput::update_mesh` builds,
+/// interpolating bilinearly between samples like `terrain_height`. Only the part of the ray
+/// above the grid is marched, through the box spanned by the heights.
+pub fn ray_terrain(ray: Ray3d, output: &NoiseOutput, scale: TerrainScale) -> Option<f32> {
+	let (low, high) = output
+		.samples
+		.iter()
+		.map(|&v| output.normalized(v) * scale.height)
+		.filter(|h| h.is_finite())
+		.fold((f32::INFINITY, f32::NEG_INFINITY), |(lo, hi), h| (lo.min(h), hi.max(h)));
+	if low > high {
+		return None;
+	}
+	let half = scale.to_world(output.size(), last_sample(output).as_vec2()).abs();
+	let (min, max) = (vec3(-half.x, low, -half.y), vec3(half.x, high, half.y));
+	// slab test, axes the ray runs parallel to give infinite bounds
+	let (mut enter, mut exit) = (0.0f32, f32::INFINITY);
+	for axis in 0 .. 3 {
+		let (origin, dir) = (ray.origin[axis], ray.direction[axis]);
+		if dir.abs() < 1e-12 {
+			if origin < min[axis] || origin > max[axis] {
+				return None;
+			}
+			continue;
+		}
+		let (a, b) = ((min[axis] - origin) / dir, (max[axis] - origin) / dir);
+		enter = enter.max(a.min(b));
+		exit = exit.min(a.max(b));
+	}
+	if enter > exit {
+		return None;
+	}
+
+	let above = |t: f32| {
+		let point = ray.get_point(t);
+		point.y - terrain_height(output, scale, vec2(point.x, point.z))
+	};
+	let step = scale.spacing(output.size()) / stepsPerSample;
+	if !(step > 0.0) {
+		return None;
+	}
+	let (mut t, mut previous) = (enter, above(enter));
+	while t < exit {
+		let next = (t + step).min(exit);
+		let height = above(next);
+		// NaN samples neither start nor end a crossing
+		if previous.is_finite() && height.is_finite() && (previous > 0.0) != (height > 0.0) {
+			let (mut lo, mut hi) = (t, next);
+			for _ in 0 .. bisections {
+				let mid = (lo + hi) / 2.0;
+				if (above(mid) > 0.0) == (previous > 0.0) {
+					lo = mid;
+				} else {
+					hi = mid;
+				}
+			}
+			return Some(hi);
+		}
+		(t, previous) = (next, height);
+	}
+	None
+}
+
+/// Probes the terrain under the pointer on middle click or P in the 3D view, and shows the
+/// result in an overlay and as a marker on the surface.
+pub fn probe_3d(
+	mut eguiCtx: EguiContexts,
+	mut gizmos: Gizmos<View3dGizmos>,
+	mut probe: ResMut<Probe>,
+	selectedTab: Res<SelectedTab>,
+	sceneMode: Res<scene::SceneMode>,
+	viewportRects: Res<ViewportRects>,
+	viewportInput: Res<ViewportInput>,
+	keyboard: Res<ButtonInput<KeyCode>>,
+	mouseButtons: Res<ButtonInput<MouseButton>>,
+	camera: Query<(&Camera, &GlobalTransform), With<Camera3d>>,
+	noiseOutput: Option<Res<NoiseOutput>>,
+	uiState: Res<UiState>,
+	theme: Res<Theme>,
+) {
+	if !selectedTab.shows(Tab::D3) || !sceneMode.shows_output() {
+		return;
+	}
+	let Some(output) = noiseOutput.filter(|output| output.width > 1 && output.height > 1) else {
+		return;
+	};
+	let ctx = eguiCtx.ctx_mut();
+	let scale = uiState.terrain_scale();
+	let (camera, cameraTransform) = camera.single();
+	let viewportRect = &viewportRects.d3;
+
+	let pressed = mouseButtons.just_pressed(MouseButton::Middle) ||
+		viewportInput.keyboard(Tab::D3) && keyboard.just_pressed(KeyCode::KeyP);
+	let pointer = ctx
+		.pointer_hover_pos()
+		.filter(|&pos| input::over_viewport(ctx, viewportRect, pos));
+	if let Some(pos) = pointer.filter(|_| pressed) {
+		let ray = camera.viewport_to_world(cameraTransform, viewportRect.to_pixels(pos));
+		let hit = ray.and_then(|ray| Some(ray.get_point(ray_terrain(ray, &output, scale)?)));
+		probe.missed = hit.is_none();
+		if let Some(hit) = hit {
+			let sample = scale.to_sample(output.size(), vec2(hit.x, hit.z));
+			probe.hit = Some(sample / last_sample(&output).as_vec2());
+		}
+	}
+	if probe.hit.is_none() && !probe.missed {
+		return;
+	}
+
+	let point = probe.hit.map(|unit| ProbePoint::new(&output, scale, unit));
+	if let Some(point) = point {
+		let radius = markerScale * cameraTransform.translation().distance(point.world);
+		gizmos.sphere(point.world, Quat::IDENTITY, radius, css::ORANGE_RED);
+	}
+	egui::Area::new(egui::Id::new("probeReadout"))
+		.fixed_pos(viewportRect.rect.left_bottom() + egui::vec2(8.0, -8.0))
+		.pivot(egui::Align2::LEFT_BOTTOM)
+		.interactable(false)
+		.show(ctx, |ui| {
+			theme.overlay_frame(ui.style()).show(ui, |ui| {
+				if probe.missed {
+					ui.label("probe missed the terrain");
+				}
+				let Some(ProbePoint { world, domain, sample, value }) = point else {
+					return;
+				};
+				if probe.missed {
+					ui.weak("last hit:");
+				}
+				ui.label(format!("world ({:.3}, {:.3}, {:.3})", world.x, world.y, world.z));
+				ui.label(format!("domain ({:.4}, {:.4})", domain.x, domain.y));
+				ui.label(format!("sample [{}, {}] = {value:.4}", sample.x, sample.y));
+			});
+		});
+}
+
+/// Marks the probed point in the 2D view too.
+pub fn draw_probe_2d(
+	mut gizmos: Gizmos<View2dGizmos>,
+	probe: Res<Probe>,
+	noiseOutput: Option<Res<NoiseOutput>>,
+	selectedTab: Res<SelectedTab>,
+	heightmaps: Res<Heightmaps>,
+	images: Res<Assets<Image>>,
+	sprite: Query<&Transform, With<HeightmapSprite>>,
+	camera: Query<&Transform, With<Camera2d>>,
+) {
+	if !selectedTab.shows(Tab::D2) {
+		return;
+	}
+	let (Some(unit), Some(output)) = (probe.hit, noiseOutput) else {
+		return;
+	};
+	let Some(image) = images.get(&heightmaps.image) else {
+		return;
+	};
+	let size = image.size_f32();
+	let transform = sprite.single();
+	// laid out like `scatter::draw_scatter_2d`, the sprite centered with rows going down
+	let (origin, spriteScale) = (transform.translation.truncate(), transform.scale.x);
+	let texel = size / vec2(output.width as f32, output.height as f32);
+	let pos = (unit * last_sample(&output).as_vec2() + 0.5) * texel;
+	let center = origin + vec2(pos.x - size.x / 2.0, size.y / 2.0 - pos.y) * spriteScale;
+	let radius = markerPixels * camera.single().scale.x;
+	gizmos.circle_2d(center, radius, css::ORANGE_RED);
+	for axis in [Vec2::X, Vec2::Y] {
+		let arm = axis * radius * 1.5;
+		gizmos.line_2d(center - arm, center + arm, css::ORANGE_RED);
+	}
+}
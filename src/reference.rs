@@ -0,0 +1,192 @@
+use std::path::Path;
+use std::sync::Arc;
+
+use bevy::prelude::*;
+use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
+use bevy::tasks::futures_lite::future;
+use bevy::tasks::{block_on, IoTaskPool, Task};
+use bevy_egui::egui;
+
+pub use noisebench_core::height_image::HeightImage;
+
+use crate::pin::DisplayMode;
+use crate::{colormap, snapshot, AResult, NoiseOutput};
+
+/// Extensions of the image files the reference menu lists.
+const imageExtensions: [&str; 2] = ["png", "exr"];
+
+/// Heightmap image loaded to compare scripts against, shown in the 2D view by
+/// `DisplayMode::Reference` and `DisplayMode::ReferenceBlend`.
+#[derive(Resource)]
+pub struct ReferenceHeightmap {
+	path: String,
+	pub image: Option<Arc<HeightImage>>,
+	loading: Option<Task<AResult<HeightImage>>>,
+	message: Option<String>,
+	/// What the display mode shows, redrawn by `reference_image`.
+	display: Option<Handle<Image>>,
+}
+
+impl Default for ReferenceHeightmap {
+	fn default() -> Self {
+		Self {
+			path: "assets/reference.png".into(),
+			image: None,
+			loading: None,
+			message: None,
+			display: None,
+		}
+	}
+}
+
+impl ReferenceHeightmap {
+	/// Image the 2D view shows instead of the heightmap, if the display mode is one of the
+	/// reference's.
+	pub fn display_image(&self, mode: DisplayMode) -> Option<Handle<Image>> {
+		let shown = matches!(mode, DisplayMode::Reference | DisplayMode::ReferenceBlend);
+		self.display.clone().filter(|_| shown && self.image.is_some())
+	}
+
+	/// Starts decoding `path` on the IO pool, replacing the current reference once it succeeds.
+	fn load(&mut self) {
+		let path = self.path.clone();
+		self.message = Some(format!("loading {path}"));
+		self.loading = Some(IoTaskPool::get().spawn(async move { HeightImage::load(&path) }));
+	}
+}
+
+/// Image files below `dir`, for the reference menu's list.
+fn find_images(dir: &Path, found: &mut Vec<String>) {
+	let Ok(entries) = std::fs::read_dir(dir) else {
+		return;
+	};
+	for path in entries.flatten().map(|entry| entry.path()) {
+		if path.is_dir() {
+			find_images(&path, found);
+		} else if path
+			.extension()
+			.and_then(|ext| ext.to_str())
+			.is_some_and(|ext| imageExtensions.contains(&ext.to_ascii_lowercase().as_str()))
+		{
+			found.push(path.display().to_string());
+		}
+	}
+}
+
+/// Contents of the 2D toolbar's "Reference" menu.
+pub fn reference_ui(ui: &mut egui::Ui, reference: &mut ReferenceHeightmap) {
+	ui.horizontal(|ui| {
+		ui.label("Image");
+		ui.text_edit_singleline(&mut reference.path)
+			.on_hover_text("Square grayscale PNG or EXR, relative to the working directory");
+		ui.menu_button("▾", |ui| {
+			let mut images = vec![];
+			find_images(Path::new("assets"), &mut images);
+			images.sort();
+			if images.is_empty() {
+				ui.label("no images in assets");
+			}
+			for image in images {
+				if ui.selectable_label(reference.path == image, &image).clicked() {
+					reference.path = image;
+					reference.load();
+					ui.close_menu();
+				}
+			}
+		})
+		.response
+		.on_hover_text("Images found in assets");
+	});
+	ui.horizontal(|ui| {
+		if ui.button("Load").clicked() {
+			reference.load();
+		}
+		if ui.add_enabled(reference.image.is_some(), egui::Button::new("Unload")).clicked() {
+			reference.image = None;
+			reference.message = None;
+		}
+	});
+	if let Some(image) = &reference.image {
+		ui.label(format!("{} ({1}x{1})", image.path, image.diameter));
+	}
+	if let Some(message) = &reference.message {
+		ui.label(message);
+	}
+	ui.small("Scripts can sample images with Noise.image(path)");
+}
+
+/// Finishes loads, and redraws the display image whenever the reference, the live output, the
+/// display mode or the colormap changes. The blend averages the reference, resampled to the live
+/// diameter, with the live output mapped from its range to `[-1, 1]`.
+pub fn reference_image(
+	noiseOutput: Option<Res<NoiseOutput>>,
+	mut mode: ResMut<DisplayMode>,
+	mut reference: ResMut<ReferenceHeightmap>,
+	mut images: ResMut<Assets<Image>>,
+	colormap: Res<colormap::ColormapSettings>,
+) {
+	// polling isn't a change, finishing is
+	let loading = reference.bypass_change_detection().loading.as_mut();
+	if let Some(res) = loading.and_then(|task| block_on(future::poll_once(task))) {
+		reference.loading = None;
+		match res {
+			Ok(image) => {
+				info!("loaded reference {0} ({1}x{1})", image.path, image.diameter);
+				reference.message = None;
+				reference.image = Some(Arc::new(image));
+			},
+			Err(err) => {
+				error!("reference load failed: {err:#}");
+				reference.message = Some(format!("{err:#}"));
+			},
+		}
+	}
+	let shown = matches!(*mode, DisplayMode::Reference | DisplayMode::ReferenceBlend);
+	if shown && reference.image.is_none() {
+		*mode = DisplayMode::Output;
+		return;
+	}
+	let changed = mode.is_changed() ||
+		reference.is_changed() ||
+		colormap.is_changed() ||
+		noiseOutput.as_ref().is_some_and(|output| output.is_changed());
+	if !shown || !changed {
+		return;
+	}
+
+	// the redraw is a result, not an edit to react to next frame
+	let reference = reference.bypass_change_detection();
+	let image = reference.image.as_ref().unwrap();
+	let shownOutput = match noiseOutput.as_deref() {
+		Some(live) if *mode == DisplayMode::ReferenceBlend => {
+			let square = (image.diameter, image.diameter);
+			let mut blend = snapshot::resample(&image.samples, (-1.0, 1.0), square, live.size());
+			for (v, &liveValue) in blend.samples.iter_mut().zip(&live.samples) {
+				*v = (*v + live.normalized(liveValue)) * 0.5;
+			}
+			blend
+		},
+		_ => {
+			let mut output = NoiseOutput::new(image.diameter);
+			output.samples.copy_from_slice(&image.samples);
+			output
+		},
+	};
+	let handle = reference.display.get_or_insert_with(|| {
+		images.add(Image::new_fill(
+			Extent3d {
+				width: 1,
+				height: 1,
+				depth_or_array_layers: 1,
+			},
+			TextureDimension::D2,
+			bytemuck::cast_slice(&[0f32; 4]),
+			TextureFormat::Rgba32Float,
+			default(),
+		))
+	});
+	match images.get_mut(&*handle) {
+		Some(image) => shownOutput.fill_image(image, &colormap.colormap),
+		None => error!("reference image is missing"),
+	}
+}
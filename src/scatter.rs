@@ -0,0 +1,671 @@
+use std::f32::consts::{SQRT_2, TAU};
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use anyhow::{ensure, Context};
+use bevy::math::vec2;
+use bevy::prelude::*;
+use bevy::tasks::futures_lite::future;
+use bevy::tasks::{block_on, AsyncComputeTaskPool, Task};
+use bevy_egui::{egui, EguiContexts};
+use serde::Serialize;
+
+use crate::gizmos::{to_color, View2dGizmos};
+use crate::log_panel::{LogKind, SessionLog, Severity};
+use crate::theme::Theme;
+use crate::{
+	lod, mesh_allowed, noise_rng, AResult, HeightmapSprite, Heightmaps, NoiseOutput, SelectedTab,
+	Tab, TerrainScale, UiState,
+};
+
+/// Candidates tried around a point before it stops spawning new ones, as in Bridson's paper.
+const candidates: usize = 30;
+/// Random starting points that have to fail in a row before scattering stops. Growing from the
+/// points so far can't cross areas of zero density, new starting points find the areas beyond.
+const seedAttempts: usize = 256;
+/// Largest radius relative to `ScatterParams::radius`. Areas of lower density, below 1/64 of the
+/// densest, get no points at all.
+const maxRadiusFactor: f32 = 8.0;
+/// Most cells per axis of the lookup grid, however small the radius.
+const maxGridCells: usize = 1024;
+/// Marker size relative to the radius around its point, so neighbouring markers don't touch.
+const markerScale: f32 = 0.3;
+
+/// Parameters of the Poisson-disk scatter. The radius is measured in samples, so the same
+/// parameters scatter alike across scripts and sizes with the same sample spacing.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ScatterParams {
+	/// Seed of the candidate positions; the same seed and parameters always scatter the same
+	/// output the same way.
+	pub seed: u64,
+	/// Least distance between points where the density is highest. Elsewhere it grows with
+	/// `1 / sqrt(density)`, keeping the number of points per area proportional to the density.
+	pub radius: f32,
+	pub maxPoints: usize,
+	/// Power the density, the output mapped from its declared range to `[0, 1]`, is raised to.
+	/// Above 1 gathers points in the densest areas, 0 spreads them evenly.
+	pub exponent: f32,
+}
+
+impl Default for ScatterParams {
+	fn default() -> Self {
+		Self {
+			seed: 0,
+			radius: 4.0,
+			maxPoints: 5000,
+			exponent: 1.0,
+		}
+	}
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ScatterPoint {
+	/// Fractional sample coordinates, x along rows and y down the columns.
+	pub pos: Vec2,
+	/// Least distance to the points placed after it, in samples.
+	pub radius: f32,
+}
+
+/// Points scattered over the current output, see `ScatterPanel`.
+#[derive(Resource, Clone, Debug)]
+pub struct ScatterOutput {
+	pub params: ScatterParams,
+	/// Width and height of the output the points were scattered over.
+	pub size: (usize, usize),
+	pub points: Vec<ScatterPoint>,
+}
+
+/// Marker shape of the points on the terrain.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MarkerShape {
+	#[default]
+	Cone,
+	Sphere,
+}
+
+impl MarkerShape {
+	pub const all: [Self; 2] = [Self::Cone, Self::Sphere];
+
+	pub fn label(self) -> &'static str {
+		match self {
+			Self::Cone => "Cones",
+			Self::Sphere => "Spheres",
+		}
+	}
+}
+
+/// Point indices bucketed by position, for finding the points near a candidate.
+struct PointGrid {
+	cellSize: f32,
+	cells: usize,
+	slots: Vec<Vec<u32>>,
+}
+
+impl PointGrid {
+	fn new(last: f32, radius: f32) -> Self {
+		// cells no wider than the smallest radius over √2 hold one point at most, unless there'd
+		// be too many of them
+		let cellSize = (radius / SQRT_2).max(last / maxGridCells as f32);
+		let cells = (last / cellSize) as usize + 1;
+		Self {
+			cellSize,
+			cells,
+			slots: vec![vec![]; cells * cells],
+		}
+	}
+
+	fn cell(&self, pos: Vec2) -> UVec2 {
+		(pos / self.cellSize).as_uvec2().min(UVec2::splat(self.cells as u32 - 1))
+	}
+
+	fn insert(&mut self, index: usize, pos: Vec2) {
+		let cell = self.cell(pos);
+		self.slots[cell.y as usize * self.cells + cell.x as usize].push(index as u32);
+	}
+
+	/// Whether no point lies within `radius` of `pos`.
+	fn is_free(&self, points: &[ScatterPoint], pos: Vec2, radius: f32) -> bool {
+		let (lo, hi) = (self.cell(pos - radius), self.cell(pos + radius));
+		(lo.y ..= hi.y)
+			.flat_map(|y| (lo.x ..= hi.x).map(move |x| y as usize * self.cells + x as usize))
+			.flat_map(|slot| &self.slots[slot])
+			.all(|&i| points[i as usize].pos.distance_squared(pos) >= radius * radius)
+	}
+}
+
+/// Sample at fractional coordinates `pos`, interpolated bilinearly and clamped to the grid.
+fn bilinear(samples: &[f32], (width, height): (usize, usize), pos: Vec2) -> f32 {
+	let (lastX, lastY) = (width - 1, height - 1);
+	let pos = pos.clamp(Vec2::ZERO, vec2(lastX as f32, lastY as f32));
+	let (x, y) = (pos.x as usize, pos.y as usize);
+	let (u, v) = (pos.x - x as f32, pos.y - y as f32);
+	let at = |x: usize, y: usize| samples[y.min(lastY) * width + x.min(lastX)];
+	let top = at(x, y) + (at(x + 1, y) - at(x, y)) * u;
+	let bottom = at(x, y + 1) + (at(x + 1, y + 1) - at(x, y + 1)) * u;
+	top + (bottom - top) * v
+}
+
+/// Poisson-disk scatter over `samples` read as a density map, by Bridson's algorithm with the
+/// radius varying with the density at each candidate, see `ScatterParams`. Deterministic for the
+/// same samples and parameters.
+pub fn scatter(
+	samples: &[f32],
+	size: (usize, usize),
+	(min, max): (f32, f32),
+	params: &ScatterParams,
+) -> Vec<ScatterPoint> {
+	let (width, height) = size;
+	if width < 2 || height < 2 || !(max > min) || !(params.radius > 0.0) {
+		return vec![];
+	}
+	let last = vec2((width - 1) as f32, (height - 1) as f32);
+	let radius_at = |pos: Vec2| {
+		let v = (bilinear(samples, size, pos) - min) / (max - min);
+		// NaN samples, from scripts failing on them, get no points
+		let density = if v.is_finite() { v.clamp(0.0, 1.0).powf(params.exponent) } else { 0.0 };
+		let radius = params.radius / density.sqrt();
+		(radius <= params.radius * maxRadiusFactor).then_some(radius)
+	};
+	let inside = |pos: Vec2| pos.cmpge(Vec2::ZERO).all() && pos.cmple(last).all();
+
+	let mut rng = noise_rng::Rng::new(params.seed);
+	// square over the longer side, which leaves the cells past the shorter one empty
+	let mut grid = PointGrid::new(last.max_element(), params.radius);
+	let mut points: Vec<ScatterPoint> = vec![];
+	// points that may still have room around them
+	let mut active: Vec<usize> = vec![];
+	let mut failedSeeds = 0;
+	while points.len() < params.maxPoints {
+		let found = if active.is_empty() {
+			if failedSeeds == seedAttempts {
+				break;
+			}
+			let pos = vec2(rng.next_f64() as f32, rng.next_f64() as f32) * last;
+			let point = radius_at(pos)
+				.filter(|&radius| grid.is_free(&points, pos, radius))
+				.map(|radius| ScatterPoint { pos, radius });
+			failedSeeds = if point.is_some() { 0 } else { failedSeeds + 1 };
+			point
+		} else {
+			let slot = (rng.next_u64() % active.len() as u64) as usize;
+			let from = points[active[slot]];
+			let point = (0 .. candidates).find_map(|_| {
+				let angle = rng.next_f64() as f32 * TAU;
+				let distance = from.radius * (1.0 + rng.next_f64() as f32);
+				let pos = from.pos + Vec2::from_angle(angle) * distance;
+				let radius = radius_at(pos).filter(|_| inside(pos))?;
+				grid.is_free(&points, pos, radius).then_some(ScatterPoint { pos, radius })
+			});
+			if point.is_none() {
+				active.swap_remove(slot);
+			}
+			point
+		};
+		if let Some(point) = found {
+			grid.insert(points.len(), point.pos);
+			active.push(points.len());
+			points.push(point);
+		}
+	}
+	points
+}
+
+/// Height of the surface `NoiseOutput::update_mesh` builds above the fractional sample
+/// coordinates `sample`, normalized to `[-1, 1]`. Interpolated across the two triangles the mesh
+/// splits each cell into, unlike the bilinear `walk::terrain_height`, so markers sit exactly on
+/// the surface. Positions off the grid take the height at its border.
+pub fn surface_height(output: &NoiseOutput, sample: Vec2) -> f32 {
+	let (lastX, lastY) = (output.width.saturating_sub(1), output.height.saturating_sub(1));
+	let pos = sample.clamp(Vec2::ZERO, vec2(lastX as f32, lastY as f32));
+	// the last row and column are the far corners of the cells before them
+	let x = (pos.x as usize).min(lastX.saturating_sub(1));
+	let y = (pos.y as usize).min(lastY.saturating_sub(1));
+	let (u, v) = (pos.x - x as f32, pos.y - y as f32);
+	let at = |x: usize, y: usize| {
+		output.normalized(output.samples[y.min(lastY) * output.width + x.min(lastX)])
+	};
+	// the diagonal runs from (x + 1, y) to (x, y + 1), see the mesh's indices
+	if u + v <= 1.0 {
+		let near = at(x, y);
+		near + (at(x + 1, y) - near) * u + (at(x, y + 1) - near) * v
+	} else {
+		let far = at(x + 1, y + 1);
+		far + (at(x, y + 1) - far) * (1.0 - u) + (at(x + 1, y) - far) * (1.0 - v)
+	}
+}
+
+/// A point as exported, from `(0, 0)` at the first sample to `(1, 1)` at the last and in the
+/// world at the current terrain scale, y up.
+#[derive(Debug, Serialize)]
+struct ExportedPoint {
+	u: f32,
+	v: f32,
+	/// Least distance to the points around it, relative to the output's longer side.
+	radius: f32,
+	/// Terrain height at the point, normalized to `[-1, 1]`.
+	height: f32,
+	x: f32,
+	y: f32,
+	z: f32,
+}
+
+fn exported_points(
+	scatter: &ScatterOutput,
+	output: &NoiseOutput,
+	scale: TerrainScale,
+) -> Vec<ExportedPoint> {
+	let steps = (vec2(scatter.size.0 as f32, scatter.size.1 as f32) - 1.0).max(Vec2::ONE);
+	scatter
+		.points
+		.iter()
+		.map(|point| {
+			let height = surface_height(output, point.pos);
+			let ground = scale.to_world(output.size(), point.pos);
+			ExportedPoint {
+				u: point.pos.x / steps.x,
+				v: point.pos.y / steps.y,
+				radius: point.radius / steps.max_element(),
+				height,
+				x: ground.x,
+				y: height * scale.height,
+				z: ground.y,
+			}
+		})
+		.collect()
+}
+
+fn write_csv(path: &Path, points: &[ExportedPoint]) -> AResult<()> {
+	let mut text = "u,v,radius,height,x,y,z\n".to_string();
+	for ExportedPoint { u, v, radius, height, x, y, z } in points {
+		writeln!(text, "{u},{v},{radius},{height},{x},{y},{z}")?;
+	}
+	std::fs::write(path, text).with_context(|| format!("writing {}", path.display()))
+}
+
+fn write_json(path: &Path, points: &[ExportedPoint]) -> AResult<()> {
+	let text = serde_json::to_string_pretty(points)?;
+	std::fs::write(path, text).with_context(|| format!("writing {}", path.display()))
+}
+
+/// Scatter window, opened from the toolbar. Points are scattered in the background over the
+/// current output, shown as circles in the 2D view and markers on the terrain, and exported as
+/// CSV or JSON.
+#[derive(Resource)]
+pub struct ScatterPanel {
+	pub open: bool,
+	params: ScatterParams,
+	/// Scatters again whenever the output changes, instead of clearing the points.
+	follow: bool,
+	pub show: bool,
+	pub shape: MarkerShape,
+	/// Export path without extension, each format appends its own.
+	path: String,
+	csv: bool,
+	json: bool,
+	task: Option<(Task<ScatterOutput>, Instant)>,
+	message: Option<String>,
+}
+
+impl Default for ScatterPanel {
+	fn default() -> Self {
+		Self {
+			open: false,
+			params: default(),
+			follow: true,
+			show: true,
+			shape: default(),
+			path: "scatter".into(),
+			csv: true,
+			json: false,
+			task: None,
+			message: None,
+		}
+	}
+}
+
+impl ScatterPanel {
+	/// Starts scattering over `output`, dropping any running pass.
+	fn start(&mut self, output: &NoiseOutput) {
+		let samples = output.samples.clone();
+		let (size, range, params) = (output.size(), output.range, self.params);
+		let task = AsyncComputeTaskPool::get().spawn(async move {
+			let points = scatter(&samples, size, range, &params);
+			ScatterOutput {
+				params,
+				size,
+				points,
+			}
+		});
+		self.task = Some((task, Instant::now()));
+		self.message = None;
+	}
+
+	/// Writes `scatter` in every selected format.
+	fn export(
+		&self,
+		scatter: Option<&ScatterOutput>,
+		output: Option<&NoiseOutput>,
+		scale: TerrainScale,
+	) -> AResult<Vec<PathBuf>> {
+		let scatter = scatter.context("nothing has been scattered yet")?;
+		let output = output.context("nothing has been generated yet")?;
+		ensure!(scatter.size == output.size(), "the output changed since scattering");
+		ensure!(self.csv || self.json, "no format selected");
+		ensure!(!self.path.trim().is_empty(), "no output path");
+
+		let points = exported_points(scatter, output, scale);
+		let mut written = vec![];
+		if self.csv {
+			let path = PathBuf::from(format!("{}.csv", self.path));
+			write_csv(&path, &points)?;
+			written.push(path);
+		}
+		if self.json {
+			let path = PathBuf::from(format!("{}.json", self.path));
+			write_json(&path, &points)?;
+			written.push(path);
+		}
+		Ok(written)
+	}
+}
+
+fn params_ui(ui: &mut egui::Ui, params: &mut ScatterParams) {
+	egui::Grid::new("scatterParams").show(ui, |ui| {
+		ui.label("Seed");
+		ui.add(egui::DragValue::new(&mut params.seed));
+		ui.end_row();
+
+		ui.label("Radius");
+		ui.add(egui::DragValue::new(&mut params.radius).range(0.5 ..= 256.0).speed(0.05))
+			.on_hover_text("Least distance between points at the highest density, in samples");
+		ui.end_row();
+
+		ui.label("Max points");
+		ui.add(egui::DragValue::new(&mut params.maxPoints).range(1 ..= 100_000).speed(10));
+		ui.end_row();
+
+		ui.label("Density exponent");
+		ui.add(egui::DragValue::new(&mut params.exponent).range(0.0 ..= 8.0).speed(0.01))
+			.on_hover_text("Above 1 gathers points where the output is highest, 0 spreads them out");
+		ui.end_row();
+	});
+}
+
+pub fn scatter_panel(
+	mut cmd: Commands,
+	mut eguiCtx: EguiContexts,
+	mut panel: ResMut<ScatterPanel>,
+	scatter: Option<Res<ScatterOutput>>,
+	noiseOutput: Option<Res<NoiseOutput>>,
+	uiState: Res<UiState>,
+	log: Res<SessionLog>,
+) {
+	let panel = &mut *panel;
+	let outputChanged = noiseOutput.as_ref().map_or(true, |output| output.is_changed());
+	if outputChanged && (panel.task.is_some() || scatter.is_some()) {
+		match noiseOutput.as_deref().filter(|_| panel.follow) {
+			Some(output) => panel.start(output),
+			None => {
+				panel.task = None;
+				cmd.remove_resource::<ScatterOutput>();
+				panel.message = Some("the output changed, points cleared".into());
+			},
+		}
+	}
+	let finished = panel
+		.task
+		.as_mut()
+		.and_then(|(task, started)| Some((block_on(future::poll_once(task))?, started.elapsed())));
+	if let Some((output, elapsed)) = finished {
+		panel.task = None;
+		info!("scattered {} points in {elapsed:?}", output.points.len());
+		panel.message = Some(format!("done in {:.2}s", elapsed.as_secs_f32()));
+		cmd.insert_resource(output);
+	}
+	if !panel.open {
+		return;
+	}
+
+	let mut open = true;
+	egui::Window::new("Scatter")
+		.open(&mut open)
+		.show(eguiCtx.ctx_mut(), |ui| {
+			egui::CollapsingHeader::new("Parameters")
+				.default_open(true)
+				.show(ui, |ui| params_ui(ui, &mut panel.params));
+			ui.checkbox(&mut panel.follow, "Follow the output")
+				.on_hover_text("Scatter again whenever the output changes, instead of clearing");
+			ui.horizontal(|ui| {
+				ui.checkbox(&mut panel.show, "Show");
+				egui::ComboBox::from_id_source("scatterShape")
+					.selected_text(panel.shape.label())
+					.show_ui(ui, |ui| {
+						for shape in MarkerShape::all {
+							ui.selectable_value(&mut panel.shape, shape, shape.label());
+						}
+					})
+					.response
+					.on_hover_text("Markers on the terrain");
+			});
+
+			ui.horizontal(|ui| {
+				let running = panel.task.is_some();
+				let start = ui.add_enabled(
+					noiseOutput.is_some() && !running,
+					egui::Button::new("Scatter"),
+				);
+				if let Some(output) = noiseOutput.as_ref().filter(|_| start.clicked()) {
+					panel.start(output);
+				}
+				if ui.add_enabled(scatter.is_some(), egui::Button::new("Clear")).clicked() {
+					panel.task = None;
+					panel.message = None;
+					cmd.remove_resource::<ScatterOutput>();
+				}
+				if running {
+					ui.spinner();
+					if ui.button("Cancel").clicked() {
+						panel.task = None;
+					}
+				}
+			});
+			if let Some(scatter) = &scatter {
+				let ScatterParams { seed, maxPoints, .. } = scatter.params;
+				let count = scatter.points.len();
+				let full = if count == maxPoints { ", the most allowed" } else { "" };
+				ui.label(format!("showing {count} points of seed {seed}{full}"));
+			}
+			if let Some(message) = &panel.message {
+				ui.label(message);
+			}
+
+			ui.separator();
+			egui::Grid::new("scatterExport").show(ui, |ui| {
+				ui.label("Path");
+				ui.text_edit_singleline(&mut panel.path)
+					.on_hover_text("Without extension, each format appends its own");
+				ui.end_row();
+
+				ui.label("Formats");
+				ui.horizontal(|ui| {
+					ui.checkbox(&mut panel.csv, "CSV");
+					ui.checkbox(&mut panel.json, "JSON");
+				});
+				ui.end_row();
+			});
+			let export = ui
+				.add_enabled(scatter.is_some(), egui::Button::new("Export points"))
+				.on_hover_text(
+					"Unit square coordinates, radius and normalized height, and the world position \
+					 on the terrain at its current height and extent",
+				);
+			if export.clicked() {
+				let res = panel.export(
+					scatter.as_deref(),
+					noiseOutput.as_deref(),
+					uiState.terrain_scale(),
+				);
+				panel.message = Some(match res {
+					Ok(paths) => {
+						let paths: Vec<_> =
+							paths.iter().map(|path| path.display().to_string()).collect();
+						let message = format!("exported scatter to {}", paths.join(", "));
+						log.record(Severity::Info, LogKind::Export, None, message);
+						format!("wrote {}", paths.join(", "))
+					},
+					Err(err) => {
+						let message = format!("scatter export failed: {err:#}");
+						log.record(Severity::Error, LogKind::Export, None, message);
+						format!("export failed: {err:#}")
+					},
+				});
+			}
+		});
+	panel.open = open;
+}
+
+/// The points of `scatter` matching the current output, if they're to be shown.
+fn shown<'a>(
+	panel: &ScatterPanel,
+	scatter: Option<&'a ScatterOutput>,
+	output: Option<&NoiseOutput>,
+) -> Option<&'a ScatterOutput> {
+	let output = output?;
+	scatter.filter(|scatter| panel.show && scatter.size == output.size())
+}
+
+/// Draws the points over the 2D view, each a circle half its radius wide so neighbours don't
+/// overlap. Works from the uploaded image's size, which may be downsampled, like the gradient
+/// arrows.
+pub fn draw_scatter_2d(
+	mut gizmos: Gizmos<View2dGizmos>,
+	panel: Res<ScatterPanel>,
+	scatter: Option<Res<ScatterOutput>>,
+	noiseOutput: Option<Res<NoiseOutput>>,
+	selectedTab: Res<SelectedTab>,
+	heightmaps: Res<Heightmaps>,
+	images: Res<Assets<Image>>,
+	sprite: Query<&Transform, With<HeightmapSprite>>,
+	theme: Res<Theme>,
+) {
+	if !selectedTab.shows(Tab::D2) {
+		return;
+	}
+	let Some(scatter) = shown(&panel, scatter.as_deref(), noiseOutput.as_deref()) else {
+		return;
+	};
+	let Some(image) = images.get(&heightmaps.image) else {
+		return;
+	};
+	let size = image.size_f32();
+	let transform = sprite.single();
+	// zoomed in domains scale the sprite, see `Domain::placement`
+	let (origin, scale) = (transform.translation.truncate(), transform.scale.x);
+	// downsampling keeps the aspect, so texels stay square
+	let texel = size.x / scatter.size.0 as f32;
+	let color = to_color(theme.accent());
+	for point in &scatter.points {
+		// the sprite is centered on its transform, with rows going down
+		let pos = (point.pos + 0.5) * texel;
+		let local = vec2(pos.x - size.x / 2.0, size.y / 2.0 - pos.y);
+		gizmos.circle_2d(origin + local * scale, point.radius * texel * scale * 0.5, color);
+	}
+}
+
+/// Marks entities placed on the terrain for scattered points.
+#[derive(Component)]
+pub struct ScatterMarker;
+
+/// Meshes and material shared by every marker, so they're drawn as instances of one another.
+pub struct MarkerAssets {
+	cone: Handle<Mesh>,
+	sphere: Handle<Mesh>,
+	material: Handle<StandardMaterial>,
+}
+
+/// What the markers were placed for, besides the points and output themselves.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MarkerLayout {
+	shape: MarkerShape,
+	scale: TerrainScale,
+	color: egui::Color32,
+}
+
+/// Places a marker on the terrain for every shown point, at the height of the mesh below it.
+/// Markers are placed again when the points, output, shape or terrain scale change.
+pub fn place_scatter_markers(
+	mut cmd: Commands,
+	panel: Res<ScatterPanel>,
+	scatter: Option<Res<ScatterOutput>>,
+	noiseOutput: Option<Res<NoiseOutput>>,
+	terrain: Option<Res<lod::TerrainChunks>>,
+	uiState: Res<UiState>,
+	theme: Res<Theme>,
+	markers: Query<Entity, With<ScatterMarker>>,
+	mut meshes: ResMut<Assets<Mesh>>,
+	mut materials: ResMut<Assets<StandardMaterial>>,
+	mut markerAssets: Local<Option<MarkerAssets>>,
+	mut placed: Local<Option<MarkerLayout>>,
+) {
+	let scatter = scatter.filter(|_| terrain.is_some());
+	let shownScatter = shown(&panel, scatter.as_deref(), noiseOutput.as_deref());
+	let layout = shownScatter.map(|_| MarkerLayout {
+		shape: panel.shape,
+		scale: uiState.terrain_scale(),
+		color: theme.accent(),
+	});
+	let changed = scatter.as_ref().is_some_and(|scatter| scatter.is_changed()) ||
+		noiseOutput.as_ref().is_some_and(|output| output.is_changed());
+	if layout == *placed && !(layout.is_some() && changed) {
+		return;
+	}
+	*placed = layout;
+	for ent in &markers {
+		cmd.entity(ent).despawn();
+	}
+	let (Some(scatter), Some(output), Some(layout)) = (shownScatter, noiseOutput, layout) else {
+		return;
+	};
+	// the terrain isn't rebuilt for outputs this large, markers would float over the old one
+	if !mesh_allowed(output.size(), &uiState) {
+		return;
+	}
+
+	let assets = markerAssets.get_or_insert_with(|| MarkerAssets {
+		cone: meshes.add(Cone {
+			radius: 1.0,
+			height: 2.0,
+		}),
+		sphere: meshes.add(Sphere::new(1.0)),
+		material: materials.add(StandardMaterial::default()),
+	});
+	if let Some(material) = materials.get_mut(&assets.material) {
+		material.base_color = to_color(layout.color);
+	}
+	let mesh = match layout.shape {
+		MarkerShape::Cone => assets.cone.clone(),
+		MarkerShape::Sphere => assets.sphere.clone(),
+	};
+	let scale = layout.scale;
+	let spacing = scale.spacing(output.size());
+	let bundles: Vec<_> = scatter
+		.points
+		.iter()
+		.map(|point| {
+			let size = point.radius * spacing * markerScale;
+			let ground = scale.to_world(output.size(), point.pos);
+			let height = surface_height(&output, point.pos) * scale.height;
+			// both meshes reach `size` below their center, which rests on the surface
+			let translation = vec3(ground.x, height + size, ground.y);
+			let bundle = PbrBundle {
+				mesh: mesh.clone(),
+				material: assets.material.clone(),
+				transform: Transform::from_translation(translation).with_scale(Vec3::splat(size)),
+				..default()
+			};
+			(bundle, ScatterMarker)
+		})
+		.collect();
+	cmd.spawn_batch(bundles);
+}
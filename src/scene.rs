@@ -0,0 +1,303 @@
+use bevy::pbr::wireframe::Wireframe;
+use bevy::prelude::*;
+use bevy_egui::egui;
+
+use crate::infinite::InfiniteTerrain;
+use crate::lod::TerrainChunks;
+use crate::pin::PinnedNoiseOutput;
+use crate::water::{planeSpan, WaterMaterial, WaterSettings};
+use crate::{mesh_allowed, Heightmaps, MeshEstimate, NoiseOutput, TerrainScale, UiState};
+
+/// Gap between the live and the pinned terrain of `SceneMode::Compare`, in terrain extents.
+const compareGap: f32 = 0.1;
+
+/// What the 3D view shows. Switching despawns everything owned by the previous mode and releases
+/// its assets, while the camera and cached `NoiseOutput` carry over.
+#[derive(Resource, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SceneMode {
+	/// The terrain, in chunks at distance-based levels of detail, with a water plane.
+	#[default]
+	Single,
+	/// A/B comparison: the terrain as in `Single`, without water, and the pinned output's
+	/// terrain beside it along +X, see `sync_pinned_terrain`.
+	Compare,
+	/// The live tree generated in tiles around the camera for as far as it goes, see
+	/// `infinite::InfiniteTerrain`.
+	Infinite,
+}
+
+impl SceneMode {
+	pub const all: [SceneMode; 3] = [SceneMode::Single, SceneMode::Compare, SceneMode::Infinite];
+
+	pub fn label(self) -> &'static str {
+		match self {
+			SceneMode::Single => "Terrain",
+			SceneMode::Compare => "A/B with pin",
+			SceneMode::Infinite => "Infinite",
+		}
+	}
+
+	/// Whether the live output's terrain is in the scene where `NoiseOutput` puts it, which
+	/// probing and handles work on.
+	pub fn shows_output(self) -> bool {
+		matches!(self, SceneMode::Single | SceneMode::Compare)
+	}
+}
+
+/// Marks entities spawned for a `SceneMode`.
+#[derive(Component)]
+pub struct SceneOwned(pub SceneMode);
+
+/// A terrain chunk entity of `SceneMode::Single`, see `lod::TerrainChunks`.
+#[derive(Component)]
+pub struct TerrainSurface;
+
+/// The water plane entity of `SceneMode::Single`.
+#[derive(Component)]
+pub struct WaterPlane;
+
+/// The pinned output's terrain entity of `SceneMode::Compare`.
+#[derive(Component)]
+pub struct PinnedSurface;
+
+/// Render modes for judging the terrain's shape, edited from the View menu on the 3D tab.
+#[derive(Resource)]
+pub struct ViewSettings {
+	pub wireframe: bool,
+	/// One normal per triangle instead of smoothed per vertex, so every facet shows.
+	pub flatShading: bool,
+	/// Horizontal grids at integer heights, which don't move with the height scale.
+	pub heightGrid: bool,
+	pub water: bool,
+	/// Shows distant terrain chunks at lower resolutions, see `lod::TerrainChunks`.
+	pub lod: bool,
+}
+
+impl Default for ViewSettings {
+	fn default() -> Self {
+		Self {
+			wireframe: false,
+			flatShading: false,
+			heightGrid: false,
+			water: true,
+			lod: true,
+		}
+	}
+}
+
+pub fn view_ui(ui: &mut egui::Ui, settings: &mut ViewSettings) {
+	ui.checkbox(&mut settings.wireframe, "Wireframe");
+	ui.checkbox(&mut settings.flatShading, "Flat shading");
+	ui.checkbox(&mut settings.heightGrid, "Height grid");
+	ui.checkbox(&mut settings.water, "Water plane");
+	ui.checkbox(&mut settings.lod, "Level of detail")
+		.on_hover_text("Show distant parts of the terrain at lower resolutions");
+}
+
+pub fn apply_scene_mode(
+	mut cmd: Commands,
+	mode: Res<SceneMode>,
+	owned: Query<(
+		Entity,
+		&SceneOwned,
+		Option<&Handle<Mesh>>,
+		Option<&Handle<StandardMaterial>>,
+		Option<&Handle<WaterMaterial>>,
+	)>,
+	assets: Res<AssetServer>,
+	mut meshes: ResMut<Assets<Mesh>>,
+	mut materials: ResMut<Assets<StandardMaterial>>,
+	mut waterMaterials: ResMut<Assets<WaterMaterial>>,
+	uiState: Res<UiState>,
+	heightmaps: Res<Heightmaps>,
+	water: Res<WaterSettings>,
+	noiseOutput: Option<Res<NoiseOutput>>,
+	mut current: Local<Option<SceneMode>>,
+) {
+	if *current == Some(*mode) {
+		return;
+	}
+
+	if let Some(previous) = current.replace(*mode) {
+		info!("switching scene from {previous:?} to {:?}", *mode);
+	}
+	for (ent, SceneOwned(owner), mesh, material, waterMaterial) in owned.iter() {
+		if *owner == *mode {
+			continue;
+		}
+		cmd.entity(ent).despawn_recursive();
+		if let Some(mesh) = mesh {
+			meshes.remove(mesh);
+		}
+		if let Some(material) = material {
+			materials.remove(material);
+		}
+		if let Some(material) = waterMaterial {
+			waterMaterials.remove(material);
+		}
+	}
+	cmd.remove_resource::<TerrainChunks>();
+	cmd.remove_resource::<InfiniteTerrain>();
+
+	if mode.shows_output() {
+		let material = materials.add(StandardMaterial {
+			base_color_texture: Some(heightmaps.colors.clone()),
+			normal_map_texture: Some(assets.load("ground_texture/normal.jpg")),
+			..default()
+		});
+		// `lod::sync_terrain_chunks` spawns the chunks
+		let mut terrain = TerrainChunks::new(material, *mode);
+		let allowed = |output: &Res<NoiseOutput>| mesh_allowed(output.size(), &uiState);
+		if let Some(output) = noiseOutput.filter(allowed) {
+			terrain.update(&output, uiState.terrain_scale(), &mut meshes);
+		}
+		cmd.insert_resource(terrain);
+	}
+
+	match *mode {
+		SceneMode::Single => {
+			// water
+			// unit sized, `update_water_material` scales it with the terrain extent
+			let mesh = meshes.add(Rectangle::new(1.0, 1.0));
+			// `update_water_material` corrects the size once the heights are written
+			let size = uiState.output_size();
+			let material = waterMaterials.add(water.material(&uiState, &heightmaps, size));
+			cmd.spawn((
+				SceneOwned(SceneMode::Single),
+				WaterPlane,
+				MaterialMeshBundle {
+					mesh,
+					material,
+					transform: Transform::from_scale(Vec3::splat(uiState.extent * planeSpan))
+						.looking_to(Vec3::NEG_Y, Vec3::Z),
+					..default()
+				},
+			));
+		},
+		SceneMode::Compare => {
+			// a placeholder `sync_pinned_terrain` replaces and shows once there's a pin
+			let mesh = meshes.add(Rectangle::new(1.0, 1.0));
+			let material = materials.add(StandardMaterial {
+				base_color: Color::srgb(0.75, 0.7, 0.65),
+				normal_map_texture: Some(assets.load("ground_texture/normal.jpg")),
+				..default()
+			});
+			cmd.spawn((
+				SceneOwned(SceneMode::Compare),
+				PinnedSurface,
+				TerrainSurface,
+				PbrBundle {
+					mesh,
+					material,
+					visibility: Visibility::Hidden,
+					..default()
+				},
+			));
+		},
+		SceneMode::Infinite => {
+			let material = materials.add(StandardMaterial {
+				base_color: Color::srgb(0.55, 0.6, 0.45),
+				normal_map_texture: Some(assets.load("ground_texture/normal.jpg")),
+				..default()
+			});
+			// `infinite::update_infinite_terrain` generates and spawns the tiles
+			cmd.insert_resource(InfiniteTerrain::new(material));
+		},
+	}
+}
+
+/// Builds the pinned output's terrain of `SceneMode::Compare` whenever the pin or the terrain
+/// scale changed, beside the live terrain, and hides it while nothing is pinned.
+pub fn sync_pinned_terrain(
+	pinned: Res<PinnedNoiseOutput>,
+	uiState: Res<UiState>,
+	mut surface: Query<(&Handle<Mesh>, &mut Transform, &mut Visibility), With<PinnedSurface>>,
+	mut meshes: ResMut<Assets<Mesh>>,
+	mut built: Local<Option<TerrainScale>>,
+) {
+	let Ok((handle, mut transform, mut visibility)) = surface.get_single_mut() else {
+		*built = None;
+		return;
+	};
+	let scale = uiState.terrain_scale();
+	if !pinned.is_changed() && *built == Some(scale) {
+		return;
+	}
+	*built = Some(scale);
+	transform.translation.x = scale.extent * (1.0 + compareGap);
+	let output = pinned.output.as_ref().filter(|pin| mesh_allowed(pin.size(), &uiState));
+	let (Some(output), Some(mesh)) = (output, meshes.get_mut(handle)) else {
+		*visibility = Visibility::Hidden;
+		return;
+	};
+	output.update_mesh(mesh, scale);
+	*visibility = Visibility::Inherited;
+}
+
+/// Applies `ViewSettings` to the terrain and water entities, whichever scene spawned them.
+/// Flat shading splits the indexed chunk meshes `TerrainChunks::update` builds into separate
+/// triangles whenever they've been rebuilt, and rebuilding them smooth is how it's turned off.
+pub fn apply_view_settings(
+	mut cmd: Commands,
+	settings: Res<ViewSettings>,
+	surfaces: Query<(Entity, Has<Wireframe>), With<TerrainSurface>>,
+	mut water: Query<&mut Visibility, With<WaterPlane>>,
+	terrain: Option<ResMut<TerrainChunks>>,
+	mut meshes: ResMut<Assets<Mesh>>,
+	uiState: Res<UiState>,
+	noiseOutput: Option<Res<NoiseOutput>>,
+	mut wasFlat: Local<bool>,
+	mut refusedFlat: Local<bool>,
+) {
+	for (ent, wireframe) in &surfaces {
+		if settings.wireframe && !wireframe {
+			cmd.entity(ent).insert(Wireframe);
+		} else if !settings.wireframe && wireframe {
+			cmd.entity(ent).remove::<Wireframe>();
+		}
+	}
+	let wanted = if settings.water { Visibility::Inherited } else { Visibility::Hidden };
+	for mut visibility in &mut water {
+		if *visibility != wanted {
+			*visibility = wanted;
+		}
+	}
+
+	let Some(mut terrain) = terrain else {
+		return;
+	};
+	let turnedOff = std::mem::replace(&mut *wasFlat, settings.flatShading) && !settings.flatShading;
+	// split up, the meshes can outgrow `MeshEstimate::hardLimit` even if the indexed ones don't
+	let flatAllowed = noiseOutput.as_ref().map_or(true, |output| {
+		let estimate = MeshEstimate::new(output.size()).flat();
+		estimate.bytes <= MeshEstimate::hardLimit || uiState.allowHugeMesh
+	});
+	let refused = settings.flatShading && !flatAllowed;
+	// refused last frame too, the meshes are still smooth
+	let wasRefused = std::mem::replace(&mut *refusedFlat, refused);
+	if refused && !wasRefused {
+		warn!("not flat shading the terrain, confirm its size in the toolbar");
+	}
+	if settings.flatShading && !refused {
+		// `get_mut` alone would have every mesh uploaded again
+		let indexed = |handle: &&Handle<Mesh>| {
+			meshes.get(*handle).is_some_and(|mesh| mesh.indices().is_some())
+		};
+		let indexed: Vec<_> = terrain.meshes().filter(indexed).cloned().collect();
+		for handle in indexed {
+			let mesh = meshes.get_mut(&handle).unwrap();
+			mesh.duplicate_vertices();
+			mesh.compute_flat_normals();
+			if let Err(err) = mesh.generate_tangents() {
+				warn!("failed to generate tangents for the flat shaded terrain: {err}");
+			}
+		}
+	} else if turnedOff && !wasRefused {
+		match noiseOutput {
+			Some(output) if mesh_allowed(output.size(), &uiState) => {
+				terrain.update(&output, uiState.terrain_scale(), &mut meshes);
+			},
+			_ => {},
+		}
+	}
+}
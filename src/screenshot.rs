@@ -0,0 +1,367 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::Context;
+use bevy::prelude::*;
+use bevy::render::render_asset::RenderAssets;
+use bevy::render::render_resource::TextureFormat;
+use bevy::render::renderer::{RenderDevice, RenderQueue};
+use bevy::render::texture::GpuImage;
+use bevy::render::{Render, RenderApp, RenderSet};
+use bevy::tasks::futures_lite::future;
+use bevy::tasks::{block_on, AsyncComputeTaskPool, Task};
+use bevy_egui::egui;
+use crossbeam_channel::{Receiver, Sender};
+use serde::Serialize;
+
+use crate::log_panel::{LogKind, SessionLog, Severity};
+use crate::{input, AResult, NoiseOutput, Tab, UiState, Viewport2D, Viewport3D};
+
+/// Directory screenshots are saved to, created with the first one.
+const screenshotsDir: &str = "screenshots";
+
+/// Pixels read back from a render target.
+pub struct Pixels {
+	pub width: u32,
+	pub height: u32,
+	/// RGBA rows without padding, top row first.
+	pub rgba: Vec<u8>,
+}
+
+/// Sidecar saved next to a screenshot, with what it takes to generate the shown output again.
+#[derive(Clone, Debug, Serialize)]
+pub struct ScreenshotInfo {
+	/// Tab the screenshot was taken of, "2D" or "3D".
+	pub view: &'static str,
+	/// Script of the active layer, relative to the scripts directory.
+	pub script: Option<String>,
+	/// Hash of the script the output was generated from, see `content_hash`.
+	pub contentHash: Option<String>,
+	/// Seed offset the output was generated with, see `UiState::seed`.
+	pub seed: Option<i64>,
+	/// Slider values passed to the script.
+	pub params: BTreeMap<String, f64>,
+	/// Samples per row and rows of the output.
+	pub outputSize: Option<(usize, usize)>,
+	/// Height the 3D view scales the samples to.
+	pub meshHeight: f32,
+	pub generationSeconds: Option<f64>,
+	/// Time the screenshot was taken, in seconds since the Unix epoch.
+	pub timestamp: f64,
+}
+
+impl ScreenshotInfo {
+	fn new(tab: Tab, uiState: &UiState, output: Option<&NoiseOutput>) -> Self {
+		Self {
+			view: match tab {
+				Tab::D2 => "2D",
+				Tab::D3 => "3D",
+				Tab::Both => unreachable!("screenshots are of a single pane"),
+			},
+			script: uiState.selected().map(|path| path.display.clone()),
+			contentHash: output
+				.and_then(|output| output.contentHash)
+				.map(|hash| format!("{hash:016x}")),
+			seed: output.map(|output| output.seed),
+			params: output.map_or_else(default, |output| output.paramInputs.clone()),
+			outputSize: output.map(NoiseOutput::size),
+			meshHeight: uiState.height,
+			generationSeconds: output
+				.and_then(|output| output.elapsed)
+				.map(|elapsed| elapsed.as_secs_f64()),
+			timestamp: SystemTime::now()
+				.duration_since(UNIX_EPOCH)
+				.map_or(0.0, |time| time.as_secs_f64()),
+		}
+	}
+
+	/// Path of the screenshot without extension, named after the time and view so screenshots
+	/// sort in the order they were taken.
+	fn stem(&self) -> PathBuf {
+		let time = Duration::from_secs_f64(self.timestamp);
+		let name = format!("{}_{}", timestamp(time), self.view.to_lowercase());
+		Path::new(screenshotsDir).join(name)
+	}
+}
+
+/// `YYYY-MM-DD_HH-MM-SS-mmm` in UTC, for `time` since the Unix epoch.
+pub fn timestamp(time: Duration) -> String {
+	let secs = time.as_secs();
+	let (days, secs) = ((secs / 86400) as i64, secs % 86400);
+	// civil date of a day number, from Howard Hinnant's date algorithms
+	let z = days + 719468;
+	let (era, dayOfEra) = (z.div_euclid(146097), z.rem_euclid(146097));
+	let yearOfEra = (dayOfEra - dayOfEra / 1460 + dayOfEra / 36524 - dayOfEra / 146096) / 365;
+	let dayOfYear = dayOfEra - (365 * yearOfEra + yearOfEra / 4 - yearOfEra / 100);
+	let shiftedMonth = (5 * dayOfYear + 2) / 153;
+	let day = dayOfYear - (153 * shiftedMonth + 2) / 5 + 1;
+	let month = if shiftedMonth < 10 { shiftedMonth + 3 } else { shiftedMonth - 9 };
+	let year = yearOfEra + era * 400 + (month <= 2) as i64;
+	let (hours, minutes, secs) = (secs / 3600, secs / 60 % 60, secs % 60);
+	let millis = time.subsec_millis();
+	format!("{year:04}-{month:02}-{day:02}_{hours:02}-{minutes:02}-{secs:02}-{millis:03}")
+}
+
+/// Writes `pixels` as a PNG and `info` as a JSON sidecar next to it, returning the PNG's path.
+pub fn save(pixels: Pixels, info: &ScreenshotInfo) -> AResult<PathBuf> {
+	std::fs::create_dir_all(screenshotsDir).with_context(|| format!("creating {screenshotsDir}"))?;
+	let stem = info.stem();
+	let path = stem.with_extension("png");
+	image::RgbaImage::from_raw(pixels.width, pixels.height, pixels.rgba)
+		.context("pixel count doesn't match the size")?
+		.save_with_format(&path, image::ImageFormat::Png)
+		.with_context(|| format!("writing {}", path.display()))?;
+	let sidecar = stem.with_extension("json");
+	let json = serde_json::to_string_pretty(info)?;
+	std::fs::write(&sidecar, json).with_context(|| format!("writing {}", sidecar.display()))?;
+	Ok(path)
+}
+
+/// Screenshots of the viewports, taken with F12 or the toolbar button. The viewports are render
+/// targets rather than the window, so the render world reads the shown one back for the main world
+/// to save.
+#[derive(Resource)]
+pub struct Screenshots {
+	/// Set by the toolbar button, taken on the next update like F12.
+	pub requested: bool,
+	/// Render target for the render world to read back after its next frame.
+	queue: Arc<Mutex<Option<AssetId<Image>>>>,
+	results: Receiver<Result<Pixels, String>>,
+	/// Whether a render world reads the queue, which headless apps lack.
+	readback: bool,
+	/// Sidecar of the screenshot being read back.
+	capturing: Option<ScreenshotInfo>,
+	task: Option<Task<AResult<PathBuf>>>,
+	/// Outcome of the last screenshot, and whether it failed.
+	message: Option<(String, bool)>,
+}
+
+impl Screenshots {
+	/// Queues a readback of `image`, unless a screenshot is still in progress.
+	fn capture(&mut self, image: AssetId<Image>, info: ScreenshotInfo) -> Result<(), String> {
+		if !self.readback {
+			return Err("there's no renderer to read the viewport back from".into());
+		}
+		if self.capturing.is_some() || self.task.is_some() {
+			return Err("the previous screenshot is still being saved".into());
+		}
+		*self.queue.lock().unwrap() = Some(image);
+		self.capturing = Some(info);
+		Ok(())
+	}
+
+	fn fail(&mut self, log: &SessionLog, err: String) {
+		log.record(Severity::Error, LogKind::Export, None, format!("screenshot failed: {err}"));
+		self.message = Some((err, true));
+	}
+}
+
+/// Render world end of `Screenshots`.
+#[derive(Resource)]
+struct ReadbackQueue {
+	queue: Arc<Mutex<Option<AssetId<Image>>>>,
+	results: Sender<Result<Pixels, String>>,
+}
+
+/// Inserts `Screenshots` and the render world system reading its targets back.
+pub fn add_readback(app: &mut App) {
+	let queue = Arc::new(Mutex::new(None));
+	let (sender, receiver) = crossbeam_channel::unbounded();
+	let renderApp = app.get_sub_app_mut(RenderApp);
+	let readback = renderApp.is_some();
+	if let Some(renderApp) = renderApp {
+		renderApp.insert_resource(ReadbackQueue {
+			queue: queue.clone(),
+			results: sender,
+		});
+		renderApp.add_systems(
+			Render,
+			read_back.after(RenderSet::Render).before(RenderSet::Cleanup),
+		);
+	}
+	app.insert_resource(Screenshots {
+		requested: false,
+		queue,
+		results: receiver,
+		readback,
+		capturing: None,
+		task: None,
+		message: None,
+	});
+}
+
+/// Reads back the queued render target once this frame is rendered. Waits for the GPU, stalling
+/// the frame, which is fine for a single screenshot.
+fn read_back(
+	readback: Res<ReadbackQueue>,
+	images: Res<RenderAssets<GpuImage>>,
+	device: Res<RenderDevice>,
+	queue: Res<RenderQueue>,
+) {
+	let Some(image) = readback.queue.lock().unwrap().take() else {
+		return;
+	};
+	let res = match images.get(image) {
+		Some(image) => read_image(image, &device, &queue),
+		None => Err("the viewport isn't on the GPU yet".into()),
+	};
+	let _ = readback.results.send(res);
+}
+
+fn read_image(
+	image: &GpuImage,
+	device: &RenderDevice,
+	queue: &RenderQueue,
+) -> Result<Pixels, String> {
+	if !image.texture.usage().contains(wgpu::TextureUsages::COPY_SRC) {
+		return Err("the viewport can't be copied from".into());
+	}
+	let bgra = match image.texture_format {
+		TextureFormat::Rgba8Unorm | TextureFormat::Rgba8UnormSrgb => false,
+		TextureFormat::Bgra8Unorm | TextureFormat::Bgra8UnormSrgb => true,
+		format => return Err(format!("reading back {format:?} viewports is unsupported")),
+	};
+	let (width, height) = (image.size.x, image.size.y);
+	// copied rows have to start at multiples of the alignment
+	let rowBytes = (width * 4).next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT);
+	let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+		label: Some("screenshot readback"),
+		size: rowBytes as u64 * height as u64,
+		usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+		mapped_at_creation: false,
+	});
+	let mut encoder = device.create_command_encoder(&default());
+	encoder.copy_texture_to_buffer(
+		image.texture.as_image_copy(),
+		wgpu::ImageCopyBuffer {
+			buffer: &buffer,
+			layout: wgpu::ImageDataLayout {
+				offset: 0,
+				bytes_per_row: Some(rowBytes),
+				rows_per_image: None,
+			},
+		},
+		wgpu::Extent3d {
+			width,
+			height,
+			depth_or_array_layers: 1,
+		},
+	);
+	queue.submit([encoder.finish()]);
+
+	let slice = buffer.slice(..);
+	let (sender, receiver) = crossbeam_channel::bounded(1);
+	slice.map_async(wgpu::MapMode::Read, move |res| {
+		let _ = sender.send(res);
+	});
+	device.poll(wgpu::Maintain::Wait);
+	match receiver.recv() {
+		Ok(Ok(())) => {},
+		Ok(Err(err)) => return Err(format!("viewport readback failed: {err}")),
+		Err(_) => return Err("viewport readback never completed".into()),
+	}
+	let rgba = unpad_rows(&slice.get_mapped_range(), width as usize, rowBytes as usize, bgra);
+	buffer.unmap();
+	Ok(Pixels {
+		width,
+		height,
+		rgba,
+	})
+}
+
+/// RGBA pixels of rows `rowBytes` apart in `bytes`, swapping red and blue for `bgra` textures.
+pub fn unpad_rows(bytes: &[u8], width: usize, rowBytes: usize, bgra: bool) -> Vec<u8> {
+	bytes
+		.chunks_exact(rowBytes)
+		.flat_map(|row| row[.. width * 4].chunks_exact(4))
+		.flat_map(|pixel| {
+			if bgra {
+				[pixel[2], pixel[1], pixel[0], pixel[3]]
+			} else {
+				[pixel[0], pixel[1], pixel[2], pixel[3]]
+			}
+		})
+		.collect()
+}
+
+/// Takes a screenshot of the shown viewport, or the focused one while both are, on F12 or the
+/// toolbar button, and saves it in the background once it's read back.
+pub fn screenshots(
+	keyboard: Res<ButtonInput<KeyCode>>,
+	mut screenshots: ResMut<Screenshots>,
+	input: Res<input::ViewportInput>,
+	viewport2d: Res<Viewport2D>,
+	viewport3d: Res<Viewport3D>,
+	uiState: Res<UiState>,
+	noiseOutput: Option<Res<NoiseOutput>>,
+	log: Res<SessionLog>,
+) {
+	let screenshots = &mut *screenshots;
+	let requested = std::mem::take(&mut screenshots.requested);
+	if requested || keyboard.just_pressed(KeyCode::F12) {
+		let pane = input.focused();
+		let image = match pane {
+			Tab::D2 => &viewport2d.bevyImage,
+			_ => &viewport3d.bevyImage,
+		};
+		let info = ScreenshotInfo::new(pane, &uiState, noiseOutput.as_deref());
+		if let Err(err) = screenshots.capture(image.id(), info) {
+			screenshots.fail(&log, err);
+		}
+	}
+
+	if let Ok(res) = screenshots.results.try_recv() {
+		match (res, screenshots.capturing.take()) {
+			(Ok(pixels), Some(info)) => {
+				let task = AsyncComputeTaskPool::get().spawn(async move { save(pixels, &info) });
+				screenshots.task = Some(task);
+			},
+			(Err(err), _) => screenshots.fail(&log, err),
+			// nothing is captured without a sidecar waiting for it
+			(Ok(_), None) => {},
+		}
+	}
+
+	let finished = screenshots
+		.task
+		.as_mut()
+		.and_then(|task| block_on(future::poll_once(task)));
+	if let Some(res) = finished {
+		screenshots.task = None;
+		match res {
+			Ok(path) => {
+				let message = format!("saved screenshot {}", path.display());
+				log.record(Severity::Info, LogKind::Export, None, message.clone());
+				screenshots.message = Some((message, false));
+			},
+			Err(err) => screenshots.fail(&log, format!("{err:#}")),
+		}
+	}
+}
+
+/// Toolbar button taking a screenshot, and the outcome of the last one.
+pub fn screenshot_ui(ui: &mut egui::Ui, screenshots: &mut Screenshots) {
+	let busy = screenshots.capturing.is_some() || screenshots.task.is_some();
+	if ui
+		.add_enabled(!busy, egui::Button::new("📷"))
+		.on_hover_text(format!("Save the shown view to {screenshotsDir}/, with a sidecar (F12)"))
+		.clicked()
+	{
+		screenshots.requested = true;
+	}
+	if busy {
+		ui.spinner();
+	}
+	match &screenshots.message {
+		Some((message, true)) => {
+			let color = ui.visuals().error_fg_color;
+			ui.colored_label(color, "screenshot failed").on_hover_text(message);
+		},
+		Some((message, false)) => {
+			ui.weak("saved").on_hover_text(message);
+		},
+		None => {},
+	}
+}
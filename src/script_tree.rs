@@ -0,0 +1,92 @@
+use std::collections::BTreeMap;
+
+use bevy_egui::egui::load::SizedTexture;
+use bevy_egui::egui::{self, ImageSource};
+
+use crate::{lua, thumbnails, InternedPath};
+
+/// Scripts grouped by the folders of their `InternedPath::display`, for the script menu.
+#[derive(Default)]
+pub struct ScriptTree<'a> {
+	folders: BTreeMap<&'a str, ScriptTree<'a>>,
+	/// File name, path and code, sorted by file name.
+	scripts: Vec<(&'a str, &'a InternedPath, &'a str)>,
+}
+
+impl<'a> ScriptTree<'a> {
+	pub fn new(scripts: impl IntoIterator<Item = (&'a InternedPath, &'a String)>) -> Self {
+		let mut tree = Self::default();
+		for (path, code) in scripts {
+			let mut node = &mut tree;
+			let mut components = path.display.split('/').peekable();
+			while let Some(component) = components.next() {
+				if components.peek().is_none() {
+					node.scripts.push((component, path, code));
+				} else {
+					node = node.folders.entry(component).or_default();
+				}
+			}
+		}
+		tree.sort();
+		tree
+	}
+
+	fn sort(&mut self) {
+		self.scripts.sort_by_key(|&(name, ..)| name);
+		self.folders.values_mut().for_each(Self::sort);
+	}
+
+	fn contains(&self, path: &InternedPath) -> bool {
+		self.scripts.iter().any(|&(_, script, _)| script == path) ||
+			self.folders.values().any(|folder| folder.contains(path))
+	}
+
+	/// Folders as collapsible sections, opened if they hold the selection, then the scripts with
+	/// their thumbnail on hover, rendered with the script's `inputs`.
+	pub fn ui(
+		&self,
+		ui: &mut egui::Ui,
+		selected: &mut Option<InternedPath>,
+		thumbnails: &mut thumbnails::Thumbnails,
+		inputs: &dyn Fn(&InternedPath) -> lua::ScriptInputs,
+	) {
+		self.folder_ui(ui, "", selected, thumbnails, inputs);
+	}
+
+	fn folder_ui(
+		&self,
+		ui: &mut egui::Ui,
+		prefix: &str,
+		selected: &mut Option<InternedPath>,
+		thumbnails: &mut thumbnails::Thumbnails,
+		inputs: &dyn Fn(&InternedPath) -> lua::ScriptInputs,
+	) {
+		for (name, folder) in &self.folders {
+			let path = format!("{prefix}{name}/");
+			let open = selected.as_ref().is_some_and(|script| folder.contains(script));
+			// keyed by the full path, so same-named folders don't share their open state
+			egui::CollapsingHeader::new(format!("{name}/"))
+				.id_source(("scriptFolder", &path))
+				.default_open(open)
+				.show(ui, |ui| folder.folder_ui(ui, &path, selected, thumbnails, inputs));
+		}
+		for &(name, path, code) in &self.scripts {
+			ui.selectable_value(selected, Some(path.clone()), name)
+				.on_hover_ui(|ui| {
+					ui.label(&path.display);
+					match thumbnails.request(path, code, &inputs(path)) {
+						Some(texture) => {
+							let size = egui::Vec2::splat(thumbnails::thumbnailSize as f32 * 2.0);
+							ui.image(ImageSource::Texture(SizedTexture::new(texture, size)));
+						},
+						None if thumbnails.failed(path) => {
+							ui.label("no preview");
+						},
+						None => {
+							ui.spinner();
+						},
+					}
+				});
+		}
+	}
+}
@@ -0,0 +1,3933 @@
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use bevy::app::AppExit;
+use bevy::color::ColorToPacked;
+use bevy::input::mouse::{MouseMotion, MouseScrollUnit, MouseWheel};
+use bevy::input::ButtonInput;
+use bevy::math::{dvec2, dvec3, uvec2, vec2, vec3, DVec2, DVec3, Ray3d, Vec2, Vec3};
+use bevy::prelude::{
+	default, App, AssetApp, AssetPlugin, Assets, Camera3d, Entity, GlobalTransform, Handle, Image,
+	IntoSystemConfigs, KeyCode, Mesh, MinimalPlugins, MouseButton, Resource, StandardMaterial,
+	Transform, Update,
+};
+use bevy::render::mesh::{Indices, PrimitiveTopology, VertexAttributeValues};
+use bevy::render::render_asset::RenderAssetUsages;
+use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
+use bevy::tasks::{block_on, AsyncComputeTaskPool, TaskPool};
+use bevy::utils::HashMap;
+
+use crate::{
+	bake,
+	bookmarks::{self, Bookmark, CameraBookmarks},
+	chunks,
+	coalesce_requests,
+	compile_script,
+	content_hash,
+	colormap::{self, Colormap, ColormapKind},
+	coloring,
+	debounce::{self, RegenDebounce},
+	diff,
+	domain::{self, Domain},
+	erosion,
+	export,
+	fnl,
+	generate_colored_cancellable,
+	generate_samples,
+	generate_samples_cancellable,
+	gpu,
+	gradients,
+	graph,
+	infinite,
+	inspect,
+	input::{self, ViewportInput},
+	lighting::{LightingSettings, ShadowQuality},
+	lod,
+	log_panel::{self, LogKind, Severity},
+	lua,
+	memory,
+	noise_rng,
+	panes::PaneLayout,
+	persist,
+	pin,
+	presets,
+	preview_diameters,
+	probe::{self, ProbePoint},
+	reference,
+	sample_position,
+	scatter,
+	scene,
+	scan_scripts,
+	screenshot,
+	scriptsDir,
+	sequence,
+	session::{self, SessionRecorder},
+	stats,
+	subsample_offsets,
+	textures,
+	thumbnails,
+	walk,
+	water::{WaterMaterial, WaterSettings, WaterUniform},
+	workers,
+	CoalescedRequests,
+	Coords,
+	GridSpec,
+	Heightmaps,
+	InternedPath,
+	MeshEstimate,
+	NoiseGenRequest,
+	NoiseOutput,
+	NonFiniteSample,
+	SampleGrid,
+	Tab,
+	TerrainScale,
+	UiState,
+	ViewportRect,
+};
+
+/// Files the app loads at runtime; a missing one means a broken install.
+const requiredAssets: &[&str] = &[
+	"assets/skybox/clouds.jpg",
+	"assets/ground_texture/normal.jpg",
+	"assets/NotoSansMono.ttf",
+	"assets/shaders/water.wgsl",
+];
+
+/// Positions every node check is evaluated at. Includes negative and large coordinates to catch
+/// platform-specific numeric issues away from the origin.
+const probePositions: &[(f64, f64)] = &[
+	(0.0, 0.0),
+	(0.5, 0.25),
+	(-3.75, 12.5),
+	(1234.5, -987.25),
+];
+
+enum Expect {
+	/// Every probe must evaluate to exactly this value.
+	Exact(f32),
+	/// Every probe must be finite and within the inclusive range.
+	Range(f32, f32),
+	/// Construction must fail with a Lua error.
+	Error,
+}
+
+/// One script per built-in node kind, exercising both the Lua constructors and `Noise::eval`.
+const nodeChecks: &[(&str, &str, Expect)] = &[
+	("const", "return Noise.const(0.5)", Expect::Exact(0.5)),
+	("simplex", "return Noise.simplex(1)", Expect::Range(-1.0, 1.0)),
+	("simplexFast", "return Noise.simplexFast(1)", Expect::Range(-1.0, 1.0)),
+	("simplex3", "return Noise.simplex3(1):translate(0, 0, 0.5)", Expect::Range(-1.0, 1.0)),
+	("simplexTileable", "return Noise.simplexTileable(1, 3, 5)", Expect::Range(-1.0, 1.0)),
+	("simplexLooped", "return Noise.simplexLooped(1, 4, 1.5)", Expect::Range(-1.0, 1.0)),
+	("sinefield", "return Noise.sinefield(2, 0.5)", Expect::Range(-1.0, 1.0)),
+	("worley", "return Noise.worley(1)", Expect::Range(0.0, std::f32::consts::SQRT_2)),
+	(
+		"worley manhattan F2",
+		"return Noise.worley(1, { distance = 'manhattan', returnType = 'F2' })",
+		Expect::Range(0.0, 3.0),
+	),
+	(
+		"worley chebyshev F2-F1",
+		"return Noise.worley(1, { distance = 'chebyshev', returnType = 'F2-F1' })",
+		Expect::Range(0.0, 2.0),
+	),
+	("worley bad option", "return Noise.worley(1, { distance = 'taxicab' })", Expect::Error),
+	("cellId", "return Noise.cellId(1)", Expect::Range(-1.0, 1.0)),
+	("cellId grid", "return Noise.cellId(1, 0)", Expect::Range(-1.0, 1.0)),
+	("cellId bad jitter", "return Noise.cellId(1, 1.5)", Expect::Error),
+	("white", "return Noise.white(1)", Expect::Range(-1.0, 1.0)),
+	("value", "return Noise.value(1):scale(8)", Expect::Range(-1.0, 1.0)),
+	("perlin", "return Noise.perlin(1):scale(8)", Expect::Range(-1.0, 1.0)),
+	("perlin octaves", "return Noise.perlin(2):scale(4):octaves(3)", Expect::Range(-1.75, 1.75)),
+	("gradientX", "return Noise.gradientX()", Expect::Range(-3.75, 1234.5)),
+	("time", "return Noise.time() + 1", Expect::Exact(1.0)),
+	("gradientY", "return Noise.gradientY()", Expect::Range(-987.25, 12.5)),
+	("radial", "return Noise.radial(0.5, 0.25)", Expect::Range(0.0, 1600.0)),
+	("radial bad center", "return Noise.radial(0/0, 1)", Expect::Error),
+	("checker", "return Noise.checker(0.5)", Expect::Range(-1.0, 1.0)),
+	("checker bad size", "return Noise.checker(0)", Expect::Error),
+	("image missing", "return Noise.image('assets/missing.png')", Expect::Error),
+	("image bad outside", "return Noise.image('assets/missing.png', 'mirror')", Expect::Error),
+	("octaves", "return Noise.const(1):octaves(3)", Expect::Exact(1.75)),
+	("octaves per-axis", "return Noise.const(1):octaves(3, 0.5, 2, 3)", Expect::Exact(1.75)),
+	(
+		"octaves options",
+		"return Noise.const(1):octaves(3, { seedOffset = 7, rotation = 30, lacunarityJitter = 0.2 \
+		 })",
+		Expect::Exact(1.75),
+	),
+	(
+		"octaves varied simplex",
+		"return Noise.simplex(1):scale(4):octaves(4, { seedOffset = 1, rotation = 37 })",
+		Expect::Range(-1.875, 1.875),
+	),
+	("octaves bad option", "return Noise.const(1):octaves(3, { lacunarity = 2 })", Expect::Error),
+	(
+		"octaves bad jitter",
+		"return Noise.const(1):octaves(3, { lacunarityJitter = 1 })",
+		Expect::Error,
+	),
+	(
+		"octaves bad seed offset",
+		"return Noise.const(1):octaves(3, { seedOffset = 0.5 })",
+		Expect::Error,
+	),
+	("ridged", "return Noise.const(0.25):ridged(2)", Expect::Exact(0.75)),
+	("ridged weighted", "return Noise.const(0.25):ridged(2, 0.5, 2, 1)", Expect::Exact(0.6875)),
+	("ridged simplex", "return Noise.simplex(1):ridged(4, 0.5, 2, 2)", Expect::Range(-1.875, 1.875)),
+	("ridged bad gain", "return Noise.simplex(1):ridged(4, 0.5, 2, 0/0)", Expect::Error),
+	("billow", "return Noise.const(0.25):billow(2)", Expect::Exact(-0.75)),
+	("billow simplex", "return Noise.simplex(1):billow(4)", Expect::Range(-1.875, 1.875)),
+	(
+		"curve",
+		"return Noise.const(0):curve({{-1, -1}, {0, -0.2}, {0.6, 0.1}, {1, 1}})",
+		Expect::Exact(-0.2),
+	),
+	("curve beyond ends", "return Noise.const(3):curve({{-1, -1}, {1, 0.5}})", Expect::Exact(0.5)),
+	("curve unsorted", "return Noise.const(0):curve({{0, 0}, {-1, 1}})", Expect::Error),
+	("curve one point", "return Noise.const(0):curve({{0, 0}})", Expect::Error),
+	("terrace", "return Noise.const(0.1):terrace(3)", Expect::Exact(0.0)),
+	("terrace simplex", "return Noise.simplex(1):terrace(4, 0.5)", Expect::Range(-1.0, 1.0)),
+	("terrace bad smoothness", "return Noise.simplex(1):terrace(4, 2)", Expect::Error),
+	("select below", "return Noise.const(-1):select(2, 3, 0, 0.5)", Expect::Exact(2.0)),
+	("select above", "return Noise.const(1):select(2, 3, 0, 0.5)", Expect::Exact(3.0)),
+	("select blend", "return Noise.const(0):select(2, 3, 0, 0.5)", Expect::Exact(2.5)),
+	("select hard edge", "return Noise.const(0):select(2, 3)", Expect::Exact(3.0)),
+	(
+		"select noise",
+		"return Noise.simplex(1):select(Noise.simplex(2), -1, 0.2, 0.1)",
+		Expect::Range(-1.0, 1.0),
+	),
+	("select bad falloff", "return Noise.simplex(1):select(0, 1, 0, -1)", Expect::Error),
+	("gradient const", "return Noise.const(2):gradientMagnitude(0.01)", Expect::Exact(0.0)),
+	("gradient zero epsilon", "return Noise.simplex(1):gradientMagnitude(0)", Expect::Error),
+	("add", "return Noise.const(1) + 2", Expect::Exact(3.0)),
+	("sub", "return Noise.const(1) - 2", Expect::Exact(-1.0)),
+	("mul", "return Noise.const(3) * 2", Expect::Exact(6.0)),
+	("mul skip zero", "return Noise.const(0) * Noise.simplex(1)", Expect::Exact(0.0)),
+	("div", "return Noise.const(3) / 2", Expect::Exact(1.5)),
+	("pow", "return Noise.const(3) ^ 2", Expect::Exact(9.0)),
+	("mod", "return Noise.const(5) % 3", Expect::Exact(2.0)),
+	("number add", "return 2 + Noise.const(1)", Expect::Exact(3.0)),
+	("number sub", "return 2 - Noise.const(0.5)", Expect::Exact(1.5)),
+	("number div", "return 3 / Noise.const(2)", Expect::Exact(1.5)),
+	("number pow", "return 2 ^ Noise.const(3)", Expect::Exact(8.0)),
+	("number mod", "return 5 % Noise.const(3)", Expect::Exact(2.0)),
+	("unary minus", "return -Noise.const(0.5)", Expect::Exact(-0.5)),
+	("non-numeric operand", "return 'a' - Noise.const(1)", Expect::Error),
+	(
+		"tostring",
+		"return Noise.const(tostring(2 - Noise.simplex(1))\
+		 :find('Sub\\n  Const%(2%)\\n  Simplex%(1%)') or 0)",
+		Expect::Exact(1.0),
+	),
+	("remEuclid", "return Noise.const(-1):remEuclid(3)", Expect::Exact(2.0)),
+	("signedPow", "return Noise.const(-3):signedPow(2)", Expect::Exact(-9.0)),
+	("floor", "return Noise.const(1.5):floor()", Expect::Exact(1.0)),
+	("ceil", "return Noise.const(1.5):ceil()", Expect::Exact(2.0)),
+	("abs", "return Noise.const(-1.5):abs()", Expect::Exact(1.5)),
+	("min", "return Noise.const(1):min(-1)", Expect::Exact(-1.0)),
+	("max", "return Noise.const(1):max(-1)", Expect::Exact(1.0)),
+	("clamp", "return Noise.const(4):clamp(-1, 1)", Expect::Exact(1.0)),
+	("toUnsignedUnit", "return Noise.const(0):toUnsignedUnit()", Expect::Exact(0.5)),
+	("toSignedUnit", "return Noise.const(0.5):toSignedUnit()", Expect::Exact(0.0)),
+	("lerp", "return Noise.const(2):lerp(6, 0.25)", Expect::Exact(3.0)),
+	("lerp clamped", "return Noise.const(2):lerp(6, 2)", Expect::Exact(6.0)),
+	("lerp unclamped", "return Noise.const(2):lerp(6, 2, false)", Expect::Exact(10.0)),
+	("smoothLerp", "return Noise.const(2):smoothLerp(6, 0.25)", Expect::Exact(2.625)),
+	("translate", "return Noise.simplex(1):translate(10, 20)", Expect::Range(-1.0, 1.0)),
+	("scale", "return Noise.simplex(1):scale(4)", Expect::Range(-1.0, 1.0)),
+	("withRange", "return Noise.const(0.5):withRange(0, 1)", Expect::Exact(0.5)),
+	("range table", "return { noise = Noise.const(0.5), range = {0, 1} }", Expect::Exact(0.5)),
+	("too many octaves", "return Noise.simplex(1):octaves(200)", Expect::Error),
+	("octave overflow", "return Noise.simplex(1):octaves(40, 0.5, 100)", Expect::Error),
+	("tiny scale", "return Noise.simplex(1):scale(1e-300)", Expect::Error),
+	("infinite scale", "return Noise.simplex(1):scale(1 / 0)", Expect::Error),
+	("infinite period", "return Noise.simplexTileable(1, 1 / 0)", Expect::Error),
+	("rotate", "return Noise.simplex(1):rotate(30)", Expect::Range(-1.0, 1.0)),
+	("warp", "return Noise.simplex(1):warp(Noise.simplex(2), nil, 0.5)", Expect::Range(-1.0, 1.0)),
+	(
+		"warp constant",
+		"return Noise.simplex(1):warp(0.25, -2):translate(1)",
+		Expect::Range(-1.0, 1.0),
+	),
+	("warp infinite strength", "return Noise.simplex(1):warp(1, 1, 1 / 0)", Expect::Error),
+	("tiled", "return Noise.simplex(1):octaves(3):tiled(2, 3)", Expect::Range(-1.75, 1.75)),
+	("tiled const", "return Noise.const(0.5):tiled(1)", Expect::Exact(0.5)),
+	("tiled zero period", "return Noise.simplex(1):tiled(0)", Expect::Error),
+	("decorrelate", "return Noise.simplex(1):decorrelate(3)", Expect::Range(-1.0, 1.0)),
+	("cached", "local v = Noise.const(2):cached() return v + v:cached() * v", Expect::Exact(6.0)),
+	("zero scale", "return Noise.simplex(1):scale(0)", Expect::Range(-1.0, 1.0)),
+	("lua function", "return function(x, y) return 0.5 end", Expect::Exact(0.5)),
+	(
+		"lua function math",
+		"return function(x, y, z) return math.sin(x) * math.cos(y) end",
+		Expect::Range(-1.0, 1.0),
+	),
+	(
+		"lua function ranged",
+		"return { noise = function() return 2 end, range = {0, 4} }",
+		Expect::Exact(2.0),
+	),
+	("returned number", "return 0.25", Expect::Exact(0.25)),
+	("returned integer", "return 2", Expect::Exact(2.0)),
+	("returned NaN", "return 0 / 0", Expect::Error),
+	(
+		"returned layers",
+		"return { height = Noise.const(0.5), moisture = Noise.simplex(1) }",
+		Expect::Exact(0.5),
+	),
+	(
+		"returned height function",
+		"return { height = function(x, y) return 1 end }",
+		Expect::Exact(1.0),
+	),
+	("returned height number", "return { height = 3, range = {0, 4} }", Expect::Exact(3.0)),
+	("returned other layers", "return { moisture = Noise.const(1) }", Expect::Error),
+	("returned string", "return 'simplex'", Expect::Error),
+	("returned nothing", "return", Expect::Error),
+];
+
+pub struct CheckResult {
+	pub name: String,
+	pub passed: bool,
+	pub detail: String,
+	pub duration: Duration,
+}
+
+/// Results of the last self-test run started from the UI; `None` hides the dialog.
+#[derive(Resource, Default)]
+pub struct SelfTestReport(pub Option<Vec<CheckResult>>);
+
+/// Runs the whole battery. Expects `AsyncComputeTaskPool` to be initialized.
+pub fn run() -> Vec<CheckResult> {
+	let mut results = vec![];
+	let mut check = |name: String, func: &dyn Fn() -> Result<String, String>| {
+		let start = Instant::now();
+		let res = func();
+		let duration = start.elapsed();
+		let (passed, detail) = match res {
+			Ok(detail) => (true, detail),
+			Err(detail) => (false, detail),
+		};
+		results.push(CheckResult {
+			name,
+			passed,
+			detail,
+			duration,
+		});
+	};
+
+	for (name, code, expect) in nodeChecks {
+		check(format!("node {name}"), &|| check_node(code, expect));
+	}
+	check("determinism".into(), &check_determinism);
+	check("optimizer equivalence".into(), &check_optimizer);
+	check("tileable edges".into(), &check_tileable);
+	check("looped time".into(), &check_looped);
+	check("animation time".into(), &check_time);
+	check("octave anisotropy".into(), &check_anisotropy);
+	check("octave variation".into(), &check_octave_variation);
+	check("precision".into(), &check_precision);
+	check("diff round trip".into(), &check_diff);
+	check("pin diff".into(), &check_pin_diff);
+	check("heightmap export".into(), &check_export);
+	check("RAW export".into(), &check_raw_export);
+	check("resampling".into(), &check_resample);
+	check("rng vectors".into(), &check_rng);
+	check("texture retry".into(), &check_texture_retry);
+	check("decorrelate".into(), &check_decorrelate);
+	check("cached subtrees".into(), &check_cached);
+	check("core sampler".into(), &check_core_sampler);
+	check("worley cells".into(), &check_worley);
+	check("cell ids".into(), &check_cell_id);
+	check("lattice noise".into(), &check_lattice_noise);
+	check("shape built-ins".into(), &check_shapes);
+	check("lerp mask".into(), &check_lerp);
+	check("curves".into(), &check_curves);
+	check("select".into(), &check_select);
+	check("warp".into(), &check_warp);
+	check("tiling".into(), &check_tiling);
+	check("3D slices".into(), &check_slices);
+	check("Seed global".into(), &check_seed_global);
+	check("compiled cache".into(), &check_compiled_cache);
+	check("lua functions".into(), &check_lua_functions);
+	check("returned values".into(), &check_returned_values);
+	check("script params".into(), &check_script_params);
+	check("modules".into(), &check_modules);
+	check("memory estimator".into(), &check_memory);
+	check("AST statistics".into(), &check_ast_stats);
+	for (name, preset, expect) in fnlChecks {
+		check(format!("FNL import {name}"), &|| check_fnl(preset, expect));
+	}
+	check("request coalescing".into(), &check_coalescing);
+	check("regeneration debounce".into(), &check_debounce);
+	check("session log".into(), &check_session_log);
+	check("session replay".into(), &check_session_replay);
+	check("screenshots".into(), &check_screenshots);
+	check("worker limit".into(), &check_worker_limit);
+	check("concurrent generations".into(), &check_concurrent_generations);
+	check("pane layout".into(), &check_pane_layout);
+	check("input routing".into(), &check_input_routing);
+	check("noise graph".into(), &check_noise_graph);
+	check("generation".into(), &check_generation);
+	check("chunked generation".into(), &check_chunks);
+	check("supersampling".into(), &check_supersampling);
+	check("sampled domain".into(), &check_domain);
+	check("coordinates".into(), &check_coords);
+	check("viewport coordinates".into(), &check_viewport_coords);
+	check("GPU backend".into(), &check_gpu);
+	check("erosion".into(), &check_erosion);
+	check("scatter".into(), &check_scatter);
+	check("camera bookmarks".into(), &check_bookmarks);
+	check("walk heights".into(), &check_walk);
+	check("terrain probe".into(), &check_probe);
+	check("preview passes".into(), &check_previews);
+	check("mesh normals".into(), &check_mesh_normals);
+	check("terrain chunks".into(), &check_terrain_chunks);
+	check("mesh estimates".into(), &check_mesh_estimate);
+	check("scene mode switches".into(), &check_scene_modes);
+	check("water texels".into(), &check_water_texels);
+	check("terrain colors".into(), &check_terrain_colors);
+	check("colormaps".into(), &check_colormaps);
+	check("script colors".into(), &check_script_colors);
+	check("gradients".into(), &check_gradients);
+	check("non-finite samples".into(), &check_non_finite);
+	check("lighting".into(), &check_lighting);
+	check("reference images".into(), &check_reference);
+	check("headless bake".into(), &check_bake);
+	check("recorded sweeps".into(), &check_sweep_recording);
+	check("saved state".into(), &check_saved_state);
+	check("presets".into(), &check_presets);
+	check("output stats".into(), &check_stats);
+	check("scripts directory".into(), &check_scripts_dir);
+	check("interned paths".into(), &check_interned_paths);
+	check("thumbnail keys".into(), &check_thumbnail_keys);
+	check("assets".into(), &|| {
+		let missing: Vec<_> = requiredAssets
+			.iter()
+			.filter(|path| !Path::new(path).is_file())
+			.copied()
+			.collect();
+		if missing.is_empty() {
+			Ok(format!("{} files present", requiredAssets.len()))
+		} else {
+			Err(format!("missing: {}", missing.join(", ")))
+		}
+	});
+
+	results
+}
+
+fn check_node(code: &str, expect: &Expect) -> Result<String, String> {
+	let ast = lua::construct_noisegen(code);
+	let ast = match (ast, expect) {
+		(Err(err), Expect::Error) => return Ok(format!("{err:#}")),
+		(Ok(_), Expect::Error) => return Err("constructed without error".into()),
+		(ast, _) => ast.map_err(|err| format!("{err:#}"))?,
+	};
+	for &(x, y) in probePositions {
+		let v = ast.eval(dvec2(x, y));
+		let ok = match *expect {
+			Expect::Exact(expected) => v == expected,
+			Expect::Range(min, max) => v.is_finite() && (min ..= max).contains(&v),
+			Expect::Error => unreachable!(),
+		};
+		if !ok {
+			return Err(format!("got {v} at ({x}, {y})"));
+		}
+	}
+	Ok(String::new())
+}
+
+fn check_determinism() -> Result<String, String> {
+	let ast = lua::construct_noisegen("return Noise.simplex(42):octaves(4) * Noise.sinefield()")
+		.map_err(|err| format!("{err:#}"))?;
+	for &(x, y) in probePositions {
+		let pos = dvec2(x, y);
+		let (a, b) = (ast.eval(pos), ast.eval(pos));
+		if a.to_bits() != b.to_bits() {
+			return Err(format!("{a} != {b} at ({x}, {y})"));
+		}
+	}
+	Ok(String::new())
+}
+
+/// Optimized trees must produce the same bits as the trees scripts built, zeros' signs included,
+/// over masks that are exactly zero in places, collapsing `min`/`max` and one-sided selects, and
+/// must actually skip the masked terrain.
+fn check_optimizer() -> Result<String, String> {
+	let scripts = [
+		include_str!("../assets/scripts/examples/masked_terrain.lua"),
+		// a factor that may be negative or -0 can't be skipped
+		"local mask = Noise.simplex(3):scale(2):clamp(0, 1)
+		return mask * (Noise.simplex(5):scale(3) + 2) + Noise.simplex(6) * mask - mask * 0",
+		"return Noise.simplex(1):min(3) + Noise.simplex(2):max(Noise.simplex(4) - 4)
+			+ Noise.simplex(5):min(Noise.simplex(6))",
+		"return Noise.const(2):select(Noise.simplex(1), Noise.simplex(2), 0.5, 0.25)
+			+ Noise.simplex(3):select(-1, Noise.simplex(4), 0, 0.5)",
+	];
+	for code in scripts {
+		let construct = |optimized: bool| {
+			let res = if optimized {
+				lua::construct_noisegen(code)
+			} else {
+				lua::construct_noisegen_unoptimized(code)
+			};
+			res.map_err(|err| format!("{err:#}"))
+		};
+		let (optimized, original) = (construct(true)?, construct(false)?);
+		let diameter = 48;
+		for i in 0 .. diameter * diameter {
+			let pos = sample_position(i % diameter, i / diameter, diameter) * 3.0 - 1.5;
+			for precision in lua::Precision::all {
+				let a = optimized.eval_with(pos, precision);
+				let b = original.eval_with(pos, precision);
+				if a.to_bits() != b.to_bits() {
+					let script = code.lines().last().unwrap_or_default().trim();
+					let at = format!("at {pos} in {precision:?}");
+					return Err(format!("`{script}` optimizes {b} to {a} {at}"));
+				}
+			}
+		}
+	}
+	let masked = lua::construct_noisegen(scripts[0]).map_err(|err| format!("{err:#}"))?;
+	if !masked.stats().kinds.contains_key("MulSkipZero") {
+		return Err("the masked terrain's mountains aren't skipped".into());
+	}
+	let unskippable = lua::construct_noisegen(scripts[1]).map_err(|err| format!("{err:#}"))?;
+	if unskippable.stats().kinds.get("MulSkipZero") != Some(&1) {
+		return Err("only the strictly positive factor may be skipped".into());
+	}
+	Ok(String::new())
+}
+
+/// Tolerance for periodic nodes, whose period is only exact up to `sin`/`cos` rounding.
+const periodEpsilon: f32 = 1e-5;
+
+fn check_tileable() -> Result<String, String> {
+	let ast = lua::construct_noisegen("return Noise.simplexTileable(3, 2, 4)")
+		.map_err(|err| format!("{err:#}"))?;
+	for i in 0 ..= 16 {
+		let t = i as f64 / 16.0;
+		let pairs = [
+			(dvec2(0.0, t * 4.0), dvec2(2.0, t * 4.0)),
+			(dvec2(t * 2.0, 0.0), dvec2(t * 2.0, 4.0)),
+		];
+		for (a, b) in pairs {
+			let (va, vb) = (ast.eval(a), ast.eval(b));
+			if (va - vb).abs() > periodEpsilon {
+				return Err(format!("{va} at {a} != {vb} at {b}"));
+			}
+		}
+	}
+	Ok(String::new())
+}
+
+/// Looped simplex must follow the animation time, back where it started after a period, at both
+/// precisions, unless a fixed time is given.
+fn check_looped() -> Result<String, String> {
+	let looped = lua::construct_noisegen("return Noise.simplexLooped(3, 2.5)")
+		.map_err(|err| format!("{err:#}"))?;
+	let fixed = lua::construct_noisegen("return Noise.simplexLooped(3, 2.5, 1)")
+		.map_err(|err| format!("{err:#}"))?;
+	if !looped.uses_time() || fixed.uses_time() {
+		return Err("only the loop without a fixed time may depend on the time".into());
+	}
+	let at = |ast: &lua::Noise, pos: DVec2, precision, time| {
+		ast.eval3_with(pos.extend(0.0), precision, &lua::EvalContext { time })
+	};
+	let mut moved = false;
+	for precision in lua::Precision::all {
+		for &(x, y) in probePositions {
+			let pos = dvec2(x, y);
+			let first = at(&looped, pos, precision, 0.0);
+			let last = at(&looped, pos, precision, 2.5);
+			if (first - last).abs() > periodEpsilon {
+				return Err(format!("first frame {first} != last frame {last} at ({x}, {y})"));
+			}
+			moved |= at(&looped, pos, precision, 1.0) != first;
+			if at(&fixed, pos, precision, 0.0) != at(&fixed, pos, precision, 2.0) {
+				return Err(format!("the fixed time loop moved at ({x}, {y})"));
+			}
+		}
+	}
+	if !moved {
+		return Err("the loop stands still over time".into());
+	}
+	Ok(String::new())
+}
+
+/// `Noise.time()` and Lua functions must see the context's time at both precisions, on the GPU
+/// too, and only trees using it may report so.
+fn check_time() -> Result<String, String> {
+	let ctx = lua::EvalContext { time: 2.5 };
+	let scripts = [
+		("return Noise.time() * 2 + Noise.gradientX()", true),
+		("return function(x, y, z, t) return t * 2 + x end", true),
+		("return Noise.simplex(1)", false),
+	];
+	for (code, usesTime) in scripts {
+		let ast = lua::construct_noisegen(code).map_err(|err| format!("{err:#}"))?;
+		if ast.uses_time() != usesTime {
+			return Err(format!("{code}: uses_time() is {}", !usesTime));
+		}
+		if !usesTime {
+			continue;
+		}
+		for precision in lua::Precision::all {
+			let v = ast.eval3_with(dvec3(0.25, 0.0, 0.0), precision, &ctx);
+			if v != 5.25 {
+				return Err(format!("{code}: {v} instead of 5.25 in {precision:?}"));
+			}
+		}
+	}
+
+	let ast = lua::construct_noisegen(scripts[0].0).map_err(|err| format!("{err:#}"))?;
+	match gpu::generate_samples(&ast, &SampleGrid::new(5), 0.0, &ctx) {
+		Ok(output) if output.samples[1] != 5.25 => {
+			Err(format!("GPU sample (1, 0) is {} instead of 5.25", output.samples[1]))
+		},
+		Ok(_) => Ok(String::new()),
+		Err(reason) => Ok(format!("GPU skipped: {reason}")),
+	}
+}
+
+/// Mean squared difference between neighboring samples along each axis, a measure of how much
+/// detail a tree has in either direction.
+fn axis_variation(ast: &lua::Noise) -> DVec2 {
+	const step: f64 = 1.0 / 64.0;
+	let mut sum = DVec2::ZERO;
+	for y in 0 .. 64 {
+		for x in 0 .. 64 {
+			let pos = dvec2(x as f64, y as f64) * step;
+			let v = ast.eval(pos) as f64;
+			let dx = ast.eval(pos + dvec2(step, 0.0)) as f64 - v;
+			let dy = ast.eval(pos + dvec2(0.0, step)) as f64 - v;
+			sum += dvec2(dx * dx, dy * dy);
+		}
+	}
+	sum / 64.0f64.powi(2)
+}
+
+fn check_anisotropy() -> Result<String, String> {
+	for (code, faster) in [
+		("return Noise.sinefield(1):octaves(4, 0.5, 3, 1)", 0),
+		("return Noise.sinefield(1):octaves(4, 0.5, 1, 3)", 1),
+	] {
+		let ast = lua::construct_noisegen(code).map_err(|err| format!("{err:#}"))?;
+		let variation = axis_variation(&ast);
+		let (fast, slow) = (variation[faster], variation[1 - faster]);
+		if !(fast > slow * 2.0) {
+			return Err(format!("`{code}` varies {variation} along x/y"));
+		}
+	}
+	Ok(String::new())
+}
+
+/// Each octave of a varied simplex stack must be the simplex of its own seed, at the position
+/// scaled and rotated by the octaves before it. Jittered lacunarity must be deterministic and
+/// change the result.
+fn check_octave_variation() -> Result<String, String> {
+	let construct = |code: &str| lua::construct_noisegen(code).map_err(|err| format!("{err:#}"));
+	let varied = "return Noise.simplex(3):octaves(2, { seedOffset = 5, rotation = 90 })";
+	let varied = construct(varied)?;
+	let rotation = DVec2::from_angle(90f64.to_radians());
+	let eval = |seed, pos| lua::Noise::Simplex(seed).eval_with(pos, lua::Precision::F64);
+	for &(x, y) in probePositions {
+		let pos = dvec2(x, y);
+		let (first, second) = (eval(3, pos), eval(8, rotation.rotate(pos * 2.0)));
+		let expected = first + 0.5 * second;
+		let actual = varied.eval_with(pos, lua::Precision::F64);
+		// the octaves are summed in f64, the expectation from f32 values
+		if (actual - expected).abs() > 1e-6 {
+			return Err(format!("varied octaves give {actual} at ({x}, {y}), expected {expected}"));
+		}
+	}
+
+	let jittered = "return Noise.simplex(3):octaves(4, { lacunarityJitter = 0.3, jitterSeed = 2 })";
+	let (a, b) = (construct(jittered)?, construct(jittered)?);
+	let plain = construct("return Noise.simplex(3):octaves(4)")?;
+	let mut changed = false;
+	for &(x, y) in probePositions {
+		let pos = dvec2(x, y);
+		if a.eval(pos).to_bits() != b.eval(pos).to_bits() {
+			return Err(format!("lacunarity jitter isn't deterministic at ({x}, {y})"));
+		}
+		changed |= a.eval(pos) != plain.eval(pos);
+	}
+	if !changed {
+		return Err("lacunarity jitter didn't change the octaves".into());
+	}
+	Ok(String::new())
+}
+
+/// A round trip far from the origin must be lossless in f64 and visibly lossy in f32.
+fn check_precision() -> Result<String, String> {
+	let reference = lua::construct_noisegen("return Noise.simplex(5)")
+		.map_err(|err| format!("{err:#}"))?;
+	let far = lua::construct_noisegen("return Noise.simplex(5):translate(-1e6):translate(1e6)")
+		.map_err(|err| format!("{err:#}"))?;
+	let mut maxError = [0f32; 2];
+	for i in 0 .. 64 {
+		let pos = dvec2(i as f64 * 0.0173, i as f64 * 0.0291);
+		let expected = reference.eval_with(pos, lua::Precision::F64);
+		for (j, precision) in lua::Precision::all.into_iter().enumerate() {
+			let error = (far.eval_with(pos, precision) - expected).abs();
+			maxError[j] = maxError[j].max(error);
+		}
+	}
+	let [f32Error, f64Error] = maxError;
+	if f64Error > 1e-6 {
+		return Err(format!("f64 path is off by {f64Error}"));
+	}
+	if f32Error < 1e-3 {
+		return Err(format!("f32 path is off by only {f32Error}, expected visible quantization"));
+	}
+	Ok(format!("max error f32 {f32Error:.4}, f64 {f64Error:e}"))
+}
+
+/// Exporting a diff between two revisions and applying it to the older one must reproduce the newer
+/// one bit for bit.
+fn check_diff() -> Result<String, String> {
+	const diameter: usize = 32;
+	let generate = |code: &str| {
+		let ast = lua::construct_noisegen(code).map_err(|err| format!("{err:#}"))?;
+		Ok::<_, String>(generate_samples(&ast, diameter, lua::Precision::F64))
+	};
+	let base = generate("return Noise.simplex(3):octaves(3)")?;
+	let target = generate("return Noise.simplex(3):octaves(3) + Noise.sinefield(4) * 0.1")?;
+
+	let diff = diff::HeightDiff::new(&base.samples, &target.samples, target.range)
+		.map_err(|err| format!("{err:#}"))?;
+	let mut file = vec![];
+	diff.write(&mut file).map_err(|err| err.to_string())?;
+	let read = diff::HeightDiff::read(&file[..]).map_err(|err| format!("{err:#}"))?;
+	let samples = read.apply(&base.samples).map_err(|err| format!("{err:#}"))?;
+	if let Some(i) = (0 .. samples.len()).find(|&i| samples[i].to_bits() != target.samples[i].to_bits()) {
+		return Err(format!("texel {i} is {} instead of {}", samples[i], target.samples[i]));
+	}
+	if read.apply(&target.samples).is_ok() {
+		return Err("applied to the wrong baseline without complaint".into());
+	}
+	let summary = read.summary();
+	if summary.changed == 0 || summary.maxDelta <= 0.0 {
+		return Err(format!("summary misses the change: {summary}"));
+	}
+	Ok(format!("{} bytes, {summary}", file.len()))
+}
+
+fn check_export() -> Result<String, String> {
+	let samples = [-2.0, -1.0, 0.0, 0.5, 1.0, f32::NAN];
+	let clamped = export::normalize(&samples, (-1.0, 1.0), export::Normalization::Clamp);
+	if clamped != [0.0, 0.0, 0.5, 0.75, 1.0, 0.0] {
+		return Err(format!("clamped to {clamped:?}"));
+	}
+	let rescaled = export::normalize(&samples, (-1.0, 1.0), export::Normalization::Rescale);
+	let expected = [0.0, 1.0 / 3.0, 2.0 / 3.0, 2.5 / 3.0, 1.0, 0.0];
+	if rescaled.iter().zip(expected).any(|(v, e)| (v - e).abs() > 1e-6) {
+		return Err(format!("rescaled to {rescaled:?}"));
+	}
+
+	let values = [0.0, 0.25, 0.5, 1.0];
+	let path = std::env::temp_dir().join(format!("noisebench-selftest-{}.png", std::process::id()));
+	export::write_png(&path, (2, 2), &values).map_err(|err| format!("{err:#}"))?;
+	let read = image::open(&path).map_err(|err| err.to_string());
+	std::fs::remove_file(&path).ok();
+	let read = read?.into_luma16();
+	let expected: Vec<u16> = values.iter().map(|v| (v * u16::MAX as f32).round() as u16).collect();
+	if read.dimensions() != (2, 2) || read.as_raw() != &expected {
+		return Err(format!("read back {:?}", read.as_raw()));
+	}
+	Ok("PNG round trip exact".into())
+}
+
+fn check_raw_export() -> Result<String, String> {
+	use export::{Endianness, RawBits, RawLayout, RowOrder};
+	let values = [0.0, 1.0, 0.5, 0.25];
+	let layout = |bits, endianness, rowOrder| RawLayout {
+		bits,
+		endianness,
+		rowOrder,
+	};
+	let r16 = |endianness, rowOrder| layout(RawBits::R16, endianness, rowOrder);
+	let cases: [(_, &[u8]); 4] = [
+		(r16(Endianness::Little, RowOrder::TopDown), &[0, 0, 255, 255, 0, 128, 0, 64]),
+		(r16(Endianness::Big, RowOrder::TopDown), &[0, 0, 255, 255, 128, 0, 64, 0]),
+		(r16(Endianness::Little, RowOrder::BottomUp), &[0, 128, 0, 64, 0, 0, 255, 255]),
+		(
+			layout(RawBits::R32, Endianness::Big, RowOrder::BottomUp),
+			&[63, 0, 0, 0, 62, 128, 0, 0, 0, 0, 0, 0, 63, 128, 0, 0],
+		),
+	];
+	for (layout, expected) in cases {
+		let bytes = export::encode_raw(2, &values, layout);
+		if bytes != expected {
+			return Err(format!("{layout:?} encoded as {bytes:?}"));
+		}
+	}
+
+	let samples = [-0.5, 0.25, 0.0, 0.5];
+	let fixed = export::normalize(&samples, (-4.0, 4.0), export::Normalization::Unit);
+	if fixed != [0.25, 0.625, 0.5, 0.75] {
+		return Err(format!("fixed [-1, 1] mapping gave {fixed:?}"));
+	}
+	let mapped = export::Normalization::Rescale.mapped_range(&samples, (-4.0, 4.0));
+	if mapped != (-0.5, 0.5) {
+		return Err(format!("rescaling maps {mapped:?} instead of the sample range"));
+	}
+	Ok(String::new())
+}
+
+/// Resampling must keep the corner samples and interpolate bilinear surfaces exactly, growing,
+/// shrinking and at sizes the original spacing doesn't divide.
+fn check_resample() -> Result<String, String> {
+	// bilinear in the unit square, so any bilinear interpolation of its samples reproduces it
+	let surface = |x: f64, y: f64| (1.0 + 2.0 * x - 3.0 * y + 4.0 * x * y) as f32;
+	let output = |diameter: usize| {
+		let mut output = NoiseOutput::new(diameter);
+		let last = (diameter - 1) as f64;
+		for (y, row) in output.rows() {
+			for (x, v) in row.iter_mut().enumerate() {
+				*v = surface(x as f64 / last, y as f64 / last);
+			}
+		}
+		output
+	};
+	for (from, to) in [(3, 5), (9, 17), (9, 5), (17, 3), (4, 7), (7, 4), (5, 1025), (6, 6)] {
+		let resampled = output(from).resampled(to, to);
+		if resampled.len() != to * to {
+			let len = resampled.len();
+			return Err(format!("{from}² resampled to {len} samples instead of {to}²"));
+		}
+		let last = (to - 1) as f64;
+		for (i, &v) in resampled.iter().enumerate() {
+			let (x, y) = ((i % to) as f64 / last, (i / to) as f64 / last);
+			let expected = surface(x, y);
+			if (v - expected).abs() > 1e-5 {
+				return Err(format!("{from}² to {to}²: ({x}, {y}) is {v} instead of {expected}"));
+			}
+		}
+		let corners = [0, to - 1, to * (to - 1), to * to - 1];
+		let original = output(from);
+		let sourceCorners = [0, from - 1, from * (from - 1), from * from - 1];
+		if corners.iter().zip(sourceCorners).any(|(&i, j)| resampled[i] != original.samples[j]) {
+			return Err(format!("{from}² to {to}² moved the corners"));
+		}
+	}
+	Ok(String::new())
+}
+
+/// Known-good outputs of `noise_rng`, which must be the same on every platform and release.
+const rngVectors: &[((u64, u64, u64), u64)] = &[
+	((0, 0, 0), 0x238275bc38fcbe91),
+	((1, 2, 3), 0xd0734750fde362b3),
+	((u64::MAX, u64::MAX, 0), 0xf14a310831123e0a),
+	((42, 12345, 67890), 0xa6b541d968b03cce),
+];
+const hashVectors: &[((i64, f64, f64), f64)] = &[
+	((0, 0.0, 0.0), 0.13870941014555427),
+	((7, 1.5, -2.25), 0.7280902830491219),
+	((-3, 100.0, 0.125), 0.12029177399449276),
+];
+
+fn check_rng() -> Result<String, String> {
+	for &((seed, x, y), expected) in rngVectors {
+		let h = noise_rng::hash(seed, x, y);
+		if h != expected {
+			return Err(format!("hash({seed}, {x}, {y}) = {h:#x}, expected {expected:#x}"));
+		}
+	}
+	for &((seed, x, y), expected) in hashVectors {
+		let v = noise_rng::unit(noise_rng::hash_pos(seed as u64, dvec2(x, y)));
+		if v != expected {
+			return Err(format!("unit hash ({seed}, {x}, {y}) = {v}, expected {expected}"));
+		}
+		let code = format!("return Noise.const(Noise.hash({seed}, {x:?}, {y:?}))");
+		let ast = lua::construct_noisegen(&code).map_err(|err| format!("{err:#}"))?;
+		let v = ast.eval(DVec2::ZERO);
+		if v != expected as f32 {
+			return Err(format!("Noise.hash({seed}, {x}, {y}) = {v}, expected {expected}"));
+		}
+	}
+	let mut a = noise_rng::Rng::new(5).split(3);
+	let mut b = noise_rng::Rng::new(5).split(3);
+	if (0 .. 16).any(|_| a.next_u64() != b.next_u64()) {
+		return Err("split streams diverge".into());
+	}
+	Ok(String::new())
+}
+
+/// Injected upload failures must step the heightmap size down until it fits.
+fn check_texture_retry() -> Result<String, String> {
+	let format = TextureFormat::Rgba32Float;
+	let mut limits = textures::TextureLimits::default();
+	limits.failAbove = Some(300 * 300);
+	match limits.fit(1024, format) {
+		Ok(256) => {},
+		res => return Err(format!("expected 256 after failures above 300², got {res:?}")),
+	}
+	limits.failAbove = Some(0);
+	if let Ok(diameter) = limits.fit(1024, format) {
+		return Err(format!("fit {diameter} despite failing every size"));
+	}
+	limits.failAbove = None;
+	match limits.fit(limits.maxDimension as usize * 2, format) {
+		Ok(diameter) if diameter as u32 <= limits.maxDimension => Ok(format!("clamped to {diameter}")),
+		res => Err(format!("oversized texture not reduced: {res:?}")),
+	}
+}
+
+/// FastNoiseLite presets, with the node kinds expected below the frequency scale and a fragment of
+/// the expected unsupported-settings report, if any.
+const fnlChecks: &[(&str, &str, (&[&str], Option<&str>))] = &[
+	(
+		"simplex fBm",
+		r#"{"noiseType": "OpenSimplex2", "seed": 3, "frequency": 0.02, "fractalType": "FBm", "octaves": 4}"#,
+		(&["CoordScale", "Mul", "Octaves", "SimplexFast", "Const"], None),
+	),
+	(
+		"ridged",
+		r#"{"NoiseType": "NoiseType_OpenSimplex2S", "FractalType": "FractalType_Ridged", "Octaves": 5, "Gain": 0.4}"#,
+		(&["CoordScale", "Mul", "Octaves", "Sub", "Const", "Mul", "Const", "Abs", "Simplex", "Const"], None),
+	),
+	(
+		"cellular",
+		r#"{"noiseType": "Cellular", "cellularDistanceFunction": "Euclidean", "cellularReturnType": "Distance"}"#,
+		(&["CoordScale", "Sub", "Worley", "Const"], None),
+	),
+	(
+		"cellular value",
+		r#"{"noiseType": "Cellular", "cellularReturnType": "CellValue", "cellularJitter": 0.5}"#,
+		(&["CoordScale", "CellId"], None),
+	),
+	(
+		"cellular ratio",
+		r#"{"noiseType": "Cellular", "cellularReturnType": "Distance2Div"}"#,
+		(&["CoordScale", "Sub", "Worley", "Const"], Some("Distance2Div")),
+	),
+	(
+		"unsupported noise",
+		r#"{"noiseType": "ValueCubic"}"#,
+		(&["CoordScale", "Simplex"], Some("ValueCubic")),
+	),
+];
+
+fn check_fnl(preset: &str, (kinds, unsupported): &(&[&str], Option<&str>)) -> Result<String, String> {
+	let (noise, report) = fnl::import(preset).map_err(|err| format!("{err:#}"))?;
+	let mut found = vec![];
+	noise.visit(&mut |_, node| found.push(node.kind_name()));
+	if found != *kinds {
+		return Err(format!("built {found:?}, expected {kinds:?}"));
+	}
+	match unsupported {
+		None if !report.is_empty() => return Err(format!("unexpected report {report:?}")),
+		Some(fragment) if !report.iter().any(|setting| setting.contains(fragment)) => {
+			return Err(format!("report {report:?} doesn't mention {fragment}"));
+		},
+		_ => {},
+	}
+	for &(x, y) in probePositions {
+		let v = noise.eval(dvec2(x, y));
+		if !(-1.0 ..= 1.0).contains(&v) {
+			return Err(format!("got {v} at ({x}, {y})"));
+		}
+	}
+	Ok(report.join(", "))
+}
+
+/// Pin comparisons must find identical outputs identical, measure differences in output units,
+/// and tell NaN mismatches apart in both the numbers and the image.
+fn check_pin_diff() -> Result<String, String> {
+	let live = [0.0, 0.5, -1.0, f32::NAN, f32::NAN];
+	let same = pin::PinDiff::new(&live, &live);
+	if same.max != 0.0 || same.mean != 0.0 || same.nans != 0 {
+		return Err(format!("identical samples compared as {same:?}"));
+	}
+	let pinned = [0.5, 0.5, 0.0, f32::NAN, 1.0];
+	let diff = pin::PinDiff::new(&live, &pinned);
+	if diff.max != 1.0 || diff.mean != 0.5 || diff.nans != 1 {
+		return Err(format!("expected max 1, mean 0.5 and 1 NaN, got {diff:?}"));
+	}
+
+	let mut output = NoiseOutput::new(2);
+	output.range = (-1.0, 1.0);
+	output.samples = live[.. 4].to_vec();
+	let other = [0.25, 0.5, 1.0, 0.0];
+	let mut image = Image::new_fill(
+		Extent3d {
+			width: 1,
+			height: 1,
+			depth_or_array_layers: 1,
+		},
+		TextureDimension::D2,
+		bytemuck::cast_slice(&[0f32; 4]),
+		TextureFormat::Rgba32Float,
+		default(),
+	);
+	output.fill_diff_image(&mut image, &other, 4.0);
+	let pixels: &[[f32; 4]] = bytemuck::cast_slice(&image.data);
+	let expected = [
+		[0.5, 0.5, 0.5, 1.0],
+		[0.0, 0.0, 0.0, 1.0],
+		[1.0, 1.0, 1.0, 1.0],
+		[1.0, 0.0, 0.0, 1.0],
+	];
+	if pixels != expected {
+		return Err(format!("diff image is {pixels:?} instead of {expected:?}"));
+	}
+	Ok(String::new())
+}
+
+/// The estimator must see each big consumer added to an otherwise empty session.
+/// Evaluation counts must multiply through octaves and tiling, and labels show node parameters.
+fn check_ast_stats() -> Result<String, String> {
+	let code = "return Noise.simplex(1):octaves(3):tiled(2) + Noise.simplex(2):abs()";
+	let ast = lua::construct_noisegen(code).map_err(|err| format!("{err:#}"))?;
+	let stats = ast.stats();
+	if stats.nodes() != 6 || stats.depth != 4 {
+		return Err(format!("{} nodes, {} deep", stats.nodes(), stats.depth));
+	}
+	if stats.kinds.get("Simplex") != Some(&2) {
+		return Err(format!("kinds {:?}", stats.kinds));
+	}
+	// 4 corners of 3 octaves, plus the second source once
+	if stats.source_evaluations() != 13.0 || stats.perNode[3] != 12.0 {
+		return Err(format!("evaluations {:?}", stats.evaluations));
+	}
+	let label = ast.node(4).map(|node| node.to_string());
+	if label.as_deref() != Some("Abs") || ast.node(3).unwrap().to_string() != "Simplex(1)" {
+		return Err(format!("labels {label:?} and {}", ast.node(3).unwrap()));
+	}
+	Ok(format!("{} source evaluations per sample", stats.source_evaluations()))
+}
+
+fn check_memory() -> Result<String, String> {
+	let snapshots = default();
+	let pinned = default();
+	let diff = default();
+	let sweep = default();
+	let thumbnails = default();
+	let graph = default();
+	let mut meshes = Assets::<Mesh>::default();
+	let mut images = Assets::<Image>::default();
+	let estimate = |output: Option<&NoiseOutput>, meshes: &Assets<Mesh>, images: &Assets<Image>| {
+		memory::MemoryUsage::estimate(&memory::MemorySources {
+			output,
+			snapshots: &snapshots,
+			pinned: &pinned,
+			diff: &diff,
+			sweep: &sweep,
+			thumbnails: &thumbnails,
+			graph: &graph,
+			session: &[],
+			meshes,
+			images,
+		})
+	};
+
+	let empty = estimate(None, &meshes, &images);
+	if empty.total() != 0 {
+		return Err(format!("empty session estimated at {empty:?}"));
+	}
+
+	let output = NoiseOutput::new(64);
+	let usage = estimate(Some(&output), &meshes, &images);
+	if usage.noise != 64 * 64 * 4 {
+		return Err(format!("64x64 output estimated at {} bytes", usage.noise));
+	}
+
+	let mut mesh = Mesh::new(PrimitiveTopology::TriangleList, RenderAssetUsages::default());
+	let scale = TerrainScale {
+		height: 1.0,
+		extent: 7.0,
+	};
+	NoiseOutput::new(8).update_mesh(&mut mesh, scale);
+	let positionAndIndexBytes = 8 * 8 * 12 + 7 * 7 * 6 * 4;
+	meshes.add(mesh);
+	let usage = estimate(None, &meshes, &images);
+	if usage.meshes < positionAndIndexBytes {
+		return Err(format!("8x8 mesh estimated at {} bytes", usage.meshes));
+	}
+
+	images.add(Image::new_fill(
+		Extent3d {
+			width: 16,
+			height: 16,
+			depth_or_array_layers: 1,
+		},
+		TextureDimension::D2,
+		bytemuck::cast_slice(&[0f32; 4]),
+		TextureFormat::Rgba32Float,
+		default(),
+	));
+	let usage = estimate(None, &meshes, &images);
+	if usage.images != 16 * 16 * 16 {
+		return Err(format!("16x16 image estimated at {} bytes", usage.images));
+	}
+	Ok(String::new())
+}
+
+/// Offsets `decorrelate` gave each source, in pre-order.
+fn decorrelate_offsets(noise: &lua::Noise, offsets: &mut Vec<DVec2>) {
+	if let lua::Noise::CoordTranslate(func, offset) = noise {
+		if matches!(**func, lua::Noise::CoordRotate(..)) {
+			offsets.push(*offset);
+		}
+	}
+	for child in noise.children() {
+		decorrelate_offsets(child, offsets);
+	}
+}
+
+fn check_decorrelate() -> Result<String, String> {
+	let ast = lua::construct_noisegen("return Noise.simplex(1) - Noise.simplex(1):scale(1)")
+		.map_err(|err| format!("{err:#}"))?;
+	let (mut a, mut b) = ((*ast).clone(), (*ast).clone());
+	a.decorrelate(9);
+	b.decorrelate(9);
+	let mut offsets = vec![];
+	decorrelate_offsets(&a, &mut offsets);
+	if offsets.len() != 2 || offsets[0] == offsets[1] {
+		return Err(format!("expected two distinct source offsets, got {offsets:?}"));
+	}
+	for &(x, y) in probePositions {
+		let pos = dvec2(x, y);
+		if a.eval(pos).to_bits() != b.eval(pos).to_bits() {
+			return Err(format!("decorrelation isn't deterministic at ({x}, {y})"));
+		}
+	}
+	// the original cancels out everywhere, the decorrelated sources must not
+	if probePositions.iter().all(|&(x, y)| a.eval(dvec2(x, y)) == 0.0) {
+		return Err("sources still identical after decorrelating".into());
+	}
+	Ok(String::new())
+}
+
+/// Subtrees of every `Cached` node in the tree, in pre-order.
+fn cached_subtrees(noise: &lua::Noise) -> Vec<*const lua::Noise> {
+	let mut subtrees = vec![];
+	noise.visit(&mut |_, node| {
+		if let lua::Noise::Cached { func, .. } = node {
+			subtrees.push(Arc::as_ptr(func));
+		}
+	});
+	subtrees
+}
+
+/// `noisebench_core`'s serial sampler must give the app's banded generations bit for bit, over a
+/// domain, with supersampling and on a non-square centered grid.
+fn check_core_sampler() -> Result<String, String> {
+	let ast = lua::construct_noisegen("return Noise.simplex(2):scale(3):octaves(4)")
+		.map_err(|err| format!("{err:#}"))?;
+	let grid = SampleGrid {
+		width: 37,
+		height: 23,
+		coords: Coords::Centered,
+		supersampling: 2,
+		domain: Domain {
+			origin: dvec2(-1.5, 0.25),
+			size: 3.0,
+		},
+	};
+	let (z, precision) = (0.5, lua::Precision::F32);
+	let cancel = AtomicBool::new(false);
+	let ctx = default();
+	let output = generate_samples_cancellable(&ast, &grid, precision, z, &ctx, &cancel)
+		.ok_or("generation was cancelled")?;
+	let samples = noisebench_core::grid::sample_grid(&ast, grid.spec(z, precision));
+	let differs = |&i: &usize| samples[i].to_bits() != output.samples[i].to_bits();
+	if let Some(i) = (0 .. samples.len()).find(differs) {
+		return Err(format!("sample {i} is {} instead of {}", samples[i], output.samples[i]));
+	}
+	Ok(String::new())
+}
+
+/// A cached subtree must be evaluated once per sample however often it's used, give the values
+/// of the uncached tree, and stay shared through clones, reseeding and decorrelation.
+fn check_cached() -> Result<String, String> {
+	let evaluations = Arc::new(AtomicUsize::new(0));
+	let counter = evaluations.clone();
+	let counted = lua::Noise::Func(Box::new(move |pos: DVec2| {
+		counter.fetch_add(1, Ordering::Relaxed);
+		pos.x as f32
+	}))
+	.cached();
+	let tree = lua::Noise::Add(counted.clone().into(), counted.into());
+	for (i, &(x, y)) in probePositions.iter().enumerate() {
+		let v = tree.eval(dvec2(x, y));
+		let count = evaluations.load(Ordering::Relaxed);
+		if v != (x * 2.0) as f32 || count != i + 1 {
+			return Err(format!("{v} at ({x}, {y}), {count} evaluations after {} samples", i + 1));
+		}
+	}
+
+	let code = "local base = Noise.simplex(1):octaves(8):cached()\n\
+		return base * Noise.gradientX() + base:abs() * 0.1";
+	let ast = lua::construct_noisegen(code).map_err(|err| format!("{err:#}"))?;
+	let uncached = ast.uncached();
+	if !cached_subtrees(&uncached).is_empty() {
+		return Err("uncached tree still has cached nodes".into());
+	}
+	for &(x, y) in probePositions {
+		for precision in lua::Precision::all {
+			let pos = dvec2(x, y);
+			let (a, b) = (ast.eval_with(pos, precision), uncached.eval_with(pos, precision));
+			if a.to_bits() != b.to_bits() {
+				return Err(format!("{a} cached, {b} uncached at ({x}, {y}) in {precision:?}"));
+			}
+		}
+	}
+
+	let mut rewritten = (*ast).clone();
+	rewritten.reseed(4);
+	rewritten.decorrelate(4);
+	for tree in [&*ast, &rewritten] {
+		let subtrees = cached_subtrees(tree);
+		if subtrees.len() != 2 || subtrees[0] != subtrees[1] {
+			return Err(format!("cached copies don't share their subtree: {subtrees:?}"));
+		}
+	}
+	if cached_subtrees(&ast)[0] == cached_subtrees(&rewritten)[0] {
+		return Err("reseeding changed the original's subtree".into());
+	}
+	Ok(String::new())
+}
+
+fn check_worley() -> Result<String, String> {
+	use lua::{CellReturn, Distance};
+	const seed: i64 = 5;
+	let metrics = [Distance::Euclidean, Distance::Manhattan, Distance::Chebyshev];
+
+	// every feature point is at distance zero from itself
+	for (x, y) in [(0, 0), (3, -2), (-7, 11)] {
+		let pos = dvec2(x as f64, y as f64) + noise_rng::cell_point(seed as u64, x, y);
+		for distanceFn in metrics {
+			let (f1, f2) = lua::worley_distances(seed, distanceFn, pos);
+			if f1 != 0.0 || !(f2 > 0.0) {
+				let at = format!("{distanceFn:?} at the point of cell ({x}, {y})");
+				return Err(format!("{at}: F1 {f1}, F2 {f2}"));
+			}
+		}
+	}
+
+	// F1 must match a search far wider than the one Worley does
+	let brute_f1 = |distanceFn: Distance, pos: DVec2| {
+		let cell = pos.floor();
+		let mut f1 = f64::INFINITY;
+		for dy in -4 ..= 4 {
+			for dx in -4 ..= 4 {
+				let cell = cell + dvec2(dx as f64, dy as f64);
+				let point = cell + noise_rng::cell_point(seed as u64, cell.x as i64, cell.y as i64);
+				f1 = f1.min(distanceFn.eval(point - pos));
+			}
+		}
+		f1
+	};
+	let positions: Vec<_> = (0 .. 400)
+		.map(|i| dvec2((i % 20) as f64 * 0.37 - 3.1, (i / 20) as f64 * 0.41 - 4.3))
+		.collect();
+	for distanceFn in metrics {
+		for &pos in &positions {
+			let (f1, _) = lua::worley_distances(seed, distanceFn, pos);
+			if f1 != brute_f1(distanceFn, pos) {
+				return Err(format!("{distanceFn:?} F1 at {pos} misses the nearest point"));
+			}
+		}
+		for returnType in [CellReturn::F1, CellReturn::F2, CellReturn::F2MinusF1] {
+			let noise = lua::Noise::Worley {
+				seed,
+				distanceFn,
+				returnType,
+			};
+			let (lo, hi) = noise.bounds().ok_or("Worley has no bounds")?;
+			if let Some(pos) = positions.iter().find(|&&pos| {
+				let v = noise.eval(pos) as f64;
+				!(v >= lo && v <= hi as f32 as f64)
+			}) {
+				return Err(format!("{distanceFn:?} {returnType:?} out of [{lo}, {hi}] at {pos}"));
+			}
+		}
+	}
+	Ok(format!("{} positions per metric", positions.len()))
+}
+
+/// Known-good outputs of `Noise.value` and `Noise.perlin`, so changes to either show up here
+/// rather than in reshaped terrain.
+const latticeVectors: &[(&str, i64, (f64, f64), f64)] = &[
+	("value", 1, (0.5, 0.5), -0.5834798681215616),
+	("value", 1, (1.25, -2.75), 0.6659795265531488),
+	("value", 7, (-3.3, 7.9), 0.06822021294713532),
+	("value", 7, (100.125, 0.875), 0.010845242130449517),
+	("perlin", 1, (1.25, -2.75), 0.14723682403564453),
+	("perlin", 1, (-3.3, 7.9), 0.2712636316800001),
+	("perlin", 7, (0.5, 0.5), -0.5),
+	("perlin", 7, (100.125, 0.875), 0.24158736504614353),
+];
+
+fn check_lattice_noise() -> Result<String, String> {
+	for &(kind, seed, (x, y), expected) in latticeVectors {
+		let code = format!("return Noise.{kind}({seed})");
+		let ast = lua::construct_noisegen(&code).map_err(|err| format!("{err:#}"))?;
+		let v = ast.eval(dvec2(x, y));
+		if v != expected as f32 {
+			return Err(format!("{kind}({seed}) at ({x}, {y}) is {v}, expected {expected}"));
+		}
+	}
+
+	let perlin = lua::Noise::Perlin {
+		seed: 3,
+		perm: Arc::new(lua::perlin_permutation(3)),
+	};
+	let value = lua::Noise::Value(3);
+	for noise in [&perlin, &value] {
+		// lattice points are where value noise takes its hashed values and Perlin noise is zero
+		let corner = noise.eval(dvec2(4.0, -2.0));
+		let expected = match noise {
+			lua::Noise::Value(seed) => noise_rng::cell_value(*seed as u64, 4, -2) as f32,
+			_ => 0.0,
+		};
+		if corner != expected {
+			return Err(format!("{} at a lattice point is {corner}", noise.kind_name()));
+		}
+		let mut reseeded = noise.clone();
+		reseeded.reseed(1);
+		let pos = dvec2(2.3, 5.6);
+		if reseeded.eval(pos) == noise.eval(pos) {
+			return Err(format!("reseeded {} didn't change", noise.kind_name()));
+		}
+	}
+	// exact in binary, so the shifted position has the same offset in its cell
+	let pos = dvec2(3.25, 1.75);
+	if perlin.eval(pos) != perlin.eval(pos + dvec2(256.0, -256.0)) {
+		return Err("Perlin noise doesn't repeat with its permutation".into());
+	}
+	Ok(String::new())
+}
+
+/// Without jitter the regions are the unit squares, so everything inside one must share its value
+/// and neighbours must not.
+fn check_cell_id() -> Result<String, String> {
+	const seed: i64 = 9;
+	let grid = lua::Noise::CellId { seed, jitter: 0.0 };
+	let inside: Vec<_> = (0 .. 64)
+		.map(|i| dvec2(0.05 + (i % 8) as f64 * 0.9 / 7.0, 0.05 + (i / 8) as f64 * 0.9 / 7.0))
+		.collect();
+	let value = grid.eval(inside[0]);
+	if let Some(pos) = inside.iter().find(|&&pos| grid.eval(pos) != value) {
+		return Err(format!("cell (0, 0) changes value at {pos}"));
+	}
+	for neighbour in [dvec2(1.5, 0.5), dvec2(0.5, 1.5), dvec2(-0.5, 0.5)] {
+		if grid.eval(neighbour) == value {
+			return Err(format!("cell at {neighbour} has the same value as cell (0, 0)"));
+		}
+	}
+
+	// jittered regions follow the nearest feature point
+	let jittered = lua::Noise::CellId { seed, jitter: 1.0 };
+	for i in 0 .. 400 {
+		let pos = dvec2((i % 20) as f64 * 0.37 - 3.1, (i / 20) as f64 * 0.41 - 4.3);
+		let (x, y) = lua::nearest_cell(seed, 1.0, pos);
+		let expected = noise_rng::cell_value(seed as u64, x, y) as f32;
+		if jittered.eval(pos) != expected {
+			return Err(format!("jittered cell at {pos} isn't the nearest point's ({x}, {y})"));
+		}
+	}
+	Ok(String::new())
+}
+
+/// The coordinate built-ins must give the coordinates and distances exactly at both precisions,
+/// the checker must alternate across every square edge, and white noise must be reproducible yet
+/// differ between neighbouring samples and seeds.
+fn check_shapes() -> Result<String, String> {
+	for (x, y) in [(0.0, 0.0), (0.75, -2.5), (-3.0, 4.0)] {
+		let pos = dvec2(x, y);
+		for (name, node, expected) in [
+			("gradientX", lua::Noise::GradientX, x as f32),
+			("gradientY", lua::Noise::GradientY, y as f32),
+			("radial", lua::Noise::Radial(dvec2(1.0, 1.0)), pos.distance(dvec2(1.0, 1.0)) as f32),
+		] {
+			for precision in lua::Precision::all {
+				let v = node.eval_with(pos, precision);
+				if (v - expected).abs() > 1e-5 {
+					return Err(format!("{name} at {pos} is {v} instead of {expected}"));
+				}
+			}
+		}
+	}
+
+	let checker = lua::Noise::Checker(0.5);
+	for (pos, expected) in [
+		(dvec2(0.25, 0.25), 1.0),
+		(dvec2(0.75, 0.25), -1.0),
+		(dvec2(0.75, 0.75), 1.0),
+		(dvec2(-0.25, 0.25), -1.0),
+		(dvec2(-0.25, -0.25), 1.0),
+	] {
+		let v = checker.eval(pos);
+		if v != expected {
+			return Err(format!("checker at {pos} is {v} instead of {expected}"));
+		}
+	}
+
+	let white = lua::Noise::White(3);
+	let samples: Vec<f32> = (0 .. 1024).map(|i| white.eval(dvec2(i as f64 * 0.01, 0.5))).collect();
+	let again: Vec<f32> = (0 .. 1024).map(|i| white.eval(dvec2(i as f64 * 0.01, 0.5))).collect();
+	if samples != again {
+		return Err("white noise isn't reproducible".into());
+	}
+	if samples.windows(2).any(|pair| pair[0] == pair[1]) {
+		return Err("white noise repeats between neighbouring samples".into());
+	}
+	let mut reseeded = white.clone();
+	reseeded.reseed(1);
+	if reseeded.eval(dvec2(0.5, 0.5)) == white.eval(dvec2(0.5, 0.5)) {
+		return Err("reseeded white noise didn't change".into());
+	}
+	let mean = samples.iter().map(|&v| v as f64).sum::<f64>() / samples.len() as f64;
+	if mean.abs() > 0.1 {
+		return Err(format!("white noise averages {mean}"));
+	}
+	Ok(format!("white noise mean {mean:.3}"))
+}
+
+/// Blends two constants by a checkerboard of unit squares, which must pick exactly one side per
+/// square.
+fn check_lerp() -> Result<String, String> {
+	let checker = || {
+		lua::Noise::Func(Box::new(|pos: DVec2| {
+			((pos.x.floor() + pos.y.floor()) as i64).rem_euclid(2) as f32
+		}))
+	};
+	let lerp = lua::Noise::Lerp {
+		a: lua::Noise::Const(-3.0).into(),
+		b: lua::Noise::Const(5.0).into(),
+		t: checker().into(),
+		clamped: true,
+	};
+	let smooth = lua::Noise::SmoothLerp {
+		a: lua::Noise::Const(-3.0).into(),
+		b: lua::Noise::Const(5.0).into(),
+		t: checker().into(),
+	};
+	for y in -4 .. 4 {
+		for x in -4 .. 4 {
+			let pos = dvec2(x as f64 + 0.5, y as f64 + 0.5);
+			let expected = if (x + y) % 2 == 0 { -3.0 } else { 5.0 };
+			for (name, noise) in [("lerp", &lerp), ("smoothLerp", &smooth)] {
+				let v = noise.eval(pos);
+				if v != expected {
+					return Err(format!("{name} at {pos} is {v} instead of {expected}"));
+				}
+			}
+		}
+	}
+	Ok(String::new())
+}
+
+/// Selects between two constants by the x coordinate, checking either side of the blend, its
+/// middle and a hard edge, in both precisions.
+fn check_select() -> Result<String, String> {
+	// (falloff, x, expected), around a threshold of 0.25
+	let cases = [
+		(0.5, -1.0, -3.0),
+		(0.5, -0.25, -3.0),
+		(0.5, 0.25, 1.0),
+		(0.5, 0.75, 5.0),
+		(0.5, 2.0, 5.0),
+		(0.0, 0.2, -3.0),
+		(0.0, 0.25, 5.0),
+		(0.0, 0.3, 5.0),
+	];
+	for (falloff, x, expected) in cases {
+		let noise = lua::Noise::Select {
+			control: lua::Noise::Func(Box::new(|pos: DVec2| pos.x as f32)).into(),
+			a: lua::Noise::Const(-3.0).into(),
+			b: lua::Noise::Const(5.0).into(),
+			threshold: 0.25,
+			falloff,
+		};
+		for precision in [lua::Precision::F64, lua::Precision::F32] {
+			let v = noise.eval_with(dvec2(x, 0.0), precision);
+			if v != expected {
+				let at = format!("falloff {falloff} at {x} in {precision:?}");
+				return Err(format!("select with {at} is {v} instead of {expected}"));
+			}
+		}
+	}
+
+	let collapsed = lua::Noise::Select {
+		control: lua::Noise::Const(2.0).into(),
+		a: lua::Noise::Const(-3.0).into(),
+		b: lua::Noise::Const(5.0).into(),
+		threshold: 1.0,
+		falloff: 1.0,
+	}
+	.optimize();
+	if collapsed.kind_name() != "Const" || collapsed.eval(DVec2::ZERO) != 5.0 {
+		return Err(format!("select on a constant optimized to {}", collapsed.kind_name()));
+	}
+	Ok(String::new())
+}
+
+/// Curves interpolate between their points and hold the end values beyond them, and hard
+/// terraces only ever output their levels.
+fn check_curves() -> Result<String, String> {
+	let points = [dvec2(-1.0, -1.0), dvec2(0.0, -0.2), dvec2(0.5, 0.2), dvec2(1.0, 1.0)];
+	let cases = [(-3.0, -1.0), (-0.5, -0.6), (0.25, 0.0), (0.5, 0.2), (2.0, 1.0)];
+	for (input, expected) in cases {
+		let v = lua::eval_curve(&points, input);
+		if (v - expected).abs() > 1e-12 {
+			return Err(format!("curve at {input} is {v}, expected {expected}"));
+		}
+	}
+
+	let construct = |code: &str| lua::construct_noisegen(code).map_err(|err| format!("{err:#}"));
+	let terrace = construct("return Noise.simplex(3):terrace(5)")?;
+	let levels = [-1.0, -0.5, 0.0, 0.5, 1.0];
+	let mut seen = [false; 5];
+	for i in 0 .. 1024 {
+		let v = terrace.eval(dvec2((i % 32) as f64 * 0.13, (i / 32) as f64 * 0.13));
+		let Some(level) = levels.iter().position(|&level| level == v) else {
+			return Err(format!("hard terrace output {v} isn't a level"));
+		};
+		seen[level] = true;
+	}
+	if !seen[1 ..= 3].iter().all(|&seen| seen) {
+		return Err(format!("middle terrace levels missing, seen {seen:?}"));
+	}
+	Ok(String::new())
+}
+
+/// A constant warp is a translation, inside octaves too, where it applies per octave.
+fn check_warp() -> Result<String, String> {
+	let pairs = [
+		(
+			"return Noise.simplex(4):warp(0.5, -0.25, 2)",
+			"return Noise.simplex(4):translate(1, -0.5)",
+		),
+		(
+			"return Noise.simplex(4):warp(0.5, -0.25, 2):octaves(4)",
+			"return Noise.simplex(4):translate(1, -0.5):octaves(4)",
+		),
+	];
+	for (warped, translated) in pairs {
+		let warped = lua::construct_noisegen(warped).map_err(|err| format!("{err:#}"))?;
+		let translated = lua::construct_noisegen(translated).map_err(|err| format!("{err:#}"))?;
+		for &(x, y) in probePositions {
+			let pos = dvec2(x, y);
+			let (a, b) = (warped.eval(pos), translated.eval(pos));
+			if a != b {
+				return Err(format!("warp gives {a} instead of {b} at ({x}, {y})"));
+			}
+		}
+	}
+	Ok(String::new())
+}
+
+/// Tiled noise repeats with its period, scaled by any `scale` above it, and is continuous across
+/// tile edges.
+fn check_tiling() -> Result<String, String> {
+	let construct = |code: &str| lua::construct_noisegen(code).map_err(|err| format!("{err:#}"));
+	let tiled = construct("return Noise.simplex(3):scale(2):octaves(3):tiled(1.5, 2)")?;
+	let scaled = construct("return Noise.simplex(3):scale(2):octaves(3):tiled(1.5, 2):scale(4)")?;
+	for &(x, y) in probePositions {
+		let pos = dvec2(x, y);
+		for (noise, period) in [(&tiled, dvec2(1.5, 2.0)), (&scaled, dvec2(1.5, 2.0) / 4.0)] {
+			for shift in [dvec2(period.x, 0.0), dvec2(0.0, -period.y), period * 3.0] {
+				let (a, b) = (noise.eval(pos), noise.eval(pos + shift));
+				if (a - b).abs() > 1e-4 {
+					return Err(format!("{a} at ({x}, {y}) but {b} one period away"));
+				}
+			}
+		}
+	}
+	for edge in [dvec2(1.5, 0.7), dvec2(0.3, 2.0)] {
+		let step = DVec2::splat(1e-7);
+		let (before, after) = (tiled.eval(edge - step), tiled.eval(edge + step));
+		if (before - after).abs() > 1e-3 {
+			return Err(format!("seam at {edge}: {before} before, {after} after"));
+		}
+	}
+	Ok(String::new())
+}
+
+/// 2D evaluation is the `z = 0` slice, `z` transforms only move 3D sources, and octaves, warps
+/// and arithmetic pass `z` through.
+fn check_slices() -> Result<String, String> {
+	let construct = |code: &str| lua::construct_noisegen(code).map_err(|err| format!("{err:#}"));
+	let volume =
+		construct("return Noise.simplex3(2):warp(0.1, 0.2):octaves(3) + Noise.simplex(4)")?;
+	let source = construct("return Noise.simplex3(2)")?;
+	let shifted = construct("return Noise.simplex3(2):translate(0, 0, 0.75)")?;
+	let flat = construct("return Noise.simplex(4):translate(0, 0, 0.75):scale(1, 1, 3)")?;
+	let mut sliceDiffers = false;
+	for &(x, y) in probePositions {
+		let pos = dvec2(x, y);
+		if volume.eval(pos).to_bits() != volume.eval3(pos.extend(0.0)).to_bits() {
+			return Err(format!("2D evaluation isn't the z = 0 slice at ({x}, {y})"));
+		}
+		if shifted.eval(pos).to_bits() != source.eval3(pos.extend(0.75)).to_bits() {
+			return Err(format!("z translation doesn't move the slice at ({x}, {y})"));
+		}
+		if flat.eval3(pos.extend(5.0)).to_bits() != flat.eval(pos).to_bits() {
+			return Err(format!("z transforms changed a 2D source at ({x}, {y})"));
+		}
+		sliceDiffers |= volume.eval3(pos.extend(0.5)) != volume.eval(pos);
+	}
+	if !sliceDiffers {
+		return Err("slices at different depths are identical".into());
+	}
+	Ok(String::new())
+}
+
+fn check_previews() -> Result<String, String> {
+	for (diameter, expected) in [(128, &[][..]), (256, &[64][..]), (1024, &[64, 256][..])] {
+		let passes: Vec<_> = preview_diameters(diameter).collect();
+		if passes != expected {
+			return Err(format!("{diameter} previews at {passes:?} instead of {expected:?}"));
+		}
+	}
+	Ok(String::new())
+}
+
+type MeshData = (Vec<[f32; 3]>, Vec<[f32; 3]>, Vec<u32>);
+
+/// Builds the terrain mesh of a 3×3 heightfield with samples in `[0, 1]` (`range` is set to that)
+/// `extent` wide, returning its positions, normals and indices.
+fn mesh_of(samples: [f32; 9], extent: f32) -> Result<MeshData, String> {
+	let mut output = NoiseOutput::new(3);
+	output.samples = samples.to_vec();
+	output.range = (0.0, 1.0);
+	let mut mesh = Mesh::new(PrimitiveTopology::TriangleList, RenderAssetUsages::default());
+	output.update_mesh(&mut mesh, TerrainScale { height: 1.0, extent });
+	let Some(VertexAttributeValues::Float32x3(positions)) =
+		mesh.attribute(Mesh::ATTRIBUTE_POSITION)
+	else {
+		return Err("mesh has no positions".into());
+	};
+	let Some(VertexAttributeValues::Float32x3(normals)) = mesh.attribute(Mesh::ATTRIBUTE_NORMAL)
+	else {
+		return Err("mesh has no normals".into());
+	};
+	let Some(Indices::U32(indices)) = mesh.indices() else {
+		return Err("mesh has no 32-bit indices".into());
+	};
+	Ok((positions.clone(), normals.clone(), indices.clone()))
+}
+
+/// Terrain chunks must lay the surface out like `update_mesh`, at every level, hang their skirts
+/// below it, face up, and keep their indices when only the height scale changes.
+fn check_terrain_chunks() -> Result<String, String> {
+	type Attributes = (Vec<[f32; 3]>, Vec<u32>);
+	let attributes = |meshes: &Assets<Mesh>, handle: &Handle<Mesh>| -> Result<Attributes, String> {
+		let mesh = meshes.get(handle).ok_or("chunk mesh missing")?;
+		let Some(VertexAttributeValues::Float32x3(positions)) =
+			mesh.attribute(Mesh::ATTRIBUTE_POSITION)
+		else {
+			return Err("chunk has no positions".into());
+		};
+		let Some(Indices::U32(indices)) = mesh.indices() else {
+			return Err("chunk has no indices".into());
+		};
+		Ok((positions.clone(), indices.clone()))
+	};
+	let mut meshes = Assets::<Mesh>::default();
+	let mut terrain = lod::TerrainChunks::new(default(), scene::SceneMode::Single);
+	// two full chunks and a one cell wide one along each axis
+	let diameter = lod::chunkCells * 2 + 2;
+	let mut output = NoiseOutput::new(diameter);
+	for (y, row) in output.rows() {
+		for (x, v) in row.iter_mut().enumerate() {
+			*v = ((x * 3 + y) % 7) as f32 / 7.0;
+		}
+	}
+	let scale = TerrainScale {
+		height: 2.0,
+		extent: 10.0,
+	};
+	terrain.update(&output, scale, &mut meshes);
+	let handles: Vec<_> = terrain.meshes().cloned().collect();
+	if handles.len() != 9 * 3 {
+		return Err(format!("{} chunk meshes instead of 27", handles.len()));
+	}
+
+	let side = lod::chunkCells + 1;
+	let (positions, _) = attributes(&meshes, &handles[0])?;
+	let surface = side * side;
+	if positions.len() != surface + 4 * lod::chunkCells {
+		return Err(format!("full resolution chunk has {} vertices", positions.len()));
+	}
+	for (i, &position) in positions[.. surface].iter().enumerate() {
+		let (expected, _) = output.surface_vertex(i % side, i / side, scale);
+		if Vec3::from(position) != expected {
+			return Err(format!("chunk vertex {i} at {position:?} instead of {expected}"));
+		}
+	}
+	let lowest = positions[.. surface].iter().map(|p| p[1]).fold(f32::INFINITY, f32::min);
+	if positions[surface ..].iter().any(|p| p[1] > lowest) {
+		return Err("skirt doesn't reach below the chunk".into());
+	}
+	let (positions, indices) = attributes(&meshes, &handles[2])?;
+	// a quarter of the cells along each axis
+	let cells = lod::chunkCells / 4;
+	for tri in indices[.. cells * cells * 6].chunks_exact(3) {
+		let [a, b, c] = [tri[0], tri[1], tri[2]].map(|i| Vec3::from(positions[i as usize]));
+		if (b - a).cross(c - a).y <= 0.0 {
+			return Err(format!("quarter resolution triangle {tri:?} faces down"));
+		}
+	}
+	let (positions, _) = attributes(&meshes, &handles[26])?;
+	if positions.len() != 2 * 2 + 4 {
+		return Err(format!("one cell chunk has {} vertices", positions.len()));
+	}
+
+	let (positions, indices) = attributes(&meshes, &handles[1])?;
+	let taller = TerrainScale {
+		height: 5.0,
+		..scale
+	};
+	terrain.update(&output, taller, &mut meshes);
+	let (newPositions, newIndices) = attributes(&meshes, &handles[1])?;
+	if terrain.meshes().count() != 27 || newIndices != indices || newPositions == positions {
+		return Err("height scale change didn't just move the vertices".into());
+	}
+	terrain.update(&NoiseOutput::new(10), scale, &mut meshes);
+	if terrain.meshes().count() != 3 {
+		return Err("chunks weren't laid out again for a new diameter".into());
+	}
+	Ok(String::new())
+}
+
+/// Mesh estimates must count exactly what the terrain chunks hold, skirts and all levels
+/// included, and flat shading must trade the indices for a vertex each.
+fn check_mesh_estimate() -> Result<String, String> {
+	// one cell at every level: 4 surface and 4 skirt vertices, 1 quad and 4 skirt quads
+	let estimate = MeshEstimate::new((2, 2));
+	if (estimate.vertices, estimate.indices) != (3 * 8, 3 * 5 * 6) {
+		return Err(format!("2x2 estimated as {estimate:?}"));
+	}
+	let flat = estimate.flat();
+	if (flat.vertices, flat.indices) != (estimate.indices, 0) ||
+		flat.bytes != estimate.indices * MeshEstimate::bytesPerVertex
+	{
+		return Err(format!("2x2 flat shaded estimated as {flat:?}"));
+	}
+
+	let scale = TerrainScale {
+		height: 1.0,
+		extent: 10.0,
+	};
+	// square with a partial chunk at the edges, and one chunk longer than wide
+	for size in [(1024, 1024), (130, 66), (66, 130)] {
+		let mut meshes = Assets::<Mesh>::default();
+		let mut terrain = lod::TerrainChunks::new(default(), scene::SceneMode::Single);
+		terrain.update(&NoiseOutput::sized(size.0, size.1), scale, &mut meshes);
+		let (mut vertices, mut indices) = (0, 0);
+		for handle in terrain.meshes() {
+			let mesh = meshes.get(handle).ok_or("chunk mesh missing")?;
+			vertices += mesh.count_vertices();
+			indices += mesh.indices().map_or(0, |indices| indices.len());
+		}
+		let estimate = MeshEstimate::new(size);
+		if (estimate.vertices, estimate.indices) != (vertices, indices) {
+			return Err(format!(
+				"{size:?} estimated as {estimate:?}, the chunks have {vertices} vertices and \
+				 {indices} indices"
+			));
+		}
+		let bytes = vertices * MeshEstimate::bytesPerVertex + indices * 4;
+		if estimate.bytes != bytes {
+			let estimated = estimate.bytes;
+			return Err(format!("{size:?} estimated at {estimated} instead of {bytes} bytes"));
+		}
+	}
+	Ok(String::new())
+}
+
+fn check_mesh_normals() -> Result<String, String> {
+	// samples rising by 0.5 per column, which `normalized` maps to a slope of 1 at unit spacing
+	let ramp = [0.0, 0.5, 1.0, 0.0, 0.5, 1.0, 0.0, 0.5, 1.0];
+	let (_, normals, indices) = mesh_of(ramp, 2.0)?;
+	if normals.len() != 9 || indices.len() != 2 * 2 * 6 {
+		return Err(format!("{} vertices and {} indices", normals.len(), indices.len()));
+	}
+	let expected = vec3(-1.0, 1.0, 0.0).normalize();
+	for (i, &normal) in normals.iter().enumerate() {
+		if !Vec3::from(normal).abs_diff_eq(expected, 1e-6) {
+			return Err(format!("slope normal {i} is {normal:?} instead of {expected}"));
+		}
+	}
+	for tri in indices.chunks_exact(3) {
+		let [a, b, c] = [tri[0], tri[1], tri[2]].map(|i| vec3((i % 3) as f32, 0.0, (i / 3) as f32));
+		if (b - a).cross(c - a).y <= 0.0 {
+			return Err(format!("triangle {tri:?} faces down"));
+		}
+	}
+
+	// twice the extent spreads the samples out, halving the slope, around the origin
+	let (positions, normals, _) = mesh_of(ramp, 4.0)?;
+	let expected = vec3(-0.5, 1.0, 0.0).normalize();
+	if !Vec3::from(normals[4]).abs_diff_eq(expected, 1e-6) {
+		return Err(format!("stretched normal is {:?} instead of {expected}", normals[4]));
+	}
+	let corners = [(0, vec3(-2.0, 0.0, -2.0)), (4, vec3(0.0, 0.5, 0.0)), (8, vec3(2.0, 1.0, 2.0))];
+	for (i, corner) in corners {
+		if Vec3::from(positions[i]) != corner {
+			return Err(format!("vertex {i} is at {:?} instead of {corner}", positions[i]));
+		}
+	}
+
+	// a single peak in the middle
+	let (_, normals, _) = mesh_of([0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0], 2.0)?;
+	if !Vec3::from(normals[4]).abs_diff_eq(Vec3::Y, 1e-6) {
+		return Err(format!("peak normal is {:?} instead of up", normals[4]));
+	}
+	// the sides lean away from the peak
+	for (i, away) in [(1, Vec3::NEG_Z), (3, Vec3::NEG_X), (5, Vec3::X), (7, Vec3::Z)] {
+		let normal = Vec3::from(normals[i]);
+		if normal.dot(away) <= 0.0 || normal.y <= 0.0 {
+			return Err(format!("side normal {i} is {normal} instead of leaning along {away}"));
+		}
+	}
+	Ok(String::new())
+}
+
+/// Going through every scene mode and back must leave exactly the entities and assets the first
+/// mode had, so nothing a mode spawned outlives it.
+fn check_scene_modes() -> Result<String, String> {
+	let mut app = App::new();
+	app.add_plugins((MinimalPlugins, AssetPlugin::default()))
+		.init_asset::<Mesh>()
+		.init_asset::<Image>()
+		.init_asset::<StandardMaterial>()
+		.init_asset::<WaterMaterial>();
+	let (_, receiver) = crossbeam_channel::unbounded();
+	let mut output = NoiseOutput::new(64);
+	let noise = lua::construct_noisegen("return Noise.simplex(1)");
+	output.noise = Some(noise.map_err(|err| format!("{err:#}"))?);
+	app.insert_resource(UiState::new(receiver, HashMap::new()))
+		.insert_resource(Heightmaps {
+			image: default(),
+			heights: default(),
+			colors: default(),
+		})
+		.insert_resource(WaterSettings::default())
+		.insert_resource(pin::PinnedNoiseOutput {
+			output: Some(NoiseOutput::new(32)),
+			..default()
+		})
+		.insert_resource(output)
+		.insert_resource(scene::SceneMode::Single)
+		.add_systems(
+			Update,
+			(
+				scene::apply_scene_mode,
+				lod::sync_terrain_chunks.after(scene::apply_scene_mode),
+				scene::sync_pinned_terrain.after(scene::apply_scene_mode),
+				infinite::update_infinite_terrain.after(scene::apply_scene_mode),
+			),
+		);
+	app.world_mut().spawn((Camera3d::default(), Transform::default(), GlobalTransform::default()));
+	app.finish();
+	app.cleanup();
+
+	let settle = |app: &mut App| {
+		for _ in 0 .. 3 {
+			app.update();
+		}
+	};
+	let counts = |app: &App| {
+		let world = app.world();
+		[
+			world.entities().len() as usize,
+			world.resource::<Assets<Mesh>>().len(),
+			world.resource::<Assets<StandardMaterial>>().len(),
+			world.resource::<Assets<WaterMaterial>>().len(),
+		]
+	};
+	settle(&mut app);
+	let baseline = counts(&app);
+
+	app.insert_resource(scene::SceneMode::Compare);
+	settle(&mut app);
+	let pinned = app.world_mut().query::<&scene::PinnedSurface>().iter(app.world()).count();
+	if pinned != 1 {
+		return Err(format!("{pinned} pinned terrains in A/B mode"));
+	}
+
+	app.insert_resource(scene::SceneMode::Infinite);
+	let tiles = ((infinite::tileRadius * 2 + 1) as usize).pow(2);
+	let start = Instant::now();
+	loop {
+		app.update();
+		let terrain = app.world().get_resource::<infinite::InfiniteTerrain>();
+		let terrain = terrain.ok_or("no infinite terrain in infinite mode")?;
+		if terrain.pending() == 0 && terrain.shown() == tiles {
+			break;
+		}
+		if start.elapsed() > Duration::from_secs(30) {
+			return Err(format!("{} of {tiles} infinite tiles shown in time", terrain.shown()));
+		}
+		std::thread::sleep(Duration::from_millis(5));
+	}
+	if app.world().get_resource::<lod::TerrainChunks>().is_some() {
+		return Err("terrain chunks left in infinite mode".into());
+	}
+
+	app.insert_resource(scene::SceneMode::Single);
+	settle(&mut app);
+	let after = counts(&app);
+	let names = ["entities", "meshes", "materials", "water materials"];
+	for ((name, before), after) in names.iter().zip(baseline).zip(after) {
+		if before != after {
+			return Err(format!("{after} {name} after switching back instead of {before}"));
+		}
+	}
+	if app.world().get_resource::<infinite::InfiniteTerrain>().is_some() {
+		return Err("infinite terrain left after switching back".into());
+	}
+	Ok(format!("{} entities, {} meshes", baseline[0], baseline[1]))
+}
+
+/// The water shader must sample the heightmap texel under each mesh vertex, along both axes of
+/// non-square outputs too, so depth tint and foam line up with the terrain.
+fn check_water_texels() -> Result<String, String> {
+	let scale = TerrainScale {
+		height: 1.0,
+		extent: 12.0,
+	};
+	for (width, height) in [(7, 4), (4, 7), (5, 5)] {
+		let output = NoiseOutput::sized(width, height);
+		let uniform = WaterUniform {
+			extent: scale.extent,
+			spacing: scale.spacing((width, height)),
+			..default()
+		};
+		for y in 0 .. height {
+			for x in 0 .. width {
+				let (vertex, _) = output.surface_vertex(x, y, scale);
+				let texel = uniform.to_texel((width, height), vec2(vertex.x, vertex.z));
+				if texel.distance(vec2(x as f32, y as f32)) > 1e-4 {
+					return Err(format!(
+						"water under vertex ({x}, {y}) of {width}×{height} samples texel {texel}"
+					));
+				}
+			}
+		}
+	}
+	Ok(String::new())
+}
+
+fn check_terrain_colors() -> Result<String, String> {
+	let palette = coloring::Palette::default();
+	// colors pass through linear space, which may round differently
+	let is_band = |color: &[u8], band: usize| {
+		let expected = palette.colors[band].to_srgba().to_u8_array();
+		color.iter().zip(expected).all(|(&a, b)| a.abs_diff(b) <= 1)
+	};
+	for (height, band) in [(-1.0, 0), (-0.15, 1), (0.0, 2), (0.5, 3), (1.0, 4)] {
+		if !is_band(&palette.color_at(height, 0.0), band) {
+			return Err(format!("height {height} isn't colored as band {band}"));
+		}
+	}
+	if !is_band(&palette.color_at(0.0, 10.0), 3) {
+		return Err("steep grass isn't rock".into());
+	}
+	if !is_band(&palette.color_at(-1.0, 10.0), 0) {
+		return Err("steep water isn't water".into());
+	}
+
+	// a ramp steep enough for rock along x, flat along y
+	let heights: Vec<f32> = (0 .. 16).map(|i| (i % 4) as f32 * 0.1).collect();
+	let colors = palette.colorize(&heights, (4, 4), 20.0);
+	if colors.len() != 16 * 4 || !is_band(&colors[.. 4], 3) {
+		return Err(format!("ramp colored {:?}", &colors[.. 4]));
+	}
+	let flat = coloring::Palette {
+		slopeShading: false,
+		..palette.clone()
+	};
+	if !is_band(&flat.colorize(&heights, (4, 4), 20.0)[.. 4], 2) {
+		return Err("slope shading can't be turned off".into());
+	}
+	Ok(String::new())
+}
+
+/// The default sun must be where the light was before it could be moved, hillshading must light
+/// flat ground by the sun's height and slopes facing it more than those facing away, and shadows
+/// must only be off when asked to.
+fn check_lighting() -> Result<String, String> {
+	let settings = LightingSettings::default();
+	let sun = settings.sun_direction();
+	let before = vec3(-0.8035929, 0.39474383, -0.44543877);
+	if sun.distance(before) > 1e-4 {
+		return Err(format!("default sun at {sun} instead of {before}"));
+	}
+	let flat = gradients::hillshade(Vec2::ZERO, 2.0, sun);
+	if (flat - sun.y).abs() > 1e-6 {
+		return Err(format!("flat ground shaded {flat} instead of {}", sun.y));
+	}
+	// rising away from the sun along x faces it
+	let facing = gradients::hillshade(vec2(-sun.x.signum(), 0.0), 1.0, sun);
+	let away = gradients::hillshade(vec2(sun.x.signum(), 0.0), 1.0, sun);
+	if !(facing > flat && flat > away && away >= 0.0) {
+		return Err(format!("slopes shaded {facing} facing and {away} away from the sun"));
+	}
+	let overhead = LightingSettings {
+		elevation: 89.9,
+		..default()
+	};
+	if gradients::hillshade(Vec2::ZERO, 1.0, overhead.sun_direction()) < 0.999 {
+		return Err("flat ground isn't fully lit by an overhead sun".into());
+	}
+	for quality in ShadowQuality::all {
+		if quality.size().is_none() != (quality == ShadowQuality::Off) {
+			return Err(format!("shadows {} have size {:?}", quality.label(), quality.size()));
+		}
+	}
+	Ok(String::new())
+}
+
+/// A division by zero must be traced to its `Div` node, and one hidden inside octaves to the
+/// octaves. NaN samples must neither break the mesh nor garble the preview.
+fn check_non_finite() -> Result<String, String> {
+	let ctx = lua::EvalContext::default();
+	let construct = |code: &str| lua::construct_noisegen(code).map_err(|err| format!("{err:#}"));
+	// zero along the x axis
+	let ast = construct("return (Noise.gradientX() + 1) / Noise.gradientY()")?;
+	let pos = dvec3(0.5, 0.0, 0.0);
+	let source = ast.trace_non_finite(pos, lua::Precision::F64, &ctx);
+	let Some(source) = source else {
+		return Err("division by zero isn't traced".into());
+	};
+	let kind = ast.node(source.node).map(|node| node.kind_name());
+	if !source.exact || source.kind != "Div" || kind != Some("Div") {
+		return Err(format!("division by zero traced to {source:?}"));
+	}
+	if ast.trace_non_finite(dvec3(0.5, 0.5, 0.0), lua::Precision::F64, &ctx).is_some() {
+		return Err("finite sample traced".into());
+	}
+	let ast = construct("return (Noise.gradientX() / Noise.gradientX()):octaves(2)")?;
+	let source = ast.trace_non_finite(DVec3::ZERO, lua::Precision::F64, &ctx);
+	if source.map(|source| (source.kind, source.exact)) != Some(("Octaves", false)) {
+		return Err(format!("division inside octaves traced to {source:?}"));
+	}
+
+	let mut output = NoiseOutput::new(5);
+	output.samples[12] = f32::NAN;
+	output.samples[3] = f32::INFINITY;
+	let spec = GridSpec::new(5);
+	let ast = construct("return Noise.gradientY() / Noise.gradientY()")?;
+	let found = NonFiniteSample::find(&output, &ast, &spec, &ctx);
+	let Some(found) = found else {
+		return Err("non-finite sample not found".into());
+	};
+	if found.pos != spec.position(3, 0) || found.source.is_none() {
+		return Err(format!("first non-finite sample found as {found:?}"));
+	}
+
+	let mut samples = [0.5; 9];
+	samples[4] = f32::NAN;
+	let (positions, normals, _) = mesh_of(samples, 2.0)?;
+	if positions.iter().chain(&normals).flatten().any(|v| !v.is_finite()) {
+		return Err("NaN sample gave a non-finite vertex".into());
+	}
+	output.range = (-1.0, 1.0);
+	output.samples[0] = 0.0;
+	let image = colormap::rgba8_image(&output, &Colormap::default());
+	if image.get_pixel(2, 2) != image.get_pixel(0, 0) {
+		return Err(format!("NaN sample colored {:?}", image.get_pixel(2, 2)));
+	}
+	Ok(String::new())
+}
+
+/// Gradients of a ramp are constant, flat ground has an upright normal, and scaling the domain
+/// inside `gradientMagnitude` steepens it by the same factor.
+fn check_gradients() -> Result<String, String> {
+	let mut output = NoiseOutput::new(5);
+	for (y, row) in output.rows() {
+		for (x, v) in row.iter_mut().enumerate() {
+			*v = x as f32 * 0.25 - y as f32 * 0.5;
+		}
+	}
+	// one sample step is a quarter of the domain
+	let expected = vec2(1.0, -2.0);
+	if let Some(gradient) = output
+		.compute_gradients()
+		.into_iter()
+		.find(|gradient| (*gradient - expected).length() > 1e-5)
+	{
+		return Err(format!("ramp gradient {gradient} instead of {expected}"));
+	}
+	if gradients::encode_normal(Vec2::ZERO, 3.0) != [0.5, 0.5, 1.0] {
+		return Err("flat normal isn't (0.5, 0.5, 1)".into());
+	}
+	let [r, g, _] = gradients::encode_normal(vec2(1.0, 1.0), 1.0);
+	if !(r < 0.5 && g > 0.5) {
+		return Err(format!("normal of a slope rising right and down encoded as ({r}, {g})"));
+	}
+
+	let construct = |code: &str| lua::construct_noisegen(code).map_err(|err| format!("{err:#}"));
+	let plain = construct("return Noise.simplex(5):gradientMagnitude(1e-5)")?;
+	let scaled = construct("return Noise.simplex(5):scale(4):gradientMagnitude(1e-5 / 4)")?;
+	for &(x, y) in probePositions {
+		let pos = dvec2(x, y);
+		let (a, b) = (plain.eval(pos * 4.0) * 4.0, scaled.eval(pos));
+		if (a - b).abs() > 1e-2 * a.abs().max(1.0) {
+			return Err(format!("scaled slope {b} instead of {a} at ({x}, {y})"));
+		}
+	}
+	Ok(String::new())
+}
+
+/// Saved state round-trips, and files missing fields, from older versions or edited by hand,
+/// still restore the fields they have.
+/// Reference images must map black and white to -1 and 1, put the unit square's corners on the
+/// corner pixels, clamp or wrap outside it, and refuse images they can't use instead of panicking.
+fn check_reference() -> Result<String, String> {
+	let dir = std::env::temp_dir();
+	let path = dir.join(format!("noisebench-selftest-ref-{}.png", std::process::id()));
+	export::write_png(&path, (2, 2), &[0.0, 0.25, 0.5, 1.0]).map_err(|err| format!("{err:#}"))?;
+	let pathText = path.display().to_string();
+	let loaded = reference::HeightImage::load(&pathText);
+	let script = format!("return Noise.image({pathText:?}, 'wrap')");
+	let ast = lua::construct_noisegen(&script);
+	// the cached tree is outdated once the image changes
+	let rewritten = export::write_png(&path, (2, 2), &[1.0, 0.25, 0.5, 1.0]);
+	let rebuilt = lua::construct_noisegen(&script);
+	std::fs::remove_file(&path).ok();
+	let image = loaded.map_err(|err| format!("{err:#}"))?;
+	let ast = ast.map_err(|err| format!("{err:#}"))?;
+	rewritten.map_err(|err| format!("{err:#}"))?;
+	let rebuilt = rebuilt.map_err(|err| format!("{err:#}"))?.eval(dvec2(0.0, 0.0));
+	if rebuilt != 1.0 {
+		return Err(format!("rebuilt after the image changed, its corner is {rebuilt}"));
+	}
+
+	let cases = [
+		((0.0, 0.0), false, -1.0),
+		((1.0, 0.0), false, -0.5),
+		((0.0, 1.0), false, 0.0),
+		((1.0, 1.0), false, 1.0),
+		((0.5, 0.5), false, -0.125),
+		((-3.0, 0.0), false, -1.0),
+		((2.0, 0.0), false, -0.5),
+		((2.0, 0.0), true, -1.0),
+		((1.5, 0.0), true, -0.75),
+	];
+	for ((x, y), wrap, expected) in cases {
+		let v = image.sample(dvec2(x, y), wrap);
+		if (v - expected).abs() > 1e-4 {
+			return Err(format!("({x}, {y}) wrap {wrap}: expected {expected}, got {v}"));
+		}
+	}
+	let v = ast.eval(dvec2(1.5, 0.0));
+	if (v + 0.75).abs() > 1e-4 {
+		return Err(format!("Noise.image wrapped to {v} instead of -0.75"));
+	}
+
+	let nonSquare = dir.join(format!("noisebench-selftest-ref-{}-wide.png", std::process::id()));
+	let text = dir.join(format!("noisebench-selftest-ref-{}.png.txt", std::process::id()));
+	let written = image::GrayImage::new(3, 2).save(&nonSquare).map_err(|err| err.to_string());
+	std::fs::write(&text, "not an image").map_err(|err| err.to_string())?;
+	let nonSquareRes = reference::HeightImage::load(&nonSquare.display().to_string());
+	let textRes = reference::HeightImage::load(&text.display().to_string());
+	std::fs::remove_file(&nonSquare).ok();
+	std::fs::remove_file(&text).ok();
+	written?;
+	if nonSquareRes.is_ok() {
+		return Err("a 3x2 image loaded".into());
+	}
+	if textRes.is_ok() {
+		return Err("a text file loaded".into());
+	}
+	Ok(String::new())
+}
+
+/// A recorded sweep must evaluate every frame at its own value, ends included, write a numbered
+/// PNG per frame and the GIF, and stop writing once cancelled.
+fn check_sweep_recording() -> Result<String, String> {
+	let id = std::process::id();
+	let dir = std::env::temp_dir().join(format!("noisebench-selftest-sweep-{id}"));
+	let job = sequence::SweepJob {
+		settings: sequence::SweepSettings {
+			param: sequence::SweepParam::Script("level".into()),
+			from: -0.5,
+			to: 0.5,
+			steps: 3,
+			diameter: 8,
+			dir: dir.display().to_string(),
+			gif: true,
+			heightmaps: false,
+		},
+		code: "return Noise.const(Params.slider('level', 0, -1, 1))".into(),
+		seed: 0,
+		params: default(),
+		precision: default(),
+		decorrelate: false,
+		sliceZ: 0.0,
+		time: 0.0,
+		grid: SampleGrid::new(8),
+		colormap: default(),
+	};
+	let res = (|| {
+		let (cancel, done) = (AtomicBool::new(false), AtomicUsize::new(0));
+		for (step, expected) in [(0, -0.5), (1, 0.0), (2, 0.5)] {
+			let frame = job.frame(step, &cancel).map_err(|err| format!("{err:#}"))?;
+			if frame.samples.iter().any(|&v| v != expected) {
+				return Err(format!("frame {step} isn't {expected}"));
+			}
+		}
+		job.run(&cancel, &done).map_err(|err| format!("{err:#}"))?;
+		let files = ["frame_0000.png", "frame_0001.png", "frame_0002.png", "sweep.gif"];
+		if let Some(missing) = files.iter().find(|file| !dir.join(file).is_file()) {
+			return Err(format!("{missing} wasn't written"));
+		}
+		if done.load(Ordering::Relaxed) != 3 {
+			return Err(format!("{} frames counted", done.load(Ordering::Relaxed)));
+		}
+
+		std::fs::remove_dir_all(&dir).map_err(|err| err.to_string())?;
+		cancel.store(true, Ordering::Relaxed);
+		if job.run(&cancel, &AtomicUsize::new(0)).is_ok() || dir.join(files[0]).exists() {
+			return Err("a cancelled sweep wrote frames".into());
+		}
+		Ok(())
+	})();
+	std::fs::remove_dir_all(&dir).ok();
+	res?;
+	Ok(String::new())
+}
+
+/// Bakes must write what the app generates, and fail on scripts that fail instead of writing.
+fn check_bake() -> Result<String, String> {
+	let dir = std::env::temp_dir();
+	let id = std::process::id();
+	let file = |name: &str| dir.join(format!("noisebench-selftest-bake-{id}-{name}"));
+	let (good, bad) = (file("good.lua"), file("bad.lua"));
+	let (raw, png, zeros) = (file("out.f32"), file("out.png"), file("zeros.f32"));
+	let res = (|| {
+		std::fs::write(&good, "return Noise.gradientX()").map_err(|err| err.to_string())?;
+		std::fs::write(&bad, "return Noise.nope()").map_err(|err| err.to_string())?;
+		let options = bake::BakeOptions {
+			size: 5,
+			..default()
+		};
+		bake::bake(&good, &raw, &options).map_err(|err| format!("{err:#}"))?;
+		bake::bake(&good, &png, &options).map_err(|err| format!("{err:#}"))?;
+		let samples = diff::read_raw(&raw).map_err(|err| format!("{err:#}"))?;
+		let expected: Vec<f32> = (0 .. 25)
+			.map(|i| sample_position(i % 5, i / 5, 5).x as f32)
+			.collect();
+		if samples != expected {
+			return Err(format!("raw bake read back as {samples:?}"));
+		}
+		let read = image::open(&png).map_err(|err| err.to_string())?;
+		if (read.width(), read.height()) != (5, 5) {
+			return Err(format!("PNG bake is {}x{}", read.width(), read.height()));
+		}
+		diff::write_raw(&zeros, &[0.0; 25]).map_err(|err| format!("{err:#}"))?;
+		let nonzero = expected.iter().filter(|&&v| v != 0.0).count();
+		for (base, changed) in [(&raw, 0), (&zeros, nonzero)] {
+			let options = bake::BakeOptions {
+				diffAgainst: Some(base.clone()),
+				..options
+			};
+			let summary = bake::bake(&good, &png, &options).map_err(|err| format!("{err:#}"))?;
+			if summary.as_ref().map(|summary| summary.changed) != Some(changed) {
+				return Err(format!("diff against {} summarized as {summary:?}", base.display()));
+			}
+		}
+		if bake::bake(&bad, &raw, &options).is_ok() {
+			return Err("a failing script baked".into());
+		}
+		Ok(String::new())
+	})();
+	for path in [good, bad, raw, png, zeros] {
+		std::fs::remove_file(path).ok();
+	}
+	res
+}
+
+fn check_saved_state() -> Result<String, String> {
+	let state = persist::AppState {
+		layerScripts: vec![Some("assets/scripts/a.lua".into()), None],
+		activeLayer: Some(1),
+		height: Some(2.5),
+		extent: Some(512.0),
+		camera2d: Some(persist::Camera2dState {
+			translation: [3.0, -4.0],
+			zoom: 0.5,
+		}),
+		uiScale: Some(1.25),
+		..default()
+	};
+	let text = serde_json::to_string(&state).map_err(|err| err.to_string())?;
+	let parsed: persist::AppState = serde_json::from_str(&text).map_err(|err| err.to_string())?;
+	if parsed != state {
+		return Err(format!("{text} read back as {parsed:?}"));
+	}
+	let partial: persist::AppState =
+		serde_json::from_str(r#"{"height": 3}"#).map_err(|err| err.to_string())?;
+	if partial.height != Some(3.0) ||
+		partial.camera3d.is_some() ||
+		!partial.layerScripts.is_empty()
+	{
+		return Err(format!("partial state read as {partial:?}"));
+	}
+
+	let (_, receiver) = crossbeam_channel::unbounded();
+	let mut uiState = UiState::new(receiver, HashMap::new());
+	let mut selectedTab = crate::SelectedTab(Tab::D2);
+	let scales = [(Some(1.25), 1.25), (Some(5.0), 2.0), (Some(f32::NAN), 1.0), (None, 1.0)];
+	for (saved, restored) in scales {
+		uiState.uiScale = 1.0;
+		let state = persist::AppState {
+			uiScale: saved,
+			..default()
+		};
+		state.apply(&mut uiState, &mut selectedTab);
+		if uiState.uiScale != restored {
+			return Err(format!("UI scale {saved:?} restored as {}", uiState.uiScale));
+		}
+	}
+	Ok(String::new())
+}
+
+/// Presets must read back as written, and files from other versions must load what they have,
+/// ignoring fields they don't know.
+fn check_presets() -> Result<String, String> {
+	let preset = presets::Preset {
+		script: Some("terrain/mountains.lua".into()),
+		heightScale: 2.5,
+		seed: -42,
+		width: 512,
+		height: 128,
+		params: [("octaves".to_string(), 5.0), ("roughness".to_string(), 0.35)].into(),
+	};
+	let text = preset.to_toml().map_err(|err| format!("{err:#}"))?;
+	let parsed = presets::Preset::from_toml(&text).map_err(|err| format!("{err:#}"))?;
+	if parsed != preset {
+		return Err(format!("{text} read back as {parsed:?}"));
+	}
+
+	let newer = r#"
+		script = "ridges.lua"
+		seed = 7
+		palette = "desert"
+
+		[params]
+		octaves = 4
+
+		[camera]
+		zoom = 2.0
+	"#;
+	let parsed = presets::Preset::from_toml(newer).map_err(|err| format!("{err:#}"))?;
+	let expected = presets::Preset {
+		script: Some("ridges.lua".into()),
+		seed: 7,
+		params: [("octaves".to_string(), 4.0)].into(),
+		..default()
+	};
+	if parsed != expected {
+		return Err(format!("newer preset read as {parsed:?}"));
+	}
+	if presets::Preset::from_toml("seed = \"seven\"").is_ok() {
+		return Err("a mistyped seed was accepted".into());
+	}
+	Ok(String::new())
+}
+
+fn check_stats() -> Result<String, String> {
+	let samples = [-0.5, 0.5, 0.5, -0.5, f32::NAN, 1.5];
+	let stats = stats::Stats::compute(&samples, (-1.0, 1.0));
+	if (stats.min, stats.max, stats.nonFinite) != (-0.5, 1.5, 1) {
+		return Err(format!("min {}, max {}, {} non-finite", stats.min, stats.max, stats.nonFinite));
+	}
+	// mean 0.3, squares averaging 0.65
+	if (stats.mean - 0.3).abs() > 1e-9 || (stats.stdDev - 0.56f64.sqrt()).abs() > 1e-9 {
+		return Err(format!("mean {}, std. dev. {}", stats.mean, stats.stdDev));
+	}
+	// the histogram widens to the sample above the declared range
+	if stats.histogramRange != (-1.0, 1.5) {
+		return Err(format!("histogram spans {:?}", stats.histogramRange));
+	}
+	let counted: u32 = stats.histogram.iter().sum();
+	let last = stats.histogram[stats::histogramBuckets - 1];
+	if counted != 5 || last != 1 {
+		return Err(format!("histogram counts {counted} samples, {last} in the top bucket"));
+	}
+	Ok(String::new())
+}
+
+fn check_seed_global() -> Result<String, String> {
+	let construct = |code: &str, seed| {
+		lua::construct_noisegen_seeded(code, seed).map_err(|err| format!("{err:#}"))
+	};
+	let pos = dvec2(0.5, 0.5);
+	// compiled trees are cached, a seed must not get another seed's tree
+	for seed in [0, 7, -3, 7] {
+		let v = construct("return Noise.const(Seed)", seed)?.eval(pos);
+		if v != seed as f32 {
+			return Err(format!("Seed read as {v} instead of {seed}"));
+		}
+	}
+	let unseeded = lua::construct_noisegen("return Noise.const(Seed)")
+		.map_err(|err| format!("{err:#}"))?
+		.eval(pos);
+	if unseeded != 0.0 {
+		return Err(format!("Seed defaults to {unseeded} instead of 0"));
+	}
+	let ignoring = "return Noise.simplex(3)";
+	if construct(ignoring, 0)?.eval(pos) != construct(ignoring, 5)?.eval(pos) {
+		return Err("Seed changed a script that doesn't read it".into());
+	}
+	Ok(String::new())
+}
+
+/// A script used between every other build stays cached however many others are built.
+fn check_compiled_cache() -> Result<String, String> {
+	let construct = |code: &str| lua::construct_noisegen(code).map_err(|err| format!("{err:#}"));
+	let kept = "return Noise.const(-0.125)";
+	let first = construct(kept)?;
+	for i in 0 .. 64 {
+		construct(&format!("return Noise.const({i} / 64)"))?;
+		if !Arc::ptr_eq(&construct(kept)?, &first) {
+			return Err(format!("evicted after {} other scripts", i + 1));
+		}
+	}
+	Ok(String::new())
+}
+
+/// Tables of layers must report the layers besides `height`, and returning something that can't
+/// become a `Noise` must name what was returned.
+fn check_returned_values() -> Result<String, String> {
+	let code = "return { height = 0, moisture = Noise.const(1), temperature = 2, range = {-1, 1} }";
+	let compiled = lua::construct_noisegen_with(code, &default()).map_err(|err| format!("{err:#}"))?;
+	if compiled.layers != ["moisture", "temperature"] {
+		return Err(format!("layers {:?}", compiled.layers));
+	}
+	let failures = [
+		("return 'simplex'", "string"),
+		("return true", "boolean"),
+		("return", "nil"),
+		("return { moisture = 1 }", "moisture"),
+	];
+	for (code, expected) in failures {
+		let err = match lua::construct_noisegen(code) {
+			Ok(_) => return Err(format!("`{code}` constructed without error")),
+			Err(err) => format!("{err:#}"),
+		};
+		if !err.contains(expected) {
+			return Err(format!("`{code}` failed with \"{err}\", which doesn't mention {expected}"));
+		}
+	}
+	Ok(String::new())
+}
+
+/// Returned functions must evaluate the same on pool threads, which load their own copy, keep the
+/// script's `Seed`, and report runtime errors once as NaN samples.
+fn check_lua_functions() -> Result<String, String> {
+	const diameter: usize = 8;
+	let construct = |code: &str, seed| {
+		lua::construct_noisegen_seeded(code, seed).map_err(|err| format!("{err:#}"))
+	};
+	let ast = construct("return function(x, y) return x * 2 + y + Seed end", 3)?;
+	let onPool = {
+		let ast = ast.clone();
+		block_on(AsyncComputeTaskPool::get().spawn(async move {
+			generate_samples(&ast, diameter, lua::Precision::F64)
+		}))
+	};
+	for (i, &v) in onPool.samples.iter().enumerate() {
+		let pos = sample_position(i % diameter, i / diameter, diameter).extend(0.0);
+		let here = ast.eval3(pos);
+		if v != here {
+			return Err(format!("sample {i} is {v} on the pool and {here} on this thread"));
+		}
+	}
+	let pos = dvec2(0.25, 0.5);
+	if ast.eval(pos) != 4.0 {
+		return Err(format!("function with Seed 3 evaluated to {}", ast.eval(pos)));
+	}
+	if let Some(err) = ast.take_lua_error() {
+		return Err(format!("unexpected error {err}"));
+	}
+
+	let failing = construct(
+		"return function(x) if x > 0.5 then error('too far') end return x end",
+		0,
+	)?;
+	let samples = generate_samples(&failing, diameter, lua::Precision::F64).samples;
+	let nans = samples.iter().filter(|v| v.is_nan()).count();
+	if nans == 0 || nans == samples.len() {
+		return Err(format!("{nans} of {} samples failed", samples.len()));
+	}
+	match failing.take_lua_error() {
+		Some(err) if err.contains("too far") => {},
+		other => return Err(format!("expected the script's error, got {other:?}")),
+	}
+	if let Some(err) = failing.take_lua_error() {
+		return Err(format!("error {err} wasn't cleared"));
+	}
+	Ok(format!("{nans} failed samples"))
+}
+
+fn check_script_params() -> Result<String, String> {
+	let code = "
+		local height = Params.slider('height', 0.5, 0, 2)
+		local again = Params.slider('height', 9, 0, 1)
+		local offset = Params.slider('offset', 7, -1, 1)
+		return Noise.const(height + again + offset * 10)
+	";
+	let run = |params: &[(&str, f64)]| {
+		let inputs = lua::ScriptInputs {
+			seed: 0,
+			params: params.iter().map(|&(name, v)| (name.to_owned(), v)).collect(),
+			..default()
+		};
+		lua::construct_noisegen_with(code, &inputs).map_err(|err| format!("{err:#}"))
+	};
+	let pos = dvec2(0.5, 0.5);
+
+	// defaults are clamped into range, and a repeated declaration reads the first one
+	let defaults = run(&[])?;
+	let names: Vec<_> = defaults.params.iter().map(|param| param.name.as_str()).collect();
+	if names != ["height", "offset"] || defaults.noise.eval(pos) != 11.0 {
+		return Err(format!("declared {names:?}, evaluating to {}", defaults.noise.eval(pos)));
+	}
+	// cached per input values, and clamped to the slider's range
+	let tuned = run(&[("height", 1.5), ("offset", -5.0), ("unused", 3.0)])?;
+	if tuned.noise.eval(pos) != -7.0 || tuned.params[0].value != 1.5 {
+		return Err(format!("tuned script evaluates to {}", tuned.noise.eval(pos)));
+	}
+	if run(&[])?.noise.eval(pos) != 11.0 {
+		return Err("defaults changed after running with other values".into());
+	}
+
+	let bad = lua::construct_noisegen("return Noise.const(Params.slider('x', 0, 1, -1))");
+	if bad.is_ok() {
+		return Err("inverted slider range accepted".into());
+	}
+	Ok(String::new())
+}
+
+/// Modules are required from the module directory only, through other modules too, and listed in
+/// `Compiled::modules`. A rerun after a module changed sees the change, cached tree or not.
+fn check_modules() -> Result<String, String> {
+	let id = std::process::id();
+	let dir = std::env::temp_dir().join(format!("noisebench-selftest-modules-{id}"));
+	let res = lua::with_module_dir(&dir, || check_modules_in(&dir));
+	let _ = std::fs::remove_dir_all(&dir);
+	res
+}
+
+fn check_modules_in(dir: &Path) -> Result<String, String> {
+	let write = |name: &str, code: &str| {
+		let path = dir.join(name);
+		std::fs::create_dir_all(path.parent().unwrap()).map_err(|err| err.to_string())?;
+		std::fs::write(&path, code).map_err(|err| format!("writing {}: {err}", path.display()))
+	};
+	write("lib/a.lua", "local b = require('lib.b') return { level = b.level * 2 }")?;
+	write("lib/b/init.lua", "return { level = 0.25 }")?;
+	let code = "return Noise.const(require('lib.a').level)";
+	let run = |code: &str| {
+		lua::construct_noisegen_with(code, &default()).map_err(|err| format!("{err:#}"))
+	};
+	let pos = dvec2(0.5, 0.5);
+
+	let compiled = run(code)?;
+	let root = dir.canonicalize().map_err(|err| err.to_string())?;
+	let expected = [root.join("lib/a.lua"), root.join("lib/b/init.lua")];
+	if compiled.noise.eval(pos) != 0.5 || compiled.modules != expected {
+		let value = compiled.noise.eval(pos);
+		return Err(format!("evaluates to {value} with the modules {:?}", compiled.modules));
+	}
+	write("lib/b/init.lua", "return { level = 0.125 }")?;
+	let rerun = run(code)?.noise.eval(pos);
+	if rerun != 0.25 {
+		return Err(format!("rerun after changing a module evaluates to {rerun}"));
+	}
+
+	for name in ["lib.missing", "../escape", "lib..a", "lib/a"] {
+		if run(&format!("return Noise.const(require('{name}').level)")).is_ok() {
+			return Err(format!("required `{name}`"));
+		}
+	}
+	// Lua's own path searchers are gone
+	if run("return Noise.const(package.loadlib and 1 or 0)")?.noise.eval(pos) != 0.0 {
+		return Err("package.loadlib is still available".into());
+	}
+	Ok(String::new())
+}
+
+/// Scripts in subdirectories are found too, and named by their path relative to the scripts
+/// directory, which keeps same-named files apart.
+fn check_scripts_dir() -> Result<String, String> {
+	std::fs::read_dir(scriptsDir).map_err(|err| format!("{scriptsDir} unreadable: {err}"))?;
+	let mut scripts = HashMap::new();
+	scan_scripts(Path::new(scriptsDir), &mut scripts);
+	for path in scripts.keys() {
+		let expected = Path::new(scriptsDir).join(&path.display).canonicalize();
+		if expected.as_ref().ok() != Some(&path.path) {
+			return Err(format!("{} is shown as {}", path.path.display(), path.display));
+		}
+	}
+	let nested = scripts.keys().filter(|path| path.display.contains('/')).count();
+	if !scripts.keys().any(|path| path.display == "examples/fractal_modes.lua") {
+		return Err("examples/fractal_modes.lua wasn't found".into());
+	}
+	Ok(format!("{} scripts, {nested} in subdirectories", scripts.len()))
+}
+
+fn check_generation() -> Result<String, String> {
+	const diameter: usize = 16;
+	let threadPool = AsyncComputeTaskPool::get();
+	let output = block_on(threadPool.spawn(async {
+		let ast = lua::construct_noisegen("return Noise.simplex(7):octaves(3)")
+			.map_err(|err| format!("{err:#}"))?;
+		Ok::<_, String>(generate_samples(&ast, diameter, lua::Precision::F64))
+	}))?;
+
+	if output.samples.len() != diameter * diameter {
+		return Err(format!("expected {} samples, got {}", diameter * diameter, output.samples.len()));
+	}
+	if let Some(v) = output.samples.iter().find(|v| !v.is_finite()) {
+		return Err(format!("non-finite sample {v}"));
+	}
+	let first = output.samples[0];
+	if output.samples.iter().all(|&v| v == first) {
+		return Err("all samples identical".into());
+	}
+	Ok(format!("{diameter}x{diameter}"))
+}
+
+/// Sub-samples must stay centered on their sample within its footprint, so linear trees come out
+/// unchanged, while averaging them must smooth out detail finer than a sample.
+fn check_supersampling() -> Result<String, String> {
+	const diameter: usize = 32;
+	let halfStep = 0.5 / (diameter - 1) as f64;
+	for supersampling in 1 .. 5 {
+		let offsets = subsample_offsets(supersampling, diameter);
+		let center = offsets.iter().sum::<DVec2>() / offsets.len() as f64;
+		if offsets.len() != supersampling.pow(2) || center.length() > 1e-12 {
+			return Err(format!("{supersampling}² offsets {offsets:?} aren't centered"));
+		}
+		if offsets.iter().any(|offset| offset.abs().max_element() >= halfStep) {
+			return Err(format!("{supersampling}² offsets {offsets:?} leave the footprint"));
+		}
+	}
+	if subsample_offsets(1, diameter) != [DVec2::ZERO] {
+		return Err("a single sub-sample is off center".into());
+	}
+
+	let generate = |code: &'static str, supersampling: usize| {
+		let threadPool = AsyncComputeTaskPool::get();
+		block_on(threadPool.spawn(async move {
+			let ast = lua::construct_noisegen(code).map_err(|err| format!("{err:#}"))?;
+			let (precision, ctx, cancel) = (lua::Precision::F64, default(), AtomicBool::new(false));
+			let grid = SampleGrid {
+				supersampling,
+				..SampleGrid::new(diameter)
+			};
+			let res = generate_samples_cancellable(&ast, &grid, precision, 0.0, &ctx, &cancel);
+			res.ok_or_else(|| "generation cancelled".to_string())
+		}))
+	};
+	let linear = "return Noise.gradientX() + Noise.gradientY() * 2";
+	let (plain, smoothed) = (generate(linear, 1)?, generate(linear, 3)?);
+	for (i, (&p, &s)) in plain.samples.iter().zip(&smoothed.samples).enumerate() {
+		if (p - s).abs() > 1e-5 {
+			return Err(format!("linear tree moved from {p} to {s} at sample {i}"));
+		}
+	}
+	if smoothed.supersampling != 3 {
+		return Err(format!("output records {}² sub-samples", smoothed.supersampling));
+	}
+
+	let variance = |samples: &[f32]| {
+		let mean = samples.iter().map(|&v| v as f64).sum::<f64>() / samples.len() as f64;
+		samples.iter().map(|&v| (v as f64 - mean).powi(2)).sum::<f64>() / samples.len() as f64
+	};
+	// several periods per sample, which a single sample per pixel aliases into full-range noise
+	let fine = "return Noise.simplex(7):scale(200)";
+	let aliased = variance(&generate(fine, 1)?.samples);
+	let averaged = variance(&generate(fine, 4)?.samples);
+	if averaged >= aliased / 2.0 {
+		return Err(format!("16 spp left variance {averaged:.3} of {aliased:.3}"));
+	}
+	Ok(format!("variance {aliased:.3} at 1 spp, {averaged:.3} at 16 spp"))
+}
+
+/// A generation over a domain must put its first and last samples on the domain's corners, and
+/// the 2D view must lay its sprite over the same points of the unit square's image.
+fn check_domain() -> Result<String, String> {
+	const diameter: usize = 17;
+	let domain = Domain {
+		origin: dvec2(0.25, -0.5),
+		size: 0.125,
+	};
+	let threadPool = AsyncComputeTaskPool::get();
+	let output = block_on(threadPool.spawn(async move {
+		let ast = lua::construct_noisegen("return Noise.gradientX() + Noise.gradientY() * 4")
+			.map_err(|err| format!("{err:#}"))?;
+		let grid = SampleGrid {
+			domain,
+			..SampleGrid::new(diameter)
+		};
+		let (ctx, cancel) = (default(), AtomicBool::new(false));
+		let res = generate_samples_cancellable(&ast, &grid, lua::Precision::F64, 0.0, &ctx, &cancel);
+		res.ok_or_else(|| "generation cancelled".to_string())
+	}))?;
+	let expected = |pos: DVec2| (pos.x + pos.y * 4.0) as f32;
+	let last = domain.origin + domain.size;
+	let corners = [(0, domain.origin), (diameter.pow(2) - 1, last)];
+	for (i, pos) in corners {
+		if (output.samples[i] - expected(pos)).abs() > 1e-6 {
+			return Err(format!("sample {i} is {} instead of {}", output.samples[i], expected(pos)));
+		}
+	}
+	if output.domain != domain {
+		return Err(format!("output records {:?}", output.domain));
+	}
+
+	let viewDiameter = 256;
+	let world = domain::to_world(dvec2(0.3, 0.7), viewDiameter);
+	let back = domain::to_domain(world, viewDiameter);
+	if back.distance(dvec2(0.3, 0.7)) > 1e-5 {
+		return Err(format!("(0.3, 0.7) maps back to {back}"));
+	}
+	let (center, scale) = domain.placement(diameter, viewDiameter).ok_or("no placement")?;
+	let wanted = domain::to_world(domain.apply(DVec2::splat(0.5)), viewDiameter);
+	// the sprite's width covers the domain's footprint in unit square texels
+	let width = scale as f64 * diameter as f64;
+	let texels = diameter as f64 / (diameter - 1) as f64;
+	let footprint = domain.size * (viewDiameter - 1) as f64 * texels;
+	if center.distance(wanted) > 1e-3 || (width - footprint).abs() > 1e-3 {
+		return Err(format!("sprite placed at {center} {width} wide"));
+	}
+	if Domain::default().placement(diameter, viewDiameter).is_some() {
+		return Err("unit square outputs are moved".into());
+	}
+	Ok(String::new())
+}
+
+/// Each coordinate mode must put the corner samples of a non-square output where it promises,
+/// generations must follow it, and scripts must see it in their `Coords` global.
+/// Pointer positions must map to the texel the 2D view draws under them whatever the UI scale, so
+/// the same logical point over the same zoom picks the same texel at either scale factor.
+fn check_viewport_coords() -> Result<String, String> {
+	use bevy::prelude::Transform;
+	use bevy_egui::egui::{self, pos2, Rect};
+	let output = NoiseOutput::new(64);
+	let rect = Rect::from_min_size(pos2(100.0, 50.0), egui::vec2(400.0, 300.0));
+	let inside = [pos2(300.0, 200.0), pos2(100.0, 50.0), pos2(420.5, 77.25), pos2(499.0, 349.0)];
+	let mut picked: Vec<Vec<Vec2>> = vec![];
+	for pixelsPerPoint in [1.0, 2.0] {
+		let viewportRect = ViewportRect {
+			rect,
+			pixelsPerPoint,
+		};
+		if viewportRect.pixel_size() != vec2(400.0, 300.0) * pixelsPerPoint {
+			return Err(format!("{pixelsPerPoint}× target of {}", viewportRect.pixel_size()));
+		}
+		// world units per logical point kept at 0.25, as zooming does
+		let camera = Transform::from_xyz(10.0, -20.0, 0.0)
+			.with_scale(Vec3::splat(0.25 / pixelsPerPoint));
+		let mut texels = vec![];
+		for pos in inside {
+			let uv = viewportRect.normalized(pos).ok_or(format!("{pos:?} outside {rect:?}"))?;
+			let back = viewportRect.from_normalized(uv);
+			if back.distance(pos) > 1e-3 {
+				return Err(format!("{pos:?} mapped back to {back:?}"));
+			}
+			let pixel = viewportRect.to_pixels(pos);
+			if pixel.distance(uv * viewportRect.pixel_size()) > 1e-3 {
+				return Err(format!("{pos:?} at pixel {pixel} of {}", viewportRect.pixel_size()));
+			}
+			let point = inspect::viewport_to_heightmap(
+				pixel,
+				viewportRect.pixel_size(),
+				&camera,
+				&output,
+				output.width,
+			);
+			// texel (32, 32) is at the origin, and rows go down while world Y goes up
+			let offset = (pos - rect.center()) * 0.25;
+			let expected = vec2(32.0 + 10.0 + offset.x, 32.0 + 20.0 + offset.y);
+			if point.texel.distance(expected) > 1e-3 {
+				let texel = point.texel;
+				let at = format!("at {pixelsPerPoint} pixels per point");
+				return Err(format!("{pos:?} picked texel {texel} instead of {expected} {at}"));
+			}
+			texels.push(point.texel);
+		}
+		if viewportRect.normalized(pos2(99.0, 60.0)).is_some() {
+			return Err("a position left of the viewport mapped into it".into());
+		}
+		picked.push(texels);
+	}
+	if picked[0].iter().zip(&picked[1]).any(|(a, b)| a.distance(*b) > 1e-3) {
+		return Err(format!("texels {:?} at 1× but {:?} at 2×", picked[0], picked[1]));
+	}
+	Ok(String::new())
+}
+
+fn check_coords() -> Result<String, String> {
+	let (width, height) = (7, 3);
+	let cases = [
+		(Coords::Normalized, dvec2(0.0, 0.0), dvec2(1.0, 1.0 / 3.0)),
+		(Coords::Centered, dvec2(-1.0, -1.0 / 3.0), dvec2(1.0, 1.0 / 3.0)),
+		(Coords::Pixels, dvec2(0.0, 0.0), dvec2(6.0, 2.0)),
+	];
+	for (coords, first, last) in cases {
+		let corners = [
+			coords.position(0, 0, width, height),
+			coords.position(width - 1, height - 1, width, height),
+		];
+		if corners[0].distance(first) > 1e-12 || corners[1].distance(last) > 1e-12 {
+			return Err(format!("{} corners at {corners:?}", coords.name()));
+		}
+
+		let threadPool = AsyncComputeTaskPool::get();
+		let output = block_on(threadPool.spawn(async move {
+			let ast = lua::construct_noisegen("return Noise.gradientX() + Noise.gradientY() * 4")
+				.map_err(|err| format!("{err:#}"))?;
+			let grid = SampleGrid {
+				width,
+				height,
+				coords,
+				..SampleGrid::new(width)
+			};
+			let (ctx, cancel) = (default(), AtomicBool::new(false));
+			let res =
+				generate_samples_cancellable(&ast, &grid, lua::Precision::F64, 0.0, &ctx, &cancel);
+			res.ok_or_else(|| "generation cancelled".to_string())
+		}))?;
+		if output.size() != (width, height) || output.samples.len() != width * height {
+			return Err(format!("{} output is {:?}", coords.name(), output.size()));
+		}
+		let expected = (last.x + last.y * 4.0) as f32;
+		let corner = output.samples[width * height - 1];
+		if (corner - expected).abs() > 1e-6 {
+			return Err(format!("{} last sample is {corner} instead of {expected}", coords.name()));
+		}
+
+		let inputs = lua::ScriptInputs { coords, ..default() };
+		let code = format!("return Coords == '{}' and Noise.gradientX() or nil", coords.name());
+		lua::construct_noisegen_with(&code, &inputs)
+			.map_err(|err| format!("{} script: {err:#}", coords.name()))?;
+	}
+	Ok(String::new())
+}
+
+/// Erosion must be reproducible from its seed, actually move material, and leave a finite
+/// heightmap finite.
+fn check_erosion() -> Result<String, String> {
+	let threadPool = AsyncComputeTaskPool::get();
+	let output = block_on(threadPool.spawn(async {
+		let ast = lua::construct_noisegen("return Noise.simplex(7):scale(3):octaves(4)")
+			.map_err(|err| format!("{err:#}"))?;
+		Ok::<_, String>(generate_samples(&ast, 64, lua::Precision::F64))
+	}))?;
+	let params = erosion::ErosionParams {
+		droplets: 2000,
+		..default()
+	};
+	let erode = |params| {
+		let mut eroded = output.clone();
+		eroded.erode(params);
+		eroded.samples
+	};
+	let first = erode(params);
+	if first != erode(params) {
+		return Err("the same seed eroded differently".into());
+	}
+	if first == output.samples {
+		return Err("erosion changed nothing".into());
+	}
+	if first == erode(erosion::ErosionParams { seed: 1, ..params }) {
+		return Err("another seed eroded identically".into());
+	}
+	if let Some(v) = first.iter().find(|v| !v.is_finite()) {
+		return Err(format!("eroded to {v}"));
+	}
+	let changed = first.iter().zip(&output.samples).filter(|(a, b)| a != b).count();
+	Ok(format!("{changed} samples changed"))
+}
+
+/// Requests sent in the same frame must merge in any order: duplicates collapse, regenerating
+/// every layer supersedes rebuilding the mesh, and a forced regeneration stays forced.
+fn check_coalescing() -> Result<String, String> {
+	use NoiseGenRequest::*;
+	let expect = |allLayers, layers: &[usize], force, rebuildMesh| CoalescedRequests {
+		allLayers,
+		layers: layers.iter().copied().collect(),
+		force,
+		rebuildMesh,
+	};
+	let cases = [
+		(vec![], expect(false, &[], false, false)),
+		(vec![AlgorithmChanged, AlgorithmChanged], expect(true, &[], false, false)),
+		(vec![ModelParamsChanged, ModelParamsChanged], expect(false, &[], false, true)),
+		(vec![ModelParamsChanged, AlgorithmChanged], expect(true, &[], false, false)),
+		(vec![AlgorithmChanged, ModelParamsChanged], expect(true, &[], false, false)),
+		(
+			vec![LayerChanged(1), LayerChanged(1), LayerChanged(0)],
+			expect(false, &[0, 1], false, false),
+		),
+		(vec![LayerChanged(2), ModelParamsChanged], expect(false, &[2], false, true)),
+		(vec![LayerChanged(2), AlgorithmChanged], expect(true, &[2], false, false)),
+		(vec![Regenerate, AlgorithmChanged], expect(true, &[], true, false)),
+		(vec![ModelParamsChanged, Regenerate, Regenerate], expect(true, &[], true, false)),
+	];
+	for (requests, expected) in cases {
+		let coalesced = coalesce_requests(requests.iter().copied());
+		if coalesced != expected {
+			return Err(format!("{requests:?} coalesced to {coalesced:?} instead of {expected:?}"));
+		}
+	}
+	Ok(String::new())
+}
+
+/// The session log must keep only its newest entries, and the panel only show the severities
+/// picked.
+fn check_session_log() -> Result<String, String> {
+	let mut log = log_panel::SessionLog::default();
+	log.shown = [true; 4];
+	for i in 0 .. 600 {
+		let severity = if i % 2 == 0 { Severity::Info } else { Severity::Debug };
+		log.record(severity, LogKind::Generation, None, format!("entry {i}"));
+	}
+	let entries = log.shown_entries();
+	if entries.len() != 500 || entries[0].message != "entry 100" {
+		let first = entries.first().map(|entry| &entry.message);
+		return Err(format!("kept {} entries starting at {first:?}", entries.len()));
+	}
+	if entries.windows(2).any(|pair| pair[0].time > pair[1].time) {
+		return Err("entries out of order".into());
+	}
+	let line = entries[0].to_string();
+	if !line.ends_with(" info  [generation] entry 100") {
+		return Err(format!("entry shown as {line:?}"));
+	}
+
+	log.shown = [true, true, true, false];
+	let shown = log.shown_entries();
+	if shown.len() != 250 || shown.iter().any(|entry| entry.severity == Severity::Debug) {
+		return Err(format!("{} entries shown with debug ones hidden", shown.len()));
+	}
+	Ok(String::new())
+}
+
+/// Replaying a recorded generation must bring back the settings it ran with, Keep must leave them
+/// live and Exit must restore the ones from before. Entries from before a setting was recorded
+/// must still load.
+fn check_session_replay() -> Result<String, String> {
+	let id = std::process::id();
+	let dir = std::env::temp_dir().join(format!("noisebench-selftest-session-{id}"));
+	let res = (|| {
+		std::fs::create_dir_all(&dir).map_err(|err| err.to_string())?;
+		let code = "return Noise.simplex(Seed) * Params.slider('amplitude', 1, 0, 2)";
+		let script = dir.join("seeded.lua");
+		std::fs::write(&script, code).map_err(|err| err.to_string())?;
+		let script = InternedPath::new(script).map_err(|err| format!("{err:#}"))?;
+		let (_, receiver) = crossbeam_channel::unbounded();
+		let scripts = HashMap::from([(script.clone(), code.to_owned())]);
+		let mut uiState = UiState::new(receiver, scripts);
+		*uiState.selected_mut() = Some(script.clone());
+		let mut session = SessionRecorder::default();
+		session.enabled = true;
+		session.entries.clear();
+		session.path = dir.join("session.jsonl");
+
+		let amplitude = (script.clone(), "amplitude".to_owned());
+		let live = |uiState: &UiState| (uiState.seed, uiState.params.get(&amplitude).copied());
+		uiState.seed = 42;
+		uiState.params.insert(amplitude.clone(), 0.5);
+		session.record(&uiState, code);
+		uiState.seed = 7;
+		uiState.params.insert(amplitude.clone(), 1.5);
+		session.record(&uiState, code);
+		let saved: Vec<_> = std::fs::read_to_string(&session.path)
+			.map_err(|err| err.to_string())?
+			.lines()
+			.map(serde_json::from_str::<session::SessionEntry>)
+			.collect::<Result<_, _>>()
+			.map_err(|err| err.to_string())?;
+		let recorded: Vec<_> = saved
+			.iter()
+			.map(|entry| (entry.seed, entry.params.get("amplitude").copied()))
+			.collect();
+		if recorded != [(42, Some(0.5)), (7, Some(1.5))] {
+			return Err(format!("recorded seeds and amplitudes {recorded:?}"));
+		}
+
+		uiState.seed = 3;
+		uiState.params.remove(&amplitude);
+		if !session.step_to(0, &mut uiState) || live(&uiState) != (42, Some(0.5)) {
+			return Err(format!("replayed the first entry with {:?}", live(&uiState)));
+		}
+		session.end_replay(false, &mut uiState);
+		if live(&uiState) != (3, None) {
+			return Err(format!("{:?} after leaving replay", live(&uiState)));
+		}
+		session.step_to(1, &mut uiState);
+		if session.end_replay(true, &mut uiState) || live(&uiState) != (7, Some(1.5)) {
+			return Err(format!("{:?} after keeping the second entry", live(&uiState)));
+		}
+
+		let old = format!(
+			r#"{{"timestamp":0,"script":{:?},"hash":{},"diameter":64,"height":1}}"#,
+			script.path,
+			content_hash(code)
+		);
+		let old: session::SessionEntry = serde_json::from_str(&old).map_err(|err| err.to_string())?;
+		if old.seed != 0 || !old.params.is_empty() || old.output_size() != (64, 64) {
+			return Err(format!("old entry loaded with seed {} and {:?}", old.seed, old.params));
+		}
+		Ok(String::new())
+	})();
+	let _ = std::fs::remove_dir_all(&dir);
+	res
+}
+
+/// Screenshot names must carry the UTC time they were taken, and read back rows lose their padding
+/// with BGRA swapped to RGBA.
+fn check_screenshots() -> Result<String, String> {
+	for (secs, millis, expected) in [
+		(0, 0, "1970-01-01_00-00-00-000"),
+		(951782400, 5, "2000-02-29_00-00-00-005"),
+		(1792108923, 250, "2026-10-16_00-02-03-250"),
+	] {
+		let name = screenshot::timestamp(Duration::new(secs, millis * 1_000_000));
+		if name != expected {
+			return Err(format!("{secs} s named {name}, expected {expected}"));
+		}
+	}
+
+	// two rows of two pixels, padded to 12 bytes
+	let padded = [1, 2, 3, 4, 5, 6, 7, 8, 0, 0, 0, 0, 9, 10, 11, 12, 13, 14, 15, 16, 0, 0, 0, 0];
+	let rgba = screenshot::unpad_rows(&padded, 2, 12, false);
+	if rgba != (1 ..= 16).collect::<Vec<u8>>() {
+		return Err(format!("unpadded to {rgba:?}"));
+	}
+	let bgra = screenshot::unpad_rows(&padded, 2, 12, true);
+	if bgra[.. 8] != [3, 2, 1, 4, 7, 6, 5, 8] {
+		return Err(format!("BGRA swapped to {:?}", &bgra[.. 8]));
+	}
+	Ok(String::new())
+}
+
+/// A single worker must generate the same samples as the default limit, and a panic must come out
+/// as an error instead of unwinding past the task.
+fn check_worker_limit() -> Result<String, String> {
+	let ast = lua::construct_noisegen("return Noise.simplex(3):octaves(4)")
+		.map_err(|err| format!("{err:#}"))?;
+	let configured = workers::configured();
+	let full = generate_samples(&ast, 67, lua::Precision::F64);
+	workers::set_limit(Some(1));
+	let single = generate_samples(&ast, 67, lua::Precision::F64);
+	workers::set_limit(configured);
+	let differing = full
+		.samples
+		.iter()
+		.zip(&single.samples)
+		.filter(|(a, b)| a.to_bits() != b.to_bits())
+		.count();
+	if differing > 0 {
+		return Err(format!("{differing} samples differ with a single worker"));
+	}
+
+	let res = workers::catch_panic::<()>(|| panic!("on purpose"));
+	if res != Err("generation panicked: on purpose".into()) {
+		return Err(format!("panic caught as {res:?}"));
+	}
+	let formatted = workers::catch_panic::<()>(|| panic!("at {}", 3));
+	if formatted != Err("generation panicked: at 3".into()) {
+		return Err(format!("formatted panic caught as {formatted:?}"));
+	}
+	Ok(String::new())
+}
+
+/// Generations running at once must share the workers instead of queueing behind each other or
+/// each taking them all. With a worker per output, three 512² outputs generated together must
+/// finish in about the time the slowest takes on a worker of its own, progressing side by side
+/// without ever having more workers busy than the limit.
+fn check_concurrent_generations() -> Result<String, String> {
+	const diameter: usize = 512;
+	let codes = [
+		"return Noise.simplex(1):octaves(2)",
+		"return Noise.simplex(2):octaves(3)",
+		"return Noise.simplex(3):octaves(4)",
+	];
+	let threadPool = AsyncComputeTaskPool::get();
+	let threads = threadPool.thread_num();
+	if threads < codes.len() {
+		return Ok(format!("skipped, the compute pool has {threads} threads"));
+	}
+	let asts = codes
+		.into_iter()
+		.map(lua::construct_noisegen)
+		.collect::<Result<Vec<_>, _>>()
+		.map_err(|err| format!("{err:#}"))?;
+	let precision = lua::Precision::F64;
+	let configured = workers::configured();
+	let res = (|| {
+		workers::set_limit(Some(1));
+		let mut slowest = Duration::ZERO;
+		for ast in &asts {
+			let ast = ast.clone();
+			let start = Instant::now();
+			block_on(threadPool.spawn(async move { generate_samples(&ast, diameter, precision) }));
+			slowest = slowest.max(start.elapsed());
+		}
+
+		workers::set_limit(Some(codes.len()));
+		let start = Instant::now();
+		let tasks: Vec<_> = asts
+			.iter()
+			.map(|ast| {
+				let ast = ast.clone();
+				let progress = Arc::new(workers::Progress::default());
+				let taskProgress = progress.clone();
+				let task = threadPool.spawn(async move {
+					let (grid, cancel) = (SampleGrid::new(diameter), AtomicBool::new(false));
+					let ctx = default();
+					generate_colored_cancellable(
+						&ast,
+						None,
+						&grid,
+						precision,
+						0.0,
+						&ctx,
+						&cancel,
+						&taskProgress,
+					)
+				});
+				(progress, task)
+			})
+			.collect();
+		let (mut mostBusy, mut sideBySide) = (0, false);
+		while tasks.iter().any(|(_, task)| !task.is_finished()) {
+			mostBusy = mostBusy.max(workers::busy());
+			let done = tasks.iter().map(|(progress, _)| progress.fraction());
+			sideBySide |= done.clone().all(|done| done > 0.0 && done < 1.0);
+			std::thread::sleep(Duration::from_micros(200));
+		}
+		let together = start.elapsed();
+		for (progress, task) in tasks {
+			block_on(task).ok_or("generation cancelled")?;
+			if progress.fraction() != 1.0 {
+				return Err(format!("finished at {} of its rows", progress.fraction()));
+			}
+		}
+
+		if mostBusy > codes.len() {
+			return Err(format!("{mostBusy} workers busy with a limit of {}", codes.len()));
+		}
+		if !sideBySide {
+			return Err("the outputs were generated one after another".into());
+		}
+		if together > slowest * 3 / 2 {
+			let times = format!("{together:.2?} together, {slowest:.2?} for the slowest");
+			return Err(format!("generated in {times}"));
+		}
+		Ok(format!("{together:.2?} together, {slowest:.2?} for the slowest alone"))
+	})();
+	workers::set_limit(configured);
+	res
+}
+
+/// Drags go to the pane they started on and the wheel to the hovered one, and nothing, not even a
+/// drag still held, carries over a tab switch. Plain shortcut keys don't fire with a modifier.
+fn check_input_routing() -> Result<String, String> {
+	let motion = [MouseMotion { delta: vec2(3.0, 4.0) }];
+	let scroll = [MouseWheel {
+		unit: MouseScrollUnit::Line,
+		x: 0.0,
+		y: 2.0,
+		window: Entity::PLACEHOLDER,
+	}];
+	let mut buttons = ButtonInput::<MouseButton>::default();
+	let mut input = ViewportInput::default();
+	type Buttons = ButtonInput<MouseButton>;
+	let frame = |input: &mut ViewportInput, tab, hovered, buttons: &mut Buttons| {
+		*input = input.route(tab, hovered, buttons, &motion, &scroll, true);
+		buttons.clear();
+	};
+	let expect = |step: &str, input: &ViewportInput, drag: [f32; 2], wheel: [f32; 2]| {
+		// in units of the frame's motion and scroll
+		let got = [Tab::D2, Tab::D3]
+			.map(|pane| (input.drag_motion(pane).x / 3.0, input.scroll(pane) / 2.0));
+		let wanted = [(drag[0], wheel[0]), (drag[1], wheel[1])];
+		if got != wanted {
+			return Err(format!("{step}: D2, D3 got {got:?}, not {wanted:?}"));
+		}
+		Ok(())
+	};
+
+	frame(&mut input, Tab::D2, Some(Tab::D2), &mut buttons);
+	expect("hovering 2D", &input, [0.0, 0.0], [1.0, 0.0])?;
+	buttons.press(MouseButton::Left);
+	frame(&mut input, Tab::D2, Some(Tab::D2), &mut buttons);
+	expect("pressed on 2D", &input, [1.0, 0.0], [1.0, 0.0])?;
+	frame(&mut input, Tab::D3, Some(Tab::D3), &mut buttons);
+	expect("switched to 3D mid-drag", &input, [0.0, 0.0], [0.0, 0.0])?;
+	frame(&mut input, Tab::D3, Some(Tab::D3), &mut buttons);
+	expect("held after the switch", &input, [0.0, 0.0], [0.0, 1.0])?;
+	buttons.release(MouseButton::Left);
+	frame(&mut input, Tab::D3, Some(Tab::D3), &mut buttons);
+	buttons.press(MouseButton::Left);
+	frame(&mut input, Tab::D3, Some(Tab::D3), &mut buttons);
+	expect("pressed on 3D", &input, [0.0, 1.0], [0.0, 1.0])?;
+	buttons.release(MouseButton::Left);
+	frame(&mut input, Tab::D3, Some(Tab::D2), &mut buttons);
+	expect("hovering the hidden 2D pane", &input, [0.0, 0.0], [0.0, 0.0])?;
+
+	frame(&mut input, Tab::Both, Some(Tab::D2), &mut buttons);
+	if input.focused() != Tab::D3 || !input.keyboard(Tab::D3) || input.keyboard(Tab::D2) {
+		return Err(format!("keyboard went to {:?} without a click", input.focused()));
+	}
+	buttons.press(MouseButton::Left);
+	frame(&mut input, Tab::Both, Some(Tab::D2), &mut buttons);
+	if input.focused() != Tab::D2 {
+		return Err("clicking the 2D pane didn't focus it".into());
+	}
+	frame(&mut input, Tab::Both, Some(Tab::D3), &mut buttons);
+	expect("dragged from 2D over 3D", &input, [1.0, 0.0], [0.0, 1.0])?;
+
+	let mut keyboard = ButtonInput::<KeyCode>::default();
+	keyboard.press(KeyCode::KeyS);
+	if !input::just_pressed_alone(&keyboard, KeyCode::KeyS) {
+		return Err("S alone wasn't taken as pressed".into());
+	}
+	keyboard.clear();
+	keyboard.release(KeyCode::KeyS);
+	keyboard.press(KeyCode::ControlLeft);
+	keyboard.press(KeyCode::KeyS);
+	if input::just_pressed_alone(&keyboard, KeyCode::KeyS) {
+		return Err("Ctrl+S was taken as S alone".into());
+	}
+	Ok(String::new())
+}
+
+/// The split panes and the divider between them must tile the central panel in either
+/// orientation, and neither pane can be dragged smaller than a tenth of it.
+fn check_pane_layout() -> Result<String, String> {
+	use bevy_egui::egui::{pos2, vec2, Rect};
+	let panel = Rect::from_min_size(pos2(10.0, 20.0), vec2(800.0, 600.0));
+	for stacked in [false, true] {
+		for fraction in [0.5, 0.0, 1.0, 0.3] {
+			let layout = PaneLayout { stacked, fraction };
+			let [pane2d, divider, pane3d] = layout.split(panel);
+			let (length, thickness) = if stacked {
+				(panel.height(), divider.height())
+			} else {
+				(panel.width(), divider.width())
+			};
+			let share = if stacked { pane2d.height() } else { pane2d.width() } / length;
+			let covered = pane2d.union(divider).union(pane3d);
+			let area = pane2d.area() + divider.area() + pane3d.area();
+			if covered != panel || (area - panel.area()).abs() > 1e-2 || thickness != 6.0 {
+				let panes = [pane2d, divider, pane3d];
+				return Err(format!("{layout:?} splits {panel:?} into {panes:?}"));
+			}
+			if !(0.09 ..= 0.91).contains(&share) {
+				return Err(format!("{layout:?} gives the 2D pane {share} of the panel"));
+			}
+		}
+	}
+
+	let mut layout = PaneLayout::default();
+	layout.drag(panel, vec2(-1000.0, 300.0));
+	if layout.fraction != 0.1 {
+		return Err(format!("dragged left past the edge to {}", layout.fraction));
+	}
+	layout.drag(panel, vec2(400.0, 0.0));
+	if (layout.fraction - 0.6).abs() > 1e-6 {
+		return Err(format!("dragged half the width to {}", layout.fraction));
+	}
+	layout.stacked = true;
+	layout.drag(panel, vec2(0.0, 1000.0));
+	if layout.fraction != 0.9 {
+		return Err(format!("dragged down past the edge to {}", layout.fraction));
+	}
+	Ok(String::new())
+}
+
+/// The graph must number nodes like `Noise::visit`, center parents over their children, and
+/// render the root's thumbnail as the output itself.
+fn check_noise_graph() -> Result<String, String> {
+	let ast = lua::construct_noisegen(
+		"return (Noise.simplex(1):octaves(3) + Noise.worley(2) * 0.5):withRange(-2, 2)",
+	)
+	.map_err(|err| format!("{err:#}"))?;
+	let nodes = graph::layout(&ast);
+	let mut visited = vec![];
+	ast.visit(&mut |id, node| visited.push((id, node.to_string())));
+	if nodes.len() != visited.len() {
+		return Err(format!("{} graph nodes for {} tree nodes", nodes.len(), visited.len()));
+	}
+	for ((id, label), node) in visited.iter().zip(&nodes) {
+		if node.label != *label {
+			return Err(format!("node #{id} labeled {:?} instead of {label:?}", node.label));
+		}
+		let children: Vec<_> = nodes.iter().filter(|child| child.parent == Some(*id)).collect();
+		if children.iter().any(|child| child.depth != node.depth + 1) {
+			return Err(format!("children of #{id} aren't a row below it"));
+		}
+		let (Some(first), Some(last)) = (children.first(), children.last()) else {
+			continue;
+		};
+		if node.column != (first.column + last.column) / 2.0 {
+			return Err(format!("#{id} at column {} isn't centered over its children", node.column));
+		}
+	}
+	let leaves: Vec<_> = nodes
+		.iter()
+		.enumerate()
+		.filter(|&(id, _)| nodes.iter().all(|node| node.parent != Some(id)))
+		.map(|(_, node)| node.column)
+		.collect();
+	if leaves.iter().zip(0 ..).any(|(&column, i)| column != i as f32) {
+		return Err(format!("leaves at columns {leaves:?}"));
+	}
+
+	let output = generate_samples(&ast, thumbnails::thumbnailSize, lua::Precision::F64);
+	let thumbnail = graph::Sampling::of(&output).thumbnail(&ast);
+	let (min, max) = graph::finite_range(&output.samples).ok_or("flat output")?;
+	for (i, (&v, &t)) in output.samples.iter().zip(&thumbnail).enumerate() {
+		let expected = (v - min) / (max - min) * 2.0 - 1.0;
+		if (t - expected).abs() > 1e-4 {
+			return Err(format!("root thumbnail sample {i} is {t}, the output's is {expected}"));
+		}
+	}
+	if graph::finite_range(&[f32::NAN, 1.0, f32::INFINITY, -1.0]) != Some((-1.0, 1.0)) {
+		return Err("non-finite samples count towards the range".into());
+	}
+	Ok(String::new())
+}
+
+/// Paths of the same file must intern to the same entry, which is dropped along with the last
+/// `InternedPath` of it. Missing files are errors rather than panics.
+fn check_interned_paths() -> Result<String, String> {
+	let id = std::process::id();
+	let dir = std::env::temp_dir().join(format!("noisebench-selftest-intern-{id}"));
+	let res = (|| {
+		std::fs::create_dir_all(dir.join("sub")).map_err(|err| err.to_string())?;
+		for name in ["a.lua", "b.lua"] {
+			std::fs::write(dir.join(name), "return Noise.const(0)").map_err(|err| err.to_string())?;
+		}
+		let intern = |path: PathBuf| InternedPath::new(path).map_err(|err| format!("{err:#}"));
+		let a = intern(dir.join("a.lua"))?;
+		let again = intern(dir.join("sub").join("..").join("a.lua"))?;
+		if !Arc::ptr_eq(&a.0, &again.0) {
+			return Err("the same file interned twice".into());
+		}
+		let b = intern(dir.join("b.lua"))?;
+		if a == b || a.display != "a.lua" {
+			return Err(format!("{a:?} and {b:?} interned as the same or misnamed"));
+		}
+		if InternedPath::new(dir.join("missing.lua")).is_ok() {
+			return Err("interned a missing file".into());
+		}
+
+		let canonical = a.path.clone();
+		drop(a);
+		if !InternedPath::is_interned(&canonical) {
+			return Err("dropped while a clone is alive".into());
+		}
+		drop(again);
+		if InternedPath::is_interned(&canonical) || !InternedPath::is_interned(&b.path) {
+			return Err("entries aren't dropped along with their last path, or too eagerly".into());
+		}
+		let a = intern(dir.join("a.lua"))?;
+		if a.path != canonical {
+			return Err(format!("interned again as {}", a.path.display()));
+		}
+
+		#[cfg(unix)]
+		{
+			use std::os::unix::ffi::OsStrExt;
+			let name = std::ffi::OsStr::from_bytes(b"\xffbad.lua");
+			std::fs::write(dir.join(name), "").map_err(|err| err.to_string())?;
+			let bad = intern(dir.join(name))?;
+			if bad.display != "\u{fffd}bad.lua" {
+				return Err(format!("non-UTF-8 name shown as {:?}", bad.display));
+			}
+		}
+		Ok(String::new())
+	})();
+	let _ = std::fs::remove_dir_all(&dir);
+	res
+}
+
+/// Thumbnails must be keyed by the script's inputs and, on disk, by its modules' contents, and
+/// scripts with the same source share one until the last of them is forgotten.
+fn check_thumbnail_keys() -> Result<String, String> {
+	let code = "return Noise.simplex(Seed)";
+	let inputs = lua::ScriptInputs::default();
+	let key = thumbnails::inputs_key(code, &inputs);
+	let seeded = lua::ScriptInputs { seed: 1, ..default() };
+	let mut tuned = lua::ScriptInputs::default();
+	tuned.params.insert("amplitude".into(), 0.5);
+	if [seeded, tuned].iter().any(|other| thumbnails::inputs_key(code, other) == key) {
+		return Err("the seed or a slider didn't change the key".into());
+	}
+
+	let id = std::process::id();
+	let dir = std::env::temp_dir().join(format!("noisebench-selftest-thumbnails-{id}"));
+	let res = (|| {
+		std::fs::create_dir_all(&dir).map_err(|err| err.to_string())?;
+		let module = dir.join("shared.lua");
+		std::fs::write(&module, "return 1").map_err(|err| err.to_string())?;
+		let before = thumbnails::cache_key(key, &[module.clone()]);
+		std::fs::write(&module, "return 2").map_err(|err| err.to_string())?;
+		if thumbnails::cache_key(key, &[module]) == before {
+			return Err("editing a module kept the disk cache key".into());
+		}
+
+		for name in ["a.lua", "b.lua"] {
+			std::fs::write(dir.join(name), code).map_err(|err| err.to_string())?;
+		}
+		let intern =
+			|name: &str| InternedPath::new(dir.join(name)).map_err(|err| format!("{err:#}"));
+		let (a, b) = (intern("a.lua")?, intern("b.lua")?);
+		let mut thumbs = thumbnails::Thumbnails::default();
+		thumbs.diskCache = None;
+		thumbs.request(&a, code, &inputs);
+		thumbs.request(&b, code, &inputs);
+		let queued = thumbs.memory_bytes();
+		if queued == 0 {
+			return Err("nothing queued".into());
+		}
+		thumbs.forget(&a);
+		if thumbs.memory_bytes() != queued {
+			return Err("forgetting one script dropped the preview another shows".into());
+		}
+		thumbs.forget(&b);
+		if thumbs.memory_bytes() != 0 {
+			return Err("the preview outlived the scripts showing it".into());
+		}
+		Ok(String::new())
+	})();
+	let _ = std::fs::remove_dir_all(&dir);
+	res
+}
+
+/// Held back requests must merge into one doing at least what each asked for, and only fire once
+/// no request came for the whole delay.
+fn check_debounce() -> Result<String, String> {
+	use NoiseGenRequest::*;
+	let cases = [
+		(ModelParamsChanged, AlgorithmChanged, AlgorithmChanged),
+		(AlgorithmChanged, ModelParamsChanged, AlgorithmChanged),
+		(ModelParamsChanged, ModelParamsChanged, ModelParamsChanged),
+		(LayerChanged(1), LayerChanged(1), LayerChanged(1)),
+		(LayerChanged(1), LayerChanged(0), AlgorithmChanged),
+		(LayerChanged(2), ModelParamsChanged, AlgorithmChanged),
+		(Regenerate, AlgorithmChanged, Regenerate),
+		(ModelParamsChanged, Regenerate, Regenerate),
+	];
+	for (a, b, expected) in cases {
+		let merged = debounce::merge_requests(a, b);
+		if merged != expected {
+			return Err(format!("{a:?} and {b:?} merged to {merged:?} instead of {expected:?}"));
+		}
+		// the merged request does everything the two would have done together
+		let (both, alone) = (coalesce_requests([a, b]), coalesce_requests([merged]));
+		let covered = alone.allLayers ||
+			(!both.allLayers &&
+				both.layers.is_subset(&alone.layers) &&
+				(alone.rebuildMesh || !both.rebuildMesh));
+		if !covered || both.force && !alone.force {
+			return Err(format!("{merged:?} does less than {a:?} and {b:?}"));
+		}
+	}
+
+	let mut regen = RegenDebounce::default();
+	let start = Instant::now();
+	let after = |millis| start + Duration::from_millis(millis);
+	regen.delay = Duration::from_millis(300);
+	regen.request_at(ModelParamsChanged, start);
+	regen.request_at(AlgorithmChanged, after(200));
+	if let Some(fired) = regen.take_due(after(400)) {
+		return Err(format!("{fired:?} fired 200ms after the latest request"));
+	}
+	regen.request_at(ModelParamsChanged, after(450));
+	match regen.take_due(after(750)) {
+		Some(AlgorithmChanged) => {},
+		fired => return Err(format!("fired {fired:?} once due instead of AlgorithmChanged")),
+	}
+	if regen.take_due(after(2000)).is_some() {
+		return Err("fired twice".into());
+	}
+	Ok(String::new())
+}
+
+/// The overview must see every corner of the terrain's bounding box at any extent and height
+/// scale, the top-down view must start above its highest point, and both must come back as the
+/// angles `camera_controller_3d` continues from.
+fn check_bookmarks() -> Result<String, String> {
+	let fov = bevy::prelude::PerspectiveProjection::default().fov;
+	let bookmarks = CameraBookmarks::default();
+	for (extent, height) in [(1.0, 0.2), (10.0, 0.5), (100.0, 40.0), (2.0, -3.0)] {
+		let scale = TerrainScale { extent, height };
+		let pose = |bookmark| {
+			bookmarks::preset_pose(bookmark, &bookmarks, scale)
+				.ok_or_else(|| format!("no pose for {bookmark:?}"))
+		};
+		let overview = pose(Bookmark::Overview)?;
+		let forward = overview.rotation * Vec3::NEG_Z;
+		for corner in 0 .. 8 {
+			let sign = |bit: i32| if corner & bit == 0 { -1.0 } else { 1.0 };
+			let point = vec3(sign(1) * extent / 2.0, sign(2) * height, sign(4) * extent / 2.0);
+			let angle = forward.angle_between(point - overview.translation);
+			if angle > fov / 2.0 {
+				return Err(format!("overview of {extent}×{height} misses {point}"));
+			}
+		}
+		let top = pose(Bookmark::TopDown)?;
+		if !top.orthographic || top.translation.y <= height.abs() {
+			return Err(format!("top-down view of {extent}×{height} at {}", top.translation));
+		}
+		for (bookmark, pose, expected) in
+			[("overview", overview, vec2(-135.0, -45.0)), ("top-down", top, vec2(0.0, -89.9))]
+		{
+			let (yaw, pitch, _) = pose.rotation.to_euler(bevy::prelude::EulerRot::YXZ);
+			let angles = vec2(yaw.to_degrees(), pitch.to_degrees());
+			if angles.distance(expected) > 0.05 {
+				return Err(format!("{bookmark} view comes back at {angles} instead of {expected}"));
+			}
+		}
+	}
+	let scale = TerrainScale { extent: 1.0, height: 1.0 };
+	if bookmarks::preset_pose(Bookmark::Slot(0), &bookmarks, scale).is_some() {
+		return Err("an empty slot has a pose".into());
+	}
+	Ok(String::new())
+}
+
+/// Grayscale must leave values as the 2D view always showed them, gradient maps must reach their
+/// end colors and only brighten along viridis, and the highlight must catch everything outside
+/// the range, NaNs included, before contrast and brightness pull it back in.
+/// Colors a script returns are clamped per channel, generated alongside identical heights, shown
+/// instead of the colormap and reseeded along with the height.
+fn check_script_colors() -> Result<String, String> {
+	let compile = |code: &str, seed| {
+		let inputs = lua::ScriptInputs { seed, ..default() };
+		compile_script(code, &inputs, false).map_err(|err| format!("{err:#}"))
+	};
+	let code = "local h = Noise.simplex(1):scale(3)
+		return { height = h, color = { Noise.gradientX(), 0.5, Noise.gradientY() * 2 - 0.5 } }";
+	let compiled = compile(code, 0)?;
+	let Some(color) = compiled.color.as_deref() else {
+		return Err("color field isn't compiled".into());
+	};
+	if !compiled.layers.is_empty() {
+		return Err(format!("color reported among the layers {:?}", compiled.layers));
+	}
+	let (grid, precision, ctx) = (SampleGrid::new(5), lua::Precision::F64, default());
+	let cancel = AtomicBool::new(false);
+	let output = generate_colored_cancellable(
+		&compiled.noise,
+		Some(color),
+		&grid,
+		precision,
+		0.0,
+		&ctx,
+		&cancel,
+		&default(),
+	)
+	.ok_or("generation cancelled")?;
+	if output.samples != generate_samples(&compiled.noise, 5, precision).samples {
+		return Err("colors changed the heights".into());
+	}
+	let Some(colors) = &output.colors else {
+		return Err("no colors generated".into());
+	};
+	for (i, &[r, g, b]) in colors.iter().enumerate() {
+		let (x, y) = ((i % 5) as f32 / 4.0, (i / 5) as f32 / 4.0);
+		let blue = (y * 2.0 - 0.5).clamp(0.0, 1.0);
+		if (r - x).abs() > 1e-6 || g != 0.5 || (b - blue).abs() > 1e-6 {
+			return Err(format!("color {:?} at ({x}, {y})", [r, g, b]));
+		}
+	}
+	let image = colormap::rgba8_image(&output, &default());
+	let expected = bevy::color::Color::linear_rgb(1.0, 0.5, 0.0).to_srgba().to_u8_array();
+	if image.get_pixel(4, 0).0 != expected {
+		return Err(format!("colored pixel shown as {:?}", image.get_pixel(4, 0).0));
+	}
+
+	let shared = "local h = Noise.simplex(1)
+		return { height = h, color = { h, h, h:scale(2) } }";
+	let reseeded = compile(shared, 5)?;
+	let color = reseeded.color.ok_or("reseeded color missing")?;
+	for &(x, y) in probePositions {
+		let pos = dvec2(x, y);
+		if color[0].eval(pos) != reseeded.noise.eval(pos) {
+			return Err(format!("channel and height reseeded apart at ({x}, {y})"));
+		}
+	}
+	if compile("return Noise.simplex(1)", 0)?.color.is_some() {
+		return Err("plain Noise has colors".into());
+	}
+	for code in ["return { height = 0, color = { 1, 2 } }", "return { height = 0, color = 1 }"] {
+		if compile(code, 0).is_ok() {
+			return Err(format!("`{code}` constructed without error"));
+		}
+	}
+	Ok(String::new())
+}
+
+fn check_colormaps() -> Result<String, String> {
+	let gray = Colormap::default();
+	let color = gray.mapper();
+	for v in [-0.5, 0.0, 0.25, 1.0, 1.5] {
+		if color(v) != [v, v, v, 1.0] {
+			return Err(format!("grayscale maps {v} to {:?}", color(v)));
+		}
+	}
+
+	let viridis = Colormap {
+		kind: ColormapKind::Viridis,
+		..default()
+	};
+	let color = viridis.mapper();
+	let srgb = |v| {
+		let [r, g, b, _] = color(v);
+		bevy::color::Color::linear_rgb(r, g, b).to_srgba().to_u8_array_no_alpha()
+	};
+	for (v, expected) in [(0.0, [0x44, 0x01, 0x54]), (1.0, [0xFD, 0xE7, 0x25])] {
+		if srgb(v).iter().zip(expected).any(|(&a, b)| a.abs_diff(b) > 1) {
+			return Err(format!("viridis maps {v} to {:?} instead of {expected:?}", srgb(v)));
+		}
+	}
+	if srgb(-1.0) != srgb(0.0) || srgb(2.0) != srgb(1.0) {
+		return Err("viridis doesn't clamp to its ends".into());
+	}
+	let luminance = |[r, g, b, _]: [f32; 4]| 0.2126 * r + 0.7152 * g + 0.0722 * b;
+	let ramp: Vec<f32> = (0 ..= 32).map(|i| luminance(color(i as f32 / 32.0))).collect();
+	if ramp.windows(2).any(|pair| pair[1] < pair[0]) {
+		return Err("viridis darkens along the range".into());
+	}
+
+	let highlighted = Colormap {
+		kind: ColormapKind::Magma,
+		contrast: 0.5,
+		highlightOutOfRange: true,
+		..default()
+	};
+	let color = highlighted.mapper();
+	let magenta = [1.0, 0.0, 1.0, 1.0];
+	for v in [-0.01, 1.01, f32::NAN, f32::INFINITY] {
+		if color(v) != magenta {
+			return Err(format!("{v} isn't highlighted"));
+		}
+	}
+	if [0.0, 0.5, 1.0].iter().any(|&v| color(v) == magenta) {
+		return Err("a value in range is highlighted".into());
+	}
+
+	let adjusted = Colormap {
+		contrast: 2.0,
+		brightness: 0.1,
+		..default()
+	};
+	let color = adjusted.mapper();
+	for (v, expected) in [(0.5, 0.6), (0.75, 1.1), (0.25, 0.1)] {
+		if (color(v)[0] - expected).abs() > 1e-6 {
+			return Err(format!("contrast 2 and brightness 0.1 map {v} to {}", color(v)[0]));
+		}
+	}
+	Ok(String::new())
+}
+
+/// Scatters over a ramp from zero density on the left to full density on the right must be
+/// deterministic, keep every point its radius away from the ones before it, leave the empty end
+/// empty and hold the point limit. Markers must sit on the mesh's triangles, not a bilinear patch.
+fn check_scatter() -> Result<String, String> {
+	let diameter = 64;
+	let ramp: Vec<f32> = (0 .. diameter * diameter)
+		.map(|i| (i % diameter) as f32 / (diameter - 1) as f32)
+		.collect();
+	let params = scatter::ScatterParams {
+		radius: 2.0,
+		..default()
+	};
+	let points = scatter::scatter(&ramp, (diameter, diameter), (0.0, 1.0), &params);
+	if points != scatter::scatter(&ramp, (diameter, diameter), (0.0, 1.0), &params) {
+		return Err("the same seed scattered differently".into());
+	}
+	for (i, point) in points.iter().enumerate() {
+		let close = |p: &&scatter::ScatterPoint| {
+			p.pos.distance_squared(point.pos) < point.radius * point.radius
+		};
+		if let Some(near) = points[.. i].iter().find(close) {
+			return Err(format!("{} is closer than {} to {}", point.pos, point.radius, near.pos));
+		}
+	}
+	// density falls below 1/64 of the highest there
+	let empty = (diameter - 1) as f32 / 64.0;
+	if let Some(point) = points.iter().find(|point| point.pos.x < empty) {
+		return Err(format!("point at {} where the density is zero", point.pos));
+	}
+	let right = points.iter().filter(|point| point.pos.x > 31.5).count();
+	if right <= points.len() - right {
+		return Err(format!("{right} of {} points on the denser half", points.len()));
+	}
+	let limited = scatter::ScatterParams {
+		maxPoints: 10,
+		..params
+	};
+	let count = scatter::scatter(&ramp, (diameter, diameter), (0.0, 1.0), &limited).len();
+	if count != 10 {
+		return Err(format!("{count} points scattered with a limit of 10"));
+	}
+
+	let mut output = NoiseOutput::new(2);
+	output.samples = vec![0.0, 1.0, 1.0, 0.0];
+	let cases = [
+		(vec2(0.0, 1.0), 1.0),
+		(vec2(0.5, 0.5), 1.0),
+		(vec2(0.25, 0.25), 0.5),
+		(vec2(0.75, 0.75), 0.5),
+		(vec2(-3.0, 0.0), 0.0),
+	];
+	for (pos, expected) in cases {
+		let height = scatter::surface_height(&output, pos);
+		if height != expected {
+			return Err(format!("surface at {pos} is {height} instead of {expected}"));
+		}
+	}
+	Ok(format!("{} points", points.len()))
+}
+
+/// The walking camera's ground must meet the mesh at the samples, interpolate between them and
+/// hold the border height off the grid, and a fall must land exactly at eye height.
+fn check_walk() -> Result<String, String> {
+	let mut output = NoiseOutput::new(3);
+	output.range = (-1.0, 1.0);
+	output.samples = vec![0.0, 1.0, 0.5, -1.0, 0.0, 0.0, 0.5, 0.5, 0.5];
+	// one world unit per sample, the middle sample at the origin
+	let scale = TerrainScale {
+		height: 4.0,
+		extent: 2.0,
+	};
+	let cases = [
+		(vec2(0.0, -1.0), 4.0),
+		(vec2(-0.5, -1.0), 2.0),
+		(vec2(-0.5, -0.5), 0.0),
+		(vec2(0.5, 0.5), 1.0),
+		(vec2(-6.0, 0.0), -4.0),
+		(vec2(8.0, 8.0), 2.0),
+	];
+	for (pos, expected) in cases {
+		let height = walk::terrain_height(&output, scale, pos);
+		if height != expected {
+			return Err(format!("ground at {pos} is {height} instead of {expected}"));
+		}
+	}
+
+	let mut state = walk::WalkState::default();
+	let mut pos = vec3(0.0, 50.0, -1.0);
+	for _ in 0 .. 600 {
+		state.update(&mut pos, &output, scale, 1.5, false, 1.0 / 60.0);
+	}
+	if pos.y != 5.5 {
+		return Err(format!("fell to {} instead of 5.5", pos.y));
+	}
+	Ok(String::new())
+}
+
+fn check_probe() -> Result<String, String> {
+	// a ramp rising along X, one world unit per sample and height `x + 2`
+	let mut output = NoiseOutput::new(5);
+	output.range = (0.0, 1.0);
+	for (_, row) in output.rows() {
+		for (x, v) in row.iter_mut().enumerate() {
+			*v = x as f32 / 4.0;
+		}
+	}
+	output.domain = Domain {
+		origin: dvec2(1.0, 1.0),
+		size: 2.0,
+	};
+	let scale = TerrainScale {
+		height: 4.0,
+		extent: 4.0,
+	};
+	let cases = [
+		(vec3(1.0, 10.0, 0.5), Vec3::NEG_Y, Some(vec3(1.0, 3.0, 0.5))),
+		(vec3(-2.0, 3.0, 0.0), vec3(1.0, -1.0, 0.0), Some(vec3(-0.5, 1.5, 0.0))),
+		// passes over the top of the ramp
+		(vec3(-2.0, 10.0, 0.0), vec3(1.0, -1.0, 0.0), None),
+		(vec3(3.0, 10.0, 0.0), Vec3::NEG_Y, None),
+	];
+	for (origin, direction, expected) in cases {
+		let ray = Ray3d::new(origin, direction);
+		let hit = probe::ray_terrain(ray, &output, scale).map(|t| ray.get_point(t));
+		let close = match (hit, expected) {
+			(Some(hit), Some(expected)) => hit.distance(expected) < 1e-3,
+			(hit, expected) => hit.is_none() && expected.is_none(),
+		};
+		if !close {
+			return Err(format!("ray from {origin} hit {hit:?} instead of {expected:?}"));
+		}
+	}
+
+	let point = ProbePoint::new(&output, scale, vec2(0.75, 0.5));
+	if point.sample != uvec2(3, 2) || point.value != 0.75 {
+		return Err(format!("probe picked sample {} = {}", point.sample, point.value));
+	}
+	if point.world != vec3(1.0, 3.0, 0.0) || point.domain != dvec2(2.5, 2.0) {
+		return Err(format!("probe at {} over {} in the domain", point.world, point.domain));
+	}
+	Ok(String::new())
+}
+
+/// Scripts covering the nodes the GPU backend translates, without discontinuities where `f32`
+/// rounding could put a sample on the other side of an edge than on the CPU.
+const gpuScripts: &[&str] = &[
+	"return Noise.simplex(1):scale(4):octaves(5)",
+	"return Noise.simplexFast(2):scale(8):translate(-3, 5):ridged(4, 0.5, 2, 1.5)",
+	"return Noise.simplex(3):scale(3):rotate(30):billow(3) * 0.5 - Noise.gradientY():abs() ^ 2",
+	"return Noise.simplex(4):scale(5):warp(Noise.simplex(5), Noise.simplex(6), 0.2):tiled(0.5)",
+	"return Noise.radial(0.5, 0.5):curve({{0, 1}, {0.5, 0}, {1, -1}})\
+	 :lerp(Noise.simplex(7):scale(6), Noise.gradientX())",
+	"return Noise.simplex(8):scale(4):select(Noise.gradientY(), -1, 0, 0.2):clamp(-0.5, 0.5) / 2",
+	"return Noise.simplex(9):scale(4):gradientMagnitude():min(Noise.simplex(10):toUnsignedUnit())",
+	"return Noise.simplexFast(11):scale(3):octaves(4, { seedOffset = 3, rotation = 37 })",
+	"return Noise.simplex(12):scale(3):ridged(1)\
+	 :octaves(4, { seedOffset = 1, lacunarityJitter = 0.2 })",
+];
+
+/// Largest difference allowed between GPU and CPU samples, from the GPU's `f32` arithmetic.
+const gpuEpsilon: f32 = 2e-3;
+
+/// The reference scripts must translate to WGSL and Lua-only nodes must not. Where a GPU is
+/// available, the GPU's samples must match the CPU's within `gpuEpsilon`.
+fn check_gpu() -> Result<String, String> {
+	const diameter: usize = 33;
+	let asts = gpuScripts
+		.iter()
+		.map(|code| lua::construct_noisegen(code).map_err(|err| format!("{code}: {err:#}")))
+		.collect::<Result<Vec<_>, _>>()?;
+	for (code, ast) in gpuScripts.iter().zip(&asts) {
+		gpu::translate(ast).map_err(|err| format!("{code}: {err}"))?;
+	}
+	let worley = lua::construct_noisegen("return Noise.worley(1) + Noise.simplex(1)")
+		.map_err(|err| format!("{err:#}"))?;
+	if gpu::translate(&worley).is_ok() {
+		return Err("worley translated to WGSL".into());
+	}
+
+	if let Err(reason) = gpu::context() {
+		return Ok(format!("{} scripts translated, comparison skipped: {reason}", asts.len()));
+	}
+	let mut maxError = 0f32;
+	for (code, ast) in gpuScripts.iter().zip(&asts) {
+		let gpuOutput = gpu::generate_samples(ast, &SampleGrid::new(diameter), 0.0, &default())
+			.map_err(|err| format!("{code}: {err}"))?;
+		let cpuOutput = generate_samples(ast, diameter, lua::Precision::F64);
+		for (i, (&g, &c)) in gpuOutput.samples.iter().zip(&cpuOutput.samples).enumerate() {
+			let error = (g - c).abs();
+			if error.is_nan() || error > gpuEpsilon {
+				let (x, y) = (i % diameter, i / diameter);
+				return Err(format!("{code}: GPU {g} but CPU {c} at sample ({x}, {y})"));
+			}
+			maxError = maxError.max(error);
+		}
+	}
+	Ok(format!("{} scripts, max error {maxError:.2e}", asts.len()))
+}
+
+/// Stitched chunks must sample the world-space grid a plain loop over the whole output would,
+/// and shared edges must repeat each border column in both neighbours.
+fn check_chunks() -> Result<String, String> {
+	let ast = lua::construct_noisegen("return Noise.simplex(3):scale(4)")
+		.map_err(|err| format!("{err:#}"))?;
+	let generate = |settings: chunks::ChunkSettings| {
+		let threadPool = AsyncComputeTaskPool::get();
+		let ast = ast.clone();
+		block_on(threadPool.spawn(async move {
+			let cancel = AtomicBool::new(false);
+			let (precision, ctx) = (lua::Precision::F64, default());
+			chunks::ChunkedNoiseOutput::generate_cancellable(
+				&ast,
+				settings,
+				precision,
+				0.0,
+				&ctx,
+				&cancel,
+				&default(),
+			)
+		}))
+		.ok_or("generation cancelled")
+	};
+
+	let settings = chunks::ChunkSettings {
+		enabled: true,
+		chunkSize: 16,
+		extent: 3,
+		sharedEdges: false,
+	};
+	let stitched = generate(settings)?.stitch();
+	let (diameter, size) = (settings.diameter(), settings.chunkSize);
+	for y in 0 .. diameter {
+		for x in 0 .. diameter {
+			let pos = settings.position((x / size, y / size), x % size, y % size);
+			let plain = dvec2(x as f64, y as f64) / diameter as f64;
+			if (pos - plain).length() > 1e-12 {
+				return Err(format!("sample ({x}, {y}) is at {pos} instead of {plain}"));
+			}
+			let (v, expected) = (stitched.samples[y * diameter + x], ast.eval(pos));
+			if v != expected {
+				return Err(format!("stitched sample ({x}, {y}) is {v} instead of {expected}"));
+			}
+		}
+	}
+
+	let shared = generate(chunks::ChunkSettings {
+		sharedEdges: true,
+		..settings
+	})?;
+	for y in 0 .. size {
+		let (left, right) = (shared.chunks[0][y * size + size - 1], shared.chunks[1][y * size]);
+		if left != right {
+			return Err(format!("shared edge differs in row {y}: {left} and {right}"));
+		}
+	}
+	Ok(format!("{} chunks of {size}x{size}", settings.extent.pow(2)))
+}
+
+pub fn print_report(results: &[CheckResult]) {
+	let nameWidth = results.iter().map(|res| res.name.len()).max().unwrap_or(0);
+	for res in results {
+		println!(
+			"{status}  {name:nameWidth$}  {ms:>8.3}ms  {detail}",
+			status = if res.passed { "PASS" } else { "FAIL" },
+			name = res.name,
+			ms = res.duration.as_secs_f64() * 1000.0,
+			detail = res.detail,
+		);
+	}
+	let failed = results.iter().filter(|res| !res.passed).count();
+	println!("{} passed, {failed} failed", results.len() - failed);
+}
+
+/// Entry point for `noisebench selftest`; the exit code reflects failures.
+pub fn main() -> AppExit {
+	AsyncComputeTaskPool::get_or_init(TaskPool::new);
+	let results = run();
+	print_report(&results);
+	if results.iter().all(|res| res.passed) {
+		AppExit::Success
+	} else {
+		AppExit::error()
+	}
+}
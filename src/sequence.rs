@@ -0,0 +1,378 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use anyhow::{bail, ensure, Context};
+use bevy::prelude::*;
+use bevy::tasks::futures_lite::future;
+use bevy::tasks::{block_on, AsyncComputeTaskPool, Task};
+use bevy_egui::{egui, EguiContexts};
+use image::codecs::gif::{GifEncoder, Repeat};
+
+use crate::{
+	colormap,
+	compile_script,
+	export,
+	generate_samples_cancellable,
+	log_panel::{LogKind, SessionLog, Severity},
+	lua,
+	AResult,
+	NoiseOutput,
+	SampleGrid,
+	UiState,
+};
+
+/// Time each frame of the assembled GIF is shown, in milliseconds.
+const gifFrameDelay: u32 = 100;
+
+/// What a recorded sweep varies between its frames.
+#[derive(Clone, Debug, PartialEq)]
+pub enum SweepParam {
+	/// The seed offset, rounded to whole numbers.
+	Seed,
+	/// Depth of the slice through the volume that 3D sources are sampled at.
+	SliceZ,
+	/// `Noise.time()`, for animated scripts.
+	Time,
+	/// A slider the script declared with `Params.slider`, by name.
+	Script(String),
+}
+
+impl SweepParam {
+	pub fn label(&self) -> String {
+		match self {
+			Self::Seed => "Seed".into(),
+			Self::SliceZ => "Slice z".into(),
+			Self::Time => "Time".into(),
+			Self::Script(name) => format!("Param {name}"),
+		}
+	}
+}
+
+/// Frames of a sweep and where they go.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SweepSettings {
+	pub param: SweepParam,
+	/// Values of the first and last frame, the others spread evenly between them.
+	pub from: f64,
+	pub to: f64,
+	pub steps: usize,
+	pub diameter: usize,
+	/// Directory the numbered frames are written to, created if missing.
+	pub dir: String,
+	/// Also assembles the frames into a looping `sweep.gif`.
+	pub gif: bool,
+	/// Writes the frames as 16-bit grayscale heightmaps like the export, instead of in the 2D
+	/// view's colors. The GIF keeps the colors.
+	pub heightmaps: bool,
+}
+
+impl Default for SweepSettings {
+	fn default() -> Self {
+		Self {
+			param: SweepParam::Seed,
+			from: 0.0,
+			to: 8.0,
+			steps: 9,
+			diameter: 256,
+			dir: "sweep".into(),
+			gif: false,
+			heightmaps: false,
+		}
+	}
+}
+
+impl SweepSettings {
+	/// Value of the swept parameter at frame `step`.
+	pub fn value(&self, step: usize) -> f64 {
+		let t = step as f64 / self.steps.saturating_sub(1).max(1) as f64;
+		self.from + (self.to - self.from) * t
+	}
+
+	/// Range a sweep of `param` starts out with, the slider's own for script parameters.
+	fn default_range(param: &SweepParam, declared: &[lua::ScriptParam]) -> (f64, f64) {
+		match param {
+			SweepParam::Seed => (0.0, 8.0),
+			SweepParam::SliceZ | SweepParam::Time => (0.0, 1.0),
+			SweepParam::Script(name) => declared
+				.iter()
+				.find(|declared| &declared.name == name)
+				.map_or((0.0, 1.0), |declared| (declared.min, declared.max)),
+		}
+	}
+}
+
+/// Everything a sweep generates its frames from, taken from the app when it starts so changes
+/// made meanwhile don't leak into later frames.
+#[derive(Clone)]
+pub struct SweepJob {
+	pub settings: SweepSettings,
+	pub code: String,
+	pub seed: i64,
+	pub params: BTreeMap<String, f64>,
+	pub precision: lua::Precision,
+	pub decorrelate: bool,
+	pub sliceZ: f64,
+	pub time: f64,
+	pub grid: SampleGrid,
+	pub colormap: colormap::Colormap,
+}
+
+impl SweepJob {
+	/// The active layer's script with the toolbar's settings, sampled over the 2D view's domain.
+	/// Sweeps evaluate on the CPU, whatever the backend.
+	fn new(
+		settings: SweepSettings,
+		uiState: &UiState,
+		colormap: &colormap::Colormap,
+	) -> AResult<Self> {
+		let path = uiState.selected().context("no script selected")?;
+		let code = uiState.scripts.get(path).context("the script is missing")?;
+		ensure!(settings.steps >= 2, "a sweep needs at least 2 steps");
+		ensure!(!settings.dir.trim().is_empty(), "no output directory");
+		Ok(Self {
+			grid: SampleGrid {
+				coords: uiState.coords,
+				supersampling: uiState.supersampling,
+				domain: uiState.domain.domain(),
+				..SampleGrid::new(settings.diameter)
+			},
+			settings,
+			code: code.clone(),
+			seed: uiState.seed,
+			params: uiState.script_params(path),
+			precision: uiState.precision,
+			decorrelate: uiState.decorrelate,
+			sliceZ: uiState.sliceZ,
+			time: uiState.animation.time,
+			colormap: colormap.clone(),
+		})
+	}
+
+	/// Generates frame `step`, giving up once `cancel` is set.
+	pub fn frame(&self, step: usize, cancel: &AtomicBool) -> AResult<NoiseOutput> {
+		let value = self.settings.value(step);
+		let mut inputs = lua::ScriptInputs {
+			seed: self.seed,
+			params: self.params.clone(),
+			coords: self.grid.coords,
+		};
+		let (mut z, mut ctx) = (self.sliceZ, lua::EvalContext { time: self.time });
+		match &self.settings.param {
+			SweepParam::Seed => inputs.seed = value.round() as i64,
+			SweepParam::SliceZ => z = value,
+			SweepParam::Time => ctx.time = value,
+			SweepParam::Script(name) => {
+				inputs.params.insert(name.clone(), value);
+			},
+		}
+		let ast = compile_script(&self.code, &inputs, self.decorrelate)?.noise;
+		// left over from generations sharing the cached tree
+		ast.take_lua_error();
+		let output = generate_samples_cancellable(&ast, &self.grid, self.precision, z, &ctx, cancel)
+			.context("cancelled")?;
+		if let Some(err) = ast.take_lua_error() {
+			bail!("Lua function failed: {err}");
+		}
+		Ok(output)
+	}
+
+	/// Generates and writes the frames one after the other, counting finished ones in `done`.
+	/// Frames written before a failure or cancellation are kept. Returns the directory.
+	pub fn run(&self, cancel: &AtomicBool, done: &AtomicUsize) -> AResult<PathBuf> {
+		let settings = &self.settings;
+		let dir = PathBuf::from(settings.dir.trim());
+		std::fs::create_dir_all(&dir).with_context(|| format!("creating {}", dir.display()))?;
+		let mut gif = None;
+		if settings.gif {
+			let path = dir.join("sweep.gif");
+			let file = File::create(&path).with_context(|| format!("writing {}", path.display()))?;
+			let mut encoder = GifEncoder::new(BufWriter::new(file));
+			encoder.set_repeat(Repeat::Infinite)?;
+			gif = Some(encoder);
+		}
+		for step in 0 .. settings.steps {
+			let output = self.frame(step, cancel).with_context(|| format!("frame {step}"))?;
+			let path = dir.join(format!("frame_{step:04}.png"));
+			let colored = (!settings.heightmaps || gif.is_some())
+				.then(|| colormap::rgba8_image(&output, &self.colormap));
+			if settings.heightmaps {
+				let clamp = export::Normalization::Clamp;
+				let values = export::normalize(&output.samples, output.range, clamp);
+				export::write_png(&path, output.size(), &values)?;
+			}
+			if let Some(colored) = colored {
+				if !settings.heightmaps {
+					colored
+						.save_with_format(&path, image::ImageFormat::Png)
+						.with_context(|| format!("writing {}", path.display()))?;
+				}
+				if let Some(gif) = &mut gif {
+					let delay = image::Delay::from_numer_denom_ms(gifFrameDelay, 1);
+					gif.encode_frame(image::Frame::from_parts(colored, 0, 0, delay))
+						.context("encoding the GIF")?;
+				}
+			}
+			done.fetch_add(1, Ordering::Relaxed);
+		}
+		Ok(dir)
+	}
+}
+
+/// The "Record sweep" window. While a sweep runs it has the generation pool to itself:
+/// `generate_noise` holds interactive requests back until it's done, and animations pause.
+#[derive(Resource, Default)]
+pub struct SweepRecorder {
+	pub open: bool,
+	settings: SweepSettings,
+	task: Option<Task<AResult<PathBuf>>>,
+	cancel: Arc<AtomicBool>,
+	done: Arc<AtomicUsize>,
+	message: Option<String>,
+}
+
+impl SweepRecorder {
+	pub fn running(&self) -> bool {
+		self.task.is_some()
+	}
+
+	/// Frames written and frames in total, while a sweep runs.
+	pub fn progress(&self) -> Option<(usize, usize)> {
+		let done = self.done.load(Ordering::Relaxed);
+		self.task.as_ref().map(|_| (done, self.settings.steps))
+	}
+
+	/// Stops the sweep after the row being generated. It keeps counting as running until its
+	/// task noticed.
+	pub fn cancel(&mut self) {
+		self.cancel.store(true, Ordering::Relaxed);
+	}
+
+	fn start(&mut self, uiState: &UiState, colormap: &colormap::Colormap) -> AResult<()> {
+		ensure!(self.task.is_none(), "a sweep is already running");
+		let job = SweepJob::new(self.settings.clone(), uiState, colormap)?;
+		self.cancel = default();
+		self.done = default();
+		let (cancel, done) = (self.cancel.clone(), self.done.clone());
+		info!("recording a sweep of {} frames", job.settings.steps);
+		self.task = Some(AsyncComputeTaskPool::get().spawn(async move { job.run(&cancel, &done) }));
+		Ok(())
+	}
+}
+
+fn settings_ui(ui: &mut egui::Ui, settings: &mut SweepSettings, declared: &[lua::ScriptParam]) {
+	let options = [SweepParam::Seed, SweepParam::SliceZ, SweepParam::Time]
+		.into_iter()
+		.chain(declared.iter().map(|param| SweepParam::Script(param.name.clone())));
+	egui::Grid::new("sweepRecorder").show(ui, |ui| {
+		ui.label("Parameter");
+		let before = settings.param.clone();
+		egui::ComboBox::from_id_source("sweepParam")
+			.selected_text(settings.param.label())
+			.show_ui(ui, |ui| {
+				for option in options {
+					let label = option.label();
+					ui.selectable_value(&mut settings.param, option, label);
+				}
+			})
+			.response
+			.on_hover_text("Sliders are those the active output's script declared");
+		if settings.param != before {
+			(settings.from, settings.to) = SweepSettings::default_range(&settings.param, declared);
+		}
+		ui.end_row();
+
+		ui.label("Range");
+		ui.horizontal(|ui| {
+			ui.add(egui::DragValue::new(&mut settings.from).speed(0.01));
+			ui.label("to");
+			ui.add(egui::DragValue::new(&mut settings.to).speed(0.01));
+		});
+		ui.end_row();
+
+		ui.label("Steps");
+		ui.add(egui::DragValue::new(&mut settings.steps).range(2 ..= 1000));
+		ui.end_row();
+
+		ui.label("Diameter");
+		ui.add(egui::DragValue::new(&mut settings.diameter).range(16 ..= 4096));
+		ui.end_row();
+
+		ui.label("Directory");
+		ui.text_edit_singleline(&mut settings.dir)
+			.on_hover_text("Frames are written as frame_0000.png, frame_0001.png, …");
+		ui.end_row();
+	});
+	ui.checkbox(&mut settings.gif, "Animated GIF").on_hover_text("Also write sweep.gif, looping");
+	ui.checkbox(&mut settings.heightmaps, "16-bit heightmaps")
+		.on_hover_text("Grayscale heightmaps like the export instead of the 2D view's colors");
+}
+
+/// Finishes sweeps and shows the recorder window.
+pub fn sweep_recorder(
+	mut eguiCtx: EguiContexts,
+	mut recorder: ResMut<SweepRecorder>,
+	uiState: Res<UiState>,
+	noiseOutput: Option<Res<NoiseOutput>>,
+	colormap: Res<colormap::ColormapSettings>,
+	log: Res<SessionLog>,
+) {
+	let recorder = &mut *recorder;
+	let finished = recorder
+		.task
+		.as_mut()
+		.and_then(|task| block_on(future::poll_once(task)));
+	if let Some(res) = finished {
+		recorder.task = None;
+		let frames = recorder.done.load(Ordering::Relaxed);
+		recorder.message = Some(match res {
+			Ok(dir) => {
+				let message = format!("recorded a sweep of {frames} frames to {}", dir.display());
+				log.record(Severity::Info, LogKind::Export, None, message);
+				format!("wrote {frames} frames to {}", dir.display())
+			},
+			Err(_) if recorder.cancel.load(Ordering::Relaxed) => {
+				format!("cancelled after {frames} frames")
+			},
+			Err(err) => {
+				let message = format!("sweep failed: {err:#}");
+				log.record(Severity::Error, LogKind::Export, None, message);
+				format!("sweep failed after {frames} frames: {err:#}")
+			},
+		});
+	}
+	if !recorder.open {
+		return;
+	}
+
+	let mut open = true;
+	egui::Window::new("Record sweep")
+		.open(&mut open)
+		.show(eguiCtx.ctx_mut(), |ui| {
+			let declared = noiseOutput.as_ref().map_or(&[][..], |output| output.params.as_slice());
+			ui.add_enabled_ui(!recorder.running(), |ui| {
+				settings_ui(ui, &mut recorder.settings, declared);
+			});
+			ui.horizontal(|ui| {
+				if let Some((done, total)) = recorder.progress() {
+					ui.spinner();
+					ui.label(format!("{done} / {total} frames"));
+					if ui.button("Cancel").clicked() {
+						recorder.cancel();
+					}
+				} else if ui.button("Record").clicked() {
+					recorder.message = None;
+					if let Err(err) = recorder.start(&uiState, &colormap.colormap) {
+						recorder.message = Some(format!("sweep failed: {err:#}"));
+					}
+				}
+			});
+			if let Some(message) = &recorder.message {
+				ui.label(message);
+			}
+		});
+	recorder.open = open;
+}
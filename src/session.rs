@@ -0,0 +1,319 @@
+use std::collections::BTreeMap;
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+use bevy_egui::egui;
+use serde::{Deserialize, Serialize};
+
+use crate::{content_hash, ContentHash, InternedPath, NoiseGenRequest, UiState};
+
+const sessionLogFile: &str = "noisebench.session.jsonl";
+
+/// One recorded generation.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SessionEntry {
+	/// Seconds since the Unix epoch.
+	pub timestamp: f64,
+	pub script: PathBuf,
+	pub hash: ContentHash,
+	#[serde(alias = "diameter")]
+	pub outputWidth: usize,
+	/// Missing from entries recorded before outputs could be non-square, which were square.
+	#[serde(default)]
+	pub outputHeight: Option<usize>,
+	pub height: f32,
+	/// Seed offset, see `UiState::seed`. Missing from entries recorded before it existed, which
+	/// ran with 0.
+	#[serde(default)]
+	pub seed: i64,
+	/// Slider values passed to the script, see `UiState::params`.
+	#[serde(default)]
+	pub params: BTreeMap<String, f64>,
+	/// Script contents at the time, if `SessionRecorder::embedSources` was set.
+	#[serde(default)]
+	pub source: Option<String>,
+}
+
+impl SessionEntry {
+	pub fn output_size(&self) -> (usize, usize) {
+		(self.outputWidth, self.outputHeight.unwrap_or(self.outputWidth))
+	}
+}
+
+/// State a replay session started from, restored when leaving replay without keeping an entry.
+struct ReplayOrigin {
+	selected: Option<InternedPath>,
+	outputSize: (usize, usize),
+	height: f32,
+	seed: i64,
+	params: HashMap<(InternedPath, String), f64>,
+}
+
+/// Appends every generation to a log file so earlier states can be stepped through and restored.
+#[derive(Resource)]
+pub struct SessionRecorder {
+	pub enabled: bool,
+	pub embedSources: bool,
+	pub entries: Vec<SessionEntry>,
+	pub path: PathBuf,
+	/// Entry being replayed, if in replay mode.
+	cursor: Option<usize>,
+	origin: Option<ReplayOrigin>,
+	pub windowOpen: bool,
+	message: Option<String>,
+}
+
+impl Default for SessionRecorder {
+	fn default() -> Self {
+		let path = PathBuf::from(sessionLogFile);
+		let entries = load_entries(&path);
+		Self {
+			enabled: false,
+			embedSources: false,
+			entries,
+			path,
+			cursor: None,
+			origin: None,
+			windowOpen: false,
+			message: None,
+		}
+	}
+}
+
+/// Reads a previous session log, skipping lines that don't parse.
+fn load_entries(path: &Path) -> Vec<SessionEntry> {
+	let Ok(file) = std::fs::File::open(path) else {
+		return vec![];
+	};
+	BufReader::new(file)
+		.lines()
+		.map_while(Result::ok)
+		.filter_map(|line| serde_json::from_str(&line).ok())
+		.collect()
+}
+
+impl SessionRecorder {
+	pub fn replaying(&self) -> bool {
+		self.cursor.is_some()
+	}
+
+	/// Records a generation of `code` with the current parameters, unless disabled or replaying.
+	pub fn record(&mut self, uiState: &UiState, code: &str) {
+		if !self.enabled || self.replaying() {
+			return;
+		}
+		let Some(selected) = uiState.selected() else {
+			return;
+		};
+
+		let entry = SessionEntry {
+			timestamp: SystemTime::now()
+				.duration_since(UNIX_EPOCH)
+				.map_or(0.0, |time| time.as_secs_f64()),
+			script: selected.path.clone(),
+			hash: content_hash(code),
+			outputWidth: uiState.outputWidth,
+			outputHeight: Some(uiState.outputHeight),
+			height: uiState.height,
+			seed: uiState.seed,
+			params: uiState.script_params(selected),
+			source: self.embedSources.then(|| code.to_owned()),
+		};
+		let res = std::fs::OpenOptions::new()
+			.create(true)
+			.append(true)
+			.open(&self.path)
+			.and_then(|mut file| {
+				let line = serde_json::to_string(&entry)?;
+				writeln!(file, "{line}")
+			});
+		if let Err(err) = res {
+			warn!("failed to append to session log {}: {err}", self.path.display());
+		}
+		self.entries.push(entry);
+	}
+
+	/// Finds a loaded script whose contents match the entry, preferring its original path.
+	fn find_script(uiState: &UiState, entry: &SessionEntry) -> Option<InternedPath> {
+		let mut matching = uiState
+			.scripts
+			.iter()
+			.filter(|(_, code)| content_hash(code) == entry.hash)
+			.map(|(path, _)| path);
+		let first = matching.next()?;
+		Some(
+			std::iter::once(first)
+				.chain(matching)
+				.find(|path| path.path == entry.script)
+				.unwrap_or(first)
+				.clone(),
+		)
+	}
+
+	/// Loads entry `index` into the live state. Returns whether it has to be regenerated.
+	pub fn step_to(&mut self, index: usize, uiState: &mut UiState) -> bool {
+		if self.origin.is_none() {
+			self.origin = Some(ReplayOrigin {
+				selected: uiState.selected().cloned(),
+				outputSize: (uiState.outputWidth, uiState.outputHeight),
+				height: uiState.height,
+				seed: uiState.seed,
+				params: uiState.params.clone(),
+			});
+		}
+		self.cursor = Some(index);
+
+		let entry = &self.entries[index];
+		(uiState.outputWidth, uiState.outputHeight) = entry.output_size();
+		uiState.height = entry.height;
+		uiState.seed = entry.seed;
+		match Self::find_script(uiState, entry) {
+			Some(path) => {
+				uiState.set_script_params(&path, &entry.params);
+				*uiState.selected_mut() = Some(path);
+				self.message = None;
+				true
+			},
+			None => {
+				self.message = Some(format!(
+					"{} has changed since this entry was recorded",
+					entry.script.display()
+				));
+				false
+			},
+		}
+	}
+
+	/// Leaves replay mode, keeping the current entry as the live state if `keep` is set. Returns
+	/// whether the restored state has to be regenerated.
+	pub fn end_replay(&mut self, keep: bool, uiState: &mut UiState) -> bool {
+		self.cursor = None;
+		self.message = None;
+		let Some(origin) = self.origin.take() else {
+			return false;
+		};
+		if keep {
+			return false;
+		}
+		*uiState.selected_mut() = origin.selected;
+		(uiState.outputWidth, uiState.outputHeight) = origin.outputSize;
+		uiState.height = origin.height;
+		uiState.seed = origin.seed;
+		uiState.params = origin.params;
+		true
+	}
+
+	/// Writes an entry's embedded source next to its original script so the watcher picks it up.
+	fn recover_source(&mut self, index: usize) {
+		let entry = &self.entries[index];
+		let Some(source) = &entry.source else {
+			return;
+		};
+		let stem = entry
+			.script
+			.file_stem()
+			.map_or("script".into(), |stem| stem.to_string_lossy());
+		let dir = entry.script.parent().unwrap_or(Path::new("assets/scripts"));
+		let path = dir.join(format!("{stem}.recovered-{:08x}.lua", entry.hash as u32));
+		self.message = Some(match std::fs::write(&path, source) {
+			Ok(()) => format!("recovered to {}", path.display()),
+			Err(err) => format!("failed to write {}: {err}", path.display()),
+		});
+	}
+}
+
+pub fn session_ui(
+	ui: &mut egui::Ui,
+	session: &mut SessionRecorder,
+	uiState: &mut UiState,
+	noiseGenRequests: &mut EventWriter<NoiseGenRequest>,
+) {
+	ui.horizontal(|ui| {
+		ui.checkbox(&mut session.enabled, "Record");
+		ui.checkbox(&mut session.embedSources, "Embed script sources");
+	});
+	ui.label(format!("{} entries in {}", session.entries.len(), session.path.display()));
+
+	if session.entries.is_empty() {
+		return;
+	}
+
+	ui.separator();
+	let last = session.entries.len() - 1;
+	let mut regenerate = false;
+	ui.horizontal(|ui| {
+		let cursor = session.cursor;
+		if ui
+			.add_enabled(cursor != Some(0), egui::Button::new("◀ Prev"))
+			.clicked()
+		{
+			let index = cursor.map_or(last, |cursor| cursor - 1);
+			regenerate |= session.step_to(index, uiState);
+		}
+		if ui
+			.add_enabled(cursor.is_some_and(|cursor| cursor < last), egui::Button::new("Next ▶"))
+			.clicked()
+		{
+			regenerate |= session.step_to(cursor.unwrap() + 1, uiState);
+		}
+		if let Some(cursor) = cursor {
+			ui.label(format!("{} / {}", cursor + 1, last + 1));
+			if ui
+				.button("Keep")
+				.on_hover_text("Leave replay with this entry as the live state")
+				.clicked()
+			{
+				regenerate |= session.end_replay(true, uiState);
+			}
+			if ui.button("Exit replay").clicked() {
+				regenerate |= session.end_replay(false, uiState);
+			}
+		}
+	});
+
+	if let Some(cursor) = session.cursor {
+		let missing = SessionRecorder::find_script(uiState, &session.entries[cursor]).is_none();
+		if missing && session.entries[cursor].source.is_some() {
+			if ui.button("Recover embedded source").clicked() {
+				session.recover_source(cursor);
+			}
+		}
+	}
+	if let Some(message) = &session.message {
+		ui.label(message);
+	}
+
+	ui.separator();
+	egui::ScrollArea::vertical().max_height(300.0).show(ui, |ui| {
+		for index in (0 ..= last).rev() {
+			let entry = &session.entries[index];
+			let name = entry
+				.script
+				.file_name()
+				.map_or("?".into(), |name| name.to_string_lossy());
+			let age = SystemTime::now()
+				.duration_since(UNIX_EPOCH)
+				.map_or(0.0, |time| time.as_secs_f64()) -
+				entry.timestamp;
+			let (width, height) = entry.output_size();
+			let label = format!(
+				"{:>5.0}m ago  {name}  {width}×{height}  h={}  seed={}",
+				age / 60.0,
+				entry.height,
+				entry.seed
+			);
+			if ui
+				.selectable_label(session.cursor == Some(index), label)
+				.clicked()
+			{
+				regenerate |= session.step_to(index, uiState);
+			}
+		}
+	});
+	if regenerate {
+		noiseGenRequests.send(NoiseGenRequest::AlgorithmChanged);
+	}
+}
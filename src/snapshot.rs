@@ -0,0 +1,228 @@
+use bevy::prelude::*;
+use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
+use bevy_egui::{egui, EguiContexts};
+
+use crate::graph::NoiseGraph;
+use crate::theme::Theme;
+use crate::pin::{DisplayMode, PinnedNoiseOutput};
+use crate::reference::ReferenceHeightmap;
+use crate::{
+	colormap, input, HeightmapSprite, Heightmaps, NoiseOutput, SelectedTab, Tab, ViewportRects,
+};
+
+const slotNames: [&str; 2] = ["A", "B"];
+
+/// A stored copy of a generation's output, independent of later regenerations.
+struct Snapshot {
+	/// Width and height.
+	size: (usize, usize),
+	samples: Vec<f32>,
+	range: (f32, f32),
+	image: Handle<Image>,
+	/// Copy of `image` resampled to the live size it was last compared against.
+	resampled: Option<((usize, usize), Handle<Image>)>,
+}
+
+/// Comparison slots for the 2D view. S stores the live output into the active slot, 1/2 pick the
+/// active slot, and holding B shows the snapshot in place of the live output.
+#[derive(Resource, Default)]
+pub struct Snapshots {
+	slots: [Option<Snapshot>; 2],
+	active: usize,
+	/// Whether the sprite currently shows the active snapshot.
+	showing: bool,
+}
+
+impl Snapshots {
+	/// Samples stored in `slot`, if any.
+	pub fn samples(&self, slot: usize) -> Option<&[f32]> {
+		self.slots[slot].as_ref().map(|snapshot| &*snapshot.samples)
+	}
+
+	/// Bytes held in sample buffers. Images live in `Assets<Image>` and are counted there.
+	pub fn memory_bytes(&self) -> usize {
+		self.slots
+			.iter()
+			.flatten()
+			.map(|snapshot| snapshot.samples.capacity() * 4)
+			.sum()
+	}
+
+	/// Drops the resampled copies, which are rebuilt when next shown.
+	pub fn shrink(&mut self, images: &mut Assets<Image>) {
+		for snapshot in self.slots.iter_mut().flatten() {
+			if let Some((_, image)) = snapshot.resampled.take() {
+				images.remove(&image);
+			}
+		}
+	}
+}
+
+pub fn display_image(output: &NoiseOutput, colormap: &colormap::Colormap) -> Image {
+	let mut image = Image::new_fill(
+		Extent3d {
+			width: 1,
+			height: 1,
+			depth_or_array_layers: 1,
+		},
+		TextureDimension::D2,
+		bytemuck::cast_slice(&[0f32; 4]),
+		TextureFormat::Rgba32Float,
+		default(),
+	);
+	output.fill_image(&mut image, colormap);
+	image
+}
+
+/// Bilinearly resamples a `from` sized sample buffer, width first, to the size `to`.
+pub fn resample(
+	samples: &[f32],
+	range: (f32, f32),
+	from: (usize, usize),
+	to: (usize, usize),
+) -> NoiseOutput {
+	let mut output = NoiseOutput::sized(to.0, to.1);
+	output.range = range;
+	let scale = vec2(
+		(from.0 - 1) as f32 / (to.0 - 1).max(1) as f32,
+		(from.1 - 1) as f32 / (to.1 - 1).max(1) as f32,
+	);
+	for (y, row) in output.rows() {
+		for (x, v) in row.iter_mut().enumerate() {
+			let src = vec2(x as f32, y as f32) * scale;
+			let (x0, y0) = (src.x as usize, src.y as usize);
+			let (x1, y1) = ((x0 + 1).min(from.0 - 1), (y0 + 1).min(from.1 - 1));
+			let frac = src - vec2(x0 as f32, y0 as f32);
+			let at = |x: usize, y: usize| samples[y * from.0 + x];
+			let top = at(x0, y0) + (at(x1, y0) - at(x0, y0)) * frac.x;
+			let bottom = at(x0, y1) + (at(x1, y1) - at(x0, y1)) * frac.x;
+			*v = top + (bottom - top) * frac.y;
+		}
+	}
+	output
+}
+
+pub fn snapshots(
+	mut eguiCtx: EguiContexts,
+	keyboard: Res<ButtonInput<KeyCode>>,
+	selectedTab: Res<SelectedTab>,
+	viewportRects: Res<ViewportRects>,
+	input: Res<input::ViewportInput>,
+	noiseOutput: Option<Res<NoiseOutput>>,
+	heightmaps: Res<Heightmaps>,
+	mut images: ResMut<Assets<Image>>,
+	mut snapshots: ResMut<Snapshots>,
+	mut sprite: Query<&mut Handle<Image>, With<HeightmapSprite>>,
+	theme: Res<Theme>,
+	pinned: Res<PinnedNoiseOutput>,
+	displayMode: Res<DisplayMode>,
+	reference: Res<ReferenceHeightmap>,
+	colormap: Res<colormap::ColormapSettings>,
+	graph: Res<NoiseGraph>,
+) {
+	let ctx = eguiCtx.ctx_mut();
+	let snapshots = &mut *snapshots;
+	let recolor = colormap.is_changed();
+	let colormap = &colormap.colormap;
+	if recolor {
+		for snapshot in snapshots.slots.iter_mut().flatten() {
+			let mut output = NoiseOutput::sized(snapshot.size.0, snapshot.size.1);
+			output.samples.clone_from(&snapshot.samples);
+			output.range = snapshot.range;
+			if let Some(image) = images.get_mut(&snapshot.image) {
+				output.fill_image(image, colormap);
+			}
+		}
+		// rebuilt with the new colors when next shown
+		snapshots.shrink(&mut images);
+	}
+	let keys = selectedTab.shows(Tab::D2) && input.keyboard(Tab::D2);
+
+	if keys {
+		if keyboard.just_pressed(KeyCode::Digit1) {
+			snapshots.active = 0;
+		}
+		if keyboard.just_pressed(KeyCode::Digit2) {
+			snapshots.active = 1;
+		}
+		// Ctrl+S exports instead
+		let store = input::just_pressed_alone(&keyboard, KeyCode::KeyS);
+		if let Some(output) = noiseOutput.as_ref().filter(|_| store) {
+			let snapshot = Snapshot {
+				size: output.size(),
+				samples: output.samples.clone(),
+				range: output.range,
+				image: images.add(display_image(output, colormap)),
+				resampled: None,
+			};
+			if let Some(old) = snapshots.slots[snapshots.active].replace(snapshot) {
+				images.remove(&old.image);
+				if let Some((_, image)) = old.resampled {
+					images.remove(&image);
+				}
+			}
+			info!("stored snapshot {}", slotNames[snapshots.active]);
+		}
+	}
+
+	let liveSize = noiseOutput.as_ref().map(|output| output.size());
+	snapshots.showing = keys && keyboard.pressed(KeyCode::KeyB);
+	let shown = match &mut snapshots.slots[snapshots.active] {
+		Some(snapshot) if snapshots.showing => match liveSize {
+			Some(size) if size != snapshot.size => {
+				if !snapshot.resampled.as_ref().is_some_and(|(s, _)| *s == size) {
+					warn!(
+						"snapshot {} is {}x{} but the live output is {}x{}, resampling",
+						slotNames[snapshots.active],
+						snapshot.size.0,
+						snapshot.size.1,
+						size.0,
+						size.1
+					);
+					let output = resample(&snapshot.samples, snapshot.range, snapshot.size, size);
+					let image = images.add(display_image(&output, colormap));
+					if let Some((_, old)) = snapshot.resampled.replace((size, image)) {
+						images.remove(&old);
+					}
+				}
+				snapshot.resampled.as_ref().unwrap().1.clone()
+			},
+			_ => snapshot.image.clone(),
+		},
+		_ => {
+			snapshots.showing = false;
+			graph
+				.promoted_image()
+				.or_else(|| pinned.diff_image(*displayMode))
+				.or_else(|| reference.display_image(*displayMode))
+				.unwrap_or_else(|| heightmaps.image.clone())
+		},
+	};
+	let mut sprite = sprite.single_mut();
+	if *sprite != shown {
+		*sprite = shown;
+	}
+
+	if !selectedTab.shows(Tab::D2) || snapshots.slots.iter().all(Option::is_none) {
+		return;
+	}
+	egui::Area::new(egui::Id::new("snapshotBadge"))
+		.fixed_pos(viewportRects.d2.rect.left_top() + egui::vec2(8.0, 8.0))
+		.interactable(false)
+		.show(ctx, |ui| {
+			theme.overlay_frame(ui.style()).show(ui, |ui| {
+				let slot = slotNames[snapshots.active];
+				if snapshots.showing {
+					ui.colored_label(ui.visuals().warn_fg_color, format!("SNAPSHOT {slot}"));
+				} else {
+					ui.label("LIVE");
+				}
+				let filled = |i: usize| if snapshots.slots[i].is_some() { "●" } else { "○" };
+				ui.small(format!(
+					"slot {slot}  (A {} B {})  S store, hold B blink, 1/2 select",
+					filled(0),
+					filled(1)
+				));
+			});
+		});
+}
@@ -0,0 +1,232 @@
+use bevy::prelude::*;
+use bevy::tasks::futures_lite::future;
+use bevy::tasks::{block_on, AsyncComputeTaskPool, Task};
+use bevy_egui::{egui, EguiContexts};
+
+use crate::{colormap, snapshot, Heightmaps, NoiseOutput};
+
+pub const histogramBuckets: usize = 64;
+
+/// Outputs with more samples than this are analyzed in the background.
+const syncSampleLimit: usize = 128 * 128;
+
+/// Distribution of an output's samples. Non-finite samples are only counted.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Stats {
+	pub min: f32,
+	pub max: f32,
+	pub mean: f64,
+	pub stdDev: f64,
+	pub nonFinite: usize,
+	/// Bounds the histogram spans: the declared range, widened to the observed one if samples
+	/// escape it.
+	pub histogramRange: (f32, f32),
+	pub histogram: [u32; histogramBuckets],
+}
+
+impl Stats {
+	pub fn compute(samples: &[f32], range: (f32, f32)) -> Self {
+		let (mut min, mut max) = (f32::INFINITY, f32::NEG_INFINITY);
+		let (mut sum, mut sumSq) = (0.0, 0.0);
+		let mut finite = 0;
+		for &v in samples.iter().filter(|v| v.is_finite()) {
+			min = min.min(v);
+			max = max.max(v);
+			sum += v as f64;
+			sumSq += (v as f64).powi(2);
+			finite += 1;
+		}
+		let mean = if finite > 0 { sum / finite as f64 } else { 0.0 };
+		// clamped, rounding can push it a hair below zero for constant outputs
+		let variance = if finite > 0 {
+			(sumSq / finite as f64 - mean.powi(2)).max(0.0)
+		} else {
+			0.0
+		};
+
+		let histogramRange = (range.0.min(min), range.1.max(max));
+		let (lo, hi) = histogramRange;
+		let mut histogram = [0; histogramBuckets];
+		for &v in samples.iter().filter(|v| v.is_finite()) {
+			let t = if hi > lo { (v - lo) / (hi - lo) } else { 0.0 };
+			let bucket = (t * histogramBuckets as f32) as usize;
+			histogram[bucket.min(histogramBuckets - 1)] += 1;
+		}
+		Self {
+			min,
+			max,
+			mean,
+			stdDev: variance.sqrt(),
+			nonFinite: samples.len() - finite,
+			histogramRange,
+			histogram,
+		}
+	}
+
+	/// Observed range to stretch the display to, if the samples aren't all equal.
+	fn observed_range(&self) -> Option<(f32, f32)> {
+		(self.max > self.min).then_some((self.min, self.max))
+	}
+}
+
+/// Statistics of the current `NoiseOutput`, recomputed whenever it's replaced.
+#[derive(Resource, Default)]
+pub struct OutputStats {
+	pub open: bool,
+	stats: Option<Stats>,
+	task: Option<Task<Stats>>,
+	/// Maps the observed min/max rather than the declared range to black and white in the 2D
+	/// view. Samples, mesh and exports are unaffected.
+	normalizeDisplay: bool,
+}
+
+impl OutputStats {
+	/// Range the 2D view maps to the ends of the colormap: the observed one with
+	/// `normalizeDisplay`, once known, otherwise the declared one.
+	pub fn display_range(&self, output: &NoiseOutput) -> (f32, f32) {
+		self.stats
+			.as_ref()
+			.and_then(Stats::observed_range)
+			.filter(|_| self.normalizeDisplay)
+			.unwrap_or(output.range)
+	}
+}
+
+pub fn output_stats(
+	mut eguiCtx: EguiContexts,
+	mut panel: ResMut<OutputStats>,
+	noiseOutput: Option<Res<NoiseOutput>>,
+	heightmaps: Res<Heightmaps>,
+	mut images: ResMut<Assets<Image>>,
+	colormap: Res<colormap::ColormapSettings>,
+) {
+	let panel = &mut *panel;
+	let Some(output) = noiseOutput else {
+		return;
+	};
+
+	let mut statsReady = false;
+	let mut toggled = false;
+	if output.is_changed() {
+		panel.stats = None;
+		let range = output.range;
+		if output.samples.len() <= syncSampleLimit {
+			panel.task = None;
+			panel.stats = Some(Stats::compute(&output.samples, range));
+			statsReady = true;
+		} else {
+			// replacing the task drops and so cancels the previous one
+			let samples = output.samples.clone();
+			panel.task = Some(
+				AsyncComputeTaskPool::get().spawn(async move { Stats::compute(&samples, range) }),
+			);
+		}
+	}
+	let finished = panel
+		.task
+		.as_mut()
+		.and_then(|task| block_on(future::poll_once(task)));
+	if let Some(stats) = finished {
+		panel.task = None;
+		panel.stats = Some(stats);
+		statsReady = true;
+	}
+
+	if panel.open {
+		let mut open = true;
+		egui::Window::new("Statistics")
+			.open(&mut open)
+			.show(eguiCtx.ctx_mut(), |ui| {
+				let Some(stats) = &panel.stats else {
+					ui.horizontal(|ui| {
+						ui.spinner();
+						ui.label("analyzing…");
+					});
+					return;
+				};
+				egui::Grid::new("outputStats").show(ui, |ui| {
+					for (name, value) in [
+						("Min", stats.min as f64),
+						("Max", stats.max as f64),
+						("Mean", stats.mean),
+						("Std. dev.", stats.stdDev),
+					] {
+						ui.label(name);
+						ui.monospace(format!("{value:+.4}"));
+						ui.end_row();
+					}
+					ui.label("Declared range");
+					ui.monospace(format!("{:+.4} ..= {:+.4}", output.range.0, output.range.1));
+					ui.end_row();
+					if stats.nonFinite > 0 {
+						ui.label("Non-finite");
+						ui.monospace(stats.nonFinite.to_string());
+						ui.end_row();
+					}
+				});
+				histogram_ui(ui, stats, output.range);
+				let resp = ui
+					.toggle_value(&mut panel.normalizeDisplay, "Normalize")
+					.on_hover_text(
+						"Stretch the 2D view to the observed min and max, without changing the \
+						 samples",
+					);
+				toggled = resp.changed();
+			});
+		panel.open = open;
+	}
+
+	// fresh outputs are uploaded with their declared range, and toggling off must restore that.
+	// Recolors redraw them like uploads.
+	let redraw = statsReady || colormap.is_changed();
+	if toggled || (redraw && panel.normalizeDisplay) {
+		let range = panel.display_range(&output);
+		let Some(image) = images.get_mut(&heightmaps.image) else {
+			error!("heightmap image is missing");
+			return;
+		};
+		// the upload may have downsampled to fit the device's texture limits
+		let size = (image.size().x as usize, image.size().y as usize);
+		let colormap = &colormap.colormap;
+		if size == output.size() {
+			output.fill_image_in(image, range, colormap);
+		} else {
+			snapshot::resample(&output.samples, output.range, output.size(), size)
+				.fill_image_in(image, range, colormap);
+		}
+	}
+}
+
+/// Bars of the histogram, with lines at the bounds of the declared range.
+fn histogram_ui(ui: &mut egui::Ui, stats: &Stats, range: (f32, f32)) {
+	let (rect, resp) = ui.allocate_exact_size(egui::vec2(256.0, 96.0), egui::Sense::hover());
+	let painter = ui.painter_at(rect);
+	let visuals = ui.visuals();
+	painter.rect_filled(rect, 0.0, visuals.extreme_bg_color);
+
+	let tallest = stats.histogram.iter().copied().max().unwrap_or(0).max(1) as f32;
+	let barWidth = rect.width() / histogramBuckets as f32;
+	for (i, &count) in stats.histogram.iter().enumerate() {
+		let height = count as f32 / tallest * rect.height();
+		let x = rect.left() + i as f32 * barWidth;
+		painter.rect_filled(
+			egui::Rect::from_min_max(
+				egui::pos2(x, rect.bottom() - height),
+				egui::pos2(x + barWidth, rect.bottom()),
+			),
+			0.0,
+			visuals.selection.bg_fill,
+		);
+	}
+
+	let (lo, hi) = stats.histogramRange;
+	if hi > lo {
+		for bound in [range.0, range.1] {
+			let x = rect.left() + (bound - lo) / (hi - lo) * rect.width();
+			painter.vline(x, rect.y_range(), visuals.widgets.noninteractive.fg_stroke);
+		}
+	}
+	resp.on_hover_text(format!(
+		"{histogramBuckets} buckets over {lo:+.3} ..= {hi:+.3}, lines mark the declared range"
+	));
+}
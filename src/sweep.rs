@@ -0,0 +1,267 @@
+use bevy::prelude::*;
+use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
+use bevy::tasks::futures_lite::future;
+use bevy::tasks::{block_on, AsyncComputeTaskPool, Task};
+use bevy_egui::egui::load::SizedTexture;
+use bevy_egui::egui::{self, ImageSource, TextureId};
+use bevy_egui::EguiContexts;
+
+use crate::{colormap, content_hash, generate_samples, lua, NoiseGenRequest, NoiseOutput, UiState};
+
+/// Cells per side of the sweep grid.
+const gridSize: usize = 3;
+const cellDiameter: usize = 96;
+
+enum Cell {
+	Running(Task<Option<NoiseOutput>>),
+	Done(NoiseOutput),
+	Failed,
+}
+
+/// Renders the selected script at several seed offsets side by side, to tell robust graphs from
+/// lucky seeds. Picking a cell locks its offset as `UiState::seed`.
+#[derive(Resource, Default)]
+pub struct SeedSweep {
+	pub open: bool,
+	/// Seed offset and state of each cell, row-major.
+	cells: Vec<(i64, Cell)>,
+	/// All cells composited into one image once every cell finished.
+	composite: Option<(Handle<Image>, TextureId, NoiseOutput)>,
+	/// Whether cells changed since `composite` was built.
+	stale: bool,
+	scriptHash: u64,
+	message: Option<String>,
+}
+
+impl SeedSweep {
+	/// Starts sweeping `code` over the offsets following `baseSeed`, cancelling any running sweep.
+	pub fn start(&mut self, code: &str, baseSeed: i64, precision: lua::Precision) {
+		self.open = true;
+		self.message = None;
+		self.scriptHash = content_hash(code);
+		let pool = AsyncComputeTaskPool::get();
+		self.cells = (0 .. gridSize.pow(2) as i64)
+			.map(|i| {
+				let seed = baseSeed.wrapping_add(i);
+				let code = code.to_owned();
+				let task = pool.spawn(async move {
+					let ast = match lua::construct_noisegen_seeded(&code, seed) {
+						Ok(ast) => ast,
+						Err(err) => {
+							warn!("seed sweep failed: {err:#}");
+							return None;
+						},
+					};
+					let mut ast = (*ast).clone();
+					ast.reseed(seed);
+					let mut output = generate_samples(&ast, cellDiameter, precision);
+					output.seed = seed;
+					Some(output)
+				});
+				(seed, Cell::Running(task))
+			})
+			.collect();
+	}
+
+	/// Abandons unfinished cells; dropping their tasks cancels them.
+	fn cancel(&mut self) {
+		for (_, cell) in &mut self.cells {
+			if matches!(cell, Cell::Running(_)) {
+				*cell = Cell::Failed;
+				self.stale = true;
+			}
+		}
+	}
+
+	pub fn memory_bytes(&self) -> usize {
+		let cells: usize = self
+			.cells
+			.iter()
+			.map(|(_, cell)| match cell {
+				Cell::Done(output) => output.samples.capacity() * 4,
+				_ => 0,
+			})
+			.sum();
+		cells + self.composite.as_ref().map_or(0, |(_, _, output)| output.samples.capacity() * 4)
+	}
+
+	fn running(&self) -> usize {
+		self.cells
+			.iter()
+			.filter(|(_, cell)| matches!(cell, Cell::Running(_)))
+			.count()
+	}
+
+	/// Lays the finished cells out in one output, row-major like `cells`.
+	fn composite(&self) -> NoiseOutput {
+		let diameter = gridSize * cellDiameter;
+		let mut output = NoiseOutput::new(diameter);
+		for (i, (_, cell)) in self.cells.iter().enumerate() {
+			let Cell::Done(cell) = cell else {
+				continue;
+			};
+			output.range = cell.range;
+			let (cellX, cellY) = (i % gridSize * cellDiameter, i / gridSize * cellDiameter);
+			for (y, row) in cell.samples.chunks_exact(cellDiameter).enumerate() {
+				let start = (cellY + y) * diameter + cellX;
+				output.samples[start .. start + cellDiameter].copy_from_slice(row);
+			}
+		}
+		output
+	}
+}
+
+fn composite_image(output: &NoiseOutput, colormap: &colormap::Colormap) -> Image {
+	let mut image = Image::new_fill(
+		Extent3d {
+			width: 1,
+			height: 1,
+			depth_or_array_layers: 1,
+		},
+		TextureDimension::D2,
+		bytemuck::cast_slice(&[0f32; 4]),
+		TextureFormat::Rgba32Float,
+		default(),
+	);
+	output.fill_image(&mut image, colormap);
+	image
+}
+
+/// 8-bit grayscale copy of `output` for saving, as only 8-bit images convert to `image` types.
+fn export_image(output: &NoiseOutput) -> Image {
+	let data = output
+		.samples
+		.iter()
+		.flat_map(|&v| {
+			let v = ((output.normalized(v) + 1.0) / 2.0).clamp(0.0, 1.0);
+			let v = (v * 255.0) as u8;
+			[v, v, v, 255]
+		})
+		.collect();
+	Image::new(
+		Extent3d {
+			width: output.width as _,
+			height: output.height as _,
+			depth_or_array_layers: 1,
+		},
+		TextureDimension::D2,
+		data,
+		TextureFormat::Rgba8Unorm,
+		default(),
+	)
+}
+
+pub fn seed_sweep(
+	mut eguiCtx: EguiContexts,
+	mut sweep: ResMut<SeedSweep>,
+	mut images: ResMut<Assets<Image>>,
+	mut uiState: ResMut<UiState>,
+	mut noiseGenRequests: EventWriter<NoiseGenRequest>,
+	colormap: Res<colormap::ColormapSettings>,
+) {
+	let sweep = &mut *sweep;
+	if colormap.is_changed() && sweep.composite.is_some() {
+		sweep.stale = true;
+	}
+	for (_, cell) in &mut sweep.cells {
+		let Cell::Running(task) = cell else {
+			continue;
+		};
+		let Some(res) = block_on(future::poll_once(task)) else {
+			continue;
+		};
+		*cell = res.map_or(Cell::Failed, Cell::Done);
+		sweep.stale = true;
+	}
+	if sweep.stale && sweep.running() == 0 {
+		sweep.stale = false;
+		if let Some((image, _, _)) = sweep.composite.take() {
+			eguiCtx.remove_image(&image);
+			images.remove(&image);
+		}
+		let output = sweep.composite();
+		let image = images.add(composite_image(&output, &colormap.colormap));
+		let texture = eguiCtx.add_image(image.clone_weak());
+		sweep.composite = Some((image, texture, output));
+	}
+
+	if !sweep.open {
+		if sweep.running() > 0 {
+			sweep.cancel();
+		}
+		return;
+	}
+
+	let mut open = true;
+	egui::Window::new("Seed sweep")
+		.open(&mut open)
+		.resizable(false)
+		.show(eguiCtx.ctx_mut(), |ui| {
+			let running = sweep.running();
+			if running > 0 {
+				ui.horizontal(|ui| {
+					ui.spinner();
+					ui.label(format!(
+						"{} / {} cells done",
+						sweep.cells.len() - running,
+						sweep.cells.len()
+					));
+					if ui.button("Cancel").clicked() {
+						sweep.cancel();
+					}
+				});
+				return;
+			}
+			let Some((_, texture, output)) = &sweep.composite else {
+				ui.label("no sweep results");
+				return;
+			};
+
+			let size = egui::Vec2::splat((gridSize * cellDiameter) as f32 * 1.5);
+			let resp = ui.add(
+				egui::Image::new(ImageSource::Texture(SizedTexture::new(*texture, size)))
+					.sense(egui::Sense::click()),
+			);
+			let cellAt = |pos: egui::Pos2| {
+				let rel = (pos - resp.rect.min) / resp.rect.size() * gridSize as f32;
+				let (x, y) = (rel.x as usize, rel.y as usize);
+				(x < gridSize && y < gridSize).then(|| y * gridSize + x)
+			};
+			if let Some(i) = resp.hover_pos().and_then(cellAt) {
+				match &sweep.cells.get(i) {
+					Some((seed, Cell::Done(_))) => {
+						resp.clone().on_hover_text(format!("seed offset {seed}, click to adopt"));
+					},
+					Some((seed, _)) => {
+						resp.clone().on_hover_text(format!("seed offset {seed} failed"));
+					},
+					None => {},
+				}
+			}
+			if let Some(i) = resp.interact_pointer_pos().filter(|_| resp.clicked()).and_then(cellAt) {
+				if let Some((seed, Cell::Done(_))) = sweep.cells.get(i) {
+					uiState.seed = *seed;
+					noiseGenRequests.send(NoiseGenRequest::AlgorithmChanged);
+				}
+			}
+
+			ui.horizontal(|ui| {
+				ui.label(format!("locked seed offset: {}", uiState.seed));
+				if ui.button("Export").clicked() {
+					let path = format!("seed-sweep-{:08x}.png", sweep.scriptHash as u32);
+					let res = export_image(output)
+						.try_into_dynamic()
+						.map_err(|err| err.to_string())
+						.and_then(|image| image.to_rgba8().save(&path).map_err(|err| err.to_string()));
+					sweep.message = Some(match res {
+						Ok(()) => format!("exported to {path}"),
+						Err(err) => format!("export failed: {err}"),
+					});
+				}
+			});
+			if let Some(message) = &sweep.message {
+				ui.label(message);
+			}
+		});
+	sweep.open = open;
+}
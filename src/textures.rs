@@ -0,0 +1,87 @@
+use bevy::prelude::*;
+use bevy::render::render_resource::TextureFormat;
+use bevy::render::renderer::RenderDevice;
+
+/// What the render device accepts for textures the app resizes at runtime. Images beyond these
+/// fail wgpu validation or run out of memory on upload, which takes the whole app down.
+#[derive(Resource, Clone, Copy, Debug)]
+pub struct TextureLimits {
+	pub maxDimension: u32,
+	/// Largest upload, as staging buffers hold the whole texture.
+	pub maxBytes: u64,
+	/// Fault injection for the self-test: textures with more texels than this fail as if the
+	/// driver had rejected them.
+	pub failAbove: Option<u64>,
+}
+
+impl Default for TextureLimits {
+	/// wgpu's guaranteed minimums, used until the render device is known.
+	fn default() -> Self {
+		Self {
+			maxDimension: 8192,
+			maxBytes: 256 << 20,
+			failAbove: None,
+		}
+	}
+}
+
+impl TextureLimits {
+	pub fn from_device(device: &RenderDevice) -> Self {
+		let limits = device.limits();
+		Self {
+			maxDimension: limits.max_texture_dimension_2d,
+			maxBytes: limits.max_buffer_size,
+			failAbove: None,
+		}
+	}
+
+	pub fn check(&self, size: UVec2, format: TextureFormat) -> Result<(), String> {
+		let texels = size.x as u64 * size.y as u64;
+		let bytes = texels * format.block_copy_size(None).unwrap_or(16) as u64;
+		if size.x > self.maxDimension || size.y > self.maxDimension {
+			return Err(format!("exceeds the maximum dimension of {}", self.maxDimension));
+		}
+		if bytes > self.maxBytes {
+			return Err(format!("{bytes} bytes exceed the upload limit of {}", self.maxBytes));
+		}
+		if self.failAbove.is_some_and(|limit| texels > limit) {
+			return Err("injected failure".into());
+		}
+		Ok(())
+	}
+
+	/// Halves `diameter` until a square texture of that size passes `check`. Returns the first
+	/// size that fits, or the last error if not even a single texel does.
+	pub fn fit(&self, diameter: usize, format: TextureFormat) -> Result<usize, String> {
+		let mut diameter = diameter.max(1);
+		loop {
+			let err = match self.check(UVec2::splat(diameter as u32), format) {
+				Ok(()) => return Ok(diameter),
+				Err(err) => err,
+			};
+			error!("{diameter}x{diameter} {format:?} texture rejected: {err}");
+			if diameter == 1 {
+				return Err(err);
+			}
+			diameter /= 2;
+		}
+	}
+}
+
+/// Picks up the real limits once the render device exists.
+pub fn update_texture_limits(
+	device: Option<Res<RenderDevice>>,
+	mut limits: ResMut<TextureLimits>,
+	mut done: Local<bool>,
+) {
+	let Some(device) = device.filter(|_| !*done) else {
+		return;
+	};
+	*done = true;
+	let failAbove = limits.failAbove;
+	*limits = TextureLimits {
+		failAbove,
+		..TextureLimits::from_device(&device)
+	};
+	info!("texture limits: {:?}", *limits);
+}
@@ -0,0 +1,192 @@
+use bevy::prelude::*;
+use bevy::window::{PrimaryWindow, WindowTheme, WindowThemeChanged};
+use bevy_egui::{egui, EguiContexts};
+use serde::{Deserialize, Serialize};
+
+const settingsFile: &str = "noisebench.settings.json";
+
+/// egui's default body text size, which `Theme::fontSize` is relative to.
+const defaultFontSize: f32 = 12.5;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ThemeMode {
+	/// Follow the OS preference, falling back to dark if it's unknown.
+	#[default]
+	System,
+	Dark,
+	Light,
+}
+
+impl ThemeMode {
+	pub const all: [ThemeMode; 3] = [ThemeMode::System, ThemeMode::Dark, ThemeMode::Light];
+
+	pub fn label(self) -> &'static str {
+		match self {
+			ThemeMode::System => "System",
+			ThemeMode::Dark => "Dark",
+			ThemeMode::Light => "Light",
+		}
+	}
+}
+
+/// Style overrides applied on top of egui's dark or light visuals, saved to the settings file.
+#[derive(Resource, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Theme {
+	pub mode: ThemeMode,
+	/// sRGB selection and highlight color.
+	pub accent: [u8; 3],
+	/// Opacity of panels drawn over the viewport, like the hover readout.
+	pub overlayOpacity: f32,
+	/// Body text size in points; other text styles scale along.
+	pub fontSize: f32,
+}
+
+impl Default for Theme {
+	fn default() -> Self {
+		Self {
+			mode: ThemeMode::System,
+			accent: [0x11, 0x7F, 0xD5],
+			overlayOpacity: 0.85,
+			fontSize: defaultFontSize,
+		}
+	}
+}
+
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+struct SettingsFile {
+	theme: Theme,
+}
+
+impl Theme {
+	/// Reads the theme from the settings file, falling back to defaults if it's missing or
+	/// malformed.
+	pub fn load() -> Self {
+		let Ok(text) = std::fs::read_to_string(settingsFile) else {
+			return default();
+		};
+		match serde_json::from_str::<SettingsFile>(&text) {
+			Ok(settings) => settings.theme,
+			Err(err) => {
+				warn!("ignoring malformed {settingsFile}: {err}");
+				default()
+			},
+		}
+	}
+
+	fn save(&self) {
+		let settings = SettingsFile {
+			theme: self.clone(),
+		};
+		let res = serde_json::to_string_pretty(&settings)
+			.map_err(std::io::Error::from)
+			.and_then(|text| std::fs::write(settingsFile, text));
+		if let Err(err) = res {
+			warn!("failed to write {settingsFile}: {err}");
+		}
+	}
+
+	pub fn accent(&self) -> egui::Color32 {
+		let [r, g, b] = self.accent;
+		egui::Color32::from_rgb(r, g, b)
+	}
+
+	/// Frame for panels drawn over the viewport, derived from the active visuals so it reads well
+	/// in both dark and light mode.
+	pub fn overlay_frame(&self, style: &egui::Style) -> egui::Frame {
+		let frame = egui::Frame::popup(style);
+		frame.fill(frame.fill.gamma_multiply(self.overlayOpacity))
+	}
+
+	fn style(&self, systemDark: bool) -> egui::Style {
+		let dark = match self.mode {
+			ThemeMode::System => systemDark,
+			ThemeMode::Dark => true,
+			ThemeMode::Light => false,
+		};
+		let mut style = egui::Style {
+			visuals: if dark {
+				egui::Visuals::dark()
+			} else {
+				egui::Visuals::light()
+			},
+			..default()
+		};
+		let accent = self.accent();
+		style.visuals.selection.bg_fill = accent;
+		style.visuals.hyperlink_color = accent;
+		let scale = self.fontSize / defaultFontSize;
+		for font in style.text_styles.values_mut() {
+			font.size *= scale;
+		}
+		style
+	}
+}
+
+pub fn theme_ui(ui: &mut egui::Ui, theme: &mut Theme) {
+	egui::Grid::new("theme").show(ui, |ui| {
+		ui.label("Theme");
+		egui::ComboBox::from_id_source("themeMode")
+			.selected_text(theme.mode.label())
+			.show_ui(ui, |ui| {
+				for mode in ThemeMode::all {
+					ui.selectable_value(&mut theme.mode, mode, mode.label());
+				}
+			});
+		ui.end_row();
+
+		ui.label("Accent");
+		ui.color_edit_button_srgb(&mut theme.accent);
+		ui.end_row();
+
+		ui.label("Overlay opacity");
+		ui.add(egui::Slider::new(&mut theme.overlayOpacity, 0.0 ..= 1.0));
+		ui.end_row();
+
+		ui.label("Font size");
+		ui.add(
+			egui::DragValue::new(&mut theme.fontSize)
+				.range(8.0 ..= 24.0)
+				.speed(0.1),
+		);
+		ui.end_row();
+	});
+	if ui.button("Reset style").clicked() {
+		*theme = default();
+	}
+}
+
+/// Applies the theme to egui when it or the OS preference changes, and saves edits.
+pub fn apply_theme(
+	mut eguiCtx: EguiContexts,
+	theme: Res<Theme>,
+	window: Query<&Window, With<PrimaryWindow>>,
+	mut themeChanges: EventReader<WindowThemeChanged>,
+	mut systemDark: Local<Option<bool>>,
+	mut applied: Local<Option<(Theme, bool)>>,
+) {
+	// winit reports the OS preference on the window where the platform supports it, and sends
+	// an event when it changes
+	if systemDark.is_none() {
+		*systemDark = window
+			.get_single()
+			.ok()
+			.and_then(|window| window.window_theme)
+			.map(|theme| theme == WindowTheme::Dark);
+	}
+	for ev in themeChanges.read() {
+		*systemDark = Some(ev.theme == WindowTheme::Dark);
+	}
+	let systemDark = systemDark.unwrap_or(true);
+
+	let current = (theme.clone(), systemDark);
+	if applied.as_ref() == Some(&current) {
+		return;
+	}
+	if applied.as_ref().is_some_and(|(old, _)| *old != *theme) {
+		theme.save();
+	}
+	eguiCtx.ctx_mut().set_style(theme.style(systemDark));
+	*applied = Some(current);
+}
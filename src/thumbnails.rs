@@ -0,0 +1,321 @@
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+
+use bevy::prelude::*;
+use bevy::render::render_asset::RenderAssetUsages;
+use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
+use bevy::tasks::futures_lite::future;
+use bevy::tasks::{block_on, AsyncComputeTaskPool, Task};
+use bevy::utils::HashMap;
+use bevy_egui::egui::TextureId;
+use bevy_egui::EguiContexts;
+
+use crate::{content_hash, lua, sample_position, ContentHash, InternedPath};
+
+pub const thumbnailSize: usize = 64;
+
+/// Jobs allowed to run at once. Kept low so thumbnails never compete with the main generation.
+const maxRunningJobs: usize = 1;
+
+enum Entry {
+	Queued(String, lua::ScriptInputs),
+	Running(Task<Option<Vec<f32>>>),
+	Ready {
+		image: Handle<Image>,
+		texture: TextureId,
+	},
+	Failed,
+}
+
+/// Renders small previews of scripts in the background, cached by `inputs_key`. On disk they're
+/// also keyed by the modules and files the script reads, see `cache_key`.
+///
+/// Call `request` every frame a preview is wanted and `update_thumbnails` takes care of the rest.
+#[derive(Resource)]
+pub struct Thumbnails {
+	entries: HashMap<ContentHash, (Entry, u64)>,
+	queue: VecDeque<ContentHash>,
+	/// Entry each script's preview is under. Scripts with the same source and inputs share one.
+	byPath: HashMap<InternedPath, ContentHash>,
+	/// Images of dropped entries, unregistered from egui on the next update.
+	released: Vec<Handle<Image>>,
+	/// Monotonic use counter for LRU eviction.
+	clock: u64,
+	/// Maximum number of ready thumbnails kept in memory.
+	pub capacity: usize,
+	/// Where computed samples are cached across runs, if anywhere.
+	pub diskCache: Option<PathBuf>,
+}
+
+impl Default for Thumbnails {
+	fn default() -> Self {
+		Self {
+			entries: default(),
+			queue: default(),
+			byPath: default(),
+			released: default(),
+			clock: 0,
+			capacity: 64,
+			diskCache: Some(PathBuf::from(".cache/thumbnails")),
+		}
+	}
+}
+
+impl Thumbnails {
+	/// Returns the thumbnail for `path` once it's rendered with `inputs`, queueing it otherwise.
+	pub fn request(
+		&mut self,
+		path: &InternedPath,
+		code: &str,
+		inputs: &lua::ScriptInputs,
+	) -> Option<TextureId> {
+		let hash = inputs_key(code, inputs);
+		if let Some(old) = self.byPath.insert(path.clone(), hash) {
+			if old != hash {
+				self.release(old);
+			}
+		}
+
+		self.clock += 1;
+		match self.entries.get_mut(&hash) {
+			Some((entry, lastUse)) => {
+				*lastUse = self.clock;
+				match entry {
+					Entry::Ready { texture, .. } => Some(*texture),
+					_ => None,
+				}
+			},
+			None => {
+				let entry = Entry::Queued(code.to_owned(), inputs.clone());
+				self.entries.insert(hash, (entry, self.clock));
+				self.queue.push_back(hash);
+				None
+			},
+		}
+	}
+
+	/// Whether the script at `path` failed to produce a thumbnail.
+	pub fn failed(&self, path: &InternedPath) -> bool {
+		let Some(hash) = self.byPath.get(path) else {
+			return false;
+		};
+		matches!(self.entries.get(hash), Some((Entry::Failed, _)))
+	}
+
+	/// Drops the thumbnail of a script that changed or was removed, unless another script with
+	/// the same source still shows it.
+	pub fn forget(&mut self, path: &InternedPath) {
+		if let Some(hash) = self.byPath.remove(path) {
+			self.release(hash);
+		}
+	}
+
+	/// Estimated bytes of ready thumbnails, whose images only live on the GPU, and queued sources.
+	pub fn memory_bytes(&self) -> usize {
+		self.entries
+			.values()
+			.map(|(entry, _)| match entry {
+				Entry::Queued(code, _) => code.capacity(),
+				Entry::Ready { .. } => thumbnailSize.pow(2) * 4,
+				Entry::Running(_) | Entry::Failed => 0,
+			})
+			.sum()
+	}
+
+	/// Drops every thumbnail. Previews are re-rendered (or read from the disk cache) on demand.
+	pub fn clear(&mut self) {
+		let hashes: Vec<_> = self.entries.keys().copied().collect();
+		for hash in hashes {
+			self.remove_entry(hash);
+		}
+		self.entries.clear();
+		self.queue.clear();
+		self.byPath.clear();
+	}
+
+	/// Drops the entry under `hash` once no script maps to it anymore.
+	fn release(&mut self, hash: ContentHash) {
+		if !self.byPath.values().any(|&used| used == hash) {
+			self.remove_entry(hash);
+		}
+	}
+
+	fn remove_entry(&mut self, hash: ContentHash) {
+		if let Some((Entry::Ready { image, .. }, _)) = self.entries.remove(&hash) {
+			self.released.push(image);
+		}
+	}
+}
+
+/// Identifies a preview of `code` run with `inputs`. Stable across runs, like `content_hash`.
+pub fn inputs_key(code: &str, inputs: &lua::ScriptInputs) -> ContentHash {
+	let lua::ScriptInputs {
+		seed,
+		params,
+		coords,
+	} = inputs;
+	let params: String = params
+		.iter()
+		.map(|(name, v)| format!("{name}={:016x};", v.to_bits()))
+		.collect();
+	content_hash(&format!("{:016x}\0{seed}\0{coords:?}\0{params}", content_hash(code)))
+}
+
+/// Key of a preview in the disk cache: `inputs_key` combined with the current contents of the
+/// modules and files the script read, so editing one renders its dependents again. Unreadable
+/// files count as empty.
+pub fn cache_key(key: ContentHash, dependencies: &[PathBuf]) -> ContentHash {
+	let dependencies: String = dependencies
+		.iter()
+		.map(|path| {
+			let contents = std::fs::read(path).unwrap_or_default();
+			format!("{}={:016x};", path.display(), content_hash(contents))
+		})
+		.collect();
+	content_hash(format!("{key:016x}\0{dependencies}"))
+}
+
+/// Samples of the preview under `key`, from the disk cache in `dir` if it has them.
+fn cached_thumbnail(
+	code: &str,
+	inputs: &lua::ScriptInputs,
+	key: ContentHash,
+	dir: Option<&Path>,
+) -> Option<Vec<f32>> {
+	// the script runs either way to find what it reads; only evaluating the tree is skipped
+	let compiled = match lua::construct_noisegen_with(code, inputs) {
+		Ok(compiled) => compiled,
+		Err(err) => {
+			warn!("thumbnail failed: {err:#}");
+			return None;
+		},
+	};
+	let cacheFile = dir.map(|dir| {
+		let key = cache_key(key, &[&compiled.modules[..], &compiled.files[..]].concat());
+		dir.join(format!("{key:016x}.f32"))
+	});
+	if let Some(bytes) = cacheFile
+		.as_ref()
+		.and_then(|path| std::fs::read(path).ok())
+		.filter(|bytes| bytes.len() == thumbnailSize.pow(2) * 4)
+	{
+		return Some(bytemuck::pod_collect_to_vec(&bytes));
+	}
+
+	let samples = render_thumbnail(&compiled.noise);
+	if let Some(path) = cacheFile {
+		let res = std::fs::create_dir_all(path.parent().unwrap())
+			.and_then(|_| std::fs::write(&path, bytemuck::cast_slice(&samples)));
+		if let Err(err) = res {
+			warn!("failed to write thumbnail cache {}: {err}", path.display());
+		}
+	}
+	Some(samples)
+}
+
+fn render_thumbnail(ast: &lua::Noise) -> Vec<f32> {
+	// stored normalized to [-1, 1] so the disk cache doesn't need to keep the range
+	let (min, max) = ast.output_range();
+	let mut samples = Vec::with_capacity(thumbnailSize * thumbnailSize);
+	for y in 0 .. thumbnailSize {
+		for x in 0 .. thumbnailSize {
+			let v = ast.eval(sample_position(x, y, thumbnailSize));
+			samples.push((v - min) / (max - min) * 2.0 - 1.0);
+		}
+	}
+	samples
+}
+
+pub fn thumbnail_image(samples: &[f32]) -> Image {
+	let data = samples
+		.iter()
+		.flat_map(|v| {
+			let v = ((v + 1.0) / 2.0).clamp(0.0, 1.0);
+			let v = (v * 255.0) as u8;
+			[v, v, v, 255]
+		})
+		.collect();
+	Image::new(
+		Extent3d {
+			width: thumbnailSize as _,
+			height: thumbnailSize as _,
+			depth_or_array_layers: 1,
+		},
+		TextureDimension::D2,
+		data,
+		TextureFormat::Rgba8Unorm,
+		RenderAssetUsages::RENDER_WORLD,
+	)
+}
+
+pub fn update_thumbnails(
+	mut thumbnails: ResMut<Thumbnails>,
+	mut images: ResMut<Assets<Image>>,
+	mut eguiCtx: EguiContexts,
+) {
+	let thumbnails = &mut *thumbnails;
+
+	let mut running = thumbnails
+		.entries
+		.values()
+		.filter(|(entry, _)| matches!(entry, Entry::Running(_)))
+		.count();
+	while running < maxRunningJobs {
+		let Some(hash) = thumbnails.queue.pop_front() else {
+			break;
+		};
+		// entries can be forgotten while still queued
+		let Some((entry, _)) = thumbnails.entries.get(&hash) else {
+			continue;
+		};
+		let Entry::Queued(code, inputs) = entry else {
+			continue;
+		};
+		let (code, inputs) = (code.clone(), inputs.clone());
+		let dir = thumbnails.diskCache.clone();
+		let task = AsyncComputeTaskPool::get()
+			.spawn(async move { cached_thumbnail(&code, &inputs, hash, dir.as_deref()) });
+		thumbnails.entries.get_mut(&hash).unwrap().0 = Entry::Running(task);
+		running += 1;
+	}
+
+	for (entry, _) in thumbnails.entries.values_mut() {
+		let Entry::Running(task) = entry else {
+			continue;
+		};
+		let Some(res) = block_on(future::poll_once(task)) else {
+			continue;
+		};
+		*entry = match res {
+			Some(samples) => {
+				let image = images.add(thumbnail_image(&samples));
+				let texture = eguiCtx.add_image(image.clone_weak());
+				Entry::Ready { image, texture }
+			},
+			None => Entry::Failed,
+		};
+	}
+
+	let ready = thumbnails
+		.entries
+		.values()
+		.filter(|(entry, _)| matches!(entry, Entry::Ready { .. }))
+		.count();
+	if ready > thumbnails.capacity {
+		let mut lru: Vec<_> = thumbnails
+			.entries
+			.iter()
+			.filter(|(_, (entry, _))| matches!(entry, Entry::Ready { .. }))
+			.map(|(&hash, &(_, lastUse))| (lastUse, hash))
+			.collect();
+		lru.sort_unstable();
+		for &(_, hash) in &lru[.. ready - thumbnails.capacity] {
+			thumbnails.remove_entry(hash);
+		}
+	}
+
+	for image in thumbnails.released.drain(..) {
+		eguiCtx.remove_image(&image);
+		images.remove(&image);
+	}
+}
@@ -0,0 +1,114 @@
+use bevy::prelude::*;
+use bevy_egui::egui;
+
+use crate::{NoiseOutput, TerrainScale};
+
+/// Downward acceleration while falling onto the terrain, in world units per second squared.
+const gravity: f32 = 30.0;
+/// Rate at which the eye climbs back to `WalkMode::eyeHeight` after stepping up a slope, per
+/// second.
+const stepRate: f32 = 12.0;
+/// Part of the eye height the camera always keeps above the ground while walking, however steep
+/// the slope it climbs.
+const minClearance: f32 = 0.5;
+
+/// First-person walking on the terrain, toggled with F or the 3D tab's "Walk" button. While on,
+/// `camera_controller_3d` keeps the camera at eye height above the current output's surface.
+#[derive(Resource)]
+pub struct WalkMode {
+	pub enabled: bool,
+	/// Height of the eye above the ground, in world units.
+	pub eyeHeight: f32,
+}
+
+impl Default for WalkMode {
+	fn default() -> Self {
+		Self {
+			enabled: false,
+			eyeHeight: 1.0,
+		}
+	}
+}
+
+pub fn walk_ui(ui: &mut egui::Ui, walk: &mut WalkMode) {
+	ui.toggle_value(&mut walk.enabled, "Walk")
+		.on_hover_text("Walk on the terrain with WASD instead of flying (F)");
+	if walk.enabled {
+		ui.add(
+			egui::DragValue::new(&mut walk.eyeHeight)
+				.range(0.05 ..= 100.0)
+				.speed(0.05)
+				.prefix("eye "),
+		);
+	}
+}
+
+/// Height of the surface `NoiseOutput::update_mesh` builds at world position `(pos.x, pos.y)` on
+/// the ground plane, interpolated bilinearly between samples. Positions off the grid take the
+/// height at its border.
+pub fn terrain_height(output: &NoiseOutput, scale: TerrainScale, pos: Vec2) -> f32 {
+	let (lastX, lastY) = (output.width.saturating_sub(1), output.height.saturating_sub(1));
+	let pos = scale.to_sample(output.size(), pos);
+	let pos = pos.clamp(Vec2::ZERO, Vec2::new(lastX as f32, lastY as f32));
+	let (x, y) = (pos.x as usize, pos.y as usize);
+	let (fx, fy) = (pos.x.fract(), pos.y.fract());
+	let at = |x: usize, y: usize| {
+		let v = output.samples[y.min(lastY) * output.width + x.min(lastX)];
+		output.normalized(v) * scale.height
+	};
+	let top = at(x, y) + (at(x + 1, y) - at(x, y)) * fx;
+	let bottom = at(x, y + 1) + (at(x + 1, y + 1) - at(x, y + 1)) * fx;
+	top + (bottom - top) * fy
+}
+
+/// Vertical motion of the walking camera, kept by `camera_controller_3d` between frames.
+#[derive(Default)]
+pub struct WalkState {
+	verticalSpeed: f32,
+	/// Rising onto terrain a new output raised above the camera, which is eased like a step
+	/// instead of snapping to `minClearance`.
+	settling: bool,
+}
+
+impl WalkState {
+	/// Moves `pos` towards eye height above `output`'s surface: falling onto it under gravity,
+	/// and easing up after steps and slopes. `replaced` tells that `output` changed since the last
+	/// frame.
+	pub fn update(
+		&mut self,
+		pos: &mut Vec3,
+		output: &NoiseOutput,
+		scale: TerrainScale,
+		eyeHeight: f32,
+		replaced: bool,
+		dt: f32,
+	) {
+		let ground = terrain_height(output, scale, vec2(pos.x, pos.z));
+		// NaN samples, from scripts failing on them, give no ground to stand on
+		if !ground.is_finite() {
+			return;
+		}
+		let target = ground + eyeHeight;
+		if replaced && pos.y < target {
+			self.settling = true;
+		}
+		if pos.y > target {
+			self.verticalSpeed -= gravity * dt;
+			pos.y = (pos.y + self.verticalSpeed * dt).max(target);
+		} else {
+			self.verticalSpeed = 0.0;
+			pos.y += (target - pos.y) * (1.0 - (-stepRate * dt).exp());
+			if !self.settling {
+				pos.y = pos.y.max(ground + eyeHeight * minClearance);
+			}
+		}
+		if pos.y >= ground + eyeHeight * minClearance {
+			self.settling = false;
+		}
+	}
+
+	/// Forgets the previous frame, so walking starts fresh from wherever the camera is.
+	pub fn reset(&mut self) {
+		*self = default();
+	}
+}
@@ -0,0 +1,267 @@
+use bevy::pbr::{ExtendedMaterial, MaterialExtension};
+use bevy::prelude::*;
+use bevy::render::render_resource::{AsBindGroup, ShaderRef, ShaderType};
+use bevy_egui::egui;
+
+use crate::{HeightmapSprite, Heightmaps, UiState};
+
+pub type WaterMaterial = ExtendedMaterial<StandardMaterial, WaterExtension>;
+
+/// Width of the water plane in terrain extents, enough to reach the horizon.
+pub const planeSpan: f32 = 64.0;
+
+/// Shades the water plane from the raw heightmap: deeper water gets a stronger tint, and a foam
+/// band marks where the terrain crosses the water level.
+#[derive(Asset, AsBindGroup, Reflect, Debug, Clone)]
+pub struct WaterExtension {
+	#[uniform(100)]
+	pub uniform: WaterUniform,
+	/// `Heightmaps::heights`, in unscaled sample values.
+	#[texture(101, sample_type = "float", filterable = false)]
+	pub heights: Handle<Image>,
+}
+
+impl MaterialExtension for WaterExtension {
+	fn fragment_shader() -> ShaderRef {
+		"shaders/water.wgsl".into()
+	}
+}
+
+#[derive(ShaderType, Reflect, Debug, Clone, Copy, Default, PartialEq)]
+pub struct WaterUniform {
+	/// Linear color of deep water, alpha being its opacity.
+	pub color: Vec4,
+	pub level: f32,
+	/// Mesh height, which heightmap samples are multiplied by.
+	pub heightScale: f32,
+	/// Width of the terrain's longer side, which the water plane is scaled by.
+	pub extent: f32,
+	/// World distance between neighbouring heightmap samples, see `TerrainScale::spacing`.
+	pub spacing: f32,
+	pub depthFalloff: f32,
+	pub foamWidth: f32,
+}
+
+#[derive(Resource)]
+pub struct WaterSettings {
+	/// Water level in output units, so it follows the mesh height.
+	pub level: f32,
+	pub color: Color,
+	/// How quickly the tint saturates with depth, per world unit.
+	pub depthFalloff: f32,
+	/// Height difference to the water level under which terrain gets foam, in world units.
+	pub foamWidth: f32,
+	/// Tints samples below the water level in the 2D view, see `WaterOverlay`, and moves the
+	/// terrain colors' water band up to the level.
+	pub markWater: bool,
+	pub windowOpen: bool,
+}
+
+impl Default for WaterSettings {
+	fn default() -> Self {
+		Self {
+			level: 0.0,
+			color: Color::srgba_u8(0x11, 0x7F, 0xD5, 0xD0),
+			depthFalloff: 4.0,
+			foamWidth: 0.02,
+			markWater: false,
+			windowOpen: false,
+		}
+	}
+}
+
+impl WaterUniform {
+	/// Texel coordinates of the heightmap under the world XZ position `pos`, for a `size`
+	/// heightmap. Mirrors `to_texel` in the shader, the inverse of `TerrainScale::to_world`.
+	pub fn to_texel(&self, (width, height): (usize, usize), pos: Vec2) -> Vec2 {
+		let size = vec2(width as f32, height as f32);
+		pos / self.spacing + (size - 1.0) / 2.0
+	}
+}
+
+impl WaterSettings {
+	/// Uniform for a `heightsSize` heightmap, whose samples the mesh places `spacing` apart.
+	pub fn uniform(&self, uiState: &UiState, heightsSize: (usize, usize)) -> WaterUniform {
+		WaterUniform {
+			color: self.color.to_linear().to_vec4(),
+			level: self.level * uiState.height,
+			heightScale: uiState.height,
+			extent: uiState.extent,
+			spacing: uiState.terrain_scale().spacing(heightsSize),
+			depthFalloff: self.depthFalloff,
+			foamWidth: self.foamWidth,
+		}
+	}
+
+	pub fn material(
+		&self,
+		uiState: &UiState,
+		heightmaps: &Heightmaps,
+		heightsSize: (usize, usize),
+	) -> WaterMaterial {
+		WaterMaterial {
+			base: StandardMaterial {
+				alpha_mode: AlphaMode::Blend,
+				perceptual_roughness: 0.1,
+				..default()
+			},
+			extension: WaterExtension {
+				uniform: self.uniform(uiState, heightsSize),
+				heights: heightmaps.heights.clone(),
+			},
+		}
+	}
+}
+
+pub fn water_ui(ui: &mut egui::Ui, settings: &mut WaterSettings) {
+	egui::Grid::new("water").show(ui, |ui| {
+		ui.label("Level");
+		ui.add(egui::Slider::new(&mut settings.level, -1.0 ..= 1.0).clamp_to_range(false))
+			.on_hover_text("In normalized output units, so the water follows the mesh height");
+		ui.end_row();
+
+		ui.label("Color");
+		let mut color = settings.color.to_srgba().to_u8_array();
+		if ui
+			.color_edit_button_srgba_unmultiplied(&mut color)
+			.changed()
+		{
+			settings.color = Color::srgba_u8(color[0], color[1], color[2], color[3]);
+		}
+		ui.end_row();
+
+		ui.label("Depth falloff");
+		ui.add(
+			egui::DragValue::new(&mut settings.depthFalloff)
+				.range(0.0 ..= 100.0)
+				.speed(0.05),
+		);
+		ui.end_row();
+
+		ui.label("Foam width");
+		ui.add(
+			egui::DragValue::new(&mut settings.foamWidth)
+				.range(0.0 ..= 10.0)
+				.speed(0.001),
+		);
+		ui.end_row();
+
+		ui.checkbox(&mut settings.markWater, "Mark water").on_hover_text(
+			"Tint samples below the level in the 2D view, and color the terrain up to it as water",
+		);
+		ui.end_row();
+	});
+}
+
+/// Tint over the 2D view's heightmap marking the samples below the water level, a child of the
+/// `HeightmapSprite`. Its image is computed from the heights texture, so moving the level or
+/// recoloring the water never runs the script again.
+#[derive(Component)]
+pub struct WaterOverlay;
+
+/// Opacity of the overlay over the shallowest water, rising to the water color's own towards the
+/// depth the 3D view's tint saturates at.
+const shallowOpacity: f32 = 0.35;
+
+/// Redraws the `WaterOverlay` whenever the heights or the settings it depends on change, and
+/// shows it only while `markWater` is on and the 2D view shows the live heightmap.
+pub fn update_water_overlay(
+	settings: Res<WaterSettings>,
+	uiState: Res<UiState>,
+	heightmaps: Res<Heightmaps>,
+	sprite: Query<&Handle<Image>, With<HeightmapSprite>>,
+	mut overlay: Query<
+		(&Handle<Image>, &mut Visibility),
+		(With<WaterOverlay>, Without<HeightmapSprite>),
+	>,
+	mut images: ResMut<Assets<Image>>,
+	mut imageEvents: EventReader<AssetEvent<Image>>,
+	mut applied: Local<Option<(f32, [u8; 4], f32)>>,
+) {
+	let heightsChanged = imageEvents
+		.read()
+		.any(|ev| ev.is_modified(&heightmaps.heights));
+	let (handle, mut visibility) = overlay.single_mut();
+	// snapshots, pins and references swap the sprite's image for one the heights don't match
+	let shown = settings.markWater && *sprite.single() == heightmaps.image;
+	let wanted = if shown { Visibility::Inherited } else { Visibility::Hidden };
+	if *visibility != wanted {
+		*visibility = wanted;
+	}
+	if !settings.markWater {
+		return;
+	}
+	let color = settings.color.to_srgba().to_u8_array();
+	// depths are in world units, like the 3D view's
+	let falloff = settings.depthFalloff * uiState.height;
+	let current = (settings.level, color, falloff);
+	if !heightsChanged && applied.as_ref() == Some(&current) {
+		return;
+	}
+	*applied = Some(current);
+
+	let Some(heights) = images.get(&heightmaps.heights) else {
+		error!("heights image is missing");
+		return;
+	};
+	let size = heights.texture_descriptor.size;
+	let heights: &[f32] = bytemuck::cast_slice(&heights.data);
+	let pixels: Vec<u8> = heights
+		.iter()
+		.flat_map(|&height| {
+			let depth = settings.level - height;
+			if !(depth > 0.0) {
+				return [0; 4];
+			}
+			let saturation = 1.0 - (-depth * falloff).exp();
+			let opacity = shallowOpacity + (1.0 - shallowOpacity) * saturation;
+			let [r, g, b, a] = color;
+			[r, g, b, (a as f32 * opacity).round() as u8]
+		})
+		.collect();
+	let Some(image) = images.get_mut(handle) else {
+		error!("water overlay image is missing");
+		return;
+	};
+	if image.texture_descriptor.size != size {
+		image.resize(size);
+	}
+	image.data = pixels;
+}
+
+/// Keeps water planes and their materials in sync with the settings and the latest heightmap.
+pub fn update_water_material(
+	settings: Res<WaterSettings>,
+	uiState: Res<UiState>,
+	heightmaps: Res<Heightmaps>,
+	mut water: Query<(&Handle<WaterMaterial>, &mut Transform)>,
+	mut materials: ResMut<Assets<WaterMaterial>>,
+	images: Res<Assets<Image>>,
+	mut imageEvents: EventReader<AssetEvent<Image>>,
+) {
+	// bind groups keep the texture they were created with, so rebind when the heights are rewritten
+	let heightsChanged = imageEvents
+		.read()
+		.any(|ev| ev.is_modified(&heightmaps.heights));
+	let size = images.get(&heightmaps.heights).map_or(uiState.output_size(), |heights| {
+		let size = heights.texture_descriptor.size;
+		(size.width as usize, size.height as usize)
+	});
+	let uniform = settings.uniform(&uiState, size);
+	for (handle, mut transform) in water.iter_mut() {
+		if transform.translation.y != uniform.level {
+			transform.translation.y = uniform.level;
+		}
+		let scale = Vec3::splat(uniform.extent * planeSpan);
+		if transform.scale != scale {
+			transform.scale = scale;
+		}
+		let Some(material) = materials.get(handle) else {
+			continue;
+		};
+		if material.extension.uniform == uniform && !heightsChanged {
+			continue;
+		}
+		materials.get_mut(handle).unwrap().extension.uniform = uniform;
+	}
+}
@@ -0,0 +1,182 @@
+use std::any::Any;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::task::{Poll, Waker};
+
+use bevy::tasks::futures_lite::future;
+use bevy::tasks::AsyncComputeTaskPool;
+use bevy_egui::egui;
+
+/// Most workers generations run at once together, 0 until set in the settings.
+static configuredLimit: AtomicUsize = AtomicUsize::new(0);
+/// Workers of all `for_each_limited` calls working on an item, see `Slot`.
+static busyWorkers: AtomicUsize = AtomicUsize::new(0);
+/// Workers waiting for a `Slot`, woken whenever one is given back.
+static waitingWorkers: Mutex<Vec<Waker>> = Mutex::new(vec![]);
+
+fn cores() -> usize {
+	std::thread::available_parallelism().map_or(1, |cores| cores.get())
+}
+
+/// One worker less than there are cores, leaving one to the UI thread.
+pub fn default_limit() -> usize {
+	(cores() - 1).max(1)
+}
+
+/// The limit set in the settings, if any.
+pub fn configured() -> Option<usize> {
+	match configuredLimit.load(Ordering::Relaxed) {
+		0 => None,
+		limit => Some(limit),
+	}
+}
+
+/// Sets the most workers generations run at once, `None` going back to `default_limit`.
+pub fn set_limit(limit: Option<usize>) {
+	configuredLimit.store(limit.map_or(0, |limit| limit.max(1)), Ordering::Relaxed);
+}
+
+/// Most workers generations run at once together, each also bounded by the async compute
+/// pool's threads.
+pub fn limit() -> usize {
+	configured().unwrap_or_else(default_limit)
+}
+
+/// Workers busy across all generations, which `limit()` bounds together.
+pub fn busy() -> usize {
+	busyWorkers.load(Ordering::Relaxed)
+}
+
+/// Permission to work on one item, out of `limit()` shared by every generation running, so
+/// several at once split the workers between them instead of each taking them all. Given back
+/// when dropped, after every item, so no generation waits for another to finish.
+struct Slot;
+
+impl Slot {
+	fn take() -> Option<Self> {
+		let limit = limit();
+		busyWorkers
+			.fetch_update(Ordering::AcqRel, Ordering::Acquire, |busy| {
+				(busy < limit).then_some(busy + 1)
+			})
+			.ok()
+			.map(|_| Slot)
+	}
+
+	/// Waits until a slot is free, without holding up a thread of the pool meanwhile.
+	async fn wait() -> Self {
+		future::poll_fn(|cx| {
+			if let Some(slot) = Slot::take() {
+				return Poll::Ready(slot);
+			}
+			waitingWorkers.lock().unwrap().push(cx.waker().clone());
+			// a slot given back before the waker was registered woke nobody
+			Slot::take().map_or(Poll::Pending, Poll::Ready)
+		})
+		.await
+	}
+}
+
+impl Drop for Slot {
+	fn drop(&mut self) {
+		busyWorkers.fetch_sub(1, Ordering::AcqRel);
+		let waiting = std::mem::take(&mut *waitingWorkers.lock().unwrap());
+		for waker in waiting {
+			waker.wake();
+		}
+	}
+}
+
+/// How far a generation got, in items of work done out of the total, read from other threads
+/// while it runs.
+#[derive(Default)]
+pub struct Progress {
+	done: AtomicUsize,
+	total: AtomicUsize,
+}
+
+impl Progress {
+	/// Starts over with `total` items to do.
+	pub fn start(&self, total: usize) {
+		self.done.store(0, Ordering::Relaxed);
+		self.total.store(total, Ordering::Relaxed);
+	}
+
+	pub fn advance(&self) {
+		self.done.fetch_add(1, Ordering::Relaxed);
+	}
+
+	/// Share of the items done, 0 before starting.
+	pub fn fraction(&self) -> f32 {
+		let total = self.total.load(Ordering::Relaxed);
+		if total == 0 {
+			return 0.0;
+		}
+		self.done.load(Ordering::Relaxed) as f32 / total as f32
+	}
+}
+
+/// Runs `work` on every item on the async compute pool, at most `limit()` at once together with
+/// every other call running. Each worker takes the next item once done with its last one, and
+/// yields in between so other tasks on the pool and cancellation checks get a turn.
+pub fn for_each_limited<T: Send>(
+	items: impl Iterator<Item = T> + Send,
+	work: impl Fn(T) + Sync,
+) {
+	let pool = AsyncComputeTaskPool::get();
+	let workers = limit().min(pool.thread_num()).max(1);
+	let (items, work) = (&Mutex::new(items), &work);
+	pool.scope(|scope| {
+		for _ in 0 .. workers {
+			scope.spawn(async move {
+				loop {
+					let slot = Slot::wait().await;
+					// the lock is released before working on the item
+					let Some(item) = items.lock().unwrap().next() else {
+						return;
+					};
+					work(item);
+					drop(slot);
+					future::yield_now().await;
+				}
+			});
+		}
+	});
+}
+
+/// Runs `f`, turning a panic into an error. A panicked task would never finish, leaving whoever
+/// polls it waiting forever.
+pub fn catch_panic<T>(f: impl FnOnce() -> Result<T, String>) -> Result<T, String> {
+	panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|payload| Err(panic_message(payload)))
+}
+
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+	let message = match (payload.downcast_ref::<&str>(), payload.downcast_ref::<String>()) {
+		(Some(message), _) => *message,
+		(_, Some(message)) => message.as_str(),
+		_ => "unknown cause",
+	};
+	format!("generation panicked: {message}")
+}
+
+/// The worker limit setting, for the Settings window.
+pub fn workers_ui(ui: &mut egui::Ui) {
+	ui.horizontal(|ui| {
+		let mut limit = limit();
+		let poolThreads = AsyncComputeTaskPool::get().thread_num();
+		let resp = ui
+			.add(egui::Slider::new(&mut limit, 1 ..= cores()).text("worker threads"))
+			.on_hover_text(format!(
+				"Most threads generations evaluate samples on at once, all of them together, so \
+				 the UI keeps a core to itself. At most the {poolThreads} threads of the compute \
+				 pool are used",
+			));
+		if resp.changed() {
+			set_limit(Some(limit));
+		}
+		if configured().is_some() && ui.button("Default").clicked() {
+			set_limit(None);
+		}
+	});
+}